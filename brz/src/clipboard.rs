@@ -0,0 +1,289 @@
+//! Clipboard handler, through the clipboard of the system or of the
+//! terminal
+//!
+//! The provider is the one `[clipboard]` of `config.toml` names, eg.
+//! `provider = "osc52"`, or else the first that works here: `pbcopy` on
+//! macOS, the Windows clipboard on Windows and WSL, `wl-clipboard` on
+//! Wayland, `xclip` or `xsel` on X11, and OSC 52 for the terminal the
+//! editor runs in otherwise, eg. over ssh.
+use crate::{env, tool};
+use libbrz::health::Finding;
+use libbrz::preview::CancelToken;
+use libbrz::register::ClipboardOp;
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::sync::Arc;
+
+/// Names of the providers, as `config.toml` gives them
+pub const PROVIDERS: &[&str] = &[
+    "pbcopy",
+    "windows",
+    "wl-clipboard",
+    "xclip",
+    "xsel",
+    "osc52",
+];
+
+pub trait ClipboardProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn copy(&self, text: &str) -> io::Result<()>;
+
+    fn paste(&self) -> io::Result<String>;
+
+    /// The programs it runs
+    fn programs(&self) -> Vec<&str> {
+        vec![]
+    }
+}
+
+/// A provider running a program to copy and another to paste
+struct Programs {
+    name: &'static str,
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+impl ClipboardProvider for Programs {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn copy(&self, text: &str) -> io::Result<()> {
+        copy_with(self.copy, text)
+    }
+
+    fn paste(&self) -> io::Result<String> {
+        let text = paste_with(self.paste)?;
+        // `Get-Clipboard` ends with a line end of its own
+        if self.name != "windows" {
+            return Ok(text);
+        }
+        let text = text.replace("\r\n", "\n");
+        Ok(text
+            .strip_suffix('\n')
+            .map_or_else(|| text.clone(), str::to_owned))
+    }
+
+    fn programs(&self) -> Vec<&str> {
+        let mut programs = vec![self.copy[0], self.paste[0]];
+        programs.dedup();
+        programs
+    }
+}
+
+/// Copying through the terminal, which can be on another machine; the
+/// terminal isn't read, so nothing can be pasted
+struct Osc52;
+
+impl ClipboardProvider for Osc52 {
+    fn name(&self) -> &str {
+        "osc52"
+    }
+
+    fn copy(&self, text: &str) -> io::Result<()> {
+        let mut sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+        if std::env::var_os("TMUX").is_some() {
+            // passed through to the terminal tmux runs in
+            sequence = format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(sequence.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn paste(&self) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the clipboard can't be read, only written",
+        ))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The provider of `name`, one of `PROVIDERS`
+pub fn provider(name: &str) -> Option<Arc<dyn ClipboardProvider>> {
+    let programs = |name: &'static str, copy, paste| Arc::new(Programs { name, copy, paste });
+    Some(match name {
+        "pbcopy" => programs("pbcopy", &["pbcopy"], &["pbpaste"]),
+        "windows" => programs(
+            "windows",
+            &["clip.exe"],
+            &["powershell.exe", "-NoProfile", "-Command", "Get-Clipboard"],
+        ),
+        "wl-clipboard" => programs("wl-clipboard", &["wl-copy"], &["wl-paste", "--no-newline"]),
+        "xclip" => programs(
+            "xclip",
+            &["xclip", "-selection", "clipboard"],
+            &["xclip", "-selection", "clipboard", "-o"],
+        ),
+        "xsel" => programs(
+            "xsel",
+            &["xsel", "--clipboard", "--input"],
+            &["xsel", "--clipboard", "--output"],
+        ),
+        "osc52" => Arc::new(Osc52),
+        _ => return None,
+    })
+}
+
+fn on_path(program: &str) -> bool {
+    env::find_system_program(program).is_some()
+}
+
+/// The first provider that works here
+pub fn detect() -> io::Result<Arc<dyn ClipboardProvider>> {
+    let set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    let name = if cfg!(target_os = "macos") {
+        "pbcopy"
+    } else if cfg!(windows) || set("WSL_DISTRO_NAME") {
+        "windows"
+    } else if set("WAYLAND_DISPLAY") && on_path("wl-copy") {
+        "wl-clipboard"
+    } else if set("DISPLAY") && on_path("xclip") {
+        "xclip"
+    } else if set("DISPLAY") && on_path("xsel") {
+        "xsel"
+    } else if set("TERM") {
+        "osc52"
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no clipboard: no display, and no terminal",
+        ));
+    };
+    Ok(provider(name).expect("a provider"))
+}
+
+/// Findings of `:health` about the provider `name`, or the one `detect`
+/// finds
+pub fn health(name: Option<&str>) -> Vec<Finding> {
+    let provider = match name.map(|name| (name, provider(name))) {
+        Some((_, Some(provider))) => provider,
+        Some((name, None)) => {
+            return vec![Finding::error(
+                format!("no clipboard provider {}", name),
+                format!(
+                    "set provider of [clipboard] in config.toml to one of {}",
+                    PROVIDERS.join(" ")
+                ),
+            )]
+        }
+        None => match detect() {
+            Ok(provider) => provider,
+            Err(e) => {
+                return vec![Finding::warning(
+                    e.to_string(),
+                    "install wl-clipboard, xclip or xsel, or run in a terminal",
+                )]
+            }
+        },
+    };
+    let how = if name.is_some() { "set" } else { "detected" };
+    let mut findings = vec![Finding::ok(format!("{}, {}", provider.name(), how))];
+    for program in provider.programs() {
+        if !on_path(program) {
+            findings.push(Finding::error(
+                format!("{} is not on PATH", program),
+                format!(
+                    "install it, or set provider of [clipboard] in config.toml to one of {}",
+                    PROVIDERS.join(" ")
+                ),
+            ));
+        }
+    }
+    if provider.name() == "osc52" {
+        findings.push(Finding::info(
+            "copying only: paste with the terminal, not with the editor",
+        ));
+    }
+    findings
+}
+
+/// The handler copying and pasting with the provider `name`, or the one
+/// `detect` finds the first time it's used
+pub fn handler(name: Option<&str>) -> impl Fn(&ClipboardOp) -> io::Result<Option<String>> {
+    let chosen = name.map(|name| {
+        provider(name).ok_or_else(|| {
+            format!(
+                "no clipboard provider {}, only {}",
+                name,
+                PROVIDERS.join(" ")
+            )
+        })
+    });
+    let detected = std::sync::OnceLock::new();
+    move |op| {
+        let provider = match chosen {
+            Some(Ok(ref provider)) => provider.clone(),
+            Some(Err(ref e)) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e.clone())),
+            None => detected
+                .get_or_init(|| detect().map_err(|e| e.to_string()))
+                .clone()
+                .map_err(io::Error::other)?,
+        };
+        let done = match op {
+            ClipboardOp::Copy(text) => provider.copy(text).map(|()| None),
+            ClipboardOp::Paste => provider.paste().map(Some),
+        };
+        done.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", provider.name(), e)))
+    }
+}
+
+/// Not through `tool::run`: the copying tools of X11 and Wayland stay in
+/// the background, to hand the text over, with the pipes they were given
+fn copy_with(args: &[&str], text: &str) -> io::Result<()> {
+    let mut child = env::system_command(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", args[0], e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed", args[0])))
+    }
+}
+
+fn paste_with(args: &[&str]) -> io::Result<String> {
+    let mut cmd = env::system_command(args[0]);
+    cmd.args(&args[1..]);
+    let output = tool::run(
+        cmd,
+        None,
+        &tool::Limits::default(),
+        &CancelToken::default(),
+        |_| {},
+    )?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let failed = format!("{} failed", args[0]);
+        return Err(io::Error::other(
+            stderr.lines().next().unwrap_or(&failed).to_owned(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
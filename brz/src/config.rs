@@ -0,0 +1,225 @@
+//! Files configuring the editor, in `$XDG_CONFIG_HOME/breeze`, and
+//! those it keeps between sessions, in `$XDG_STATE_HOME/breeze`
+//!
+//! A project can have config files of its own, in `.breeze` in the
+//! directory the editor is started in. They, and the environment of the
+//! project, are only loaded once the project is trusted, by its directory
+//! being in the `trusted` config file, which `--trust` adds it to, as
+//! they can bind keys to run any command.
+//!
+//! A named profile has config and state of its own, in `profiles/<name>`
+//! of both directories, next to the project's. A clean start has none:
+//! nothing is read nor kept, the project's config included.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The config the editor starts with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Profile {
+    Default,
+    Named(String),
+    Clean,
+}
+
+static PROFILE: OnceLock<Profile> = OnceLock::new();
+
+fn profile() -> &'static Profile {
+    PROFILE.get_or_init(|| Profile::Default)
+}
+
+/// Read the config of `profile` from now on
+///
+/// Fails if the profile's directory doesn't exist, a misspelled name
+/// would otherwise start the editor with no config at all.
+pub fn set_profile(profile: Profile) -> io::Result<()> {
+    if let Profile::Named(name) = &profile {
+        let invalid = name.is_empty() || name.starts_with('.') || name.contains('/');
+        if invalid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid profile name `{}`", name),
+            ));
+        }
+        let dir = base_dir().map(|dir| dir.join("profiles").join(name));
+        if !dir.as_ref().is_some_and(|dir| dir.is_dir()) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no profile `{}`", name),
+            ));
+        }
+    }
+    let _ = PROFILE.set(profile);
+    Ok(())
+}
+
+/// `dir` of the profile, none on a clean start
+fn in_profile(dir: Option<PathBuf>) -> Option<PathBuf> {
+    match profile() {
+        Profile::Default => dir,
+        Profile::Named(name) => dir.map(|dir| dir.join("profiles").join(name)),
+        Profile::Clean => None,
+    }
+}
+
+fn base_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("breeze"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/breeze"))
+}
+
+fn dir() -> Option<PathBuf> {
+    in_profile(base_dir())
+}
+
+fn read_in(dir: Option<PathBuf>, name: &str) -> io::Result<Option<String>> {
+    let path = match dir {
+        Some(dir) => dir.join(name),
+        None => return Ok(None),
+    };
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Content of the config file `name`, if there is one
+pub fn read(name: &str) -> io::Result<Option<String>> {
+    read_in(dir(), name)
+}
+
+/// Names and contents of the config files in the directory `name` with
+/// `extension`, the names without it
+pub fn read_all(name: &str, extension: &str) -> io::Result<Vec<(String, String)>> {
+    let dir = match dir() {
+        Some(dir) => dir.join(name),
+        None => return Ok(vec![]),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+            continue;
+        }
+        if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+            files.push((stem.to_owned(), fs::read_to_string(&path)?));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Paths of the files in the config directory `name`, sorted
+pub fn paths(name: &str) -> io::Result<Vec<PathBuf>> {
+    let dir = match dir() {
+        Some(dir) => dir.join(name),
+        None => return Ok(vec![]),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Content of the config file `name` of the project, if there is one and
+/// the project is trusted
+pub fn read_project(name: &str) -> io::Result<Option<String>> {
+    if *profile() == Profile::Clean || !is_project_trusted() {
+        return Ok(None);
+    }
+    read_in(Some(PathBuf::from(".breeze")), name)
+}
+
+/// The directory of the project, as it's written in `trusted`
+fn project_dir() -> io::Result<PathBuf> {
+    std::env::current_dir()?.canonicalize()
+}
+
+/// Whether the directory the editor is started in is in the `trusted`
+/// config file, one directory a line, so that the config and the
+/// environment of the project can be loaded
+pub fn is_project_trusted() -> bool {
+    let (Ok(dir), Ok(Some(trusted))) = (project_dir(), read("trusted")) else {
+        return false;
+    };
+    trusted.lines().any(|line| Path::new(line.trim()) == dir)
+}
+
+/// Add the directory the editor is started in to the `trusted` config
+/// file, if it's not there yet
+pub fn trust_project() -> io::Result<()> {
+    let config = match dir() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    if is_project_trusted() {
+        return Ok(());
+    }
+    let mut trusted = read("trusted")?.unwrap_or_default();
+    if !trusted.is_empty() && !trusted.ends_with('\n') {
+        trusted.push('\n');
+    }
+    trusted.push_str(&project_dir()?.to_string_lossy());
+    trusted.push('\n');
+    fs::create_dir_all(&config)?;
+    fs::write(config.join("trusted"), trusted)
+}
+
+/// The state directory of the user, whatever the profile
+pub fn base_state_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => Some(PathBuf::from(dir).join("breeze")),
+        None => {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/breeze"))
+        }
+    }
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    in_profile(base_state_dir())
+}
+
+/// Name of a state file kept for the file at `path`, its `%` and `/`
+/// escaped
+pub fn escape_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('%', "%25")
+        .replace('/', "%2F")
+}
+
+/// The path a state file is named after, as `escape_path` named it
+pub fn unescape_path(name: &str) -> PathBuf {
+    PathBuf::from(name.replace("%2F", "/").replace("%25", "%"))
+}
+
+/// Content of the state file `name`, if there is one
+pub fn read_state(name: &str) -> io::Result<Option<String>> {
+    read_in(state_dir(), name)
+}
+
+pub fn write_state(name: &str, text: &str) -> io::Result<()> {
+    let dir = match state_dir() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(name), text)
+}
@@ -0,0 +1,125 @@
+//! Running tools with the environment of the project, as described in
+//! `libbrz::env`
+//!
+//! The project's part of it is only loaded for a trusted project, see
+//! `config::is_project_trusted`. The helpers the editor runs itself,
+//! like git, sh or the clipboard tools, are always the editor's own, from
+//! its `PATH`, whatever that of the project.
+use libbrz::env::{Environment, ENV_FILES, PROJECT_BIN_DIRS};
+use libbrz::health::Finding;
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+
+static ENV: OnceLock<(PathBuf, Environment)> = OnceLock::new();
+
+/// The tools run by name, and what needs them
+const TOOLS: &[(&str, &str)] = &[
+    ("git", "the git gutter, :git and :history"),
+    ("sh", "pipes"),
+    ("sudo", ":sudo-w"),
+];
+
+/// Add the `bin` directories and env files of the project to `env`
+pub fn load_project(env: &mut Environment) {
+    for dir in PROJECT_BIN_DIRS {
+        if fs::metadata(dir).is_ok_and(|meta| meta.is_dir()) {
+            env.add_path(dir);
+        }
+    }
+    for name in ENV_FILES {
+        if let Ok(text) = fs::read_to_string(name) {
+            env.add_env_file(&text);
+        }
+    }
+}
+
+/// Whether the project has `bin` directories or env files, to be loaded
+/// if it's trusted
+pub fn has_project_env() -> bool {
+    PROJECT_BIN_DIRS
+        .iter()
+        .chain(&ENV_FILES)
+        .any(|path| fs::metadata(path).is_ok())
+}
+
+/// Run the tools with `env` from now on
+pub fn set(env: Environment) {
+    let project = std::env::current_dir().unwrap_or_default();
+    let _ = ENV.set((project, env));
+}
+
+/// `Command::new(program)`, in the environment of the project
+pub fn command(program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    if let Some((project, env)) = ENV.get() {
+        // with `PATH` set, `program` is looked for in the new one
+        cmd.envs(env.vars_for(program, project, |name| std::env::var(name).ok()));
+    }
+    cmd
+}
+
+/// `command(program)` for a helper of the editor, found in the `PATH` of
+/// the editor, not the project's
+///
+/// Not found there, it's not looked for anywhere else, and fails to run.
+pub fn system_command(program: &str) -> Command {
+    match find_system_program(program) {
+        Some(path) => {
+            let mut cmd = command(&path.to_string_lossy());
+            cmd.arg0(program);
+            cmd
+        }
+        None => Command::new(program),
+    }
+}
+
+/// Where `program` is in the `PATH` of the editor, or right there if
+/// it's a path
+pub fn find_system_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| path.is_file());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Where `program` is, as `command` finds it: in the `PATH` of the
+/// project, or right there if it's a path
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| path.is_file());
+    }
+    let inherited = |name: &str| std::env::var(name).ok();
+    let path = ENV
+        .get()
+        .and_then(|(project, env)| {
+            let vars = env.vars_for(program, project, inherited);
+            vars.into_iter()
+                .rev()
+                .find(|(name, _)| name == "PATH")
+                .map(|(_, value)| value)
+        })
+        .or_else(|| inherited("PATH"))?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Findings of `:health` about the tools run
+pub fn health() -> Vec<Finding> {
+    TOOLS
+        .iter()
+        .map(|(tool, needed_by)| match find_system_program(tool) {
+            Some(path) => Finding::ok(format!("{} at {}", tool, path.display())),
+            None => Finding::warning(
+                format!("{} is not on PATH, for {}", tool, needed_by),
+                format!("install {}", tool),
+            ),
+        })
+        .collect()
+}
@@ -0,0 +1,78 @@
+//! Git handler, running the `git` command line tool
+use crate::{env, tool};
+use libbrz::{git::GitRequest, preview::CancelToken};
+use std::io;
+use std::path::Path;
+
+pub fn run(request: &GitRequest) -> io::Result<String> {
+    let mut cmd = env::system_command("git");
+    let mut stdin = None;
+    match request {
+        GitRequest::Status => {
+            cmd.args(["status", "--porcelain=v1", "-z", "--untracked-files=all"]);
+        }
+        GitRequest::Stage(path) => {
+            cmd.args(["add", "--"]).arg(path);
+        }
+        GitRequest::Unstage(path) => {
+            cmd.args(["restore", "--staged", "--"]).arg(path);
+        }
+        GitRequest::Diff { staged, path } => {
+            cmd.args(["diff", "--no-color", "--no-ext-diff"]);
+            if *staged {
+                cmd.arg("--cached");
+            }
+            if let Some(path) = path {
+                cmd.arg("--").arg(path);
+            }
+        }
+        GitRequest::LastCommitMessage => {
+            cmd.args(["log", "-1", "--format=%B"]);
+        }
+        GitRequest::Commit { message, amend } => {
+            cmd.args(["commit", "--quiet", "--cleanup=strip", "--file=-"]);
+            if *amend {
+                cmd.arg("--amend");
+            }
+            stdin = Some(message.clone());
+        }
+        GitRequest::Log(path) => {
+            let (dir, file) = split_path(path);
+            cmd.arg("-C")
+                .arg(dir)
+                .args(["log", "--format=%H%x00%h%x00%as%x00%s", "--"]);
+            cmd.arg(file);
+        }
+        GitRequest::Show { rev, path } => {
+            let (dir, file) = split_path(path);
+            cmd.arg("-C")
+                .arg(dir)
+                .arg("show")
+                .arg(format!("{}:./{}", rev, file.display()));
+        }
+    }
+
+    let output = tool::run(
+        cmd,
+        stdin.map(String::into_bytes),
+        &tool::Limits::default(),
+        &CancelToken::default(),
+        |_| {},
+    )?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(
+            stderr.lines().next().unwrap_or("git failed").to_owned(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Directory to run git in and the file name relative to it
+fn split_path(path: &Path) -> (&Path, &Path) {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    (dir, path.file_name().map(Path::new).unwrap_or(path))
+}
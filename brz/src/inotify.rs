@@ -0,0 +1,136 @@
+//! Changes of local files, as Linux tells them with inotify
+//!
+//! The directory of a file is watched rather than the file itself: files
+//! are written by renaming a new one over them, which a watch of the old
+//! one would not see. Its events are those naming the file, once it's
+//! closed or moved there, so that a write half done is not told.
+use libbrz::vfs::{OnChange, Watch};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+// not in the `libc` crate yet
+const IN_CLOSE_WRITE: u32 = 0x008;
+const IN_MOVED_FROM: u32 = 0x040;
+const IN_MOVED_TO: u32 = 0x080;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+const IN_DELETE_SELF: u32 = 0x400;
+const IN_MOVE_SELF: u32 = 0x800;
+
+/// Size of `struct inotify_event`, before the name
+const EVENT_HEADER: usize = 16;
+
+/// How often the watch looks whether it was dropped
+const STOP_CHECK_MS: i32 = 500;
+
+extern "C" {
+    fn inotify_init1(flags: libc::c_int) -> libc::c_int;
+    fn inotify_add_watch(fd: libc::c_int, path: *const libc::c_char, mask: u32) -> libc::c_int;
+}
+
+/// An inotify instance watching a path
+pub struct Inotify {
+    fd: libc::c_int,
+    path: PathBuf,
+    /// Name of the file in the directory watched; `None` if the path is a
+    /// directory, whose entries changing are all told
+    name: Option<Vec<u8>>,
+}
+
+impl Inotify {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let (dir, name) = if path.is_dir() {
+            (path, None)
+        } else {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            (dir, path.file_name().map(|name| name.as_bytes().to_vec()))
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { inotify_init1(libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mask = IN_CLOSE_WRITE
+            | IN_MOVED_FROM
+            | IN_MOVED_TO
+            | IN_CREATE
+            | IN_DELETE
+            | IN_DELETE_SELF
+            | IN_MOVE_SELF;
+        if unsafe { inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+            let e = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        Ok(Self {
+            fd,
+            path: path.to_owned(),
+            name,
+        })
+    }
+
+    /// Call `on_change` with the path for the events of it, until the
+    /// watch is dropped
+    pub fn watch(self, on_change: OnChange) -> Watch {
+        let (watch, active) = Watch::new();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while active.load(Ordering::Relaxed) {
+                let mut poll = libc::pollfd {
+                    fd: self.fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if unsafe { libc::poll(&mut poll, 1, STOP_CHECK_MS) } <= 0 {
+                    continue;
+                }
+                let read = unsafe {
+                    libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                };
+                if read <= 0 {
+                    break;
+                }
+                if self.concerned(&buf[..read as usize]) && active.load(Ordering::Relaxed) {
+                    on_change(&self.path);
+                }
+            }
+        });
+        watch
+    }
+
+    /// Whether any of the events read is about the path
+    fn concerned(&self, mut events: &[u8]) -> bool {
+        let name = match &self.name {
+            Some(name) => name,
+            None => return true,
+        };
+        let mut concerned = false;
+        while EVENT_HEADER <= events.len() {
+            let mask = u32::from_ne_bytes([events[4], events[5], events[6], events[7]]);
+            let len = u32::from_ne_bytes([events[12], events[13], events[14], events[15]]) as usize;
+            let end = (EVENT_HEADER + len).min(events.len());
+            let event_name = &events[EVENT_HEADER..end];
+            let event_name = &event_name[..event_name
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(event_name.len())];
+            concerned |= mask & (IN_DELETE_SELF | IN_MOVE_SELF) != 0 || event_name == &name[..];
+            events = &events[end..];
+        }
+        concerned
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
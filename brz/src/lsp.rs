@@ -0,0 +1,837 @@
+//! Language servers, started as `config.toml` says
+//!
+//! A `[lsp.<language>]` table gives the command running the server of a
+//! language, eg. `command = "rust-analyzer"`, and optionally its
+//! `priority`. The server is started in the project directory when the
+//! first document of its language is opened, and spoken to in JSON-RPC
+//! over its stdin and stdout: documents are sent whole on each change,
+//! its diagnostics are shown as they're published, and it's asked for
+//! definitions, references, hovers and completions. Positions are
+//! converted from and to the encoding it picked, with the text it was
+//! last sent.
+use crate::{env, Input};
+use libbrz::completion::{Candidate, CompletionRequest};
+use libbrz::config::{self, Value as ConfigValue};
+use libbrz::document::{Diagnostic, Severity};
+use libbrz::health::Finding;
+use libbrz::json::{self, Value};
+use libbrz::lsp::{DocumentSync, Query, QueryRequest, QueryResult, ServerInfo};
+use libbrz::position::encoding::{EncodedPosition, PositionEncoding};
+use libbrz::quickfix;
+use libbrz::state::State;
+use libbrz::util::uri::percent_decode;
+use libbrz::Idx;
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Stdio};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// A `[lsp.<language>]` table
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub language: String,
+    /// The program and its arguments, split at whitespace
+    pub command: Vec<String>,
+    pub priority: i32,
+}
+
+/// The servers the tables of `config.toml` give
+pub fn configs(tables: &[config::Table]) -> Vec<ServerConfig> {
+    tables
+        .iter()
+        .filter_map(|table| match table.path.as_slice() {
+            [lsp, language] if lsp == "lsp" => {
+                let value = |key| {
+                    let entry = table.entries.iter().find(|entry| entry.key == key);
+                    entry.map(|entry| &entry.value)
+                };
+                let command = match value("command") {
+                    Some(ConfigValue::String(command)) => command,
+                    _ => return None,
+                };
+                let priority = match value("priority") {
+                    Some(&ConfigValue::Integer(priority)) => priority as i32,
+                    _ => 0,
+                };
+                Some(ServerConfig {
+                    language: language.clone(),
+                    command: command.split_whitespace().map(str::to_owned).collect(),
+                    priority,
+                })
+            }
+            _ => None,
+        })
+        .filter(|config| !config.command.is_empty())
+        .collect()
+}
+
+/// Findings of `:health` about the servers of `configs`, not run when
+/// the files are `remote`
+pub fn health(configs: &[ServerConfig], remote: bool) -> Vec<Finding> {
+    if configs.is_empty() {
+        return vec![Finding::info(
+            "none in config.toml, as tables [lsp.<language>] with a command",
+        )];
+    }
+    if remote {
+        return vec![Finding::warning(
+            "not run over ssh",
+            "run the editor where the files are for the servers",
+        )];
+    }
+    configs
+        .iter()
+        .map(|config| {
+            let program = &config.command[0];
+            match env::find_program(program) {
+                Some(path) => Finding::ok(format!("{}: {}", config.language, path.display())),
+                None => Finding::error(
+                    format!("{}: {} is not on PATH", config.language, program),
+                    format!(
+                        "install it, or fix command of [lsp.{}] in config.toml",
+                        config.language
+                    ),
+                ),
+            }
+        })
+        .collect()
+}
+
+/// What the servers tell the main loop
+pub enum Event {
+    Attach(PathBuf, ServerInfo),
+    Detach(PathBuf, String),
+    Diagnostics(PathBuf, String, Vec<Diagnostic>),
+    Answer(u64, QueryResult),
+    Completions(u64, Vec<Candidate>),
+    Message(String),
+}
+
+/// Pass an `Event` on to the editor
+pub fn deliver(state: &mut State, event: Event) {
+    match event {
+        Event::Attach(path, info) => {
+            state.attach_server(&path, info);
+        }
+        Event::Detach(path, name) => state.detach_server(&path, &name),
+        Event::Diagnostics(path, name, diagnostics) => {
+            state.publish_diagnostics(&path, &name, diagnostics);
+        }
+        Event::Answer(id, result) => {
+            state.deliver_query(id, result);
+        }
+        Event::Completions(id, candidates) => {
+            state.deliver_completions(id, candidates);
+        }
+        Event::Message(msg) => state.show_message(&msg),
+    }
+}
+
+/// Run the servers of `configs` for the documents of `state`
+pub fn register(state: &mut State, configs: Vec<ServerConfig>, tx: mpsc::Sender<Input>) {
+    let client = Rc::new(Client {
+        configs,
+        root: std::env::current_dir().unwrap_or_default(),
+        servers: RefCell::default(),
+        languages: RefCell::default(),
+        tx,
+    });
+    let sync_client = client.clone();
+    state.register_document_sync_handler(move |sync| sync_client.sync(sync));
+    state.register_completion_source(Completions(client.clone()));
+    state.register_query_handler(move |request| client.query(request));
+}
+
+/// The servers, as a source of completions
+struct Completions(Rc<Client>);
+
+impl libbrz::completion::Source for Completions {
+    fn name(&self) -> &str {
+        "lsp"
+    }
+
+    fn complete(&self, request: &CompletionRequest) -> Option<Vec<Candidate>> {
+        self.0.complete(request);
+        // as the server answers
+        None
+    }
+}
+
+/// A request sent to a server, waiting for its response
+enum Pending {
+    Initialize,
+    Query(u64, Query),
+    Completion(u64),
+}
+
+struct Shared {
+    /// Messages held until the server answers `initialize`; `None` after
+    queued: Option<Vec<Value>>,
+    info: ServerInfo,
+    encoding: PositionEncoding,
+    next_id: u64,
+    pending: HashMap<u64, Pending>,
+    /// Documents open, with the text last sent
+    documents: HashMap<PathBuf, Rope>,
+    exited: bool,
+}
+
+struct Server {
+    stdin: Mutex<ChildStdin>,
+    shared: Mutex<Shared>,
+    root: PathBuf,
+    tx: mpsc::Sender<Input>,
+}
+
+impl Server {
+    fn start(config: &ServerConfig, root: &Path, tx: mpsc::Sender<Input>) -> io::Result<Arc<Self>> {
+        let mut child = env::command(&config.command[0])
+            .args(&config.command[1..])
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("piped");
+        let stdout = child.stdout.take().expect("piped");
+        let name = Path::new(&config.command[0]).file_name().map_or_else(
+            || config.command[0].clone(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let server = Arc::new(Server {
+            stdin: Mutex::new(stdin),
+            shared: Mutex::new(Shared {
+                queued: Some(vec![]),
+                info: ServerInfo {
+                    name,
+                    priority: config.priority,
+                    capabilities: vec![],
+                },
+                encoding: PositionEncoding::default(),
+                next_id: 1,
+                pending: HashMap::new(),
+                documents: HashMap::new(),
+                exited: false,
+            }),
+            root: root.to_owned(),
+            tx,
+        });
+
+        let reader = server.clone();
+        thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            while let Ok(Some(body)) = read_message(&mut stdout) {
+                if let Ok(message) = json::parse(&body) {
+                    reader.handle(message);
+                }
+            }
+            reader.exited();
+            let _ = child.wait();
+        });
+
+        let mut shared = server.shared.lock().expect("not poisoned");
+        shared.pending.insert(0, Pending::Initialize);
+        let root_uri = uri_of(root);
+        let params = Value::object(vec![
+            ("processId", u64::from(std::process::id()).into()),
+            ("clientInfo", Value::object(vec![("name", "brz".into())])),
+            ("rootUri", root_uri.clone().into()),
+            (
+                "workspaceFolders",
+                vec![Value::object(vec![
+                    ("uri", root_uri.into()),
+                    (
+                        "name",
+                        root.file_name()
+                            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+                            .into(),
+                    ),
+                ])]
+                .into(),
+            ),
+            ("capabilities", client_capabilities()),
+        ]);
+        server.write(&request(0, "initialize", params));
+        drop(shared);
+        Ok(server)
+    }
+
+    fn write(&self, message: &Value) {
+        let body = message.to_string();
+        let mut stdin = self.stdin.lock().expect("not poisoned");
+        // if it's gone, the reader tells
+        let _ = write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = stdin.flush();
+    }
+
+    /// Send `message` once the server is initialized
+    fn send(&self, shared: &mut Shared, message: Value) {
+        match shared.queued {
+            Some(ref mut queued) => queued.push(message),
+            None => self.write(&message),
+        }
+    }
+
+    fn notify(&self, shared: &mut Shared, method: &str, params: Value) {
+        let message = Value::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]);
+        self.send(shared, message);
+    }
+
+    fn handle(&self, message: Value) {
+        let method = message.get("method").as_str();
+        let id = message.get("id");
+        match (method, id.is_null()) {
+            (Some(method), false) => self.answer(method, id, message.get("params")),
+            (Some(method), true) => self.handle_notification(method, message.get("params")),
+            (None, false) => self.handle_response(id, &message),
+            (None, true) => {}
+        }
+    }
+
+    /// Respond to what a server asks the editor, with nothing set
+    fn answer(&self, method: &str, id: &Value, params: &Value) {
+        let result = match method {
+            "workspace/configuration" => Value::Array(
+                params
+                    .get("items")
+                    .items()
+                    .iter()
+                    .map(|_| Value::Null)
+                    .collect(),
+            ),
+            _ => Value::Null,
+        };
+        self.write(&Value::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            ("result", result),
+        ]));
+    }
+
+    fn handle_notification(&self, method: &str, params: &Value) {
+        let shared = self.shared.lock().expect("not poisoned");
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let path = match params.get("uri").as_str().and_then(|uri| self.path_of(uri)) {
+                    Some(path) => path,
+                    None => return,
+                };
+                let text = match shared.documents.get(&path) {
+                    Some(text) => text,
+                    None => return,
+                };
+                let mut diagnostics: Vec<Diagnostic> = params
+                    .get("diagnostics")
+                    .items()
+                    .iter()
+                    .map(|diagnostic| {
+                        let range = diagnostic.get("range");
+                        let idx = |end| to_idx(range.get(end), text, shared.encoding);
+                        Diagnostic {
+                            start: idx("start"),
+                            end: idx("end"),
+                            severity: match diagnostic.get("severity").as_u64() {
+                                Some(2) => Severity::Warning,
+                                Some(3) => Severity::Info,
+                                Some(4) => Severity::Hint,
+                                _ => Severity::Error,
+                            },
+                            message: diagnostic.get("message").as_str().unwrap_or("").to_owned(),
+                            source: diagnostic.get("source").as_str().map(str::to_owned),
+                        }
+                    })
+                    .collect();
+                diagnostics.sort_by_key(|diagnostic| (diagnostic.start, diagnostic.end));
+                let name = shared.info.name.clone();
+                self.event(Event::Diagnostics(path, name, diagnostics));
+            }
+            // errors and warnings only
+            "window/showMessage" if params.get("type").as_u64().is_some_and(|t| t <= 2) => {
+                let message = params.get("message").as_str().unwrap_or("");
+                self.event(Event::Message(format!("{}: {}", shared.info.name, message)));
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_response(&self, id: &Value, message: &Value) {
+        let mut shared = self.shared.lock().expect("not poisoned");
+        let pending = match id.as_u64().and_then(|id| shared.pending.remove(&id)) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let error = message.get("error").get("message").as_str();
+        match pending {
+            Pending::Initialize => {
+                if let Some(error) = error {
+                    let msg = format!("{}: {}", shared.info.name, error);
+                    shared.exited = true;
+                    self.event(Event::Message(msg));
+                    return;
+                }
+                let capabilities = message.get("result").get("capabilities");
+                shared.encoding =
+                    PositionEncoding::negotiate(capabilities.get("positionEncoding").as_str());
+                shared.info.capabilities = [
+                    ("definitionProvider", "definition"),
+                    ("referencesProvider", "references"),
+                    ("hoverProvider", "hover"),
+                    ("completionProvider", "completion"),
+                ]
+                .iter()
+                .filter(|(provider, _)| {
+                    let provider = capabilities.get(provider);
+                    !provider.is_null() && provider.as_bool() != Some(false)
+                })
+                .map(|(_, name)| (*name).to_owned())
+                .collect();
+                self.write(&Value::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("method", "initialized".into()),
+                    ("params", Value::object(vec![])),
+                ]));
+                for message in shared.queued.take().unwrap_or_default() {
+                    self.write(&message);
+                }
+                for path in shared.documents.keys() {
+                    self.event(Event::Attach(path.clone(), shared.info.clone()));
+                }
+            }
+            Pending::Query(id, query) => {
+                if let Some(error) = error {
+                    // to end the query, then tell why
+                    self.event(Event::Answer(id, QueryResult::Locations(vec![])));
+                    let msg = format!("{}: {}", shared.info.name, error);
+                    self.event(Event::Message(msg));
+                    return;
+                }
+                let result = message.get("result");
+                let answer = match query {
+                    Query::Hover => QueryResult::Hover(hover_text(result.get("contents"))),
+                    Query::Definition | Query::References => {
+                        let locations = match result {
+                            Value::Array(items) => items.iter().collect(),
+                            Value::Null => vec![],
+                            location => vec![location],
+                        };
+                        let entries = locations
+                            .into_iter()
+                            .filter_map(|location| self.entry(&shared, location))
+                            .collect();
+                        QueryResult::Locations(entries)
+                    }
+                };
+                self.event(Event::Answer(id, answer));
+            }
+            Pending::Completion(id) => {
+                let result = message.get("result");
+                let items = match result {
+                    Value::Array(items) => items,
+                    list => list.get("items").items(),
+                };
+                let candidates = items
+                    .iter()
+                    .filter_map(completion_text)
+                    .map(|label| Candidate {
+                        label,
+                        source: shared.info.name.clone(),
+                    })
+                    .collect();
+                self.event(Event::Completions(id, candidates));
+            }
+        }
+    }
+
+    /// A `Location` or `LocationLink` as a quickfix entry, with the text
+    /// of its line from the document, or else the file
+    fn entry(&self, shared: &Shared, location: &Value) -> Option<quickfix::Entry> {
+        let (uri, range) = match location.get("targetUri").as_str() {
+            Some(uri) => (uri, location.get("targetSelectionRange")),
+            None => (location.get("uri").as_str()?, location.get("range")),
+        };
+        let path = self.path_of(uri)?;
+        let read;
+        let text = match shared.documents.get(&path) {
+            Some(text) => text,
+            None => {
+                read = Rope::from_str(&std::fs::read_to_string(self.root.join(&path)).ok()?);
+                &read
+            }
+        };
+        let start = to_idx(range.get("start"), text, shared.encoding);
+        let end = to_idx(range.get("end"), text, shared.encoding);
+        let line = text.char_to_line(start.0);
+        let line_start = text.line_to_char(line);
+        let line_text = text.line(line).to_string();
+        let line_text = line_text.trim_end_matches(&['\r', '\n'][..]);
+        let byte = |idx: usize| {
+            line_text
+                .char_indices()
+                .nth(idx - line_start)
+                .map_or(line_text.len(), |(byte, _)| byte)
+        };
+        let end = if text.char_to_line(end.0) == line {
+            byte(end.0)
+        } else {
+            line_text.len()
+        };
+        Some(quickfix::Entry {
+            path,
+            line,
+            text: line_text.to_owned(),
+            matched: Some(byte(start.0)..end),
+        })
+    }
+
+    /// The path of a `file://` URI, relative to the project if it's in it
+    fn path_of(&self, uri: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(percent_decode(uri.strip_prefix("file://")?)?);
+        Some(
+            path.strip_prefix(&self.root)
+                .map_or(path.clone(), Path::to_owned),
+        )
+    }
+
+    fn exited(&self) {
+        let mut shared = self.shared.lock().expect("not poisoned");
+        shared.exited = true;
+        let name = shared.info.name.clone();
+        for (_, pending) in shared.pending.drain() {
+            if let Pending::Query(id, _) = pending {
+                self.event(Event::Answer(id, QueryResult::Locations(vec![])));
+            }
+        }
+        for path in shared.documents.keys() {
+            self.event(Event::Detach(path.clone(), name.clone()));
+        }
+        self.event(Event::Message(format!("{} exited", name)));
+    }
+
+    fn event(&self, event: Event) {
+        let _ = self.tx.send(Input::Lsp(event));
+    }
+}
+
+/// The servers, started as needed; used by the handlers only
+struct Client {
+    configs: Vec<ServerConfig>,
+    root: PathBuf,
+    /// By language, `None` if it couldn't be started
+    servers: RefCell<HashMap<String, Option<Arc<Server>>>>,
+    /// Language of each document open
+    languages: RefCell<HashMap<PathBuf, String>>,
+    tx: mpsc::Sender<Input>,
+}
+
+impl Client {
+    /// The server of `language`, started if it's not yet
+    fn server(&self, language: &str) -> Option<Arc<Server>> {
+        let config = self.configs.iter().find(|c| c.language == language)?;
+        let mut servers = self.servers.borrow_mut();
+        let server = servers.entry(language.to_owned()).or_insert_with(|| {
+            match Server::start(config, &self.root, self.tx.clone()) {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    let msg = format!("{}: {}", config.command[0], e);
+                    let _ = self.tx.send(Input::Lsp(Event::Message(msg)));
+                    None
+                }
+            }
+        });
+        server.clone()
+    }
+
+    /// The server of a document open
+    fn server_of(&self, path: &Path) -> Option<Arc<Server>> {
+        let language = self.languages.borrow().get(path)?.clone();
+        self.server(&language)
+    }
+
+    fn sync(&self, sync: &DocumentSync) {
+        match sync {
+            DocumentSync::Open {
+                path,
+                language,
+                version,
+                text,
+            } => {
+                let server = match self.server(language) {
+                    Some(server) => server,
+                    None => return,
+                };
+                self.languages
+                    .borrow_mut()
+                    .insert(path.clone(), language.clone());
+                let mut shared = server.shared.lock().expect("not poisoned");
+                if shared.exited {
+                    return;
+                }
+                shared.documents.insert(path.clone(), text.clone());
+                let document = Value::object(vec![
+                    ("uri", uri_of(&self.root.join(path)).into()),
+                    ("languageId", language_id(language).into()),
+                    ("version", (*version).into()),
+                    ("text", text.to_string().into()),
+                ]);
+                let params = Value::object(vec![("textDocument", document)]);
+                server.notify(&mut shared, "textDocument/didOpen", params);
+                // else attached once initialized
+                if shared.queued.is_none() {
+                    let info = shared.info.clone();
+                    let _ = self.tx.send(Input::Lsp(Event::Attach(path.clone(), info)));
+                }
+            }
+            DocumentSync::Change {
+                path,
+                version,
+                text,
+            } => {
+                let server = match self.server_of(path) {
+                    Some(server) => server,
+                    None => return,
+                };
+                let mut shared = server.shared.lock().expect("not poisoned");
+                shared.documents.insert(path.clone(), text.clone());
+                let document = Value::object(vec![
+                    ("uri", uri_of(&self.root.join(path)).into()),
+                    ("version", (*version).into()),
+                ]);
+                let change = Value::object(vec![("text", text.to_string().into())]);
+                let params = Value::object(vec![
+                    ("textDocument", document),
+                    ("contentChanges", vec![change].into()),
+                ]);
+                server.notify(&mut shared, "textDocument/didChange", params);
+            }
+            DocumentSync::Close { path } => {
+                let server = match self.server_of(path) {
+                    Some(server) => server,
+                    None => return,
+                };
+                self.languages.borrow_mut().remove(path);
+                let mut shared = server.shared.lock().expect("not poisoned");
+                shared.documents.remove(path);
+                let document = Value::object(vec![("uri", uri_of(&self.root.join(path)).into())]);
+                let params = Value::object(vec![("textDocument", document)]);
+                server.notify(&mut shared, "textDocument/didClose", params);
+            }
+        }
+    }
+
+    fn query(&self, request: QueryRequest) -> io::Result<()> {
+        let not_running = || io::Error::new(io::ErrorKind::NotConnected, "server not running");
+        let server = self.server_of(&request.path).ok_or_else(not_running)?;
+        let mut shared = server.shared.lock().expect("not poisoned");
+        if shared.exited {
+            return Err(not_running());
+        }
+        let mut params = self
+            .position_params(&shared, &request.path, &request.position)
+            .ok_or_else(not_running)?;
+        let method = match request.query {
+            Query::Definition => "textDocument/definition",
+            Query::References => {
+                let context = Value::object(vec![("includeDeclaration", true.into())]);
+                params.push(("context", context));
+                "textDocument/references"
+            }
+            Query::Hover => "textDocument/hover",
+        };
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared
+            .pending
+            .insert(id, Pending::Query(request.id, request.query));
+        server.send(
+            &mut shared,
+            self::request(id, method, Value::object(params)),
+        );
+        Ok(())
+    }
+
+    /// Ask the server of the document for completions, if it does them
+    fn complete(&self, request: &CompletionRequest) {
+        let server = match request.path.as_ref().and_then(|path| self.server_of(path)) {
+            Some(server) => server,
+            None => return,
+        };
+        let mut shared = server.shared.lock().expect("not poisoned");
+        let able = shared.info.capabilities.iter().any(|c| c == "completion");
+        if shared.exited || !able {
+            return;
+        }
+        let path = request.path.as_ref().expect("has a server");
+        let params = match self.position_params(&shared, path, &request.position) {
+            Some(params) => params,
+            None => return,
+        };
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared.pending.insert(id, Pending::Completion(request.id));
+        server.send(
+            &mut shared,
+            self::request(id, "textDocument/completion", Value::object(params)),
+        );
+    }
+
+    /// The params of a request about `position` in the document `path`,
+    /// the position encoded as the server wants
+    fn position_params(
+        &self,
+        shared: &Shared,
+        path: &Path,
+        position: &libbrz::lsp::Position,
+    ) -> Option<Vec<(&'static str, Value)>> {
+        let text = shared.documents.get(path)?;
+        let line = position.line.min(text.len_lines().saturating_sub(1));
+        let idx = Idx((text.line_to_char(line) + position.character).min(text.len_chars()));
+        let position = EncodedPosition::from_idx(idx, text, shared.encoding);
+        Some(vec![
+            (
+                "textDocument",
+                Value::object(vec![("uri", uri_of(&self.root.join(path)).into())]),
+            ),
+            (
+                "position",
+                Value::object(vec![
+                    ("line", position.line.into()),
+                    ("character", position.character.into()),
+                ]),
+            ),
+        ])
+    }
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    Value::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+/// What the editor tells servers it does
+fn client_capabilities() -> Value {
+    let encodings = PositionEncoding::SUPPORTED
+        .iter()
+        .map(|encoding| encoding.as_str().into())
+        .collect::<Vec<Value>>();
+    let formats = vec!["plaintext".into(), "markdown".into()];
+    Value::object(vec![
+        (
+            "general",
+            Value::object(vec![("positionEncodings", encodings.into())]),
+        ),
+        (
+            "textDocument",
+            Value::object(vec![
+                ("synchronization", Value::object(vec![])),
+                ("publishDiagnostics", Value::object(vec![])),
+                (
+                    "hover",
+                    Value::object(vec![("contentFormat", formats.into())]),
+                ),
+                (
+                    "definition",
+                    Value::object(vec![("linkSupport", true.into())]),
+                ),
+                ("references", Value::object(vec![])),
+                ("completion", Value::object(vec![])),
+            ]),
+        ),
+    ])
+}
+
+/// The text a completion item inserts, as the word completed is
+/// replaced with it; the label if that's a snippet
+fn completion_text(item: &Value) -> Option<String> {
+    let label = item.get("label").as_str()?;
+    // 2 is a snippet, with placeholders
+    if item.get("insertTextFormat").as_u64() == Some(2) {
+        return Some(label.to_owned());
+    }
+    let text = item
+        .get("textEdit")
+        .get("newText")
+        .as_str()
+        .or_else(|| item.get("insertText").as_str())
+        .unwrap_or(label);
+    Some(text.to_owned())
+}
+
+/// A message read from a server, without its headers; `None` at the end
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && len.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            len = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; len.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A `Position` of a server in `text`
+fn to_idx(position: &Value, text: &Rope, encoding: PositionEncoding) -> Idx {
+    EncodedPosition {
+        line: position.get("line").as_usize().unwrap_or(0),
+        character: position.get("character").as_usize().unwrap_or(0),
+    }
+    .to_idx(text, encoding)
+}
+
+/// Hover contents, a `MarkupContent` or (a list of) `MarkedString`s, as
+/// text
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(hover_text)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        contents => contents.get("value").as_str().unwrap_or("").to_owned(),
+    }
+}
+
+/// The protocol's name of a language, where it's not the editor's
+fn language_id(language: &str) -> &str {
+    match language {
+        "shell" => "shellscript",
+        "make" => "makefile",
+        language => language,
+    }
+}
+
+fn uri_of(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.to_string_lossy().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
@@ -1,17 +1,100 @@
-use std::path::Path;
-
 use structopt::StructOpt;
 use termion::event::Event;
 use termion::input::TermRead;
 
-use ropey::Rope;
-use std;
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
+mod clipboard;
+mod config;
+mod env;
+mod git;
+#[cfg(target_os = "linux")]
+mod inotify;
+mod lsp;
 mod opts;
+mod pipe;
+mod plugin;
+mod remote;
 mod render;
+mod resource;
+mod swap;
+mod tool;
+mod trash;
+mod undo;
+mod vfs;
+mod write;
+
+use libbrz::{
+    git::GitRequest,
+    grep,
+    prelude::*,
+    preview,
+    profile::FrameTiming,
+    quickfix,
+    remote::Location,
+    startup::{Phase, Startup},
+    state::State,
+    syntax, todo,
+};
+
+const MAX_GREP_RESULTS: usize = 1000;
 
-use libbrz::{prelude::*, state::State};
+/// What terminals send around a paste, with bracketed paste on
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// How long the input has to be idle before background work starts
+const IDLE_DELAY: Duration = Duration::from_millis(150);
+
+/// Everything the main loop waits on
+enum Input {
+    Term(std::io::Result<Event>),
+    Preview(u64, preview::Preview),
+    /// Sent over the socket, by `brz --remote` or a client of `--server`,
+    /// and where to send the answer
+    Remote(libbrz::remote::Request, mpsc::Sender<String>),
+    Highlights(u64, Vec<syntax::Highlight>),
+    GitBase(u64, Option<String>),
+    Todos(u64, Vec<todo::Todo>),
+    /// Files of the project found, and whether the walk is over
+    Files(u64, Vec<std::path::PathBuf>, bool),
+    /// Matches found by a grep, and whether the search is over
+    Grep(u64, Vec<quickfix::Entry>, bool),
+    /// Output of the runs of a pipe, or why it failed
+    Piped(u64, std::result::Result<Vec<String>, String>),
+    /// File watched that changed on disk
+    FileChanged(std::path::PathBuf),
+    Lsp(lsp::Event),
+    /// What a plugin asked, or why it's not understood
+    Plugin(usize, std::result::Result<libbrz::plugin::Request, String>),
+}
+
+/// What the editor takes of a mouse event; termion counts cells from 1
+fn termion_to_brz_mouse(mouse: termion::event::MouseEvent) -> Option<libbrz::Mouse> {
+    use termion::event::{MouseButton, MouseEvent};
+    let at = |x: u16, y: u16| libbrz::render::Coord {
+        x: usize::from(x.saturating_sub(1)),
+        y: usize::from(y.saturating_sub(1)),
+    };
+    Some(match mouse {
+        MouseEvent::Press(MouseButton::Left, x, y) => libbrz::Mouse::Press(at(x, y)),
+        MouseEvent::Press(MouseButton::WheelUp, x, y) => libbrz::Mouse::Scroll {
+            up: true,
+            at: at(x, y),
+        },
+        MouseEvent::Press(MouseButton::WheelDown, x, y) => libbrz::Mouse::Scroll {
+            up: false,
+            at: at(x, y),
+        },
+        MouseEvent::Press(..) => return None,
+        MouseEvent::Hold(x, y) => libbrz::Mouse::Drag(at(x, y)),
+        MouseEvent::Release(x, y) => libbrz::Mouse::Release(at(x, y)),
+    })
+}
 
 fn termion_to_brz_key(key: termion::event::Key) -> libbrz::Key {
     match key {
@@ -36,27 +119,22 @@ fn termion_to_brz_key(key: termion::event::Key) -> libbrz::Key {
 /// Screen drawing + state handling
 struct Breeze {
     state: State,
-    render: render::Render,
+    /// None when running as `--server`
+    render: Option<render::Render>,
+    input_tx: mpsc::Sender<Input>,
+    input_rx: mpsc::Receiver<Input>,
 }
 
 impl Breeze {
-    fn init() -> Result<Self> {
+    fn init(headless: bool) -> Result<Self> {
         let mut state: State = default();
+        let (input_tx, input_rx) = mpsc::channel();
 
-        state.register_read_handler(|path| {
-            Rope::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
-        });
-
-        state.register_write_handler(|path, rope| {
-            let tmp_path = path.with_extension("brz.tmp");
-            rope.write_to(std::io::BufWriter::new(std::fs::File::create(&tmp_path)?))?;
-            std::fs::rename(tmp_path, path)?;
-            Ok(())
-        });
+        vfs::register(&mut state, Arc::new(vfs::LocalFs), input_tx.clone());
+        state.register_elevated_write_handler(write::sudo_write);
 
         state.register_find_handler(|pattern| {
             Ok(ignore::Walk::new(".")
-                .into_iter()
                 .filter_map(|entry| entry.ok())
                 .filter(|entry| entry.file_type().map(|f| f.is_file()).unwrap_or(false))
                 .filter(|entry| {
@@ -81,16 +159,213 @@ impl Breeze {
                 .take(10)
                 .collect())
         });
+
+        state.register_grep_handler(|pattern| {
+            let re = regex::Regex::new(pattern)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            let mut entries = vec![];
+            for entry in ignore::Walk::new(".").filter_map(|entry| entry.ok()) {
+                if !entry.file_type().map(|f| f.is_file()).unwrap_or(false) {
+                    continue;
+                }
+                // binary and non-UTF-8 files are skipped
+                let content = match std::fs::read_to_string(entry.path()) {
+                    Ok(content) => content,
+                    Err(_) => continue,
+                };
+                let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                entries.extend(grep::matches(&re, path, &content));
+                if MAX_GREP_RESULTS <= entries.len() {
+                    entries.truncate(MAX_GREP_RESULTS);
+                    return Ok(entries);
+                }
+            }
+            Ok(entries)
+        });
+
+        let grep_tx = input_tx.clone();
+        state.register_project_search_handler(move |req| {
+            let re = regex::Regex::new(&req.pattern)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            let tx = grep_tx.clone();
+            thread::spawn(move || {
+                let (re, req) = (Arc::new(re), Arc::new(req));
+                let found = Arc::new(AtomicUsize::new(0));
+                ignore::WalkBuilder::new(".").build_parallel().run(|| {
+                    let (re, req, tx, found) = (re.clone(), req.clone(), tx.clone(), found.clone());
+                    Box::new(move |entry| {
+                        use ignore::WalkState;
+                        let entry = match entry {
+                            Ok(entry) => entry,
+                            Err(_) => return WalkState::Continue,
+                        };
+                        if req.cancel.is_cancelled()
+                            || MAX_GREP_RESULTS <= found.load(Ordering::Relaxed)
+                        {
+                            return WalkState::Quit;
+                        }
+                        if !entry.file_type().is_some_and(|f| f.is_file()) {
+                            return WalkState::Continue;
+                        }
+                        // binary and non-UTF-8 files are skipped
+                        let content = match std::fs::read_to_string(entry.path()) {
+                            Ok(content) => content,
+                            Err(_) => return WalkState::Continue,
+                        };
+                        let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                        let mut entries = grep::matches(&re, path, &content);
+                        if entries.is_empty() {
+                            return WalkState::Continue;
+                        }
+                        let before = found.fetch_add(entries.len(), Ordering::Relaxed);
+                        entries.truncate(MAX_GREP_RESULTS.saturating_sub(before));
+                        match tx.send(Input::Grep(req.id, entries, false)) {
+                            Ok(()) => WalkState::Continue,
+                            Err(_) => WalkState::Quit,
+                        }
+                    })
+                });
+                if !req.cancel.is_cancelled() {
+                    let _ = tx.send(Input::Grep(req.id, vec![], true));
+                }
+            });
+            Ok(())
+        });
+
+        state.register_git_handler(git::run);
+        let git_base_tx = input_tx.clone();
+        state.register_git_base_handler(move |req| {
+            let tx = git_base_tx.clone();
+            thread::spawn(move || {
+                let base = git::run(&GitRequest::Show {
+                    rev: "HEAD".into(),
+                    path: req.path,
+                });
+                let _ = tx.send(Input::GitBase(req.id, base.ok()));
+            });
+            Ok(())
+        });
+        state.register_resource_handler(resource::apply);
+        state.register_trash_handler(trash::apply);
+        state.register_undo_file_handler(undo::apply);
+        state.register_swap_handler(swap::apply);
+        state.register_clipboard_handler(clipboard::handler(None));
+        state.register_health_check("clipboard", || clipboard::health(None));
+        state.register_health_check("terminal", render::health);
+        state.register_health_check("tools", env::health);
+        state.register_chdir_handler(|dir| std::env::set_current_dir(dir));
+
+        let preview_tx = input_tx.clone();
+        state.register_preview_handler(move |req| {
+            let tx = preview_tx.clone();
+            thread::spawn(move || {
+                let preview = match std::fs::File::open(&req.path)
+                    .and_then(|file| preview::load_partial(file, req.max_bytes, &req.cancel))
+                {
+                    Ok(Some(preview)) => preview,
+                    Ok(None) => return,
+                    Err(e) => preview::Preview::Failed(e.to_string()),
+                };
+                let _ = tx.send(Input::Preview(req.id, preview));
+            });
+            Ok(())
+        });
+
+        let highlight_tx = input_tx.clone();
+        state.register_highlight_handler(move |req| {
+            let tx = highlight_tx.clone();
+            thread::spawn(move || {
+                if let Some(highlights) =
+                    req.highlighter
+                        .highlight(&req.text, &req.language, &req.cancel)
+                {
+                    let _ = tx.send(Input::Highlights(req.id, highlights));
+                }
+            });
+            Ok(())
+        });
+
+        let todo_tx = input_tx.clone();
+        state.register_todo_handler(move |req| {
+            let tx = todo_tx.clone();
+            thread::spawn(move || {
+                let mut todos = vec![];
+                for entry in ignore::Walk::new(".").filter_map(|entry| entry.ok()) {
+                    if req.cancel.is_cancelled() {
+                        return;
+                    }
+                    if !entry.file_type().map(|f| f.is_file()).unwrap_or(false) {
+                        continue;
+                    }
+                    // binary and non-UTF-8 files are skipped
+                    let content = match std::fs::read_to_string(entry.path()) {
+                        Ok(content) => content,
+                        Err(_) => continue,
+                    };
+                    let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                    todos.extend(todo::scan(path, &content));
+                }
+                let _ = tx.send(Input::Todos(req.id, todos));
+            });
+            Ok(())
+        });
+
+        let files_tx = input_tx.clone();
+        state.register_file_list_handler(move |req| {
+            let tx = files_tx.clone();
+            thread::spawn(move || {
+                const BATCH: usize = 1000;
+                let mut paths = vec![];
+                for entry in ignore::Walk::new(".").filter_map(|entry| entry.ok()) {
+                    if req.cancel.is_cancelled() {
+                        return;
+                    }
+                    if !entry.file_type().map(|f| f.is_file()).unwrap_or(false) {
+                        continue;
+                    }
+                    let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                    paths.push(path.to_owned());
+                    if paths.len() == BATCH {
+                        let batch = std::mem::take(&mut paths);
+                        if tx.send(Input::Files(req.id, batch, false)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                let _ = tx.send(Input::Files(req.id, paths, true));
+            });
+            Ok(())
+        });
+
+        let pipe_tx = input_tx.clone();
+        state.register_pipe_handler(move |req| {
+            let tx = pipe_tx.clone();
+            thread::spawn(move || {
+                let outputs = match pipe::run(&req) {
+                    Err(_) if req.cancel.is_cancelled() => return,
+                    outputs => outputs.map_err(|e| e.to_string()),
+                };
+                let _ = tx.send(Input::Piped(req.id, outputs));
+            });
+            Ok(())
+        });
+
         let breeze = Breeze {
             state,
-            render: render::Render::new()?,
+            render: if headless {
+                None
+            } else {
+                Some(render::Render::new()?)
+            },
+            input_tx,
+            input_rx,
         };
 
         Ok(breeze)
     }
 
-    fn open(&mut self, path: &Path) -> Result<()> {
-        self.state.open_buffer(path);
+    fn open(&mut self, location: &Location) -> Result<()> {
+        self.state.open_location(location);
 
         Ok(())
     }
@@ -98,43 +373,552 @@ impl Breeze {
     fn run(&mut self) -> Result<()> {
         self.draw_buffer()?;
 
-        let stdin = std::io::stdin();
-        for e in stdin.events() {
+        if self.render.is_some() {
+            let term_tx = self.input_tx.clone();
+            thread::spawn(move || {
+                for e in std::io::stdin().events() {
+                    if term_tx.send(Input::Term(e)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let mut last_key_time = Instant::now();
+        let mut idle = false;
+        // text pasted so far, while in a paste
+        let mut paste: Option<String> = None;
+        // taken while looking for repeats of a key, to handle next
+        let mut pending: Option<Input> = None;
+        // time spent blocked waiting for the input is idle time, not latency
+        loop {
+            let input = if let Some(input) = pending.take() {
+                Some(input)
+            } else if idle {
+                match self.state.get_mode().timeout() {
+                    Some(timeout) => {
+                        let left = timeout.saturating_sub(last_key_time.elapsed());
+                        match self.input_rx.recv_timeout(left) {
+                            Ok(input) => Some(input),
+                            Err(mpsc::RecvTimeoutError::Timeout) => {
+                                self.state.note_time(SystemTime::now());
+                                self.state.note_idle(last_key_time.elapsed());
+                                self.draw_buffer()?;
+                                continue;
+                            }
+                            Err(mpsc::RecvTimeoutError::Disconnected) => None,
+                        }
+                    }
+                    None => self.input_rx.recv().ok(),
+                }
+            } else {
+                match self.input_rx.recv_timeout(IDLE_DELAY) {
+                    Ok(input) => Some(input),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        self.state.request_highlights();
+                        self.state.request_document_highlights();
+                        self.state.update_git_gutter();
+                        self.state.note_time(SystemTime::now());
+                        self.state.write_swaps();
+                        self.draw_buffer()?;
+                        idle = true;
+                        continue;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => None,
+                }
+            };
+            let input = match input {
+                Some(input) => input,
+                None => break,
+            };
+            let e = match input {
+                Input::Term(e) => e,
+                Input::Preview(id, preview) => {
+                    if self.state.deliver_preview(id, preview) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::Remote(request, answer_tx) => {
+                    let answer = self.serve(request);
+                    let _ = answer_tx.send(libbrz::remote::answer(Ok(answer)));
+                    if self.state.is_finished() {
+                        return Ok(());
+                    }
+                    self.draw_buffer()?;
+                    idle = false;
+                    continue;
+                }
+                Input::Highlights(id, highlights) => {
+                    if self.state.deliver_highlights(id, highlights) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::GitBase(id, base) => {
+                    if self.state.deliver_git_base(id, base) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::Todos(id, todos) => {
+                    if self.state.deliver_todos(id, todos) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::Files(id, paths, done) => {
+                    if self.state.deliver_files(id, paths, done) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::Grep(id, entries, done) => {
+                    if self.state.deliver_grep(id, entries, done) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::Piped(id, outputs) => {
+                    if self.state.deliver_pipe(id, outputs) {
+                        self.draw_buffer()?;
+                    }
+                    continue;
+                }
+                Input::FileChanged(path) => {
+                    self.state.file_changed(&path);
+                    self.draw_buffer()?;
+                    continue;
+                }
+                Input::Lsp(event) => {
+                    lsp::deliver(&mut self.state, event);
+                    self.draw_buffer()?;
+                    continue;
+                }
+                Input::Plugin(plugin_i, request) => {
+                    plugin::deliver(&mut self.state, plugin_i, request);
+                    self.draw_buffer()?;
+                    continue;
+                }
+            };
+            idle = false;
+            let input_start = Instant::now();
+            let mut timing = FrameTiming::default();
+
             // TODO: https://gitlab.redox-os.org/redox-os/termion/issues/151
-            match e {
-                Ok(Event::Key(key)) => {
-                    self.state.handle_key(termion_to_brz_key(key));
+            let key = match e {
+                Ok(Event::Key(key)) if paste.is_some() => {
+                    if let (Some(paste), libbrz::Key::Char(ch)) =
+                        (paste.as_mut(), termion_to_brz_key(key))
+                    {
+                        paste.push(ch);
+                    }
+                    continue;
+                }
+                Ok(Event::Key(key)) => Some(termion_to_brz_key(key)),
+                Ok(Event::Unsupported(ref u)) if u == PASTE_START => {
+                    paste = Some(String::new());
+                    continue;
                 }
-                Ok(Event::Unsupported(_u)) => {}
-                Ok(Event::Mouse(_)) => {
-                    // no animal support yet
+                Ok(Event::Unsupported(ref u)) if u == PASTE_END => {
+                    if let Some(text) = paste.take() {
+                        self.state.handle_paste(&text);
+                        last_key_time = Instant::now();
+                    }
+                    None
+                }
+                Ok(Event::Unsupported(_u)) => None,
+                Ok(Event::Mouse(mouse)) => {
+                    if let Some(mouse) = termion_to_brz_mouse(mouse) {
+                        self.state.handle_mouse(mouse);
+                    }
+                    None
                 }
                 Err(e) => panic!("{}", e),
+            };
+            timing.input = input_start.elapsed();
+
+            // drawn once for the repeats already waiting
+            let mut times = 1;
+            if let Some(key) = key.filter(|&key| self.state.coalesces(key)) {
+                while times < libbrz::repeat::MAX_REPEATS {
+                    match self.input_rx.try_recv() {
+                        Ok(Input::Term(Ok(Event::Key(next))))
+                            if termion_to_brz_key(next) == key =>
+                        {
+                            times += 1;
+                        }
+                        Ok(other) => {
+                            pending = Some(other);
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
             }
 
+            let update_start = Instant::now();
+            if let Some(key) = key {
+                self.state.note_time(SystemTime::now());
+                self.state
+                    .note_idle(input_start.duration_since(last_key_time));
+                self.state.handle_repeated_key(key, times);
+                last_key_time = Instant::now();
+            }
+            timing.update = update_start.elapsed();
+
             if self.state.is_finished() {
                 return Ok(());
             }
+
+            let render_start = Instant::now();
             self.draw_buffer()?;
+            timing.render = render_start.elapsed();
+
+            self.state.profiler.record(timing);
         }
         Ok(())
     }
 
+    /// Do what is asked over the socket, and tell what to answer
+    fn serve(&mut self, request: libbrz::remote::Request) -> libbrz::json::Value {
+        use libbrz::remote::Request;
+        match request {
+            Request::Open(mut location) => {
+                if let Ok(cwd) = std::env::current_dir() {
+                    location.path = libbrz::remote::relative_to(&location.path, &cwd);
+                }
+                self.state.open_location(&location);
+            }
+            Request::Keys(keys) => {
+                self.state.note_time(SystemTime::now());
+                for key in keys {
+                    self.state.handle_key(key);
+                    if self.state.is_finished() {
+                        break;
+                    }
+                }
+            }
+            Request::State { text } => return self.state.remote_state(text),
+        }
+        libbrz::json::Value::Null
+    }
+
     fn draw_buffer(&mut self) -> Result<()> {
-        self.render.draw(&self.state)?;
+        if let Some(render) = &mut self.render {
+            let cells = render.draw(&self.state)?;
+            self.state.profiler.record_cells_drawn(cells);
+        }
         Ok(())
     }
 }
 
+/// What startup sets up, and what its phases leave to those after
+struct Setup {
+    brz: Breeze,
+    opt: opts::Opts,
+    /// Files to open
+    locations: Vec<Location>,
+    /// Of `config.toml` of the user
+    tables: Vec<libbrz::config::Table>,
+    /// Serving `--remote`, if this is the first instance
+    server: Option<remote::Server>,
+}
+
+/// What the features that only work on local files fail with over ssh
+fn not_over_ssh() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "not over ssh")
+}
+
+/// The hooks of startup, in the phases they belong to
+fn startup() -> Startup<Setup, failure::Error> {
+    let mut startup = Startup::new();
+    startup.add(Phase::Core, "options", |setup: &mut Setup| {
+        let (state, opt) = (&mut setup.brz.state, &setup.opt);
+        state.profiler.show_overlay = opt.frame_timing;
+        state.set_capabilities(render::capabilities());
+        let mut limits = libbrz::document::LargeFileLimits::default();
+        limits.lines = opt.large_file_lines.unwrap_or(limits.lines);
+        limits.bytes = opt.large_file_bytes.unwrap_or(limits.bytes);
+        limits.preview_bytes = opt.large_file_preview_bytes.unwrap_or(limits.preview_bytes);
+        state.set_large_file_limits(limits);
+        state.set_delete_to_trash(!opt.no_trash);
+        Ok(())
+    });
+    startup.add(Phase::Core, "ssh", |setup: &mut Setup| {
+        let brz = &mut setup.brz;
+        let host = match &setup.opt.ssh {
+            Some(host) => host,
+            None => return Ok(()),
+        };
+        vfs::register(
+            &mut brz.state,
+            Arc::new(vfs::SshFs::new(host)),
+            brz.input_tx.clone(),
+        );
+        let state = &mut brz.state;
+        state.register_grep_handler(|_pattern| Err(not_over_ssh()));
+        state.register_project_search_handler(|_request| Err(not_over_ssh()));
+        state.register_todo_handler(|_request| Err(not_over_ssh()));
+        state.register_file_list_handler(|_request| Err(not_over_ssh()));
+        state.register_git_base_handler(|_request| Err(not_over_ssh()));
+        Ok(())
+    });
+    startup.add(Phase::Config, "keys", |setup: &mut Setup| {
+        let state = &mut setup.brz.state;
+        if let Some(layers) = config::read("layers")? {
+            state.define_layers(&layers);
+        }
+        if let Some(keys) = config::read("keys")? {
+            state.define_keys(&keys, false);
+        }
+        if let Some(keys) = config::read_project("keys")? {
+            state.define_keys(&keys, true);
+        }
+        Ok(())
+    });
+    startup.add(Phase::Config, "config.toml", |setup: &mut Setup| {
+        let state = &mut setup.brz.state;
+        if let Some(text) = config::read("config.toml")? {
+            state.define_config(&text, false);
+            setup.tables = libbrz::config::parse(&text).unwrap_or_default();
+        }
+        let tables = &setup.tables;
+        if let Some(libbrz::config::Value::String(numbers)) =
+            libbrz::config::lookup(tables, "editor", "numbers")
+        {
+            if let Some(numbers) = libbrz::document::LineNumbers::parse(numbers) {
+                state.set_line_numbers(numbers);
+            }
+        }
+        if let Some(libbrz::config::Value::String(coalesce)) =
+            libbrz::config::lookup(tables, "input", "coalesce")
+        {
+            if let Some(coalescing) = libbrz::repeat::Coalescing::parse(coalesce) {
+                state.set_key_coalescing(coalescing);
+            }
+        }
+        if let Some(libbrz::config::Value::Boolean(keep)) =
+            libbrz::config::lookup(tables, "scratch", "keep")
+        {
+            state.set_keep_scratch(*keep);
+        }
+        if let Some(libbrz::config::Value::Boolean(auto_reload)) =
+            libbrz::config::lookup(tables, "files", "auto-reload")
+        {
+            state.set_auto_reload(*auto_reload);
+        }
+        if let Some(libbrz::config::Value::String(provider)) =
+            libbrz::config::lookup(tables, "clipboard", "provider")
+        {
+            state.register_clipboard_handler(clipboard::handler(Some(provider)));
+            let provider = provider.clone();
+            state.register_health_check("clipboard", move || clipboard::health(Some(&provider)));
+        }
+        if let Some(text) = config::read_project("config.toml")? {
+            state.define_config(&text, true);
+        }
+        Ok(())
+    });
+    startup.add(Phase::Config, "statusline", |setup: &mut Setup| {
+        if let Some(segments) = config::read("status-segments")? {
+            setup
+                .brz
+                .state
+                .set_status_segments(&segments.replace('\n', " "));
+        }
+        if let Some(template) = config::read("statusline")? {
+            setup
+                .brz
+                .state
+                .set_status_template(template.trim_end_matches('\n'));
+        }
+        Ok(())
+    });
+    startup.add(Phase::Config, "env", |setup: &mut Setup| {
+        let state = &mut setup.brz.state;
+        if config::is_project_trusted() {
+            env::load_project(&mut state.env);
+        } else if env::has_project_env() || std::path::Path::new(".breeze").is_dir() {
+            state.show_message(
+                "the config and environment of this project are not loaded: start with --trust to trust it",
+            );
+        }
+        if let Some(text) = config::read("env")? {
+            state.define_env(&text);
+        }
+        if let Some(text) = config::read_project("env")? {
+            state.define_env(&text);
+        }
+        env::set(state.env.clone());
+        Ok(())
+    });
+    startup.add(Phase::Themes, "background", |setup: &mut Setup| {
+        // `background` of `[theme]`: `light`, `dark`, or `auto` for the terminal's
+        let background = match libbrz::config::lookup(&setup.tables, "theme", "background") {
+            Some(libbrz::config::Value::String(value)) => libbrz::theme::Background::parse(value),
+            _ => None,
+        };
+        // the terminal is only asked when there's one to draw on
+        let background = if setup.opt.server {
+            background
+        } else {
+            background.or_else(render::background)
+        };
+        if let Some(background) = background {
+            setup.brz.state.set_background(background);
+        }
+        Ok(())
+    });
+    startup.add(Phase::Themes, "themes", |setup: &mut Setup| {
+        let state = &mut setup.brz.state;
+        for (name, text) in config::read_all("themes", "toml")? {
+            state.define_theme(&name, &text);
+        }
+        if let Some(name) = config::read("theme")? {
+            state.set_theme(name.trim());
+        }
+        if let Some(colors) = config::read("colors")? {
+            state.define_colors(&colors);
+        }
+        Ok(())
+    });
+    startup.add(Phase::Plugins, "language servers", |setup: &mut Setup| {
+        // the servers run here, not where the files are
+        let servers = lsp::configs(&setup.tables);
+        let remote = setup.opt.ssh.is_some();
+        let checked = servers.clone();
+        let brz = &mut setup.brz;
+        brz.state
+            .register_health_check("language servers", move || lsp::health(&checked, remote));
+        if !remote && !servers.is_empty() {
+            lsp::register(&mut brz.state, servers, brz.input_tx.clone());
+        }
+        Ok(())
+    });
+    startup.add(Phase::Plugins, "plugins", |setup: &mut Setup| {
+        let paths = config::paths("plugins")?;
+        let brz = &mut setup.brz;
+        brz.state
+            .register_health_check("plugins", move || plugin::health(&paths));
+        if setup.opt.ssh.is_none() {
+            plugin::register(&mut brz.state, brz.input_tx.clone())?;
+        }
+        Ok(())
+    });
+    startup.add(Phase::PostStartup, "state", |setup: &mut Setup| {
+        let state = &mut setup.brz.state;
+        // losing it is no reason not to start
+        if let Ok(Some(usage)) = config::read_state("actions") {
+            state.load_action_usage(&usage);
+        }
+        if let Ok(Some(files)) = config::read_state("files") {
+            state.load_recent_files(&files);
+        }
+        if let Ok(Some(projects)) = config::read_state("projects") {
+            state.load_recent_projects(&projects);
+        }
+        if let Ok(Some(sessions)) = config::read_state("sessions") {
+            state.load_sessions(&sessions);
+        }
+        if let Ok(dir) = std::env::current_dir() {
+            state.set_project_dir(dir);
+        }
+        Ok(())
+    });
+    startup.add(Phase::PostStartup, "remote", |setup: &mut Setup| {
+        let remote_tx = setup.brz.input_tx.clone();
+        // only the first instance gets to serve `--remote`
+        let server = remote::listen(move |request, answer_tx| {
+            let _ = remote_tx.send(Input::Remote(request, answer_tx));
+        });
+        // being driven over it is what `--server` is for
+        setup.server = match server {
+            Err(e) if setup.opt.server => return Err(e.into()),
+            server => server.ok(),
+        };
+        Ok(())
+    });
+    startup.add(Phase::PostStartup, "files", |setup: &mut Setup| {
+        let brz = &mut setup.brz;
+        let mut kept = 0;
+        if brz.state.keeps_scratch() {
+            if let Ok(Some(scratch)) = config::read_state("scratch") {
+                kept = brz.state.load_scratch_buffers(&scratch);
+            }
+        }
+        let mut restored = false;
+        if let Some(path) = &setup.opt.session {
+            match brz.state.load_session(Some(path)) {
+                Ok(()) => restored = true,
+                Err(e) => brz.state.show_message(&e),
+            }
+        }
+        for location in &setup.locations {
+            brz.open(location)?;
+        }
+        if setup.locations.is_empty() && kept == 0 && !restored {
+            brz.state.open_dashboard();
+        }
+        // for the counts of the status line; they are just missing without it
+        let _ = brz.state.request_todos();
+        Ok(())
+    });
+    startup.add(Phase::PostStartup, "swap files", |setup: &mut Setup| {
+        setup.brz.state.offer_recovery(swap::left_over());
+        Ok(())
+    });
+    startup
+}
+
 fn run() -> Result<()> {
     let opt = opts::Opts::from_args();
-    let mut brz = Breeze::init()?;
+    let locations = libbrz::remote::parse_args(&opt.inputs);
+    if opt.remote && remote::send(&locations).is_ok() {
+        return Ok(());
+    }
 
-    for path in opt.inputs {
-        brz.open(&path)?;
+    let profile = match &opt.profile {
+        _ if opt.clean => config::Profile::Clean,
+        Some(name) => config::Profile::Named(name.clone()),
+        None => config::Profile::Default,
+    };
+    config::set_profile(profile)?;
+    if opt.trust {
+        config::trust_project()?;
     }
 
+    let clock = Instant::now();
+    let startuptime = opt.startuptime.clone();
+    let mut setup = Setup {
+        brz: Breeze::init(opt.server)?,
+        opt,
+        locations,
+        tables: vec![],
+        server: None,
+    };
+    let report = startup().run(&mut setup, || clock.elapsed())?;
+    if let Some(path) = startuptime {
+        std::fs::write(path, report.to_string())?;
+    }
+    let Setup {
+        mut brz,
+        server: _server,
+        ..
+    } = setup;
+
     brz.run()?;
+    brz.state.remove_swaps();
+    let _ = config::write_state("actions", &brz.state.action_usage());
+    let _ = config::write_state("files", &brz.state.recent_files());
+    let _ = config::write_state("projects", &brz.state.recent_projects());
+    let _ = config::write_state("sessions", &brz.state.sessions());
+    // those kept before are dropped once it's turned off
+    if brz.state.keeps_scratch() {
+        let _ = config::write_state("scratch", &brz.state.scratch_buffers());
+    } else if let Ok(Some(_)) = config::read_state("scratch") {
+        let _ = config::write_state("scratch", "");
+    }
     Ok(())
 }
 
@@ -5,7 +5,63 @@ use std::path::PathBuf;
 #[derive(Debug, StructOpt, Clone)]
 #[structopt(name = "indexer", about = "Bitcoin Indexer")]
 pub struct Opts {
-    //Input files or directories.
+    /// Show frame timing overlay
+    #[structopt(long = "frame-timing")]
+    pub frame_timing: bool,
+
+    /// Start with the config of this profile, in `profiles/<name>` of
+    /// the config directory
+    #[structopt(long = "profile")]
+    pub profile: Option<String>,
+
+    /// Start with no config of the user or the project, nor the state of
+    /// earlier sessions
+    #[structopt(long = "clean", conflicts_with = "profile")]
+    pub clean: bool,
+
+    /// Open the files in the instance already running, if any
+    #[structopt(long = "remote")]
+    pub remote: bool,
+
+    /// Run with no terminal, driven over the socket of `--remote`
+    #[structopt(long = "server", conflicts_with = "remote")]
+    pub server: bool,
+
+    /// Trust the project in the current directory from now on, loading
+    /// the environment it sets for the tools
+    #[structopt(long = "trust", conflicts_with = "clean")]
+    pub trust: bool,
+
+    /// Open files of at least this many lines with fewer features
+    #[structopt(long = "large-file-lines")]
+    pub large_file_lines: Option<usize>,
+
+    /// Open files of at least this many bytes with fewer features
+    #[structopt(long = "large-file-bytes")]
+    pub large_file_bytes: Option<usize>,
+
+    /// Only show the beginning of files of at least this many bytes,
+    /// read-only, until `:full`
+    #[structopt(long = "large-file-preview-bytes")]
+    pub large_file_preview_bytes: Option<u64>,
+
+    /// Delete files outright with `:delete-file`, not to the trash
+    #[structopt(long = "no-trash")]
+    pub no_trash: bool,
+
+    /// Write where startup spent its time to this file, by phase and hook
+    #[structopt(long = "startuptime", parse(from_os_str))]
+    pub startuptime: Option<PathBuf>,
+
+    /// Open the files and windows of this session, written by `:mksession`
+    #[structopt(long = "session", parse(from_os_str))]
+    pub session: Option<PathBuf>,
+
+    /// Edit the files of this host, through `ssh`
+    #[structopt(long = "ssh")]
+    pub ssh: Option<String>,
+
+    //Input files or directories, `+<line>` after a file to go to that line.
     #[structopt(parse(from_os_str))]
     pub inputs: Vec<PathBuf>,
 }
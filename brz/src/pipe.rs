@@ -0,0 +1,35 @@
+//! Pipe handler, running the commands in the shell
+use crate::{env, tool};
+use libbrz::pipe::PipeRequest;
+use std::io;
+
+/// The output of each run of the command, in order
+pub fn run(request: &PipeRequest) -> io::Result<Vec<String>> {
+    match &request.inputs {
+        Some(inputs) => inputs
+            .iter()
+            .map(|input| run_once(request, Some(input.as_bytes().to_vec())))
+            .collect(),
+        None => Ok(vec![run_once(request, None)?]),
+    }
+}
+
+fn run_once(request: &PipeRequest, input: Option<Vec<u8>>) -> io::Result<String> {
+    let mut cmd = env::system_command("sh");
+    cmd.arg("-c").arg(&request.command);
+    let output = tool::run(
+        cmd,
+        input,
+        &tool::Limits::default(),
+        &request.cancel,
+        |_| {},
+    )?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let failed = format!("{} failed", request.command);
+        return Err(io::Error::other(
+            stderr.lines().next().unwrap_or(&failed).to_owned(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,167 @@
+//! Plugins, the scripts in the `plugins` config directory
+//!
+//! Each is run in the project directory when the editor starts, by the
+//! runtime of its extension: `.lua` scripts with `lua`, `.wasm`
+//! components with `wasmtime`, and others as programs of their own. It's
+//! spoken to as described in `libbrz::plugin`, a line of JSON a message
+//! on its stdin and stdout; what it writes to stderr is dropped.
+//!
+//! A plugin is a process of its own rather than embedded, so that it can
+//! be written in anything and a crashing one doesn't take the editor with
+//! it. It's given nothing but the messages, but it's not sandboxed: it
+//! runs with the rights of the user, in the environment of the project,
+//! like the user's config it's part of, and which only the user writes.
+//! Nothing of the project is run as a plugin. The runtimes are those on
+//! the editor's own `PATH`.
+//!
+//! Events are written to a plugin from a thread of its own, through a
+//! queue of `QUEUED_EVENTS`: one that fills it, not reading what it's
+//! told, is stopped rather than made to hold up the editor.
+use crate::{config, env, Input};
+use libbrz::health::Finding;
+use libbrz::plugin::{Event, Request};
+use libbrz::state::State;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// How many events may wait for a plugin to read them
+const QUEUED_EVENTS: usize = 256;
+
+/// The program running the plugin at `path`, and its arguments before
+/// the path
+fn runtime(path: &Path) -> Option<(&'static str, &'static [&'static str])> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("lua") => Some(("lua", &[])),
+        Some("wasm") => Some(("wasmtime", &["run"])),
+        _ => None,
+    }
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Run the plugins of the config, telling the main loop what they ask
+pub fn register(state: &mut State, tx: mpsc::Sender<Input>) -> io::Result<()> {
+    for path in config::paths("plugins")? {
+        if let Err(e) = start(state, &path, tx.clone()) {
+            state.show_message(&format!("plugin {}: {}", path.display(), e));
+        }
+    }
+    Ok(())
+}
+
+fn start(state: &mut State, path: &Path, tx: mpsc::Sender<Input>) -> io::Result<()> {
+    let mut command = match runtime(path) {
+        Some((program, args)) => {
+            let mut command = env::system_command(program);
+            command.args(args).arg(path);
+            command
+        }
+        None => env::command(&path.to_string_lossy()),
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("piped");
+    let stdout = child.stdout.take().expect("piped");
+    let child: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(Some(child)));
+    let (events_tx, events_rx) = mpsc::sync_channel::<String>(QUEUED_EVENTS);
+    thread::spawn(move || {
+        for line in events_rx {
+            if writeln!(stdin, "{}", line)
+                .and_then(|()| stdin.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    let stopped = AtomicBool::new(false);
+    let name = name_of(path);
+    let stalled = child.clone();
+    let plugin_i = state.register_plugin(&name, move |event: &Event| {
+        if stopped.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let e = match events_tx.try_send(event.to_json().to_string()) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::TrySendError::Full(_)) => {
+                if let Some(child) = stalled.lock().expect("not poisoned").as_mut() {
+                    let _ = child.kill();
+                }
+                io::Error::other("stopped, not reading its events")
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => io::Error::other("exited"),
+        };
+        stopped.store(true, Ordering::Relaxed);
+        Err(e)
+    });
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if tx
+                .send(Input::Plugin(plugin_i, Request::parse(&line)))
+                .is_err()
+            {
+                break;
+            }
+        }
+        let child = child.lock().expect("not poisoned").take();
+        if let Some(mut child) = child {
+            let _ = child.wait();
+        }
+    });
+    Ok(())
+}
+
+/// Pass what the plugin `plugin_i` asks on to the editor
+pub fn deliver(state: &mut State, plugin_i: usize, request: Result<Request, String>) {
+    match request {
+        Ok(request) => state.deliver_plugin_request(plugin_i, request),
+        Err(e) => {
+            let name = state
+                .plugin_names()
+                .get(plugin_i)
+                .cloned()
+                .unwrap_or_default();
+            state.show_message(&format!("plugin {}: {}", name, e));
+        }
+    }
+}
+
+/// Findings of `:health` about the plugins at `paths`
+pub fn health(paths: &[PathBuf]) -> Vec<Finding> {
+    if paths.is_empty() {
+        return vec![Finding::info("none, in the plugins config directory")];
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let name = name_of(path);
+            match runtime(path) {
+                Some((program, _)) => match env::find_system_program(program) {
+                    Some(found) => Finding::ok(format!("{}: run by {}", name, found.display())),
+                    None => Finding::error(
+                        format!("{}: {} is not on PATH", name, program),
+                        format!("install {} to run it", program),
+                    ),
+                },
+                None => Finding::ok(format!("{}: run as a program", name)),
+            }
+        })
+        .collect()
+}
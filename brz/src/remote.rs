@@ -0,0 +1,195 @@
+//! Socket the first instance listens on for `--remote` requests, and
+//! those of the clients of `--server`
+//!
+//! It's in `$XDG_RUNTIME_DIR`, or else in a directory of the state
+//! directory only the user can get into, never in a directory others can
+//! write to. A socket there that isn't the user's is neither connected to
+//! nor replaced.
+//!
+//! The keys a client sends can do whatever the editor does, running
+//! commands and pipes included, so only the clients of processes of the
+//! user are served, whatever the permissions of the socket.
+use crate::config;
+use libbrz::remote::{self, Location, Request};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+fn socket_path() -> io::Result<PathBuf> {
+    if let Some(path) = std::env::var_os("BRZ_SOCKET") {
+        return Ok(path.into());
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("brz.sock"));
+    }
+    let dir = config::base_state_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"))?
+        .join("run");
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    let meta = fs::metadata(&dir)?;
+    if meta.uid() != current_uid() || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not the user's alone", dir.display()),
+        ));
+    }
+    Ok(dir.join("brz.sock"))
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// The user of the process at the other end of `stream`
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let fd = stream.as_raw_fd();
+    #[cfg(target_os = "linux")]
+    {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let got = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if got < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(cred.uid)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+}
+
+/// Fails if there's something at `path` that isn't the user's
+fn check_owner(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.uid() != current_uid() => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not the user's", path.display()),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Send the locations to the running instance
+///
+/// Fails if there's none. Relative paths are sent as absolute ones, as
+/// the instance may run in another directory.
+pub fn send(locations: &[Location]) -> io::Result<()> {
+    let path = socket_path()?;
+    check_owner(&path)?;
+    let mut stream = UnixStream::connect(path)?;
+    let cwd = std::env::current_dir()?;
+    for location in locations {
+        let location = Location {
+            path: cwd.join(&location.path),
+            line: location.line,
+        };
+        stream.write_all(location.encode().as_bytes())?;
+    }
+    stream.flush()
+}
+
+/// Listening socket, removed when dropped
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen for requests unless another instance already is
+///
+/// Each is passed on with where to send its answer; those of `--remote`
+/// aren't waited on.
+pub fn listen(
+    on_request: impl Fn(Request, mpsc::Sender<String>) + Send + Sync + 'static,
+) -> io::Result<Server> {
+    let path = socket_path()?;
+    check_owner(&path)?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another instance is listening",
+        ));
+    }
+    // left behind by an instance that didn't exit cleanly
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    let on_request = Arc::new(on_request);
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+            if peer_uid(&stream).ok() != Some(current_uid()) {
+                continue;
+            }
+            let on_request = on_request.clone();
+            // clients of `--server` stay connected, and are served at once
+            thread::spawn(move || serve(stream, &*on_request));
+        }
+    });
+    Ok(Server { path })
+}
+
+fn serve(stream: UnixStream, on_request: &dyn Fn(Request, mpsc::Sender<String>)) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let answered = Request::is_answered(&line);
+        let answer = match Request::decode(&line) {
+            Ok(request) => {
+                let (tx, rx) = mpsc::channel();
+                on_request(request, tx);
+                if !answered {
+                    continue;
+                }
+                match rx.recv() {
+                    Ok(answer) => answer,
+                    Err(_) => break,
+                }
+            }
+            Err(_) if !answered => break,
+            Err(e) => remote::answer(Err(e)),
+        };
+        if writeln!(writer, "{}", answer)
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
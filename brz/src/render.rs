@@ -3,9 +3,120 @@ use termion::raw::IntoRawMode;
 use termion::screen::*;
 use termion::style;
 
-use libbrz::render;
+use libbrz::grid::DiffRenderer;
+use libbrz::health::Finding;
+use libbrz::render::{self, Attrs, Color, Color::Ansi};
+use libbrz::term::Capabilities;
+use libbrz::theme::Background;
 use libbrz::{prelude::*, state::State};
 use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+/// Pastes come between `ESC [200~` and `ESC [201~`, to tell them from
+/// typing
+const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+
+/// Presses, releases and moves with a button held are reported, in the
+/// SGR encoding, which has no limit on coordinates
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+
+/// Asks the color of the background, then the device attributes
+const BACKGROUND_QUERY: &str = "\x1b]11;?\x07\x1b[c";
+
+/// Time the terminal has to answer `BACKGROUND_QUERY`
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// What the terminal can show, as its environment tells
+pub fn capabilities() -> Capabilities {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let locale = var("LC_ALL")
+        .or_else(|| var("LC_CTYPE"))
+        .or_else(|| var("LANG"));
+    Capabilities::detect(
+        var("TERM").as_deref(),
+        var("NO_COLOR").is_some(),
+        locale.as_deref(),
+    )
+}
+
+/// Findings of `:health` about the terminal, beyond what it can show
+pub fn health() -> Vec<Finding> {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let mut findings = vec![];
+    if let Some(term) = var("TERM") {
+        findings.push(Finding::info(format!("TERM is {}", term)));
+    }
+    if !termion::is_tty(&io::stdout()) {
+        findings.push(Finding::warning(
+            "the output is not a terminal",
+            "run the editor in a terminal",
+        ));
+    }
+    let colorterm = var("COLORTERM").unwrap_or_default();
+    findings.push(if colorterm == "truecolor" || colorterm == "24bit" {
+        Finding::ok("24-bit colors")
+    } else {
+        Finding::warning(
+            "no 24-bit colors told: the #rrggbb colors of themes may be off",
+            "set COLORTERM=truecolor if the terminal has 24-bit colors",
+        )
+    });
+    findings
+}
+
+/// The background of the terminal, as it answers the OSC 11 query, or
+/// else as `COLORFGBG` says
+///
+/// To be asked in raw mode, before the keys are read. Every terminal
+/// answers the device attributes query following, so reading stops there
+/// even if the first one isn't answered, and no key typed is taken.
+pub fn background() -> Option<Background> {
+    let from_env = || {
+        std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| Background::from_colorfgbg(&value))
+    };
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    if !termion::is_tty(&stdin) || !termion::is_tty(&stdout) {
+        return from_env();
+    }
+    if write!(stdout, "{}", BACKGROUND_QUERY)
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        return from_env();
+    }
+    let fd = stdin.as_raw_fd();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = vec![];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut poll = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if left.is_zero() || unsafe { libc::poll(&mut poll, 1, left.as_millis() as i32) } <= 0 {
+            break;
+        }
+        let mut buf = [0u8; 64];
+        let read = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if read <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..read as usize]);
+        // `ESC [ ? … c`, the device attributes
+        let attributes = reply.windows(3).position(|w| w == b"\x1b[?");
+        if attributes.is_some_and(|start| reply[start..].contains(&b'c')) {
+            break;
+        }
+    }
+    Background::from_osc11_reply(&String::from_utf8_lossy(&reply)).or_else(from_env)
+}
 
 /// Keep track of color codes in output
 ///
@@ -14,9 +125,10 @@ use std::io::{self, Write};
 #[derive(Default)]
 struct CachingAnsciWriter {
     buf: Vec<u8>,
-    cur_fg: Option<u8>,
-    cur_bg: Option<u8>,
-    cur_bold: bool,
+    /// `None` for the terminal's own
+    cur_fg: Option<Color>,
+    cur_bg: Option<Color>,
+    cur_attrs: Attrs,
 }
 
 impl CachingAnsciWriter {
@@ -40,67 +152,74 @@ impl CachingAnsciWriter {
         Ok(())
     }
 
-    fn reset_style(&mut self) -> io::Result<()> {
-        if self.cur_bold {
-            self.cur_bold = false;
-            write!(&mut self.buf, "{}", style::Reset)?;
-        }
-        Ok(())
+    fn reset_attrs(&mut self) -> io::Result<()> {
+        self.change_attrs(Attrs::empty())
     }
 
     fn reset_all(&mut self) -> io::Result<()> {
         self.reset_fg()?;
         self.reset_bg()?;
-        self.reset_style()?;
+        self.reset_attrs()?;
 
         Ok(())
     }
 
-    fn change_fg(&mut self, fg: color::AnsiValue) -> io::Result<()> {
-        if self.cur_fg != Some(fg.0) {
-            self.cur_fg = Some(fg.0);
-            write!(&mut self.buf, "{}", color::Fg(fg),)?;
+    fn change_fg(&mut self, fg: Color) -> io::Result<()> {
+        if self.cur_fg == Some(fg) {
+            return Ok(());
         }
+        match fg {
+            Color::Default => return self.reset_fg(),
+            Color::Ansi(n) => write!(&mut self.buf, "{}", color::Fg(color::AnsiValue(n)))?,
+            Color::Rgb(r, g, b) => write!(&mut self.buf, "{}", color::Fg(color::Rgb(r, g, b)))?,
+        }
+        self.cur_fg = Some(fg);
         Ok(())
     }
 
-    fn change_bg(&mut self, bg: color::AnsiValue) -> io::Result<()> {
-        if self.cur_bg != Some(bg.0) {
-            self.cur_bg = Some(bg.0);
-            write!(&mut self.buf, "{}", color::Bg(bg),)?;
+    fn change_bg(&mut self, bg: Color) -> io::Result<()> {
+        if self.cur_bg == Some(bg) {
+            return Ok(());
+        }
+        match bg {
+            Color::Default => return self.reset_bg(),
+            Color::Ansi(n) => write!(&mut self.buf, "{}", color::Bg(color::AnsiValue(n)))?,
+            Color::Rgb(r, g, b) => write!(&mut self.buf, "{}", color::Bg(color::Rgb(r, g, b)))?,
         }
+        self.cur_bg = Some(bg);
         Ok(())
     }
 
-    fn change_style(&mut self, bold: bool) -> io::Result<()> {
-        if self.cur_bold != bold {
-            self.cur_bold = bold;
-            if bold {
-                write!(&mut self.buf, "{}", style::Bold)?;
-            } else {
-                write!(&mut self.buf, "{}", style::Reset)?;
-            }
+    fn change_attrs(&mut self, attrs: Attrs) -> io::Result<()> {
+        if !self.cur_attrs.without(attrs).is_empty() {
+            // attributes only go away all together, and colors with them
+            write!(&mut self.buf, "{}", style::Reset)?;
+            self.cur_attrs = Attrs::empty();
+            self.cur_fg = None;
+            self.cur_bg = None;
         }
+        let added = attrs.without(self.cur_attrs);
+        if added.contains(Attrs::BOLD) {
+            write!(&mut self.buf, "{}", style::Bold)?;
+        }
+        if added.contains(Attrs::ITALIC) {
+            write!(&mut self.buf, "{}", style::Italic)?;
+        }
+        if added.contains(Attrs::UNDERLINE) {
+            write!(&mut self.buf, "{}", style::Underline)?;
+        }
+        if added.contains(Attrs::REVERSE) {
+            write!(&mut self.buf, "{}", style::Invert)?;
+        }
+        self.cur_attrs = attrs;
         Ok(())
     }
 
     fn set_style(&mut self, style: render::Style) -> io::Result<()> {
-        if let Some(fg) = style.fg {
-            self.change_fg(color::AnsiValue(fg as u8))?;
-        } else {
-            self.reset_fg()?;
-        }
-        if let Some(bg) = style.bg {
-            self.change_bg(color::AnsiValue(bg as u8))?;
-        } else {
-            self.reset_bg()?;
-        }
-
-        if let Some(style) = style.style {
-            self.change_style((style & 1) == 1)?;
-        } else {
-            self.reset_style()?;
-        }
+        // first, as it may reset the colors
+        self.change_attrs(style.attrs.unwrap_or_default())?;
+        self.change_fg(style.fg.unwrap_or(Color::Default))?;
+        self.change_bg(style.bg.unwrap_or(Color::Default))?;
 
         Ok(())
     }
@@ -116,131 +235,197 @@ impl io::Write for CachingAnsciWriter {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-struct Char {
-    ch: char,
-    style: render::Style,
-}
-
-impl Default for Char {
-    fn default() -> Self {
-        Char {
-            ch: ' ',
-            style: render::Style::default(),
-        }
-    }
-}
-
 pub struct Render {
     screen: AlternateScreen<termion::raw::RawTerminal<std::io::Stdout>>,
-    display_cols: usize,
-    display_rows: usize,
-
-    color_map: render::ColorMap,
-
-    cur_buffer: Vec<Char>,
-    cur_cursor_pos: Option<render::Coord>,
-    prev_buffer: Vec<Char>,
+    grid: DiffRenderer,
 }
 
 impl Render {
     pub fn new() -> Result<Self> {
-        let screen = AlternateScreen::from(std::io::stdout().into_raw_mode().unwrap());
-        let color_map = render::ColorMap {
+        let mut screen = AlternateScreen::from(std::io::stdout().into_raw_mode().unwrap());
+        write!(screen, "{}{}", BRACKETED_PASTE_ON, MOUSE_ON)?;
+        let grid = DiffRenderer::new(render::ColorMap {
             default: render::Style::default(),
             actions: render::Style {
-                fg: Some(8),
-                bg: Some(14),
+                fg: Some(Ansi(8)),
+                bg: Some(Ansi(14)),
                 ..Default::default()
             },
             line_num: render::Style {
-                fg: Some(10),
+                fg: Some(Ansi(10)),
                 ..Default::default()
             },
             direction_marker: render::Style {
-                fg: Some(16),
-                bg: Some(4),
+                fg: Some(Ansi(16)),
+                bg: Some(Ansi(4)),
                 ..Default::default()
             },
             selection: render::Style {
-                fg: Some(16),
-                bg: Some(4),
+                fg: Some(Ansi(16)),
+                bg: Some(Ansi(4)),
                 ..Default::default()
             },
             special: render::Style {
-                fg: Some(14),
+                fg: Some(Ansi(14)),
                 ..Default::default()
             },
-        };
-
-        let mut s = Render {
-            display_cols: 0,
-            screen,
-            display_rows: 0,
-            color_map,
+            conflict_marker: render::Style {
+                fg: Some(Ansi(11)),
+                ..Default::default()
+            },
+            conflict_ours: render::Style {
+                bg: Some(Ansi(22)),
+                ..Default::default()
+            },
+            conflict_base: render::Style {
+                bg: Some(Ansi(58)),
+                ..Default::default()
+            },
+            conflict_theirs: render::Style {
+                bg: Some(Ansi(17)),
+                ..Default::default()
+            },
+            syntax_keyword: render::Style {
+                fg: Some(Ansi(5)),
+                ..Default::default()
+            },
+            syntax_string: render::Style {
+                fg: Some(Ansi(2)),
+                ..Default::default()
+            },
+            syntax_comment: render::Style {
+                fg: Some(Ansi(8)),
+                ..Default::default()
+            },
+            syntax_number: render::Style {
+                fg: Some(Ansi(3)),
+                ..Default::default()
+            },
+            diagnostic_error: render::Style {
+                fg: Some(Ansi(1)),
+                ..Default::default()
+            },
+            diagnostic_warning: render::Style {
+                fg: Some(Ansi(3)),
+                ..Default::default()
+            },
+            diagnostic_info: render::Style {
+                fg: Some(Ansi(4)),
+                ..Default::default()
+            },
+            diagnostic_hint: render::Style {
+                fg: Some(Ansi(8)),
+                ..Default::default()
+            },
+            code_lens: render::Style {
+                fg: Some(Ansi(8)),
+                ..Default::default()
+            },
+            fold: render::Style {
+                fg: Some(Ansi(8)),
+                bg: Some(Ansi(236)),
+                ..Default::default()
+            },
+            jump_label: render::Style {
+                fg: Some(Ansi(16)),
+                bg: Some(Ansi(11)),
+                attrs: Some(Attrs::BOLD),
+            },
+            reference_text: render::Style {
+                bg: Some(Ansi(237)),
+                ..Default::default()
+            },
+            reference_read: render::Style {
+                bg: Some(Ansi(23)),
+                ..Default::default()
+            },
+            reference_write: render::Style {
+                bg: Some(Ansi(52)),
+                ..Default::default()
+            },
+            status: render::Style::default(),
+            mode: render::Style::default(),
+            search_match: render::Style {
+                attrs: Some(Attrs::UNDERLINE),
+                ..Default::default()
+            },
+            bracket_match: render::Style {
+                bg: Some(Ansi(238)),
+                attrs: Some(Attrs::BOLD),
+                ..Default::default()
+            },
+            picker_match: render::Style {
+                fg: Some(Ansi(11)),
+                attrs: Some(Attrs::BOLD),
+                ..Default::default()
+            },
+            git_added: render::Style {
+                fg: Some(Ansi(2)),
+                ..Default::default()
+            },
+            git_modified: render::Style {
+                fg: Some(Ansi(3)),
+                ..Default::default()
+            },
+            git_removed: render::Style {
+                fg: Some(Ansi(1)),
+                ..Default::default()
+            },
+        });
 
-            cur_buffer: Vec::new(),
-            cur_cursor_pos: None,
-            prev_buffer: Vec::new(),
-        };
+        let mut s = Render { screen, grid };
         s.fix_size()?;
         Ok(s)
     }
 
-    fn coord_to_i(&self, coord: render::Coord) -> usize {
-        coord.x + coord.y * self.display_cols
-    }
-
-    fn char_at_mut(&mut self, coord: render::Coord) -> Option<&mut Char> {
-        let i = self.coord_to_i(coord);
-        self.cur_buffer.get_mut(i)
-    }
-
     fn fix_size(&mut self) -> Result<()> {
         let (cols, rows) = termion::terminal_size()?;
-        let cols = cols as usize;
-        let rows = rows as usize;
-        if self.display_cols != cols || self.display_rows != rows {
-            self.display_cols = cols;
-            self.display_rows = rows;
-            self.cur_buffer.resize_with(cols * rows, Default::default);
-            self.prev_buffer.truncate(0);
-            self.cur_cursor_pos = None;
-        }
+        self.grid.resize(render::Coord {
+            x: cols as usize,
+            y: rows as usize,
+        });
         Ok(())
     }
 
-    pub fn draw(&mut self, state: &State) -> Result<()> {
-        state.render(self);
+    /// Draw the frame of `state`, returning the number of cells drawn
+    pub fn draw(&mut self, state: &State) -> Result<usize> {
+        state.render(&mut self.grid);
+        let frame = self.grid.finish_frame();
         let mut buf = CachingAnsciWriter::default();
-        if self.prev_buffer.is_empty() {
-            self.prev_buffer
-                .resize_with(self.cur_buffer.len(), Char::default);
+        if frame.clear {
             write!(&mut buf, "{}{}", style::Reset, termion::clear::All).unwrap();
             buf.reset_all()?;
         }
-        self.draw_diff(&mut buf);
-        self.draw_cursor(&mut buf);
+        let mut cells = 0;
+        for run in &frame.runs {
+            write!(
+                buf,
+                "{}",
+                termion::cursor::Goto(run.coord.x as u16 + 1, run.coord.y as u16 + 1)
+            )
+            .unwrap();
+            // the terminal moves past both halves of a wide char
+            for cell in run.cells.iter().filter(|cell| !cell.is_continuation()) {
+                cells += 1;
+                buf.set_style(cell.style).unwrap();
+                for ch in cell.chars() {
+                    write!(buf, "{}", ch).unwrap();
+                }
+            }
+        }
+        Self::draw_cursor(&mut buf, frame.cursor);
         buf.reset_all()?;
 
-        let buf = buf.into_vec();
-        buf.len();
-
-        self.screen.write_all(&buf)?;
+        self.screen.write_all(&buf.into_vec())?;
         self.screen.flush()?;
-        std::mem::swap(&mut self.prev_buffer, &mut self.cur_buffer);
-        self.cur_buffer
-            .iter_mut()
-            .map(|ch| *ch = Char::default())
-            .count();
 
         self.fix_size()?;
 
-        Ok(())
+        Ok(cells)
     }
 
-    fn draw_cursor(&mut self, buf: &mut CachingAnsciWriter) {
-        if let Some(coord) = self.cur_cursor_pos {
+    fn draw_cursor(buf: &mut CachingAnsciWriter, cursor: Option<render::Coord>) {
+        if let Some(coord) = cursor {
             write!(
                 buf,
                 "\x1b[6 q{}{}",
@@ -252,56 +437,11 @@ impl Render {
             write!(buf, "{}", termion::cursor::Hide).unwrap();
         }
     }
-
-    fn draw_diff(&mut self, buf: &mut CachingAnsciWriter) {
-        let mut needs_goto = true;
-
-        for (ch_i, new_ch) in self.cur_buffer.iter().copied().enumerate() {
-            let old_ch = self.prev_buffer[ch_i];
-
-            if new_ch == old_ch {
-                needs_goto = true;
-                continue;
-            }
-
-            if needs_goto {
-                needs_goto = false;
-
-                let term_line = ch_i / self.display_cols + 1;
-                let term_column = ch_i % self.display_cols + 1;
-
-                write!(
-                    buf,
-                    "{}",
-                    termion::cursor::Goto(term_column as u16, term_line as u16)
-                )
-                .unwrap();
-            }
-
-            buf.set_style(new_ch.style).unwrap();
-
-            write!(buf, "{}", new_ch.ch).unwrap();
-        }
-    }
 }
 
-impl render::Renderer for Render {
-    fn color_map(&self) -> &render::ColorMap {
-        &self.color_map
-    }
-
-    fn dimensions(&self) -> render::Coord {
-        render::Coord {
-            x: self.display_cols,
-            y: self.display_rows,
-        }
-    }
-
-    fn put(&mut self, coord: render::Coord, ch: char, style: render::Style) {
-        self.char_at_mut(coord).map(|c| *c = Char { ch, style });
-    }
-
-    fn set_cursor(&mut self, coord: Option<render::Coord>) {
-        self.cur_cursor_pos = coord;
+impl Drop for Render {
+    fn drop(&mut self) {
+        let _ = write!(self.screen, "{}{}", MOUSE_OFF, BRACKETED_PASTE_OFF);
+        let _ = self.screen.flush();
     }
 }
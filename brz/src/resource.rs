@@ -0,0 +1,49 @@
+//! Resource handler, creating, renaming and deleting files
+use libbrz::lsp::{FileOptions, ResourceOp};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What to do about an existing `path`: `false` to leave it alone
+fn may_replace(path: &Path, options: FileOptions) -> io::Result<bool> {
+    if !path.exists() || options.overwrite {
+        Ok(true)
+    } else if options.ignore_if_exists {
+        Ok(false)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ))
+    }
+}
+
+pub fn apply(op: &ResourceOp) -> io::Result<()> {
+    match op {
+        ResourceOp::Create { path, options } => {
+            if may_replace(path, *options)? {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::File::create(path)?;
+            }
+        }
+        ResourceOp::Rename { old, new, options } => {
+            if may_replace(new, *options)? {
+                fs::rename(old, new)?;
+            }
+        }
+        ResourceOp::Delete {
+            path,
+            recursive,
+            ignore_if_not_exists,
+        } => match fs::symlink_metadata(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && *ignore_if_not_exists => {}
+            Err(e) => return Err(e),
+            Ok(meta) if meta.is_dir() && *recursive => fs::remove_dir_all(path)?,
+            Ok(meta) if meta.is_dir() => fs::remove_dir(path)?,
+            Ok(_) => fs::remove_file(path)?,
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Swap files of the buffers, in `$XDG_STATE_HOME/breeze/swap`, each in a
+//! file named after the absolute path of the file, as the undo histories
+//! are
+use crate::config;
+use libbrz::recover::{Swap, SwapOp};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn swap_dir() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("swap"))
+}
+
+/// The file may not be there yet, nor its swap file
+fn swap_file(path: &Path) -> io::Result<Option<PathBuf>> {
+    let dir = match swap_dir() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let path = std::env::current_dir()?.join(path);
+    Ok(Some(dir.join(config::escape_path(&path))))
+}
+
+pub fn apply(op: &SwapOp) -> io::Result<()> {
+    match op {
+        SwapOp::Write { path, text } => {
+            if let Some(file) = swap_file(path)? {
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(file, text)?;
+            }
+            Ok(())
+        }
+        SwapOp::Remove(path) => match swap_file(path)? {
+            Some(file) => match fs::remove_file(file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        },
+    }
+}
+
+/// The swap files left over, their paths relative to the current
+/// directory when they are in it
+pub fn left_over() -> Vec<Swap> {
+    let entries = match swap_dir().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return vec![],
+    };
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut swaps: Vec<Swap> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = config::unescape_path(entry.file_name().to_str()?);
+            Some(Swap {
+                path: libbrz::remote::relative_to(&path, &cwd),
+                text: fs::read_to_string(entry.path()).ok()?,
+            })
+        })
+        .collect();
+    swaps.sort_by(|a, b| a.path.cmp(&b.path));
+    swaps
+}
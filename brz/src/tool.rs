@@ -0,0 +1,203 @@
+//! Running external commands, so that one that hangs or floods its
+//! output can't freeze the editor or take all of its memory
+//!
+//! Every command runs with `Limits` on how long it takes and how much it
+//! prints, can be cancelled with a `CancelToken`, and waits its turn when
+//! `MAX_RUNNING` commands already run.
+use libbrz::preview::CancelToken;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Commands running at once, at most
+const MAX_RUNNING: usize = 4;
+
+/// How often a running command is checked for being cancelled or late
+const POLL: Duration = Duration::from_millis(50);
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+static RUNNING: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// From the start of the command, waiting for its turn included
+    pub timeout: Duration,
+    /// Of stdout; stderr is cut at the same size, without failing
+    pub max_output: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_output: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// A place among the `MAX_RUNNING` commands, until it's dropped
+struct Slot;
+
+impl Slot {
+    fn take(deadline: Instant, cancel: &CancelToken) -> io::Result<Slot> {
+        let (running, freed) = &RUNNING;
+        let mut running = running.lock().unwrap_or_else(|e| e.into_inner());
+        while *running == MAX_RUNNING {
+            check(deadline, cancel)?;
+            running = freed
+                .wait_timeout(running, POLL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        *running += 1;
+        Ok(Slot)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let (running, freed) = &RUNNING;
+        *running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        freed.notify_one();
+    }
+}
+
+fn check(deadline: Instant, cancel: &CancelToken) -> io::Result<()> {
+    if cancel.is_cancelled() {
+        Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+    } else if Instant::now() >= deadline {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"))
+    } else {
+        Ok(())
+    }
+}
+
+enum Chunk {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+/// Send what `pipe` reads as chunks made by `chunk`, until its end
+fn forward(
+    mut pipe: impl Read + Send + 'static,
+    tx: mpsc::Sender<Chunk>,
+    chunk: fn(Vec<u8>) -> Chunk,
+) {
+    thread::spawn(move || {
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(chunk(buf[..n].to_vec())).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Run `cmd` with `input` on its stdin, calling `on_output` with every
+/// piece of its stdout as it comes
+///
+/// Whatever `cmd` had for its stdio is replaced. Being cancelled, late or
+/// over `max_output` kills the command, with an error starting with the
+/// program's name.
+pub fn run(
+    mut cmd: Command,
+    input: Option<Vec<u8>>,
+    limits: &Limits,
+    cancel: &CancelToken,
+    mut on_output: impl FnMut(&[u8]),
+) -> io::Result<Output> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let context = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", program, e));
+    let deadline = Instant::now() + limits.timeout;
+    let _slot = Slot::take(deadline, cancel).map_err(context)?;
+
+    let mut child = cmd
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(context)?;
+    if let (Some(input), Some(stdin)) = (input, child.stdin.take()) {
+        // in a thread of its own, as the command may print before it
+        // reads everything; a command that quits early closes the pipe
+        thread::spawn(move || {
+            let mut stdin = io::BufWriter::new(stdin);
+            let _ = stdin.write_all(&input).and_then(|_| stdin.flush());
+        });
+    }
+    let (tx, rx) = mpsc::channel();
+    forward(
+        child.stdout.take().expect("piped"),
+        tx.clone(),
+        Chunk::Stdout,
+    );
+    forward(child.stderr.take().expect("piped"), tx, Chunk::Stderr);
+
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+    loop {
+        if let Err(e) = check(deadline, cancel) {
+            kill(&mut child);
+            return Err(context(e));
+        }
+        match rx.recv_timeout(POLL) {
+            Ok(Chunk::Stdout(chunk)) => {
+                if stdout.len() + chunk.len() > limits.max_output {
+                    kill(&mut child);
+                    return Err(context(io::Error::other(format!(
+                        "more than {} bytes of output",
+                        limits.max_output
+                    ))));
+                }
+                on_output(&chunk);
+                stdout.extend_from_slice(&chunk);
+            }
+            Ok(Chunk::Stderr(chunk)) => {
+                let room = limits.max_output.saturating_sub(stderr.len());
+                stderr.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    // the pipes are closed, so the command is done or about to be
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(context)? {
+            break status;
+        }
+        if let Err(e) = check(deadline, cancel) {
+            kill(&mut child);
+            return Err(context(e));
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
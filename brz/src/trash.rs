@@ -0,0 +1,133 @@
+//! The freedesktop.org trash of the user, in `$XDG_DATA_HOME/Trash`
+//!
+//! Only files on the file system of the trash can go there: for others
+//! it's `Unsupported`, and they are deleted outright.
+use libbrz::trash::TrashOp;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// `rename` across file systems
+const EXDEV: i32 = libc::EXDEV;
+
+fn trash_dir() -> io::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_DATA_HOME") {
+        return Ok(PathBuf::from(dir).join("Trash"));
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local/share/Trash")),
+        None => Err(io::Error::new(io::ErrorKind::Unsupported, "no home")),
+    }
+}
+
+/// `path` as a URI path, as `.trashinfo` files have it
+fn encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Now, in local time, as `.trashinfo` files have it
+fn deletion_date() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return String::new();
+    }
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+fn trash(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let absolute = fs::canonicalize(dir)?.join(name);
+    let trash = trash_dir()?;
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+
+    // the info file, created first, reserves the name
+    let mut n = 1;
+    let (trashed_name, mut info, info_path) = loop {
+        let mut trashed_name = name.to_owned();
+        if 1 < n {
+            trashed_name.push(format!(".{}", n));
+        }
+        let mut info_name = trashed_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = trash.join("info").join(info_name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(info) => break (trashed_name, info, info_path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    let trashed = trash.join("files").join(trashed_name);
+    let result = write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(&absolute),
+        deletion_date()
+    )
+    .and_then(|()| fs::rename(path, &trashed));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&info_path);
+        if e.raw_os_error() == Some(EXDEV) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "on another file system than the trash",
+            ));
+        }
+        return Err(e);
+    }
+    Ok(trashed)
+}
+
+fn restore(trashed: &Path, path: &Path) -> io::Result<PathBuf> {
+    if fs::symlink_metadata(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists again", path.display()),
+        ));
+    }
+    fs::rename(trashed, path)?;
+    if let (Some(files), Some(name)) = (trashed.parent(), trashed.file_name()) {
+        let mut info_name = name.to_owned();
+        info_name.push(".trashinfo");
+        if let Some(trash) = files.parent() {
+            let _ = fs::remove_file(trash.join("info").join(info_name));
+        }
+    }
+    Ok(path.to_owned())
+}
+
+pub fn apply(op: &TrashOp) -> io::Result<PathBuf> {
+    match op {
+        TrashOp::Trash(path) => trash(path),
+        TrashOp::Restore { trashed, path } => restore(trashed, path),
+    }
+}
@@ -0,0 +1,42 @@
+//! Undo histories of files, in `$XDG_STATE_HOME/breeze/undo`, each in a
+//! file named after the absolute path of the file, its `%` and `/`
+//! escaped
+use crate::config;
+use libbrz::undo::UndoFileOp;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn undo_file(path: &Path) -> io::Result<Option<PathBuf>> {
+    let dir = match config::state_dir() {
+        Some(dir) => dir.join("undo"),
+        None => return Ok(None),
+    };
+    let path = fs::canonicalize(path)?;
+    Ok(Some(dir.join(config::escape_path(&path))))
+}
+
+pub fn apply(op: &UndoFileOp) -> io::Result<Option<String>> {
+    match op {
+        UndoFileOp::Read(path) => {
+            let file = match undo_file(path)? {
+                Some(file) => file,
+                None => return Ok(None),
+            };
+            match fs::read_to_string(file) {
+                Ok(text) => Ok(Some(text)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+        UndoFileOp::Write { path, text } => {
+            if let Some(file) = undo_file(path)? {
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(file, text)?;
+            }
+            Ok(None)
+        }
+    }
+}
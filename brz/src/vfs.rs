@@ -0,0 +1,256 @@
+//! The local file system, and files of another host reached with `ssh`
+//!
+//! Local files are watched with inotify on Linux. Otherwise watching
+//! polls the modification time of the path, which for a directory
+//! changes with its entries.
+use crate::{env, tool, Input};
+use libbrz::preview::CancelToken;
+use libbrz::vfs::{DirEntry, EntryKind, Metadata, OnChange, Vfs, Watch};
+use libbrz::State;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOCAL_POLL: Duration = Duration::from_secs(1);
+const SSH_POLL: Duration = Duration::from_secs(5);
+
+/// Whole files go through `ssh`, which takes longer and prints more
+/// than other commands need
+const SSH_LIMITS: tool::Limits = tool::Limits {
+    timeout: Duration::from_secs(120),
+    max_output: 1024 * 1024 * 1024,
+};
+
+/// Call `on_change` with `path` whenever `modified` tells something else
+/// than before, until the watch is dropped
+fn poll(
+    path: &Path,
+    every: Duration,
+    on_change: OnChange,
+    modified: impl Fn(&Path) -> Option<SystemTime> + Send + 'static,
+) -> Watch {
+    let (watch, active) = Watch::new();
+    let path = path.to_owned();
+    thread::spawn(move || {
+        let mut last = modified(&path);
+        while active.load(Ordering::Relaxed) {
+            thread::sleep(every);
+            let now = modified(&path);
+            if now != last && active.load(Ordering::Relaxed) {
+                on_change(&path);
+            }
+            last = now;
+        }
+    });
+    watch
+}
+
+/// Read and write the files of `state` in `vfs`, its watches telling the
+/// changes through `tx`
+pub fn register(state: &mut State, vfs: Arc<dyn Vfs>, tx: mpsc::Sender<Input>) {
+    state.register_vfs(vfs.clone());
+    state.register_watch_handler(move |path| {
+        let tx = tx.clone();
+        vfs.watch(
+            path,
+            Box::new(move |path| {
+                let _ = tx.send(Input::FileChanged(path.to_owned()));
+            }),
+        )
+    });
+}
+
+fn kind(file_type: fs::FileType) -> EntryKind {
+    if file_type.is_file() {
+        EntryKind::File
+    } else if file_type.is_dir() {
+        EntryKind::Dir
+    } else {
+        EntryKind::Other
+    }
+}
+
+pub struct LocalFs;
+
+impl Vfs for LocalFs {
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            // symlinks count as what they point to
+            let kind = match fs::metadata(entry.path()) {
+                Ok(metadata) => kind(metadata.file_type()),
+                Err(_) => EntryKind::Other,
+            };
+            entries.push(DirEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                kind,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Metadata> {
+        let metadata = fs::metadata(path)?;
+        Ok(Metadata {
+            kind: kind(metadata.file_type()),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            writable: crate::write::writable(path)?,
+        })
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let tmp_path = path.with_extension("brz.tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<Watch> {
+        fs::metadata(path)?;
+        #[cfg(target_os = "linux")]
+        {
+            if let Ok(inotify) = crate::inotify::Inotify::new(path) {
+                return Ok(inotify.watch(on_change));
+            }
+        }
+        Ok(poll(path, LOCAL_POLL, on_change, |path| {
+            fs::metadata(path).and_then(|m| m.modified()).ok()
+        }))
+    }
+}
+
+/// Quote `arg` for the shell on the other side
+fn quote(arg: &Path) -> String {
+    format!("'{}'", arg.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// Files of `host`, through `ssh` running commands there
+///
+/// `ssh` must not ask for a password: use an agent or keys.
+#[derive(Clone)]
+pub struct SshFs {
+    host: Arc<str>,
+}
+
+impl SshFs {
+    pub fn new(host: &str) -> Self {
+        Self { host: host.into() }
+    }
+
+    /// Run the shell `script` on the host, with `input` on its stdin
+    fn run(&self, script: &str, input: Option<&[u8]>) -> io::Result<Vec<u8>> {
+        let mut cmd = env::system_command("ssh");
+        cmd.args(["-o", "BatchMode=yes", &self.host, "--", script]);
+        let output = tool::run(
+            cmd,
+            input.map(<[u8]>::to_vec),
+            &SSH_LIMITS,
+            &CancelToken::default(),
+            |_| {},
+        )?;
+        let err = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() {
+            Ok(output.stdout)
+        } else if err.contains("No such file") {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                err.trim().to_owned(),
+            ))
+        } else if err.contains("Permission denied") {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                err.trim().to_owned(),
+            ))
+        } else {
+            Err(io::Error::other(format!(
+                "ssh {}: {}",
+                self.host,
+                err.trim()
+            )))
+        }
+    }
+}
+
+/// `%F %s %Y` of `stat`, and whether the path is writable
+fn parse_stat(out: &str) -> io::Result<Metadata> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("stat: {}", out));
+    let mut lines = out.lines();
+    let mut fields = lines.next().ok_or_else(invalid)?.rsplitn(3, ' ');
+    let mtime: u64 = fields
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(invalid)?;
+    let len = fields
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(invalid)?;
+    let kind = match fields.next().ok_or_else(invalid)? {
+        "directory" => EntryKind::Dir,
+        kind if kind.contains("regular") => EntryKind::File,
+        _ => EntryKind::Other,
+    };
+    Ok(Metadata {
+        kind,
+        len,
+        modified: Some(UNIX_EPOCH + Duration::from_secs(mtime)),
+        writable: lines.next() == Some("w"),
+    })
+}
+
+impl Vfs for SshFs {
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let out = self.run(&format!("ls -1Ap -- {}", quote(path)), None)?;
+        let mut entries: Vec<_> = String::from_utf8_lossy(&out)
+            .lines()
+            .map(|name| match name.strip_suffix('/') {
+                Some(dir) => DirEntry {
+                    name: dir.to_owned(),
+                    kind: EntryKind::Dir,
+                },
+                None => DirEntry {
+                    name: name.to_owned(),
+                    kind: EntryKind::File,
+                },
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Metadata> {
+        let path = quote(path);
+        let script = format!(
+            "stat -L -c '%F %s %Y' -- {0} && if test -w {0}; then echo w; else echo -; fi",
+            path
+        );
+        parse_stat(&String::from_utf8_lossy(&self.run(&script, None)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.run(&format!("cat -- {}", quote(path)), None)
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let tmp = quote(&path.with_extension("brz.tmp"));
+        let script = format!("cat > {0} && mv -- {0} {1}", tmp, quote(path));
+        self.run(&script, Some(bytes)).map(|_| ())
+    }
+
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<Watch> {
+        self.stat(path)?;
+        let fs = self.clone();
+        Ok(poll(path, SSH_POLL, on_change, move |path| {
+            fs.stat(path).ok().and_then(|metadata| metadata.modified)
+        }))
+    }
+}
@@ -0,0 +1,51 @@
+//! Whether files can be written, and writing them with `sudo` otherwise
+use crate::env;
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Stdio;
+
+fn access_w(path: &Path) -> io::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0)
+}
+
+/// Can `path` be written: the file, if it exists, and its directory,
+/// where the new content goes before replacing it
+pub fn writable(path: &Path) -> io::Result<bool> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if path.exists() && !access_w(path)? {
+        return Ok(false);
+    }
+    access_w(dir)
+}
+
+/// Write through `sudo tee`, which must not need a password: `sudo -v`
+/// beforehand, outside of the editor
+pub fn sudo_write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut child = env::system_command("sudo")
+        .args(["-n", "tee", "--"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().expect("piped");
+        stdin.write_all(bytes)?;
+    }
+    let mut stderr = String::new();
+    if let Some(mut err) = child.stderr.take() {
+        err.read_to_string(&mut stderr)?;
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("sudo: {}", stderr.trim())))
+    }
+}
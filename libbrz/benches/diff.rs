@@ -0,0 +1,75 @@
+//! Time diffs of big texts: `cargo bench -p libbrz`
+use libbrz::diff::{diff_inline, diff_lines};
+use std::time::Instant;
+
+fn time(name: &str, f: impl Fn() -> usize) {
+    let runs = 5;
+    let start = Instant::now();
+    let mut hunks = 0;
+    for _ in 0..runs {
+        hunks = f();
+    }
+    println!(
+        "{:45} {:>9.2?} per run, {} hunks",
+        name,
+        start.elapsed() / runs,
+        hunks
+    );
+}
+
+/// Lines like code: unique ones, with blank lines and braces between
+fn code(lines: usize) -> Vec<String> {
+    (0..lines)
+        .map(|i| match i % 5 {
+            0 => format!("fn f{}() {{", i),
+            1 => format!("    let x = {};", i * 7),
+            2 => "}".to_owned(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// `lines` with every `every` one changed, and every `every * 3` removed
+fn edited(lines: &[String], every: usize) -> Vec<String> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % (every * 3) != every)
+        .map(|(i, line)| {
+            if i % every == 0 {
+                format!("{} // edited", line)
+            } else {
+                line.clone()
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    let old = code(1_000_000);
+    let new = edited(&old, 1000);
+    time("1M lines, 1 in 1000 edited", || {
+        diff_lines(&old, &new).len()
+    });
+    let new = edited(&old, 10);
+    time("1M lines, 1 in 10 edited", || diff_lines(&old, &new).len());
+
+    // the same lines over and over, which don't anchor anything
+    let old: Vec<String> = (0..200_000).map(|i| format!("{}", i % 100)).collect();
+    let new = edited(&old, 1000);
+    time("200k lines of 100 kinds, 1 in 1000 edited", || {
+        diff_lines(&old, &new).len()
+    });
+
+    let mut shuffled = old.clone();
+    shuffled.reverse();
+    time("200k lines of 100 kinds, reversed", || {
+        diff_lines(&old, &shuffled).len()
+    });
+
+    let line = "let value = compute(first, second) + other.field * 3; ".repeat(200);
+    let changed = line.replace("second", "third");
+    time("10k char line, 200 words edited", || {
+        diff_inline(&line, &changed).len()
+    });
+}
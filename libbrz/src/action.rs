@@ -1,16 +1,24 @@
 pub use super::Mode;
 pub use super::State;
 
+pub mod goto;
 pub mod normal;
+pub mod view;
+pub mod window;
 
 pub use self::normal::default_key_mappings;
 use crate::NaturalyOrderedKey;
 use once_cell::sync::OnceCell;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
 #[macro_export]
 macro_rules! action {
     ($name:ident, $help:expr, ($state:ident) $body:block) => {
+        action!($name, $help, ($state, _args) $body);
+    };
+    ($name:ident, $help:expr, ($state:ident, $args:ident) $body:block) => {
         pub struct $name;
 
         impl $crate::action::Action for $name {
@@ -18,7 +26,7 @@ macro_rules! action {
                 $help
             }
 
-            fn execute(&self, $state: &mut $crate::State) {
+            fn execute(&self, $state: &mut $crate::State, $args: &[String]) {
                 $body
             }
         }
@@ -35,8 +43,8 @@ action!(
 macro_rules! actions {
     ($m:ident) => {};
     ($m:ident,) => {};
-    ($m:ident,  $name:ident, $help:expr, ($state:ident) $body:block , $($rest:tt)*) => {
-        action!($name, $help, ($state) $body);
+    ($m:ident,  $name:ident, $help:expr, ($($param:ident),+) $body:block , $($rest:tt)*) => {
+        action!($name, $help, ($($param),+) $body);
         $m.insert(stringify!($name), Box::new($name) as Box<_>);
         actions!($m, $($rest)*);
     };
@@ -54,6 +62,10 @@ macro_rules! key_mappings {
         $m.insert($crate::action::NaturalyOrderedKey(Key::Alt(stringify!($k).chars().next().unwrap())), stringify!($name));
         key_mappings!($m, $($rest)*);
     };
+    ($m:ident, { key $k:expr, $name:ident }, $($rest:tt)*) => {
+        $m.insert($crate::action::NaturalyOrderedKey($k), stringify!($name));
+        key_mappings!($m, $($rest)*);
+    };
 
     ($m:ident, { $k:ident, $name:ident }, $($rest:tt)*) => {
         $m.insert($crate::action::NaturalyOrderedKey(Key::Char(stringify!($k).chars().next().unwrap())), stringify!($name));
@@ -81,8 +93,152 @@ pub fn empty_key_mappings() -> &'static KeyMappings {
     INSTANCE.get_or_init(BTreeMap::new)
 }
 
+/// All the actions that can be executed by name
+pub fn registry() -> &'static ActionsById {
+    normal::actions()
+}
+
 pub trait Action {
     fn help(&self) -> &str;
 
-    fn execute(&self, state: &mut State);
+    fn execute(&self, state: &mut State, args: &[String]);
+}
+
+/// Count given as the first argument, or the numerical prefix
+pub fn count_arg(state: &mut State, args: &[String]) -> usize {
+    let prefix = state.take_num_prefix();
+    args.first().and_then(|a| a.parse().ok()).unwrap_or(prefix)
+}
+
+/// A named action along with its arguments
+///
+/// This is what gets executed, recorded and sent around. Its textual
+/// form is the name followed by whitespace separated arguments, with
+/// `"` quoting for arguments that contain whitespace:
+///
+/// ```text
+/// InsertText "hello world"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionCall {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ActionCall {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: vec![],
+        }
+    }
+
+    pub fn with_arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl fmt::Display for ActionCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        for arg in &self.args {
+            if arg.is_empty()
+                || arg.contains(|ch: char| ch.is_whitespace() || ch == '"' || ch == '\\')
+            {
+                f.write_str(" \"")?;
+                for ch in arg.chars() {
+                    match ch {
+                        '"' | '\\' => write!(f, "\\{}", ch)?,
+                        '\n' => f.write_str("\\n")?,
+                        ch => write!(f, "{}", ch)?,
+                    }
+                }
+                f.write_str("\"")?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseActionCallError(pub String);
+
+impl fmt::Display for ParseActionCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid action: {}", self.0)
+    }
+}
+
+impl FromStr for ActionCall {
+    type Err = ParseActionCallError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = vec![];
+        let mut chars = s.chars().peekable();
+        loop {
+            while chars.peek().map(|ch| ch.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+            let mut word = String::new();
+            match chars.peek() {
+                None => break,
+                Some('"') => {
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            None => return Err(ParseActionCallError("unterminated quote".into())),
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some('n') => word.push('\n'),
+                                Some(ch) => word.push(ch),
+                                None => return Err(ParseActionCallError("trailing escape".into())),
+                            },
+                            Some(ch) => word.push(ch),
+                        }
+                    }
+                }
+                Some(_) => {
+                    while let Some(&ch) = chars.peek() {
+                        if ch.is_whitespace() {
+                            break;
+                        }
+                        word.push(ch);
+                        chars.next();
+                    }
+                }
+            }
+            words.push(word);
+        }
+
+        let mut words = words.into_iter();
+        let name = words
+            .next()
+            .ok_or_else(|| ParseActionCallError("empty".into()))?;
+        Ok(ActionCall {
+            name,
+            args: words.collect(),
+        })
+    }
+}
+
+#[test]
+fn action_call_roundtrip_test() {
+    for s in &[
+        "MoveDown",
+        "MoveDown 3",
+        r#"InsertText "hello world""#,
+        r#"InsertText "a \"quoted\" \\ thing\n""#,
+        r#"InsertText """#,
+    ] {
+        let call: ActionCall = s.parse().unwrap();
+        assert_eq!(&call.to_string(), s);
+        assert_eq!(call.to_string().parse::<ActionCall>().unwrap(), call);
+    }
+    assert_eq!(
+        "InsertText \"a b\" c".parse::<ActionCall>().unwrap(),
+        ActionCall::new("InsertText").with_arg("a b").with_arg("c")
+    );
 }
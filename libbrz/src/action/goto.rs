@@ -0,0 +1,38 @@
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+
+use crate::key_mappings;
+use crate::Key;
+
+pub fn default_key_mappings() -> &'static super::KeyMappings {
+    static INSTANCE: OnceCell<super::KeyMappings> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        let mut m = BTreeMap::new();
+
+        key_mappings!(
+            m,
+            { h, GotoLineStart },
+            { l, GotoLineEnd },
+            { k, GotoFirstLine },
+            { j, GotoLastLine },
+            { i, GotoFirstNonWhitespace },
+            { t, GotoViewTop },
+            { c, GotoViewMiddle },
+            { b, GotoViewBottom },
+            { n, NextHunk },
+            { N, PrevHunk },
+            { e, NextDiagnostic },
+            { E, PrevDiagnostic },
+            { d, GotoDefinition },
+            { r, ListReferences },
+            { f, FuzzyFindFile },
+            { m, MatchBracket },
+            { p, SelectLastPaste },
+            { a, SelectLastInsert },
+            { '.', SelectLastChange },
+            { '>', NextTab },
+            { '<', PrevTab },
+        );
+        m
+    })
+}
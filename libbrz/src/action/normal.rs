@@ -1,12 +1,43 @@
 use once_cell::sync::OnceCell;
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 
+use crate::conflict::Pick;
+use crate::layout::{Direction, RESIZE_STEP};
+use crate::lsp::Query;
 use crate::mode;
+use crate::sanitize;
+use crate::span::RecentEdit;
 use crate::Idx;
 use crate::Key;
+use crate::{Motion, TextObject};
 
+use super::count_arg;
+use crate::state::State;
+use crate::target::Target;
+use crate::view::ViewAlign;
 use crate::{action, actions, key_mappings};
 
+/// Grow the focused window in `direction` by the count of steps, or the
+/// argument; shrink it with `sign` -1
+fn resize_window(state: &mut State, args: &[String], direction: Direction, sign: isize) {
+    let steps = count_arg(state, args) as isize;
+    state.resize_window(direction, sign * steps * RESIZE_STEP as isize);
+}
+
+/// With a count (or an argument), first move to that 1-based line
+fn scroll_cursor_to(state: &mut State, args: &[String], align: ViewAlign) {
+    let line = args
+        .first()
+        .and_then(|a| a.parse::<usize>().ok())
+        .or_else(|| state.num_prefix.take());
+    let mut buffer = state.cur_buffer_mut();
+    if let Some(line) = line {
+        buffer.apply_motion(Motion::Line(line.saturating_sub(1)), false);
+    }
+    buffer.align_cursor(align);
+}
+
 pub fn actions() -> &'static super::ActionsById {
     static INSTANCE: OnceCell<super::ActionsById> = OnceCell::new();
     INSTANCE.get_or_init(|| {
@@ -19,6 +50,10 @@ pub fn actions() -> &'static super::ActionsById {
                 state.set_mode(mode::Command::new());
             },
 
+            LineExtend, "line extend mode, selecting whole lines", (state) {
+                state.set_mode(mode::LineExtend::new());
+            },
+
             Insert, "insert mode", (state) {
                 state.set_mode(mode::Insert::new_normal());
             },
@@ -27,12 +62,600 @@ pub fn actions() -> &'static super::ActionsById {
                 state.set_mode(mode::Insert::new_extend());
             },
 
-            MoveDownPage, "move down page", (state) {
-                state.cur_buffer_mut().move_cursor_down(25);
+            InsertText, "insert text", (state, args) {
+                let mut removed = 0;
+                for arg in args {
+                    let (text, n) = sanitize::strip(arg);
+                    removed += n;
+                    state.cur_buffer_mut().insert(&text, false);
+                }
+                if 0 < removed {
+                    state.msg = Some(format!("dropped {} control or bidi chars", removed));
+                }
+            },
+
+            MoveLeft, "move left", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Left(times), false);
+            },
+
+            ExtendLeft, "extend left", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Left(times), true);
+            },
+
+            MoveRight, "move right", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Right(times), false);
+            },
+
+            ExtendRight, "extend right", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Right(times), true);
+            },
+
+            MoveDown, "move down", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Down(times), false);
+            },
+
+            ExtendDown, "extend down", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Down(times), true);
+            },
+
+            MoveUp, "move up", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Up(times), false);
+            },
+
+            ExtendUp, "extend up", (state, args) {
+                let times = count_arg(state, args);
+                state.cur_buffer_mut().apply_motion(Motion::Up(times), true);
+            },
+
+            MoveDownPage, "move down page", (state, args) {
+                let times = count_arg(state, args);
+                let mut buffer = state.cur_buffer_mut();
+                let height = buffer.view.viewport().height;
+                buffer.apply_motion(Motion::Down(height * times), false);
+            },
+
+            ExtendDownPage, "extend down page", (state, args) {
+                let times = count_arg(state, args);
+                let mut buffer = state.cur_buffer_mut();
+                let height = buffer.view.viewport().height;
+                buffer.apply_motion(Motion::Down(height * times), true);
+            },
+
+            MoveUpPage, "move up page", (state, args) {
+                let times = count_arg(state, args);
+                let mut buffer = state.cur_buffer_mut();
+                let height = buffer.view.viewport().height;
+                buffer.apply_motion(Motion::Up(height * times), false);
+            },
+
+            ExtendUpPage, "extend up page", (state, args) {
+                let times = count_arg(state, args);
+                let mut buffer = state.cur_buffer_mut();
+                let height = buffer.view.viewport().height;
+                buffer.apply_motion(Motion::Up(height * times), true);
+            },
+
+            ScrollHalfPageDown, "scroll half a page down", (state, args) {
+                let times = count_arg(state, args) as isize;
+                let mut buffer = state.cur_buffer_mut();
+                let half = (buffer.view.viewport().height / 2).max(1) as isize;
+                buffer.scroll_with_cursor(half * times);
+            },
+
+            ScrollHalfPageUp, "scroll half a page up", (state, args) {
+                let times = count_arg(state, args) as isize;
+                let mut buffer = state.cur_buffer_mut();
+                let half = (buffer.view.viewport().height / 2).max(1) as isize;
+                buffer.scroll_with_cursor(-half * times);
+            },
+
+            ScrollPageDown, "scroll a page down", (state, args) {
+                let times = count_arg(state, args) as isize;
+                let mut buffer = state.cur_buffer_mut();
+                let page = buffer.view.viewport().height.saturating_sub(2).max(1) as isize;
+                buffer.scroll(page * times);
+            },
+
+            ScrollPageUp, "scroll a page up", (state, args) {
+                let times = count_arg(state, args) as isize;
+                let mut buffer = state.cur_buffer_mut();
+                let page = buffer.view.viewport().height.saturating_sub(2).max(1) as isize;
+                buffer.scroll(-page * times);
+            },
+
+            ScrollLineDown, "scroll a line down", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.cur_buffer_mut().scroll(times);
+            },
+
+            ScrollLineUp, "scroll a line up", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.cur_buffer_mut().scroll(-times);
+            },
+
+            View, "view mode", (state) {
+                let line = state.num_prefix.take();
+                state.set_mode(mode::View::new(line));
+            },
+
+            Window, "window mode", (state) {
+                state.set_mode(mode::Window);
+            },
+
+            SplitWindow, "split the window, one above the other", (state) {
+                state.split_window(Direction::Horizontal);
+            },
+
+            SplitWindowSideways, "split the window, side by side", (state) {
+                state.split_window(Direction::Vertical);
+            },
+
+            CloseWindow, "close the window", (state) {
+                state.close_window();
+            },
+
+            OnlyWindow, "close the other windows", (state) {
+                state.only_window();
+            },
+
+            NextWindow, "focus the next window", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.focus_window(times);
+            },
+
+            PrevWindow, "focus the previous window", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.focus_window(-times);
+            },
+
+            GrowWindow, "make the window taller", (state, args) {
+                resize_window(state, args, Direction::Horizontal, 1);
+            },
+
+            ShrinkWindow, "make the window shorter", (state, args) {
+                resize_window(state, args, Direction::Horizontal, -1);
+            },
+
+            WidenWindow, "make the window wider", (state, args) {
+                resize_window(state, args, Direction::Vertical, 1);
+            },
+
+            NarrowWindow, "make the window narrower", (state, args) {
+                resize_window(state, args, Direction::Vertical, -1);
+            },
+
+            NewTab, "open a tab with the buffer", (state) {
+                state.new_tab();
+            },
+
+            CloseTab, "close the tab", (state) {
+                state.close_tab();
+            },
+
+            NextTab, "switch to the next tab", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.focus_tab(times);
+            },
+
+            PrevTab, "switch to the previous tab", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.focus_tab(-times);
+            },
+
+            ScrollCursorTop, "scroll cursor line to the top", (state, args) {
+                scroll_cursor_to(state, args, ViewAlign::Top);
+            },
+
+            ScrollCursorCenter, "scroll cursor line to the center", (state, args) {
+                scroll_cursor_to(state, args, ViewAlign::Center);
+            },
+
+            ScrollCursorBottom, "scroll cursor line to the bottom", (state, args) {
+                scroll_cursor_to(state, args, ViewAlign::Bottom);
+            },
+
+            ToggleFold, "open the fold of the cursor line, or close it", (state) {
+                state.toggle_fold();
+            },
+
+            OpenFold, "open the folds of the cursor line", (state) {
+                state.open_fold();
+            },
+
+            CloseFold, "close the fold of the cursor line", (state) {
+                state.close_fold();
+            },
+
+            OpenAllFolds, "open all the folds", (state) {
+                state.open_all_folds();
+            },
+
+            CloseAllFolds, "close all the folds", (state) {
+                state.close_all_folds();
+            },
+
+            MoveWordForward, "move word forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordForward, false);
+            },
+
+            ExtendWordForward, "extend word forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordForward, true);
+            },
+
+            MoveWordBackward, "move word backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordBackward, false);
+            },
+
+            ExtendWordBackward, "extend word backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordBackward, true);
+            },
+
+            MoveWordEnd, "move to word end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordEnd, false);
+            },
+
+            ExtendWordEnd, "extend to word end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::WordEnd, true);
+            },
+
+            MoveParagraphForward, "move to the next paragraph", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::ParagraphForward, false);
+            },
+
+            ExtendParagraphForward, "extend to the next paragraph", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::ParagraphForward, true);
+            },
+
+            MoveParagraphBackward, "move to the previous paragraph", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::ParagraphBackward, false);
+            },
+
+            ExtendParagraphBackward, "extend to the previous paragraph", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::ParagraphBackward, true);
+            },
+
+            MoveSentenceForward, "move to the next sentence", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SentenceForward, false);
+            },
+
+            ExtendSentenceForward, "extend to the next sentence", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SentenceForward, true);
+            },
+
+            MoveSentenceBackward, "move to the previous sentence", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SentenceBackward, false);
+            },
+
+            ExtendSentenceBackward, "extend to the previous sentence", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SentenceBackward, true);
+            },
+
+            FindChar, "select up to the next given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(false, false, false, n));
+            },
+
+            ExtendFindChar, "extend up to the next given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(false, false, true, n));
+            },
+
+            TillChar, "select up to before the next given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(true, false, false, n));
+            },
+
+            ExtendTillChar, "extend up to before the next given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(true, false, true, n));
+            },
+
+            FindCharBackward, "select back to the previous given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(false, true, false, n));
+            },
+
+            ExtendFindCharBackward, "extend back to the previous given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(false, true, true, n));
+            },
+
+            TillCharBackward, "select back to after the previous given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(true, true, false, n));
+            },
+
+            ExtendTillCharBackward, "extend back to after the previous given char", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::FindChar::new(true, true, true, n));
+            },
+
+            Jump, "go to two given chars in the window, by their label", (state) {
+                state.set_mode(mode::Jump::new(false));
+            },
+
+            ExtendJump, "extend to two given chars in the window, by their label", (state) {
+                state.set_mode(mode::Jump::new(true));
+            },
+
+            RepeatFind, "repeat the last find", (state, args) {
+                let n = count_arg(state, args);
+                state.repeat_find(false, n);
+            },
+
+            RepeatFindReversed, "repeat the last find in the other direction", (state, args) {
+                let n = count_arg(state, args);
+                state.repeat_find(true, n);
+            },
+
+            MoveBigWordForward, "move WORD forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordForward, false);
+            },
+
+            ExtendBigWordForward, "extend WORD forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordForward, true);
+            },
+
+            MoveBigWordBackward, "move WORD backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordBackward, false);
+            },
+
+            ExtendBigWordBackward, "extend WORD backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordBackward, true);
+            },
+
+            MoveBigWordEnd, "move to WORD end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordEnd, false);
+            },
+
+            ExtendBigWordEnd, "extend to WORD end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::BigWordEnd, true);
+            },
+
+            MoveSubWordForward, "move sub-word forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordForward, false);
+            },
+
+            ExtendSubWordForward, "extend sub-word forward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordForward, true);
+            },
+
+            MoveSubWordBackward, "move sub-word backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordBackward, false);
+            },
+
+            ExtendSubWordBackward, "extend sub-word backward", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordBackward, true);
+            },
+
+            MoveSubWordEnd, "move to sub-word end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordEnd, false);
+            },
+
+            ExtendSubWordEnd, "extend to sub-word end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::SubWordEnd, true);
+            },
+
+            SelectLine, "select line", (state) {
+                state.cur_buffer_mut().select_object(TextObject::Line);
+            },
+
+            ExtendLine, "extend line", (state) {
+                state.cur_buffer_mut().extend_object(TextObject::Line);
+            },
+
+            SelectParagraph, "select the paragraph", (state) {
+                state.cur_buffer_mut().select_object(TextObject::Paragraph);
+            },
+
+            ExtendParagraph, "extend to the paragraph", (state) {
+                state.cur_buffer_mut().extend_object(TextObject::Paragraph);
+            },
+
+            SelectSentence, "select the sentence", (state) {
+                state.cur_buffer_mut().select_object(TextObject::Sentence);
+            },
+
+            ExtendSentence, "extend to the sentence", (state) {
+                state.cur_buffer_mut().extend_object(TextObject::Sentence);
+            },
+
+            SelectAll, "select all", (state) {
+                state.cur_buffer_mut().select_object(TextObject::All);
+            },
+
+            Collapse, "collapse selections", (state) {
+                state.cur_buffer_mut().collapse();
+            },
+
+            ReverseSelections, "reverse selections", (state) {
+                state.cur_buffer_mut().reverse_selections();
+            },
+
+            RotateContents, "move the text of each selection to the next one", (state, args) {
+                let n = count_arg(state, args);
+                state.cur_buffer_mut().rotate_contents(n, false);
+            },
+
+            RotateContentsBackward, "move the text of each selection to the previous one", (state, args) {
+                let n = count_arg(state, args);
+                state.cur_buffer_mut().rotate_contents(n, true);
+            },
+
+            RotatePrimary, "make the next selection the primary one", (state, args) {
+                let n = count_arg(state, args);
+                state.cur_buffer_mut().rotate_primary(n, false);
+            },
+
+            RotatePrimaryBackward, "make the previous selection the primary one", (state, args) {
+                let n = count_arg(state, args);
+                state.cur_buffer_mut().rotate_primary(n, true);
+            },
+
+            RemovePrimary, "drop the primary selection", (state) {
+                if !state.cur_buffer_mut().remove_primary() {
+                    state.show_message("only one selection");
+                }
+            },
+
+            SplitLines, "split the selections into lines", (state) {
+                state.cur_buffer_mut().split_lines();
+            },
+
+            Align, "line up the cursors with spaces", (state) {
+                state.cur_buffer_mut().align();
+            },
+
+            UndoSelection, "go back to the selections before", (state) {
+                state.undo_selection(false);
+            },
+
+            RedoSelection, "go forward to the selections gone back from", (state) {
+                state.undo_selection(true);
+            },
+
+            Undo, "undo", (state, args) {
+                let times = count_arg(state, args);
+                state.undo(times);
+            },
+
+            Redo, "redo", (state, args) {
+                let times = count_arg(state, args);
+                state.redo(times);
+            },
+
+            Earlier, "go to the change made before, whatever the branch", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.undo_steps(times);
+            },
+
+            Later, "go to the change made after, whatever the branch", (state, args) {
+                let times = count_arg(state, args) as isize;
+                state.undo_steps(-times);
+            },
+
+            Delete, "delete", (state) {
+                let deleted = state.cur_buffer_mut().delete();
+                state.set_yanked(deleted);
+            },
+
+            Change, "change", (state) {
+                let deleted = state.cur_buffer_mut().delete();
+                state.set_yanked(deleted);
+                state.set_mode(mode::Insert::new_normal());
+            },
+
+            Yank, "yank", (state) {
+                let yanked = state.cur_buffer_mut().yank();
+                state.set_yanked(yanked);
+            },
+
+            Paste, "paste", (state) {
+                state.paste(false);
+            },
+
+            PasteExtend, "paste (extend)", (state) {
+                state.paste(true);
+            },
+
+            PasteOlder, "swap what was just pasted for the yank before it", (state) {
+                state.paste_older();
+            },
+
+            YankHistory, "pick a recent yank to paste", (state) {
+                state.set_mode(mode::YankPicker::default());
+            },
+
+            SelectLastChange, "select the last change", (state) {
+                state.select_recent_edit(RecentEdit::Change);
+            },
+
+            SelectLastInsert, "select the text of the last insert", (state) {
+                state.select_recent_edit(RecentEdit::Insert);
+            },
+
+            SelectLastPaste, "select the last paste", (state) {
+                state.select_recent_edit(RecentEdit::Paste);
+            },
+
+            Goto, "goto mode / goto line", (state) {
+                if let Some(line) = state.num_prefix.take() {
+                    state.note_jump();
+                    state
+                        .cur_buffer_mut()
+                        .apply_motion(Motion::Line(line.saturating_sub(1)), false);
+                } else {
+                    state.set_mode(mode::Goto);
+                }
+            },
+
+            GotoLine, "goto line", (state, args) {
+                let line = count_arg(state, args);
+                state.note_jump();
+                state
+                    .cur_buffer_mut()
+                    .apply_motion(Motion::Line(line.saturating_sub(1)), false);
+            },
+
+            GotoLineEnd, "goto line end", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::LineEnd, false);
+            },
+
+            GotoLineStart, "goto line start", (state) {
+                state.cur_buffer_mut().apply_motion(Motion::LineStart, false);
+            },
+
+            GotoFirstLine, "goto first line", (state) {
+                state.note_jump();
+                state.cur_buffer_mut().apply_motion(Motion::FirstLine, false);
+            },
+
+            GotoLastLine, "goto last line", (state) {
+                state.note_jump();
+                state.cur_buffer_mut().apply_motion(Motion::LastLine, false);
+            },
+
+            JumpBack, "go back to where the window jumped from", (state, args) {
+                let n = count_arg(state, args);
+                state.jump_back(n, false);
+            },
+
+            JumpForward, "go forward again in the jump list", (state, args) {
+                let n = count_arg(state, args);
+                state.jump_back(n, true);
+            },
+
+            SetMark, "name the place of the cursor with a char", (state) {
+                state.set_mode(mode::PickMark::new(true));
+            },
+
+            GotoMark, "go to a mark", (state) {
+                state.set_mode(mode::PickMark::new(false));
+            },
+
+            GotoViewTop, "goto top of the view", (state) {
+                state.cur_buffer_mut().move_cursor_in_view(ViewAlign::Top, false);
+            },
+
+            GotoViewMiddle, "goto middle of the view", (state) {
+                state.cur_buffer_mut().move_cursor_in_view(ViewAlign::Center, false);
             },
 
-            ExtendDownPage, "extend down page", (state) {
-                state.cur_buffer_mut().extend_cursor_down(25);
+            GotoViewBottom, "goto bottom of the view", (state) {
+                state.cur_buffer_mut().move_cursor_in_view(ViewAlign::Bottom, false);
+            },
+
+            GotoFirstNonWhitespace, "goto first non-whitespace", (state) {
+                state
+                    .cur_buffer_mut()
+                    .apply_motion(Motion::FirstNonWhitespace, false);
             },
 
             LineAppend, "append to line", (state) {
@@ -49,16 +672,36 @@ pub fn actions() -> &'static super::ActionsById {
                 state.set_mode(mode::Insert::new_extend());
             },
 
+            Run, "run a command line", (state, args) {
+                mode::Command::run(state, &args.join(" "));
+            },
+
+            Palette, "pick an action to run", (state) {
+                state.set_mode(mode::Palette::default());
+            },
+
             OpenFile, "open mode", (state) {
                 state.set_mode(mode::Find::default());
             },
 
-            MoveUpPage, "move up page", (state) {
-                state.cur_buffer_mut().move_cursor_up(25);
+            FuzzyFindFile, "find a file of the project, fuzzily", (state) {
+                if state.list_files() {
+                    state.set_mode(mode::FuzzyFind::default());
+                }
+            },
+
+            Open, "open file", (state, args) {
+                for path in args {
+                    state.open_buffer(&PathBuf::from(path));
+                }
+            },
+
+            OpenEntry, "open the dashboard entry or the location of the line", (state) {
+                state.open_entry();
             },
 
-            ExtendUpPage, "extend up page", (state) {
-                state.cur_buffer_mut().extend_cursor_up(25);
+            Dashboard, "recent files and projects", (state) {
+                state.open_dashboard();
             },
 
             IndentRight, "indent right", (state) {
@@ -77,11 +720,168 @@ pub fn actions() -> &'static super::ActionsById {
                 state.set_mode(mode::Insert::new_normal());
             },
 
+            Search, "search", (state) {
+                let options = state.search_options;
+                state.set_mode(mode::Search::new(options, false));
+            },
+
+            SearchBackward, "search backward", (state) {
+                let options = state.search_options;
+                state.set_mode(mode::Search::new(options, true));
+            },
+
+            SelectMatches, "select the matches of a regex in the selections", (state) {
+                let options = state.search_options;
+                state.set_mode(mode::Search::selecting(options, false));
+            },
+
+            SplitSelections, "split the selections on a regex", (state) {
+                let options = state.search_options;
+                state.set_mode(mode::Search::selecting(options, true));
+            },
+
+            SelectRegister, "name the register for the next yank, delete or paste", (state) {
+                state.set_mode(mode::PickRegister::new(mode::RegisterUse::Select));
+            },
+
+            Record, "record keys in a register, or stop recording", (state) {
+                if state.macros.recording().is_some() {
+                    state.toggle_recording(None);
+                } else {
+                    state.set_mode(mode::PickRegister::new(mode::RegisterUse::Record));
+                }
+            },
+
+            ReplayMacro, "replay the keys recorded in a register", (state, args) {
+                let n = count_arg(state, args);
+                state.set_mode(mode::PickRegister::new(mode::RegisterUse::Replay(n)));
+            },
+
+            PipeSelections, "replace the selections with the output of a command", (state) {
+                state.set_mode(mode::Command::with_text("|", ""));
+            },
+
+            InsertCommandOutput, "insert the output of a command before the selections", (state) {
+                state.set_mode(mode::Command::with_text("!", ""));
+            },
+
+            SearchNext, "select next match", (state) {
+                state.search_next(false);
+            },
+
+            SearchPrev, "select previous match", (state) {
+                state.search_next(true);
+            },
+
+            MatchBracket, "select to the matching bracket or tag", (state) {
+                state.jump_to_match(false);
+            },
+
+            ExtendMatchBracket, "extend to the matching bracket or tag", (state) {
+                state.jump_to_match(true);
+            },
+
+            UndoCheckpoint, "end the current undo chunk", (state) {
+                state.commit_undo_point();
+            },
+
+            NextConflict, "goto next merge conflict", (state) {
+                state.goto_conflict(false);
+            },
+
+            PrevConflict, "goto previous merge conflict", (state) {
+                state.goto_conflict(true);
+            },
+
+            PickOurs, "resolve conflict with ours", (state) {
+                state.pick_conflict(Pick::Ours);
+            },
+
+            PickTheirs, "resolve conflict with theirs", (state) {
+                state.pick_conflict(Pick::Theirs);
+            },
+
+            PickBoth, "resolve conflict with both", (state) {
+                state.pick_conflict(Pick::Both);
+            },
+
+            ConflictView, "three-way view of the conflict", (state) {
+                state.set_mode(mode::ConflictView);
+            },
+
+            NextHunk, "select next git hunk", (state) {
+                state.goto_target(Target::Hunk, false);
+            },
+
+            PrevHunk, "select previous git hunk", (state) {
+                state.goto_target(Target::Hunk, true);
+            },
+
+            SelectHunk, "select git hunk", (state) {
+                state.select_target(Target::Hunk);
+            },
+
+            NextDiagnostic, "select next diagnostic", (state) {
+                state.goto_target(Target::Diagnostic, false);
+            },
+
+            PrevDiagnostic, "select previous diagnostic", (state) {
+                state.goto_target(Target::Diagnostic, true);
+            },
+
+            GotoDefinition, "go to the definition of the symbol", (state) {
+                state.query(Query::Definition);
+            },
+
+            ListReferences, "list the references to the symbol", (state) {
+                state.query(Query::References);
+            },
+
+            ShowHover, "show what the language server says of the symbol", (state) {
+                state.query(Query::Hover);
+            },
+
+            SelectDiagnostic, "select diagnostic", (state) {
+                state.select_target(Target::Diagnostic);
+            },
+
+            Structure, "syntax navigation mode", (state) {
+                state.set_mode(mode::Structure::default());
+            },
+
+            Layer, "turn a keymap layer on", (state, args) {
+                match args.first() {
+                    Some(name) => state.enter_layer(name),
+                    None => state.msg = Some("usage: Layer <name>".into()),
+                }
+            },
+
+            SplitJoin, "split or join the brackets around", (state) {
+                state.split_join();
+            },
+
             SelectInnerSurrounding, "select inner surrounding", (state) {
-                state.cur_buffer_mut().select_inner_surrounding();
+                state.cur_buffer_mut().select_object(TextObject::InnerSurrounding);
             },
+
             ExpandInnerSurrounding, "expand inner surrounding", (state) {
-                state.cur_buffer_mut().select_inner_surrounding();
+                state.cur_buffer_mut().extend_object(TextObject::InnerSurrounding);
+            },
+
+            SelectInner, "select inside the text object of the next key", (state) {
+                state.set_mode(mode::PickObject::new(false, false));
+            },
+
+            SelectAround, "select the text object of the next key and around it", (state) {
+                state.set_mode(mode::PickObject::new(true, false));
+            },
+
+            ExtendInner, "extend to inside the text object of the next key", (state) {
+                state.set_mode(mode::PickObject::new(false, true));
+            },
+
+            ExtendAround, "extend to the text object of the next key and around it", (state) {
+                state.set_mode(mode::PickObject::new(true, true));
             },
         );
         m
@@ -97,19 +897,121 @@ pub fn default_key_mappings() -> &'static super::KeyMappings {
             m,
             { i, Insert },
             { I, InsertExtend },
+            { V, LineExtend },
             { ':', Command },
+            { h, MoveLeft },
+            { H, ExtendLeft },
+            { l, MoveRight },
+            { L, ExtendRight },
+            { j, MoveDown },
+            { J, ExtendDown },
+            { k, MoveUp },
+            { K, ExtendUp },
+            { key Key::Left, MoveLeft },
+            { key Key::Right, MoveRight },
+            { key Key::Down, MoveDown },
+            { key Key::Up, MoveUp },
+            { w, MoveWordForward },
+            { W, ExtendWordForward },
+            { b, MoveWordBackward },
+            { B, ExtendWordBackward },
+            { e, MoveWordEnd },
+            { E, ExtendWordEnd },
+            { a w, MoveBigWordForward },
+            { a W, ExtendBigWordForward },
+            { '}', MoveParagraphForward },
+            { key Key::Alt('}'), ExtendParagraphForward },
+            { '{', MoveParagraphBackward },
+            { key Key::Alt('{'), ExtendParagraphBackward },
+            { ')', MoveSentenceForward },
+            { key Key::Alt(')'), ExtendSentenceForward },
+            { '(', MoveSentenceBackward },
+            { key Key::Alt('('), ExtendSentenceBackward },
+            { f, FindChar },
+            { F, ExtendFindChar },
+            { t, TillChar },
+            { T, ExtendTillChar },
+            { a f, FindCharBackward },
+            { a F, ExtendFindCharBackward },
+            { v, Jump },
+            { a v, ExtendJump },
+            { ';', RepeatFind },
+            { ',', RepeatFindReversed },
+            { x, SelectLine },
+            { X, ExtendLine },
+            { '%', SelectAll },
+            { ' ', Collapse },
+            { key Key::Alt(';'), ReverseSelections },
+            { a r, RotateContents },
+            { a R, RotateContentsBackward },
+            { a n, RotatePrimary },
+            { a N, RotatePrimaryBackward },
+            { key Key::Alt(','), RemovePrimary },
+            { a l, SplitLines },
+            { '&', Align },
+            { c h, UndoSelection },
+            { c k, RedoSelection },
+            { u, Undo },
+            { U, Redo },
+            { a u, Earlier },
+            { a U, Later },
+            { d, Delete },
+            { c, Change },
+            { y, Yank },
+            { p, Paste },
+            { P, PasteExtend },
+            { g, Goto },
             { a, LineAppend },
             { A, LineAppendExtend },
             { c p, OpenFile },
-            { c u, MoveUpPage },
-            { c d, MoveDownPage },
+            { c o, JumpBack },
+            { key Key::Char('\t'), JumpForward },
+            { c u, ScrollHalfPageUp },
+            { c d, ScrollHalfPageDown },
+            { c b, ScrollPageUp },
+            { c f, ScrollPageDown },
+            { c y, ScrollLineUp },
+            { c e, ScrollLineDown },
+            { z, View },
+            { c w, Window },
             { c U, ExtendUpPage },
             { c D, ExtendDownPage },
             { '>', IndentRight },
             { '<', IndentLeft },
             { 'o', OpenLine },
-            { a i, SelectInnerSurrounding },
-            { a I, ExpandInnerSurrounding },
+            { '/', Search },
+            { '?', SearchBackward },
+            { n, SearchNext },
+            { N, SearchPrev },
+            { m, SetMark },
+            { '\'', GotoMark },
+            { M, ExtendMatchBracket },
+            { s, SelectMatches },
+            { S, SplitSelections },
+            { '"', SelectRegister },
+            { q, Record },
+            { '@', ReplayMacro },
+            { '|', PipeSelections },
+            { '!', InsertCommandOutput },
+            { ']', NextConflict },
+            { '[', PrevConflict },
+            { a o, PickOurs },
+            { a t, PickTheirs },
+            { a b, PickBoth },
+            { a m, ConflictView },
+            { a i, SelectInner },
+            { a I, ExtendInner },
+            { a a, SelectAround },
+            { a A, ExtendAround },
+            { a k, ShowHover },
+            { a h, SelectHunk },
+            { a e, SelectDiagnostic },
+            { a j, SplitJoin },
+            { a s, Structure },
+            { a x, Palette },
+            { a p, PasteOlder },
+            { a y, YankHistory },
+            { key Key::Char('\n'), OpenEntry },
         );
         m
     })
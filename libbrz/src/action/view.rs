@@ -0,0 +1,26 @@
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+
+use crate::key_mappings;
+use crate::Key;
+
+pub fn default_key_mappings() -> &'static super::KeyMappings {
+    static INSTANCE: OnceCell<super::KeyMappings> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        let mut m = BTreeMap::new();
+
+        key_mappings!(
+            m,
+            { t, ScrollCursorTop },
+            { z, ScrollCursorCenter },
+            { b, ScrollCursorBottom },
+            { a, ToggleFold },
+            { o, OpenFold },
+            { c, CloseFold },
+            { R, OpenAllFolds },
+            { M, CloseAllFolds },
+            { key Key::Char('\n'), ScrollCursorTop },
+        );
+        m
+    })
+}
@@ -0,0 +1,31 @@
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+
+use crate::key_mappings;
+use crate::Key;
+
+pub fn default_key_mappings() -> &'static super::KeyMappings {
+    static INSTANCE: OnceCell<super::KeyMappings> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        let mut m = BTreeMap::new();
+
+        key_mappings!(
+            m,
+            { s, SplitWindow },
+            { v, SplitWindowSideways },
+            { c, CloseWindow },
+            { q, CloseWindow },
+            { o, OnlyWindow },
+            { w, NextWindow },
+            { c w, NextWindow },
+            { W, PrevWindow },
+            { '+', GrowWindow },
+            { '-', ShrinkWindow },
+            { '>', WidenWindow },
+            { '<', NarrowWindow },
+            { t, NewTab },
+            { T, CloseTab },
+        );
+        m
+    })
+}
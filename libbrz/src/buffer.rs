@@ -1,10 +1,22 @@
 #![allow(dead_code)]
-use crate::{idx::*, position::*, prelude::*, selection::*, util::char};
+use crate::{
+    idx::*,
+    indent::Indent,
+    motion::{Motion, TextObject},
+    object::Object,
+    position::{convert, *},
+    prelude::*,
+    register::{Register, RegisterKind},
+    search::Search,
+    selection::*,
+    undo::UndoTree,
+    util::char,
+    view::{ViewAlign, ViewState},
+};
 use ropey::Rope;
-use std::cell::RefCell;
 use std::cmp::{max, min};
-use std::collections::BTreeSet;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum VisualSelection {
@@ -37,6 +49,10 @@ fn distance_to_prev_tabstop_test() {
     }
 }
 
+/// All the selections of a view
+///
+/// There's always at least one selection; `primary` is the index of the
+/// one the view follows.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SelectionSet {
     pub primary: usize,
@@ -113,6 +129,25 @@ impl SelectionSet {
         }
     }
 
+    /// Move along with a change made through another view; what was in
+    /// the text removed goes to where it was
+    pub fn follow_change(&mut self, change: &TextChange) {
+        let end = change.start.0 + change.removed;
+        let fix = |idx: Idx| {
+            if change.start < idx && end <= idx.0 {
+                Idx(idx.0 - change.removed + change.inserted)
+            } else if change.start < idx {
+                change.start
+            } else {
+                idx
+            }
+        };
+        for sel in &mut self.selections {
+            sel.cursor = fix(sel.cursor);
+            sel.anchor = fix(sel.anchor);
+        }
+    }
+
     pub fn maybe_save_cursor_column(&mut self, text: &Rope) {
         if self.cursor_column.is_empty() {
             self.cursor_column = self
@@ -123,6 +158,16 @@ impl SelectionSet {
         }
     }
 
+    /// Make sure all selections point inside `text`
+    ///
+    /// Needed after the text was modified through a different view.
+    pub fn trim_to_text(&mut self, text: &Rope) {
+        for sel in &mut self.selections {
+            sel.anchor = sel.anchor.trim_to_text(text);
+            sel.cursor = sel.cursor.trim_to_text(text);
+        }
+    }
+
     pub fn clear_cursor_column(&mut self) {
         self.cursor_column.clear();
     }
@@ -140,35 +185,134 @@ impl SelectionSet {
             *sel = sel.sorted()
         }
     }
+
+    /// Merge the selections that overlap or are the same into one, so
+    /// that no char is covered twice
+    ///
+    /// One containing the other stays as it is; otherwise they become
+    /// one covering both, in the direction of the one starting first.
+    /// The others keep their order, the primary being what it went into.
+    pub fn merge_overlapping(&mut self, text: &Rope) {
+        if self.selections.len() < 2 {
+            return;
+        }
+        let range = |sel: Selection| sel.normalized(text).sorted_range_usize();
+        let mut order: Vec<usize> = (0..self.selections.len()).collect();
+        order.sort_by_key(|&i| {
+            let range = range(self.selections[i]);
+            (range.start, range.end)
+        });
+        let mut into: Vec<usize> = (0..self.selections.len()).collect();
+        let mut last: Option<usize> = None;
+        for i in order {
+            let Some(l) = last else {
+                last = Some(i);
+                continue;
+            };
+            let (a, b) = (range(self.selections[l]), range(self.selections[i]));
+            if a.end <= b.start && a != b {
+                last = Some(i);
+                continue;
+            }
+            into[i] = l;
+            if a.end < b.end {
+                self.selections[l] = if b.start == a.start {
+                    self.selections[i]
+                } else if self.selections[l].is_forward() {
+                    Selection::new_from_normalized(Idx(a.start), Idx(b.end))
+                } else {
+                    Selection::new_from_normalized(Idx(b.end), Idx(a.start))
+                };
+            }
+        }
+        if into.iter().enumerate().all(|(i, &l)| i == l) {
+            return;
+        }
+        let mut new_index = vec![0; into.len()];
+        let mut kept = 0;
+        for (i, &l) in into.iter().enumerate() {
+            if i == l {
+                new_index[i] = kept;
+                kept += 1;
+            }
+        }
+        self.primary = new_index[into[self.primary]];
+        let column = std::mem::take(&mut self.cursor_column);
+        if column.len() == into.len() {
+            self.cursor_column = column
+                .into_iter()
+                .enumerate()
+                .filter(|&(i, _)| into[i] == i)
+                .map(|(_, column)| column)
+                .collect();
+        }
+        self.selections = std::mem::take(&mut self.selections)
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, _)| into[i] == i)
+            .map(|(_, sel)| sel)
+            .collect();
+    }
+
+    pub fn idx_selection_type(&self, idx: Idx, text: &Rope) -> VisualSelection {
+        if self.selections.iter().any(|sel| {
+            /*sel.is_empty()
+            && */
+            sel.normalized(text)
+                .is_idx_inside_direction_marker(idx, text)
+        }) {
+            VisualSelection::DirectionMarker
+        } else if self
+            .selections
+            .iter()
+            .any(|sel| sel.normalized(text).is_idx_strictly_inside(idx))
+        {
+            VisualSelection::Selection
+        } else {
+            VisualSelection::None
+        }
+    }
+
+    pub fn cursor_coord(&self, text: &Rope) -> Position {
+        self.selections[0].cursor.to_position(text)
+    }
+}
+
+/// How many most recent `TextChange`s a `Buffer` remembers
+const CHANGE_LOG_CAPACITY: usize = 1024;
+
+/// A single edit of the text
+///
+/// `removed` chars starting at `start` were replaced with `inserted`
+/// chars.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: Idx,
+    pub removed: usize,
+    pub inserted: usize,
 }
 
 /// Buffer
 ///
-/// A file opened for edition + some state around
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Text of a file opened for edition, along with its undo history.
+///
+/// All the edits should go through `insert`, `remove` and `replace_text`,
+/// so that anything keeping track of positions in the text (search
+/// matches, diagnostics, etc.) can follow them using `changes_since`.
+///
+/// Selections are not a part of a buffer: they belong to each `ViewState`
+/// looking into it. Editing is done through a `BufferView`.
+#[derive(Debug, Clone, Default)]
 pub struct Buffer {
-    pub text: ropey::Rope,
-    pub selection: SelectionSet,
+    pub text: Rope,
 
-    pub path: Option<PathBuf>,
+    pub indent: Indent,
 
-    pub tabstop: usize,
-    pub expand_tabs: bool,
+    pub(crate) history: UndoTree,
 
-    pub view_line_offset: RefCell<usize>,
-}
-
-impl Default for Buffer {
-    fn default() -> Self {
-        Self {
-            text: Rope::default(),
-            tabstop: 4,
-            selection: default(),
-            expand_tabs: true,
-            path: None,
-            view_line_offset: RefCell::new(0),
-        }
-    }
+    /// Number of changes made so far
+    version: u64,
+    changes: VecDeque<TextChange>,
 }
 
 impl Buffer {
@@ -180,15 +324,168 @@ impl Buffer {
         self.text.len_lines()
     }
 
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn record_change(&mut self, change: TextChange) {
+        if self.changes.len() == CHANGE_LOG_CAPACITY {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+        self.version += 1;
+    }
+
+    /// Changes made after `version`, oldest first
+    ///
+    /// `None` if they are too old to be remembered, in which case whatever
+    /// was computed at `version` has to be recomputed from scratch.
+    pub fn changes_since(&self, version: u64) -> Option<impl Iterator<Item = &TextChange>> {
+        let missing = self.version.checked_sub(version)?;
+        if self.changes.len() < missing as usize {
+            return None;
+        }
+        Some(
+            self.changes
+                .iter()
+                .skip(self.changes.len() - missing as usize),
+        )
+    }
+
+    pub fn insert(&mut self, idx: Idx, s: &str) {
+        self.text.insert(idx.0, s);
+        self.record_change(TextChange {
+            start: idx,
+            removed: 0,
+            inserted: s.chars().count(),
+        });
+    }
+
+    pub fn remove(&mut self, range: std::ops::Range<usize>) {
+        self.record_change(TextChange {
+            start: Idx(range.start),
+            removed: range.len(),
+            inserted: 0,
+        });
+        self.text.remove(range);
+    }
+
+    /// Replace the whole text
+    pub fn replace_text(&mut self, text: Rope) {
+        self.record_change(TextChange {
+            start: Idx(0),
+            removed: self.text.len_chars(),
+            inserted: text.len_chars(),
+        });
+        self.text = text;
+    }
+
+    /// Go back to `text`, as if the last change, committed as an undo
+    /// point, never happened
+    pub fn revert_last_change(&mut self, text: Rope) {
+        self.history.forget_cur(&self.text, self.version);
+        self.replace_text(text);
+    }
+
+    pub fn to_visual(&self, coord: Position) -> Position {
+        Position {
+            line: coord.line,
+            column: convert::cell_column(
+                &self.text,
+                coord.to_idx(&self.text),
+                self.indent.tab_width,
+            ),
+        }
+    }
+
+    pub(crate) fn indent_text(&self, times: usize) -> String {
+        self.indent.text(times)
+    }
+
+    pub fn maybe_commit_undo_point(&mut self, selection: &SelectionSet) {
+        self.history.commit(&self.text, self.version, selection);
+    }
+
+    /// Go to the text of undo point `i`, returning the selection to
+    /// restore
+    fn restore_undo_point(&mut self, i: usize) -> SelectionSet {
+        let text = self.history.node(i).text.clone();
+        self.replace_text(text);
+        self.history.restore(i, self.version).selection.clone()
+    }
+
+    /// Undo `times`, returning the selection to restore
+    pub fn undo(&mut self, times: usize, selection: &SelectionSet) -> SelectionSet {
+        self.maybe_commit_undo_point(selection); // commit to unify
+        let i = self.history.undo_target(times).expect("committed");
+        self.restore_undo_point(i)
+    }
+
+    /// Redo `times`, returning the selection to restore if anything was redone
+    pub fn redo(&mut self, times: usize) -> Option<SelectionSet> {
+        if !self.history.is_at_cur(&self.text, self.version) {
+            return None;
+        }
+        let i = self.history.redo_target(times)?;
+        Some(self.restore_undo_point(i))
+    }
+
+    /// Go to the state `steps` undo points made before the current one,
+    /// or after with negative `steps`, whatever the branch; the selection
+    /// to restore if there's one
+    pub fn undo_steps(&mut self, steps: isize, selection: &SelectionSet) -> Option<SelectionSet> {
+        self.maybe_commit_undo_point(selection);
+        let i = self.history.step_target(steps)?;
+        Some(self.restore_undo_point(i))
+    }
+
+    /// Go to the state the text was in `duration` earlier, or later with
+    /// `later`, whatever the branch
+    pub fn undo_by_time(
+        &mut self,
+        duration: Duration,
+        later: bool,
+        selection: &SelectionSet,
+    ) -> Option<SelectionSet> {
+        self.maybe_commit_undo_point(selection);
+        let i = self.history.time_target(duration, later)?;
+        Some(self.restore_undo_point(i))
+    }
+}
+
+/// A `Buffer` edited through the selections of one `ViewState`
+///
+/// Every operation applies to all the selections. See `crate::motion`
+/// for an example.
+pub struct BufferView<'a> {
+    pub buffer: &'a mut Buffer,
+    pub view: &'a mut ViewState,
+}
+
+impl<'a> BufferView<'a> {
+    pub fn new(buffer: &'a mut Buffer, view: &'a mut ViewState) -> Self {
+        view.selection.trim_to_text(&buffer.text);
+        Self { buffer, view }
+    }
+
+    pub fn text(&self) -> &Rope {
+        &self.buffer.text
+    }
+
+    pub fn lines(&self) -> usize {
+        self.buffer.lines()
+    }
+
+    pub fn cursor_coord(&self) -> Position {
+        self.view.selection.cursor_coord(&self.buffer.text)
+    }
+
     fn for_each_selection<F, R>(&self, mut f: F) -> Vec<R>
     where
         F: FnMut(&Selection, &Rope) -> R,
     {
-        let Self {
-            ref selection,
-            ref text,
-            ..
-        } = *self;
+        let text = &self.buffer.text;
+        let selection = &self.view.selection;
 
         selection
             .selections
@@ -201,11 +498,8 @@ impl Buffer {
     where
         F: FnMut(&Selection, &Rope) -> R,
     {
-        let Self {
-            ref selection,
-            ref text,
-            ..
-        } = *self;
+        let text = &self.buffer.text;
+        let selection = &self.view.selection;
 
         selection
             .selections
@@ -218,19 +512,13 @@ impl Buffer {
     where
         F: FnMut(&mut Selection, &mut Rope) -> R,
     {
-        let Self {
-            ref mut selection,
-            ref mut text,
-            ..
-        } = *self;
+        let text = &mut self.buffer.text;
+        let selection = &mut self.view.selection;
 
         selection
             .selections
             .iter_mut()
-            .map(|sel| {
-                let res = f(sel, text);
-                res
-            })
+            .map(|sel| f(sel, text))
             .collect()
     }
 
@@ -238,11 +526,8 @@ impl Buffer {
     where
         F: FnMut(usize, &Selection, &Rope) -> R,
     {
-        let Self {
-            ref selection,
-            ref text,
-            ..
-        } = *self;
+        let text = &self.buffer.text;
+        let selection = &self.view.selection;
 
         selection
             .selections
@@ -256,11 +541,8 @@ impl Buffer {
     where
         F: FnMut(usize, &mut Selection, &mut Rope) -> R,
     {
-        let Self {
-            ref mut selection,
-            ref mut text,
-            ..
-        } = *self;
+        let text = &mut self.buffer.text;
+        let selection = &mut self.view.selection;
 
         selection
             .selections
@@ -270,28 +552,188 @@ impl Buffer {
             .collect()
     }
 
-    pub fn idx_selection_type(&self, idx: Idx) -> VisualSelection {
-        if self.selection.selections.iter().any(|sel| {
-            /*sel.is_empty()
-            && */
-            sel.normalized(&self.text)
-                .is_idx_inside_direction_marker(idx, &self.text)
-        }) {
-            VisualSelection::DirectionMarker
-        } else if self
-            .selection
-            .selections
+    pub fn reverse_selections(&mut self) {
+        self.map_each_selection_mut(|sel, _text| *sel = sel.reversed());
+    }
+
+    /// Move the text of each selection to the `n`th selection after it,
+    /// in the order of the text, or before it if `backward`, those past
+    /// the last one going round to the first; the selections select the
+    /// text they get
+    pub fn rotate_contents(&mut self, n: usize, backward: bool) {
+        // the contents of selections on the same chars would go there twice
+        self.view.selection.merge_overlapping(&self.buffer.text);
+        let selections = &self.view.selection.selections;
+        if selections.len() < 2 {
+            return;
+        }
+        let text = &self.buffer.text;
+        let primary = selections[self.view.selection.primary];
+        let mut ranges: Vec<_> = selections
             .iter()
-            .any(|sel| sel.normalized(&self.text).is_idx_strictly_inside(idx))
-        {
-            VisualSelection::Selection
+            .map(|sel| sel.normalized(text).sorted_range_usize())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        let mut contents: Vec<String> = ranges
+            .iter()
+            .map(|range| text.slice(range.clone()).to_string())
+            .collect();
+        let n = n % contents.len();
+        if backward {
+            contents.rotate_left(n);
         } else {
-            VisualSelection::None
+            contents.rotate_right(n);
         }
+        let primary_i = ranges
+            .iter()
+            .position(|range| *range == primary.normalized(text).sorted_range_usize())
+            .unwrap_or(0);
+        self.replace_ranges(ranges.into_iter().zip(contents).collect());
+        self.view.selection.primary = primary_i;
     }
 
-    pub fn reverse_selections(&mut self) {
-        self.map_each_selection_mut(|sel, _text| *sel = sel.reversed());
+    /// The selections in the order of the text, by index
+    fn selections_in_text_order(&self) -> Vec<usize> {
+        let selections = &self.view.selection.selections;
+        let mut order: Vec<usize> = (0..selections.len()).collect();
+        order.sort_by_key(|&i| selections[i].sorted_pair());
+        order
+    }
+
+    /// Make the `n`th selection after the primary one, in the order of
+    /// the text, the primary one, or the `n`th before it if `backward`
+    pub fn rotate_primary(&mut self, n: usize, backward: bool) {
+        let order = self.selections_in_text_order();
+        let len = order.len();
+        let rank = order
+            .iter()
+            .position(|&i| i == self.view.selection.primary)
+            .unwrap_or(0);
+        let rank = if backward {
+            (rank + len - n % len) % len
+        } else {
+            (rank + n) % len
+        };
+        self.view.selection.primary = order[rank];
+    }
+
+    /// Drop the primary selection, the one after it becoming primary;
+    /// `false` if it's the only one
+    pub fn remove_primary(&mut self) -> bool {
+        let order = self.selections_in_text_order();
+        if order.len() < 2 {
+            return false;
+        }
+        let primary = self.view.selection.primary;
+        let rank = order.iter().position(|&i| i == primary).unwrap_or(0);
+        let next = order[(rank + 1) % order.len()];
+        self.view.selection.selections.remove(primary);
+        self.view.selection.primary = if primary < next { next - 1 } else { next };
+        self.view.selection.clear_cursor_column();
+        true
+    }
+
+    /// Split each selection at the ends of its lines, into one a line with
+    /// its line end; the first part of the primary one is primary
+    pub fn split_lines(&mut self) {
+        let text = &self.buffer.text;
+        let primary = self.view.selection.primary;
+        let mut selections = vec![];
+        let mut new_primary = 0;
+        for (i, sel) in self.view.selection.selections.iter().enumerate() {
+            let range = sel.normalized(text).sorted_range_usize();
+            if i == primary {
+                new_primary = selections.len();
+            }
+            let first_line = text.char_to_line(range.start);
+            let last_line = text.char_to_line(range.end.saturating_sub(1).max(range.start));
+            for line in first_line..=last_line {
+                let start = text.line_to_char(line).max(range.start);
+                let end = text.line_to_char(line + 1).min(range.end);
+                if start < end {
+                    selections.push(Selection::new_from_normalized(Idx(start), Idx(end)));
+                }
+            }
+        }
+        if selections.is_empty() {
+            return;
+        }
+        self.view.selection.selections = selections;
+        self.view.selection.primary = new_primary.min(self.view.selection.selections.len() - 1);
+        self.view.selection.clear_cursor_column();
+        self.view.selection.merge_overlapping(text);
+    }
+
+    /// Line up the cursors in a column, with spaces before the selections
+    /// of those less far right
+    ///
+    /// The first selections of the lines are lined up together, then
+    /// the second ones, and so on.
+    pub fn align(&mut self) {
+        for nth in 0.. {
+            let text = &self.buffer.text;
+            let mut on_line: BTreeMap<usize, usize> = BTreeMap::new();
+            // start and cursor column of the `nth` selection of each line
+            let mut aligned = vec![];
+            for i in self.selections_in_text_order() {
+                let sel = self.view.selection.selections[i];
+                let line = text.char_to_line(sel.cursor.0.min(text.len_chars()));
+                let count = on_line.entry(line).or_insert(0);
+                if *count == nth {
+                    let column = sel.cursor.0 - text.line_to_char(line);
+                    aligned.push((sel.sorted_pair().0, column));
+                }
+                *count += 1;
+            }
+            if aligned.is_empty() {
+                break;
+            }
+            let widest = aligned.iter().map(|&(_, column)| column).max().unwrap_or(0);
+            let padding: Vec<(Idx, usize)> = aligned
+                .into_iter()
+                .map(|(start, column)| (start, widest - column))
+                .filter(|&(_, width)| width != 0)
+                .collect();
+            // moved past the padding before them, and that at their start
+            let shift = |idx: Idx, is_start: bool| {
+                let before: usize = padding
+                    .iter()
+                    .filter(|&&(at, _)| at < idx || (at == idx && is_start))
+                    .map(|&(_, width)| width)
+                    .sum();
+                Idx(idx.0 + before)
+            };
+            for sel in &mut self.view.selection.selections {
+                let start = sel.sorted_pair().0;
+                *sel = Selection::new_from_normalized(
+                    shift(sel.anchor, sel.anchor == start),
+                    shift(sel.cursor, sel.cursor == start),
+                );
+            }
+            for &(at, width) in padding.iter().rev() {
+                self.buffer.insert(at, &" ".repeat(width));
+            }
+        }
+        self.view.selection.clear_cursor_column();
+    }
+
+    /// Go back to the selections before, or forward again if `forward`,
+    /// as described in `selection_history`; `false` if there's none
+    pub fn undo_selection(&mut self, forward: bool) -> bool {
+        let history = &mut self.view.selection_history;
+        let cur = &self.view.selection;
+        let selection = if forward {
+            history.forward(cur, self.buffer)
+        } else {
+            history.back(cur, self.buffer)
+        };
+        match selection {
+            Some(selection) => {
+                self.view.selection = selection;
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn insert_char(&mut self, ch: char, extend: bool) {
@@ -299,13 +741,16 @@ impl Buffer {
     }
 
     pub fn insert_tab(&mut self, extend: bool) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
 
-        if self.expand_tabs {
+        if self.buffer.indent.expand_tabs {
             let mut insertions = self.map_each_selection(|sel, text| {
-                let v_col = self.to_visual(sel.cursor.to_position(text)).column;
+                let v_col = self.buffer.to_visual(sel.cursor.to_position(text)).column;
 
-                (sel.cursor, distance_to_next_tabstop(v_col, self.tabstop))
+                (
+                    sel.cursor,
+                    distance_to_next_tabstop(v_col, self.buffer.indent.width),
+                )
             });
 
             insertions.sort_by_key(|insertion| insertion.0);
@@ -313,11 +758,11 @@ impl Buffer {
 
             for (idx, n) in insertions {
                 if !extend {
-                    self.selection.collapse();
+                    self.view.selection.collapse();
                 }
-                self.selection.sort();
-                self.selection.fix_on_insert(idx, n);
-                self.text.insert(idx.0, &" ".repeat(n));
+                self.view.selection.sort();
+                self.view.selection.fix_on_insert(idx, n);
+                self.buffer.insert(idx, &" ".repeat(n));
             }
         } else {
             self.insert_char('\t', extend);
@@ -325,21 +770,21 @@ impl Buffer {
     }
 
     pub fn insert(&mut self, s: &str, extend: bool) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
 
         let mut insertion_points = self.map_each_selection_mut(|sel, _text| sel.cursor);
         insertion_points.sort();
         insertion_points.reverse();
 
         if !extend {
-            self.selection.collapse();
-            self.selection.sort();
+            self.view.selection.collapse();
+            self.view.selection.sort();
         }
 
         for idx in insertion_points {
             if !s.is_empty() {
-                self.selection.fix_on_insert(idx, s.len());
-                self.text.insert(idx.0, s);
+                self.view.selection.fix_on_insert(idx, s.chars().count());
+                self.buffer.insert(idx, s);
             }
         }
     }
@@ -353,7 +798,8 @@ impl Buffer {
     }
 
     fn open_impl(&mut self, was_enter: bool, extend: bool) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
+        let settings = self.buffer.indent;
         let mut indents = self.map_each_enumerated_selection(|i, sel, text| {
             let (indent, increase_indent) = sel.cursor.desired_indent_when_opening_line(text);
             let insert_idx = if was_enter {
@@ -361,154 +807,264 @@ impl Buffer {
             } else {
                 sel.cursor.forward_to_line_end(text)
             };
+            let before = insert_idx.backward_to_line_start(text).range_to(insert_idx);
+            let increase_indent =
+                increase_indent || settings.opens_block(&before.slice(text).to_string());
             (i, indent, insert_idx, increase_indent)
         });
         indents.sort_by_key(|&(_, _, insert_idx, _)| insert_idx);
         indents.reverse();
 
         if !extend {
-            self.selection.collapse();
+            self.view.selection.collapse();
         }
 
         for (i, (_, indent, insert_idx, increase_indent)) in indents.iter().enumerate() {
             let mut inserted_len = 0;
-            self.text.insert(insert_idx.0, &indent.to_string());
+            self.buffer.insert(*insert_idx, &indent.to_string());
             inserted_len += indent.len_chars();
             if *increase_indent {
-                let indent_text = &self.indent_text(1);
-                self.text.insert(insert_idx.0, &indent_text);
+                let indent_text = &self.buffer.indent_text(1);
+                self.buffer.insert(*insert_idx, indent_text);
                 inserted_len += indent_text.len();
             }
-            self.text.insert_char(insert_idx.0, '\n');
+            self.buffer.insert(*insert_idx, "\n");
             inserted_len += 1;
 
-            self.selection.fix_on_insert(*insert_idx, inserted_len);
-            let sel = &mut self.selection.selections[indents[i].0];
-            sel.cursor = insert_idx.forward_n(inserted_len, &self.text);
+            self.view.selection.fix_on_insert(*insert_idx, inserted_len);
+            let sel = &mut self.view.selection.selections[indents[i].0];
+            sel.cursor = insert_idx.forward_n(inserted_len, &self.buffer.text);
             *sel = if extend { *sel } else { sel.collapsed() };
         }
     }
 
-    pub fn delete(&mut self) -> Vec<Rope> {
-        self.selection.clear_cursor_column();
-        let res = self.map_each_enumerated_selection_mut(|i, sel, text| {
+    pub fn delete(&mut self) -> Register {
+        self.view.selection.clear_cursor_column();
+        let ranges = self.map_each_enumerated_selection_mut(|_, sel, text| {
             let range = sel
                 .normalized(text)
                 .self_or_direction_marker(text)
                 .sorted_range_usize();
-            let yanked = text.slice(range.clone()).into();
             *sel = sel.collapsed();
-            (yanked, i, range)
+            range
         });
-        let mut removal_points = vec![];
-        let mut yanked = vec![];
-
-        for (y, _, r) in res.into_iter() {
-            removal_points.push(r);
-            yanked.push(y);
-        }
+        let yanked = Register::yanked(&self.buffer.text, &ranges);
 
-        self.remove_ranges(removal_points);
+        self.remove_ranges(ranges);
 
         yanked
     }
 
-    pub fn yank(&mut self) -> Vec<Rope> {
-        self.map_each_selection_mut(|sel, text| {
-            let range = sel.normalized(text).sorted_range_usize();
-            text.slice(range).into()
-        })
+    pub fn yank(&mut self) -> Register {
+        let ranges =
+            self.map_each_selection_mut(|sel, text| sel.normalized(text).sorted_range_usize());
+        Register::yanked(&self.buffer.text, &ranges)
     }
 
-    pub fn paste(&mut self, yanked: &[Rope]) {
-        let mut insertion_points = self.map_each_selection_mut(|sel, _text| (sel.cursor));
-        insertion_points.sort();
-        insertion_points.reverse();
+    /// Paste each part of the `register` at its selection, collapsing it
+    ///
+    /// Lines go after the lines of the selection, and a block spreads over
+    /// the lines from the cursor if there are fewer selections than parts.
+    pub fn paste(&mut self, register: &Register) {
+        match register.kind {
+            RegisterKind::Lines => self.paste_lines(&register.parts, false),
+            RegisterKind::Block if self.view.selection.selections.len() < register.parts.len() => {
+                self.paste_block(&register.parts)
+            }
+            _ => {
+                self.view.selection.collapse();
+                self.paste_at_cursors(&register.parts);
+            }
+        }
+    }
 
-        for (i, idx) in insertion_points.iter().enumerate() {
-            self.selection.collapse();
-            if let Some(to_yank) = yanked.get(i) {
-                self.selection.fix_on_insert(*idx, to_yank.len_chars());
-                for chunk in to_yank.chunks() {
-                    self.text.insert(idx.0, chunk);
-                }
+    /// Like `paste`, but extending the selections over what's pasted;
+    /// lines go before the lines of the selection
+    pub fn paste_extend(&mut self, register: &Register) {
+        match register.kind {
+            RegisterKind::Lines => self.paste_lines(&register.parts, true),
+            RegisterKind::Block if self.view.selection.selections.len() < register.parts.len() => {
+                self.paste_block(&register.parts)
             }
+            _ => self.paste_at_cursors(&register.parts),
         }
     }
 
-    pub fn paste_extend(&mut self, yanked: &[Rope]) {
-        let mut insertion_points = self.map_each_enumerated_selection(|_i, sel, _text| sel.cursor);
+    /// Insert the `parts` at the cursors, in order
+    fn paste_at_cursors(&mut self, parts: &[Rope]) {
+        let mut insertion_points = self.map_each_selection_mut(|sel, _text| sel.cursor);
         insertion_points.sort();
-        insertion_points.reverse();
 
-        for (i, idx) in insertion_points.iter().enumerate() {
-            if let Some(to_yank) = yanked.get(i) {
-                self.selection.fix_on_insert(*idx, to_yank.len_chars());
-                for chunk in to_yank.chunks() {
-                    self.text.insert(idx.0, chunk);
-                }
+        for (idx, part) in insertion_points.iter().zip(parts).rev() {
+            self.view.selection.fix_on_insert(*idx, part.len_chars());
+            self.buffer.insert(*idx, &part.to_string());
+        }
+    }
+
+    /// Insert the `parts` as lines after (or `before`) the lines of each
+    /// selection
+    fn paste_lines(&mut self, parts: &[Rope], before: bool) {
+        let mut insertion_points = self.map_each_selection_mut(|sel, text| {
+            let range = sel.normalized(text).sorted_range_usize();
+            if before {
+                return text.line_to_char(text.char_to_line(range.start));
+            }
+            let last = if range.is_empty() {
+                range.start
+            } else {
+                range.end - 1
+            };
+            let next_line = text.char_to_line(last) + 1;
+            if next_line < text.len_lines() {
+                text.line_to_char(next_line)
+            } else {
+                text.len_chars()
+            }
+        });
+        insertion_points.sort();
+        let mut pasted = vec![];
+        for (idx, part) in insertion_points.iter().zip(parts).rev() {
+            let mut part = part.to_string();
+            let at_end_of_last_line = *idx == self.buffer.text.len_chars()
+                && *idx
+                    != self
+                        .buffer
+                        .text
+                        .line_to_char(self.buffer.text.char_to_line(*idx));
+            if at_end_of_last_line {
+                let line = part.strip_suffix('\n').unwrap_or(&part);
+                part = format!("\n{}", line);
+            } else if !part.ends_with('\n') {
+                part.push('\n');
             }
+            let start = Idx(*idx + at_end_of_last_line as usize);
+            let len = part.chars().count();
+            if before {
+                self.view.selection.fix_on_insert(Idx(*idx), len);
+            }
+            self.buffer.insert(Idx(*idx), &part);
+            for start in &mut pasted {
+                *start += len;
+            }
+            pasted.push(start.0);
+        }
+        if !before && !pasted.is_empty() {
+            pasted.sort_unstable();
+            self.view.selection.selections = pasted
+                .into_iter()
+                .map(|start| Selection {
+                    anchor: Idx(start),
+                    cursor: Idx(start),
+                })
+                .collect();
+            self.view.selection.primary = 0;
+            self.view.selection.clear_cursor_column();
+        }
+    }
+
+    /// Insert the `parts` at the column of the primary cursor, one per
+    /// line from its line, selecting each
+    fn paste_block(&mut self, parts: &[Rope]) {
+        let cursor = self.view.selection.selections[self.view.selection.primary].cursor;
+        let line = self.buffer.text.char_to_line(cursor.0);
+        let column = cursor.0 - self.buffer.text.line_to_char(line);
+        let mut selections = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            let text = &self.buffer.text;
+            if text.len_lines() <= line + i {
+                let end = Idx(text.len_chars());
+                self.buffer.insert(end, "\n");
+            }
+            let text = &self.buffer.text;
+            let line_start = text.line_to_char(line + i);
+            let line_len = text
+                .line(line + i)
+                .chars()
+                .take_while(|&ch| ch != '\n' && ch != '\r')
+                .count();
+            let padding = " ".repeat(column.saturating_sub(line_len));
+            let idx = Idx(line_start + column.min(line_len));
+            let part = format!("{}{}", padding, part);
+            self.buffer.insert(idx, &part);
+            let start = idx.0 + padding.len();
+            selections.push(Selection {
+                anchor: Idx(start),
+                cursor: Idx(start + part.chars().count() - padding.len()),
+            });
+        }
+        if !selections.is_empty() {
+            self.view.selection.selections = selections;
+            self.view.selection.primary = 0;
+            self.view.selection.clear_cursor_column();
         }
     }
 
     /// Remove text at given ranges
     ///
-    /// `removal_points` contains list of `(selection_index, range)`,
+    /// Overlapping ranges are removed as one, and the selections that meet
+    /// once they're gone become one.
     fn remove_ranges(&mut self, mut removal_points: Vec<std::ops::Range<usize>>) {
-        removal_points.sort_by(|a, b| a.start.cmp(&b.start));
-        removal_points.reverse();
-
+        removal_points.sort_by_key(|a| a.start);
+        let mut merged: Vec<std::ops::Range<usize>> = vec![];
         for range in removal_points {
-            self.selection
-                .fix_on_delete(Idx(range.start), range.len(), &self.text);
-            self.text.remove(range.clone());
+            match merged.last_mut() {
+                Some(last) if range.start < last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
         }
+
+        for range in merged.into_iter().rev() {
+            self.view
+                .selection
+                .fix_on_delete(Idx(range.start), range.len(), &self.buffer.text);
+            self.buffer.remove(range.clone());
+        }
+        self.view.selection.merge_overlapping(&self.buffer.text);
     }
 
     pub fn backspace_one(&mut self) {
-        self.selection.clear_cursor_column();
-        let removal_points = self.map_each_enumerated_selection_mut(|_, sel, text| {
+        self.view.selection.clear_cursor_column();
+        let mut removal_points = self.map_each_enumerated_selection_mut(|_, sel, text| {
             let sel_aligned = sel.normalized(text);
-            let range = (sel_aligned.cursor.0 - 1)..sel_aligned.cursor.0;
+            let range = sel_aligned.cursor.0.saturating_sub(1)..sel_aligned.cursor.0;
             *sel = sel.collapsed();
 
             range
         });
+        // nothing before the start of the text
+        removal_points.retain(|range| !range.is_empty());
 
         self.remove_ranges(removal_points);
     }
 
     pub fn backspace(&mut self, extend: bool) {
-        self.selection.clear_cursor_column();
-        if self.expand_tabs {
-            let mut removal = self.map_each_selection(|sel, text| {
-                let v_col = self.to_visual(sel.cursor.to_position(text)).column;
+        self.view.selection.clear_cursor_column();
+        if self.buffer.indent.expand_tabs {
+            let removal = self.map_each_selection(|sel, text| {
+                let v_col = self.buffer.to_visual(sel.cursor.to_position(text)).column;
 
                 (
                     sel.cursor,
                     if v_col == 0 {
                         1
                     } else if sel.cursor == sel.cursor.before_first_non_whitespace(text) {
-                        distance_to_prev_tabstop(v_col, self.tabstop)
+                        distance_to_prev_tabstop(v_col, self.buffer.indent.width)
                     } else {
                         1
                     },
                 )
             });
 
-            removal.sort_by_key(|r| r.0);
-            removal.reverse();
+            let ranges = removal
+                .into_iter()
+                .map(|(idx, n)| idx.backward_n(n, &self.buffer.text).0..idx.0)
+                .collect();
 
             if !extend {
-                self.selection.collapse();
-                self.selection.sort();
-            }
-            for (idx, n) in removal {
-                let start = idx.backward_n(n, &self.text);
-                self.selection
-                    .fix_on_delete(start, idx.0 - start.0, &self.text);
-                self.text.remove(start.0..idx.0);
+                self.view.selection.collapse();
+                self.view.selection.sort();
             }
+            self.remove_ranges(ranges);
         } else {
             self.backspace_one();
         }
@@ -529,7 +1085,7 @@ impl Buffer {
     where
         F: Fn(Idx, Option<usize>, &Rope) -> Idx,
     {
-        let selection = self.selection.clone();
+        let selection = self.view.selection.clone();
         self.map_each_enumerated_selection_mut(|i, sel, text| {
             let column = selection.cursor_column.get(i).cloned();
             let new_cursor = f(sel.cursor, column, text);
@@ -562,7 +1118,7 @@ impl Buffer {
     where
         F: Fn(Idx, Option<usize>, &Rope) -> Idx,
     {
-        let selection = self.selection.clone();
+        let selection = self.view.selection.clone();
         self.map_each_enumerated_selection_mut(|i, sel, text| {
             let column = selection.cursor_column.get(i).cloned();
             sel.cursor = f(sel.cursor, column, text);
@@ -588,6 +1144,7 @@ impl Buffer {
             sel.anchor = new_anchor;
             sel.cursor = new_cursor;
         });
+        self.view.selection.merge_overlapping(&self.buffer.text);
     }
 
     pub fn move_cursor_coord<F>(&mut self, f: F)
@@ -609,18 +1166,260 @@ impl Buffer {
             sel.cursor = f(sel.cursor.to_position(text), text).to_idx(text);
         });
     }
+    /// Move (or with `extend`, extend) all the selections
+    pub fn apply_motion(&mut self, motion: Motion, extend: bool) {
+        let set_line = |line: usize| {
+            move |coord: Position, text: &Rope| coord.set_line(line, text).trim_column_to_buf(text)
+        };
+        match motion {
+            Motion::Left(n) if extend => self.extend_cursor_backward(n),
+            Motion::Left(n) => self.move_cursor_backward(n),
+            Motion::Right(n) if extend => self.extend_cursor_forward(n),
+            Motion::Right(n) => self.move_cursor_forward(n),
+            Motion::Up(n) if extend => self.extend_cursor_up(n),
+            Motion::Up(n) => self.move_cursor_up(n),
+            Motion::Down(n) if extend => self.extend_cursor_down(n),
+            Motion::Down(n) => self.move_cursor_down(n),
+            Motion::WordForward if extend => self.extend_cursor_2(Idx::forward_word),
+            Motion::WordForward => self.move_cursor_forward_word(),
+            Motion::WordBackward if extend => self.extend_cursor_2(Idx::backward_word),
+            Motion::WordBackward => self.move_cursor_backward_word(),
+            Motion::WordEnd => {
+                self.apply_word_motion(WordKind::Word, Idx::forward_word_end_of, extend)
+            }
+            Motion::BigWordForward => {
+                self.apply_word_motion(WordKind::BigWord, Idx::forward_word_of, extend)
+            }
+            Motion::BigWordBackward => {
+                self.apply_word_motion(WordKind::BigWord, Idx::backward_word_of, extend)
+            }
+            Motion::BigWordEnd => {
+                self.apply_word_motion(WordKind::BigWord, Idx::forward_word_end_of, extend)
+            }
+            Motion::SubWordForward => {
+                self.apply_word_motion(WordKind::SubWord, Idx::forward_word_of, extend)
+            }
+            Motion::SubWordBackward => {
+                self.apply_word_motion(WordKind::SubWord, Idx::backward_word_of, extend)
+            }
+            Motion::SubWordEnd => {
+                self.apply_word_motion(WordKind::SubWord, Idx::forward_word_end_of, extend)
+            }
+            Motion::FirstNonWhitespace if extend => {
+                self.extend_cursor(|idx, text| idx.before_first_non_whitespace(text))
+            }
+            Motion::FirstNonWhitespace => {
+                self.move_cursor(|idx, text| idx.before_first_non_whitespace(text))
+            }
+            Motion::LineStart => {
+                self.apply_coord_motion(|coord, text| coord.set_column(0, text), extend)
+            }
+            Motion::LineEnd => self.apply_coord_motion(
+                |coord, text| {
+                    let line = text.line(coord.line);
+                    coord.set_column(line.len_chars().saturating_sub(1), text)
+                },
+                extend,
+            ),
+            Motion::FirstLine => self.apply_coord_motion(set_line(0), extend),
+            Motion::LastLine => {
+                let last = self.buffer.text.len_lines().saturating_sub(1);
+                self.apply_coord_motion(set_line(last), extend)
+            }
+            Motion::Line(line) => self.apply_coord_motion(set_line(line), extend),
+            Motion::ParagraphForward if extend => self.extend_cursor_2(Idx::forward_paragraph),
+            Motion::ParagraphForward => self.move_cursor_2(Idx::forward_paragraph),
+            Motion::ParagraphBackward if extend => self.extend_cursor_2(Idx::backward_paragraph),
+            Motion::ParagraphBackward => self.move_cursor_2(Idx::backward_paragraph),
+            Motion::SentenceForward if extend => self.extend_cursor_2(Idx::forward_sentence),
+            Motion::SentenceForward => self.move_cursor_2(Idx::forward_sentence),
+            Motion::SentenceBackward if extend => self.extend_cursor_2(Idx::backward_sentence),
+            Motion::SentenceBackward => self.move_cursor_2(Idx::backward_sentence),
+            Motion::Find(find, n) => {
+                self.view.selection.clear_cursor_column();
+                self.map_each_selection_mut(|sel, text| {
+                    // turning back over the char a find just went past
+                    let from = match (sel.is_forward(), find.backward) {
+                        _ if find.till || sel.is_empty() => sel.cursor,
+                        (true, true) if sel.cursor.prev_char(text) == Some(find.ch) => {
+                            sel.cursor.backward(text)
+                        }
+                        (false, false) if sel.cursor.next_char(text) == Some(find.ch) => {
+                            sel.cursor.forward(text)
+                        }
+                        _ => sel.cursor,
+                    };
+                    if let Some(cursor) = from.find_char(find, n, text) {
+                        if !extend {
+                            sel.anchor = sel.cursor;
+                        }
+                        sel.cursor = cursor;
+                    }
+                });
+            }
+        }
+        self.view.selection.merge_overlapping(&self.buffer.text);
+    }
+
+    fn apply_word_motion(
+        &mut self,
+        kind: WordKind,
+        f: fn(Idx, WordKind, &Rope) -> (Idx, Idx),
+        extend: bool,
+    ) {
+        self.view.selection.clear_cursor_column();
+        let f = |idx, text: &Rope| f(idx, kind, text);
+        if extend {
+            self.extend_cursor_2(f)
+        } else {
+            self.move_cursor_2(f)
+        }
+    }
+
+    fn apply_coord_motion(&mut self, f: impl Fn(Position, &Rope) -> Position, extend: bool) {
+        self.view.selection.clear_cursor_column();
+        if extend {
+            self.extend_cursor_coord(f)
+        } else {
+            self.move_cursor_coord(f)
+        }
+    }
+
+    /// Scroll the view by `lines` (up if negative), dragging the cursor
+    /// along only if it would go off the screen
+    pub fn scroll(&mut self, lines: isize) {
+        let viewport = self.view.viewport();
+        let max_offset = self.buffer.lines().saturating_sub(viewport.height);
+        let offset = (viewport.first_line as isize + lines).clamp(0, max_offset as isize);
+        *self.view.line_offset.borrow_mut() = offset as usize;
+        self.keep_cursor_in_view();
+    }
+
+    /// Scroll the view and move the cursor by the same number of lines
+    pub fn scroll_with_cursor(&mut self, lines: isize) {
+        if lines < 0 {
+            self.apply_motion(Motion::Up(lines.unsigned_abs()), false);
+        } else {
+            self.apply_motion(Motion::Down(lines as usize), false);
+        }
+        self.scroll(lines);
+    }
+
+    /// Scroll so that the cursor line is at the top, center or bottom of the view
+    pub fn align_cursor(&mut self, align: ViewAlign) {
+        let viewport = self.view.viewport();
+        let line = self.cursor_coord().line;
+        let offset = match align {
+            ViewAlign::Top => line.saturating_sub(viewport.margin()),
+            ViewAlign::Center => line.saturating_sub(viewport.height / 2),
+            ViewAlign::Bottom => (line + viewport.margin()).saturating_sub(viewport.height),
+        };
+        let max_offset = self.buffer.lines().saturating_sub(viewport.height);
+        *self.view.line_offset.borrow_mut() = offset.min(max_offset);
+    }
+
+    /// Move the cursor to the top, middle or bottom line of the view,
+    /// keeping it clear of the scroll margin
+    ///
+    /// The other cursors are moved by as many lines as the primary one.
+    pub fn move_cursor_in_view(&mut self, align: ViewAlign, extend: bool) {
+        let target = self.view.viewport().line_at(self.buffer.lines(), align);
+        let line = self.cursor_coord().line;
+        if line < target {
+            self.apply_motion(Motion::Down(target - line), extend);
+        } else if target < line {
+            self.apply_motion(Motion::Up(line - target), extend);
+        }
+    }
+
+    fn keep_cursor_in_view(&mut self) {
+        let (top, bottom) = self.view.viewport().cursor_lines(self.buffer.lines());
+        let line = self.cursor_coord().line;
+        if line < top {
+            self.apply_motion(Motion::Down(top - line), false);
+        } else if bottom < line {
+            self.apply_motion(Motion::Up(line - bottom), false);
+        }
+    }
+
+    /// Select `object` around each selection
+    pub fn select_object(&mut self, object: TextObject) {
+        match object {
+            TextObject::Line => self.move_line(),
+            TextObject::InnerSurrounding => self.select_inner_surrounding(),
+            TextObject::All => self.select_all(),
+            TextObject::Paragraph => self.select_around(Idx::paragraph_around, false),
+            TextObject::Sentence => self.select_around(Idx::sentence_around, false),
+            TextObject::Inner(object) => self.select_object_range(object, false, false),
+            TextObject::Around(object) => self.select_object_range(object, true, false),
+        }
+        self.view.selection.merge_overlapping(&self.buffer.text);
+    }
+
+    /// Grow each selection to include `object` around it
+    pub fn extend_object(&mut self, object: TextObject) {
+        match object {
+            TextObject::Line => self.extend_line(),
+            TextObject::InnerSurrounding => self.expand_inner_surrounding(),
+            TextObject::All => self.select_all(),
+            TextObject::Paragraph => self.select_around(Idx::paragraph_around, true),
+            TextObject::Sentence => self.select_around(Idx::sentence_around, true),
+            TextObject::Inner(object) => self.select_object_range(object, false, true),
+            TextObject::Around(object) => self.select_object_range(object, true, true),
+        }
+        self.view.selection.merge_overlapping(&self.buffer.text);
+    }
+
+    /// Select `object` at the cursors, or with `extend`, grow the
+    /// selections to it at both their ends; those with none there stay
+    fn select_object_range(&mut self, object: Object, around: bool, extend: bool) {
+        self.view.selection.clear_cursor_column();
+        self.change_selection(|cursor, anchor, text| {
+            let range = |idx: Idx| object.range(idx.0, around, text);
+            if !extend {
+                return match range(cursor) {
+                    Some(range) => (Idx(range.end), Idx(range.start)),
+                    None => (cursor, anchor),
+                };
+            }
+            let (first, last) = (min(cursor, anchor), max(cursor, anchor));
+            let start = range(first).map_or(first, |range| min(first, Idx(range.start)));
+            let last_char = last.backward(text).max(first);
+            let end = range(last_char).map_or(last, |range| max(last, Idx(range.end)));
+            (end, start)
+        });
+    }
+
+    /// Select what `around` finds around the cursors, or with `extend`,
+    /// around both ends of the selections as well as them
+    fn select_around(&mut self, around: fn(Idx, &Rope) -> (Idx, Idx), extend: bool) {
+        self.view.selection.clear_cursor_column();
+        self.change_selection(|cursor, anchor, text| {
+            if !extend {
+                let (start, end) = around(cursor, text);
+                return (end, start);
+            }
+            let (first, last) = (min(cursor, anchor), max(cursor, anchor));
+            let start = min(first, around(first, text).0);
+            let end = max(last, around(last.backward(text).max(first), text).1);
+            (end, start)
+        });
+    }
+
     pub fn move_cursor_backward(&mut self, n: usize) {
-        self.selection.clear_cursor_column();
-        self.move_cursor(|idx, text| idx.backward_n(n, text));
+        self.view.selection.clear_cursor_column();
+        self.move_cursor(|idx, text| idx.backward_n_graphemes(n, text));
     }
 
     pub fn move_cursor_forward(&mut self, n: usize) {
-        self.selection.clear_cursor_column();
-        self.move_cursor(|idx, text| idx.forward_n(n, text));
+        self.view.selection.clear_cursor_column();
+        self.move_cursor(|idx, text| idx.forward_n_graphemes(n, text));
     }
 
     pub fn move_cursor_down(&mut self, n: usize) {
-        self.selection.maybe_save_cursor_column(&self.text);
+        self.view
+            .selection
+            .maybe_save_cursor_column(&self.buffer.text);
 
         self.move_cursor_vertically_with_column(|idx, column, text| {
             idx.down_unaligned(n, column, text)
@@ -628,44 +1427,50 @@ impl Buffer {
     }
 
     pub fn move_cursor_up(&mut self, n: usize) {
-        self.selection.maybe_save_cursor_column(&self.text);
+        self.view
+            .selection
+            .maybe_save_cursor_column(&self.buffer.text);
         self.move_cursor_vertically_with_column(|idx, column, text| {
             idx.up_unaligned(n, column, text)
         });
     }
 
     pub fn extend_cursor_down(&mut self, n: usize) {
-        self.selection.maybe_save_cursor_column(&self.text);
+        self.view
+            .selection
+            .maybe_save_cursor_column(&self.buffer.text);
         self.extend_cursor_with_column(|idx, column, text| idx.down_unaligned(n, column, text));
     }
 
     pub fn extend_cursor_up(&mut self, n: usize) {
-        self.selection.maybe_save_cursor_column(&self.text);
+        self.view
+            .selection
+            .maybe_save_cursor_column(&self.buffer.text);
         self.extend_cursor_with_column(|idx, column, text| idx.up_unaligned(n, column, text));
     }
 
     pub fn extend_cursor_backward(&mut self, n: usize) {
-        self.selection.clear_cursor_column();
-        self.extend_cursor(|idx, text| idx.backward_n(n, text));
+        self.view.selection.clear_cursor_column();
+        self.extend_cursor(|idx, text| idx.backward_n_graphemes(n, text));
     }
 
     pub fn extend_cursor_forward(&mut self, n: usize) {
-        self.selection.clear_cursor_column();
-        self.extend_cursor(|idx, text| idx.forward_n(n, text));
+        self.view.selection.clear_cursor_column();
+        self.extend_cursor(|idx, text| idx.forward_n_graphemes(n, text));
     }
 
     pub fn move_cursor_forward_word(&mut self) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
         self.move_cursor_2(Idx::forward_word)
     }
 
     pub fn move_cursor_backward_word(&mut self) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
         self.move_cursor_2(Idx::backward_word)
     }
 
     pub fn select_inner_surrounding(&mut self) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
 
         self.map_each_selection_mut(|sel, text| {
             let (start_left, start_right) = sel.sorted_pair();
@@ -688,7 +1493,7 @@ impl Buffer {
     }
 
     pub fn expand_inner_surrounding(&mut self) {
-        self.selection.clear_cursor_column();
+        self.view.selection.clear_cursor_column();
 
         self.map_each_selection_mut(|sel, text| {
             let (start_left, start_right) = sel.sorted_pair();
@@ -727,9 +1532,6 @@ impl Buffer {
             }
         });
     }
-    pub fn cursor_coord(&self) -> Position {
-        self.selection.selections[0].cursor.to_position(&self.text)
-    }
 
     pub fn move_line(&mut self) {
         self.change_selection(|cursor, _anchor, text| {
@@ -755,92 +1557,414 @@ impl Buffer {
         });
     }
 
-    pub fn select_all(&mut self) {
-        self.selection.selections = vec![Selection {
-            anchor: Idx(0),
-            cursor: Idx(self.text.len_chars()),
+    /// Select the match closest to the primary selection in the direction
+    ///
+    /// Returns `false` if there are no matches at all.
+    pub fn select_next_match(&mut self, search: &Search, backward: bool) -> bool {
+        let sel = self.view.selection.selections[self.view.selection.primary];
+        let from = sel.sorted_pair().0;
+        let found = if backward {
+            search.find_prev(&self.buffer.text, from)
+        } else {
+            search.find_next(&self.buffer.text, from)
+        };
+        if let Some((start, end)) = found {
+            self.view.selection.selections = vec![Selection {
+                anchor: start,
+                cursor: end,
+            }];
+            self.view.selection.primary = 0;
+            self.view.selection.clear_cursor_column();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Make each match of `search` inside the selections a selection of
+    /// its own; `false`, leaving the selections, if there's none
+    pub fn select_matches(&mut self, search: &Search) -> bool {
+        let matches = search.find_all(&self.buffer.text);
+        self.replace_selections(|sel| sel.select_matches(&matches))
+    }
+
+    /// Split the selections on the matches of `search`, selecting what's
+    /// between them; `false`, leaving the selections, if nothing is left
+    pub fn split_selections(&mut self, search: &Search) -> bool {
+        let matches = search.find_all(&self.buffer.text);
+        self.replace_selections(|sel| sel.split_on(&matches))
+    }
+
+    /// Replace each selection with those `f` makes of it, the first of
+    /// those of the primary being the primary, if it makes any
+    fn replace_selections(&mut self, f: impl Fn(Selection) -> Vec<Selection>) -> bool {
+        let mut selections = vec![];
+        let mut primary = 0;
+        for (i, &sel) in self.view.selection.selections.iter().enumerate() {
+            let new = f(sel);
+            if i == self.view.selection.primary && !new.is_empty() {
+                primary = selections.len();
+            }
+            selections.extend(new);
+        }
+        if selections.is_empty() {
+            return false;
+        }
+        self.view.selection.selections = selections;
+        self.view.selection.primary = primary;
+        self.view.selection.clear_cursor_column();
+        true
+    }
+
+    /// Replace `range` of the text with `s`, leaving a single cursor at its start
+    pub fn replace_range(&mut self, range: std::ops::Range<usize>, s: &str) {
+        let start = Idx(range.start);
+        self.buffer.remove(range);
+        self.buffer.insert(start, s);
+        self.view.selection.selections = vec![Selection {
+            anchor: start,
+            cursor: start,
         }];
+        self.view.selection.primary = 0;
+        self.view.selection.clear_cursor_column();
     }
 
-    pub fn collapse(&mut self) {
-        if self.selection.selections.len() > 1 {
-            self.selection.selections = vec![self.selection.selections[self.selection.primary]];
-        } else {
-            self.selection.selections[self.selection.primary] =
-                self.selection.selections[self.selection.primary].collapsed();
+    /// Select the chars of `range`, as a single selection
+    pub fn select_range(&mut self, range: std::ops::Range<usize>) {
+        self.view.selection.selections = vec![Selection {
+            anchor: Idx(range.start),
+            cursor: Idx(range.end),
+        }];
+        self.view.selection.primary = 0;
+        self.view.selection.clear_cursor_column();
+    }
+
+    /// Select the chars of each range, the first being the primary
+    pub fn select_ranges(&mut self, ranges: Vec<std::ops::Range<usize>>) {
+        if ranges.is_empty() {
+            return;
         }
+        self.view.selection.selections = ranges
+            .into_iter()
+            .map(|range| Selection {
+                anchor: Idx(range.start),
+                cursor: Idx(range.end),
+            })
+            .collect();
+        self.view.selection.primary = 0;
+        self.view.selection.clear_cursor_column();
     }
 
-    pub fn to_visual(&self, coord: Position) -> Position {
-        let line = self.text.line(coord.line);
-        let v_col = line.slice(..coord.column).chars().fold(0, |v_col, ch| {
-            if ch == '\t' {
-                v_col + distance_to_next_tabstop(v_col, self.tabstop)
-            } else {
-                v_col + 1
-            }
-        });
+    /// Replace each range with its text, selecting what was inserted
+    ///
+    /// Of the ranges that overlap, only the first one in the text is
+    /// replaced.
+    pub fn replace_ranges(&mut self, mut edits: Vec<(std::ops::Range<usize>, String)>) {
+        edits.sort_by_key(|(range, _)| range.start);
+        edits.dedup_by(|(next, _), (kept, _)| next.start < kept.end);
+        let mut selections = vec![];
+        let mut shift = 0isize;
+        for (range, text) in &edits {
+            let start = (range.start as isize + shift) as usize;
+            let len = text.chars().count();
+            selections.push(Selection {
+                anchor: Idx(start),
+                cursor: Idx(start + len),
+            });
+            shift += len as isize - range.len() as isize;
+        }
+        for (range, text) in edits.into_iter().rev() {
+            let start = Idx(range.start);
+            self.buffer.remove(range);
+            self.buffer.insert(start, &text);
+        }
+        if !selections.is_empty() {
+            self.view.selection.selections = selections;
+            self.view.selection.primary = 0;
+            self.view.selection.clear_cursor_column();
+        }
+    }
 
-        Position {
-            line: coord.line,
-            column: v_col,
+    /// Replace each range with its text, moving the selections along
+    ///
+    /// Of the ranges that overlap, only the first one in the text is
+    /// replaced.
+    pub fn edit_ranges(&mut self, mut edits: Vec<(std::ops::Range<usize>, String)>) {
+        edits.sort_by_key(|(range, _)| range.start);
+        edits.dedup_by(|(next, _), (kept, _)| next.start < kept.end);
+        for (range, text) in edits.into_iter().rev() {
+            let start = Idx(range.start);
+            self.view
+                .selection
+                .fix_on_delete(start, range.len(), &self.buffer.text);
+            self.buffer.remove(range);
+            self.view
+                .selection
+                .fix_on_insert(start, text.chars().count());
+            self.buffer.insert(start, &text);
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.view.selection.selections = vec![Selection {
+            anchor: Idx(0),
+            cursor: Idx(self.buffer.text.len_chars()),
+        }];
+        self.view.selection.primary = 0;
+        self.view.selection.clear_cursor_column();
+    }
+
+    pub fn collapse(&mut self) {
+        if self.view.selection.selections.len() > 1 {
+            self.view.selection.selections =
+                vec![self.view.selection.selections[self.view.selection.primary]];
+            self.view.selection.primary = 0;
+            self.view.selection.clear_cursor_column();
+        } else {
+            self.view.selection.selections[self.view.selection.primary] =
+                self.view.selection.selections[self.view.selection.primary].collapsed();
         }
     }
 
     pub fn increase_indent(&mut self, times: usize) {
-        let affected_lines = self.selection.to_lines(&self.text);
+        let affected_lines = self.view.selection.to_lines(&self.buffer.text);
         let mut insertions: Vec<_> = affected_lines
             .into_iter()
-            .map(|line| Position { line, column: 0 }.to_idx(&self.text))
+            .map(|line| Position { line, column: 0 }.to_idx(&self.buffer.text))
             .collect();
 
         insertions.sort_by_key(|insertion| insertion.0);
         insertions.reverse();
 
-        let text = if !self.expand_tabs {
-            "\t".to_owned()
-        } else {
-            " ".repeat(self.tabstop * times)
-        };
+        let text = self.buffer.indent_text(times);
 
         for idx in insertions {
-            self.selection.fix_on_insert(idx, text.len());
-            self.text.insert(idx.0, &text);
-        }
-    }
-
-    fn indent_text(&self, times: usize) -> String {
-        if !self.expand_tabs {
-            "\t".to_owned()
-        } else {
-            " ".repeat(self.tabstop * times)
+            self.view.selection.fix_on_insert(idx, text.len());
+            self.buffer.insert(idx, &text);
         }
     }
 
     pub fn decrease_indent(&mut self, times: usize) {
-        let affected_lines = self.selection.to_lines(&self.text);
+        let affected_lines = self.view.selection.to_lines(&self.buffer.text);
 
         let mut removals: Vec<_> = affected_lines
             .into_iter()
-            .map(|line| Position { line, column: 0 }.to_idx(&self.text))
+            .map(|line| Position { line, column: 0 }.to_idx(&self.buffer.text))
             .collect();
 
         removals.sort_by_key(|insertion| insertion.0);
         removals.reverse();
 
-        let indent_text = self.indent_text(times);
+        let indent_text = self.buffer.indent_text(times);
 
         for idx in removals {
-            let range = idx.range_to(idx.forward_n(indent_text.len(), &self.text));
+            let range = idx.range_to(idx.forward_n(indent_text.len(), &self.buffer.text));
             if range.len() < indent_text.len() {
                 continue;
             }
-            let existing = range.slice(&self.text);
+            let existing = range.slice(&self.buffer.text);
             if existing == indent_text {
-                self.selection
-                    .fix_on_delete(idx, indent_text.len(), &self.text);
-                range.remove_from(&mut self.text);
+                self.view
+                    .selection
+                    .fix_on_delete(idx, indent_text.len(), &self.buffer.text);
+                self.buffer.remove(idx.0..idx.0 + indent_text.len());
             }
         }
     }
 }
+
+#[test]
+fn paste_test() {
+    let mut buffer = Buffer::from_text(Rope::from_str("ab\ncd\ne"));
+    let mut view = ViewState::default();
+    let mut edit = BufferView::new(&mut buffer, &mut view);
+    let select = |edit: &mut BufferView<'_>, ranges: &[(usize, usize)]| {
+        edit.view.selection.selections = ranges
+            .iter()
+            .map(|&(anchor, cursor)| Selection {
+                anchor: Idx(anchor),
+                cursor: Idx(cursor),
+            })
+            .collect();
+        edit.view.selection.primary = 0;
+    };
+
+    select(&mut edit, &[(0, 1), (3, 4)]);
+    let block = edit.yank();
+    assert_eq!(block.kind, RegisterKind::Block);
+    select(&mut edit, &[(7, 7)]);
+    edit.paste(&block);
+    assert_eq!(edit.text().to_string(), "ab\ncd\nea\n c");
+
+    select(&mut edit, &[(3, 6)]);
+    let lines = edit.delete();
+    assert_eq!(lines.kind, RegisterKind::Lines);
+    assert_eq!(edit.text().to_string(), "ab\nea\n c");
+    select(&mut edit, &[(7, 8)]);
+    edit.paste(&lines);
+    assert_eq!(edit.text().to_string(), "ab\nea\n c\ncd");
+    assert_eq!(edit.view.selection.selections[0].cursor, Idx(9));
+    select(&mut edit, &[(0, 1)]);
+    edit.paste_extend(&lines);
+    assert_eq!(edit.text().to_string(), "cd\nab\nea\n c\ncd");
+    assert_eq!(edit.view.selection.selections[0].sorted_range_usize(), 0..4);
+}
+
+#[test]
+fn follow_change_test() {
+    let mut selection = SelectionSet {
+        selections: vec![
+            Selection {
+                anchor: Idx(2),
+                cursor: Idx(4),
+            },
+            Selection {
+                anchor: Idx(10),
+                cursor: Idx(8),
+            },
+        ],
+        ..SelectionSet::default()
+    };
+    let change = |start, removed, inserted| TextChange {
+        start: Idx(start),
+        removed,
+        inserted,
+    };
+    let ends = |selection: &SelectionSet| -> Vec<(usize, usize)> {
+        selection
+            .selections
+            .iter()
+            .map(|sel| (sel.anchor.0, sel.cursor.0))
+            .collect()
+    };
+    // text inserted where a selection starts goes in it
+    selection.follow_change(&change(2, 0, 3));
+    assert_eq!(ends(&selection), vec![(2, 7), (13, 11)]);
+    selection.follow_change(&change(5, 4, 1));
+    assert_eq!(ends(&selection), vec![(2, 5), (10, 8)]);
+    selection.follow_change(&change(0, 20, 0));
+    assert_eq!(ends(&selection), vec![(0, 0), (0, 0)]);
+}
+
+#[test]
+fn rotate_contents_test() {
+    let mut buffer = Buffer::from_text(Rope::from_str("f(a, bb, ccc)"));
+    let mut view = ViewState::default();
+    let mut edit = BufferView::new(&mut buffer, &mut view);
+    edit.select_ranges(vec![9..12, 2..3, 5..7]);
+    edit.rotate_contents(1, false);
+    assert_eq!(edit.text().to_string(), "f(ccc, a, bb)");
+    let selected: Vec<_> = edit
+        .view
+        .selection
+        .selections
+        .iter()
+        .map(|sel| sel.sorted_range_usize())
+        .collect();
+    assert_eq!(selected, vec![2..5, 7..8, 10..12]);
+    edit.rotate_contents(2, true);
+    assert_eq!(edit.text().to_string(), "f(bb, ccc, a)");
+    edit.rotate_contents(3, false);
+    assert_eq!(edit.text().to_string(), "f(bb, ccc, a)");
+
+    // cursors on the same char are one selection, rotated with no other
+    edit.view.selection.selections = [0, 1]
+        .iter()
+        .map(|&idx| Selection::new_from_normalized(Idx(idx), Idx(idx)))
+        .collect();
+    edit.view.selection.primary = 0;
+    edit.rotate_contents(1, false);
+    assert_eq!(edit.text().to_string(), "f(bb, ccc, a)");
+    assert_eq!(edit.view.selection.selections.len(), 1);
+
+    edit.replace_ranges(vec![(2..4, "x".to_owned()), (3..9, "y".to_owned())]);
+    assert_eq!(edit.text().to_string(), "f(x, ccc, a)");
+    assert_eq!(edit.view.selection.selections.len(), 1);
+
+    let mut state = crate::testing::state_with_text("1a2b\n");
+    crate::testing::type_keys(&mut state, "%s\\d<ret>d<a-r>cxy");
+    assert_eq!(state.cur_buffer().text.to_string(), "xyb\n");
+}
+
+#[test]
+fn multi_selection_test() {
+    let mut buffer = Buffer::from_text(Rope::from_str("a = 1;\nlong = 2;\nbc = 3;\n"));
+    let mut view = ViewState::default();
+    let mut edit = BufferView::new(&mut buffer, &mut view);
+    let ranges = |edit: &BufferView<'_>| -> Vec<std::ops::Range<usize>> {
+        edit.view
+            .selection
+            .selections
+            .iter()
+            .map(|sel| sel.sorted_range_usize())
+            .collect()
+    };
+    edit.select_ranges(vec![12..13, 2..3, 20..21]);
+    edit.align();
+    assert_eq!(edit.text().to_string(), "a    = 1;\nlong = 2;\nbc   = 3;\n");
+    assert_eq!(ranges(&edit), vec![15..16, 5..6, 25..26]);
+
+    // in the order of the text, whatever that of the selections
+    edit.rotate_primary(1, false);
+    assert_eq!(edit.view.selection.primary, 2);
+    edit.rotate_primary(2, true);
+    assert_eq!(edit.view.selection.primary, 1);
+    assert!(edit.remove_primary());
+    assert_eq!(ranges(&edit), vec![15..16, 25..26]);
+    assert_eq!(edit.view.selection.primary, 0);
+    edit.rotate_primary(1, true);
+    assert!(edit.remove_primary());
+    assert!(!edit.remove_primary());
+
+    edit.select_ranges(std::iter::once(3..23).collect());
+    edit.split_lines();
+    assert_eq!(ranges(&edit), vec![3..10, 10..20, 20..23]);
+
+    // the lines moved down onto the same one are deleted once
+    let mut state = crate::testing::state_with_text("a\nb\n");
+    crate::testing::type_keys(&mut state, "%<a-l><down>");
+    assert_eq!(state.cur_buffer_mut().view.selection.selections.len(), 1);
+    crate::testing::type_keys(&mut state, "d");
+    assert_eq!(state.cur_buffer().text.to_string(), "a\n");
+
+    // down to one selection with another one primary, it's the primary
+    for script in ["}<a-l><a-n>%<tab>", "<a-}><a-l><a-n><space><space>"] {
+        let mut state = crate::testing::state_with_text("one. two? three!\n\nfour\n");
+        crate::testing::type_keys(&mut state, script);
+        let selection = &state.cur_buffer_mut().view.selection;
+        assert_eq!((selection.selections.len(), selection.primary), (1, 0));
+    }
+}
+
+#[test]
+fn select_matches_test() {
+    use crate::search::SearchOptions;
+    let mut buffer = Buffer::from_text(Rope::from_str("a1 b22 c3\nd4"));
+    let mut view = ViewState::default();
+    let mut edit = BufferView::new(&mut buffer, &mut view);
+    let ranges = |edit: &BufferView<'_>| -> Vec<(usize, usize)> {
+        edit.view
+            .selection
+            .selections
+            .iter()
+            .map(|sel| (sel.anchor.0, sel.cursor.0))
+            .collect()
+    };
+    let digits = Search::new("[0-9]+", SearchOptions::default()).unwrap();
+    edit.select_ranges(vec![10..12, 0..9]);
+    assert!(edit.split_selections(&Search::new(" ", SearchOptions::default()).unwrap()));
+    assert_eq!(ranges(&edit), vec![(10, 12), (0, 2), (3, 6), (7, 9)]);
+
+    edit.select_ranges(std::iter::once(2..12).collect());
+    assert!(edit.select_matches(&digits));
+    assert_eq!(ranges(&edit), vec![(4, 6), (8, 9), (11, 12)]);
+    assert!(!edit.split_selections(&digits));
+    assert_eq!(edit.view.selection.selections.len(), 3);
+
+    // moved onto each other, they become one, deleted once
+    let mut state = crate::testing::state_with_text("ab ab\nx\n");
+    crate::testing::type_keys(&mut state, "%sab<ret><down><down>");
+    assert_eq!(state.cur_buffer_mut().view.selection.selections.len(), 1);
+    crate::testing::type_keys(&mut state, "d");
+    assert_eq!(state.cur_buffer().text.to_string(), "ab ab\nx");
+}
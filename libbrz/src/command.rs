@@ -0,0 +1,1110 @@
+//! Commands of the `:` prompt, by name
+//!
+//! A command line is the name of a command, or one of its aliases, and
+//! its arguments, separated by whitespace: `e src/main.rs`. Each command
+//! tells how its arguments complete: `Tab` at the prompt completes the
+//! name, then the argument before the cursor, eg. paths for `:e`.
+//! Frontends can add commands of their own with
+//! `State::register_command`, replacing the built-in ones of the same
+//! name.
+use crate::document::{DiagnosticDisplay, LineNumbers, Wrap};
+use crate::encoding::{self, Encoding, LineEnding};
+use crate::hierarchy::HierarchyKind;
+use crate::layout::Direction;
+use crate::lsp::Query;
+use crate::mode::{FileHistory, GitStatus, Hierarchy};
+use crate::repeat::Coalescing;
+use crate::state::State;
+use crate::theme::Background;
+use crate::undo::{self, UndoSpan};
+use crate::vfs::{DirEntry, EntryKind};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub type CommandFn = Arc<dyn Fn(&mut State, &Args) -> Result<(), String>>;
+
+/// What the arguments of a command complete to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    Nothing,
+    Paths,
+    /// One of these
+    Words(&'static [&'static str]),
+}
+
+#[derive(Clone)]
+pub struct CommandDef {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// Of the arguments, eg. `[<path>…]`
+    pub usage: String,
+    pub help: String,
+    pub completion: Completion,
+    run: CommandFn,
+}
+
+impl fmt::Debug for CommandDef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandDef")
+            .field("name", &self.name)
+            .field("aliases", &self.aliases)
+            .field("usage", &self.usage)
+            .finish()
+    }
+}
+
+impl CommandDef {
+    pub fn new(
+        name: &str,
+        usage: &str,
+        help: &str,
+        run: impl Fn(&mut State, &Args) -> Result<(), String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            aliases: vec![],
+            usage: usage.to_owned(),
+            help: help.to_owned(),
+            completion: Completion::Nothing,
+            run: Arc::new(run),
+        }
+    }
+
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    pub fn complete(mut self, completion: Completion) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// Run the command with the arguments in `rest`, the command line
+    /// after the `name` it was called by
+    pub fn run(&self, state: &mut State, name: &str, rest: &str) -> Result<(), String> {
+        let args = Args {
+            name,
+            usage: &self.usage,
+            rest: rest.trim(),
+            words: rest.split_whitespace().collect(),
+        };
+        (self.run)(state, &args)
+    }
+}
+
+/// The arguments a command is run with
+#[derive(Debug, Clone)]
+pub struct Args<'a> {
+    /// The command was called by
+    pub name: &'a str,
+    usage: &'a str,
+    /// The command line after the name, trimmed
+    pub rest: &'a str,
+    pub words: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    pub fn get(&self, i: usize) -> Option<&'a str> {
+        self.words.get(i).copied()
+    }
+
+    /// All the arguments from `i` on
+    pub fn from(&self, i: usize) -> &[&'a str] {
+        &self.words[i.min(self.words.len())..]
+    }
+
+    /// Tell how the command is used, for `Err`
+    pub fn usage(&self) -> String {
+        format!("usage: {} {}", self.name, self.usage)
+            .trim_end()
+            .to_owned()
+    }
+
+    /// Argument `i` as a `T`, if there is one
+    pub fn parse<T: FromStr>(&self, i: usize) -> Result<Option<T>, String> {
+        self.get(i)
+            .map(|arg| arg.parse().map_err(|_| self.usage()))
+            .transpose()
+    }
+
+    /// Argument `i`, if there is one, as one of `words`
+    pub fn one_of(&self, i: usize, words: &[&str]) -> Result<Option<&'a str>, String> {
+        match self.get(i) {
+            Some(arg) if !words.contains(&arg) => Err(self.usage()),
+            arg => Ok(arg),
+        }
+    }
+}
+
+/// All the commands, by name
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<String, CommandDef>,
+    /// To the names they stand for
+    aliases: BTreeMap<String, String>,
+}
+
+impl CommandRegistry {
+    /// Add `def`, replacing the command of the same name and the aliases
+    /// it takes
+    pub fn register(&mut self, def: CommandDef) {
+        for alias in &def.aliases {
+            self.aliases.insert(alias.clone(), def.name.clone());
+        }
+        if let Some(old) = self.commands.remove(&def.name) {
+            for alias in old.aliases {
+                if self.aliases.get(&alias) == Some(&def.name) && !def.aliases.contains(&alias) {
+                    self.aliases.remove(&alias);
+                }
+            }
+        }
+        self.aliases.remove(&def.name);
+        self.commands.insert(def.name.clone(), def);
+    }
+
+    /// The command of `name`, or of the alias `name`
+    pub fn get(&self, name: &str) -> Option<&CommandDef> {
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        self.commands.get(name)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &CommandDef> {
+        self.commands.values()
+    }
+
+    /// Names and aliases starting with `prefix`, sorted
+    fn names(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .commands
+            .keys()
+            .chain(self.aliases.keys())
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// What the end of `line`, the command line before the cursor,
+    /// completes to: how many chars the completions replace, and the
+    /// completions, those that are done ending with a space
+    pub fn complete(
+        &self,
+        line: &str,
+        list_dir: impl Fn(&Path) -> io::Result<Vec<DirEntry>>,
+    ) -> (usize, Vec<String>) {
+        let word_start = line
+            .char_indices()
+            .rev()
+            .find(|(_, ch)| ch.is_whitespace())
+            .map_or(0, |(i, ch)| i + ch.len_utf8());
+        let word = &line[word_start..];
+        let len = word.chars().count();
+        let name = match line[..word_start].split_whitespace().next() {
+            None => {
+                let names = self.names(word);
+                return (len, names.into_iter().map(|name| name + " ").collect());
+            }
+            Some(name) => name,
+        };
+        let completions = match self.get(name).map(|def| def.completion) {
+            Some(Completion::Paths) => complete_path(word, list_dir),
+            Some(Completion::Words(words)) => words
+                .iter()
+                .filter(|w| w.starts_with(word))
+                .map(|&w| {
+                    if w.ends_with('=') {
+                        w.to_owned()
+                    } else {
+                        format!("{} ", w)
+                    }
+                })
+                .collect(),
+            Some(Completion::Nothing) | None => vec![],
+        };
+        (len, completions)
+    }
+}
+
+/// Paths starting with `word`: those in its directory whose name starts
+/// with the rest of it, directories ending with `/`; dotfiles only if
+/// the rest does
+fn complete_path(word: &str, list_dir: impl Fn(&Path) -> io::Result<Vec<DirEntry>>) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..i + 1], &word[i + 1..]),
+        None => ("", word),
+    };
+    let entries = match list_dir(Path::new(if dir.is_empty() { "." } else { dir })) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut paths: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(prefix))
+        .filter(|entry| !entry.name.starts_with('.') || prefix.starts_with('.'))
+        .map(|entry| match entry.kind {
+            EntryKind::Dir => format!("{}{}/", dir, entry.name),
+            _ => format!("{}{} ", dir, entry.name),
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The longest start all of `completions` share
+pub fn common_prefix(completions: &[String]) -> &str {
+    let first = match completions.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+    let len = completions[1..].iter().fold(first.len(), |len, other| {
+        first[..len]
+            .char_indices()
+            .zip(other.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(other.len()), |((i, _), _)| i)
+    });
+    &first[..len]
+}
+
+fn open_all(state: &mut State, paths: &[&str]) {
+    for path in paths {
+        state.open_buffer(&PathBuf::from(path))
+    }
+}
+
+/// Options of `:set`, and the values they take
+const OPTIONS: &[&str] = &[
+    "wrap=",
+    "numbers=",
+    "diagnostics=",
+    "theme=",
+    "background=",
+    "trash=",
+    "keep-scratch=",
+    "tab-width=",
+    "indent-width=",
+    "expand-tabs=",
+    "coalesce=",
+    "readonly=",
+    "modifiable=",
+    "auto-reload=",
+];
+
+/// The value of `option`, as `:set` takes it
+fn option_value(state: &State, option: &str) -> Option<String> {
+    Some(match option {
+        "wrap" => match state.cur_document_opt()?.wrap {
+            Wrap::None => "off",
+            Wrap::Chars => "chars",
+            Wrap::Words => "words",
+        }
+        .to_owned(),
+        "numbers" => state.line_numbers.name().to_owned(),
+        "diagnostics" => match state.diagnostic_display {
+            DiagnosticDisplay::Hints => "hints",
+            DiagnosticDisplay::VirtualLines {
+                cursor_line_only: false,
+            } => "lines",
+            DiagnosticDisplay::VirtualLines {
+                cursor_line_only: true,
+            } => "cursor",
+        }
+        .to_owned(),
+        "theme" => state.theme.as_deref().unwrap_or("default").to_owned(),
+        "background" => state.background.name().to_owned(),
+        "trash" => state.delete_to_trash.to_string(),
+        "keep-scratch" => state.keep_scratch.to_string(),
+        "tab-width" => state.cur_buffer_opt()?.indent.tab_width.to_string(),
+        "indent-width" => state.cur_buffer_opt()?.indent.width.to_string(),
+        "expand-tabs" => state.cur_buffer_opt()?.indent.expand_tabs.to_string(),
+        "coalesce" => state.key_coalescing().name().to_owned(),
+        "readonly" => state.cur_document_opt()?.read_only.to_string(),
+        "modifiable" => state.cur_document_opt()?.is_modifiable().to_string(),
+        "auto-reload" => state.auto_reload.to_string(),
+        _ => return None,
+    })
+}
+
+fn set_option(state: &mut State, option: &str, value: &str) -> Result<(), String> {
+    let unknown = || format!("unknown {}: {}", option, value);
+    match option {
+        "wrap" => state.set_wrap(match value {
+            "off" => Wrap::None,
+            "chars" => Wrap::Chars,
+            "words" => Wrap::Words,
+            _ => return Err(unknown()),
+        }),
+        "numbers" => state.set_line_numbers(LineNumbers::parse(value).ok_or_else(unknown)?),
+        "diagnostics" => state.set_diagnostic_display(match value {
+            "hints" => DiagnosticDisplay::Hints,
+            "lines" => DiagnosticDisplay::VirtualLines {
+                cursor_line_only: false,
+            },
+            "cursor" => DiagnosticDisplay::VirtualLines {
+                cursor_line_only: true,
+            },
+            _ => return Err(unknown()),
+        }),
+        "theme" => state.set_theme(value),
+        "background" => state.set_background(Background::parse(value).ok_or_else(unknown)?),
+        "trash" => state.set_delete_to_trash(value.parse().map_err(|_| unknown())?),
+        "keep-scratch" => state.set_keep_scratch(value.parse().map_err(|_| unknown())?),
+        "tab-width" | "indent-width" | "expand-tabs" => {
+            let mut indent = state.cur_buffer_opt().ok_or("no buffer")?.indent;
+            let width = || {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|&width| 0 < width)
+                    .ok_or_else(unknown)
+            };
+            match option {
+                "tab-width" => indent.tab_width = width()?,
+                "indent-width" => indent.width = width()?,
+                _ => indent.expand_tabs = value.parse().map_err(|_| unknown())?,
+            }
+            state.set_indent(indent);
+        }
+        "coalesce" => state.set_key_coalescing(Coalescing::parse(value).ok_or_else(unknown)?),
+        "readonly" => state.set_read_only(value.parse().map_err(|_| unknown())?),
+        "modifiable" => state.set_modifiable(value.parse().map_err(|_| unknown())?),
+        "auto-reload" => state.set_auto_reload(value.parse().map_err(|_| unknown())?),
+        _ => return Err(format!("no option {}", option)),
+    }
+    Ok(())
+}
+
+/// `:set`: `name=value` sets options, `name` tells the value of one,
+/// and nothing tells all of them
+fn set(state: &mut State, args: &Args) -> Result<(), String> {
+    if args.words.is_empty() {
+        let values: Vec<String> = OPTIONS
+            .iter()
+            .filter_map(|option| {
+                let option = option.trim_end_matches('=');
+                Some(format!("{}={}", option, option_value(state, option)?))
+            })
+            .collect();
+        state.msg = Some(values.join(" "));
+        return Ok(());
+    }
+    for &arg in &args.words {
+        match arg.split_once('=') {
+            Some((option, value)) => set_option(state, option, value)?,
+            None => {
+                let value = option_value(state, arg).ok_or_else(|| format!("no option {}", arg))?;
+                state.msg = Some(format!("{}={}", arg, value));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl CommandRegistry {
+    /// The commands the editor comes with
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        let commands = vec![
+            CommandDef::new("q", "", "quit", |state, _| {
+                state.quit = true;
+                Ok(())
+            })
+            .alias("quit"),
+            CommandDef::new("bn", "", "go to the next buffer", |state, _| {
+                state.buffer_next();
+                Ok(())
+            }),
+            CommandDef::new("bp", "", "go to the previous buffer", |state, _| {
+                state.buffer_prev();
+                Ok(())
+            }),
+            CommandDef::new("e", "[<path>…]", "open files", |state, args| {
+                open_all(state, &args.words);
+                Ok(())
+            })
+            .alias("edit")
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "scratch",
+                "",
+                "open a buffer not backed by any file",
+                |state, _| {
+                    state.open_scratch_buffer("");
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "reload",
+                "",
+                "read the file of the buffer again",
+                |state, _| {
+                    state.reload_buffer();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "recover",
+                "",
+                "apply the changes not written before a crash to their files' buffers",
+                |state, _| {
+                    state.recover();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "recover-drop",
+                "",
+                "forget the changes not written before a crash",
+                |state, _| {
+                    state.drop_recovery();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "earlier",
+                "[<n>|<n>s|<n>m|<n>h|<n>d]",
+                "go back in the undo history",
+                |state, args| undo_span(state, args, false),
+            ),
+            CommandDef::new(
+                "later",
+                "[<n>|<n>s|<n>m|<n>h|<n>d]",
+                "go forward in the undo history",
+                |state, args| undo_span(state, args, true),
+            ),
+            CommandDef::new(
+                "split",
+                "[<path>…]",
+                "split the window, above and below",
+                |state, args| {
+                    state.split_window(Direction::Horizontal);
+                    open_all(state, &args.words);
+                    Ok(())
+                },
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "vsplit",
+                "[<path>…]",
+                "split the window, side by side",
+                |state, args| {
+                    state.split_window(Direction::Vertical);
+                    open_all(state, &args.words);
+                    Ok(())
+                },
+            )
+            .complete(Completion::Paths),
+            CommandDef::new("close", "", "close the window", |state, _| {
+                state.close_window();
+                Ok(())
+            }),
+            CommandDef::new("only", "", "close the other windows", |state, _| {
+                state.only_window();
+                Ok(())
+            }),
+            CommandDef::new("tabnew", "[<path>…]", "open a tab", |state, args| {
+                state.new_tab();
+                open_all(state, &args.words);
+                Ok(())
+            })
+            .complete(Completion::Paths),
+            CommandDef::new("tabclose", "", "close the tab", |state, _| {
+                state.close_tab();
+                Ok(())
+            }),
+            CommandDef::new(
+                "tabrename",
+                "[<name>]",
+                "name the tab, or name it after its buffer",
+                |state, args| {
+                    state.rename_tab(Some(args.rest.to_owned()).filter(|name| !name.is_empty()));
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "tabnext",
+                "[<tab number>]",
+                "go to the next tab, or tab n",
+                |state, args| {
+                    match args.parse::<usize>(0)? {
+                        None => state.focus_tab(1),
+                        Some(n) if 0 < n => state.goto_tab(n - 1),
+                        Some(_) => return Err(args.usage()),
+                    }
+                    Ok(())
+                },
+            ),
+            CommandDef::new("tabprev", "", "go to the previous tab", |state, _| {
+                state.focus_tab(-1);
+                Ok(())
+            }),
+            CommandDef::new(
+                "resize",
+                "+<percent>|-<percent>",
+                "make the window taller or shorter",
+                |state, args| {
+                    let delta = args.parse(0)?.ok_or_else(|| args.usage())?;
+                    state.resize_window(Direction::Horizontal, delta);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "vresize",
+                "+<percent>|-<percent>",
+                "make the window wider or narrower",
+                |state, args| {
+                    let delta = args.parse(0)?.ok_or_else(|| args.usage())?;
+                    state.resize_window(Direction::Vertical, delta);
+                    Ok(())
+                },
+            ),
+            CommandDef::new("db", "", "close the buffer", |state, _| {
+                state.delete_buffer();
+                Ok(())
+            })
+            .alias("bd"),
+            CommandDef::new(
+                "delete-file",
+                "",
+                "delete the file of the buffer",
+                |state, _| {
+                    state.delete_file();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "undelete-file",
+                "",
+                "bring the file deleted last back",
+                |state, _| {
+                    state.undelete_file();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "w",
+                "[<path>]",
+                "write the buffer, to its file or to path",
+                |state, args| {
+                    state.write_buffer(args.get(0).map(PathBuf::from));
+                    Ok(())
+                },
+            )
+            .alias("write")
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "w!",
+                "[<path>]",
+                "write the buffer, even over changes made to its file elsewhere",
+                |state, args| {
+                    state.write_buffer_forced(args.get(0).map(PathBuf::from));
+                    Ok(())
+                },
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "sudo-w",
+                "",
+                "write the buffer with elevated rights",
+                |state, _| {
+                    state.write_buffer_elevated();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "git",
+                "[status|commit|amend]",
+                "show the status of the repository, or commit",
+                |state, args| {
+                    match args.get(0) {
+                        None | Some("status") => state.set_mode(GitStatus::default()),
+                        Some("commit") => state.git_commit_buffer(false),
+                        Some("amend") => state.git_commit_buffer(true),
+                        Some(sub) => return Err(format!("unknown git command: {}", sub)),
+                    }
+                    Ok(())
+                },
+            )
+            .complete(Completion::Words(&["status", "commit", "amend"])),
+            CommandDef::new(
+                "history",
+                "",
+                "show the revisions of the file",
+                |state, _| {
+                    state.set_mode(FileHistory::default());
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "restore",
+                "",
+                "restore the file from the revision shown",
+                |state, _| {
+                    state.restore_from_revision();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "definition",
+                "",
+                "go to the definition of the symbol",
+                |state, _| {
+                    state.query(Query::Definition);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "references",
+                "",
+                "list the references to the symbol",
+                |state, _| {
+                    state.query(Query::References);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "hover",
+                "",
+                "show what the language server says of the symbol",
+                |state, _| {
+                    state.query(Query::Hover);
+                    Ok(())
+                },
+            ),
+            CommandDef::new("health", "", "check the environment", |state, _| {
+                state.health();
+                Ok(())
+            }),
+            CommandDef::new(
+                "lsp-info",
+                "",
+                "show the servers of the buffer",
+                |state, _| {
+                    state.lsp_info();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "full",
+                "",
+                "enable all features for a large file, reading it all if previewed",
+                |state, _| {
+                    state.full_features();
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "tasks",
+                "",
+                "show or hide the tasks in progress",
+                |state, _| {
+                    state.tasks.show_list = !state.tasks.show_list;
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "plugins",
+                "[close]",
+                "tell the plugins running, or close the overlay of one",
+                |state, args| {
+                    match args.get(0) {
+                        Some("close") => state.plugin_overlay = None,
+                        Some(_) => return Err(args.usage()),
+                        None if state.plugin_names().is_empty() => {
+                            state.msg = Some("no plugins".into())
+                        }
+                        None => state.msg = Some(state.plugin_names().join(" ")),
+                    }
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "key",
+                "<key>",
+                "tell what a key does in normal mode",
+                |state, args| {
+                    let key = args.get(0).and_then(crate::layer::parse_key);
+                    state.describe_key(key.ok_or_else(|| args.usage())?);
+                    Ok(())
+                },
+            ),
+            CommandDef::new("layer", "<name>", "enter a layer", |state, args| {
+                state.enter_layer(args.get(0).ok_or_else(|| args.usage())?);
+                Ok(())
+            }),
+            CommandDef::new(
+                "colors",
+                "<kind>|view [<style>=<attrs>…]",
+                "change the colors of a kind of panes, or of the view",
+                |state, args| {
+                    let scope = args.get(0).ok_or_else(|| args.usage())?;
+                    state.set_colors(scope, &args.from(1).join(" "));
+                    Ok(())
+                },
+            )
+            .complete(Completion::Words(crate::theme::PANE_KINDS)),
+            CommandDef::new("theme", "[<name>]", "pick a theme", |state, args| {
+                match args.get(0) {
+                    Some(name) => state.set_theme(name),
+                    None => state.msg = Some(state.theme_info()),
+                }
+                Ok(())
+            }),
+            CommandDef::new(
+                "toggle-background",
+                "",
+                "draw with the light or dark variant of the theme, the other one",
+                |state, _| {
+                    state.set_background(state.background.toggled());
+                    state.msg = Some(format!("{} background", state.background.name()));
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "statusline",
+                "[<template>]",
+                "lay the status line out",
+                |state, args| {
+                    state.set_status_template(args.rest);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "status-segments",
+                "[<segment>[:<style>]…]",
+                "show the segments on the status line, in order",
+                |state, args| {
+                    state.set_status_segments(args.rest);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "calls",
+                "[in|out]",
+                "show the calls of the symbol",
+                |state, args| {
+                    let kind = match args.one_of(0, &["in", "out"])? {
+                        None | Some("in") => HierarchyKind::IncomingCalls,
+                        Some(_) => HierarchyKind::OutgoingCalls,
+                    };
+                    state.set_mode(Hierarchy::new(kind));
+                    Ok(())
+                },
+            )
+            .complete(Completion::Words(&["in", "out"])),
+            CommandDef::new(
+                "types",
+                "[super|sub]",
+                "show the supertypes or subtypes of the symbol",
+                |state, args| {
+                    let kind = match args.one_of(0, &["super", "sub"])? {
+                        None | Some("super") => HierarchyKind::Supertypes,
+                        Some(_) => HierarchyKind::Subtypes,
+                    };
+                    state.set_mode(Hierarchy::new(kind));
+                    Ok(())
+                },
+            )
+            .complete(Completion::Words(&["super", "sub"])),
+            CommandDef::new(
+                "lens",
+                "[<n>]",
+                "run the code lens n of the line",
+                |state, args| {
+                    state.run_code_lens(args.parse(0)?.unwrap_or(1));
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "diagnostics",
+                "[hints|lines|cursor]",
+                "show diagnostics as hints or on lines of their own",
+                |state, args| match args.get(0) {
+                    None if state.diagnostic_display == DiagnosticDisplay::Hints => {
+                        set_option(state, "diagnostics", "lines")
+                    }
+                    None => set_option(state, "diagnostics", "hints"),
+                    Some(display) => set_option(state, "diagnostics", display),
+                },
+            )
+            .complete(Completion::Words(&["hints", "lines", "cursor"])),
+            CommandDef::new(
+                "wrap",
+                "[off|chars|words]",
+                "wrap long lines, or not",
+                |state, args| {
+                    let wrap = state.cur_document_opt().ok_or("no buffer")?.wrap;
+                    match args.get(0) {
+                        None if wrap == Wrap::None => set_option(state, "wrap", "words"),
+                        None => set_option(state, "wrap", "off"),
+                        Some(wrap) => set_option(state, "wrap", wrap),
+                    }
+                },
+            )
+            .complete(Completion::Words(&["off", "chars", "words"])),
+            CommandDef::new(
+                "numbers",
+                "[off|absolute|relative|hybrid]",
+                "number lines from the start, or from the cursor",
+                |state, args| match args.get(0) {
+                    None if state.line_numbers == LineNumbers::Absolute => {
+                        set_option(state, "numbers", "hybrid")
+                    }
+                    None => set_option(state, "numbers", "absolute"),
+                    Some(numbers) => set_option(state, "numbers", numbers),
+                },
+            )
+            .complete(Completion::Words(LineNumbers::NAMES)),
+            CommandDef::new(
+                "set",
+                "[<option>[=<value>]…]",
+                "set options, or tell their values",
+                set,
+            )
+            .complete(Completion::Words(OPTIONS)),
+            CommandDef::new(
+                "rename",
+                "<old> <new>",
+                "rename a symbol in the project",
+                |state, args| match (args.get(0), args.get(1)) {
+                    (Some(old), Some(new)) => {
+                        state.rename(old, new);
+                        Ok(())
+                    }
+                    _ => Err(args.usage()),
+                },
+            ),
+            CommandDef::new(
+                "rename-file",
+                "<path>",
+                "rename the file of the buffer, and the references to it",
+                |state, args| match args.get(0) {
+                    Some(path) => {
+                        state.rename_file(PathBuf::from(path));
+                        Ok(())
+                    }
+                    None => Err(args.usage()),
+                },
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "export-selections",
+                "[<path>]",
+                "write the selections to a file, or yank them",
+                |state, args| state.write_selections(args.get(0).map(Path::new)),
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "import-selections",
+                "[<path>]",
+                "select again what was exported, from a file or a register",
+                |state, args| state.read_selections(args.get(0).map(Path::new)),
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "mksession",
+                "[<path>]",
+                "write the files, windows and registers, to load later",
+                |state, args| state.write_session(args.get(0).map(Path::new)),
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "loadsession",
+                "[<path>]",
+                "open the files and windows of a session again",
+                |state, args| state.load_session(args.get(0).map(Path::new)),
+            )
+            .complete(Completion::Paths),
+            CommandDef::new(
+                "encoding",
+                "[<encoding>]",
+                "write the file in another encoding, or tell its own",
+                |state, args| match args.get(0) {
+                    None => state.set_file_format(None, None),
+                    Some(name) => match Encoding::parse(name) {
+                        Some(encoding) => state.set_file_format(Some(encoding), None),
+                        None => Err(format!("no encoding {}", name)),
+                    },
+                },
+            )
+            .complete(Completion::Words(encoding::ENCODINGS)),
+            CommandDef::new(
+                "line-ending",
+                "[lf|crlf]",
+                "end the lines of the file with LF or CRLF when written",
+                |state, args| match args.get(0) {
+                    None => state.set_file_format(None, None),
+                    Some(name) => match LineEnding::parse(name) {
+                        Some(line_ending) => state.set_file_format(None, Some(line_ending)),
+                        None => Err(args.usage()),
+                    },
+                },
+            )
+            .complete(Completion::Words(&["lf", "crlf"])),
+            CommandDef::new(
+                "grep",
+                "<pattern>",
+                "search all the files, in the background",
+                |state, args| {
+                    state.grep(args.rest);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "files",
+                "",
+                "find a file of the project, fuzzily",
+                |state, _| {
+                    if state.list_files() {
+                        state.set_mode(crate::mode::FuzzyFind::default());
+                    }
+                    Ok(())
+                },
+            ),
+            CommandDef::new("todos", "", "list the TODOs", |state, _| {
+                state.show_todos();
+                Ok(())
+            }),
+            CommandDef::new("dashboard", "", "open the dashboard", |state, _| {
+                state.open_dashboard();
+                Ok(())
+            }),
+            CommandDef::new(
+                "action",
+                "<action> [<arg>…]",
+                "run an action",
+                |state, args| {
+                    let call = args
+                        .rest
+                        .parse::<crate::action::ActionCall>()
+                        .map_err(|e| e.to_string())?;
+                    state.execute_action(&call);
+                    Ok(())
+                },
+            ),
+            CommandDef::new(
+                "profile",
+                "[overlay|hud]",
+                "show the profile, or its overlay or HUD",
+                |state, args| {
+                    match args.get(0) {
+                        Some("overlay") => {
+                            state.profiler.show_overlay = !state.profiler.show_overlay
+                        }
+                        Some("hud") => state.profiler.show_hud = !state.profiler.show_hud,
+                        _ => {
+                            let report = state.profiler.report();
+                            state.open_report(&report);
+                        }
+                    }
+                    Ok(())
+                },
+            )
+            .complete(Completion::Words(&["overlay", "hud"])),
+        ];
+        for def in commands {
+            registry.register(def);
+        }
+        registry
+    }
+}
+
+fn undo_span(state: &mut State, args: &Args, later: bool) -> Result<(), String> {
+    match args.get(0).map(undo::parse_span) {
+        None => state.undo_steps(if later { -1 } else { 1 }),
+        Some(Some(UndoSpan::Steps(steps))) => state.undo_steps(if later { -steps } else { steps }),
+        Some(Some(UndoSpan::Time(duration))) => state.undo_by_time(duration, later),
+        Some(None) => return Err(args.usage()),
+    }
+    Ok(())
+}
+
+#[test]
+fn command_registry_test() {
+    let mut registry = CommandRegistry::builtin();
+    assert_eq!(registry.get("bd").map(|def| def.name.as_str()), Some("db"));
+    assert!(registry.get("nope").is_none());
+
+    let dir = |path: &Path| -> io::Result<Vec<DirEntry>> {
+        let entries: &[(&str, EntryKind)] = match path.to_str() {
+            Some(".") => &[
+                ("src", EntryKind::Dir),
+                ("README.md", EntryKind::File),
+                (".git", EntryKind::Dir),
+            ],
+            Some("src/") => &[("main.rs", EntryKind::File), ("mode", EntryKind::Dir)],
+            _ => return Err(io::ErrorKind::NotFound.into()),
+        };
+        Ok(entries
+            .iter()
+            .map(|&(name, kind)| DirEntry {
+                name: name.to_owned(),
+                kind,
+            })
+            .collect())
+    };
+    assert_eq!(
+        registry.complete("tab", dir),
+        (
+            3,
+            vec![
+                "tabclose ".to_owned(),
+                "tabnew ".to_owned(),
+                "tabnext ".to_owned(),
+                "tabprev ".to_owned(),
+                "tabrename ".to_owned()
+            ]
+        )
+    );
+    assert_eq!(
+        registry.complete("e ", dir),
+        (0, vec!["README.md ".to_owned(), "src/".to_owned()])
+    );
+    assert_eq!(
+        registry.complete("e src/m", dir).1,
+        vec!["src/main.rs ", "src/mode/"]
+    );
+    assert_eq!(registry.complete("edit .", dir).1, vec![".git/"]);
+    assert_eq!(
+        registry.complete("set wr", dir),
+        (2, vec!["wrap=".to_owned()])
+    );
+    assert_eq!(registry.complete("grep x", dir), (1, vec![]));
+    assert_eq!(
+        common_prefix(&["tabnew ".into(), "tabnext ".into()]),
+        "tabne"
+    );
+    assert_eq!(common_prefix(&[]), "");
+
+    // commands of the frontend go over the built-in ones, aliases too
+    registry.register(CommandDef::new("edit", "", "", |_, _| Ok(())));
+    assert_eq!(registry.get("edit").map(|def| def.usage.as_str()), Some(""));
+    registry.register(CommandDef::new("e", "<path>", "", |_, _| Ok(())));
+    assert_eq!(registry.get("e").map(|def| def.aliases.len()), Some(0));
+    assert_eq!(
+        registry.get("edit").map(|def| def.name.as_str()),
+        Some("edit")
+    );
+
+    let args = Args {
+        name: "lens",
+        usage: "[<n>]",
+        rest: "x",
+        words: vec!["x"],
+    };
+    assert_eq!(args.parse::<usize>(0), Err("usage: lens [<n>]".to_owned()));
+    assert_eq!(args.parse::<usize>(1), Ok(None));
+
+    let mut state = State::default();
+    let wrap = registry.get("wrap").unwrap();
+    assert_eq!(
+        wrap.run(&mut state, "wrap", ""),
+        Err("no buffer".to_owned())
+    );
+}
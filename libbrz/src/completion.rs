@@ -0,0 +1,251 @@
+//! Completing the word before the cursor, in insert mode
+//!
+//! The candidates come from sources: the words of the buffer, and
+//! whatever the frontend registers, eg. language servers. A source
+//! answers at once, or later through `State::deliver_completions`; the
+//! menu shows what came in so far, filtered fuzzily by the word as it's
+//! typed further. It follows the primary cursor, but the candidate picked
+//! replaces the word before each of them, however far it was typed there.
+use crate::fuzzy::{self, Match};
+use crate::lsp;
+use crate::Idx;
+use ropey::Rope;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Chars of a word typed before the menu opens by itself
+pub const MIN_PREFIX: usize = 2;
+
+/// Lines around the cursor the words of the buffer are taken from
+const WORD_LINES: usize = 5000;
+
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// The word `cursor` is at the end of, maybe empty
+pub fn word_before(text: &Rope, cursor: Idx) -> Range<usize> {
+    let mut start = cursor.0;
+    while 0 < start && is_word_char(text.char(start - 1)) {
+        start -= 1;
+    }
+    start..cursor.0
+}
+
+/// What a source is asked to complete
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub id: u64,
+    pub path: Option<PathBuf>,
+    pub text: Rope,
+    pub cursor: Idx,
+    pub position: lsp::Position,
+    /// The word before the cursor
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub label: String,
+    /// Name of the source, shown next to it
+    pub source: String,
+}
+
+pub trait Source {
+    fn name(&self) -> &str;
+
+    /// The candidates for `request` if known now; `None` if they are to
+    /// be passed to `State::deliver_completions` later, or not at all
+    fn complete(&self, request: &CompletionRequest) -> Option<Vec<Candidate>>;
+}
+
+/// The words of the buffer, the nearest to the cursor first
+#[derive(Debug, Clone, Default)]
+pub struct BufferWords;
+
+impl Source for BufferWords {
+    fn name(&self) -> &str {
+        "buffer"
+    }
+
+    fn complete(&self, request: &CompletionRequest) -> Option<Vec<Candidate>> {
+        let text = &request.text;
+        let line = text.char_to_line(request.cursor.0);
+        let first = line.saturating_sub(WORD_LINES);
+        let last = (line + WORD_LINES).min(text.len_lines());
+        let start = text.line_to_char(first);
+        let word = word_before(text, request.cursor);
+        let mut words: Vec<(usize, String)> = vec![];
+        let mut current: Option<(usize, String)> = None;
+        let chars = text.slice(start..text.line_to_char(last)).chars();
+        for (idx, ch) in (start..).zip(chars.chain(Some(' '))) {
+            if is_word_char(ch) {
+                current
+                    .get_or_insert_with(|| (idx, String::new()))
+                    .1
+                    .push(ch);
+                continue;
+            }
+            if let Some((word_start, found)) = current.take() {
+                // not the one being typed
+                if word_start != word.start
+                    && found.chars().count() > request.prefix.chars().count()
+                {
+                    let distance = (word_start as isize - word.start as isize).unsigned_abs();
+                    words.push((distance, found));
+                }
+            }
+        }
+        words.sort_by_key(|&(distance, _)| distance);
+        let mut candidates: Vec<Candidate> = vec![];
+        for (_, label) in words {
+            if candidates.iter().all(|c| c.label != label) {
+                candidates.push(Candidate {
+                    label,
+                    source: self.name().to_owned(),
+                });
+            }
+        }
+        Some(candidates)
+    }
+}
+
+/// The menu: what the sources answered, and what the word matches
+#[derive(Debug, Clone)]
+pub(crate) struct Completion {
+    pub id: u64,
+    /// Where the word completed starts
+    pub start: Idx,
+    candidates: Vec<Candidate>,
+    pattern: String,
+    /// Indices in `candidates`, with the match, best first
+    matches: Vec<(usize, Match)>,
+    /// Index in the matches; none until one is picked
+    pub selected: Option<usize>,
+    /// Closed with `Ctrl-e`, until the cursor leaves the word
+    pub dismissed: bool,
+}
+
+impl Completion {
+    pub fn new(id: u64, start: Idx, pattern: &str) -> Self {
+        Self {
+            id,
+            start,
+            candidates: vec![],
+            pattern: pattern.to_owned(),
+            matches: vec![],
+            selected: None,
+            dismissed: false,
+        }
+    }
+
+    /// There are matches to show, and it wasn't dismissed
+    pub fn is_shown(&self) -> bool {
+        !self.dismissed && !self.matches.is_empty()
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Add what a source answered, leaving out labels there already
+    pub fn add(&mut self, candidates: Vec<Candidate>) {
+        for candidate in candidates {
+            if self.candidates.iter().all(|c| c.label != candidate.label) {
+                self.candidates.push(candidate);
+            }
+        }
+        self.set_pattern(&self.pattern.clone());
+    }
+
+    pub fn set_pattern(&mut self, pattern: &str) {
+        let selected = self.selected_candidate().map(|c| c.label.clone());
+        let candidates = &self.candidates;
+        // nothing to complete the word to
+        let found = (0..candidates.len()).filter(|&i| candidates[i].label != pattern);
+        self.matches = fuzzy::filter(pattern, found, |&i| candidates[i].label.clone());
+        self.pattern = pattern.to_owned();
+        // the one picked stays so if it still matches
+        self.selected = selected.and_then(|label| {
+            self.matches
+                .iter()
+                .position(|&(i, _)| self.candidates[i].label == label)
+        });
+    }
+
+    /// The candidates matching, best first, with the indices of the chars
+    /// found
+    pub fn matches(&self) -> impl Iterator<Item = (&Candidate, &[usize])> + '_ {
+        self.matches
+            .iter()
+            .map(move |(i, found)| (&self.candidates[*i], found.indices.as_slice()))
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Pick the next match, or the previous one, wrapping around
+    pub fn select(&mut self, forward: bool) {
+        let count = self.match_count();
+        if count == 0 {
+            return;
+        }
+        self.selected = Some(match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(i), true) => (i + 1) % count,
+            (Some(i), false) => (i + count - 1) % count,
+        });
+    }
+
+    pub fn selected_candidate(&self) -> Option<&Candidate> {
+        self.selected
+            .and_then(|i| self.matches.get(i))
+            .map(|&(i, _)| &self.candidates[i])
+    }
+}
+
+#[test]
+fn completion_test() {
+    let text = Rope::from_str("alpha beta\nalphabet al\nalp_bar");
+    let cursor = Idx(text.line_to_char(1) + 11);
+    assert_eq!(word_before(&text, cursor), cursor.0 - 2..cursor.0);
+    let request = CompletionRequest {
+        id: 0,
+        path: None,
+        text: text.clone(),
+        cursor,
+        position: lsp::Position {
+            line: 1,
+            character: 11,
+        },
+        prefix: "al".into(),
+    };
+    let labels = |candidates: &[Candidate]| -> Vec<String> {
+        candidates.iter().map(|c| c.label.clone()).collect()
+    };
+    // the nearest first, without the word typed
+    let words = BufferWords.complete(&request).unwrap();
+    assert_eq!(labels(&words), vec!["alp_bar", "alphabet", "beta", "alpha"]);
+
+    let mut menu = Completion::new(0, Idx(cursor.0 - 2), "al");
+    menu.add(words);
+    menu.add(vec![Candidate {
+        label: "alpha".into(),
+        source: "lsp".into(),
+    }]);
+    let matches = |menu: &Completion| -> Vec<String> {
+        menu.matches().map(|(c, _)| c.label.clone()).collect()
+    };
+    assert_eq!(matches(&menu), vec!["alp_bar", "alphabet", "alpha"]);
+    menu.select(false);
+    assert_eq!(menu.selected_candidate().unwrap().label, "alpha");
+    menu.set_pattern("alpha");
+    // the word as typed is no completion
+    assert_eq!(matches(&menu), vec!["alphabet"]);
+    assert_eq!(menu.selected, None);
+    menu.select(true);
+    menu.set_pattern("alphab");
+    assert_eq!(menu.selected_candidate().unwrap().label, "alphabet");
+}
@@ -0,0 +1,249 @@
+//! The bit of TOML of `config.toml`
+//!
+//! Only what the config needs is read: tables, as `[keys.normal]`, and in
+//! them keys, bare or quoted, each with a string, an integer or a
+//! boolean. Strings are written in `"` with `\` escapes, or in `'` as
+//! they are. Comments go after `#`.
+//!
+//! ```text
+//! [keys]
+//! timeout = 1000
+//!
+//! [keys.normal]
+//! "space,f" = "OpenFile"
+//! a-j = 'Run "grep TODO"'
+//! ```
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    /// From 1, for errors
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Table {
+    /// Names in the header, eg. `["keys", "normal"]`; none for the keys
+    /// before any header
+    pub path: Vec<String>,
+    /// Of the header, from 1
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseConfigError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+/// A string at the start of `s`, and what's after it
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    let quote = s.chars().next().filter(|&c| c == '"' || c == '\'');
+    let quote = quote.ok_or_else(|| format!("expected a string: {}", s))?;
+    let mut string = String::new();
+    let mut chars = s[1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if c == quote => return Ok((string, &s[1 + i + 1..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c @ ('"' | '\\')) => string.push(c),
+                Some(c) => return Err(format!("unknown escape: \\{}", c)),
+                None => break,
+            },
+            _ => string.push(c),
+        }
+    }
+    Err("unterminated string".into())
+}
+
+/// A key, bare or quoted, at the start of `s`, and what's after it
+fn parse_key(s: &str) -> Result<(String, &str), String> {
+    if s.starts_with('"') || s.starts_with('\'') {
+        return parse_string(s);
+    }
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(s.len());
+    if end == 0 {
+        return Err(format!("expected a key: {}", s));
+    }
+    Ok((s[..end].to_owned(), &s[end..]))
+}
+
+/// Nothing but a comment is left in `rest`
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected {}", rest))
+    }
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if s.starts_with('"') || s.starts_with('\'') {
+        let (string, rest) = parse_string(s)?;
+        expect_end(rest)?;
+        return Ok(Value::String(string));
+    }
+    let value = s.split('#').next().unwrap_or("").trim();
+    match value {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => value
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| format!("expected a string, an integer or a boolean: {}", value)),
+    }
+}
+
+/// The tables of `text`, in order, the first one for the keys before
+/// any header
+pub fn parse(text: &str) -> Result<Vec<Table>, ParseConfigError> {
+    let mut tables = vec![Table::default()];
+    for (i, line) in text.lines().enumerate() {
+        let error = |msg: String| ParseConfigError { line: i + 1, msg };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(mut rest) = line.strip_prefix('[') {
+            let mut path = vec![];
+            loop {
+                let (name, after) = parse_key(rest.trim_start()).map_err(error)?;
+                path.push(name);
+                let after = after.trim_start();
+                if let Some(after) = after.strip_prefix('.') {
+                    rest = after;
+                    continue;
+                }
+                let after = after
+                    .strip_prefix(']')
+                    .ok_or_else(|| error(format!("expected ] or .: {}", after)))?;
+                expect_end(after).map_err(error)?;
+                break;
+            }
+            if tables.iter().any(|table| table.path == path) {
+                return Err(error(format!("table {} defined twice", path.join("."))));
+            }
+            tables.push(Table {
+                path,
+                line: i + 1,
+                entries: vec![],
+            });
+            continue;
+        }
+        let (key, rest) = parse_key(line).map_err(error)?;
+        let value = rest
+            .trim_start()
+            .strip_prefix('=')
+            .ok_or_else(|| error(format!("expected = after {}", key)))?;
+        let value = parse_value(value.trim()).map_err(error)?;
+        let table = tables.last_mut().expect("the first table");
+        if table.entries.iter().any(|entry| entry.key == key) {
+            return Err(error(format!("{} defined twice", key)));
+        }
+        table.entries.push(Entry {
+            key,
+            value,
+            line: i + 1,
+        });
+    }
+    Ok(tables)
+}
+
+/// The value of `key` in the table `table`, eg. `keys.normal`, of
+/// `tables`
+pub fn lookup<'t>(tables: &'t [Table], table: &str, key: &str) -> Option<&'t Value> {
+    tables
+        .iter()
+        .find(|t| t.path.join(".") == table)?
+        .entries
+        .iter()
+        .find(|entry| entry.key == key)
+        .map(|entry| &entry.value)
+}
+
+#[test]
+fn parse_test() {
+    let tables = parse(
+        "# settings\nname = \"a \\\"b\\\"\"\n\n[keys]\ntimeout = 1_000 # ms\n\
+         [keys.normal]\n\"space,f\" = 'Run \"x\"'\na-j = true\n",
+    )
+    .expect("valid");
+    let keys: Vec<_> = tables
+        .iter()
+        .map(|table| {
+            let entries: Vec<_> = table
+                .entries
+                .iter()
+                .map(|entry| format!("{}={}", entry.key, entry.value))
+                .collect();
+            (table.path.join("."), entries.join(" "))
+        })
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            ("".to_owned(), "name=\"a \\\"b\\\"\"".to_owned()),
+            ("keys".to_owned(), "timeout=1000".to_owned()),
+            (
+                "keys.normal".to_owned(),
+                "space,f=\"Run \\\"x\\\"\" a-j=true".to_owned()
+            ),
+        ]
+    );
+    assert_eq!(tables[2].entries[0].line, 7);
+    assert_eq!(
+        lookup(&tables, "keys", "timeout"),
+        Some(&Value::Integer(1000))
+    );
+    assert_eq!(lookup(&tables, "keys", "leader"), None);
+
+    let error = |text| parse(text).map_err(|e| e.to_string());
+    assert_eq!(
+        error("a = 'b"),
+        Err("line 1: unterminated string".to_owned())
+    );
+    assert_eq!(
+        error("[a]\n[a]"),
+        Err("line 2: table a defined twice".to_owned())
+    );
+    assert_eq!(
+        error("a = b"),
+        Err("line 1: expected a string, an integer or a boolean: b".to_owned())
+    );
+    assert_eq!(
+        error("[a b]"),
+        Err("line 1: expected ] or .: b]".to_owned())
+    );
+}
@@ -0,0 +1,389 @@
+//! Merge conflicts
+//!
+//! Conflicts are the regions between the markers that git leaves in the
+//! files it couldn't merge:
+//!
+//! ```text
+//! <<<<<<< ours
+//! our version
+//! ||||||| base (only with `merge.conflictStyle = diff3`)
+//! common ancestor
+//! =======
+//! their version
+//! >>>>>>> theirs
+//! ```
+use ropey::Rope;
+use std::ops::Range;
+
+use crate::buffer::{Buffer, TextChange};
+
+/// A conflict, as line numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Line of the `<<<<<<<` marker
+    pub start: usize,
+    pub ours: Range<usize>,
+    pub base: Option<Range<usize>>,
+    pub theirs: Range<usize>,
+    /// Line of the `>>>>>>>` marker
+    pub end: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineKind {
+    Marker,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// Which version to keep when resolving a conflict
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pick {
+    Ours,
+    Theirs,
+    /// Ours followed by theirs
+    Both,
+    Base,
+}
+
+impl Conflict {
+    pub fn line_kind(&self, line: usize) -> Option<LineKind> {
+        if line < self.start || self.end < line {
+            None
+        } else if self.ours.contains(&line) {
+            Some(LineKind::Ours)
+        } else if self.theirs.contains(&line) {
+            Some(LineKind::Theirs)
+        } else if self.base.as_ref().is_some_and(|base| base.contains(&line)) {
+            Some(LineKind::Base)
+        } else {
+            Some(LineKind::Marker)
+        }
+    }
+
+    /// Text of the `lines`, including their newlines
+    pub fn section(&self, text: &Rope, lines: Range<usize>) -> String {
+        lines.map(|line| text.line(line).to_string()).collect()
+    }
+
+    /// Text to replace the whole conflict with
+    pub fn resolution(&self, text: &Rope, pick: Pick) -> String {
+        match pick {
+            Pick::Ours => self.section(text, self.ours.clone()),
+            Pick::Theirs => self.section(text, self.theirs.clone()),
+            Pick::Both => {
+                self.section(text, self.ours.clone()) + &self.section(text, self.theirs.clone())
+            }
+            Pick::Base => self
+                .base
+                .clone()
+                .map(|base| self.section(text, base))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Char range of the whole conflict, markers included
+    pub fn char_range(&self, text: &Rope) -> Range<usize> {
+        let end = if self.end + 1 < text.len_lines() {
+            text.line_to_char(self.end + 1)
+        } else {
+            text.len_chars()
+        };
+        text.line_to_char(self.start)..end
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum MarkerKind {
+    Start,
+    Base,
+    Separator,
+    End,
+}
+
+/// Markers are ASCII, so there's no need to look further into a line
+const MARKER_PREFIX_LEN: usize = 8;
+
+/// Kind of marker a line starting with `prefix` is
+fn marker_kind(prefix: &[u8]) -> Option<MarkerKind> {
+    let (marker, kind) = match prefix.first()? {
+        b'<' => (b"<<<<<<<", MarkerKind::Start),
+        b'|' => (b"|||||||", MarkerKind::Base),
+        b'=' => (b"=======", MarkerKind::Separator),
+        b'>' => (b">>>>>>>", MarkerKind::End),
+        _ => return None,
+    };
+    let mut rest = prefix.strip_prefix(marker)?;
+    while let Some(trimmed) = rest.strip_suffix(b"\r") {
+        rest = trimmed;
+    }
+    match (kind, rest) {
+        (_, []) => Some(kind),
+        (MarkerKind::Separator, _) => None,
+        (_, [b' ', ..]) => Some(kind),
+        _ => None,
+    }
+}
+
+/// Markers of the lines in `chars`, which starts at a line start, as the
+/// char index of their line and their kind
+///
+/// Looks at the bytes of the text directly, as going through its lines
+/// takes seconds in files of millions of lines.
+fn scan_markers(text: &Rope, chars: Range<usize>) -> Vec<(usize, MarkerKind)> {
+    let mut markers = vec![];
+    let mut check = |line_start: usize, prefix: &[u8]| {
+        if let Some(kind) = marker_kind(prefix) {
+            markers.push((text.byte_to_char(line_start), kind));
+        }
+    };
+    let mut line_start = text.char_to_byte(chars.start);
+    let mut at = line_start;
+    let mut prefix = Vec::with_capacity(MARKER_PREFIX_LEN);
+    for chunk in text.slice(chars).chunks() {
+        for &byte in chunk.as_bytes() {
+            at += 1;
+            if byte == b'\n' {
+                check(line_start, &prefix);
+                prefix.clear();
+                line_start = at;
+            } else if prefix.len() < MARKER_PREFIX_LEN {
+                prefix.push(byte);
+            }
+        }
+    }
+    if line_start < at {
+        check(line_start, &prefix);
+    }
+    markers
+}
+
+/// The complete conflicts of `markers`, in order
+fn conflicts_of(text: &Rope, markers: &[(usize, MarkerKind)]) -> Vec<Conflict> {
+    let mut conflicts = vec![];
+    // line of `<<<<<<<`, `|||||||` and `=======` of the current conflict
+    let mut start = None;
+    let mut base = None;
+    let mut separator = None;
+    for &(idx, kind) in markers {
+        let i = text.char_to_line(idx);
+        match kind {
+            MarkerKind::Start => {
+                start = Some(i);
+                base = None;
+                separator = None;
+            }
+            MarkerKind::Base if start.is_some() && separator.is_none() => base = Some(i),
+            MarkerKind::Separator if start.is_some() => separator = Some(i),
+            MarkerKind::End => {
+                if let (Some(start), Some(separator)) = (start, separator) {
+                    conflicts.push(Conflict {
+                        start,
+                        ours: start + 1..base.unwrap_or(separator),
+                        base: base.map(|base| base + 1..separator),
+                        theirs: separator + 1..i,
+                        end: i,
+                    });
+                }
+                start = None;
+                base = None;
+                separator = None;
+            }
+            _ => {}
+        }
+    }
+    conflicts
+}
+
+/// All the complete conflicts in `text`
+pub fn find_conflicts(text: &Rope) -> Vec<Conflict> {
+    conflicts_of(text, &scan_markers(text, 0..text.len_chars()))
+}
+
+/// The conflicts of a buffer, looked for again only after it changes
+///
+/// Only the lines around the changes are scanned again for markers.
+#[derive(Debug, Clone, Default)]
+pub struct ConflictSet {
+    version: Option<u64>,
+    /// Char index of the line of every marker, in order
+    markers: Vec<(usize, MarkerKind)>,
+    conflicts: Vec<Conflict>,
+}
+
+impl ConflictSet {
+    pub fn refresh(&mut self, buffer: &Buffer) {
+        if self.version == Some(buffer.version()) {
+            return;
+        }
+        let text = &buffer.text;
+        match self
+            .version
+            .and_then(|version| buffer.changes_since(version))
+        {
+            Some(changes) => {
+                let mut dirty = vec![];
+                for change in changes {
+                    self.follow(change, &mut dirty);
+                }
+                for range in dirty {
+                    self.rescan(text, range);
+                }
+                self.markers.sort_unstable();
+                self.markers.dedup();
+            }
+            None => self.markers = scan_markers(text, 0..text.len_chars()),
+        }
+        self.conflicts = conflicts_of(text, &self.markers);
+        self.version = Some(buffer.version());
+    }
+
+    /// Move the markers after `change`, forget the ones it touched, and
+    /// add the chars to scan again to `dirty`
+    fn follow(&mut self, change: &TextChange, dirty: &mut Vec<Range<usize>>) {
+        let start = change.start.0;
+        let removed_end = start + change.removed;
+        let inserted_end = start + change.inserted;
+        let shift = |idx: usize| idx - change.removed + change.inserted;
+        self.markers.retain_mut(|(idx, _)| {
+            if *idx + MARKER_PREFIX_LEN <= start {
+                true
+            } else if removed_end < *idx {
+                // the newline before the marker is left alone too
+                *idx = shift(*idx);
+                true
+            } else {
+                false
+            }
+        });
+        for range in dirty.iter_mut() {
+            if removed_end < range.start {
+                *range = shift(range.start)..shift(range.end);
+            } else if start <= range.end {
+                let end = if removed_end <= range.end {
+                    shift(range.end)
+                } else {
+                    inserted_end
+                };
+                *range = range.start.min(start)..end.max(inserted_end);
+            }
+        }
+        // the lines of the markers forgotten above start at most that far
+        dirty.push(start.saturating_sub(MARKER_PREFIX_LEN)..inserted_end);
+    }
+
+    /// Look for markers again in the lines of the chars in `range`
+    fn rescan(&mut self, text: &Rope, range: Range<usize>) {
+        let first = text.char_to_line(range.start.min(text.len_chars()));
+        let last = text.char_to_line(range.end.min(text.len_chars()));
+        let chars = text.line_to_char(first)..text.line_to_char(last) + text.line(last).len_chars();
+        self.markers
+            .retain(|&(idx, _)| idx < chars.start || chars.end <= idx);
+        self.markers.extend(scan_markers(text, chars));
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Index of the conflict containing `line`
+    pub fn index_at_line(&self, line: usize) -> Option<usize> {
+        self.conflicts
+            .iter()
+            .position(|c| c.start <= line && line <= c.end)
+    }
+
+    pub fn at_line(&self, line: usize) -> Option<&Conflict> {
+        self.index_at_line(line).map(|i| &self.conflicts[i])
+    }
+
+    /// First conflict starting after `line`, or the last one before it, wrapping around
+    pub fn next_from(&self, line: usize, backward: bool) -> Option<&Conflict> {
+        if backward {
+            self.conflicts
+                .iter()
+                .rev()
+                .find(|c| c.start < line)
+                .or_else(|| self.conflicts.last())
+        } else {
+            self.conflicts
+                .iter()
+                .find(|c| line < c.start)
+                .or_else(|| self.conflicts.first())
+        }
+    }
+}
+
+#[test]
+fn find_and_resolve_test() {
+    let text = Rope::from_str(concat!(
+        "a\n",
+        "<<<<<<< HEAD\n",
+        "ours\n",
+        "=======\n",
+        "theirs\n",
+        ">>>>>>> branch\n",
+        "b\n",
+        "<<<<<<< HEAD\n",
+        "ours 2\n",
+        "||||||| base\n",
+        "base 2\n",
+        "=======\n",
+        ">>>>>>> branch\n",
+    ));
+    let conflicts = find_conflicts(&text);
+    assert_eq!(conflicts.len(), 2);
+    assert_eq!(conflicts[0].line_kind(0), None);
+    assert_eq!(conflicts[0].line_kind(2), Some(LineKind::Ours));
+    assert_eq!(conflicts[0].line_kind(3), Some(LineKind::Marker));
+    assert_eq!(conflicts[1].line_kind(10), Some(LineKind::Base));
+
+    assert_eq!(conflicts[0].resolution(&text, Pick::Both), "ours\ntheirs\n");
+    assert_eq!(conflicts[1].resolution(&text, Pick::Theirs), "");
+    assert_eq!(conflicts[1].resolution(&text, Pick::Base), "base 2\n");
+    assert_eq!(
+        text.slice(conflicts[1].char_range(&text)).to_string(),
+        text.slice(text.line_to_char(7)..).to_string()
+    );
+}
+
+#[test]
+fn refresh_follows_edits_test() {
+    use crate::Idx;
+
+    let mut buffer = Buffer::from_text(Rope::from_str(concat!(
+        "a\n",
+        "<<<<<<< HEAD\n",
+        "ours\n",
+        "=======\n",
+        "theirs\n",
+        ">>>>>>> branch\n",
+    )));
+    let mut set = ConflictSet::default();
+    set.refresh(&buffer);
+    assert_eq!(set.conflicts().len(), 1);
+
+    let mut check = |buffer: &Buffer| {
+        set.refresh(buffer);
+        assert_eq!(set.conflicts(), find_conflicts(&buffer.text).as_slice());
+        set.conflicts().len()
+    };
+    buffer.insert(Idx(0), "b\nc\n");
+    check(&buffer);
+    // break the separator, then fix it
+    buffer.insert(Idx(26), "x");
+    assert_eq!(check(&buffer), 0);
+    buffer.remove(26..27);
+    check(&buffer);
+    // a second conflict, typed in pieces
+    let end = buffer.text.len_chars();
+    buffer.insert(Idx(end), "<<<<<<<\n=");
+    buffer.insert(Idx(end + 9), "======\n>>>>>>");
+    check(&buffer);
+    let end = buffer.text.len_chars();
+    buffer.insert(Idx(end), ">\n");
+    assert_eq!(check(&buffer), 2);
+    buffer.remove(0..end);
+    assert_eq!(check(&buffer), 0);
+}
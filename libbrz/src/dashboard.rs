@@ -0,0 +1,159 @@
+//! Dashboard, the buffer shown when the editor starts with no file to open
+//!
+//! It lists the files and the projects used recently, offers to open the
+//! files of the last session in the project again, and hints at a few
+//! keys. Enter on a line opens what it's about.
+use std::path::{Path, PathBuf};
+
+/// What a line of the dashboard opens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    File(PathBuf),
+    Project(PathBuf),
+    RestoreSession(Vec<PathBuf>),
+}
+
+/// What goes on the dashboard
+#[derive(Debug, Clone, Default)]
+pub struct Contents {
+    /// Best first, like the rest
+    pub files: Vec<PathBuf>,
+    pub projects: Vec<PathBuf>,
+    /// Files open at the end of the last session in the project
+    pub session: Vec<PathBuf>,
+    /// Keys, and what they do
+    pub hints: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dashboard {
+    /// Lines with an entry, and the entry
+    entries: Vec<(usize, Entry)>,
+}
+
+impl Dashboard {
+    /// The dashboard of `contents` for the project in `project`, and its
+    /// text
+    pub fn new(project: &Path, contents: &Contents) -> (Self, String) {
+        let mut dashboard = Self::default();
+        let mut lines: Vec<String> = vec![
+            format!("Breeze v{}", env!("CARGO_PKG_VERSION")),
+            format!("in {}", project.display()),
+        ];
+        let mut section = |lines: &mut Vec<String>, title: &str, entries: Vec<(String, Entry)>| {
+            if entries.is_empty() {
+                return;
+            }
+            lines.push(String::new());
+            lines.push(title.to_owned());
+            for (text, entry) in entries {
+                dashboard.entries.push((lines.len(), entry));
+                lines.push(format!("  {}", text));
+            }
+        };
+        if !contents.session.is_empty() {
+            let text = match contents.session.len() {
+                1 => "restore the last session, 1 file".to_owned(),
+                n => format!("restore the last session, {} files", n),
+            };
+            section(
+                &mut lines,
+                "Session",
+                vec![(text, Entry::RestoreSession(contents.session.clone()))],
+            );
+        }
+        section(
+            &mut lines,
+            "Recent files",
+            contents
+                .files
+                .iter()
+                .map(|path| {
+                    let shown = path.strip_prefix(project).unwrap_or(path);
+                    (shown.display().to_string(), Entry::File(path.clone()))
+                })
+                .collect(),
+        );
+        section(
+            &mut lines,
+            "Recent projects",
+            contents
+                .projects
+                .iter()
+                .filter(|dir| dir.as_path() != project)
+                .map(|dir| (dir.display().to_string(), Entry::Project(dir.clone())))
+                .collect(),
+        );
+        if !contents.hints.is_empty() {
+            lines.push(String::new());
+            lines.push("Keys".to_owned());
+            let width = contents
+                .hints
+                .iter()
+                .map(|(keys, _)| keys.chars().count())
+                .max();
+            for (keys, help) in &contents.hints {
+                lines.push(format!(
+                    "  {:width$}  {}",
+                    keys,
+                    help,
+                    width = width.unwrap_or(0)
+                ));
+            }
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
+        (dashboard, text)
+    }
+
+    pub fn entry_at(&self, line: usize) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|(entry_line, _)| *entry_line == line)
+            .map(|(_, entry)| entry)
+    }
+}
+
+#[test]
+fn dashboard_test() {
+    let contents = Contents {
+        files: vec![PathBuf::from("/p/src/a.rs"), PathBuf::from("/etc/hosts")],
+        projects: vec![PathBuf::from("/p"), PathBuf::from("/q")],
+        session: vec![PathBuf::from("/p/src/a.rs")],
+        hints: vec![
+            ("c-p".to_owned(), "open a file".to_owned()),
+            (":".to_owned(), "command".to_owned()),
+        ],
+    };
+    let (dashboard, text) = Dashboard::new(Path::new("/p"), &contents);
+    let lines: Vec<&str> = text.lines().skip(2).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "",
+            "Session",
+            "  restore the last session, 1 file",
+            "",
+            "Recent files",
+            "  src/a.rs",
+            "  /etc/hosts",
+            "",
+            "Recent projects",
+            "  /q",
+            "",
+            "Keys",
+            "  c-p  open a file",
+            "  :    command",
+        ]
+    );
+    assert_eq!(
+        dashboard.entry_at(4),
+        Some(&Entry::RestoreSession(contents.session.clone()))
+    );
+    assert_eq!(
+        dashboard.entry_at(8),
+        Some(&Entry::File("/etc/hosts".into()))
+    );
+    assert_eq!(dashboard.entry_at(11), Some(&Entry::Project("/q".into())));
+    assert_eq!(dashboard.entry_at(9), None);
+}
@@ -0,0 +1,558 @@
+//! Line-based diffs, and diffs of the words of a line
+//!
+//! Lines are diffed around anchors, which the diff goes on between. The
+//! lines found once on each side anchor it first, those of them that are
+//! in the same order on both, as in the patience diff; without any, the
+//! line found the fewest times does, as in the histogram diff of git.
+//! Anchoring on rare lines, rather than on the longest common
+//! subsequence, keeps blank lines and lone braces from pairing unrelated
+//! blocks, and most of a big text never gets compared against most of
+//! the rest. Where all the common lines are too frequent to anchor on,
+//! Myers' algorithm finds the fewest lines to add and remove instead.
+use ropey::Rope;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::Hash;
+use std::ops::Range;
+
+/// Above this many lines added and removed in a region without an
+/// anchor, the region is one big change: finding them takes memory in
+/// their square
+const MAX_EDITS: usize = 2000;
+
+/// Lines occurring more often than this in a region don't anchor it
+const MAX_OCCURRENCES: usize = 64;
+
+/// A region where the old and new lines differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// The changed regions between the `old` and `new` lines, in order
+pub fn diff_lines<S: AsRef<str>>(old: &[S], new: &[S]) -> Vec<Hunk> {
+    let old: Vec<&str> = old.iter().map(AsRef::as_ref).collect();
+    let new: Vec<&str> = new.iter().map(AsRef::as_ref).collect();
+    diff(&old, &new)
+}
+
+/// The changed regions between the lines of `old` and `new`
+pub fn diff_ropes(old: &Rope, new: &Rope) -> Vec<Hunk> {
+    diff_lines(&rope_lines(old), &rope_lines(new))
+}
+
+/// The changed chars between two versions of a line, word by word
+pub fn diff_inline(old: &str, new: &str) -> Vec<Hunk> {
+    let (old_words, old_starts) = words(old);
+    let (new_words, new_starts) = words(new);
+    diff(&old_words, &new_words)
+        .into_iter()
+        .map(|hunk| Hunk {
+            old: old_starts[hunk.old.start]..old_starts[hunk.old.end],
+            new: new_starts[hunk.new.start]..new_starts[hunk.new.end],
+        })
+        .collect()
+}
+
+/// The edits turning `old` into `new`, as char ranges of `old` and what
+/// replaces them, in order
+///
+/// Lines are compared with their line endings. Lines changed one for one
+/// are edited word by word, so that positions in what they kept stay
+/// where they were.
+pub fn edits(old: &Rope, new: &Rope) -> Vec<(Range<usize>, String)> {
+    let lines =
+        |text: &Rope| -> Vec<String> { text.lines().map(|line| line.to_string()).collect() };
+    let (old_lines, new_lines) = (lines(old), lines(new));
+    let line_start = |text: &Rope, line: usize| {
+        if line < text.len_lines() {
+            text.line_to_char(line)
+        } else {
+            text.len_chars()
+        }
+    };
+    let mut edits = vec![];
+    for hunk in diff_lines(&old_lines, &new_lines) {
+        if hunk.old.len() != hunk.new.len() {
+            let range = line_start(old, hunk.old.start)..line_start(old, hunk.old.end);
+            edits.push((range, new_lines[hunk.new].concat()));
+            continue;
+        }
+        for (old_line, new_line) in hunk.old.zip(hunk.new) {
+            let start = line_start(old, old_line);
+            let new_chars: Vec<char> = new_lines[new_line].chars().collect();
+            for inline in diff_inline(&old_lines[old_line], &new_lines[new_line]) {
+                let range = start + inline.old.start..start + inline.old.end;
+                edits.push((range, new_chars[inline.new].iter().collect()));
+            }
+        }
+    }
+    edits
+}
+
+/// Words, runs of spaces and other chars of `line`, and the chars they
+/// start at, with the length of `line` last
+fn words(line: &str) -> (Vec<&str>, Vec<usize>) {
+    let class = |ch: char| {
+        if ch.is_alphanumeric() || ch == '_' {
+            0
+        } else if ch.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut words = vec![];
+    let mut starts = vec![];
+    let mut start: Option<(usize, usize, u8)> = None;
+    let mut chars = 0;
+    for (byte, ch) in line.char_indices() {
+        match start {
+            Some((_, _, c)) if c == class(ch) && c != 2 => {}
+            _ => {
+                if let Some((start_byte, start_char, _)) = start {
+                    words.push(&line[start_byte..byte]);
+                    starts.push(start_char);
+                }
+                start = Some((byte, chars, class(ch)));
+            }
+        }
+        chars += 1;
+    }
+    if let Some((start_byte, start_char, _)) = start {
+        words.push(&line[start_byte..]);
+        starts.push(start_char);
+    }
+    starts.push(chars);
+    (words, starts)
+}
+
+/// The changed regions between `old` and `new`
+fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = vec![];
+    // regions left, the first one last
+    let mut regions = vec![(0..old.len(), 0..new.len())];
+    while let Some((old_range, new_range)) = regions.pop() {
+        let (o, n) = (old_range.start, new_range.start);
+        match anchor(&old[old_range.clone()], &new[new_range.clone()]) {
+            Anchor::Same => {}
+            Anchor::At(common) => {
+                // the regions between the common runs, last pushed first
+                let (mut end_o, mut end_n) = (old_range.end, new_range.end);
+                for (common_o, common_n) in common.into_iter().rev() {
+                    regions.push((o + common_o.end..end_o, n + common_n.end..end_n));
+                    end_o = o + common_o.start;
+                    end_n = n + common_n.start;
+                }
+                regions.push((o..end_o, n..end_n));
+            }
+            Anchor::Changed => hunks.push(Hunk {
+                old: old_range,
+                new: new_range,
+            }),
+            Anchor::Frequent => {
+                let found = myers_hunks(&old[old_range], &new[new_range]);
+                hunks.extend(found.into_iter().map(|hunk| Hunk {
+                    old: o + hunk.old.start..o + hunk.old.end,
+                    new: n + hunk.new.start..n + hunk.new.end,
+                }));
+            }
+        }
+    }
+    hunks
+}
+
+/// How to go on with a region
+enum Anchor {
+    /// Both sides are the same
+    Same,
+    /// Runs of common lines to split the region around, in order
+    At(Vec<(Range<usize>, Range<usize>)>),
+    /// A change with nothing in common
+    Changed,
+    /// A change where all the common lines are too frequent to anchor on
+    Frequent,
+}
+
+fn anchor<T: Eq + Hash>(old: &[T], new: &[T]) -> Anchor {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == old.len() && prefix == new.len() {
+        return Anchor::Same;
+    }
+    if prefix > 0 || suffix > 0 {
+        let mut common = vec![];
+        if prefix > 0 {
+            common.push((0..prefix, 0..prefix));
+        }
+        if suffix > 0 {
+            common.push((old.len() - suffix..old.len(), new.len() - suffix..new.len()));
+        }
+        return Anchor::At(common);
+    }
+    if old.is_empty() || new.is_empty() {
+        return Anchor::Changed;
+    }
+
+    let mut occurrences: HashMap<&T, (Vec<usize>, usize)> = HashMap::new();
+    for (i, line) in old.iter().enumerate() {
+        occurrences.entry(line).or_default().0.push(i);
+    }
+    for line in new {
+        if let Some((_, count)) = occurrences.get_mut(line) {
+            *count += 1;
+        }
+    }
+
+    // the lines found once on each side, where they are in the same order
+    let unique: Vec<(usize, usize)> = new
+        .iter()
+        .enumerate()
+        .filter_map(|(j, line)| match occurrences.get(line) {
+            Some((positions, 1)) if positions.len() == 1 => Some((positions[0], j)),
+            _ => None,
+        })
+        .collect();
+    if !unique.is_empty() {
+        return Anchor::At(
+            in_order(&unique)
+                .into_iter()
+                .map(|(i, j)| (i..i + 1, j..j + 1))
+                .collect(),
+        );
+    }
+
+    // or the longest run from the line found the fewest times
+    let mut common = false;
+    // occurrences of the line, and the run
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut j = 0;
+    while j < new.len() {
+        let mut next = j + 1;
+        if let Some((positions, new_count)) = occurrences.get(&new[j]) {
+            common = true;
+            let count = positions.len() + new_count;
+            if count <= MAX_OCCURRENCES && best.is_none_or(|(best, ..)| count <= best) {
+                for &i in positions {
+                    let before = old[..i]
+                        .iter()
+                        .rev()
+                        .zip(new[..j].iter().rev())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    let after = old[i..]
+                        .iter()
+                        .zip(&new[j..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    let len = before + after;
+                    if best.is_none_or(|(best, .., best_len)| count < best || best_len < len) {
+                        best = Some((count, i - before, j - before, len));
+                    }
+                    next = next.max(j + after);
+                }
+            }
+        }
+        j = next;
+    }
+    match best {
+        Some((_, i, j, len)) => Anchor::At(vec![(i..i + len, j..j + len)]),
+        None if common => Anchor::Frequent,
+        None => Anchor::Changed,
+    }
+}
+
+/// The longest run of `pairs`, sorted by their second value, that are
+/// sorted by their first one too
+fn in_order(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // last pair of the best run of each length so far, and the pair
+    // before each in its run
+    let mut ends: Vec<usize> = vec![];
+    let mut before: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (k, &(i, _)) in pairs.iter().enumerate() {
+        let len = ends.partition_point(|&end| pairs[end].0 < i);
+        before[k] = len.checked_sub(1).map(|len| ends[len]);
+        if len == ends.len() {
+            ends.push(k);
+        } else {
+            ends[len] = k;
+        }
+    }
+    let mut run = vec![];
+    let mut cur = ends.last().copied();
+    while let Some(k) = cur {
+        run.push(pairs[k]);
+        cur = before[k];
+    }
+    run.reverse();
+    run
+}
+
+/// The changed regions between `old` and `new`, with the fewest lines
+/// added and removed as found by Myers' algorithm, or one big region if
+/// that's more than `MAX_EDITS` lines
+fn myers_hunks<T: Eq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let (n, m) = (old.len(), new.len());
+    let limit = (n + m).min(MAX_EDITS);
+    // furthest x on each diagonal k = x - y, at k + limit, and its values
+    // before each number of edits d, for k in -d..=d
+    let mut v = vec![0usize; 2 * limit + 2];
+    let mut trace: Vec<Vec<usize>> = vec![];
+    let mut found = false;
+    for d in 0..=limit {
+        trace.push(v[limit - d..=limit + d].to_vec());
+        for k in (limit - d..=limit + d).step_by(2) {
+            let mut x = if k == limit - d || (k != limit + d && v[k - 1] < v[k + 1]) {
+                v[k + 1]
+            } else {
+                v[k - 1] + 1
+            };
+            let mut y = (x + limit).wrapping_sub(k);
+            while x < n && y < m && old[x] == new[y] {
+                x += 1;
+                y += 1;
+            }
+            v[k] = x;
+            if n <= x && m <= y {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            break;
+        }
+    }
+    if !found {
+        return vec![Hunk {
+            old: 0..n,
+            new: 0..m,
+        }];
+    }
+
+    // back from the end, through the runs of common lines between edits,
+    // as (start, end) on both sides
+    let mut runs: Vec<((usize, usize), (usize, usize))> = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, prev) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| prev[(k + d) as usize];
+        let k = x as isize - y as isize;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = (prev_x as isize - prev_k) as usize;
+        // the line added or removed, then the common ones up to (x, y)
+        let start = if prev_k == k + 1 {
+            (prev_x, prev_y + 1)
+        } else {
+            (prev_x + 1, prev_y)
+        };
+        runs.push((start, (x, y)));
+        x = prev_x;
+        y = prev_y;
+    }
+    runs.push(((0, 0), (x, y)));
+    runs.reverse();
+
+    let mut hunks: Vec<Hunk> = vec![];
+    for pair in runs.windows(2) {
+        let ((_, (end_x, end_y)), ((start_x, start_y), _)) = (pair[0], pair[1]);
+        let hunk = Hunk {
+            old: end_x..start_x,
+            new: end_y..start_y,
+        };
+        if hunk.old.is_empty() && hunk.new.is_empty() {
+            continue;
+        }
+        match hunks.last_mut() {
+            Some(last) if last.old.end == hunk.old.start && last.new.end == hunk.new.start => {
+                last.old.end = hunk.old.end;
+                last.new.end = hunk.new.end;
+            }
+            _ => hunks.push(hunk),
+        }
+    }
+    hunks
+}
+
+/// Lines of `text` without the newlines, to diff
+pub fn rope_lines(text: &Rope) -> Vec<String> {
+    let mut lines: Vec<String> = text
+        .lines()
+        .map(|line| line.to_string().trim_end_matches('\n').to_owned())
+        .collect();
+    if text.len_chars() == 0 || text.char(text.len_chars() - 1) == '\n' {
+        lines.pop();
+    }
+    lines
+}
+
+/// Lines of the new text corresponding to the `old` lines
+///
+/// Lines inside a changed region map to the whole region.
+pub fn map_range(hunks: &[Hunk], old: Range<usize>) -> Range<usize> {
+    let map = |line: usize, end: bool| {
+        let mut shift = 0isize;
+        for hunk in hunks {
+            if line < hunk.old.start || (end && line == hunk.old.start) {
+                break;
+            }
+            if line < hunk.old.end || (end && line == hunk.old.end) {
+                return if end { hunk.new.end } else { hunk.new.start };
+            }
+            shift += hunk.new.len() as isize - hunk.old.len() as isize;
+        }
+        (line as isize + shift) as usize
+    };
+    let start = map(old.start, false);
+    start..map(old.end, true).max(start)
+}
+
+/// Unified diff of the hunks with `context` lines around them
+pub fn unified<S: AsRef<str>>(old: &[S], new: &[S], hunks: &[Hunk], context: usize) -> String {
+    let mut out = String::new();
+    for hunk in hunks {
+        let before = hunk.old.start.saturating_sub(context)..hunk.old.start;
+        let after = hunk.old.end..(hunk.old.end + context).min(old.len());
+        let context_len = before.len() + after.len();
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            before.start + 1,
+            hunk.old.len() + context_len,
+            hunk.new.start - before.len() + 1,
+            hunk.new.len() + context_len
+        );
+        for line in &old[before] {
+            let _ = writeln!(out, " {}", line.as_ref());
+        }
+        for line in &old[hunk.old.clone()] {
+            let _ = writeln!(out, "-{}", line.as_ref());
+        }
+        for line in &new[hunk.new.clone()] {
+            let _ = writeln!(out, "+{}", line.as_ref());
+        }
+        for line in &old[after] {
+            let _ = writeln!(out, " {}", line.as_ref());
+        }
+    }
+    out
+}
+
+#[test]
+fn diff_lines_test() {
+    let old = ["a", "b", "c", "d", "e"];
+    let new = ["a", "x", "c", "e", "f"];
+    let hunks = diff_lines(&old, &new);
+    assert_eq!(
+        hunks,
+        vec![
+            Hunk {
+                old: 1..2,
+                new: 1..2
+            },
+            Hunk {
+                old: 3..4,
+                new: 3..3
+            },
+            Hunk {
+                old: 5..5,
+                new: 4..5
+            },
+        ]
+    );
+    assert_eq!(map_range(&hunks, 0..1), 0..1);
+    assert_eq!(map_range(&hunks, 1..3), 1..3);
+    assert_eq!(map_range(&hunks, 4..5), 3..4);
+    assert_eq!(map_range(&hunks, 3..4), 3..3);
+    assert!(unified(&old, &new, &hunks, 1).starts_with("@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"));
+    assert!(diff_lines(&old, &old).is_empty());
+}
+
+#[test]
+fn histogram_test() {
+    // the lines found once anchor the diff, not the braces all over
+    let old = ["fn a() {", "}", "fn b() {", "    two();", "}", "}"];
+    let new = ["fn b() {", "    two();", "}", "}", "fn a() {", "}"];
+    assert_eq!(
+        diff_lines(&old, &new),
+        vec![
+            Hunk {
+                old: 0..2,
+                new: 0..0
+            },
+            Hunk {
+                old: 5..5,
+                new: 3..5
+            },
+        ]
+    );
+
+    // big texts are diffed as finely as small ones
+    let old: Vec<String> = (0..100_000).map(|i| format!("line {}", i % 1000)).collect();
+    let mut new = old.clone();
+    new[10] = "changed".to_owned();
+    new.remove(50_000);
+    new.push("added".to_owned());
+    assert_eq!(
+        diff_lines(&old, &new),
+        vec![
+            Hunk {
+                old: 10..11,
+                new: 10..11
+            },
+            Hunk {
+                old: 50_000..50_001,
+                new: 50_000..50_000
+            },
+            Hunk {
+                old: 100_000..100_000,
+                new: 99_999..100_000
+            },
+        ]
+    );
+
+    assert_eq!(
+        diff_inline("let count = old + 1;", "let total = old + 10;"),
+        vec![
+            Hunk {
+                old: 4..9,
+                new: 4..9
+            },
+            Hunk {
+                old: 18..19,
+                new: 18..20
+            },
+        ]
+    );
+    assert!(diff_inline("same", "same").is_empty());
+}
+
+#[test]
+fn edits_test() {
+    let old = Rope::from_str("fn main() {\n    let x = 1;\n}\nend");
+    let new = Rope::from_str("fn main() {\n    let y = 1;\n}\nfn more() {}\nend\n");
+    let found = edits(&old, &new);
+    let mut text = old.clone();
+    for (range, inserted) in found.iter().rev() {
+        text.remove(range.clone());
+        text.insert(range.start, inserted);
+    }
+    assert_eq!(text, new);
+    // the line changed one for one only loses its changed word
+    assert_eq!(found[0], (20..21, "y".to_owned()));
+    assert_eq!(
+        found.last(),
+        Some(&(29..32, "fn more() {}\nend\n".to_owned()))
+    );
+    assert!(edits(&old, &old).is_empty());
+}
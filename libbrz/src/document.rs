@@ -0,0 +1,400 @@
+//! Document
+//!
+//! Everything the editor knows about a buffer that is not its text:
+//! where it came from, what language it is in, and what tools think of it.
+use crate::dashboard::Dashboard;
+use crate::encoding::FileFormat;
+use crate::git::{CommitMessage, FileRevision};
+use crate::idx::Idx;
+use crate::lsp::{self, AttachedServer, ServerInfo};
+use crate::quickfix::{PendingEdits, Quickfix};
+use ropey::Rope;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub start: Idx,
+    pub end: Idx,
+    pub severity: Severity,
+    pub message: String,
+    /// Tool that produced the diagnostic
+    pub source: Option<String>,
+}
+
+impl Diagnostic {
+    /// The message cut in pieces at most `width` chars long, each of its
+    /// lines starting a new piece
+    pub fn wrap_message(&self, width: usize) -> Vec<String> {
+        let width = width.max(1);
+        let mut pieces = vec![];
+        for line in self.message.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                pieces.push(String::new());
+            }
+            pieces.extend(
+                chars
+                    .chunks(width)
+                    .map(|chunk| chunk.iter().collect::<String>()),
+            );
+        }
+        pieces
+    }
+}
+
+/// Bytes of the beginning of a file shown in a `FilePreview`
+pub const PREVIEW_BYTES: usize = 1024 * 1024;
+
+/// Sizes from which a file is opened with fewer features
+///
+/// Large files get no syntax highlighting, no language servers and a
+/// short undo history, until `:full` asks for them anyway. Those of
+/// `preview_bytes` or more are not even read whole: only their beginning
+/// is shown, in a `FilePreview`, for opening one not to freeze the editor
+/// while it's read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LargeFileLimits {
+    pub bytes: usize,
+    pub lines: usize,
+    pub preview_bytes: u64,
+}
+
+impl Default for LargeFileLimits {
+    fn default() -> Self {
+        Self {
+            bytes: 16 * 1024 * 1024,
+            lines: 200_000,
+            preview_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl LargeFileLimits {
+    pub fn exceeded_by(&self, text: &Rope) -> bool {
+        self.bytes <= text.len_bytes() || self.lines <= text.len_lines()
+    }
+
+    /// Is a file of `len` bytes only previewed
+    pub fn previews(&self, len: u64) -> bool {
+        self.preview_bytes <= len
+    }
+}
+
+/// The beginning of a file too large to open, loaded in the background
+///
+/// It can't be edited, has no undo history, and only the lines loaded
+/// are indexed; `:full` reads the file whole, to edit it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePreview {
+    /// Of the `PreviewRequest` loading it
+    pub id: u64,
+    /// Bytes of the whole file
+    pub len: u64,
+    pub loaded: bool,
+}
+
+/// How diagnostics are shown in the buffer
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DiagnosticDisplay {
+    /// The first message at the end of the line
+    #[default]
+    Hints,
+    /// All the messages, in full, on virtual lines under the line
+    VirtualLines {
+        /// Only for the line the cursor is on; the others get hints
+        cursor_line_only: bool,
+    },
+}
+
+/// What the gutter numbers lines with
+///
+/// Those numbering from the cursor are absolute in insert mode, where no
+/// count gets anywhere.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum LineNumbers {
+    /// No numbers, nor room for them
+    Off,
+    #[default]
+    Absolute,
+    /// How far lines are from the cursor line, for counts to get there
+    Relative,
+    /// As `Relative`, but the number of the cursor line itself
+    Hybrid,
+}
+
+impl LineNumbers {
+    pub const NAMES: &'static [&'static str] = &["off", "absolute", "relative", "hybrid"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "off" => LineNumbers::Off,
+            "absolute" => LineNumbers::Absolute,
+            "relative" => LineNumbers::Relative,
+            "hybrid" => LineNumbers::Hybrid,
+            _ => return None,
+        })
+    }
+}
+
+/// How lines wider than the view are shown
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Wrap {
+    /// Cut at the right edge
+    #[default]
+    None,
+    /// On the next rows, from where the view ends
+    Chars,
+    /// On the next rows, between words where they fit
+    Words,
+}
+
+/// What the buffer is for, when it's not just text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DocumentKind {
+    #[default]
+    Text,
+    /// Editable list of locations, written back to the files they point to
+    Quickfix(Quickfix),
+    /// Diff of quickfix edits waiting to be applied
+    PendingEdits(PendingEdits),
+    /// Message of a commit to make once written
+    GitCommit(CommitMessage),
+    /// Old revision of a file, read-only
+    FileRevision(FileRevision),
+    /// Recent files and projects, shown at startup
+    Dashboard(Dashboard),
+    /// Beginning of a file too large to open, read-only
+    Preview(FilePreview),
+    /// What the editor tells about itself, like `:health` does, read-only
+    Report,
+}
+
+impl DocumentKind {
+    /// Name of the kind, as in `theme::PANE_KINDS`
+    pub fn name(&self) -> &'static str {
+        match self {
+            DocumentKind::Text => "text",
+            DocumentKind::Quickfix(_) => "quickfix",
+            DocumentKind::PendingEdits(_) => "pending-edits",
+            DocumentKind::GitCommit(_) => "commit",
+            DocumentKind::FileRevision(_) => "revision",
+            DocumentKind::Dashboard(_) => "dashboard",
+            DocumentKind::Preview(_) => "preview",
+            DocumentKind::Report => "report",
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            DocumentKind::FileRevision(_)
+                | DocumentKind::Dashboard(_)
+                | DocumentKind::Preview(_)
+                | DocumentKind::Report
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub path: Option<PathBuf>,
+    pub language: Option<String>,
+    /// Diagnostics of all the attached servers, merged
+    pub diagnostics: Vec<Diagnostic>,
+    pub servers: Vec<AttachedServer>,
+    pub kind: DocumentKind,
+    /// Opened with fewer features, for being large
+    pub reduced: bool,
+    /// The file can't be written where it is
+    pub unwritable: bool,
+    /// Not to be written, though it can be edited
+    pub read_only: bool,
+    /// Not to be edited at all, by keys or by anything else
+    pub unmodifiable: bool,
+    pub wrap: Wrap,
+    /// The encoding and line endings of the file, to write it in
+    pub format: FileFormat,
+}
+
+impl Document {
+    pub fn from_path(path: &Path) -> Self {
+        Self {
+            path: Some(path.to_owned()),
+            language: detect_language(path).map(str::to_owned),
+            diagnostics: vec![],
+            servers: vec![],
+            kind: DocumentKind::Text,
+            reduced: false,
+            unwritable: false,
+            read_only: false,
+            unmodifiable: false,
+            wrap: Wrap::None,
+            format: FileFormat::default(),
+        }
+    }
+
+    /// Can the text be edited: not if it's set so, or it's a kind of
+    /// buffer that can't
+    pub fn is_modifiable(&self) -> bool {
+        !self.unmodifiable && !self.kind.is_read_only()
+    }
+
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.language = detect_language(&path).map(str::to_owned);
+        self.path = Some(path);
+    }
+
+    /// Diagnostics starting in the `range` of chars
+    pub fn diagnostics_in(&self, range: Range<usize>) -> &[Diagnostic] {
+        let start = self
+            .diagnostics
+            .partition_point(|d| d.start.0 < range.start);
+        let end = start + self.diagnostics[start..].partition_point(|d| d.start.0 < range.end);
+        &self.diagnostics[start..end]
+    }
+
+    /// Attach a server, replacing the one with the same name
+    pub(crate) fn attach_server(&mut self, info: ServerInfo) {
+        self.detach_server(&info.name);
+        self.servers.push(AttachedServer {
+            info,
+            diagnostics: vec![],
+        });
+    }
+
+    pub(crate) fn detach_server(&mut self, name: &str) {
+        self.servers.retain(|server| server.info.name != name);
+        self.diagnostics = lsp::merge_diagnostics(&self.servers);
+    }
+
+    /// Replace the diagnostics reported by the server `name`
+    ///
+    /// Returns `false` if no such server is attached.
+    pub(crate) fn publish_diagnostics(&mut self, name: &str, diagnostics: Vec<Diagnostic>) -> bool {
+        match self
+            .servers
+            .iter_mut()
+            .find(|server| server.info.name == name)
+        {
+            Some(server) => server.diagnostics = diagnostics,
+            None => return false,
+        }
+        self.diagnostics = lsp::merge_diagnostics(&self.servers);
+        true
+    }
+}
+
+/// Guess the language name from a file path
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str()?;
+    match file_name {
+        "Makefile" | "makefile" => return Some("make"),
+        "Cargo.lock" => return Some("toml"),
+        _ => {}
+    }
+    Some(match path.extension()?.to_str()? {
+        "rs" => "rust",
+        "py" => "python",
+        "md" | "markdown" => "markdown",
+        "toml" => "toml",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "hpp" => "cpp",
+        "go" => "go",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "json" => "json",
+        "sh" | "bash" => "shell",
+        "html" | "htm" => "html",
+        "sql" => "sql",
+        "yml" | "yaml" => "yaml",
+        _ => return None,
+    })
+}
+
+#[test]
+fn wrap_message_test() {
+    let diagnostic = Diagnostic {
+        start: Idx(0),
+        end: Idx(0),
+        severity: Severity::Error,
+        message: "mismatched types\n\nexpected `u8`".into(),
+        source: None,
+    };
+    assert_eq!(
+        diagnostic.wrap_message(8),
+        vec!["mismatch", "ed types", "", "expected", " `u8`"]
+    );
+}
+
+#[test]
+fn file_preview_test() {
+    use crate::preview::Preview;
+    use crate::state::State;
+    use crate::vfs::MemoryFs;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let fs = MemoryFs::new();
+    fs.add("big.txt", "one\ntwo\nthree\n");
+    let mut state = State::default();
+    state.register_vfs(Arc::new(fs));
+    state.set_large_file_limits(LargeFileLimits {
+        preview_bytes: 10,
+        ..LargeFileLimits::default()
+    });
+    let requested = Rc::new(Cell::new(None));
+    let sent = requested.clone();
+    state.register_preview_handler(move |request| {
+        sent.set(Some((request.id, request.max_bytes)));
+        Ok(())
+    });
+    state.open_buffer(Path::new("big.txt"));
+    let (id, max_bytes) = requested.get().unwrap();
+    assert_eq!(max_bytes, PREVIEW_BYTES);
+    assert_eq!(state.cur_document().kind.name(), "preview");
+    assert!(!state.cur_document().is_modifiable());
+    let text = Rope::from_str("one\n");
+    state.deliver_preview(
+        id,
+        Preview::Text {
+            text,
+            truncated: true,
+        },
+    );
+    assert_eq!(state.cur_buffer().text.to_string(), "one\n");
+
+    state.full_features();
+    assert_eq!(state.cur_buffer().text.to_string(), "one\ntwo\nthree\n");
+    assert_eq!(state.cur_document().kind, DocumentKind::Text);
+    assert!(state.cur_document().is_modifiable() && !state.cur_document().read_only);
+}
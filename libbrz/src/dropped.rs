@@ -0,0 +1,86 @@
+//! Files dropped on the terminal
+//!
+//! Terminals paste the paths of the files dropped on them. Depending on
+//! the terminal they are `file://` URIs, quoted, or with their spaces
+//! escaped, one per line or separated by spaces:
+//!
+//! ```text
+//! file:///home/me/notes%20old.txt
+//! '/home/me/notes old.txt' /tmp/a.rs
+//! /home/me/notes\ old.txt
+//! ```
+//!
+//! A paste made only of such absolute paths is taken for a drop.
+use crate::util::uri::percent_decode;
+use std::path::PathBuf;
+
+/// The words of `text`, split at unquoted and unescaped whitespace
+fn words(text: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        quote if quote == ch => break,
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            ch if ch.is_whitespace() => words.extend(word.take()),
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// The paths in a paste, if it's only paths of dropped files
+pub fn parse_dropped(text: &str) -> Option<Vec<PathBuf>> {
+    let paths = words(text)?
+        .into_iter()
+        .map(|word| {
+            let path = match word.strip_prefix("file://") {
+                // with a host, or not
+                Some(uri) => percent_decode(&uri[uri.find('/')?..])?,
+                None => word,
+            };
+            Some(PathBuf::from(path)).filter(|path| path.is_absolute())
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(paths).filter(|paths| !paths.is_empty())
+}
+
+#[test]
+fn parse_dropped_test() {
+    let paths = |text| {
+        parse_dropped(text).map(|paths| {
+            paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(
+        paths("file:///home/me/notes%20old.txt\r\nfile://host/tmp/a.rs\n"),
+        Some(vec![
+            "/home/me/notes old.txt".to_owned(),
+            "/tmp/a.rs".to_owned()
+        ])
+    );
+    assert_eq!(
+        paths("'/home/me/notes old.txt' /tmp/a\\ b.rs "),
+        Some(vec![
+            "/home/me/notes old.txt".to_owned(),
+            "/tmp/a b.rs".to_owned()
+        ])
+    );
+    assert_eq!(paths("/tmp/a.rs and more"), None);
+    assert_eq!(paths("let x = '/tmp"), None);
+    assert_eq!(paths("file:///bad%zz"), None);
+    assert_eq!(paths("  \n"), None);
+}
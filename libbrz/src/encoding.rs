@@ -0,0 +1,284 @@
+//! Encodings and line endings of files
+//!
+//! Buffers are UTF-8, with `\n` line ends. A file is decoded as its bytes
+//! tell: UTF-16 or UTF-8 after a byte order mark, UTF-8 if it's valid,
+//! and Latin-1 otherwise, which any bytes are: the mark too, if what
+//! follows it isn't valid, so that no byte of a file is lost once it's
+//! written back. If all of its lines end
+//! with `\r\n`, they end with `\n` in the buffer and get their `\r` back
+//! once written; those of a file with both keep their `\r`.
+//!
+//! A file is written back in the format it was read in, which
+//! `:encoding` and `:line-ending` change, to be written in another.
+use ropey::Rope;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// After a byte order mark
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+/// Names of the encodings, as `:encoding` takes them
+pub const ENCODINGS: &[&str] = &["utf-8", "utf-8-bom", "utf-16le", "utf-16be", "latin-1"];
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+        }
+    }
+
+    /// By `name`, in any case, or by one of the other names it goes by
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-8-bom" | "utf8-bom" => Encoding::Utf8Bom,
+            "utf-16le" | "utf16le" | "utf-16" => Encoding::Utf16Le,
+            "utf-16be" | "utf16be" => Encoding::Utf16Be,
+            "latin-1" | "latin1" | "iso-8859-1" => Encoding::Latin1,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "lf" | "unix" => Some(LineEnding::Lf),
+            "crlf" | "dos" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+}
+
+/// How the text of a file is written in it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+}
+
+impl FileFormat {
+    /// For the status line: `None` for UTF-8 with `\n`, the most common
+    pub fn indicator(self) -> Option<String> {
+        let parts: Vec<&str> = [
+            Some(self.encoding)
+                .filter(|&encoding| encoding != Encoding::Utf8)
+                .map(Encoding::name),
+            Some(self.line_ending)
+                .filter(|&line_ending| line_ending != LineEnding::Lf)
+                .map(LineEnding::name),
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+        Some(parts.join(" ")).filter(|indicator| !indicator.is_empty())
+    }
+}
+
+/// A char that can't be written in an encoding, at a line from 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeError {
+    pub ch: char,
+    pub line: usize,
+    pub encoding: Encoding,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} of line {} is not in {}: :encoding utf-8 to write it",
+            self.ch,
+            self.line + 1,
+            self.encoding.name()
+        )
+    }
+}
+
+/// `None` if it's not valid UTF-16: of an odd length, or with a
+/// surrogate unpaired
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks(2).map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units).collect::<Result<_, _>>().ok()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// The text of a file of `bytes`, and the format it's in
+pub fn decode(bytes: Vec<u8>) -> (Rope, FileFormat) {
+    let marks = [
+        (&b"\xef\xbb\xbf"[..], Encoding::Utf8Bom),
+        (b"\xff\xfe", Encoding::Utf16Le),
+        (b"\xfe\xff", Encoding::Utf16Be),
+    ];
+    let marked = marks
+        .iter()
+        .find_map(|&(mark, encoding)| Some((bytes.strip_prefix(mark)?, encoding)));
+    let (text, encoding) = match marked {
+        Some((rest, encoding)) => {
+            let text = match encoding {
+                Encoding::Utf8Bom => String::from_utf8(rest.to_vec()).ok(),
+                Encoding::Utf16Le => decode_utf16(rest, u16::from_le_bytes),
+                _ => decode_utf16(rest, u16::from_be_bytes),
+            };
+            match text {
+                Some(text) => (text, encoding),
+                None => (decode_latin1(&bytes), Encoding::Latin1),
+            }
+        }
+        None => match String::from_utf8(bytes) {
+            Ok(text) => (text, Encoding::Utf8),
+            Err(e) => (decode_latin1(e.as_bytes()), Encoding::Latin1),
+        },
+    };
+    let ends = text.matches('\n').count();
+    let line_ending = if 0 < ends && text.matches("\r\n").count() == ends {
+        LineEnding::Crlf
+    } else {
+        LineEnding::Lf
+    };
+    let text = match line_ending {
+        LineEnding::Crlf => Rope::from_str(&text.replace("\r\n", "\n")),
+        LineEnding::Lf => Rope::from_str(&text),
+    };
+    (
+        text,
+        FileFormat {
+            encoding,
+            line_ending,
+        },
+    )
+}
+
+/// The bytes of a file of `text`, in `format`
+pub fn encode(text: &Rope, format: FileFormat) -> Result<Vec<u8>, EncodeError> {
+    let mut bytes = Vec::with_capacity(text.len_bytes());
+    match format.encoding {
+        Encoding::Utf8Bom => bytes.extend_from_slice(b"\xef\xbb\xbf"),
+        Encoding::Utf16Le => bytes.extend_from_slice(b"\xff\xfe"),
+        Encoding::Utf16Be => bytes.extend_from_slice(b"\xfe\xff"),
+        Encoding::Utf8 | Encoding::Latin1 => {}
+    }
+    let mut line = 0;
+    let mut buf = [0u16; 2];
+    for ch in text.chars() {
+        if ch == '\n' && format.line_ending == LineEnding::Crlf {
+            bytes.push(b'\r');
+            bytes.push(b'\n');
+            line += 1;
+            continue;
+        }
+        match format.encoding {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                let mut utf8 = [0u8; 4];
+                bytes.extend_from_slice(ch.encode_utf8(&mut utf8).as_bytes());
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for unit in ch.encode_utf16(&mut buf) {
+                    bytes.extend_from_slice(&match format.encoding {
+                        Encoding::Utf16Le => unit.to_le_bytes(),
+                        _ => unit.to_be_bytes(),
+                    });
+                }
+            }
+            Encoding::Latin1 if (ch as u32) < 0x100 => bytes.push(ch as u8),
+            Encoding::Latin1 => {
+                return Err(EncodeError {
+                    ch,
+                    line,
+                    encoding: format.encoding,
+                })
+            }
+        }
+        line += (ch == '\n') as usize;
+    }
+    Ok(bytes)
+}
+
+#[test]
+fn encoding_test() {
+    let format = |encoding, line_ending| FileFormat {
+        encoding,
+        line_ending,
+    };
+    let (text, found) = decode(b"caf\xe9\r\nbar\r\n".to_vec());
+    assert_eq!(text.to_string(), "café\nbar\n");
+    assert_eq!(found, format(Encoding::Latin1, LineEnding::Crlf));
+    assert_eq!(found.indicator().as_deref(), Some("latin-1 crlf"));
+    assert_eq!(encode(&text, found).unwrap(), b"caf\xe9\r\nbar\r\n");
+
+    // both line endings: the `\r`s are kept
+    let (text, found) = decode(b"a\r\nb\n".to_vec());
+    assert_eq!(text.to_string(), "a\r\nb\n");
+    assert_eq!(found, FileFormat::default());
+    assert_eq!(found.indicator(), None);
+
+    let utf16 = b"\xff\xfeh\x00\xe9\x00\n\x00=\xd8\x00\xde".to_vec();
+    let (text, found) = decode(utf16.clone());
+    assert_eq!(text.to_string(), "hé\n\u{1f600}");
+    assert_eq!(found, format(Encoding::Utf16Le, LineEnding::Lf));
+    assert_eq!(encode(&text, found).unwrap(), utf16);
+    let be = encode(&text, format(Encoding::Utf16Be, LineEnding::Lf)).unwrap();
+    assert_eq!(decode(be).0, text);
+
+    let (text, found) = decode(b"\xef\xbb\xbfok\n".to_vec());
+    assert_eq!(
+        (text.to_string().as_str(), found.encoding),
+        ("ok\n", Encoding::Utf8Bom)
+    );
+    assert_eq!(encode(&text, found).unwrap(), b"\xef\xbb\xbfok\n");
+
+    // not valid after the mark: all of it as Latin-1, written back as it was
+    for bytes in [
+        b"\xef\xbb\xbfcaf\xe9\n".to_vec(),
+        b"\xff\xfeh\x00i".to_vec(),
+        b"\xff\xfe\x00\xd8h\x00".to_vec(),
+    ] {
+        let (text, found) = decode(bytes.clone());
+        assert_eq!(found.encoding, Encoding::Latin1);
+        assert!(!text.to_string().contains(char::REPLACEMENT_CHARACTER));
+        assert_eq!(encode(&text, found).unwrap(), bytes);
+    }
+
+    let e = encode(
+        &Rope::from_str("a\nb \u{20ac}\n"),
+        format(Encoding::Latin1, LineEnding::Lf),
+    );
+    assert_eq!(
+        e.unwrap_err().to_string(),
+        "'€' of line 2 is not in latin-1: :encoding utf-8 to write it"
+    );
+    assert_eq!(Encoding::parse("ISO-8859-1"), Some(Encoding::Latin1));
+}
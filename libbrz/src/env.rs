@@ -0,0 +1,291 @@
+//! Environment of the tools the frontend runs
+//!
+//! They should behave as they do in the shell of the project, so on top
+//! of the editor's own environment they get, in order:
+//!
+//! - the `bin` directories of the project, like `node_modules/.bin`,
+//!   first in `PATH`
+//! - what the `.env` and `.envrc` files of the project set, as far as
+//!   they are plain assignments: the rest of what direnv would run is
+//!   left out
+//! - what the `env` config files set, for every tool and then for the
+//!   tool, by the name of its command:
+//!
+//! ```text
+//! RUST_BACKTRACE=1
+//! PATH_add scripts
+//!
+//! [git]
+//! export GIT_PAGER=cat
+//! ```
+//!
+//! Values can use `$NAME` and `${NAME}` of what's set before them or in
+//! the editor's environment, except in single quotes. `PATH_add`, as in
+//! direnv, puts a directory, relative to the project, first in `PATH`.
+//!
+//! The frontend only adds what the project sets, its `bin` directories,
+//! env files and `env` config file, for a project the user trusts, and
+//! never for the helpers it runs itself.
+use std::fmt;
+use std::path::Path;
+
+/// Directories of the project with tools of its own, first in `PATH` if
+/// they exist
+pub const PROJECT_BIN_DIRS: [&str; 2] = ["node_modules/.bin", ".venv/bin"];
+
+/// Files of the project to take variables from
+pub const ENV_FILES: [&str; 2] = [".env", ".envrc"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Var(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Setting {
+    Var(String, Vec<Piece>),
+    PathAdd(Vec<Piece>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEnvError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseEnvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line + 1, self.msg)
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// The name of a `$NAME` or `${NAME}` that `s` follows the `$` of, and
+/// how long it is
+fn var_ref(s: &str) -> Option<(&str, usize)> {
+    if let Some(rest) = s.strip_prefix('{') {
+        let end = rest.find('}')?;
+        return Some(&rest[..end])
+            .filter(|name| is_name(name))
+            .map(|name| (name, end + 2));
+    }
+    let end = s
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+        .unwrap_or(s.len());
+    Some(&s[..end])
+        .filter(|name| is_name(name))
+        .map(|name| (name, end))
+}
+
+/// The pieces of a value, as a shell would take it
+fn parse_value(value: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut quote = None;
+    let mut chars = value.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), ch) => text.push(ch),
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '#') if value[..i].ends_with(char::is_whitespace) => break,
+            (None, ch) if ch.is_whitespace() => {
+                if !value[i..].trim_start().starts_with('#') {
+                    return Err("spaces outside of quotes".to_owned());
+                }
+            }
+            (_, '\\') => text.extend(chars.next().map(|(_, ch)| ch)),
+            (_, '$') => match var_ref(&value[i + 1..]) {
+                Some((name, len)) => {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Var(name.to_owned()));
+                    // names and braces are ASCII
+                    chars.nth(len - 1);
+                }
+                None => text.push('$'),
+            },
+            (_, ch) => text.push(ch),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_owned());
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// The setting of `line`, if it's not blank or a comment
+fn parse_setting(line: &str) -> Result<Option<Setting>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if let Some(dir) = line.strip_prefix("PATH_add ") {
+        return Ok(Some(Setting::PathAdd(parse_value(dir.trim())?)));
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+    match line.split_once('=') {
+        Some((name, value)) if is_name(name) => {
+            Ok(Some(Setting::Var(name.to_owned(), parse_value(value)?)))
+        }
+        _ => Err("expected NAME=value or PATH_add dir".to_owned()),
+    }
+}
+
+/// What tools get set in their environment
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    /// For every tool
+    common: Vec<Setting>,
+    /// By the name of the command of the tool
+    tools: Vec<(String, Vec<Setting>)>,
+}
+
+impl Environment {
+    /// Put `dir`, relative to the project, first in `PATH`, for every tool
+    pub fn add_path(&mut self, dir: &str) {
+        self.common
+            .push(Setting::PathAdd(vec![Piece::Text(dir.to_owned())]));
+    }
+
+    /// Add what the `.env` or `.envrc` file with `text` sets, leaving out
+    /// whatever else a shell would have run
+    pub fn add_env_file(&mut self, text: &str) {
+        self.common.extend(
+            text.lines()
+                .filter_map(|line| parse_setting(line).ok().flatten()),
+        );
+    }
+
+    /// Add what the `env` config file with `text` sets
+    pub fn define(&mut self, text: &str) -> Result<(), ParseEnvError> {
+        let mut tool: Option<usize> = None;
+        for (i, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                let name = name.trim();
+                tool = Some(match self.tools.iter().position(|(tool, _)| tool == name) {
+                    Some(i) => i,
+                    None => {
+                        self.tools.push((name.to_owned(), vec![]));
+                        self.tools.len() - 1
+                    }
+                });
+                continue;
+            }
+            let setting = match parse_setting(line) {
+                Ok(Some(setting)) => setting,
+                Ok(None) => continue,
+                Err(msg) => return Err(ParseEnvError { line: i, msg }),
+            };
+            match tool {
+                Some(tool) => self.tools[tool].1.push(setting),
+                None => self.common.push(setting),
+            }
+        }
+        Ok(())
+    }
+
+    /// The variables to set to run `tool` for the project in `project`,
+    /// `inherited` giving those of the editor
+    pub fn vars_for(
+        &self,
+        tool: &str,
+        project: &Path,
+        inherited: impl Fn(&str) -> Option<String>,
+    ) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = vec![];
+        let tool_settings = self
+            .tools
+            .iter()
+            .filter(|(name, _)| name == tool)
+            .flat_map(|(_, settings)| settings);
+        for setting in self.common.iter().chain(tool_settings) {
+            let lookup = |name: &str| {
+                vars.iter()
+                    .rev()
+                    .find(|(var, _)| var == name)
+                    .map(|(_, value)| value.clone())
+                    .or_else(|| inherited(name))
+            };
+            let expand = |pieces: &[Piece]| -> String {
+                pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => text.clone(),
+                        Piece::Var(name) => lookup(name).unwrap_or_default(),
+                    })
+                    .collect()
+            };
+            let (name, value) = match setting {
+                Setting::Var(name, pieces) => (name.clone(), expand(pieces)),
+                Setting::PathAdd(dir) => {
+                    let dir = project.join(expand(dir)).display().to_string();
+                    let value = match lookup("PATH").filter(|path| !path.is_empty()) {
+                        Some(path) => format!("{}:{}", dir, path),
+                        None => dir,
+                    };
+                    ("PATH".to_owned(), value)
+                }
+            };
+            vars.retain(|(var, _)| *var != name);
+            vars.push((name, value));
+        }
+        vars
+    }
+}
+
+#[test]
+fn vars_for_test() {
+    let mut env = Environment::default();
+    env.add_path("node_modules/.bin");
+    env.add_env_file(
+        "# direnv\nexport DB='postgres://$USER'\nuse flake\nNAME=\"${USER}_dev\" # mine\n",
+    );
+    env.define("RUST_LOG=info\n\n[git]\nGIT_PAGER=cat\nPATH_add $HOME/bin\n")
+        .unwrap();
+    let inherited = |name: &str| match name {
+        "PATH" => Some("/usr/bin".to_owned()),
+        "USER" => Some("me".to_owned()),
+        "HOME" => Some("/home/me".to_owned()),
+        _ => None,
+    };
+    let vars = |tool| env.vars_for(tool, Path::new("/p"), inherited);
+    assert_eq!(
+        vars("sudo"),
+        vec![
+            (
+                "PATH".to_owned(),
+                "/p/node_modules/.bin:/usr/bin".to_owned()
+            ),
+            ("DB".to_owned(), "postgres://$USER".to_owned()),
+            ("NAME".to_owned(), "me_dev".to_owned()),
+            ("RUST_LOG".to_owned(), "info".to_owned()),
+        ]
+    );
+    let git = vars("git");
+    assert_eq!(git[3], ("GIT_PAGER".to_owned(), "cat".to_owned()));
+    assert_eq!(
+        git[4],
+        (
+            "PATH".to_owned(),
+            "/home/me/bin:/p/node_modules/.bin:/usr/bin".to_owned()
+        )
+    );
+
+    let err = Environment::default().define("A=1\nB=\"x\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: unterminated quote");
+    assert!(Environment::default().define("not a setting").is_err());
+}
@@ -0,0 +1,116 @@
+//! The files of the project, for the fuzzy finder
+//!
+//! The frontend walks the project in the background, leaving out what
+//! `.gitignore` does, and passes the paths it found to
+//! `State::deliver_files` in batches as it goes, so the finder lists
+//! them, filtered, before the walk is done. Each batch is only matched
+//! against the pattern typed, and a pattern typed further only looks at
+//! the matches of the one before.
+use crate::fuzzy::{self, Match};
+use crate::preview::CancelToken;
+use crate::progress::TaskId;
+use std::path::PathBuf;
+
+/// What the frontend is asked to list the files of the project for
+#[derive(Debug, Clone)]
+pub struct FileListRequest {
+    pub id: u64,
+    pub cancel: CancelToken,
+}
+
+/// The files found so far, and those the pattern matches
+#[derive(Debug, Clone)]
+pub(crate) struct FileList {
+    pub id: u64,
+    paths: Vec<PathBuf>,
+    /// The walk is over
+    pub done: bool,
+    pattern: String,
+    /// Indices in `paths`, with the match, best first
+    matches: Vec<(usize, Match)>,
+    pub cancel: CancelToken,
+    pub task: Option<TaskId>,
+}
+
+impl FileList {
+    pub fn new(id: u64, cancel: CancelToken, task: TaskId) -> Self {
+        Self {
+            id,
+            paths: vec![],
+            done: false,
+            pattern: String::new(),
+            matches: vec![],
+            cancel,
+            task: Some(task),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Add the paths of another batch
+    pub fn add(&mut self, paths: Vec<PathBuf>) {
+        let start = self.paths.len();
+        self.paths.extend(paths);
+        let text = |&i: &usize| self.paths[i].to_string_lossy().into_owned();
+        let found = fuzzy::filter(&self.pattern, start..self.paths.len(), text);
+        self.matches.extend(found);
+        // stable, so those an earlier batch found stay ahead of as good ones
+        self.matches
+            .sort_by_key(|(_, found)| std::cmp::Reverse(found.score));
+    }
+
+    pub fn set_pattern(&mut self, pattern: &str) {
+        let text = |&i: &usize| self.paths[i].to_string_lossy().into_owned();
+        let candidates: Vec<usize> = if pattern.starts_with(&self.pattern) {
+            let mut found: Vec<usize> = self.matches.iter().map(|&(i, _)| i).collect();
+            // in the order they were found in
+            found.sort_unstable();
+            found
+        } else {
+            (0..self.paths.len()).collect()
+        };
+        self.matches = fuzzy::filter(pattern, candidates, text);
+        self.pattern = pattern.to_owned();
+    }
+
+    /// The paths matching, best first, with the indices of the chars
+    /// found
+    pub fn matches(&self) -> impl Iterator<Item = (&PathBuf, &[usize])> + '_ {
+        self.matches
+            .iter()
+            .map(move |(i, found)| (&self.paths[*i], found.indices.as_slice()))
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+}
+
+#[test]
+fn file_list_test() {
+    let mut list = FileList::new(
+        0,
+        CancelToken::default(),
+        crate::progress::Tasks::default().begin("files"),
+    );
+    let paths = |list: &FileList| -> Vec<String> {
+        list.matches()
+            .map(|(path, _)| path.display().to_string())
+            .collect()
+    };
+    list.add(vec!["src/main.rs".into(), "README.md".into()]);
+    assert_eq!(paths(&list), vec!["src/main.rs", "README.md"]);
+    list.set_pattern("ma");
+    assert_eq!(paths(&list), vec!["src/main.rs"]);
+
+    // later batches go through the pattern, and ahead if better
+    list.add(vec!["main.rs".into(), "docs/oops.txt".into()]);
+    assert_eq!(paths(&list), vec!["main.rs", "src/main.rs"]);
+    list.set_pattern("mai");
+    assert_eq!(paths(&list), vec!["main.rs", "src/main.rs"]);
+    list.set_pattern("o");
+    assert_eq!(paths(&list), vec!["docs/oops.txt"]);
+    assert_eq!((list.len(), list.match_count()), (4, 1));
+}
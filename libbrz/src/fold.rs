@@ -0,0 +1,221 @@
+//! Folds: lines of a view hidden under the one before them
+//!
+//! A fold is found by indentation: a line, and those after it indented
+//! further, but for the blank lines ending them. Each view closes folds
+//! of its own, kept as chars of the text, from the start of the first
+//! line to that of the line after, to move along with the edits; a
+//! closed fold shows its first line only, with a summary of the others.
+use crate::buffer::Buffer;
+use crate::span;
+use ropey::Rope;
+use std::ops::Range;
+
+/// Cells of indentation of `line`; `None` if it's blank
+fn indentation(text: &Rope, line: usize, tab_width: usize) -> Option<usize> {
+    let mut cells = 0;
+    for ch in text.line(line).chars() {
+        match ch {
+            ' ' => cells += 1,
+            '\t' => cells += tab_width - cells % tab_width,
+            '\n' | '\r' => return None,
+            _ => return Some(cells),
+        }
+    }
+    None
+}
+
+/// Lines of the fold starting at `first`, if any line after is indented
+/// further
+pub fn fold_from(text: &Rope, first: usize, tab_width: usize) -> Option<Range<usize>> {
+    let indent = indentation(text, first, tab_width)?;
+    let mut end = first + 1;
+    for line in first + 1..text.len_lines() {
+        match indentation(text, line, tab_width) {
+            Some(inner) if inner <= indent => break,
+            Some(_) => end = line + 1,
+            None => {}
+        }
+    }
+    Some(first..end).filter(|fold| 1 < fold.len())
+}
+
+/// Lines of the innermost fold `line` is in, the one it starts first
+pub fn fold_at(text: &Rope, line: usize, tab_width: usize) -> Option<Range<usize>> {
+    fold_from(text, line, tab_width).or_else(|| enclosing_fold(text, line, tab_width))
+}
+
+/// Lines of the innermost fold `line` is in, other than the one it starts
+pub fn enclosing_fold(text: &Rope, line: usize, tab_width: usize) -> Option<Range<usize>> {
+    // a blank line is as far in as the next line that isn't
+    let indent = (line..text.len_lines()).find_map(|line| indentation(text, line, tab_width))?;
+    let first = (0..line)
+        .rev()
+        .find(|&first| indentation(text, first, tab_width).is_some_and(|outer| outer < indent))?;
+    fold_from(text, first, tab_width).filter(|fold| fold.contains(&line))
+}
+
+/// Lines of all the folds, the outer ones first
+pub fn all_folds(text: &Rope, tab_width: usize) -> Vec<Range<usize>> {
+    (0..text.len_lines())
+        .filter_map(|line| fold_from(text, line, tab_width))
+        .collect()
+}
+
+/// The folds closed in a view
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Folds {
+    version: u64,
+    /// Chars of the closed folds, in order
+    closed: Vec<Range<usize>>,
+}
+
+impl Folds {
+    pub fn is_empty(&self) -> bool {
+        self.closed.is_empty()
+    }
+
+    fn lines(&self, text: &Rope, chars: &Range<usize>) -> Range<usize> {
+        let end = if chars.end < text.len_chars() {
+            text.char_to_line(chars.end)
+        } else {
+            text.len_lines()
+        };
+        text.char_to_line(chars.start)..end
+    }
+
+    /// Lines of the closed folds, in order
+    pub fn closed(&self, text: &Rope) -> Vec<Range<usize>> {
+        self.closed
+            .iter()
+            .map(|chars| self.lines(text, chars))
+            .collect()
+    }
+
+    /// Lines hidden, in order: all but the first of each closed fold
+    pub fn hidden(&self, text: &Rope) -> Vec<Range<usize>> {
+        let mut hidden: Vec<Range<usize>> = vec![];
+        for lines in self.closed(text) {
+            let lines = lines.start + 1..lines.end;
+            match hidden.last_mut() {
+                Some(last) if lines.start <= last.end => last.end = last.end.max(lines.end),
+                _ => hidden.push(lines),
+            }
+        }
+        hidden.retain(|lines| !lines.is_empty());
+        hidden
+    }
+
+    /// The hidden lines `line` is in
+    pub fn hidden_at(&self, text: &Rope, line: usize) -> Option<Range<usize>> {
+        self.hidden(text)
+            .into_iter()
+            .find(|lines| lines.contains(&line))
+    }
+
+    /// Lines hidden under `line`, if it's shown, as the first one of a
+    /// closed fold
+    pub fn folded_under(&self, text: &Rope, line: usize) -> Option<usize> {
+        self.hidden(text)
+            .into_iter()
+            .find(|lines| lines.start == line + 1)
+            .map(|lines| lines.len())
+    }
+
+    pub fn is_closed(&self, text: &Rope, lines: &Range<usize>) -> bool {
+        self.closed(text).contains(lines)
+    }
+
+    pub fn close(&mut self, buffer: &Buffer, lines: Range<usize>) {
+        let text = &buffer.text;
+        let char_of = |line: usize| {
+            if line < text.len_lines() {
+                text.line_to_char(line)
+            } else {
+                text.len_chars()
+            }
+        };
+        let chars = char_of(lines.start)..char_of(lines.end);
+        self.follow(buffer);
+        if !self.closed.contains(&chars) {
+            self.closed.push(chars);
+            self.closed
+                .sort_by_key(|chars| (chars.start, usize::MAX - chars.end));
+        }
+    }
+
+    /// Open the closed folds `line` is in; `false` if there's none
+    pub fn open_at(&mut self, text: &Rope, line: usize) -> bool {
+        let count = self.closed.len();
+        let closed: Vec<_> = self.closed.drain(..).collect();
+        for chars in closed {
+            if !self.lines(text, &chars).contains(&line) {
+                self.closed.push(chars);
+            }
+        }
+        self.closed.len() != count
+    }
+
+    pub fn open_all(&mut self) {
+        self.closed.clear();
+    }
+
+    /// Move along the changes made since; the folds are opened if they
+    /// are too old to know, or their lines were removed
+    pub fn follow(&mut self, buffer: &Buffer) {
+        match buffer.changes_since(self.version) {
+            Some(changes) => {
+                for change in changes {
+                    for chars in &mut self.closed {
+                        *chars = span::map_range(chars, change);
+                    }
+                }
+            }
+            None => self.closed.clear(),
+        }
+        let text = &buffer.text;
+        let lines = |chars: &Range<usize>| self.lines(text, chars);
+        let kept: Vec<_> = self
+            .closed
+            .iter()
+            .filter(|chars| 1 < lines(chars).len())
+            .cloned()
+            .collect();
+        self.closed = kept;
+        self.version = buffer.version();
+    }
+}
+
+#[test]
+fn fold_test() {
+    let text =
+        Rope::from_str("fn a() {\n    b;\n\n    if c {\n        d;\n    }\n}\n\nfn e() {}\n");
+    assert_eq!(fold_from(&text, 0, 4), Some(0..6));
+    assert_eq!(fold_from(&text, 3, 4), Some(3..5));
+    assert_eq!(fold_from(&text, 1, 4), None);
+    assert_eq!(fold_at(&text, 4, 4), Some(3..5));
+    // a blank line, and the end of a fold, are in it
+    assert_eq!(fold_at(&text, 2, 4), Some(0..6));
+    assert_eq!(fold_at(&text, 5, 4), Some(0..6));
+    assert_eq!(enclosing_fold(&text, 3, 4), Some(0..6));
+    assert_eq!(fold_at(&text, 8, 4), None);
+    assert_eq!(all_folds(&text, 4), vec![0..6, 3..5]);
+
+    let mut buffer = Buffer::from_text(text);
+    let mut folds = Folds::default();
+    folds.close(&buffer, 3..5);
+    folds.close(&buffer, 0..6);
+    assert_eq!(folds.hidden(&buffer.text), vec![1..6]);
+    assert_eq!(folds.folded_under(&buffer.text, 0), Some(5));
+    assert!(folds.open_at(&buffer.text, 0));
+    assert_eq!(folds.hidden(&buffer.text), vec![4..5]);
+    assert_eq!(folds.hidden_at(&buffer.text, 4), Some(4..5));
+
+    // along with the edits
+    buffer.insert(crate::Idx(0), "// a\n");
+    folds.follow(&buffer);
+    assert_eq!(folds.closed(&buffer.text), vec![4..6]);
+    let start = buffer.text.line_to_char(4);
+    buffer.remove(start..buffer.text.line_to_char(6));
+    folds.follow(&buffer);
+    assert!(folds.is_empty());
+}
@@ -0,0 +1,75 @@
+//! Views following another one
+//!
+//! An outline can follow the cursor in the code it outlines, a preview
+//! scroll along with its source: a view following another is moved
+//! whenever the cursor of the leader moves or its text changes, and so
+//! are the views following it in turn. Where it goes is up to the
+//! `FollowMap` of the follow, given the text and the cursor of the
+//! leader and the text of the follower; `None` leaves it where it is.
+use crate::position::Position;
+use ropey::Rope;
+use std::fmt;
+use std::sync::Arc;
+
+/// Where the follower goes, from the text and the cursor of the leader
+/// and the text of the follower
+pub type FollowMap = Arc<dyn Fn(&Rope, Position, &Rope) -> Option<Position>>;
+
+#[derive(Clone)]
+pub struct Follow {
+    /// Index of the view followed
+    pub leader: usize,
+    pub map: FollowMap,
+}
+
+impl fmt::Debug for Follow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Follow")
+            .field("leader", &self.leader)
+            .finish()
+    }
+}
+
+/// To the position of the cursor, as far as the text of the follower
+/// goes, for views of the same text or close to it
+pub fn same_position() -> FollowMap {
+    Arc::new(|_, cursor, _| Some(cursor))
+}
+
+/// To the line as far into the text of the follower as the cursor is
+/// into that of the leader, for a preview with lines of its own
+pub fn proportional() -> FollowMap {
+    Arc::new(|leader, cursor, follower| {
+        let leader_lines = leader.len_lines().saturating_sub(1).max(1);
+        let follower_lines = follower.len_lines().saturating_sub(1);
+        Some(Position {
+            line: cursor.line.min(leader_lines) * follower_lines / leader_lines,
+            column: 0,
+        })
+    })
+}
+
+#[test]
+fn proportional_test() {
+    let text = |lines: usize| Rope::from_str(&"x\n".repeat(lines));
+    let map = proportional();
+    let line = |leader, cursor, follower| {
+        map(
+            &text(leader),
+            Position {
+                line: cursor,
+                column: 3,
+            },
+            &text(follower),
+        )
+        .map(|position| position.line)
+    };
+    assert_eq!(line(100, 50, 10), Some(5));
+    assert_eq!(line(100, 100, 10), Some(10));
+    assert_eq!(line(10, 3, 100), Some(30));
+    assert_eq!(line(0, 0, 10), Some(0));
+    assert_eq!(
+        same_position()(&text(1), Position { line: 4, column: 2 }, &text(1)),
+        Some(Position { line: 4, column: 2 })
+    );
+}
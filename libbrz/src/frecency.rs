@@ -0,0 +1,107 @@
+//! Names ranked by how often and how recently they were used
+//!
+//! The clock is the number of uses so far, not the time: a name used a
+//! lot a while ago fades as others get used, however long the editor
+//! was closed in between, and a score halves every `HALF_LIFE` uses of
+//! anything.
+//!
+//! Kept across sessions as text, a line per name with its count and the
+//! use it was last used at: `GotoDefinition 12 340`.
+use std::collections::BTreeMap;
+
+/// Uses after which a use counts half as much
+const HALF_LIFE: f64 = 50.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Entry {
+    count: u64,
+    last: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frecency {
+    clock: u64,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Frecency {
+    pub fn note(&mut self, name: &str) {
+        self.clock += 1;
+        let entry = self
+            .entries
+            .entry(name.to_owned())
+            .or_insert(Entry { count: 0, last: 0 });
+        entry.count += 1;
+        entry.last = self.clock;
+    }
+
+    fn score(&self, entry: &Entry) -> f64 {
+        let age = (self.clock - entry.last) as f64;
+        entry.count as f64 * 0.5f64.powf(age / HALF_LIFE)
+    }
+
+    /// At most `n` names, best first
+    pub fn top(&self, n: usize) -> Vec<&str> {
+        let mut ranked: Vec<(&str, f64)> = self
+            .entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), self.score(entry)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked.into_iter().take(n).map(|(name, _)| name).collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(name, entry)| format!("{} {} {}\n", name, entry.count, entry.last))
+            .collect()
+    }
+
+    /// Read back what `to_text` wrote; lines that don't make sense are
+    /// skipped
+    pub fn from_text(text: &str) -> Self {
+        let mut frecency = Self::default();
+        for line in text.lines() {
+            // names, like paths, can have spaces
+            let mut words = line.rsplitn(3, ' ');
+            let (last, count, name) = match (words.next(), words.next(), words.next()) {
+                (Some(last), Some(count), Some(name)) => (last, count, name),
+                _ => continue,
+            };
+            if let (Ok(count), Ok(last)) = (count.parse(), last.parse()) {
+                frecency.clock = frecency.clock.max(last);
+                frecency
+                    .entries
+                    .insert(name.to_owned(), Entry { count, last });
+            }
+        }
+        frecency
+    }
+}
+
+#[test]
+fn frecency_test() {
+    let mut frecency = Frecency::default();
+    for _ in 0..5 {
+        frecency.note("Old");
+    }
+    for _ in 0..100 {
+        frecency.note("Filler");
+    }
+    frecency.note("New");
+    frecency.note("New");
+    // 5 uses 100 uses ago count for 1.25 now
+    assert_eq!(frecency.top(2), vec!["Filler", "New"]);
+    assert_eq!(frecency.top(5), vec!["Filler", "New", "Old"]);
+
+    let text = frecency.to_text();
+    assert_eq!(text, "Filler 100 105\nNew 2 107\nOld 5 5\n");
+    let read = Frecency::from_text(&format!("{}nonsense\nOld x 3\n", text));
+    assert_eq!(read, frecency);
+
+    let mut paths = Frecency::default();
+    paths.note("/home/me/my notes.txt");
+    let read = Frecency::from_text(&paths.to_text());
+    assert_eq!(read.top(1), vec!["/home/me/my notes.txt"]);
+}
@@ -0,0 +1,263 @@
+//! Fuzzy matching, as in pickers
+//!
+//! The chars of the pattern have to be found in the text in order, not
+//! necessarily side by side, and the best of the ways to find them is
+//! scored like fzf does: every char found scores, a gap between two
+//! costs, most at its start, and a char found at the start of a word,
+//! after a path separator or at a hump of camelCase scores a bonus, as
+//! do the chars following it in a run. The first char of the pattern
+//! counts its bonus twice, for `fb` to go to `foo_bar` rather than
+//! `afab`.
+//!
+//! A pattern all in lowercase finds either case, one with an uppercase
+//! char only the case written.
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+
+/// After whitespace, or at the start of the text
+const BONUS_BOUNDARY_WHITE: i64 = SCORE_MATCH / 2 + 2;
+/// After a path separator, or a delimiter like `:`
+const BONUS_BOUNDARY_DELIMITER: i64 = SCORE_MATCH / 2 + 1;
+/// After another non-word char, like `_`
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+/// A non-word char itself
+const BONUS_NON_WORD: i64 = SCORE_MATCH / 2;
+/// An uppercase char after a lowercase one, or a digit after a letter
+const BONUS_CAMEL: i64 = BONUS_BOUNDARY - 1;
+/// At least, in a run of chars found side by side
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Text longer than that, in chars, is only matched greedily, rather
+/// than looked through for the best way
+const MAX_TEXT: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better
+    pub score: i64,
+    /// Indices, in chars, of the chars of the text found
+    pub indices: Vec<usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Class {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Number,
+}
+
+fn class(ch: char) -> Class {
+    match ch {
+        _ if ch.is_whitespace() => Class::White,
+        '/' | '\\' | ':' | ';' | ',' | '|' => Class::Delimiter,
+        _ if ch.is_lowercase() => Class::Lower,
+        _ if ch.is_uppercase() => Class::Upper,
+        _ if ch.is_numeric() => Class::Number,
+        _ if ch.is_alphabetic() => Class::Lower,
+        _ => Class::NonWord,
+    }
+}
+
+/// Bonus of a char of class `cur` after one of class `prev`
+fn bonus(prev: Class, cur: Class) -> i64 {
+    use Class::*;
+    match (prev, cur) {
+        (White, _) if cur != White => BONUS_BOUNDARY_WHITE,
+        (Delimiter, Lower | Upper | Number) => BONUS_BOUNDARY_DELIMITER,
+        (NonWord, Lower | Upper | Number) => BONUS_BOUNDARY,
+        (Lower, Upper) | (Lower | Upper, Number) => BONUS_CAMEL,
+        (_, White) => BONUS_BOUNDARY_WHITE,
+        (_, Delimiter | NonWord) => BONUS_NON_WORD,
+        _ => 0,
+    }
+}
+
+/// How well `pattern` matches `text`, and where, if it does at all
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let fold = |ch: char| {
+        if case_sensitive {
+            ch
+        } else {
+            ch.to_lowercase().next().unwrap_or(ch)
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+
+    // chars can't be found earlier than greedily, nor the last one later
+    // than it's last found: look in between only
+    let mut first = Vec::with_capacity(pattern.len());
+    let mut j = 0;
+    for &ch in &pattern {
+        j += folded[j..].iter().position(|&t| t == ch)?;
+        first.push(j);
+        j += 1;
+    }
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            indices: vec![],
+        });
+    }
+
+    let bonuses: Vec<i64> = chars
+        .iter()
+        .scan(Class::White, |prev, &ch| {
+            let cur = class(ch);
+            Some(bonus(std::mem::replace(prev, cur), cur))
+        })
+        .collect();
+    if MAX_TEXT < chars.len() {
+        return Some(score_indices(&bonuses, first));
+    }
+
+    let start = first[0];
+    let end = folded
+        .iter()
+        .rposition(|&t| t == pattern[pattern.len() - 1])?
+        + 1;
+    let width = end - start;
+
+    // best score with the char `i` of the pattern found at `start + j`,
+    // the bonus of the run it ends, and where the char before was found
+    let none = i64::MIN / 2;
+    let mut scores = vec![none; pattern.len() * width];
+    let mut runs = vec![0; pattern.len() * width];
+    let mut from = vec![usize::MAX; pattern.len() * width];
+    for (i, &ch) in pattern.iter().enumerate() {
+        let row = i * width;
+        // best score of the char before, found by `j - 1` with a gap
+        let mut gap: Option<(i64, usize)> = None;
+        for j in 0..width {
+            let t = start + j;
+            if 0 < i && 2 <= j {
+                let k = j - 2;
+                let opened = scores[row - width + k] + SCORE_GAP_START;
+                gap = match gap {
+                    Some((score, at)) if opened <= score + SCORE_GAP_EXTENSION => {
+                        Some((score + SCORE_GAP_EXTENSION, at))
+                    }
+                    _ if scores[row - width + k] == none => {
+                        gap.map(|(score, at)| (score + SCORE_GAP_EXTENSION, at))
+                    }
+                    _ => Some((opened, k)),
+                };
+            }
+            if folded[t] != ch {
+                continue;
+            }
+            if i == 0 {
+                scores[row + j] = SCORE_MATCH + bonuses[t] * BONUS_FIRST_CHAR_MULTIPLIER;
+                runs[row + j] = bonuses[t];
+                continue;
+            }
+            if let Some((score, at)) = gap {
+                scores[row + j] = score + SCORE_MATCH + bonuses[t];
+                runs[row + j] = bonuses[t];
+                from[row + j] = at;
+            }
+            if 0 < j && scores[row - width + j - 1] != none {
+                let run = bonuses[t]
+                    .max(runs[row - width + j - 1])
+                    .max(BONUS_CONSECUTIVE);
+                let score = scores[row - width + j - 1] + SCORE_MATCH + run;
+                if scores[row + j] <= score {
+                    scores[row + j] = score;
+                    runs[row + j] = run;
+                    from[row + j] = j - 1;
+                }
+            }
+        }
+    }
+
+    let row = (pattern.len() - 1) * width;
+    let (mut j, &score) = scores[row..]
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|&(_, score)| *score)?;
+    let mut indices = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        indices[i] = start + j;
+        j = from[i * width + j];
+    }
+    Some(Match { score, indices })
+}
+
+/// The score of finding the chars at `indices`, as `fuzzy_match` would
+fn score_indices(bonuses: &[i64], indices: Vec<usize>) -> Match {
+    let mut score = 0;
+    let mut run = 0;
+    for (n, &t) in indices.iter().enumerate() {
+        if n == 0 {
+            score += SCORE_MATCH + bonuses[t] * BONUS_FIRST_CHAR_MULTIPLIER;
+            run = bonuses[t];
+        } else if indices[n - 1] + 1 == t {
+            run = bonuses[t].max(run).max(BONUS_CONSECUTIVE);
+            score += SCORE_MATCH + run;
+        } else {
+            let gap = (t - indices[n - 1] - 1) as i64;
+            score += SCORE_GAP_START + SCORE_GAP_EXTENSION * (gap - 1) + SCORE_MATCH + bonuses[t];
+            run = bonuses[t];
+        }
+    }
+    Match { score, indices }
+}
+
+/// The items of `items` whose text `pattern` matches, best first, with
+/// the match; items as good as one another stay in order
+pub fn filter<T>(
+    pattern: &str,
+    items: impl IntoIterator<Item = T>,
+    text: impl Fn(&T) -> String,
+) -> Vec<(T, Match)> {
+    let mut matches: Vec<(T, Match)> = items
+        .into_iter()
+        .filter_map(|item| {
+            let found = fuzzy_match(pattern, &text(&item))?;
+            Some((item, found))
+        })
+        .collect();
+    matches.sort_by_key(|(_, found)| std::cmp::Reverse(found.score));
+    matches
+}
+
+#[test]
+fn fuzzy_match_test() {
+    let indices = |pattern, text| fuzzy_match(pattern, text).map(|found| found.indices);
+    assert_eq!(indices("", "abc"), Some(vec![]));
+    assert_eq!(indices("abc", "ab"), None);
+    assert_eq!(indices("ba", "ab"), None);
+    // word starts rather than the first chars found
+    assert_eq!(indices("fb", "afab foo_bar"), Some(vec![5, 9]));
+    assert_eq!(indices("mr", "src/mode/render.rs"), Some(vec![4, 9]));
+    assert_eq!(indices("gd", "GotoDefinition"), Some(vec![0, 4]));
+    // runs rather than scattered chars
+    assert_eq!(indices("abc", "a_b_c abc"), Some(vec![6, 7, 8]));
+    // smart case
+    assert_eq!(indices("A", "ab"), None);
+    assert_eq!(indices("a", "bA"), Some(vec![1]));
+
+    let score = |pattern, text| fuzzy_match(pattern, text).unwrap().score;
+    assert!(score("rs", "src/render.rs") > score("rs", "src/cursor.txt"));
+    assert!(score("main", "src/main.rs") > score("main", "src/domain.rs"));
+    assert!(score("view", "view.rs") > score("view", "preview.rs"));
+
+    // long text matches greedily, scored the same way
+    let long = format!("{}x_y", "a".repeat(MAX_TEXT));
+    assert_eq!(indices("xy", &long), Some(vec![MAX_TEXT, MAX_TEXT + 2]));
+
+    let found = filter("ab", vec!["xaxb", "ab", "nope", "a_b"], |text| {
+        text.to_string()
+    });
+    let texts: Vec<&str> = found.iter().map(|(text, _)| *text).collect();
+    assert_eq!(texts, vec!["ab", "a_b", "xaxb"]);
+}
@@ -0,0 +1,337 @@
+//! Git integration
+//!
+//! The editor doesn't access the repository itself: it sends `GitRequest`s
+//! to the handler registered by the frontend, and parses the
+//! machine-readable output that comes back.
+//!
+//! The gutter next to the line numbers marks the lines changed since
+//! `HEAD`: the file as it is there comes in through a `GitBaseRequest`,
+//! and the buffer is diffed against it again when it changed, while the
+//! input is idle.
+use crate::buffer::Buffer;
+use crate::diff;
+use ropey::Rope;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitRequest {
+    /// `git status --porcelain=v1 -z` output
+    Status,
+    Stage(PathBuf),
+    Unstage(PathBuf),
+    /// Diff of the staged or unstaged changes, of one file or all of them
+    Diff {
+        staged: bool,
+        path: Option<PathBuf>,
+    },
+    /// Full message of `HEAD`
+    LastCommitMessage,
+    Commit {
+        message: String,
+        amend: bool,
+    },
+    /// Commits that changed the file, newest first, one
+    /// `hash\0short hash\0date\0subject` line each
+    Log(PathBuf),
+    /// Content of the file at a revision
+    Show {
+        rev: String,
+        path: PathBuf,
+    },
+}
+
+/// The content of a file in `HEAD`, for the gutter
+///
+/// Answered through `State::deliver_git_base`, with nothing if the file
+/// is not in `HEAD`, or not in a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitBaseRequest {
+    pub id: u64,
+    pub path: PathBuf,
+}
+
+/// How a line of a buffer differs from the file in `HEAD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Lines were removed after it
+    Removed,
+    /// Lines were removed before it, the first one
+    RemovedAbove,
+}
+
+impl LineChange {
+    /// What marks it in the gutter
+    pub fn sign(self) -> char {
+        match self {
+            LineChange::Added | LineChange::Modified => '▎',
+            LineChange::Removed => '▁',
+            LineChange::RemovedAbove => '▔',
+        }
+    }
+}
+
+/// The lines of `text` changed from `base`, in order
+pub fn line_changes(base: &Rope, text: &Rope) -> Vec<(usize, LineChange)> {
+    let mut changes: Vec<(usize, LineChange)> = vec![];
+    for hunk in diff::diff_ropes(base, text) {
+        if hunk.new.is_empty() {
+            let change = match hunk.new.start.checked_sub(1) {
+                Some(line) => (line, LineChange::Removed),
+                None => (0, LineChange::RemovedAbove),
+            };
+            // a line added or changed says more
+            if changes.last().map(|&(line, _)| line) != Some(change.0) {
+                changes.push(change);
+            }
+            continue;
+        }
+        let change = if hunk.old.is_empty() {
+            LineChange::Added
+        } else {
+            LineChange::Modified
+        };
+        changes.extend(hunk.new.map(|line| (line, change)));
+    }
+    changes
+}
+
+/// The lines of a buffer changed since `HEAD`
+#[derive(Debug, Clone, Default)]
+pub struct Gutter {
+    /// Of the pending `GitBaseRequest`
+    pending_id: Option<u64>,
+    /// The file in `HEAD`, once known and if there is one
+    base: Option<Rope>,
+    /// Version of the buffer `changes` are of
+    version: Option<u64>,
+    changes: Vec<(usize, LineChange)>,
+}
+
+impl Gutter {
+    pub fn start(&mut self, id: u64) {
+        self.pending_id = Some(id);
+    }
+
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending_id
+    }
+
+    /// Take the file in `HEAD` asked for with `id`, and diff `buffer`
+    /// against it; `false` if it was not the one pending
+    pub fn deliver(&mut self, id: u64, base: Option<Rope>, buffer: &Buffer) -> bool {
+        if self.pending_id != Some(id) {
+            return false;
+        }
+        self.pending_id = None;
+        self.base = base;
+        self.version = None;
+        self.update(buffer);
+        true
+    }
+
+    /// Diff `buffer` again if it changed; `false` if it did not
+    pub fn update(&mut self, buffer: &Buffer) -> bool {
+        if self.version == Some(buffer.version()) {
+            return false;
+        }
+        self.version = Some(buffer.version());
+        self.changes = match self.base {
+            Some(ref base) => line_changes(base, &buffer.text),
+            None => vec![],
+        };
+        true
+    }
+
+    /// Has it a column, the file being in `HEAD`
+    pub fn is_shown(&self) -> bool {
+        self.base.is_some()
+    }
+
+    pub fn line(&self, line: usize) -> Option<LineChange> {
+        self.changes
+            .binary_search_by_key(&line, |&(line, _)| line)
+            .ok()
+            .map(|i| self.changes[i].1)
+    }
+}
+
+/// A commit in the history of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub hash: String,
+    pub short: String,
+    pub date: String,
+    pub subject: String,
+}
+
+/// A file as it was at a revision, shown read-only
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRevision {
+    pub path: PathBuf,
+    pub revision: Revision,
+    /// Buffer with the working copy of the file
+    pub source_buffer_i: usize,
+}
+
+/// Parse the output of `GitRequest::Log`
+pub fn parse_log(out: &str) -> Vec<Revision> {
+    out.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, '\0');
+            Some(Revision {
+                hash: parts.next()?.to_owned(),
+                short: parts.next()?.to_owned(),
+                date: parts.next()?.to_owned(),
+                subject: parts.next()?.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Status of a file, as in `git status --short`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatus {
+    pub path: PathBuf,
+    /// Status in the index, eg. `M`, `A` or ` `
+    pub staged: char,
+    /// Status in the worktree
+    pub unstaged: char,
+}
+
+impl FileStatus {
+    pub fn is_untracked(&self) -> bool {
+        self.staged == '?'
+    }
+
+    pub fn is_staged(&self) -> bool {
+        !self.is_untracked() && self.staged != ' '
+    }
+
+    pub fn is_unstaged(&self) -> bool {
+        self.is_untracked() || self.unstaged != ' '
+    }
+}
+
+/// Parse `git status --porcelain=v1 -z` output
+pub fn parse_status(porcelain: &str) -> Vec<FileStatus> {
+    let mut statuses = vec![];
+    let mut entries = porcelain.split('\0');
+    while let Some(entry) = entries.next() {
+        let mut chars = entry.chars();
+        let (staged, unstaged) = match (chars.next(), chars.next(), chars.next()) {
+            (Some(staged), Some(unstaged), Some(' ')) => (staged, unstaged),
+            _ => continue,
+        };
+        if staged == 'R' || staged == 'C' {
+            // followed by the original path
+            entries.next();
+        }
+        statuses.push(FileStatus {
+            path: PathBuf::from(chars.as_str()),
+            staged,
+            unstaged,
+        });
+    }
+    statuses
+}
+
+/// A commit message being written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitMessage {
+    pub amend: bool,
+    /// Changes that will be committed, shown next to the message
+    pub diff: String,
+}
+
+impl CommitMessage {
+    /// Initial content of the message buffer
+    pub fn template(&self, message: &str) -> String {
+        format!(
+            "{}\n# {}; lines starting with # are ignored.\n# :w to commit, :db to abort\n",
+            message.trim_end(),
+            if self.amend {
+                "Amending the last commit"
+            } else {
+                "Write the commit message"
+            }
+        )
+    }
+}
+
+/// The message without comments and surrounding blank lines
+pub fn clean_message(text: &str) -> String {
+    let lines: Vec<_> = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::trim_end)
+        .collect();
+    lines.join("\n").trim().to_owned()
+}
+
+#[test]
+fn line_changes_test() {
+    let base = Rope::from_str("a\nb\nc\nd\ne\n");
+    let changes = |text: &str| line_changes(&base, &Rope::from_str(text));
+    assert_eq!(changes("a\nb\nc\nd\ne\n"), vec![]);
+    assert_eq!(
+        changes("a\nnew\nb\nC\nd\n"),
+        vec![
+            (1, LineChange::Added),
+            (3, LineChange::Modified),
+            (4, LineChange::Removed),
+        ]
+    );
+    assert_eq!(changes("b\nc\nd\ne\n"), vec![(0, LineChange::RemovedAbove)]);
+    // removed right after a change
+    assert_eq!(changes("a\nB\nd\ne\n"), vec![(1, LineChange::Modified)]);
+
+    let mut gutter = Gutter::default();
+    let mut buffer = Buffer::from_text(Rope::from_str("a\nb\nc\nd\ne\n"));
+    gutter.start(1);
+    assert!(!gutter.deliver(0, Some(base.clone()), &buffer));
+    assert!(gutter.deliver(1, Some(base.clone()), &buffer));
+    assert!(gutter.is_shown());
+    assert_eq!(gutter.line(0), None);
+    buffer.insert(crate::Idx(0), "x");
+    assert!(gutter.update(&buffer));
+    assert!(!gutter.update(&buffer));
+    assert_eq!(gutter.line(0), Some(LineChange::Modified));
+}
+
+#[test]
+fn parse_status_test() {
+    let statuses = parse_status("M  src/a.rs\0 M b.rs\0R  new.rs\0old.rs\0?? c.rs\0");
+    let summary: Vec<_> = statuses
+        .iter()
+        .map(|s| {
+            (
+                s.path.to_str().unwrap(),
+                s.is_staged(),
+                s.is_unstaged(),
+                s.is_untracked(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("src/a.rs", true, false, false),
+            ("b.rs", false, true, false),
+            ("new.rs", true, false, false),
+            ("c.rs", false, true, true),
+        ]
+    );
+}
+
+#[test]
+fn commit_message_test() {
+    let commit = CommitMessage {
+        amend: false,
+        diff: String::new(),
+    };
+    let text = format!("\nFix things  \n\nDetails{}", commit.template(""));
+    assert_eq!(clean_message(&text), "Fix things\n\nDetails");
+    assert_eq!(clean_message(&commit.template("")), "");
+}
@@ -0,0 +1,98 @@
+//! Searching the files of the project in the background
+//!
+//! `:grep <pattern>` opens a quickfix buffer for the results right away,
+//! and asks the frontend to search with a `GrepRequest`. The frontend
+//! passes what it finds to `State::deliver_grep` in batches as it goes,
+//! which are added at the end of the buffer, the last batch once the
+//! search is done. The buffer can't be edited until then; it's a quickfix
+//! list like any other after that, and Enter on one of its lines opens
+//! the file at the line. A new search, or closing the buffer, cancels the
+//! one running.
+use crate::preview::CancelToken;
+use crate::progress::TaskId;
+use crate::quickfix::Entry;
+use regex::Regex;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct GrepRequest {
+    pub id: u64,
+    /// A regex, valid
+    pub pattern: String,
+    pub cancel: CancelToken,
+}
+
+/// A `GrepRequest` in flight, and the buffer it fills
+#[derive(Debug, Clone)]
+pub(crate) struct PendingGrep {
+    pub id: u64,
+    pub pattern: String,
+    pub buffer_i: usize,
+    pub cancel: CancelToken,
+    pub task: TaskId,
+}
+
+/// The lines of `content`, the text of the file at `path`, that `re`
+/// matches, with the first match of each
+pub fn matches(re: &Regex, path: &Path, content: &str) -> Vec<Entry> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let matched = re.find(text)?;
+            Some(Entry {
+                path: path.to_owned(),
+                line,
+                text: text.to_owned(),
+                matched: Some(matched.start()..matched.end()),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn grep_test() {
+    use crate::testing;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let re = Regex::new("b+").unwrap();
+    let entries = matches(&re, Path::new("a.txt"), "abba\nccc\nb\n");
+    assert_eq!(
+        entries
+            .iter()
+            .map(|e| (e.line, e.matched.clone()))
+            .collect::<Vec<_>>(),
+        vec![(0, Some(1..3)), (2, Some(0..1))]
+    );
+
+    let mut state = testing::state_with_text("");
+    let requests = Rc::new(RefCell::new(vec![]));
+    let sent = requests.clone();
+    state.register_project_search_handler(move |request| {
+        sent.borrow_mut().push(request);
+        Ok(())
+    });
+    state.grep("(");
+    assert!(requests.borrow().is_empty());
+    state.grep("b+");
+    let id = requests.borrow()[0].id;
+    assert!(!state.cur_document().is_modifiable());
+    assert!(state.deliver_grep(id, entries[..1].to_vec(), false));
+    assert!(state.deliver_grep(id, entries[1..].to_vec(), true));
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "a.txt:1:abba\na.txt:3:b\n"
+    );
+    assert!(state.cur_document().is_modifiable());
+
+    // the search before is cancelled, and what it finds dropped
+    state.grep("c");
+    state.grep("a");
+    let (cancelled, id) = {
+        let requests = requests.borrow();
+        (requests[1].cancel.is_cancelled(), requests[1].id)
+    };
+    assert!(cancelled);
+    assert!(!state.deliver_grep(id, entries, true));
+}
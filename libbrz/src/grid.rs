@@ -0,0 +1,263 @@
+//! A double-buffered grid of cells behind the `Renderer` trait
+//!
+//! A frame is drawn into the grid, then `finish_frame` tells the runs of
+//! cells that differ from the last frame, so that the frontend only
+//! writes those to the terminal. What's left as it was costs nothing,
+//! whatever happened to the state in between.
+use crate::render::{Cell, ColorMap, Coord, Rect, Renderer, Style};
+
+/// Unchanged cells between two changed ones, at most, to write them
+/// again rather than move over them: a move takes about as many bytes
+const MAX_GAP: usize = 4;
+
+/// Cells side by side on a row, from `coord` on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub coord: Coord,
+    pub cells: Vec<Cell>,
+}
+
+/// What changed in a frame
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// The screen is to be cleared first, and `runs` are what's not blank
+    pub clear: bool,
+    pub runs: Vec<Run>,
+    pub cursor: Option<Coord>,
+}
+
+pub struct DiffRenderer {
+    color_map: ColorMap,
+    dimensions: Coord,
+    cur: Vec<Cell>,
+    /// Empty when the last frame is not on the screen anymore
+    prev: Vec<Cell>,
+    cursor: Option<Coord>,
+}
+
+impl DiffRenderer {
+    pub fn new(color_map: ColorMap) -> Self {
+        Self {
+            color_map,
+            dimensions: Coord { x: 0, y: 0 },
+            cur: vec![],
+            prev: vec![],
+            cursor: None,
+        }
+    }
+
+    /// Draw the next frame with `dimensions`; other ones than before draw
+    /// everything again
+    pub fn resize(&mut self, dimensions: Coord) {
+        if self.dimensions != dimensions {
+            self.dimensions = dimensions;
+            self.cur = vec![Cell::default(); dimensions.x * dimensions.y];
+            self.invalidate();
+        }
+    }
+
+    /// Forget the last frame, eg. when something else drew over the screen
+    pub fn invalidate(&mut self) {
+        self.prev.clear();
+        self.cursor = None;
+    }
+
+    fn index(&self, coord: Coord) -> usize {
+        coord.x + coord.y * self.dimensions.x
+    }
+
+    /// What changed since the last frame, which the one drawn becomes
+    pub fn finish_frame(&mut self) -> Frame {
+        fix_wide(&mut self.cur, self.dimensions.x);
+        let clear = self.prev.is_empty();
+        if clear {
+            self.prev = vec![Cell::default(); self.cur.len()];
+        }
+        let mut runs: Vec<Run> = vec![];
+        let width = self.dimensions.x.max(1);
+        for (row_i, (cur, prev)) in self
+            .cur
+            .chunks(width)
+            .zip(self.prev.chunks(width))
+            .enumerate()
+        {
+            // changed cells of the row, and where the run they go on ended
+            let mut run_end: Option<usize> = None;
+            for (x, (new, old)) in cur.iter().zip(prev).enumerate() {
+                if new == old {
+                    continue;
+                }
+                match run_end {
+                    Some(end) if x - end <= MAX_GAP => {
+                        let run = runs.last_mut().expect("run started");
+                        run.cells.extend_from_slice(&cur[end..=x]);
+                    }
+                    _ => runs.push(Run {
+                        coord: Coord { x, y: row_i },
+                        cells: vec![*new],
+                    }),
+                }
+                run_end = Some(x + 1);
+            }
+        }
+        std::mem::swap(&mut self.prev, &mut self.cur);
+        self.cur.iter_mut().for_each(|cell| *cell = Cell::default());
+        Frame {
+            clear,
+            runs,
+            cursor: self.cursor,
+        }
+    }
+}
+
+/// Blank the halves of wide chars that lost the other one, drawn over or
+/// cut at the edge, so that a wide char always comes with its
+/// continuation, in its style
+pub(crate) fn fix_wide(cells: &mut [Cell], width: usize) {
+    for row in cells.chunks_mut(width.max(1)) {
+        for x in 0..row.len() {
+            if row[x].wide {
+                match row.get(x + 1) {
+                    Some(next) if next.is_continuation() => row[x + 1].style = row[x].style,
+                    _ => row[x] = Cell::new(' ', row[x].style),
+                }
+            } else if row[x].is_continuation() && (x == 0 || !row[x - 1].wide) {
+                row[x] = Cell::new(' ', row[x].style);
+            }
+        }
+    }
+}
+
+impl Renderer for DiffRenderer {
+    fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    fn dimensions(&self) -> Coord {
+        self.dimensions
+    }
+
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        if coord.x < self.dimensions.x && coord.y < self.dimensions.y {
+            let i = self.index(coord);
+            self.cur[i] = Cell::new(ch, style);
+        }
+    }
+
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        if coord.x < self.dimensions.x && coord.y < self.dimensions.y {
+            let i = self.index(coord);
+            self.cur[i] = cell;
+        }
+    }
+
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.cursor = coord;
+    }
+
+    fn keep(&mut self, rect: Rect) -> bool {
+        if self.prev.len() != self.cur.len()
+            || self.dimensions.x < rect.offset.x + rect.dimensions.x
+            || self.dimensions.y < rect.offset.y + rect.dimensions.y
+        {
+            return false;
+        }
+        for y in rect.offset.y..rect.offset.y + rect.dimensions.y {
+            let start = self.index(Coord {
+                x: rect.offset.x,
+                y,
+            });
+            let row = start..start + rect.dimensions.x;
+            self.cur[row.clone()].copy_from_slice(&self.prev[row]);
+        }
+        true
+    }
+}
+
+#[test]
+fn finish_frame_test() {
+    let mut grid = DiffRenderer::new(ColorMap::default());
+    grid.resize(Coord { x: 20, y: 2 });
+    grid.print(Coord { x: 0, y: 0 }, "hello", Style::default());
+    let frame = grid.finish_frame();
+    assert!(frame.clear);
+    assert_eq!(frame.runs.len(), 1);
+    assert_eq!(frame.runs[0].cells.len(), 5);
+
+    // the same frame again changes nothing
+    grid.print(Coord { x: 0, y: 0 }, "hello", Style::default());
+    assert_eq!(grid.finish_frame(), Frame::default());
+
+    // close changes go in one run, far ones in their own
+    grid.print(Coord { x: 0, y: 0 }, "jello", Style::default());
+    grid.print(Coord { x: 3, y: 0 }, "p", Style::default());
+    grid.print(Coord { x: 15, y: 0 }, "!", Style::default());
+    grid.print(Coord { x: 1, y: 1 }, "x", Style::default());
+    grid.set_cursor(Some(Coord { x: 1, y: 1 }));
+    let frame = grid.finish_frame();
+    let runs: Vec<(usize, usize, String)> = frame
+        .runs
+        .iter()
+        .map(|run| {
+            let text = run.cells.iter().map(|cell| cell.ch).collect();
+            (run.coord.x, run.coord.y, text)
+        })
+        .collect();
+    assert_eq!(
+        runs,
+        vec![
+            (0, 0, "jelp".to_owned()),
+            (15, 0, "!".to_owned()),
+            (1, 1, "x".to_owned())
+        ]
+    );
+    assert!(!frame.clear);
+    assert_eq!(frame.cursor, Some(Coord { x: 1, y: 1 }));
+
+    // what's kept is as before, and what's not drawn is cleared
+    assert!(grid.keep(Rect {
+        offset: Coord { x: 0, y: 1 },
+        dimensions: Coord { x: 20, y: 1 },
+    }));
+    let frame = grid.finish_frame();
+    assert_eq!(frame.runs.len(), 2);
+    assert!(frame
+        .runs
+        .iter()
+        .all(|run| run.coord.y == 0 && run.cells.iter().all(|cell| cell.ch == ' ')));
+
+    grid.resize(Coord { x: 10, y: 2 });
+    assert!(!grid.keep(grid.dimensions_rect()));
+    assert!(grid.finish_frame().clear);
+
+    // wide chars come with their continuation, or not at all
+    grid.print(Coord { x: 0, y: 0 }, "a中b", Style::default());
+    grid.print(Coord { x: 8, y: 1 }, "x中", Style::default());
+    grid.put_cell(
+        Coord { x: 9, y: 1 },
+        Cell::grapheme("中", Style::default()).0,
+    );
+    grid.put(Coord { x: 0, y: 1 }, '中', Style::default());
+    grid.put_cell(Coord { x: 1, y: 1 }, Cell::continuation(Style::default()));
+    grid.put(Coord { x: 0, y: 1 }, 'y', Style::default());
+    let frame = grid.finish_frame();
+    let rows: Vec<(usize, String)> = frame
+        .runs
+        .iter()
+        .map(|run| {
+            (
+                run.coord.y,
+                run.cells.iter().flat_map(Cell::chars).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            (0, "a中b".to_owned()),
+            (1, "y".to_owned()),
+            (1, "x".to_owned())
+        ]
+    );
+    assert!(frame.runs[0].cells[2].is_continuation());
+}
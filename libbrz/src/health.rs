@@ -0,0 +1,202 @@
+//! Checks of what the editor depends on, for `:health`
+//!
+//! `:health` opens a report of checks in sections. The editor checks what
+//! it knows itself: what the terminal can show, the config files that
+//! didn't parse, and the languages of the buffers it can't highlight.
+//! Each subsystem of the frontend registers checks of its own with
+//! `State::register_health_check`, eg. of the clipboard or of the language
+//! servers on `PATH`; those of a section named like one of the editor add
+//! to it. A finding tells what's fine as well as what's not, and what to
+//! do about the latter.
+use crate::term::Capabilities;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Ok,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Ok => "ok",
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// What a check found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub level: Level,
+    pub text: String,
+    /// What to do about it
+    pub advice: Option<String>,
+}
+
+impl Finding {
+    pub fn ok(text: impl Into<String>) -> Self {
+        Self {
+            level: Level::Ok,
+            text: text.into(),
+            advice: None,
+        }
+    }
+
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            level: Level::Info,
+            ..Self::ok(text)
+        }
+    }
+
+    pub fn warning(text: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warning,
+            text: text.into(),
+            advice: Some(advice.into()),
+        }
+    }
+
+    pub fn error(text: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self {
+            level: Level::Error,
+            ..Self::warning(text, advice)
+        }
+    }
+}
+
+/// Findings of the checks of a section, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub findings: Vec<Finding>,
+}
+
+/// What the terminal can show, as `Capabilities::detect` guessed it
+pub fn terminal(caps: Capabilities) -> Vec<Finding> {
+    let lacking = |what: &str| {
+        Finding::warning(
+            format!("no {}: drawn without", what),
+            "set TERM to the terminal the editor runs in, eg. xterm-256color",
+        )
+    };
+    let mut findings = vec![if caps.colors {
+        Finding::ok("colors")
+    } else {
+        Finding::warning(
+            "no colors: selections are in reverse video",
+            "unset NO_COLOR, or set TERM to a terminal with colors",
+        )
+    }];
+    if !caps.italic {
+        findings.push(lacking("italics"));
+    }
+    if !caps.underline {
+        findings.push(lacking("underlines"));
+    }
+    findings.push(if caps.unicode {
+        Finding::ok("unicode")
+    } else {
+        Finding::warning(
+            "no unicode: drawn as ASCII",
+            "set LANG to a UTF-8 locale, eg. en_US.UTF-8",
+        )
+    });
+    findings
+}
+
+/// The config files that didn't parse, by what they told
+pub fn config(errors: &[String]) -> Vec<Finding> {
+    if errors.is_empty() {
+        return vec![Finding::ok("the config files parsed")];
+    }
+    errors
+        .iter()
+        .map(|e| Finding::error(e.clone(), "fix the file, then start again"))
+        .collect()
+}
+
+/// The `languages` of the buffers, with whether they're highlighted
+pub fn languages<'l>(languages: impl IntoIterator<Item = (&'l str, bool)>) -> Vec<Finding> {
+    let mut languages: Vec<(&str, bool)> = languages.into_iter().collect();
+    languages.sort_unstable();
+    languages.dedup();
+    if languages.is_empty() {
+        return vec![Finding::info("no buffer of a known language")];
+    }
+    languages
+        .into_iter()
+        .map(|(language, known)| {
+            if known {
+                Finding::ok(format!("{} is highlighted", language))
+            } else {
+                Finding::warning(
+                    format!("{} has no highlighting rules", language),
+                    "its buffers are shown in plain text",
+                )
+            }
+        })
+        .collect()
+}
+
+/// The text of the report, a summary then a line per finding, indented
+/// under its section, with its advice after
+pub fn report_text(sections: &[Section]) -> String {
+    let findings = || sections.iter().flat_map(|section| &section.findings);
+    let count = |level| findings().filter(|f| f.level == level).count();
+    let mut out = format!(
+        "health: errors {}, warnings {}\n",
+        count(Level::Error),
+        count(Level::Warning)
+    );
+    for section in sections {
+        let _ = writeln!(out, "\n{}", section.name);
+        for finding in &section.findings {
+            let _ = writeln!(out, "  {:<8} {}", finding.level.name(), finding.text);
+            if let Some(advice) = &finding.advice {
+                let _ = writeln!(out, "  {:<8} - {}", "", advice);
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn health_test() {
+    let findings = terminal(Capabilities::detect(Some("screen"), false, Some("C")));
+    let levels: Vec<Level> = findings.iter().map(|f| f.level).collect();
+    assert_eq!(levels, [Level::Ok, Level::Warning, Level::Warning]);
+    assert_eq!(findings[1].text, "no italics: drawn without");
+
+    let findings = languages(vec![("rust", true), ("cobol", false), ("rust", true)]);
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].text, "cobol has no highlighting rules");
+
+    let sections = vec![
+        Section {
+            name: "config".into(),
+            findings: config(&["keys: line 2: no key".into()]),
+        },
+        Section {
+            name: "clipboard".into(),
+            findings: vec![Finding::ok("xclip")],
+        },
+    ];
+    assert_eq!(
+        report_text(&sections),
+        "health: errors 1, warnings 0\n\
+         \n\
+         config\n\
+        \x20 error    keys: line 2: no key\n\
+        \x20          - fix the file, then start again\n\
+         \n\
+         clipboard\n\
+        \x20 ok       xclip\n"
+    );
+}
@@ -0,0 +1,182 @@
+//! Call and type hierarchies
+//!
+//! A hierarchy starts from the item under the cursor, eg. a function,
+//! and is shown as a tree: each item can be expanded into the functions
+//! calling it (or that it calls), or into its supertypes (or subtypes).
+//! Children are asked for only when an item is first expanded.
+use crate::lsp::Position;
+use std::path::PathBuf;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HierarchyKind {
+    IncomingCalls,
+    OutgoingCalls,
+    Supertypes,
+    Subtypes,
+}
+
+impl HierarchyKind {
+    pub fn title(self) -> &'static str {
+        match self {
+            HierarchyKind::IncomingCalls => "incoming calls",
+            HierarchyKind::OutgoingCalls => "outgoing calls",
+            HierarchyKind::Supertypes => "supertypes",
+            HierarchyKind::Subtypes => "subtypes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HierarchyItem {
+    pub name: String,
+    /// Eg. the signature of a function, or the module it's in
+    pub detail: String,
+    pub path: PathBuf,
+    pub position: Position,
+}
+
+/// What the hierarchy handler is asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HierarchyRequest {
+    /// The items at a position of a file, to start from
+    Prepare {
+        kind: HierarchyKind,
+        path: PathBuf,
+        position: Position,
+    },
+    /// The children of an item
+    Children {
+        kind: HierarchyKind,
+        item: HierarchyItem,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub item: HierarchyItem,
+    pub depth: usize,
+    pub expanded: bool,
+    /// Children, once asked for; kept when collapsed
+    children: Option<Vec<HierarchyItem>>,
+}
+
+/// The tree, as the list of the nodes that are visible
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub nodes: Vec<Node>,
+}
+
+impl Tree {
+    pub fn new(roots: Vec<HierarchyItem>) -> Self {
+        Self {
+            nodes: roots
+                .into_iter()
+                .map(|item| Node {
+                    item,
+                    depth: 0,
+                    expanded: false,
+                    children: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Does the node `i` need its children before it can be expanded
+    pub fn needs_children(&self, i: usize) -> bool {
+        self.nodes
+            .get(i)
+            .is_some_and(|node| node.children.is_none())
+    }
+
+    /// Expand the node `i`, with its `children` if it didn't have them yet
+    pub fn expand(&mut self, i: usize, children: Option<Vec<HierarchyItem>>) {
+        let node = match self.nodes.get_mut(i) {
+            Some(node) if !node.expanded => node,
+            _ => return,
+        };
+        if let Some(children) = children {
+            node.children.get_or_insert(children);
+        }
+        let depth = node.depth + 1;
+        let children: Vec<_> = match node.children {
+            Some(ref children) => children
+                .iter()
+                .map(|item| Node {
+                    item: item.clone(),
+                    depth,
+                    expanded: false,
+                    children: None,
+                })
+                .collect(),
+            None => return,
+        };
+        node.expanded = true;
+        self.nodes.splice(i + 1..i + 1, children);
+    }
+
+    /// Collapse the node `i`, hiding everything under it
+    pub fn collapse(&mut self, i: usize) {
+        let depth = match self.nodes.get_mut(i) {
+            Some(node) => {
+                node.expanded = false;
+                node.depth
+            }
+            None => return,
+        };
+        let end = i
+            + 1
+            + self.nodes[i + 1..]
+                .iter()
+                .take_while(|node| depth < node.depth)
+                .count();
+        self.nodes.drain(i + 1..end);
+    }
+
+    /// Index of the parent of the node `i`
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        let depth = self.nodes.get(i)?.depth;
+        self.nodes[..i].iter().rposition(|node| node.depth < depth)
+    }
+}
+
+#[test]
+fn tree_test() {
+    let item = |name: &str| HierarchyItem {
+        name: name.into(),
+        detail: String::new(),
+        path: "a.rs".into(),
+        position: Position {
+            line: 0,
+            character: 0,
+        },
+    };
+    let names = |tree: &Tree| -> Vec<(String, usize)> {
+        tree.nodes
+            .iter()
+            .map(|node| (node.item.name.clone(), node.depth))
+            .collect()
+    };
+    let mut tree = Tree::new(vec![item("main"), item("other")]);
+    assert!(tree.needs_children(0));
+    tree.expand(0, Some(vec![item("a"), item("b")]));
+    tree.expand(2, Some(vec![item("c")]));
+    assert_eq!(
+        names(&tree),
+        vec![
+            ("main".into(), 0),
+            ("a".into(), 1),
+            ("b".into(), 1),
+            ("c".into(), 2),
+            ("other".into(), 0)
+        ]
+    );
+    assert_eq!(tree.parent(3), Some(2));
+    assert_eq!(tree.parent(4), None);
+
+    tree.collapse(0);
+    assert_eq!(names(&tree), vec![("main".into(), 0), ("other".into(), 0)]);
+    // children are remembered, but not the state of their own subtrees
+    assert!(!tree.needs_children(0));
+    tree.expand(0, None);
+    assert_eq!(tree.nodes.len(), 4);
+}
@@ -0,0 +1,89 @@
+//! What colors the text of buffers
+//!
+//! A `Highlighter` finds the spans of a text worth coloring, and maps
+//! each to the `Style` it's painted with when the buffer is drawn.
+//! Finding the spans happens away from the keys, as `syntax` describes:
+//! the `HighlightRequest` carries the highlighter for the frontend's
+//! handler to run, and `State` renders what it delivers with the same
+//! one. `State::register_highlighter` replaces the default,
+//! `syntax::RuleHighlighter`, which knows a few languages like Rust,
+//! Python and Markdown by their rules.
+use crate::preview::CancelToken;
+use crate::render::{ColorMap, Style};
+use crate::syntax::Highlight;
+use ropey::Rope;
+
+pub trait Highlighter: Send + Sync {
+    /// Whether it highlights `language`
+    fn is_known(&self, language: &str) -> bool;
+
+    /// Spans of `text`, in order
+    ///
+    /// `None` if cancelled, or if the language is not known. Called from
+    /// other threads: it should check `cancel` now and then.
+    fn highlight(
+        &self,
+        text: &Rope,
+        language: &str,
+        cancel: &CancelToken,
+    ) -> Option<Vec<Highlight>>;
+
+    /// Style of a span, painted over the default one of the text
+    fn style(&self, highlight: &Highlight, colors: &ColorMap) -> Style;
+}
+
+#[test]
+fn highlighter_test() {
+    use crate::render::Color;
+    use crate::syntax::HighlightKind;
+    use crate::testing::{state_with_text, TestRenderer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    struct Digits;
+    impl Highlighter for Digits {
+        fn is_known(&self, language: &str) -> bool {
+            language == "digits"
+        }
+        fn highlight(&self, text: &Rope, _: &str, _: &CancelToken) -> Option<Vec<Highlight>> {
+            let spans = text
+                .chars()
+                .enumerate()
+                .filter(|(_, ch)| ch.is_ascii_digit());
+            let spans = spans.map(|(i, _)| Highlight {
+                range: i..i + 1,
+                kind: HighlightKind::Number,
+            });
+            Some(spans.collect())
+        }
+        fn style(&self, _: &Highlight, _: &ColorMap) -> Style {
+            Style {
+                fg: Some(Color::Ansi(9)),
+                ..Style::default()
+            }
+        }
+    }
+
+    let mut state = state_with_text("ab1\n");
+    state.cur_document_mut().language = Some("digits".into());
+    state.register_highlighter(Arc::new(Digits));
+    let requests = Rc::new(RefCell::new(vec![]));
+    let sent = requests.clone();
+    state.register_highlight_handler(move |request| {
+        sent.borrow_mut().push(request);
+        Ok(())
+    });
+    state.request_highlights();
+    let request = requests.borrow_mut().pop().unwrap();
+    let highlights = request
+        .highlighter
+        .highlight(&request.text, &request.language, &request.cancel)
+        .unwrap();
+    assert!(state.deliver_highlights(request.id, highlights));
+
+    let frame = TestRenderer::draw(&state, 20, 4);
+    let x = frame.row(0).chars().position(|ch| ch == '1').unwrap();
+    assert_eq!(frame.style_at(x, 0).unwrap().fg, Some(Color::Ansi(9)));
+    assert_ne!(frame.style_at(x - 1, 0).unwrap().fg, Some(Color::Ansi(9)));
+}
@@ -1,5 +1,7 @@
 use ropey::Rope;
 
+use crate::motion::CharFind;
+use crate::position::convert;
 use crate::position::*;
 use crate::range::Range;
 use crate::util::char;
@@ -23,18 +25,78 @@ fn char_category(ch: char) -> CharCategory {
     }
 }
 
-fn is_indent_opening_char(ch: char) -> bool {
-    match ch {
-        '{' | '(' | '[' | '<' | '"' | '\'' => true,
-        _ => false,
+/// What the word motions move by
+///
+/// Chars fall in three classes:
+///
+/// | class       | chars                                     |
+/// |-------------|-------------------------------------------|
+/// | word        | letters, digits and `_`                   |
+/// | whitespace  | spaces, tabs, line ends and the like      |
+/// | punctuation | anything else                             |
+///
+/// Moving forward goes over a word and the whitespace after it, up to
+/// the end of the line; moving backward goes over the whitespace before
+/// the cursor and the word before it; moving to the end goes over the
+/// whitespace after the cursor and the word after it. Line ends before
+/// the cursor in the direction of the motion are skipped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WordKind {
+    /// A run of chars of the word or of the punctuation class
+    Word,
+    /// A run of chars other than whitespace, like `foo.bar()`
+    BigWord,
+    /// A part of a word, as in `camelCase` or `snake_case`
+    ///
+    /// `_` separates sub-words, like whitespace, and one starts at an
+    /// uppercase letter after a lowercase one or a digit, and at the last
+    /// of a run of uppercase letters before a lowercase one:
+    /// `parseHTTPResponse_v2` has `parse`, `HTTP`, `Response` and `v2`.
+    SubWord,
+}
+
+impl WordKind {
+    fn category(self, ch: char) -> CharCategory {
+        match (self, char_category(ch)) {
+            (WordKind::BigWord, CharCategory::Punctuation) => CharCategory::Alphanumeric,
+            (WordKind::SubWord, _) if ch == '_' => CharCategory::Whitespace,
+            (_, category) => category,
+        }
+    }
+
+    /// Whether `a` and `b` go in the same run of chars
+    pub(crate) fn same_class(self, a: char, b: char) -> bool {
+        self.category(a) == self.category(b)
+    }
+
+    fn is_blank(self, ch: char) -> bool {
+        ch != '\n' && self.category(ch) == CharCategory::Whitespace
     }
+
+    /// Whether a word starts at `idx` of `text` although the char before
+    /// is of the same class
+    fn starts_within_run(self, idx: usize, text: &Rope) -> bool {
+        if self != WordKind::SubWord || idx == 0 || idx >= text.len_chars() {
+            return false;
+        }
+        let prev = text.char(idx - 1);
+        let cur = text.char(idx);
+        let next = Some(idx + 1)
+            .filter(|&i| i < text.len_chars())
+            .map(|i| text.char(i));
+        cur.is_uppercase()
+            && (prev.is_lowercase()
+                || prev.is_numeric()
+                || prev.is_uppercase() && next.is_some_and(char::is_lowercase))
+    }
+}
+
+fn is_indent_opening_char(ch: char) -> bool {
+    matches!(ch, '{' | '(' | '[' | '<' | '"' | '\'')
 }
 
 fn is_indent_closing_char(ch: char) -> bool {
-    match ch {
-        '}' | ')' | ']' | '>' | '"' | '\'' => true,
-        _ => false,
-    }
+    matches!(ch, '}' | ')' | ']' | '>' | '"' | '\'')
 }
 
 fn matching_char(ch: char) -> char {
@@ -78,6 +140,29 @@ impl Idx {
     pub fn forward(self, text: &Rope) -> Self {
         self.forward_n(1, text)
     }
+
+    /// Move `n` grapheme clusters forward
+    pub fn forward_n_graphemes(self, n: usize, text: &Rope) -> Self {
+        n_steps(self, n, |idx| convert::next_grapheme_boundary(text, idx))
+    }
+
+    /// Move `n` grapheme clusters backward
+    pub fn backward_n_graphemes(self, n: usize, text: &Rope) -> Self {
+        n_steps(self, n, |idx| convert::prev_grapheme_boundary(text, idx))
+    }
+
+    /// The end of the line, before its newline if it has one
+    pub fn line_end(self, text: &Rope) -> Self {
+        let line = text.char_to_line(self.0);
+        let start = text.line_to_char(line);
+        let line = text.line(line);
+        let mut end = line.len_chars();
+        while 0 < end && matches!(line.char(end - 1), '\n' | '\r') {
+            end -= 1;
+        }
+        Idx(start + end)
+    }
+
     pub fn to_position(self, text: &Rope) -> Position {
         Position::from_idx(self, text)
     }
@@ -99,14 +184,27 @@ impl Idx {
     }
 
     pub fn backward_word(self, text: &Rope) -> (Idx, Idx) {
+        self.backward_word_of(WordKind::Word, text)
+    }
+
+    /// The previous word of `kind`, from its end to its start, and the
+    /// whitespace after it
+    pub fn backward_word_of(self, kind: WordKind, text: &Rope) -> (Idx, Idx) {
         let mut cur = self;
 
         cur = cur.backward_while(char::is_newline, text);
-        cur = cur.backward_while(char::is_non_newline_whitespace, text);
+        cur = cur.backward_while(|ch| kind.is_blank(ch), text);
 
         let start = cur;
-        if let Some(start_ch_category) = start.prev_char(text).map(char_category) {
-            cur = cur.backward_while(|ch| char_category(ch) == start_ch_category, text);
+        if let Some(category) = start.prev_char(text).map(|ch| kind.category(ch)) {
+            cur = cur.backward(text);
+            while cur
+                .prev_char(text)
+                .is_some_and(|ch| kind.category(ch) == category)
+                && !kind.starts_within_run(cur.0, text)
+            {
+                cur = cur.backward(text);
+            }
         }
 
         (start, cur)
@@ -256,41 +354,215 @@ impl Idx {
             self = self.forward(text);
         }
     }
-    /// Desired indent when opening a line when on position `self`
+    /// Desired indent when opening a line when on position `self`: the
+    /// one of its line
     ///
-    /// `bool` - increase indent
+    /// `bool` - increase indent, after a bracket left open on the line
     pub fn desired_indent_when_opening_line(&self, text: &Rope) -> (Rope, bool) {
         // TODO: this could finish faster then go to the begining of the buffer (potentially)
-        if let Some(indent_opening) = self.to_after_indent_opening_char(text) {
-            let increase_indent =
-                indent_opening.to_position(text).line == self.to_position(text).line;
-
-            let line_begining = self.backward_to_line_start(text);
-            let indent_end = self.before_first_non_whitespace(text);
-            (
-                line_begining.range_to(indent_end).slice(text).into(),
-                increase_indent,
-            )
-        } else {
-            (Rope::new(), false)
-        }
+        let increase_indent = self
+            .to_after_indent_opening_char(text)
+            .is_some_and(|opening| opening.to_position(text).line == self.to_position(text).line);
+        let line_begining = self.backward_to_line_start(text);
+        let indent_end = self.before_first_non_whitespace(text);
+        (
+            line_begining.range_to(indent_end).slice(text).into(),
+            increase_indent,
+        )
     }
 
     pub fn forward_word(self, text: &Rope) -> (Idx, Idx) {
+        self.forward_word_of(WordKind::Word, text)
+    }
+
+    /// The next word of `kind` and the whitespace after it
+    pub fn forward_word_of(self, kind: WordKind, text: &Rope) -> (Idx, Idx) {
         let mut cur = self;
 
         cur = cur.forward_while(char::is_newline, text);
 
         let start = cur;
-        if let Some(start_ch_category) = start.next_char(text).map(char_category) {
-            cur = cur.forward_while(|ch| char_category(ch) == start_ch_category, text);
-        }
+        cur = cur.forward_run(kind, text);
+        cur = cur.forward_while(|ch| kind.is_blank(ch), text);
 
-        cur = cur.forward_while(char::is_non_newline_whitespace, text);
+        (start, cur)
+    }
+
+    /// The whitespace after, and the next word of `kind` up to its end
+    pub fn forward_word_end_of(self, kind: WordKind, text: &Rope) -> (Idx, Idx) {
+        let mut cur = self;
+
+        cur = cur.forward_while(char::is_newline, text);
+
+        let start = cur;
+        cur = cur.forward_while(|ch| kind.is_blank(ch), text);
+        cur = cur.forward_run(kind, text);
 
         (start, cur)
     }
 
+    /// Past the chars of the class of the next one, up to the start of
+    /// the next word of `kind`
+    fn forward_run(self, kind: WordKind, text: &Rope) -> Idx {
+        let mut cur = self;
+        if let Some(category) = cur.next_char(text).map(|ch| kind.category(ch)) {
+            cur = cur.forward(text);
+            while cur
+                .next_char(text)
+                .is_some_and(|ch| kind.category(ch) == category)
+                && !kind.starts_within_run(cur.0, text)
+            {
+                cur = cur.forward(text);
+            }
+        }
+        cur
+    }
+
+    /// Where a cursor at `self` goes to find the `n`th occurrence of the
+    /// char of `find`, if there's one; past a char, it goes past the marks
+    /// combining with it too
+    pub fn find_char(self, find: CharFind, n: usize, text: &Rope) -> Option<Idx> {
+        let mut cur = self;
+        for _ in 0..n.max(1) {
+            cur = cur.find_char_once(find, text)?;
+        }
+        Some(cur)
+    }
+
+    fn find_char_once(self, find: CharFind, text: &Rope) -> Option<Idx> {
+        let past = |i: usize| convert::next_grapheme_boundary(text, Idx(i));
+        let stop = |i: usize| {
+            if find.till == find.backward {
+                past(i)
+            } else {
+                Idx(i)
+            }
+        };
+        let matches = |&i: &usize| text.char(i) == find.ch && stop(i) != self;
+        let start = self.0.min(text.len_chars());
+        let found = if find.backward {
+            (0..start).rev().find(matches)
+        } else {
+            (start..text.len_chars()).find(matches)
+        };
+        found.map(stop)
+    }
+
+    /// Up to the start of the next paragraph, past the end of the one of
+    /// the cursor, or of the next one from blank lines, and the blank lines
+    /// after it
+    ///
+    /// Paragraphs are separated by blank lines, with nothing but
+    /// whitespace.
+    pub fn forward_paragraph(self, text: &Rope) -> (Idx, Idx) {
+        let lines = text.len_lines();
+        let mut line = text.char_to_line(self.0);
+        while line < lines && is_blank_line(text, line) {
+            line += 1;
+        }
+        while line < lines && !is_blank_line(text, line) {
+            line += 1;
+        }
+        while line < lines && is_blank_line(text, line) {
+            line += 1;
+        }
+        (self, Idx(text.line_to_char(line.min(lines))))
+    }
+
+    /// Back to the start of the paragraph of the cursor, or of the one
+    /// before from its start or from blank lines
+    pub fn backward_paragraph(self, text: &Rope) -> (Idx, Idx) {
+        let mut line = text.char_to_line(self.0);
+        if self.0 == text.line_to_char(line) {
+            if line == 0 {
+                return (self, self);
+            }
+            line -= 1;
+        }
+        while 0 < line && is_blank_line(text, line) {
+            line -= 1;
+        }
+        while 0 < line && !is_blank_line(text, line - 1) {
+            line -= 1;
+        }
+        (self, Idx(text.line_to_char(line)))
+    }
+
+    /// The paragraph around the cursor, or after it from blank lines, and
+    /// the blank lines after it
+    pub fn paragraph_around(self, text: &Rope) -> (Idx, Idx) {
+        let line = text.char_to_line(self.0);
+        let start = if is_blank_line(text, line) {
+            Idx(text.line_to_char(line))
+        } else {
+            Idx(text.line_to_char(line))
+                .forward(text)
+                .backward_paragraph(text)
+                .1
+        };
+        (start, start.forward_paragraph(text).1)
+    }
+
+    /// Up to the start of the next sentence
+    ///
+    /// A sentence ends with `.`, `!` or `?`, more of them and closing
+    /// brackets or quotes, and then whitespace, so that `3.14` or `...`
+    /// within a sentence don't end it; the end of a paragraph ends its
+    /// last sentence too.
+    pub fn forward_sentence(self, text: &Rope) -> (Idx, Idx) {
+        let len = text.len_chars();
+        let mut i = self.0;
+        while i < len {
+            let ch = text.char(i);
+            let end = if is_sentence_end(ch) {
+                let mut j = i + 1;
+                while j < len && (is_sentence_end(text.char(j)) || is_closing(text.char(j))) {
+                    j += 1;
+                }
+                i = j;
+                j == len || text.char(j).is_whitespace()
+            } else {
+                i += 1;
+                ch == '\n' && i < len && is_blank_line(text, text.char_to_line(i))
+            };
+            if end {
+                let start = Idx(i).forward_while(char::is_whitespace, text);
+                if self < start {
+                    return (self, start);
+                }
+            }
+        }
+        (self, Idx(len))
+    }
+
+    /// Back to the start of the sentence of the cursor, or of the one
+    /// before from its start
+    pub fn backward_sentence(self, text: &Rope) -> (Idx, Idx) {
+        let mut from = self;
+        loop {
+            from = from.backward_paragraph(text).1;
+            let mut start = from.forward_while(char::is_whitespace, text);
+            if start < self {
+                loop {
+                    let next = start.forward_sentence(text).1;
+                    if self <= next || next == start {
+                        return (self, start);
+                    }
+                    start = next;
+                }
+            }
+            if from.0 == 0 {
+                return (self, from);
+            }
+        }
+    }
+
+    /// The sentence around the cursor, and the whitespace after it
+    pub fn sentence_around(self, text: &Rope) -> (Idx, Idx) {
+        let start = self.forward(text).backward_sentence(text).1;
+        (start, start.forward_sentence(text).1)
+    }
+
     pub fn backward_while(self, mut f: impl FnMut(char) -> bool, text: &Rope) -> Self {
         let mut cur = self;
         while cur.prev_char(text).map(&mut f).unwrap_or(false) {
@@ -384,3 +656,156 @@ impl From<Idx> for usize {
         idx.0
     }
 }
+
+/// Whether line `line` has nothing but whitespace
+pub(crate) fn is_blank_line(text: &Rope, line: usize) -> bool {
+    text.line(line).chars().all(char::is_whitespace)
+}
+
+fn is_sentence_end(ch: char) -> bool {
+    matches!(ch, '.' | '!' | '?')
+}
+
+fn is_closing(ch: char) -> bool {
+    matches!(ch, ')' | ']' | '"' | '\'' | '’' | '”' | '»')
+}
+
+/// `step` taken `n` times from `idx`, or until it goes nowhere further,
+/// for large counts not to take forever at the ends of the text
+fn n_steps(idx: Idx, n: usize, step: impl Fn(Idx) -> Idx) -> Idx {
+    let mut idx = idx;
+    for _ in 0..n {
+        let next = step(idx);
+        if next == idx {
+            break;
+        }
+        idx = next;
+    }
+    idx
+}
+
+#[test]
+fn word_motions_test() {
+    let text = Rope::from_str("let fooBar = a.b_c(HTTPServer2x);\n  next");
+    // the words moved over, from `at`, in `kind`
+    let words = |kind, at: usize, f: fn(Idx, WordKind, &Rope) -> (Idx, Idx)| {
+        let mut idx = Idx(at);
+        let mut words = vec![];
+        for _ in 0..6 {
+            let (start, end) = f(idx, kind, &text);
+            let (from, to) = (start.min(end), start.max(end));
+            words.push(text.slice(from.0..to.0).to_string());
+            idx = end;
+        }
+        words
+    };
+    assert_eq!(
+        words(WordKind::Word, 0, Idx::forward_word_of),
+        vec!["let ", "fooBar ", "= ", "a", ".", "b_c"]
+    );
+    assert_eq!(
+        words(WordKind::BigWord, 0, Idx::forward_word_of),
+        vec![
+            "let ",
+            "fooBar ",
+            "= ",
+            "a.b_c(HTTPServer2x);",
+            "  ",
+            "next"
+        ]
+    );
+    assert_eq!(
+        words(WordKind::SubWord, 4, Idx::forward_word_of),
+        vec!["foo", "Bar ", "= ", "a", ".", "b_"]
+    );
+    assert_eq!(
+        words(WordKind::SubWord, 19, Idx::forward_word_of)[..3],
+        ["HTTP", "Server2x", ");"]
+    );
+    assert_eq!(
+        words(WordKind::Word, 3, Idx::forward_word_end_of)[..3],
+        [" fooBar", " =", " a"]
+    );
+    assert_eq!(
+        words(WordKind::SubWord, 31, Idx::backward_word_of)[..5],
+        ["Server2x", "HTTP", "(", "c", "b"]
+    );
+    assert_eq!(
+        words(WordKind::BigWord, 40, Idx::backward_word_of)[..3],
+        ["next", "\n", "a.b_c(HTTPServer2x);"]
+    );
+}
+
+#[test]
+fn find_char_test() {
+    let text = Rope::from_str("a(b) é\u{301}(c)\nd(");
+    let find = |ch, till, backward| CharFind { ch, till, backward };
+    let at = |idx: Option<Idx>| idx.map(|idx| idx.0);
+    assert_eq!(
+        at(Idx(0).find_char(find('(', false, false), 1, &text)),
+        Some(2)
+    );
+    assert_eq!(
+        at(Idx(0).find_char(find('(', false, false), 3, &text)),
+        Some(13)
+    );
+    assert_eq!(
+        at(Idx(0).find_char(find('x', false, false), 1, &text)),
+        None
+    );
+    // till never stays in place, so that it can be repeated
+    assert_eq!(
+        at(Idx(0).find_char(find('(', true, false), 1, &text)),
+        Some(1)
+    );
+    assert_eq!(
+        at(Idx(1).find_char(find('(', true, false), 1, &text)),
+        Some(7)
+    );
+    assert_eq!(
+        at(Idx(7).find_char(find('(', false, true), 1, &text)),
+        Some(1)
+    );
+    assert_eq!(
+        at(Idx(8).find_char(find('(', true, true), 1, &text)),
+        Some(2)
+    );
+    // past the marks combining with the char found
+    assert_eq!(
+        at(Idx(0).find_char(find('é', false, false), 1, &text)),
+        Some(7)
+    );
+    assert_eq!(
+        at(Idx(9).find_char(find('é', true, true), 1, &text)),
+        Some(7)
+    );
+}
+
+#[test]
+fn paragraph_and_sentence_test() {
+    let text = Rope::from_str("One. Two 3.14 too!) Three\nstill.\n\n  \nNext \"one.\" Last\n");
+    let stops = |at: usize, f: fn(Idx, &Rope) -> (Idx, Idx)| {
+        let mut at = Idx(at);
+        let mut stops = vec![];
+        loop {
+            let next = f(at, &text).1;
+            if next == at {
+                return stops;
+            }
+            stops.push(next.0);
+            at = next;
+        }
+    };
+    assert_eq!(stops(0, Idx::forward_paragraph), [37, 54]);
+    assert_eq!(stops(54, Idx::backward_paragraph), [37, 0]);
+    assert_eq!(stops(45, Idx::backward_paragraph), [37, 0]);
+    assert_eq!(stops(0, Idx::forward_sentence), [5, 20, 37, 49, 54]);
+    assert_eq!(stops(54, Idx::backward_sentence), [49, 37, 20, 5, 0]);
+    assert_eq!(stops(22, Idx::backward_sentence)[0], 20);
+
+    assert_eq!(Idx(10).paragraph_around(&text), (Idx(0), Idx(37)));
+    // from blank lines, the paragraph after them
+    assert_eq!(Idx(35).paragraph_around(&text), (Idx(34), Idx(54)));
+    assert_eq!(Idx(10).sentence_around(&text), (Idx(5), Idx(20)));
+    assert_eq!(Idx(5).sentence_around(&text), (Idx(5), Idx(20)));
+}
@@ -0,0 +1,138 @@
+//! How the lines of a buffer are indented
+//!
+//! The settings come from the language of the file, then from the file
+//! itself: lines indented with tabs, or with fewer spaces, win over what
+//! the language usually does. `Enter` and `o` carry the indentation of the
+//! line over, a level more after an unclosed bracket, or after one of the
+//! `openers` ending the line, like the `:` of Python.
+use ropey::Rope;
+
+/// Lines looked at to guess the indentation of a file
+const DETECT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indent {
+    /// Cells a tab takes
+    pub tab_width: usize,
+    /// Indent with spaces, rather than tabs
+    pub expand_tabs: bool,
+    /// Spaces of a level, when indenting with them
+    pub width: usize,
+    /// Chars that, ending a line, open a block indented further
+    pub openers: &'static [char],
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            expand_tabs: true,
+            width: 4,
+            openers: &[],
+        }
+    }
+}
+
+impl Indent {
+    /// What's usual for `language`
+    pub fn for_language(language: Option<&str>) -> Self {
+        let default = Self::default();
+        match language {
+            Some("python") => Self {
+                openers: &[':'],
+                ..default
+            },
+            Some("yaml") => Self {
+                width: 2,
+                openers: &[':'],
+                ..default
+            },
+            Some("javascript" | "typescript" | "json" | "html") => Self {
+                width: 2,
+                ..default
+            },
+            Some("go") => Self {
+                expand_tabs: false,
+                ..default
+            },
+            Some("make") => Self {
+                tab_width: 8,
+                expand_tabs: false,
+                ..default
+            },
+            _ => default,
+        }
+    }
+
+    /// The settings for `language`, amended to how `text` is indented
+    pub fn detect(language: Option<&str>, text: &Rope) -> Self {
+        let mut indent = Self::for_language(language);
+        let (mut tabs, mut spaces) = (0, 0);
+        let mut min_spaces = None;
+        for line in text.lines().take(DETECT_LINES) {
+            match line.chars().next() {
+                Some('\t') => tabs += 1,
+                Some(' ') => {
+                    let count = line.chars().take_while(|&ch| ch == ' ').count();
+                    // not alignment, nor a comment continued
+                    if line
+                        .chars()
+                        .nth(count)
+                        .is_some_and(|ch| !ch.is_whitespace() && ch != '*')
+                    {
+                        spaces += 1;
+                        min_spaces = Some(min_spaces.map_or(count, |min: usize| min.min(count)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if spaces < tabs {
+            indent.expand_tabs = false;
+        } else if tabs < spaces {
+            indent.expand_tabs = true;
+            if let Some(width @ (2 | 4 | 8)) = min_spaces {
+                indent.width = width;
+            }
+        }
+        indent
+    }
+
+    /// The text of `levels` levels
+    pub fn text(&self, levels: usize) -> String {
+        if self.expand_tabs {
+            " ".repeat(self.width * levels)
+        } else {
+            "\t".repeat(levels)
+        }
+    }
+
+    /// `line` ends with one of the `openers`, ignoring trailing spaces
+    pub fn opens_block(&self, line: &str) -> bool {
+        line.trim_end()
+            .chars()
+            .last()
+            .is_some_and(|ch| self.openers.contains(&ch))
+    }
+}
+
+#[test]
+fn indent_test() {
+    let python = Indent::for_language(Some("python"));
+    assert_eq!(python.text(2), "        ");
+    assert!(python.opens_block("def f(x):  "));
+    assert!(!python.opens_block("x = {}"));
+    assert_eq!(Indent::for_language(Some("go")).text(2), "\t\t");
+
+    let detect = |language, text| Indent::detect(language, &Rope::from_str(text));
+    // as the file is, not as the language usually is
+    let tabbed = detect(
+        Some("rust"),
+        "fn f() {\n\tg();\n\tif x {\n\t\ty();\n\t}\n}\n",
+    );
+    assert!(!tabbed.expand_tabs);
+    let two = detect(Some("rust"), "fn f() {\n  g();\n    h();\n}\n");
+    assert_eq!((two.expand_tabs, two.width), (true, 2));
+    let go = detect(Some("go"), "/*\n * doc\n */\nfunc f() {\n}\n");
+    assert!(!go.expand_tabs);
+}
@@ -0,0 +1,394 @@
+//! Tracking what has to be drawn again
+//!
+//! Drawing the buffer pane means styling every visible line: selections,
+//! conflicts and whatever decorations come next. Most keys change only a
+//! few of those lines, so the styled lines are cached and only the dirty
+//! ones are styled again. When nothing in the pane is dirty, the
+//! `Renderer` is asked to `keep` what it drew there in the last frame.
+//!
+//! Lines get dirty in two ways. Edits and selection changes are picked up
+//! by the pane itself, from the buffer's `changes_since` and by comparing
+//! selections. Everything else (conflicts, overlays drawn over the pane,
+//! switching modes) has to be marked in the `Invalidation` by whoever
+//! changes it.
+use crate::buffer::{Buffer, SelectionSet};
+use crate::document::DiagnosticDisplay;
+use crate::render::{Cell, ColorMap, Rect};
+use crate::view::Row;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Sorted, non-overlapping ranges of lines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineSet {
+    ranges: Vec<Range<usize>>,
+}
+
+impl LineSet {
+    pub fn insert(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        self.ranges.retain(|r| {
+            let touches = r.start <= end && start <= r.end;
+            if touches {
+                start = start.min(r.start);
+                end = end.max(r.end);
+            }
+            !touches
+        });
+        let i = self.ranges.partition_point(|r| r.start < start);
+        self.ranges.insert(i, start..end);
+    }
+
+    pub fn extend(&mut self, other: &LineSet) {
+        for range in &other.ranges {
+            self.insert(range.clone());
+        }
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        let i = self.ranges.partition_point(|r| r.end <= line);
+        self.ranges.get(i).is_some_and(|r| r.start <= line)
+    }
+
+    pub fn intersects(&self, lines: Range<usize>) -> bool {
+        let i = self.ranges.partition_point(|r| r.end <= lines.start);
+        self.ranges.get(i).is_some_and(|r| r.start < lines.end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DirtyLines {
+    /// Version of the buffer the lines refer to
+    version: u64,
+    lines: LineSet,
+    /// All the lines are dirty
+    whole: bool,
+}
+
+/// Lines and screen regions marked dirty since the last frame
+#[derive(Debug, Clone, Default)]
+pub struct Invalidation {
+    all: bool,
+    buffers: BTreeMap<usize, DirtyLines>,
+    /// In screen coordinates
+    regions: Vec<Rect>,
+}
+
+impl Invalidation {
+    /// Everything has to be drawn again, eg. after the layout changed
+    pub fn invalidate_all(&mut self) {
+        self.all = true;
+    }
+
+    /// The `lines` of a buffer, as of its `version`, look different
+    ///
+    /// Marks made at different versions make the whole buffer dirty, as
+    /// the lines could have moved in between.
+    pub fn invalidate_lines(&mut self, buffer_i: usize, version: u64, lines: Range<usize>) {
+        let dirty = self.buffers.entry(buffer_i).or_insert_with(|| DirtyLines {
+            version,
+            lines: LineSet::default(),
+            whole: false,
+        });
+        dirty.whole |= dirty.version != version;
+        dirty.lines.insert(lines);
+    }
+
+    pub fn invalidate_buffer(&mut self, buffer_i: usize) {
+        self.buffers.insert(
+            buffer_i,
+            DirtyLines {
+                version: 0,
+                lines: LineSet::default(),
+                whole: true,
+            },
+        );
+    }
+
+    /// Something was drawn over a region of the screen
+    pub fn invalidate_region(&mut self, rect: Rect) {
+        self.regions.push(rect);
+    }
+
+    pub fn is_all_dirty(&self) -> bool {
+        self.all
+    }
+
+    /// Buffers with lines marked dirty
+    pub fn dirty_buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn dirty_regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Lines of the buffer marked dirty
+    pub fn dirty_lines(&self, buffer_i: usize, buffer: &Buffer) -> LineSet {
+        let mut lines = LineSet::default();
+        match self.buffers.get(&buffer_i) {
+            Some(dirty) if dirty.whole || dirty.version != buffer.version() || self.all => {
+                lines.insert(0..buffer.lines())
+            }
+            Some(dirty) => lines.extend(&dirty.lines),
+            None if self.all => lines.insert(0..buffer.lines()),
+            None => {}
+        }
+        lines
+    }
+
+    pub fn is_region_dirty(&self, rect: Rect) -> bool {
+        self.all || self.regions.iter().any(|region| region.overlaps(rect))
+    }
+}
+
+/// How the lines of an old version of a buffer map to the current one
+///
+/// Edits leave the text before the first change and after the last one
+/// untouched; the lines in between are dirty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMap {
+    /// First line touched by the changes, in both versions
+    first_dirty: usize,
+    /// First old line after the changes
+    old_clean: usize,
+    /// First current line after the changes
+    new_clean: usize,
+}
+
+impl LineMap {
+    /// Map following the changes made to `buffer` after `version`, when
+    /// the text was `old_len_chars` chars and `old_lines` lines long
+    ///
+    /// `None` if the changes are too old to be remembered.
+    pub fn since(
+        buffer: &Buffer,
+        version: u64,
+        old_len_chars: usize,
+        old_lines: usize,
+    ) -> Option<Self> {
+        let text = &buffer.text;
+        let mut len = old_len_chars;
+        // chars at the start and at the end that no change touched
+        let mut prefix = len;
+        let mut suffix = len;
+        let mut changed = false;
+        for change in buffer.changes_since(version)? {
+            changed = true;
+            len = (len + change.inserted).checked_sub(change.removed)?;
+            prefix = prefix.min(change.start.0);
+            suffix = suffix.min(len.checked_sub(change.start.0 + change.inserted)?);
+        }
+        if len != text.len_chars() {
+            return None;
+        }
+        if !changed {
+            return Some(Self {
+                first_dirty: old_lines,
+                old_clean: old_lines,
+                new_clean: old_lines,
+            });
+        }
+        // a line is clean only if it starts after a newline of the suffix
+        let new_clean = (text.char_to_line(len - suffix) + 1).min(text.len_lines());
+        Some(Self {
+            first_dirty: text.char_to_line(prefix),
+            old_clean: (new_clean + old_lines).checked_sub(text.len_lines())?,
+            new_clean,
+        })
+    }
+
+    /// Where an old line is now, if it wasn't touched
+    pub fn map(&self, old_line: usize) -> Option<usize> {
+        if old_line < self.first_dirty {
+            Some(old_line)
+        } else if self.old_clean <= old_line {
+            Some(old_line - self.old_clean + self.new_clean)
+        } else {
+            None
+        }
+    }
+
+    /// Current lines touched by the changes
+    pub fn dirty(&self) -> Range<usize> {
+        self.first_dirty..self.new_clean.max(self.first_dirty)
+    }
+
+    /// Current lines that an old range of lines could have ended up on
+    pub fn map_range(&self, old: Range<usize>) -> Range<usize> {
+        let start = self.map(old.start).unwrap_or(self.first_dirty);
+        let end = old
+            .end
+            .checked_sub(1)
+            .and_then(|last| self.map(last))
+            .map_or(self.new_clean, |last| last + 1);
+        start.min(end)..end.max(start)
+    }
+}
+
+/// A line as drawn: its cells, with tabs expanded
+pub type StyledLine = Vec<Cell>;
+
+/// What the cached lines of a pane depend on, besides the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PaneKey {
+    pub view_i: usize,
+    pub buffer_i: usize,
+    pub color_map: ColorMap,
+    pub tabstop: usize,
+    pub diagnostic_display: DiagnosticDisplay,
+}
+
+/// Where a pane was drawn, to tell if the `Renderer` can keep it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PaneFrame {
+    pub frame: u64,
+    /// In screen coordinates
+    pub rect: Rect,
+    pub rows: Vec<Row>,
+}
+
+/// Styled lines of the buffer pane, as drawn in the last frame
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PaneCache {
+    pub key: Option<PaneKey>,
+    pub drawn: Option<PaneFrame>,
+    version: u64,
+    len_chars: usize,
+    lines: usize,
+    selection: Option<SelectionSet>,
+    /// Lines spanned by each selection
+    selection_lines: Vec<Range<usize>>,
+    /// Brackets shown matched, and their lines
+    brackets: Vec<usize>,
+    bracket_lines: Vec<usize>,
+    pub styled: BTreeMap<usize, StyledLine>,
+}
+
+impl PaneCache {
+    /// Follow the changes since the last frame
+    ///
+    /// Cached lines that are dirty are dropped, the others are moved to
+    /// where their lines are now. Returns the dirty lines.
+    pub fn update(
+        &mut self,
+        key: PaneKey,
+        buffer: &Buffer,
+        selection: &SelectionSet,
+        brackets: &[usize],
+        invalidation: &Invalidation,
+    ) -> LineSet {
+        let mut dirty = invalidation.dirty_lines(key.buffer_i, buffer);
+        let map = LineMap::since(buffer, self.version, self.len_chars, self.lines);
+        match map {
+            Some(ref map) if self.key.as_ref() == Some(&key) && !invalidation.is_all_dirty() => {
+                dirty.insert(map.dirty());
+            }
+            _ => {
+                dirty.insert(0..buffer.lines());
+                self.drawn = None;
+            }
+        }
+
+        if self.selection.as_ref() != Some(selection) {
+            let new_lines = selection_lines(selection, buffer);
+            if let Some(ref map) = map {
+                for lines in &self.selection_lines {
+                    dirty.insert(map.map_range(lines.clone()));
+                }
+            }
+            for lines in &new_lines {
+                dirty.insert(lines.clone());
+            }
+            self.selection = Some(selection.clone());
+            self.selection_lines = new_lines;
+        }
+        if self.brackets != brackets {
+            let text = &buffer.text;
+            let new_lines: Vec<usize> =
+                brackets.iter().map(|&idx| text.char_to_line(idx)).collect();
+            if let Some(ref map) = map {
+                for &line in &self.bracket_lines {
+                    dirty.insert(map.map_range(line..line + 1));
+                }
+            }
+            for &line in &new_lines {
+                dirty.insert(line..line + 1);
+            }
+            self.brackets = brackets.to_vec();
+            self.bracket_lines = new_lines;
+        }
+
+        let styled = std::mem::take(&mut self.styled);
+        if let (Some(map), true) = (map, self.drawn.is_some()) {
+            self.styled = styled
+                .into_iter()
+                .filter_map(|(line, styled)| Some((map.map(line)?, styled)))
+                .filter(|(line, _)| !dirty.contains(*line))
+                .collect();
+        }
+        self.key = Some(key);
+        self.version = buffer.version();
+        self.len_chars = buffer.text.len_chars();
+        self.lines = buffer.lines();
+        dirty
+    }
+}
+
+/// Lines each selection is drawn on
+///
+/// Selections are drawn a char wider than they are: the direction marker
+/// is in front of the cursor, and empty selections cover a char.
+fn selection_lines(selection: &SelectionSet, buffer: &Buffer) -> Vec<Range<usize>> {
+    let text = &buffer.text;
+    let line = |idx: usize| text.char_to_line(idx.min(text.len_chars()));
+    selection
+        .selections
+        .iter()
+        .map(|sel| {
+            let (start, end) = (
+                sel.anchor.0.min(sel.cursor.0),
+                sel.anchor.0.max(sel.cursor.0),
+            );
+            line(start.saturating_sub(1))..line(end + 1) + 1
+        })
+        .collect()
+}
+
+#[test]
+fn line_set_test() {
+    let mut set = LineSet::default();
+    set.insert(5..7);
+    set.insert(1..2);
+    set.insert(7..9);
+    assert_eq!(set.ranges, vec![1..2, 5..9]);
+    assert!(set.contains(8) && !set.contains(9) && !set.contains(3));
+    assert!(set.intersects(0..2) && !set.intersects(2..5));
+}
+
+#[test]
+fn line_map_test() {
+    use crate::Idx;
+    let mut buffer = Buffer::from_text(ropey::Rope::from_str("a\nb\nc\nd\n"));
+    let (len_chars, lines) = (buffer.text.len_chars(), buffer.lines());
+    // "a\nb\nc\nd\n" -> "a\nbx\ny\nc\nd\n"
+    buffer.insert(Idx(3), "x\ny");
+    let map = LineMap::since(&buffer, 0, len_chars, lines).unwrap();
+    assert_eq!(map.dirty(), 1..3);
+    assert_eq!(map.map(0), Some(0));
+    assert_eq!(map.map(1), None);
+    assert_eq!(map.map(2), Some(3));
+    assert_eq!(map.map(3), Some(4));
+    assert_eq!(map.map_range(1..3), 1..4);
+
+    let (len_chars, lines) = (buffer.text.len_chars(), buffer.lines());
+    let unchanged = LineMap::since(&buffer, buffer.version(), len_chars, lines).unwrap();
+    assert!(unchanged.dirty().is_empty());
+}
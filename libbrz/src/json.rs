@@ -0,0 +1,400 @@
+//! JSON, as language servers speak it
+//!
+//! Just enough of it for JSON-RPC: a `Value` parsed from text and printed
+//! back compact, objects keeping their members in order. Numbers are
+//! `f64`s, which holds every id and position a server sends.
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+const NULL: Value = Value::Null;
+
+/// Arrays and objects nested deeper are refused, not to run out of stack
+const MAX_DEPTH: usize = 128;
+
+impl Value {
+    /// An object of `members`, in order
+    pub fn object<'a>(members: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// The member `key` of an object; `Null` if there's none, or it's not
+    /// an object
+    pub fn get(&self, key: &str) -> &Value {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    /// The items of an array; none if it's not one
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Value::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The number, if it's a whole one
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(53) => Some(n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().and_then(|n| u64::try_from(n).ok())
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_u64().and_then(|n| usize::try_from(n).ok())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        Value::Array(items)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => match self.as_i64() {
+                Some(n) => write!(f, "{}", n),
+                // there's no JSON for them
+                None if !n.is_finite() => f.write_str("null"),
+                None => write!(f, "{}", n),
+            },
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if 0 < i {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if 0 < i {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseJsonError {
+    /// Byte offset in the text
+    pub offset: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for ParseJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.msg)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, msg: &'static str) -> Result<T, ParseJsonError> {
+        Err(ParseJsonError {
+            offset: self.pos,
+            msg,
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseJsonError> {
+        if MAX_DEPTH < depth {
+            return self.error("nested too deep");
+        }
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if !self.eat("}") {
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() != Some(b'"') {
+                            return self.error("expected a key");
+                        }
+                        let key = self.string()?;
+                        self.skip_whitespace();
+                        if !self.eat(":") {
+                            return self.error("expected `:`");
+                        }
+                        members.push((key, self.value(depth + 1)?));
+                        self.skip_whitespace();
+                        if self.eat("}") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return self.error("expected `,` or `}`");
+                        }
+                    }
+                }
+                Value::Object(members)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        self.skip_whitespace();
+                        if self.eat("]") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return self.error("expected `,` or `]`");
+                        }
+                    }
+                }
+                Value::Array(items)
+            }
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            _ if self.eat("null") => Value::Null,
+            _ if self.eat("true") => Value::Bool(true),
+            _ if self.eat("false") => Value::Bool(false),
+            _ => return self.error("expected a value"),
+        };
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, ParseJsonError> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        match self.text[start..self.pos].parse() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => {
+                self.pos = start;
+                self.error("invalid number")
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseJsonError> {
+        let digits = self.text.get(self.pos..self.pos + 4);
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(n) => {
+                self.pos += 4;
+                Ok(n)
+            }
+            None => self.error("invalid `\\u` escape"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseJsonError> {
+        // the opening quote
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let end = match rest.find(['"', '\\']) {
+                Some(end) => end,
+                None => return self.error("unterminated string"),
+            };
+            s.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(s);
+            }
+            let escaped = match self.peek() {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    self.pos += 1;
+                    let mut code = self.hex4()?;
+                    // UTF-16 surrogate pair
+                    if (0xd800..0xdc00).contains(&code) && self.eat("\\u") {
+                        let low = self.hex4()?;
+                        code =
+                            0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                    }
+                    s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    continue;
+                }
+                _ => return self.error("invalid escape"),
+            };
+            s.push(escaped);
+            self.pos += 1;
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, ParseJsonError> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return parser.error("trailing characters");
+    }
+    Ok(value)
+}
+
+#[test]
+fn json_test() {
+    let text = r#" {"id": 3, "result": [null, true, -1.5e1, "a\"\né😀"],
+        "nested": {"empty": {}, "list": []}} "#;
+    let value = parse(text).unwrap();
+    assert_eq!(value.get("id").as_u64(), Some(3));
+    assert_eq!(value.get("result").items()[2], Value::Number(-15.0));
+    assert_eq!(value.get("result").items()[3].as_str(), Some("a\"\né😀"));
+    assert!(value.get("missing").get("deeper").is_null());
+    assert_eq!(
+        value.to_string(),
+        r#"{"id":3,"result":[null,true,-15,"a\"\né😀"],"nested":{"empty":{},"list":[]}}"#
+    );
+    assert_eq!(parse(&value.to_string()), Ok(value));
+
+    assert_eq!(
+        Value::object(vec![("s", "\u{1}".into()), ("n", 0.5.into())]).to_string(),
+        r#"{"s":"\u0001","n":0.5}"#
+    );
+    assert_eq!(parse(r#""\ud83d\ude00\/""#).unwrap().as_str(), Some("😀/"));
+    assert_eq!(parse("[1,]").unwrap_err().offset, 3);
+    assert_eq!(parse("\"abc").unwrap_err().msg, "unterminated string");
+    assert!(parse("[1] x").is_err());
+    assert!(parse(&"[".repeat(1000)).is_err());
+}
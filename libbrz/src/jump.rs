@@ -0,0 +1,74 @@
+//! Jumps to two chars shown on screen, by the labels put over them
+//!
+//! Typing two chars labels each place of the window they're found at,
+//! those nearest to the cursor first and with the shortest labels; typing
+//! a label goes there. The case of the chars is ignored unless there's an
+//! uppercase one among them.
+use ropey::Rope;
+use std::ops::Range;
+
+/// Chars of the labels, those easiest to type first
+pub const LABEL_CHARS: &[char] = &[
+    'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p',
+    'z', 'x', 'c', 'v', 'b', 'n', 'm',
+];
+
+/// Where `pair` starts in the `lines` of `text`, nearest to `cursor` first
+pub fn targets(text: &Rope, lines: Range<usize>, pair: [char; 2], cursor: usize) -> Vec<usize> {
+    let ignore_case = !pair.iter().any(|ch| ch.is_uppercase());
+    let same = |ch: char, wanted: char| {
+        if ignore_case {
+            ch.to_lowercase().eq(wanted.to_lowercase())
+        } else {
+            ch == wanted
+        }
+    };
+    let end = lines.end.min(text.len_lines());
+    let start = text.line_to_char(lines.start.min(end));
+    let end = if end < text.len_lines() {
+        text.line_to_char(end)
+    } else {
+        text.len_chars()
+    };
+    let mut found: Vec<usize> = (start..end.saturating_sub(1))
+        .filter(|&idx| same(text.char(idx), pair[0]) && same(text.char(idx + 1), pair[1]))
+        .collect();
+    found.sort_by_key(|&idx| (idx.max(cursor) - idx.min(cursor), idx));
+    found
+}
+
+/// Labels for `count` places, all as long, of one char if there are few
+/// enough of them, of two otherwise; there are no more than all the pairs
+/// of `LABEL_CHARS`
+pub fn labels(count: usize) -> Vec<String> {
+    if count <= LABEL_CHARS.len() {
+        return LABEL_CHARS[..count]
+            .iter()
+            .map(|ch| ch.to_string())
+            .collect();
+    }
+    LABEL_CHARS
+        .iter()
+        .flat_map(|&first| {
+            LABEL_CHARS
+                .iter()
+                .map(move |&second| format!("{}{}", first, second))
+        })
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn jump_test() {
+    let text = Rope::from_str("one two\nthe twine\nTwo\n");
+    // nearest first, case ignored
+    assert_eq!(targets(&text, 0..3, ['t', 'w'], 12), vec![12, 18, 4]);
+    assert_eq!(targets(&text, 0..3, ['T', 'w'], 0), vec![18]);
+    assert_eq!(targets(&text, 1..2, ['t', 'w'], 0), vec![12]);
+    // the lines past the end are none
+    assert_eq!(targets(&text, 2..9, ['w', 'o'], 0), vec![19]);
+
+    assert_eq!(labels(3), vec!["a", "s", "d"]);
+    let many = labels(30);
+    assert_eq!((many[0].as_str(), many[29].as_str()), ("aa", "sf"));
+}
@@ -0,0 +1,515 @@
+//! Keys bound by the user, for some languages or for the project
+//!
+//! Keymaps are defined in text like layers, a `keys` line followed by
+//! the keys, each with the action it runs, or a command line after `:`.
+//! The `keys` line names the language the keys are for, or nothing for
+//! all of them:
+//!
+//! ```text
+//! leader ,
+//! keys rust
+//! space,r Run "grep TODO"
+//! a-j SplitJoin
+//! leader,g,s :git status
+//! ```
+//!
+//! Keys separated by commas are a sequence: each key but the last waits
+//! for the next one, showing what can come, and any other key goes back
+//! to normal mode. `leader` in a sequence is the leader key, `space`
+//! unless a `leader` line tells another one; the last one defined goes
+//! for all the keymaps.
+//!
+//! Keys of the project, from a file in it, go over those of the user,
+//! and for each of them keys of the language of the buffer go over those
+//! for all languages. Those of the user go over the built-in ones. The
+//! keys after the start of a sequence of all the keymaps binding it to a
+//! sequence are merged.
+//!
+//! The keys of `config.toml` are in tables of `keys`, one for each
+//! mode, and one for each language in it, which `[keys]` can tell the
+//! leader key and the time a sequence waits for its next key, in
+//! milliseconds, for:
+//!
+//! ```text
+//! [keys]
+//! leader = ","
+//! timeout = 1000
+//!
+//! [keys.normal]
+//! "leader,g,s" = ":git status"
+//!
+//! [keys.normal.rust]
+//! "space,r" = 'Run "grep TODO"'
+//!
+//! [keys.goto]
+//! t = ":open TODO"
+//! ```
+use crate::action::ActionCall;
+use crate::config::{self, ParseConfigError, Value};
+use crate::layer::{self, ParseLayersError};
+use crate::Key;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Modes with keys the user can bind
+pub const MODES: &[&str] = &["normal", "goto", "view", "window"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Action(ActionCall),
+    /// Keys of sequences so far, and what the next ones do
+    Prefix(BTreeMap<Key, Binding>),
+}
+
+/// The keys that can follow a prefix, eg. "f r", to show what they do
+pub fn key_list(keys: &BTreeMap<Key, Binding>) -> String {
+    keys.keys()
+        .map(|&key| layer::key_name(key))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Add the bindings of `lower` that `keys` doesn't have, merging
+/// sequences both start
+fn merge(keys: &mut BTreeMap<Key, Binding>, lower: &BTreeMap<Key, Binding>) {
+    for (key, binding) in lower {
+        match (keys.get_mut(key), binding) {
+            (None, _) => {
+                keys.insert(*key, binding.clone());
+            }
+            (Some(Binding::Prefix(keys)), Binding::Prefix(lower)) => merge(keys, lower),
+            (Some(_), _) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    /// From the project, not from the user
+    pub project: bool,
+    /// One of `MODES`
+    pub mode: String,
+    /// For buffers of this language only
+    pub language: Option<String>,
+    pub keys: BTreeMap<Key, Binding>,
+    /// Sequences after the leader key, whichever it ends up being
+    pub leader: BTreeMap<Key, Binding>,
+}
+
+impl Keymap {
+    fn new(project: bool, mode: &str, language: Option<String>) -> Self {
+        Self {
+            project,
+            mode: mode.to_owned(),
+            language,
+            keys: BTreeMap::new(),
+            leader: BTreeMap::new(),
+        }
+    }
+
+    /// Where the keys come from, eg. "rust keys of the project", or
+    /// "user goto keys" for another mode than normal
+    pub fn origin(&self) -> String {
+        let mut keys = "keys".to_owned();
+        if self.mode != "normal" {
+            keys = format!("{} {}", self.mode, keys);
+        }
+        if let Some(ref language) = self.language {
+            keys = format!("{} {}", language, keys);
+        }
+        if self.project {
+            format!("{} of the project", keys)
+        } else {
+            format!("user {}", keys)
+        }
+    }
+
+    /// Where the keymap goes among the others: higher goes over lower
+    fn precedence(&self) -> u8 {
+        (self.project as u8) * 2 + self.language.is_some() as u8
+    }
+
+    /// What `key` does in the keymap, `leader` being the leader key
+    fn binding(&self, key: Key, leader: Key) -> Option<Binding> {
+        let binding = self.keys.get(&key).cloned();
+        if key != leader || self.leader.is_empty() {
+            return binding;
+        }
+        match binding {
+            None => Some(Binding::Prefix(self.leader.clone())),
+            Some(Binding::Prefix(mut keys)) => {
+                merge(&mut keys, &self.leader);
+                Some(Binding::Prefix(keys))
+            }
+            Some(action) => Some(action),
+        }
+    }
+
+    /// Bind `keys`, after the leader key with `leader`
+    fn bind(&mut self, leader: bool, keys: &[Key], call: ActionCall) -> Result<(), String> {
+        let mut bindings = if leader {
+            &mut self.leader
+        } else {
+            &mut self.keys
+        };
+        let (&last, prefix) = keys.split_last().ok_or("no keys")?;
+        let mut name = if leader {
+            vec!["leader".to_owned()]
+        } else {
+            vec![]
+        };
+        for &key in prefix {
+            name.push(layer::key_name(key));
+            let binding = bindings
+                .entry(key)
+                .or_insert_with(|| Binding::Prefix(BTreeMap::new()));
+            bindings = match binding {
+                Binding::Prefix(keys) => keys,
+                Binding::Action(_) => {
+                    return Err(format!("{} is bound to an action", name.join(",")))
+                }
+            };
+        }
+        if let Some(Binding::Prefix(_)) = bindings.get(&last) {
+            name.push(layer::key_name(last));
+            return Err(format!("{} starts sequences", name.join(",")));
+        }
+        bindings.insert(last, Binding::Action(call));
+        Ok(())
+    }
+}
+
+/// `space,r` or `leader,f,f` for a sequence, or one key as for layers;
+/// and whether it's after the leader key
+fn parse_keys(s: &str) -> Option<(bool, Vec<Key>)> {
+    if let Some(key) = layer::parse_key(s) {
+        return Some((false, vec![key]));
+    }
+    let mut names = s.split(',').peekable();
+    let leader = names.next_if_eq(&"leader").is_some();
+    let keys = names.map(layer::parse_key).collect::<Option<Vec<_>>>()?;
+    Some((leader, keys)).filter(|(_, keys)| !keys.is_empty())
+}
+
+/// What a key runs: an action, or a command line after `:`
+fn parse_call(s: &str) -> Result<ActionCall, String> {
+    match s.strip_prefix(':') {
+        Some(line) => Ok(ActionCall::new("Run").with_arg(line.trim())),
+        None => s.parse().map_err(|e| format!("{}", e)),
+    }
+}
+
+/// The keymaps in `text`, and the leader key it tells, if any
+pub fn parse_keymaps(
+    text: &str,
+    project: bool,
+) -> Result<(Vec<Keymap>, Option<Key>), ParseLayersError> {
+    let mut keymaps: Vec<Keymap> = vec![];
+    let mut leader = None;
+    for (i, line) in text.lines().enumerate() {
+        let error = |reason: String| ParseLayersError {
+            line: i + 1,
+            reason,
+        };
+        let line = line.trim();
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        let (first, rest) = match line.split_once(char::is_whitespace) {
+            Some((first, rest)) => (first, rest.trim()),
+            None => (line, ""),
+        };
+        if first == "keys" {
+            let language = rest.split_whitespace().next().map(str::to_owned);
+            keymaps.push(Keymap::new(project, "normal", language));
+            continue;
+        }
+        if first == "leader" {
+            let key =
+                layer::parse_key(rest).ok_or_else(|| error(format!("invalid key: {}", rest)))?;
+            leader = Some(key);
+            continue;
+        }
+        let keymap = keymaps
+            .last_mut()
+            .ok_or_else(|| error("key before any keys line".into()))?;
+        let (after_leader, keys) =
+            parse_keys(first).ok_or_else(|| error(format!("invalid key: {}", first)))?;
+        let call = parse_call(rest).map_err(error)?;
+        keymap.bind(after_leader, &keys, call).map_err(error)?;
+    }
+    Ok((keymaps, leader))
+}
+
+/// A keymap of `mode` for all languages binding `keys`, written as in a
+/// keymap, to `run`, like those plugins bind
+pub fn single(mode: &str, keys: &str, run: &str) -> Result<Keymap, String> {
+    if !MODES.contains(&mode) {
+        return Err(format!("no mode {}, only {}", mode, MODES.join(" ")));
+    }
+    let (after_leader, keys) = parse_keys(keys).ok_or_else(|| format!("invalid key: {}", keys))?;
+    let mut keymap = Keymap::new(false, mode, None);
+    keymap.bind(after_leader, &keys, parse_call(run)?)?;
+    Ok(keymap)
+}
+
+/// What `config.toml` binds
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyConfig {
+    pub keymaps: Vec<Keymap>,
+    pub leader: Option<Key>,
+    /// Of sequences, for their next key
+    pub timeout: Option<Duration>,
+}
+
+/// The keys of the `config.toml` in `text`; tables other than those of
+/// `keys` are left to others
+pub fn parse_config(text: &str, project: bool) -> Result<KeyConfig, ParseConfigError> {
+    let mut found = KeyConfig::default();
+    for table in config::parse(text)? {
+        let (mode, language) = match table.path.split_first() {
+            Some((keys, rest)) if keys == "keys" => (rest.first(), rest.get(1)),
+            _ => continue,
+        };
+        if 3 < table.path.len() {
+            return Err(ParseConfigError {
+                line: table.line,
+                msg: format!("no table {}", table.path.join(".")),
+            });
+        }
+        let mut keymap = match mode {
+            Some(mode) if MODES.contains(&mode.as_str()) => {
+                Keymap::new(project, mode, language.cloned())
+            }
+            Some(mode) => {
+                return Err(ParseConfigError {
+                    line: table.line,
+                    msg: format!("no mode {}, only {}", mode, MODES.join(" ")),
+                })
+            }
+            None => Keymap::new(project, "normal", None),
+        };
+        for entry in table.entries {
+            let error = |msg: String| ParseConfigError {
+                line: entry.line,
+                msg,
+            };
+            match (mode, entry.key.as_str(), &entry.value) {
+                (None, "leader", Value::String(key)) => {
+                    let key = layer::parse_key(key)
+                        .ok_or_else(|| error(format!("invalid key: {}", key)))?;
+                    found.leader = Some(key);
+                }
+                (None, "timeout", &Value::Integer(ms)) if 0 <= ms => {
+                    found.timeout = Some(Duration::from_millis(ms as u64));
+                }
+                (None, key, value) => {
+                    return Err(error(format!("no setting {} = {}", key, value)));
+                }
+                (Some(_), keys, Value::String(call)) => {
+                    let (after_leader, keys) =
+                        parse_keys(keys).ok_or_else(|| error(format!("invalid key: {}", keys)))?;
+                    let call = parse_call(call).map_err(error)?;
+                    keymap.bind(after_leader, &keys, call).map_err(error)?;
+                }
+                (Some(_), keys, value) => {
+                    return Err(error(format!("expected an action for {}: {}", keys, value)));
+                }
+            }
+        }
+        if mode.is_some() {
+            found.keymaps.push(keymap);
+        }
+    }
+    Ok(found)
+}
+
+/// All the keymaps defined
+#[derive(Debug, Clone)]
+pub struct Keymaps {
+    /// Highest precedence first
+    keymaps: Vec<Keymap>,
+    pub leader: Key,
+    /// Of sequences, for their next key; none to wait for it
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        Self {
+            keymaps: vec![],
+            leader: Key::Char(' '),
+            timeout: None,
+        }
+    }
+}
+
+impl Keymaps {
+    /// Add keymaps; the keys of one with the same origin replace those
+    /// it had
+    pub fn define(&mut self, keymaps: Vec<Keymap>) {
+        for keymap in keymaps {
+            let same = self.keymaps.iter_mut().find(|k| {
+                k.project == keymap.project
+                    && k.mode == keymap.mode
+                    && k.language == keymap.language
+            });
+            match same {
+                Some(same) => {
+                    same.keys.extend(keymap.keys);
+                    same.leader.extend(keymap.leader);
+                }
+                None => self.keymaps.push(keymap),
+            }
+        }
+        self.keymaps
+            .sort_by_key(|keymap| std::cmp::Reverse(keymap.precedence()));
+    }
+
+    /// What `key` does in `mode` in a buffer of `language`, and the
+    /// keymap that tells it
+    pub fn lookup(
+        &self,
+        mode: &str,
+        key: Key,
+        language: Option<&str>,
+    ) -> Option<(Binding, &Keymap)> {
+        let mut found = self
+            .keymaps
+            .iter()
+            .filter(|keymap| keymap.mode == mode)
+            .filter(|keymap| keymap.language.is_none() || keymap.language.as_deref() == language)
+            .filter_map(|keymap| Some((keymap.binding(key, self.leader)?, keymap)));
+        let (binding, keymap) = found.next()?;
+        let mut keys = match binding {
+            Binding::Action(_) => return Some((binding, keymap)),
+            Binding::Prefix(keys) => keys,
+        };
+        for (binding, _) in found {
+            if let Binding::Prefix(lower) = binding {
+                merge(&mut keys, &lower);
+            }
+        }
+        Some((Binding::Prefix(keys), keymap))
+    }
+}
+
+#[test]
+fn keymaps_test() {
+    let parse = |text, project| parse_keymaps(text, project).expect("valid").0;
+    let mut keymaps = Keymaps::default();
+    keymaps.define(parse(
+        "keys\na-j SplitJoin\nspace,f OpenFile\nkeys rust\nspace,r Run \"grep x\"\n",
+        false,
+    ));
+    keymaps.define(parse("keys\na-j Undo\n", true));
+
+    let (binding, keymap) = keymaps
+        .lookup("normal", Key::Alt('j'), Some("rust"))
+        .expect("bound");
+    assert_eq!(binding, Binding::Action(ActionCall::new("Undo")));
+    assert_eq!(keymap.origin(), "keys of the project");
+
+    let prefix = |keymaps: &Keymaps, key, language| match keymaps.lookup("normal", key, language) {
+        Some((Binding::Prefix(keys), keymap)) => (key_list(&keys), keymap.origin()),
+        other => panic!("{:?}", other),
+    };
+    let space = Key::Char(' ');
+    assert_eq!(
+        prefix(&keymaps, space, Some("rust")),
+        ("f r".to_owned(), "user rust keys".to_owned())
+    );
+    assert_eq!(
+        prefix(&keymaps, space, None),
+        ("f".to_owned(), "user keys".to_owned())
+    );
+    assert_eq!(keymaps.lookup("normal", Key::Char('x'), None), None);
+
+    // longer sequences, after the leader key, merged with those of the
+    // key itself
+    let (project, leader) =
+        parse_keymaps("keys\nleader,f,f :open x\nleader,f,g Undo\n", true).expect("valid");
+    assert_eq!(leader, None);
+    keymaps.define(project);
+    assert_eq!(
+        prefix(&keymaps, space, None),
+        ("f".to_owned(), "keys of the project".to_owned())
+    );
+    let keys = match keymaps.lookup("normal", space, None) {
+        Some((Binding::Prefix(keys), _)) => keys,
+        other => panic!("{:?}", other),
+    };
+    let keys = match keys.get(&Key::Char('f')) {
+        Some(Binding::Prefix(keys)) => keys.clone(),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(
+        keys.get(&Key::Char('f')),
+        Some(&Binding::Action(ActionCall::new("Run").with_arg("open x")))
+    );
+    assert_eq!(key_list(&keys), "f g");
+
+    // another leader key, for the sequences of all the keymaps
+    let (user, leader) = parse_keymaps("leader ,\nkeys\nleader,w Undo\n", false).expect("valid");
+    assert_eq!(leader, Some(Key::Char(',')));
+    keymaps.define(user);
+    keymaps.leader = Key::Char(',');
+    assert_eq!(
+        prefix(&keymaps, Key::Char(','), None),
+        ("f w".to_owned(), "keys of the project".to_owned())
+    );
+    assert_eq!(
+        prefix(&keymaps, space, None),
+        ("f".to_owned(), "user keys".to_owned())
+    );
+
+    // keys of other modes, from config.toml
+    let config = parse_config(
+        "[keys]\ntimeout = 500\n[keys.goto]\nt = \":open TODO\"\n\
+         [keys.normal.rust]\n\"space,x\" = 'Run \"grep x\"'\n[theme]\nname = 1\n",
+        false,
+    )
+    .expect("valid");
+    assert_eq!(config.timeout, Some(Duration::from_millis(500)));
+    assert_eq!(config.leader, None);
+    keymaps.define(config.keymaps);
+    let (binding, keymap) = keymaps.lookup("goto", Key::Char('t'), None).expect("bound");
+    assert_eq!(
+        binding,
+        Binding::Action(ActionCall::new("Run").with_arg("open TODO"))
+    );
+    assert_eq!(keymap.origin(), "user goto keys");
+    assert_eq!(keymaps.lookup("normal", Key::Char('t'), None), None);
+    // the keys of the same keymap replace those it had
+    assert_eq!(
+        prefix(&keymaps, space, Some("rust")),
+        ("f x".to_owned(), "user rust keys".to_owned())
+    );
+    let config_error = |text| parse_config(text, false).map_err(|e| e.to_string());
+    assert_eq!(
+        config_error("[keys.insert]\nx = \"Undo\""),
+        Err("line 1: no mode insert, only normal goto view window".to_owned())
+    );
+    assert_eq!(
+        config_error("[keys.view]\nx = 1"),
+        Err("line 2: expected an action for x: 1".to_owned())
+    );
+
+    let error = |text| parse_keymaps(text, false).map_err(|e| e.to_string());
+    assert_eq!(
+        error("keys\na-j Undo\na-j,k Undo"),
+        Err("line 3: a-j is bound to an action".to_owned())
+    );
+    assert_eq!(
+        error("keys\nx,y,z Undo\nx,y Undo"),
+        Err("line 3: x,y starts sequences".to_owned())
+    );
+    assert_eq!(
+        error("a Undo"),
+        Err("line 1: key before any keys line".to_owned())
+    );
+    assert_eq!(
+        error("leader xy"),
+        Err("line 1: invalid key: xy".to_owned())
+    );
+}
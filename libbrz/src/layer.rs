@@ -0,0 +1,175 @@
+//! Transient keymap layers
+//!
+//! A layer maps a few keys to actions that are usually repeated, like
+//! scrolling by lines: while it's on, its keys run their action and keep
+//! the layer on, and any other key turns it off and is handled by normal
+//! mode. With a timeout, the layer also turns off after that long
+//! without a key.
+//!
+//! Layers are defined in text, a `layer` line followed by the keys of
+//! the layer, each with the action it runs; lines starting with `#` are comments:
+//!
+//! ```text
+//! layer scroll 2000
+//! j ScrollLineDown
+//! k ScrollLineUp
+//! ```
+//!
+//! The number after the name is the timeout, in milliseconds.
+use crate::action::ActionCall;
+use crate::Key;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+    pub timeout: Option<Duration>,
+    pub keys: BTreeMap<Key, ActionCall>,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            timeout: None,
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// The keys of the layer, eg. "j k", to show what it does
+    pub fn key_list(&self) -> String {
+        self.keys
+            .keys()
+            .map(|&key| key_name(key))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLayersError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseLayersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// How `key` is written, as `parse_key` reads it
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_owned(),
+        key => key.to_string(),
+    }
+}
+
+/// A key in the form `Key` is displayed in, eg. `j`, `a-j`, `c-d` or `left`
+pub fn parse_key(s: &str) -> Option<Key> {
+    let one = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Some(ch),
+            _ => None,
+        }
+    };
+    if let Some(ch) = one(s) {
+        return Some(Key::Char(ch));
+    }
+    if let Some(rest) = s.strip_prefix("a-") {
+        return one(rest).map(Key::Alt);
+    }
+    if let Some(rest) = s.strip_prefix("c-") {
+        return one(rest).map(Key::Ctrl);
+    }
+    if let Some(n) = s.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return Some(Key::F(n));
+    }
+    Some(match s {
+        "space" => Key::Char(' '),
+        "esc" => Key::Esc,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "bs" => Key::Backspace,
+        "ret" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        _ => return None,
+    })
+}
+
+pub fn parse_layers(text: &str) -> Result<Vec<Layer>, ParseLayersError> {
+    let mut layers: Vec<Layer> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let error = |reason: String| ParseLayersError {
+            line: i + 1,
+            reason,
+        };
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let (first, rest) = match line.split_once(char::is_whitespace) {
+            Some((first, rest)) => (first, rest.trim()),
+            None if line.is_empty() => continue,
+            None => (line, ""),
+        };
+        if first == "layer" {
+            let mut words = rest.split_whitespace();
+            let mut layer = Layer::new(
+                words
+                    .next()
+                    .ok_or_else(|| error("layer without a name".into()))?,
+            );
+            if let Some(ms) = words.next() {
+                let ms = ms
+                    .parse()
+                    .map_err(|_| error(format!("invalid timeout: {}", ms)))?;
+                layer.timeout = Some(Duration::from_millis(ms));
+            }
+            layers.push(layer);
+            continue;
+        }
+        let layer = layers
+            .last_mut()
+            .ok_or_else(|| error("key before any layer".into()))?;
+        let key = parse_key(first).ok_or_else(|| error(format!("invalid key: {}", first)))?;
+        let call = rest.parse().map_err(|e| error(format!("{}", e)))?;
+        layer.keys.insert(key, call);
+    }
+    Ok(layers)
+}
+
+#[test]
+fn parse_layers_test() {
+    let layers = parse_layers(
+        "# scrolling\nlayer scroll 1500\nj ScrollLineDown\na-k ScrollLineUp\n\nlayer x\nspace InsertText \"a b\"\n",
+    )
+    .expect("valid");
+    assert_eq!(layers.len(), 2);
+    assert_eq!(layers[0].name, "scroll");
+    assert_eq!(layers[0].timeout, Some(Duration::from_millis(1500)));
+    assert_eq!(layers[0].key_list(), "j a-k");
+    assert_eq!(layers[1].key_list(), "space");
+    assert_eq!(
+        layers[0].keys.get(&Key::Alt('k')),
+        Some(&ActionCall::new("ScrollLineUp"))
+    );
+    assert_eq!(
+        layers[1].keys.get(&Key::Char(' ')),
+        Some(&ActionCall::new("InsertText").with_arg("a b"))
+    );
+    assert_eq!(
+        parse_layers("j MoveDown").map_err(|e| e.to_string()),
+        Err("line 1: key before any layer".to_owned())
+    );
+    assert_eq!(
+        parse_layers("layer a\nnope MoveDown").map_err(|e| e.line),
+        Err(2)
+    );
+}
@@ -0,0 +1,290 @@
+//! Windows, and how they share the buffer pane
+//!
+//! The pane is a tree of splits down to windows, each showing a view of
+//! a buffer with selections and a scroll of its own. A split has two
+//! parts side by side or one above the other, with a line between them,
+//! the first part taking `percent` of the room. The window of the
+//! current view is the one focused.
+use crate::render::Rect;
+use std::mem;
+
+/// The least and most of the room the first part of a split takes
+pub const MIN_PERCENT: usize = 10;
+pub const MAX_PERCENT: usize = 90;
+/// Percent of the room a window grows or shrinks by at a time
+pub const RESIZE_STEP: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// One above the other
+    Horizontal,
+    /// Side by side
+    Vertical,
+}
+
+/// Where the windows of a layout go
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placement {
+    /// Views of the windows, and their rect
+    pub windows: Vec<(usize, Rect)>,
+    /// The lines between windows
+    pub lines: Vec<(Direction, Rect)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    /// A window, and the index of the view it shows
+    Window(usize),
+    Split {
+        direction: Direction,
+        percent: usize,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Views of the windows, from the top left
+    pub fn windows(&self) -> Vec<usize> {
+        match *self {
+            Layout::Window(view_i) => vec![view_i],
+            Layout::Split {
+                ref first,
+                ref second,
+                ..
+            } => {
+                let mut windows = first.windows();
+                windows.extend(second.windows());
+                windows
+            }
+        }
+    }
+
+    pub fn contains(&self, view_i: usize) -> bool {
+        match *self {
+            Layout::Window(i) => i == view_i,
+            Layout::Split {
+                ref first,
+                ref second,
+                ..
+            } => first.contains(view_i) || second.contains(view_i),
+        }
+    }
+
+    /// The layout with the windows of the views `f` maps to another, the
+    /// other part of their split taking the room of those it doesn't
+    pub fn filter_map(&self, f: &impl Fn(usize) -> Option<usize>) -> Option<Layout> {
+        match *self {
+            Layout::Window(view_i) => f(view_i).map(Layout::Window),
+            Layout::Split {
+                direction,
+                percent,
+                ref first,
+                ref second,
+            } => match (first.filter_map(f), second.filter_map(f)) {
+                (Some(first), Some(second)) => Some(Layout::Split {
+                    direction,
+                    percent,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (one, other) => one.or(other),
+            },
+        }
+    }
+
+    /// Show `new` in the window of `old`
+    pub fn replace(&mut self, old: usize, new: usize) {
+        match *self {
+            Layout::Window(ref mut view_i) if *view_i == old => *view_i = new,
+            Layout::Window(_) => {}
+            Layout::Split {
+                ref mut first,
+                ref mut second,
+                ..
+            } => {
+                first.replace(old, new);
+                second.replace(old, new);
+            }
+        }
+    }
+
+    /// Split the window of `view_i` in two, `new` showing in the first
+    /// half
+    pub fn split(&mut self, view_i: usize, new: usize, direction: Direction) {
+        match *self {
+            Layout::Window(i) if i == view_i => {
+                *self = Layout::Split {
+                    direction,
+                    percent: 50,
+                    first: Box::new(Layout::Window(new)),
+                    second: Box::new(Layout::Window(view_i)),
+                }
+            }
+            Layout::Window(_) => {}
+            Layout::Split {
+                ref mut first,
+                ref mut second,
+                ..
+            } => {
+                first.split(view_i, new, direction);
+                second.split(view_i, new, direction);
+            }
+        }
+    }
+
+    /// Close the window of `view_i`, the other part of its split taking
+    /// the room; returns the view of the window to focus instead, if the
+    /// window wasn't the last
+    pub fn close(&mut self, view_i: usize) -> Option<usize> {
+        let (first, second) = match *self {
+            Layout::Window(_) => return None,
+            Layout::Split {
+                ref mut first,
+                ref mut second,
+                ..
+            } => (first, second),
+        };
+        let rest = if **first == Layout::Window(view_i) {
+            mem::replace(&mut **second, Layout::Window(view_i))
+        } else if **second == Layout::Window(view_i) {
+            mem::replace(&mut **first, Layout::Window(view_i))
+        } else {
+            return first.close(view_i).or_else(|| second.close(view_i));
+        };
+        let focus = rest.windows()[0];
+        *self = rest;
+        Some(focus)
+    }
+
+    /// Make the window of `view_i` take `delta` percent more of the room
+    /// of the closest split around it in `direction`, if there's one
+    pub fn resize(&mut self, view_i: usize, direction: Direction, delta: isize) -> bool {
+        let (split_direction, percent, first, second) = match *self {
+            Layout::Window(_) => return false,
+            Layout::Split {
+                direction,
+                ref mut percent,
+                ref mut first,
+                ref mut second,
+            } => (direction, percent, first, second),
+        };
+        let delta = if first.contains(view_i) {
+            if first.resize(view_i, direction, delta) {
+                return true;
+            }
+            delta
+        } else if second.contains(view_i) {
+            if second.resize(view_i, direction, delta) {
+                return true;
+            }
+            -delta
+        } else {
+            return false;
+        };
+        if split_direction != direction {
+            return false;
+        }
+        *percent =
+            (*percent as isize + delta).clamp(MIN_PERCENT as isize, MAX_PERCENT as isize) as usize;
+        true
+    }
+
+    /// Where the windows go in `rect`, and the lines between them
+    ///
+    /// A split with no room for a line and two parts gives it all to its
+    /// first part.
+    pub fn place(&self, rect: Rect) -> Placement {
+        let mut placement = Placement::default();
+        self.place_into(rect, &mut placement);
+        placement
+    }
+
+    fn place_into(&self, rect: Rect, placement: &mut Placement) {
+        let (direction, percent, first, second) = match *self {
+            Layout::Window(view_i) => {
+                placement.windows.push((view_i, rect));
+                return;
+            }
+            Layout::Split {
+                direction,
+                percent,
+                ref first,
+                ref second,
+            } => (direction, percent, first, second),
+        };
+        let size = match direction {
+            Direction::Horizontal => rect.dimensions.y,
+            Direction::Vertical => rect.dimensions.x,
+        };
+        if size < 3 {
+            first.place_into(rect, placement);
+            return;
+        }
+        let at = ((size - 1) * percent / 100).clamp(1, size - 2) as isize;
+        let (first_rect, rest) = match direction {
+            Direction::Horizontal => rect.split_horizontaly_at(at),
+            Direction::Vertical => rect.split_verticaly_at(at),
+        };
+        let (line, second_rect) = match direction {
+            Direction::Horizontal => rest.split_horizontaly_at(1),
+            Direction::Vertical => rest.split_verticaly_at(1),
+        };
+        first.place_into(first_rect, placement);
+        placement.lines.push((direction, line));
+        second.place_into(second_rect, placement);
+    }
+}
+
+#[test]
+fn layout_test() {
+    use crate::render::Coord;
+    let mut layout = Layout::Window(0);
+    assert_eq!(layout.close(0), None);
+    layout.split(0, 1, Direction::Vertical);
+    layout.split(0, 2, Direction::Horizontal);
+    assert_eq!(layout.windows(), vec![1, 2, 0]);
+
+    let rect = |x, y, width, height| Rect {
+        offset: Coord { x, y },
+        dimensions: Coord {
+            x: width,
+            y: height,
+        },
+    };
+    let placement = layout.place(rect(0, 0, 21, 11));
+    assert_eq!(
+        placement.windows,
+        vec![
+            (1, rect(0, 0, 10, 11)),
+            (2, rect(11, 0, 10, 5)),
+            (0, rect(11, 6, 10, 5))
+        ]
+    );
+    assert_eq!(
+        placement.lines,
+        vec![
+            (Direction::Vertical, rect(10, 0, 1, 11)),
+            (Direction::Horizontal, rect(11, 5, 10, 1))
+        ]
+    );
+    // no room to split
+    assert_eq!(
+        layout.place(rect(0, 0, 2, 11)).windows,
+        vec![(1, rect(0, 0, 2, 11))]
+    );
+
+    // the closest split in the direction is resized, as far as it goes
+    assert!(layout.resize(0, Direction::Vertical, 20));
+    assert!(layout.resize(2, Direction::Horizontal, -100));
+    assert!(!layout.resize(1, Direction::Horizontal, 10));
+    let windows = layout.place(rect(0, 0, 21, 11)).windows;
+    assert_eq!(windows[0].1, rect(0, 0, 6, 11));
+    assert_eq!(windows[1].1, rect(7, 0, 14, 1));
+
+    layout.replace(2, 3);
+    assert_eq!(layout.close(3), Some(0));
+    assert_eq!(layout.windows(), vec![1, 0]);
+    assert_eq!(layout.close(1), Some(0));
+    assert_eq!(layout, Layout::Window(0));
+}
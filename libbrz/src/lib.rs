@@ -1,26 +1,100 @@
 #![allow(dead_code)]
+// for the `actions!` of normal mode
+#![recursion_limit = "256"]
 
 pub mod action;
 pub mod buffer;
+pub mod command;
+pub mod completion;
+pub mod config;
+pub mod conflict;
+pub mod dashboard;
+pub mod diff;
+pub mod document;
+pub mod dropped;
+pub mod encoding;
+pub mod env;
+pub mod finder;
+pub mod fold;
+pub mod follow;
+pub mod frecency;
+pub mod fuzzy;
+pub mod git;
+pub mod grep;
+pub mod grid;
+pub mod health;
+pub mod hierarchy;
+pub mod highlight;
 pub mod idx;
+pub mod indent;
+pub mod invalidate;
+pub mod json;
+pub mod jump;
+pub mod keymap;
+pub mod layer;
+pub mod layout;
+pub mod line_editor;
+pub mod lsp;
+pub mod mark;
 pub mod mode;
+pub mod motion;
+pub mod object;
 pub mod position;
 pub mod range;
+pub mod recover;
+pub mod register;
 pub mod selection;
+pub mod selection_history;
+pub mod session;
+pub mod span;
 
+pub mod pipe;
+pub mod plugin;
 pub mod prelude;
+pub mod preview;
+pub mod profile;
+pub mod progress;
+pub mod quickfix;
+pub mod remote;
 pub mod render;
+pub mod repeat;
+pub mod sanitize;
+pub mod search;
+pub mod share;
+pub mod startup;
 pub mod state;
+pub mod status;
+pub mod structure;
+pub mod surface;
+pub mod syntax;
+pub mod tab;
+pub mod target;
+pub mod term;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod theme;
+pub mod todo;
+pub mod trash;
+pub mod undo;
 pub mod util;
+pub mod vfs;
+pub mod view;
 
+pub use self::buffer::{Buffer, BufferView, SelectionSet};
 pub use self::idx::Idx;
+pub use self::line_editor::{LineEditor, LineEditorEvent};
 pub use self::mode::Mode;
+pub use self::motion::{CharFind, Motion, TextObject};
 pub use self::position::Position;
+pub use self::selection::Selection;
 pub use self::state::State;
+pub use self::view::ViewState;
+pub use ropey;
 use std::cmp;
 use std::fmt;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+#[non_exhaustive]
 pub enum Key {
     /// Backspace.
     Backspace,
@@ -60,23 +134,42 @@ pub enum Key {
     Null,
     /// Esc key.
     Esc,
-
-    #[doc(hidden)]
-    __IsNotComplete,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct NaturalyOrderedKey(pub Key);
 
+/// What the mouse did, at a cell of the screen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mouse {
+    /// The left button pressed
+    Press(render::Coord),
+    /// Moved with the left button held
+    Drag(render::Coord),
+    Release(render::Coord),
+    /// The wheel turned, up or down
+    Scroll {
+        up: bool,
+        at: render::Coord,
+    },
+}
+
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Key::*;
         match *self {
             F(c) => f.pad(&format!("f{}", c)),
+            Char('\n') => f.pad("ret"),
+            Char('\t') => f.pad("tab"),
             Char(c) => f.pad(&format!("{}", c)),
             Alt(c) => f.pad(&format!("a-{}", c)),
             Ctrl(c) => f.pad(&format!("c-{}", c)),
             Esc => f.pad("esc"),
+            Left => f.pad("left"),
+            Right => f.pad("right"),
+            Up => f.pad("up"),
+            Down => f.pad("down"),
+            Backspace => f.pad("bs"),
             _ => f.pad("?"),
         }
     }
@@ -86,7 +179,7 @@ impl NaturalyOrderedKey {
     fn ordering_keys(self) -> (usize, char, usize) {
         use self::Key::*;
         match self.0 {
-            F(c) => (9, ('0' as u8 + c) as char, 0),
+            F(c) => (9, (b'0' + c) as char, 0),
             Char(c) => (
                 1,
                 c.to_ascii_lowercase(),
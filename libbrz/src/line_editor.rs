@@ -0,0 +1,316 @@
+//! Single (or few) line editor widget
+//!
+//! Editing of prompts and other short inputs, using the same engine as
+//! the main buffers: the text lives in a `Buffer` (so it has undo), the
+//! selections in a `ViewState`, and yanking and pasting use a register
+//! the owner can share with the rest of the editor. Whole lines from the
+//! register are pasted as text, without their line end.
+//!
+//! The editor starts in insert mode, where `Ctrl-w` deletes the word
+//! before the cursor into the register and `Ctrl-y` pastes it. If it's
+//! `modal`, `Esc` switches to
+//! a small normal mode (`h`/`l`/`w`/`b` and their extending uppercase
+//! variants, `x`, `d`, `c`, `y`, `p`, `P`, `u`, `U`, `i`, `a`) and a
+//! second `Esc` cancels; otherwise `Esc` cancels right away.
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::{Buffer, BufferView};
+use crate::motion::{Motion, TextObject};
+use crate::position::convert::cell_column;
+use crate::register::Register;
+use crate::render::{self, Coord, Renderer, Style};
+use crate::view::ViewState;
+use crate::{Idx, Key};
+
+/// What happened after handling a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineEditorEvent {
+    /// Still editing
+    Continue,
+    /// `Enter` was pressed with the given text
+    Submit(String),
+    Cancel,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineEditor {
+    buffer: Buffer,
+    view: ViewState,
+    insert: bool,
+    modal: bool,
+    max_lines: usize,
+    /// Yanked text; copy it from and to the editor's register to share it
+    pub register: Register,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self {
+            buffer: Buffer::default(),
+            view: ViewState::default(),
+            insert: true,
+            modal: false,
+            max_lines: 1,
+            register: Register::default(),
+        }
+    }
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start with `text`, with the cursor at its end
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.buffer = Buffer::from_text(Rope::from_str(text));
+        self.edit()
+            .apply_motion(Motion::Right(text.chars().count()), false);
+        self.view.selection.collapse();
+        self
+    }
+
+    /// Make `Esc` switch to normal mode instead of cancelling
+    pub fn modal(mut self, modal: bool) -> Self {
+        self.modal = modal;
+        self
+    }
+
+    /// Allow inserting newlines with `Alt-Enter`, up to `max_lines` lines
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = max_lines.max(1);
+        self
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.text.to_string()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.text.len_chars() == 0
+    }
+
+    pub fn is_insert(&self) -> bool {
+        self.insert
+    }
+
+    /// The text before the cursor, eg. to complete
+    pub fn before_cursor(&self) -> String {
+        let text = &self.buffer.text;
+        let cursor = self.view.selection.selections[self.view.selection.primary].cursor;
+        text.slice(..cursor.0.min(text.len_chars())).to_string()
+    }
+
+    /// Replace the `len` chars before the cursor with `text`, eg. what
+    /// they complete to
+    pub fn replace_before_cursor(&mut self, len: usize, text: &str) {
+        self.buffer.maybe_commit_undo_point(&self.view.selection);
+        self.view.selection.collapse();
+        let mut edit = self.edit();
+        if 0 < len {
+            edit.apply_motion(Motion::Left(len), true);
+            edit.delete();
+        }
+        edit.insert(text, false);
+        self.buffer.maybe_commit_undo_point(&self.view.selection);
+    }
+
+    fn edit(&mut self) -> BufferView<'_> {
+        BufferView::new(&mut self.buffer, &mut self.view)
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> LineEditorEvent {
+        match key {
+            Key::Char('\n') => return LineEditorEvent::Submit(self.text()),
+            Key::Esc if self.insert && self.modal => {
+                self.insert = false;
+                return LineEditorEvent::Continue;
+            }
+            Key::Esc => return LineEditorEvent::Cancel,
+            _ => {}
+        }
+
+        self.buffer.maybe_commit_undo_point(&self.view.selection);
+        if self.insert {
+            self.handle_insert(key);
+        } else {
+            self.handle_normal(key);
+        }
+        self.buffer.maybe_commit_undo_point(&self.view.selection);
+        LineEditorEvent::Continue
+    }
+
+    fn handle_insert(&mut self, key: Key) {
+        let lines = self.buffer.lines();
+        let max_lines = self.max_lines;
+        let register = self.register.to_chars();
+        let mut edit = self.edit();
+        match key {
+            Key::Backspace => edit.backspace_one(),
+            Key::Left => edit.apply_motion(Motion::Left(1), false),
+            Key::Right => edit.apply_motion(Motion::Right(1), false),
+            Key::Up => edit.apply_motion(Motion::Up(1), false),
+            Key::Down => edit.apply_motion(Motion::Down(1), false),
+            Key::Home | Key::Ctrl('a') => edit.apply_motion(Motion::LineStart, false),
+            Key::Ctrl('e') => edit.move_cursor(Idx::line_end),
+            Key::Ctrl('w') => {
+                edit.apply_motion(Motion::WordBackward, false);
+                self.register = edit.delete();
+            }
+            Key::Ctrl('y') => {
+                edit.paste_extend(&register);
+                self.view.selection.collapse();
+            }
+            Key::Alt('\n') if lines < max_lines => edit.insert("\n", false),
+            Key::Char(ch) if !ch.is_control() => edit.insert_char(ch, false),
+            _ => {}
+        }
+    }
+
+    fn handle_normal(&mut self, key: Key) {
+        let register = self.register.to_chars();
+        let mut edit = self.edit();
+        match key {
+            Key::Char('h') | Key::Left => edit.apply_motion(Motion::Left(1), false),
+            Key::Char('H') => edit.apply_motion(Motion::Left(1), true),
+            Key::Char('l') | Key::Right => edit.apply_motion(Motion::Right(1), false),
+            Key::Char('L') => edit.apply_motion(Motion::Right(1), true),
+            Key::Char('w') => edit.apply_motion(Motion::WordForward, false),
+            Key::Char('W') => edit.apply_motion(Motion::WordForward, true),
+            Key::Char('b') => edit.apply_motion(Motion::WordBackward, false),
+            Key::Char('B') => edit.apply_motion(Motion::WordBackward, true),
+            Key::Char('x') => edit.select_object(TextObject::Line),
+            Key::Char('d') => self.register = edit.delete(),
+            Key::Char('c') => {
+                self.register = edit.delete();
+                self.insert = true;
+            }
+            Key::Char('y') => self.register = edit.yank(),
+            Key::Char('p') => edit.paste(&register),
+            Key::Char('P') => edit.paste_extend(&register),
+            Key::Char('i') => self.insert = true,
+            Key::Char('a') => {
+                edit.apply_motion(Motion::Right(1), false);
+                self.view.selection.collapse();
+                self.insert = true;
+            }
+            Key::Char('u') => {
+                self.view.selection = self.buffer.undo(1, &self.view.selection);
+            }
+            Key::Char('U') => {
+                if let Some(selection) = self.buffer.redo(1) {
+                    self.view.selection = selection;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Render at `coord`, using at most `width` columns per line
+    ///
+    /// Scrolls horizontally to keep the cursor visible, and sets the
+    /// cursor of `render` if `focused`.
+    pub fn render(
+        &self,
+        render: &mut dyn Renderer,
+        coord: Coord,
+        width: usize,
+        style: Style,
+        focused: bool,
+    ) {
+        let text = &self.buffer.text;
+        let selection_style = style.paintover(render.color_map().selection);
+        let cursor = self.view.selection.selections[self.view.selection.primary].cursor;
+        let cursor = Idx(cursor.0.min(text.len_chars()));
+        let cursor_pos = cursor.to_position(text);
+        let cursor_x = cell_column(text, cursor, 1);
+        let scroll = (cursor_x + 1).saturating_sub(width.max(1));
+
+        for (line_i, line) in text.lines().enumerate() {
+            let line_start = text.line_to_char(line_i);
+            let scroll = if line_i == cursor_pos.line { scroll } else { 0 };
+            let line = line.to_string();
+            let (mut x, mut i) = (0, 0);
+            for grapheme in line.graphemes(true) {
+                if grapheme.ends_with('\n') {
+                    break;
+                }
+                let selected = !self.insert
+                    && self
+                        .view
+                        .selection
+                        .selections
+                        .iter()
+                        .any(|sel| sel.sorted_range_usize().contains(&(line_start + i)));
+                let style = if selected { selection_style } else { style };
+                for cell in render::cells(grapheme, style) {
+                    if scroll <= x && x < scroll + width {
+                        render.put_cell(coord.add_x(x - scroll).add_y(line_i), cell);
+                    }
+                    x += 1;
+                }
+                i += grapheme.chars().count();
+            }
+        }
+        if focused {
+            render.set_cursor(Some(coord.add_x(cursor_x - scroll).add_y(cursor_pos.line)));
+        }
+    }
+}
+
+#[test]
+fn line_editor_test() {
+    let mut editor = LineEditor::new().modal(true);
+    for ch in "hello world".chars() {
+        editor.handle_key(Key::Char(ch));
+    }
+    assert_eq!(editor.text(), "hello world");
+
+    editor.handle_key(Key::Esc);
+    assert!(!editor.is_insert());
+    for ch in "bbd".chars() {
+        editor.handle_key(Key::Char(ch));
+    }
+    assert_eq!(editor.text(), " world");
+    editor.handle_key(Key::Char('u'));
+    assert_eq!(editor.text(), "hello world");
+    editor.handle_key(Key::Char('p'));
+    assert_eq!(editor.text(), "hellohello world");
+
+    assert_eq!(
+        editor.handle_key(Key::Char('\n')),
+        LineEditorEvent::Submit("hellohello world".into())
+    );
+    assert_eq!(editor.handle_key(Key::Esc), LineEditorEvent::Cancel);
+
+    let mut editor = LineEditor::new().with_text("e foo");
+    editor.handle_key(Key::Ctrl('w'));
+    assert_eq!(editor.text(), "e ");
+    editor.handle_key(Key::Ctrl('y'));
+    editor.handle_key(Key::Char('x'));
+    assert_eq!(editor.text(), "e foox");
+
+    // at the ends of the text, going further goes nowhere
+    let mut editor = LineEditor::new();
+    editor.handle_key(Key::Ctrl('e'));
+    editor.handle_key(Key::Backspace);
+    assert_eq!(editor.text(), "");
+    for key in [Key::Char('a'), Key::Left, Key::Backspace] {
+        editor.handle_key(key);
+    }
+    assert_eq!(editor.text(), "a");
+    for key in [
+        Key::Char('b'),
+        Key::Ctrl('a'),
+        Key::Ctrl('e'),
+        Key::Char('c'),
+    ] {
+        editor.handle_key(key);
+    }
+    assert_eq!(editor.text(), "bac");
+
+    let mut state = crate::testing::state_with_text("ab\n");
+    crate::testing::type_keys(&mut state, "/<c-e>a<left><bs><c-e>b<ret>");
+    assert_eq!(state.cur_buffer().text.to_string(), "ab\n");
+}
@@ -0,0 +1,670 @@
+//! Language servers attached to documents
+//!
+//! The editor doesn't talk to the servers itself: whatever runs them
+//! attaches them to documents and publishes what they report. A document
+//! can have several servers, eg. a language server and a linter. When
+//! they report the same thing, the one with the highest priority wins.
+//!
+//! Positions follow the protocol's (0-based lines, and chars in the
+//! line) but count chars, not UTF-16 code units.
+use crate::buffer::Buffer;
+use crate::document::Diagnostic;
+use crate::quickfix;
+use crate::Idx;
+use ropey::Rope;
+use std::fmt::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Higher wins when servers report the same diagnostic or completion
+    pub priority: i32,
+    /// What the server supports, eg. `completion` or `hover`
+    pub capabilities: Vec<String>,
+}
+
+/// A server attached to a document, with what it last reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachedServer {
+    pub info: ServerInfo,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Diagnostics of all the servers, in order, without duplicates
+///
+/// Diagnostics with the same range and message are duplicates; only the
+/// one from the server with the highest priority is kept.
+pub fn merge_diagnostics(servers: &[AttachedServer]) -> Vec<Diagnostic> {
+    let mut all: Vec<(i32, Diagnostic)> = servers
+        .iter()
+        .flat_map(|server| {
+            server.diagnostics.iter().map(move |d| {
+                let mut d = d.clone();
+                d.source.get_or_insert_with(|| server.info.name.clone());
+                (server.info.priority, d)
+            })
+        })
+        .collect();
+    all.sort_by(|(a_priority, a), (b_priority, b)| {
+        (a.start, a.end, &a.message)
+            .cmp(&(b.start, b.end, &b.message))
+            .then(b_priority.cmp(a_priority))
+    });
+    all.dedup_by(|(_, b), (_, a)| (a.start, a.end, &a.message) == (b.start, b.end, &b.message));
+    all.into_iter().map(|(_, d)| d).collect()
+}
+
+/// Completion labels from several servers, highest priority first,
+/// without duplicates
+pub fn merge_completions(lists: &[(&ServerInfo, Vec<String>)]) -> Vec<String> {
+    let mut lists: Vec<_> = lists.iter().collect();
+    lists.sort_by_key(|(info, _)| -info.priority);
+    let mut merged: Vec<String> = vec![];
+    for label in lists.iter().flat_map(|(_, labels)| labels) {
+        if !merged.contains(label) {
+            merged.push(label.clone());
+        }
+    }
+    merged
+}
+
+/// Content of the `:lsp-info` buffer
+pub fn info_text(servers: &[AttachedServer]) -> String {
+    if servers.is_empty() {
+        return "no language servers attached\n".into();
+    }
+    let mut out = String::new();
+    for server in servers {
+        let _ = writeln!(
+            out,
+            "{} (priority {}): {} diagnostics",
+            server.info.name,
+            server.info.priority,
+            server.diagnostics.len()
+        );
+        let _ = writeln!(
+            out,
+            "  capabilities: {}",
+            server.info.capabilities.join(", ")
+        );
+    }
+    out
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// Replace the text between two positions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: Position,
+    pub end: Position,
+    pub new_text: String,
+}
+
+/// Edits to a file, all relative to the same version of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextDocumentEdit {
+    pub path: PathBuf,
+    /// Version of the buffer the edits were computed for, `None` if the
+    /// file isn't open
+    pub version: Option<u64>,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileOptions {
+    /// Replace an existing file; wins over `ignore_if_exists`
+    pub overwrite: bool,
+    /// Do nothing if the file exists
+    pub ignore_if_exists: bool,
+}
+
+/// Operations on files, carried out by the resource handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceOp {
+    Create {
+        path: PathBuf,
+        options: FileOptions,
+    },
+    Rename {
+        old: PathBuf,
+        new: PathBuf,
+        options: FileOptions,
+    },
+    Delete {
+        path: PathBuf,
+        recursive: bool,
+        ignore_if_not_exists: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentChange {
+    Edit(TextDocumentEdit),
+    Resource(ResourceOp),
+}
+
+impl DocumentChange {
+    /// Path the change is about, for reports
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            DocumentChange::Edit(edit) => &edit.path,
+            DocumentChange::Resource(ResourceOp::Create { path, .. })
+            | DocumentChange::Resource(ResourceOp::Delete { path, .. }) => path,
+            DocumentChange::Resource(ResourceOp::Rename { old, .. }) => old,
+        }
+    }
+}
+
+/// Changes to make to the workspace, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceEdit {
+    pub changes: Vec<DocumentChange>,
+}
+
+impl WorkspaceEdit {
+    /// Edit made of unversioned text edits only, as in the `changes`
+    /// form of the protocol
+    pub fn from_changes(changes: impl IntoIterator<Item = (PathBuf, Vec<TextEdit>)>) -> Self {
+        Self {
+            changes: changes
+                .into_iter()
+                .map(|(path, edits)| {
+                    DocumentChange::Edit(TextDocumentEdit {
+                        path,
+                        version: None,
+                        edits,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Why a workspace edit stopped before the end
+///
+/// The changes before `failed_change` were applied, the others weren't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditFailure {
+    pub failed_change: usize,
+    pub reason: String,
+}
+
+/// Char index of a position
+///
+/// Characters past the end of the line mean the end of the line, as in
+/// the protocol.
+fn position_to_char(text: &Rope, pos: Position) -> Result<usize, String> {
+    if pos.line >= text.len_lines() {
+        return Err(format!("line {} is past the end", pos.line + 1));
+    }
+    let line = text.line(pos.line).to_string();
+    let len = line.trim_end_matches(['\n', '\r']).chars().count();
+    Ok(text.line_to_char(pos.line) + pos.character.min(len))
+}
+
+/// Char ranges of edits all relative to the same version of `text`, in
+/// order, with their new text
+///
+/// Fails if an edit is out of bounds or edits overlap.
+pub fn text_edit_ranges(
+    text: &Rope,
+    edits: &[TextEdit],
+) -> Result<Vec<(Range<usize>, String)>, String> {
+    let mut ranges = edits
+        .iter()
+        .map(|edit| {
+            let start = position_to_char(text, edit.start)?;
+            let end = position_to_char(text, edit.end)?;
+            if end < start {
+                return Err(format!(
+                    "edit at line {} ends before it starts",
+                    edit.start.line + 1
+                ));
+            }
+            Ok((start..end, edit.new_text.clone()))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    // stable, so inserts at the same place keep their order
+    ranges.sort_by_key(|(range, _)| (range.start, range.end));
+    if let Some(pair) = ranges
+        .windows(2)
+        .find(|pair| pair[1].0.start < pair[0].0.end)
+    {
+        return Err(format!(
+            "overlapping edits at line {}",
+            text.char_to_line(pair[1].0.start) + 1
+        ));
+    }
+    Ok(ranges)
+}
+
+/// Apply edits all relative to the same version of `text`
+///
+/// Nothing is changed if an edit is out of bounds or edits overlap.
+pub fn apply_text_edits(text: &mut Rope, edits: &[TextEdit]) -> Result<(), String> {
+    for (range, new_text) in text_edit_ranges(text, edits)?.into_iter().rev() {
+        let start = range.start;
+        text.remove(range);
+        text.insert(start, &new_text);
+    }
+    Ok(())
+}
+
+/// A command a server offers to run, eg. from a code lens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub title: String,
+    pub command: String,
+    pub arguments: Vec<String>,
+}
+
+/// A command shown above a line, eg. how many references a function has
+/// or how to run a test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeLens {
+    pub start: Idx,
+    /// `None` until the server resolves it; not shown until then
+    pub command: Option<Command>,
+}
+
+/// What the frontend is asked to get code lenses for
+#[derive(Debug, Clone)]
+pub struct CodeLensRequest {
+    pub id: u64,
+    pub path: PathBuf,
+    pub text: Rope,
+}
+
+/// Code lenses of a buffer, and the request that will replace them
+#[derive(Debug, Clone, Default)]
+pub struct CodeLenses {
+    /// Version of the buffer the lenses were moved to
+    version: u64,
+    lenses: Vec<CodeLens>,
+    /// Request in flight, and the version it's for
+    pending: Option<(u64, u64)>,
+}
+
+impl CodeLenses {
+    /// Lenses starting in the `range` of chars
+    pub fn in_range(&self, range: Range<usize>) -> &[CodeLens] {
+        let start = self.lenses.partition_point(|l| l.start.0 < range.start);
+        let end = start + self.lenses[start..].partition_point(|l| l.start.0 < range.end);
+        &self.lenses[start..end]
+    }
+
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending.map(|(id, _)| id)
+    }
+
+    pub(crate) fn start(&mut self, id: u64, buffer: &Buffer) {
+        self.pending = Some((id, buffer.version()));
+    }
+
+    pub(crate) fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    /// Take the result of the pending request `id`, moved along the edits
+    /// made since it was sent
+    ///
+    /// Returns `false` if it's not the pending one.
+    pub(crate) fn deliver(&mut self, id: u64, mut lenses: Vec<CodeLens>, buffer: &Buffer) -> bool {
+        match self.pending {
+            Some((pending_id, version)) if pending_id == id => {
+                self.pending = None;
+                lenses.sort_by_key(|lens| lens.start);
+                self.lenses = lenses;
+                self.version = version;
+                self.follow(buffer);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the lenses along the edits made since
+    ///
+    /// Lenses whose start was removed are dropped.
+    pub(crate) fn follow(&mut self, buffer: &Buffer) {
+        if self.version == buffer.version() {
+            return;
+        }
+        match buffer.changes_since(self.version) {
+            Some(changes) => {
+                for change in changes {
+                    let start = change.start.0;
+                    self.lenses.retain_mut(|lens| {
+                        if lens.start.0 < start {
+                            true
+                        } else if start + change.removed <= lens.start.0 {
+                            lens.start = Idx(lens.start.0 + change.inserted - change.removed);
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                }
+            }
+            None => self.lenses.clear(),
+        }
+        self.version = buffer.version();
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DocumentHighlightKind {
+    /// Textual occurrence, eg. when the server can't tell reads and writes
+    Text,
+    Read,
+    Write,
+}
+
+/// An occurrence of the symbol under the cursor, in chars
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentHighlight {
+    pub range: Range<usize>,
+    pub kind: DocumentHighlightKind,
+}
+
+/// What the frontend is asked to find the occurrences of
+#[derive(Debug, Clone)]
+pub struct DocumentHighlightRequest {
+    pub id: u64,
+    pub path: PathBuf,
+    pub position: Position,
+}
+
+/// What the frontend is asked to format after typing `ch`
+///
+/// The edits are for the `version` of the buffer, with the char typed
+/// just before `position`.
+#[derive(Debug, Clone)]
+pub struct OnTypeFormattingRequest {
+    pub id: u64,
+    pub path: PathBuf,
+    pub version: u64,
+    pub position: Position,
+    pub ch: char,
+}
+
+/// A document as the servers are to see it, from when it's opened to
+/// when it's closed; each change sends the whole text
+#[derive(Debug, Clone)]
+pub enum DocumentSync {
+    Open {
+        path: PathBuf,
+        language: String,
+        version: u64,
+        text: Rope,
+    },
+    Change {
+        path: PathBuf,
+        version: u64,
+        text: Rope,
+    },
+    Close {
+        path: PathBuf,
+    },
+}
+
+/// What can be asked about the symbol under the cursor
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Query {
+    Definition,
+    References,
+    Hover,
+}
+
+impl Query {
+    /// Also the capability of the servers answering it
+    pub fn name(self) -> &'static str {
+        match self {
+            Query::Definition => "definition",
+            Query::References => "references",
+            Query::Hover => "hover",
+        }
+    }
+}
+
+/// What the frontend is asked about a position of a document
+#[derive(Debug, Clone)]
+pub struct QueryRequest {
+    pub id: u64,
+    pub query: Query,
+    pub path: PathBuf,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryResult {
+    /// Where the symbol is defined or used, the symbol itself `matched`
+    Locations(Vec<quickfix::Entry>),
+    /// What the server says of the symbol, as text
+    Hover(String),
+}
+
+/// Occurrences of the symbol under the cursor of a buffer
+///
+/// They are dropped as soon as the buffer changes or the cursor leaves
+/// them, and asked for again once the input is idle.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentHighlights {
+    highlights: Vec<DocumentHighlight>,
+    /// Version of the buffer and cursor they were last asked for
+    requested: Option<(u64, usize)>,
+    pending: Option<u64>,
+}
+
+impl DocumentHighlights {
+    pub fn highlights(&self) -> &[DocumentHighlight] {
+        &self.highlights
+    }
+
+    /// Kind of the occurrence at `idx`, if any
+    pub fn kind_at(&self, idx: usize) -> Option<DocumentHighlightKind> {
+        let i = self.highlights.partition_point(|h| h.range.end <= idx);
+        self.highlights
+            .get(i)
+            .filter(|h| h.range.start <= idx)
+            .map(|h| h.kind)
+    }
+
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending
+    }
+
+    /// Are the occurrences the ones of the symbol at `cursor`, or about to be
+    pub fn is_current(&self, buffer: &Buffer, cursor: usize) -> bool {
+        self.requested == Some((buffer.version(), cursor))
+            || (self
+                .requested
+                .is_some_and(|(version, _)| version == buffer.version())
+                && self.kind_at(cursor).is_some())
+    }
+
+    pub(crate) fn start(&mut self, id: u64, buffer: &Buffer, cursor: usize) {
+        self.pending = Some(id);
+        self.requested = Some((buffer.version(), cursor));
+    }
+
+    /// Use `highlights` right away, eg. when not asking a server
+    pub(crate) fn set(
+        &mut self,
+        mut highlights: Vec<DocumentHighlight>,
+        buffer: &Buffer,
+        cursor: usize,
+    ) {
+        highlights.sort_by_key(|h| h.range.start);
+        self.highlights = highlights;
+        self.pending = None;
+        self.requested = Some((buffer.version(), cursor));
+    }
+
+    /// Take the result of the pending request `id`
+    ///
+    /// Returns `false` if it's not the pending one.
+    pub(crate) fn deliver(&mut self, id: u64, mut highlights: Vec<DocumentHighlight>) -> bool {
+        if self.pending != Some(id) {
+            return false;
+        }
+        highlights.sort_by_key(|h| h.range.start);
+        self.highlights = highlights;
+        self.pending = None;
+        true
+    }
+
+    /// Drop the occurrences if they are outdated, and forget the request
+    /// if it was for another cursor or version
+    ///
+    /// Returns `true` if some occurrences were dropped.
+    pub(crate) fn follow(&mut self, buffer: &Buffer, cursor: usize) -> bool {
+        let same_version = self
+            .requested
+            .is_some_and(|(version, _)| version == buffer.version());
+        if same_version && self.kind_at(cursor).is_some() {
+            return false;
+        }
+        if !(same_version && self.requested.map(|(_, at)| at) == Some(cursor)) {
+            self.pending = None;
+            self.requested = None;
+        }
+        !std::mem::take(&mut self.highlights).is_empty()
+    }
+}
+
+/// Occurrences of the word at `idx`, for when no server can tell better
+pub fn word_highlights(text: &Rope, idx: usize) -> Vec<DocumentHighlight> {
+    use crate::util::char::is_word_forming;
+    if text.len_chars() <= idx || !is_word_forming(text.char(idx)) {
+        return vec![];
+    }
+    let line_start = text.line_to_char(text.char_to_line(idx));
+    let line: Vec<char> = text.line(text.char_to_line(idx)).chars().collect();
+    let at = idx - line_start;
+    let start = at
+        - line[..at]
+            .iter()
+            .rev()
+            .take_while(|&&ch| is_word_forming(ch))
+            .count();
+    let end = at
+        + line[at..]
+            .iter()
+            .take_while(|&&ch| is_word_forming(ch))
+            .count();
+    let word = &line[start..end];
+
+    let mut highlights = vec![];
+    let mut run = 0;
+    let mut run_start = 0;
+    // runs of word forming chars equal to the word
+    for (i, ch) in text.chars().chain(std::iter::once(' ')).enumerate() {
+        if is_word_forming(ch) {
+            if run == 0 {
+                run_start = i;
+            }
+            run += 1;
+        } else {
+            if run == word.len() && text.slice(run_start..i).chars().eq(word.iter().copied()) {
+                highlights.push(DocumentHighlight {
+                    range: run_start..i,
+                    kind: DocumentHighlightKind::Text,
+                });
+            }
+            run = 0;
+        }
+    }
+    highlights
+}
+
+#[test]
+fn merge_test() {
+    use crate::document::Severity;
+    use crate::Idx;
+    let info = |name: &str, priority| ServerInfo {
+        name: name.into(),
+        priority,
+        capabilities: vec![],
+    };
+    let diagnostic = |start, message: &str| Diagnostic {
+        start: Idx(start),
+        end: Idx(start + 1),
+        severity: Severity::Warning,
+        message: message.into(),
+        source: None,
+    };
+    let servers = vec![
+        AttachedServer {
+            info: info("linter", 1),
+            diagnostics: vec![diagnostic(5, "unused"), diagnostic(9, "style")],
+        },
+        AttachedServer {
+            info: info("analyzer", 10),
+            diagnostics: vec![diagnostic(5, "unused")],
+        },
+    ];
+    let merged: Vec<_> = merge_diagnostics(&servers)
+        .into_iter()
+        .map(|d| (d.start.0, d.source.unwrap()))
+        .collect();
+    assert_eq!(
+        merged,
+        vec![(5, "analyzer".to_owned()), (9, "linter".to_owned())]
+    );
+
+    let (linter, analyzer) = (info("linter", 1), info("analyzer", 10));
+    let completions = merge_completions(&[
+        (&linter, vec!["foo".into(), "bar".into()]),
+        (&analyzer, vec!["bar".into(), "baz".into()]),
+    ]);
+    assert_eq!(completions, vec!["bar", "baz", "foo"]);
+}
+
+#[test]
+fn apply_text_edits_test() {
+    let pos = |line, character| Position { line, character };
+    let edit = |start, end, new_text: &str| TextEdit {
+        start,
+        end,
+        new_text: new_text.into(),
+    };
+    let mut text = Rope::from_str("fn foo() {\n    foo();\n}\n");
+    apply_text_edits(
+        &mut text,
+        &[
+            edit(pos(1, 4), pos(1, 7), "bar"),
+            edit(pos(0, 3), pos(0, 6), "bar"),
+            edit(pos(2, 99), pos(2, 99), " // end"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(text.to_string(), "fn bar() {\n    bar();\n} // end\n");
+
+    let orig = text.clone();
+    let overlapping = [
+        edit(pos(0, 0), pos(0, 5), "a"),
+        edit(pos(0, 3), pos(0, 6), "b"),
+    ];
+    assert!(apply_text_edits(&mut text, &overlapping).is_err());
+    assert!(apply_text_edits(&mut text, &[edit(pos(9, 0), pos(9, 0), "x")]).is_err());
+    assert_eq!(text, orig);
+}
+
+#[test]
+fn word_highlights_test() {
+    let text = Rope::from_str("let foo = foo_bar(foo);\nfoo\n");
+    let ranges: Vec<_> = word_highlights(&text, 5)
+        .into_iter()
+        .map(|h| h.range)
+        .collect();
+    assert_eq!(ranges, vec![4..7, 18..21, 24..27]);
+    assert!(word_highlights(&text, 3).is_empty());
+}
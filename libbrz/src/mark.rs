@@ -0,0 +1,165 @@
+//! Marks and jump lists: places of the text to go back to
+//!
+//! A mark is a char of a buffer named by a char, set with `m` and gone to
+//! with `'`. The jump list of a window is where its cursor was before each
+//! large jump, like to a line, a match or a definition, gone back and forth
+//! through with `Ctrl-O` and `Ctrl-I`. Both are kept as chars of the text,
+//! as of some version of it, to move along with the edits.
+use crate::buffer::Buffer;
+use crate::span;
+use std::collections::BTreeMap;
+
+/// Jumps a window remembers
+const JUMP_LIST_CAPACITY: usize = 100;
+
+/// A char of a buffer, as of some version of it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub idx: usize,
+    version: u64,
+}
+
+impl Anchor {
+    pub fn new(idx: usize, buffer: &Buffer) -> Self {
+        Self {
+            idx,
+            version: buffer.version(),
+        }
+    }
+
+    /// Move along the changes made since; if they are too old to know, it
+    /// stays where it was, within the text
+    pub fn follow(&mut self, buffer: &Buffer) {
+        if let Some(changes) = buffer.changes_since(self.version) {
+            for change in changes {
+                self.idx = span::map_range(&(self.idx..self.idx), change).start;
+            }
+        }
+        self.idx = self.idx.min(buffer.text.len_chars());
+        self.version = buffer.version();
+    }
+}
+
+/// The marks of a buffer, by name
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    marks: BTreeMap<char, Anchor>,
+}
+
+impl Marks {
+    pub fn set(&mut self, name: char, idx: usize, buffer: &Buffer) {
+        self.marks.insert(name, Anchor::new(idx, buffer));
+    }
+
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.marks.get(&name).map(|anchor| anchor.idx)
+    }
+
+    pub fn follow(&mut self, buffer: &Buffer) {
+        for anchor in self.marks.values_mut() {
+            anchor.follow(buffer);
+        }
+    }
+}
+
+/// Where the cursor of a window jumped from, by buffer, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct JumpList {
+    jumps: Vec<(usize, Anchor)>,
+    /// Of the jump gone back to, or the number of jumps if not gone back
+    cur: usize,
+}
+
+impl JumpList {
+    /// Note the place a jump leaves from; the jumps gone back from are
+    /// forgotten
+    pub fn push(&mut self, buffer_i: usize, idx: usize, buffer: &Buffer) {
+        self.jumps.truncate(self.cur);
+        if self.jumps.last().map(|&(i, anchor)| (i, anchor.idx)) != Some((buffer_i, idx)) {
+            self.jumps.push((buffer_i, Anchor::new(idx, buffer)));
+        }
+        if JUMP_LIST_CAPACITY < self.jumps.len() {
+            self.jumps.remove(0);
+        }
+        self.cur = self.jumps.len();
+    }
+
+    /// Buffer and char of the jump before, going back from `idx` of
+    /// `buffer_i`, to come forward to again
+    pub fn back(&mut self, buffer_i: usize, idx: usize, buffer: &Buffer) -> Option<(usize, usize)> {
+        if self.cur == self.jumps.len() {
+            self.push(buffer_i, idx, buffer);
+            self.cur = self.jumps.len() - 1;
+        }
+        self.cur = self.cur.checked_sub(1)?;
+        let (buffer_i, anchor) = self.jumps[self.cur];
+        Some((buffer_i, anchor.idx))
+    }
+
+    /// Buffer and char of the jump after the one gone back to
+    pub fn forward(&mut self) -> Option<(usize, usize)> {
+        if self.jumps.len() <= self.cur + 1 {
+            return None;
+        }
+        self.cur += 1;
+        let (buffer_i, anchor) = self.jumps[self.cur];
+        Some((buffer_i, anchor.idx))
+    }
+
+    /// Move along the changes made to the buffers, by index; the jumps to
+    /// buffers closed are forgotten
+    pub fn follow<'a>(&mut self, buffer: impl Fn(usize) -> Option<&'a Buffer>) {
+        let mut kept = vec![];
+        let mut cur = self.cur;
+        for (i, &(buffer_i, mut anchor)) in self.jumps.iter().enumerate() {
+            match buffer(buffer_i) {
+                Some(buffer) => {
+                    anchor.follow(buffer);
+                    kept.push((buffer_i, anchor));
+                }
+                None if i < self.cur => cur -= 1,
+                None => {}
+            }
+        }
+        self.jumps = kept;
+        self.cur = cur.min(self.jumps.len());
+    }
+}
+
+#[test]
+fn mark_test() {
+    use crate::Idx;
+    use ropey::Rope;
+    let mut buffer = Buffer::from_text(Rope::from_str("one two three"));
+    let mut marks = Marks::default();
+    marks.set('a', 4, &buffer);
+    marks.set('b', 8, &buffer);
+    buffer.insert(Idx(0), "zero ");
+    buffer.remove(8..10);
+    marks.follow(&buffer);
+    assert_eq!(buffer.text.to_string(), "zero onewo three");
+    // kept on its char, or where that was removed
+    assert_eq!((marks.get('a'), marks.get('b')), (Some(8), Some(11)));
+    assert_eq!(marks.get('c'), None);
+
+    let mut jumps = JumpList::default();
+    assert_eq!(jumps.back(0, 0, &buffer), None);
+    jumps.push(0, 1, &buffer);
+    jumps.push(1, 5, &buffer);
+    jumps.push(1, 5, &buffer);
+    assert_eq!(jumps.back(0, 9, &buffer), Some((1, 5)));
+    assert_eq!(jumps.back(1, 5, &buffer), Some((0, 1)));
+    assert_eq!(jumps.back(0, 1, &buffer), None);
+    assert_eq!(jumps.forward(), Some((1, 5)));
+    assert_eq!(jumps.forward(), Some((0, 9)));
+    assert_eq!(jumps.forward(), None);
+
+    // a jump after going back forgets those gone back from
+    jumps.back(0, 9, &buffer);
+    jumps.push(1, 5, &buffer);
+    assert_eq!(jumps.forward(), None);
+    buffer.insert(Idx(0), "..");
+    jumps.follow(|buffer_i| Some(&buffer).filter(|_| buffer_i == 0));
+    assert_eq!(jumps.back(0, 0, &buffer), Some((0, 3)));
+    assert_eq!(jumps.back(0, 3, &buffer), None);
+}
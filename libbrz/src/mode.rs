@@ -1,21 +1,67 @@
 use crate::action;
-use crate::idx::*;
+use crate::line_editor::{LineEditor, LineEditorEvent};
+use crate::position::convert::{char_width, str_width};
+use crate::preview;
 use crate::state::State;
+use crate::status;
 use crate::Key;
 use std::cmp::min;
 use std::path::PathBuf;
+use std::time::Duration;
 
 mod command;
+mod conflict;
+mod dropped;
+mod file_history;
 mod find;
+mod find_char;
+mod fuzzy_find;
+mod git_status;
 mod goto;
+mod hierarchy;
+mod hover;
 mod insert;
+mod jump;
+mod line_extend;
+mod mark;
 mod normal;
+mod object;
+mod palette;
+mod register;
+mod search;
+mod sequence;
+mod structure;
+mod transient;
+mod view;
+mod window;
+mod yank_picker;
 
 pub use self::command::Command;
+pub use self::conflict::ConflictView;
+pub use self::dropped::OpenDropped;
+pub use self::file_history::FileHistory;
 pub use self::find::Find;
+pub use self::find_char::FindChar;
+pub use self::fuzzy_find::FuzzyFind;
+pub use self::git_status::GitStatus;
 pub use self::goto::Goto;
+pub use self::hierarchy::Hierarchy;
+pub use self::hover::Hover;
 pub use self::insert::Insert;
+pub use self::jump::Jump;
+pub use self::line_extend::LineExtend;
+pub use self::mark::PickMark;
 pub use self::normal::Normal;
+pub use self::object::PickObject;
+pub use self::palette::Palette;
+pub use self::register::{PickRegister, RegisterUse};
+pub use self::search::Search;
+pub use self::sequence::KeySequence;
+pub use self::structure::Structure;
+pub use self::transient::Transient;
+pub use self::view::View;
+pub use self::window::Window;
+pub use self::yank_picker::YankPicker;
 
 pub use crate::render::{self, Coord, Rect, Renderer};
 
@@ -33,7 +79,7 @@ pub trait Mode {
         let actions = self.actions();
         Box::new(
             self.key_mappings()
-                .into_iter()
+                .iter()
                 .map(move |(key, action)| (key.0, &**actions.get(action).expect("action present"))),
         )
     }
@@ -46,14 +92,19 @@ pub trait Mode {
         action::empty_actions_by_id()
     }
 
-    fn action_by_key(&self, key: super::Key) -> Option<action::ActionRef> {
+    fn action_by_key(&self, key: super::Key) -> Option<action::ActionRef<'_>> {
         self.key_mappings()
             .get(&super::NaturalyOrderedKey(key))
             .and_then(|name| self.actions().get(name))
             .map(|boxed| &**boxed)
     }
 
-    fn on_enter(&mut self, _state: &State) {}
+    fn on_enter(&mut self, _state: &mut State) {}
+
+    /// Time without a key after which the mode goes back to normal
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
 
     fn handle(&mut self, state: &mut State, key: Key);
 
@@ -69,19 +120,72 @@ fn default_render_split_status_rect(render: &mut dyn Renderer) -> (Rect, Rect) {
 
 fn default_render_status(
     mode: &(impl Mode + ?Sized),
+    state: &State,
     mut render: &mut dyn Renderer,
     status_rect: Rect,
 ) {
-    let style = render.color_map().default;
+    let color_map = *render.color_map();
+    let style = color_map.default.paintover(color_map.status);
     let mut status_view = status_rect.to_renderer(&mut render);
+    if style.bg.is_some() {
+        status_view.fill(status_view.dimensions_rect(), ' ', style);
+    }
+    if let Some(ref template) = state.status_template {
+        let line = template.eval(&color_map, |field| match field {
+            status::Field::Mode => Some(mode.name().to_owned()),
+            status::Field::Mode4 => Some(mode.name4().to_owned()),
+            field => state.status_field(field),
+        });
+        let mut x = 0;
+        for (text, style) in &line.left {
+            status_view.print(render::Coord { x, y: 0 }, text, *style);
+            x += str_width(text);
+        }
+        let width: usize = line.right.iter().map(|(text, _)| str_width(text)).sum();
+        let mut x = status_rect.dimensions.x.saturating_sub(width);
+        for (text, style) in &line.right {
+            status_view.print(render::Coord { x, y: 0 }, text, *style);
+            x += str_width(text);
+        }
+        // messages must not go unseen
+        if let (Some(ref msg), false) = (&state.msg, template.uses(status::Field::Msg)) {
+            status_view.print(render::Coord { x: 0, y: 0 }, msg, style);
+        }
+        return;
+    }
+    let mode_x = status_rect.dimensions.x.saturating_sub(4);
     status_view.print(
-        render::Coord {
-            x: status_rect.dimensions.x.saturating_sub(4),
-            y: 0,
-        },
+        render::Coord { x: mode_x, y: 0 },
         mode.name4(),
-        style,
+        style.paintover(color_map.mode),
     );
+    let mut right_x = mode_x;
+    let document = state.cur_document_opt();
+    let reduced = document
+        .filter(|document| document.reduced)
+        .map(|_| "reduced".to_owned());
+    let unwritable = document
+        .filter(|document| document.unwritable)
+        .map(|_| "unwritable".to_owned());
+    let format = document.and_then(|document| document.format.indicator());
+    for indicator in [
+        state.recording_indicator(),
+        state.match_indicator(),
+        state.task_summary(),
+        state.todo_summary(),
+        reduced,
+        unwritable,
+        format,
+    ]
+    .iter()
+    .flatten()
+    {
+        right_x = right_x.saturating_sub(str_width(indicator) + 1);
+        status_view.print(render::Coord { x: right_x, y: 0 }, indicator, style);
+    }
+    if let Some(ref msg) = state.msg {
+        status_view.print(render::Coord { x: 0, y: 0 }, msg, style);
+    }
 }
 
 fn default_render(
@@ -95,17 +199,154 @@ fn default_render(
         default_render_available_actions(mode, state, render, buffer_rect);
     }
 
-    default_render_status(mode, render, status_rect);
+    default_render_status(mode, state, render, status_rect);
 
     (buffer_rect, status_rect)
 }
 
+/// Pass `key` to the `editor` of a prompt, sharing the yank register
+fn handle_prompt_key(editor: &mut LineEditor, state: &mut State, key: Key) -> LineEditorEvent {
+    editor.register = state.yanked.clone();
+    let event = editor.handle_key(key);
+    if editor.register != state.yanked {
+        state.set_yanked(editor.register.clone());
+    }
+    event
+}
+
+/// Render a prompt on the status line: `prefix`, the `editor` and `suffix`
+fn render_prompt(
+    editor: &LineEditor,
+    prefix: &str,
+    suffix: &str,
+    mut render: &mut dyn Renderer,
+    status_rect: Rect,
+) {
+    let style = render.color_map().default;
+    let mut status_view = status_rect.to_renderer(&mut render);
+    let prefix_len = str_width(prefix);
+    let room = status_rect.dimensions.x.saturating_sub(prefix_len);
+    // the suffix gives way to the text when there's not enough room
+    let text_width = str_width(&editor.text());
+    let suffix_room = room.saturating_sub(text_width + 1);
+    let mut suffix_width = 0;
+    let suffix: String = suffix
+        .chars()
+        .take_while(|&ch| {
+            suffix_width += char_width(ch);
+            suffix_width <= suffix_room
+        })
+        .collect();
+    let width = room - str_width(&suffix);
+    status_view.print(render::Coord { x: 0, y: 0 }, prefix, style);
+    editor.render(
+        &mut status_view,
+        render::Coord {
+            x: prefix_len,
+            y: 0,
+        },
+        width,
+        style,
+        true,
+    );
+    let text_len = text_width.min(width);
+    status_view.print(
+        render::Coord {
+            x: prefix_len + text_len,
+            y: 0,
+        },
+        &suffix,
+        style,
+    );
+}
+
+/// Draw a box floating over the middle of what's drawn, of `width` and
+/// `height` percents of it, with `title` in its top border, themed as
+/// an `overlay` pane; the rect inside the border, to draw in
+pub(crate) fn render_overlay(
+    state: &State,
+    render: &mut dyn Renderer,
+    width: usize,
+    height: usize,
+    title: &str,
+) -> Rect {
+    let dims = render.dimensions();
+    let size = Coord {
+        x: (dims.x * width / 100).max(20).min(dims.x),
+        y: (dims.y * height / 100).max(5).min(dims.y),
+    };
+    let rect = Rect {
+        offset: Coord {
+            x: (dims.x - size.x) / 2,
+            y: (dims.y - size.y) / 2,
+        },
+        dimensions: size,
+    };
+    state.invalidation().invalidate_region(Rect {
+        offset: render.origin() + rect.offset,
+        ..rect
+    });
+    let mut render = state.themed(render, "overlay");
+    let style = render.color_map().default;
+    let border = render.color_map().line_num;
+    let mut view = rect.to_renderer(&mut render);
+    let (right, bottom) = (size.x.saturating_sub(1), size.y.saturating_sub(1));
+    view.fill(view.dimensions_rect(), ' ', style);
+    for x in 1..right {
+        view.put(Coord { x, y: 0 }, '─', border);
+        view.put(Coord { x, y: bottom }, '─', border);
+    }
+    for y in 1..bottom {
+        view.put(Coord { x: 0, y }, '│', border);
+        view.put(Coord { x: right, y }, '│', border);
+    }
+    for (x, y, ch) in [
+        (0, 0, '┌'),
+        (right, 0, '┐'),
+        (0, bottom, '└'),
+        (right, bottom, '┘'),
+    ] {
+        view.put(Coord { x, y }, ch, border);
+    }
+    view.print(Coord { x: 2, y: 0 }, &format!(" {} ", title), border);
+    Rect {
+        offset: rect.offset + Coord { x: 1, y: 1 },
+        dimensions: Coord {
+            x: size.x.saturating_sub(2),
+            y: size.y.saturating_sub(2),
+        },
+    }
+}
+
+/// Print an entry of a picker at `coord`, with the chars at `indices`,
+/// those found by the pattern typed, painted over with `picker_match`
+pub(crate) fn print_picker_entry(
+    render: &mut dyn Renderer,
+    coord: Coord,
+    text: &str,
+    indices: &[usize],
+    style: render::Style,
+) {
+    render.print(coord, text, style);
+    let match_style = style.paintover(render.color_map().picker_match);
+    let mut x = coord.x;
+    let mut indices = indices.iter().peekable();
+    for (i, ch) in text.chars().enumerate() {
+        if indices.next_if_eq(&&i).is_some() {
+            render.put_cell(Coord { x, y: coord.y }, render::Cell::new(ch, match_style));
+        }
+        x += char_width(ch);
+    }
+}
+
 fn default_render_available_actions(
     mode: &(impl Mode + ?Sized),
     state: &State,
-    mut render: &mut dyn Renderer,
+    render: &mut dyn Renderer,
     buffer_rect: Rect,
 ) {
+    let mut render = state.themed(render, "help");
+    let mut render: &mut dyn Renderer = &mut render;
     let actions = mode.key_mappings();
 
     if actions.is_empty() {
@@ -132,6 +373,10 @@ fn default_render_available_actions(
             .0
     };
 
+    state.invalidation().invalidate_region(Rect {
+        offset: render.origin() + rect.offset,
+        ..rect
+    });
     let mut view = rect.to_renderer(&mut render);
     view.fill(view.dimensions_rect(), ' ', style);
     view.print_centered(
@@ -1,13 +1,28 @@
 use super::*;
 
+use crate::command;
+
 #[derive(Clone, Debug, Default)]
 pub struct Command {
-    cmd: String,
+    editor: LineEditor,
+    /// Shown after the command
+    hint: String,
+    /// Of the last `Tab`, shown instead of the hint until the next key
+    completions: String,
 }
 
 impl Command {
     pub fn new() -> Self {
-        Self { cmd: String::new() }
+        Self::default()
+    }
+
+    /// Start with the `text` of a command to complete, eg. after it failed
+    pub fn with_text(text: &str, hint: &str) -> Self {
+        Self {
+            editor: LineEditor::new().with_text(text),
+            hint: hint.to_owned(),
+            completions: String::new(),
+        }
     }
 }
 
@@ -17,69 +32,84 @@ impl Mode for Command {
     }
 
     fn cmd_string(&self) -> Option<String> {
-        Some(format!(":{}", self.cmd))
+        Some(format!(":{}", self.editor.text()))
     }
 
     fn handle(&mut self, state: &mut State, key: Key) {
-        match key {
-            Key::Esc => {
-                state.set_mode(Normal::default());
-            }
-            Key::Char('\n') => {
-                self.handle_command_complete(state);
-                state.set_mode(Normal::default());
-            }
-            Key::Char(ch) => {
-                self.cmd.push(ch);
-            }
-            Key::Backspace => {
-                self.cmd.pop();
+        if key == Key::Char('\t') && self.editor.is_insert() {
+            self.complete(state);
+            return;
+        }
+        self.completions.clear();
+        match handle_prompt_key(&mut self.editor, state, key) {
+            LineEditorEvent::Continue => {}
+            LineEditorEvent::Submit(cmd) => {
+                state.set_mode(Normal);
+                state.last_command = Some(cmd.clone());
+                Self::run(state, &cmd);
             }
-            _ => {}
+            LineEditorEvent::Cancel => state.set_mode(Normal),
         }
     }
 
-    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+    fn render(&self, state: &State, render: &mut dyn Renderer) {
         let (_, status_rect) = super::default_render(self, state, render);
-        let style = render.color_map().default;
-        let mut status_view = status_rect.to_renderer(&mut render);
-        status_view.print(
-            render::Coord { x: 0, y: 0 },
-            &format!(":{}", self.cmd),
-            style,
-        );
+        let hint = if self.completions.is_empty() {
+            &self.hint
+        } else {
+            &self.completions
+        };
+        render_prompt(&self.editor, ":", hint, render, status_rect);
     }
 }
 
 impl Command {
-    fn handle_command_complete(&self, state: &mut State) {
-        let cmd: Vec<_> = self.cmd.split_whitespace().map(str::to_owned).collect();
-        if cmd.is_empty() {
+    /// Run a command line, as if typed at the prompt
+    pub(crate) fn run(state: &mut State, line: &str) {
+        let trimmed = line.trim_start();
+        if let Some(pipe) = trimmed
+            .strip_prefix('|')
+            .or_else(|| trimmed.strip_prefix('!'))
+        {
+            match pipe.trim() {
+                "" => state.msg = Some(format!("usage: {}<command>", &trimmed[..1])),
+                command => state.pipe_selections(command, trimmed.starts_with('!')),
+            }
             return;
         }
-
-        match cmd[0].as_str() {
-            "q" => {
-                state.quit = true;
-            }
-            "bn" => {
-                state.buffer_next();
-            }
-            "bp" => {
-                state.buffer_prev();
-            }
-            "e" => {
-                for s in &cmd[1..] {
-                    state.open_buffer(&PathBuf::from(s))
-                }
-            }
-            "db" => {
-                state.delete_buffer();
-            }
-            "w" => {
-                state.write_buffer(cmd.get(1).map(PathBuf::from));
+        let (name, rest) = match trimmed.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest),
+            None => (trimmed.trim_end(), ""),
+        };
+        if name.is_empty() {
+            return;
+        }
+        let def = match state.commands.get(name) {
+            Some(def) => def.clone(),
+            None => {
+                state.msg = Some(format!("unrecognized command: {}", line));
+                return;
             }
-            _ => state.msg = Some(format!("unrecognized command: {}", self.cmd)),
+        };
+        if let Err(msg) = def.run(state, name, rest) {
+            state.msg = Some(msg);
+        }
+    }
+
+    /// Complete the command line before the cursor: to what all the
+    /// completions start with, listing them if there's more than one
+    fn complete(&mut self, state: &State) {
+        let list_dir = state.list_dir_handler.clone();
+        let before = self.editor.before_cursor();
+        let (len, completions) = state.commands.complete(&before, |dir| list_dir(dir));
+        let prefix = command::common_prefix(&completions);
+        self.completions = match completions.len() {
+            0 => "  no completions".to_owned(),
+            1 => String::new(),
+            _ => format!("  {}", completions.join(" ")),
+        };
+        if !prefix.is_empty() {
+            self.editor.replace_before_cursor(len, prefix);
         }
     }
 }
@@ -0,0 +1,102 @@
+use super::*;
+
+use crate::conflict::{Conflict, LineKind, Pick};
+
+/// Three-way view of the merge conflict under the cursor
+///
+/// Shows ours, the base (if the file has it) and theirs side by side.
+/// `o`, `t`, `b` and `B` resolve the conflict with ours, theirs, both or
+/// the base, `n` and `p` move to the next and previous conflict.
+#[derive(Clone, Debug, Default)]
+pub struct ConflictView;
+
+impl ConflictView {
+    fn cur_conflict(state: &State) -> Option<&Conflict> {
+        let line = state
+            .cur_view_opt()?
+            .selection
+            .cursor_coord(&state.cur_buffer_opt()?.text)
+            .line;
+        state.cur_conflicts()?.at_line(line)
+    }
+
+    fn pick(state: &mut State, pick: Pick) {
+        state.set_mode(Normal);
+        state.pick_conflict(pick);
+    }
+}
+
+impl Mode for ConflictView {
+    fn name(&self) -> &str {
+        "conflict"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        if Self::cur_conflict(state).is_none() {
+            state.set_mode(Normal);
+            return;
+        }
+        match key {
+            Key::Char('o') => Self::pick(state, Pick::Ours),
+            Key::Char('t') => Self::pick(state, Pick::Theirs),
+            Key::Char('b') => Self::pick(state, Pick::Both),
+            Key::Char('B') => Self::pick(state, Pick::Base),
+            Key::Char('n') => state.goto_conflict(false),
+            Key::Char('p') => state.goto_conflict(true),
+            Key::Esc | Key::Char('q') => state.set_mode(Normal),
+            _ => {}
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (main_rect, status_rect) = super::default_render_split_status_rect(render);
+        default_render_status(self, state, render, status_rect);
+
+        let color_map = *render.color_map();
+        let conflict = match Self::cur_conflict(state) {
+            Some(conflict) => conflict,
+            None => {
+                render.print(
+                    render::Coord { x: 0, y: 0 },
+                    "no conflict under the cursor",
+                    color_map.special,
+                );
+                return;
+            }
+        };
+        let text = &state.cur_buffer().text;
+
+        let mut sections = vec![("ours", LineKind::Ours, conflict.ours.clone())];
+        if let Some(ref base) = conflict.base {
+            sections.push(("base", LineKind::Base, base.clone()));
+        }
+        sections.push(("theirs", LineKind::Theirs, conflict.theirs.clone()));
+
+        let width = main_rect.dimensions.x / sections.len();
+        for (i, (title, kind, lines)) in sections.into_iter().enumerate() {
+            let rect = Rect {
+                offset: main_rect.offset.add_x(i * width),
+                dimensions: render::Coord {
+                    x: width.saturating_sub(1),
+                    y: main_rect.dimensions.y,
+                },
+            };
+            let mut view = rect.to_renderer(&mut render);
+            view.print(
+                render::Coord { x: 0, y: 0 },
+                title,
+                color_map.conflict_marker,
+            );
+            let style = color_map.default.paintover(color_map.conflict_line(kind));
+            let mut text_view = Rect {
+                offset: render::Coord { x: 0, y: 1 },
+                dimensions: render::Coord {
+                    x: rect.dimensions.x,
+                    y: rect.dimensions.y.saturating_sub(1),
+                },
+            }
+            .to_renderer(&mut view);
+            render::print_text(&mut text_view, &conflict.section(text, lines), style);
+        }
+    }
+}
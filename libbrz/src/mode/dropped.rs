@@ -0,0 +1,61 @@
+use super::*;
+
+/// Ask whether to open files dropped on the terminal, or to paste their
+/// paths as text in the mode the drop came in
+pub struct OpenDropped {
+    paths: Vec<PathBuf>,
+    text: String,
+    previous: Option<Box<dyn Mode>>,
+}
+
+impl OpenDropped {
+    pub fn new(paths: Vec<PathBuf>, text: String, previous: Box<dyn Mode>) -> Self {
+        Self {
+            paths,
+            text,
+            previous: Some(previous),
+        }
+    }
+
+    fn question(&self) -> String {
+        let files = match self.paths.as_slice() {
+            [path] => path.display().to_string(),
+            paths => format!("{} dropped files", paths.len()),
+        };
+        format!("open {}? y: open, n: paste as text, esc: drop", files)
+    }
+}
+
+impl Mode for OpenDropped {
+    fn name(&self) -> &str {
+        "dropped"
+    }
+
+    fn cmd_string(&self) -> Option<String> {
+        Some(self.question())
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Char('y') | Key::Char('\n') => {
+                state.set_mode(Normal);
+                for path in &self.paths {
+                    state.open_buffer(path);
+                }
+            }
+            Key::Char('n') => {
+                state.restore_mode(self.previous.take().expect("previous mode"));
+                state.type_text(&self.text);
+            }
+            Key::Esc => state.restore_mode(self.previous.take().expect("previous mode")),
+            _ => {}
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (_, status_rect) = default_render(self, state, render);
+        let style = render.color_map().special;
+        let mut status_view = status_rect.to_renderer(&mut render);
+        status_view.print(render::Coord { x: 0, y: 0 }, &self.question(), style);
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+
+use crate::diff;
+use crate::document::Document;
+use crate::git::{FileRevision, Revision};
+use ropey::Rope;
+
+/// Picker of the git revisions of the current file
+///
+/// The diff between the selected revision and the working copy is shown
+/// next to the list, and `Enter` opens the revision read-only. From
+/// there `:restore` copies the selected lines back to the working copy.
+#[derive(Default, Debug, Clone)]
+pub struct FileHistory {
+    path: PathBuf,
+    source_buffer_i: usize,
+    revisions: Vec<Revision>,
+    selected: usize,
+    /// Content of the selected revision, if it could be loaded
+    content: Option<String>,
+    /// Diff against the working copy, or why the content couldn't be loaded
+    diff: String,
+}
+
+impl FileHistory {
+    fn update_content(&mut self, state: &State) {
+        let revision = match self.revisions.get(self.selected) {
+            Some(revision) => revision,
+            None => return,
+        };
+        let content = state.git_show(&revision.hash, &self.path);
+        self.diff = match content {
+            Ok(ref content) => {
+                let old = diff::rope_lines(&Rope::from_str(content));
+                let new = diff::rope_lines(&state.cur_buffer().text);
+                let hunks = diff::diff_lines(&old, &new);
+                if hunks.is_empty() {
+                    "same as the working copy".into()
+                } else {
+                    diff::unified(&old, &new, &hunks, 3)
+                }
+            }
+            Err(ref e) => e.to_string(),
+        };
+        self.content = content.ok();
+    }
+}
+
+impl Mode for FileHistory {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        let path = match (state.cur_buffer_i(), state.cur_document_opt()) {
+            (
+                Some(buffer_i),
+                Some(Document {
+                    path: Some(path), ..
+                }),
+            ) => {
+                self.source_buffer_i = buffer_i;
+                path.clone()
+            }
+            _ => {
+                state.msg = Some("buffer has no file".into());
+                return;
+            }
+        };
+        self.revisions = state.git_log(&path).unwrap_or_default();
+        self.path = path;
+        if self.revisions.is_empty() && state.msg.is_none() {
+            state.msg = Some("no history".into());
+        }
+        self.update_content(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Esc | Key::Char('q') => state.set_mode(Normal),
+            Key::Up | Key::Char('k') | Key::Ctrl('p') => {
+                self.selected = self.selected.saturating_sub(1);
+                self.update_content(state);
+            }
+            Key::Down | Key::Char('j') | Key::Ctrl('n') => {
+                if self.selected + 1 < self.revisions.len() {
+                    self.selected += 1;
+                }
+                self.update_content(state);
+            }
+            Key::Char('\n') => {
+                state.set_mode(Normal);
+                match (self.revisions.get(self.selected), &self.content) {
+                    (Some(revision), Some(content)) => state.open_file_revision(
+                        content,
+                        FileRevision {
+                            path: self.path.clone(),
+                            revision: revision.clone(),
+                            source_buffer_i: self.source_buffer_i,
+                        },
+                    ),
+                    (Some(_), None) => state.msg = Some(self.diff.clone()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (main_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (list_rect, diff_rect) = main_rect.split_verticaly();
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_special = render.color_map().special;
+        let mut list_view = list_rect.to_renderer(&mut render);
+        let height = list_view.dimensions().y;
+        let first = (self.selected + 1).saturating_sub(height);
+        for (y, (i, revision)) in self
+            .revisions
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            list_view.print(
+                render::Coord { x: 0, y },
+                &format!("{} {} {}", revision.short, revision.date, revision.subject),
+                if i == self.selected {
+                    style_selected
+                } else {
+                    style_default
+                },
+            );
+        }
+
+        render::print_text(
+            &mut diff_rect.to_renderer(&mut render),
+            &self.diff,
+            style_special,
+        );
+    }
+}
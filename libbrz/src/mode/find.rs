@@ -2,15 +2,68 @@ use super::*;
 
 #[derive(Default, Debug, Clone)]
 pub struct Find {
-    match_str: String,
+    editor: LineEditor,
     cur_matches: Vec<PathBuf>,
+    selected: usize,
 }
 
 impl Find {
-    fn update_matches(&mut self, state: &State) {
-        self.cur_matches = (state.find_handler)(&self.match_str)
+    fn update_matches(&mut self, state: &mut State) {
+        self.cur_matches = (state.find_handler)(&self.editor.text())
             .ok()
-            .unwrap_or_else(|| vec![]);
+            .unwrap_or_default();
+        self.selected = 0;
+        self.update_preview(state);
+    }
+
+    fn update_preview(&self, state: &mut State) {
+        let path = self.cur_matches.get(self.selected).cloned();
+        state.request_preview(path.as_deref());
+    }
+
+    fn leave(&mut self, state: &mut State) {
+        self.editor = LineEditor::new();
+        state.request_preview(None);
+        state.set_mode(Normal);
+    }
+
+    fn render_preview(&self, state: &State, render: &mut dyn Renderer) {
+        let style = render.color_map().default;
+        let style_special = render.color_map().special;
+        let dims = render.dimensions();
+        if style.bg.is_some() {
+            render.fill(render.dimensions_rect(), ' ', style);
+        }
+        let (text, truncated) = match state.cur_preview() {
+            None => return,
+            Some(preview::Preview::Text { text, truncated }) => (text, *truncated),
+            Some(preview::Preview::Loading) => {
+                render.print(Coord { x: 0, y: 0 }, "loading…", style_special);
+                return;
+            }
+            Some(preview::Preview::Binary) => {
+                render.print(Coord { x: 0, y: 0 }, "binary file", style_special);
+                return;
+            }
+            Some(preview::Preview::Failed(e)) => {
+                render.print(Coord { x: 0, y: 0 }, e, style_special);
+                return;
+            }
+        };
+
+        let mut y = 0;
+        for line in text.lines().take(dims.y) {
+            let line: String = line
+                .chars()
+                .filter(|&ch| ch != '\n')
+                .map(|ch| if ch == '\t' { ' ' } else { ch })
+                .collect();
+            render.print(Coord { x: 0, y }, &line, style);
+            y += 1;
+        }
+        if truncated && y < dims.y {
+            render.print(Coord { x: 0, y }, "…", style_special);
+        }
     }
 }
 impl Mode for Find {
@@ -18,33 +71,38 @@ impl Mode for Find {
         "find"
     }
 
-    fn on_enter(&mut self, state: &State) {
+    fn on_enter(&mut self, state: &mut State) {
         self.update_matches(state);
     }
 
     fn handle(&mut self, state: &mut State, key: Key) {
         match key {
-            Key::Esc => {
-                self.match_str = "".into();
-                state.set_mode(Normal::default());
+            Key::Up | Key::Ctrl('p') => {
+                if self.selected + 1 < self.cur_matches.len() {
+                    self.selected += 1;
+                }
+                self.update_preview(state);
                 return;
             }
-            Key::Backspace => {
-                self.match_str.pop();
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = self.selected.saturating_sub(1);
+                self.update_preview(state);
+                return;
             }
-            Key::Char('\n') => {
-                if let Some(path) = self.cur_matches.get(0) {
-                    state.open_buffer(path);
+            _ => {}
+        }
+        let text = self.editor.text();
+        match handle_prompt_key(&mut self.editor, state, key) {
+            LineEditorEvent::Continue if self.editor.text() != text => self.update_matches(state),
+            LineEditorEvent::Continue => {}
+            LineEditorEvent::Submit(_) => {
+                if let Some(path) = self.cur_matches.get(self.selected).cloned() {
+                    state.open_buffer(&path);
                 }
-                self.match_str = "".into();
-                state.set_mode(Normal::default());
+                self.leave(state);
             }
-            Key::Char(ch) => {
-                self.match_str.push(ch);
-            }
-            _ => return,
+            LineEditorEvent::Cancel => self.leave(state),
         }
-        self.update_matches(state)
     }
 
     fn render(&self, state: &State, mut render: &mut dyn Renderer) {
@@ -52,9 +110,14 @@ impl Mode for Find {
         let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
         let (buffer_rect, results_rect) =
             buffer_rect.split_horizontaly_at(-(dimensions.y as isize / 4));
-        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+        if state.cur_preview().is_some() {
+            let mut preview = buffer_rect.to_renderer(&mut render);
+            self.render_preview(state, &mut state.themed(&mut preview, "preview"));
+        } else {
+            state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+        }
 
-        default_render_status(self, render, status_rect);
+        default_render_status(self, state, render, status_rect);
 
         let style_default = render.color_map().default;
         let style_selected = render.color_map().selection;
@@ -67,7 +130,7 @@ impl Mode for Find {
                     y: view_y.saturating_sub(1).saturating_sub(i),
                 },
                 &format!("{}", match_.display()),
-                if i == 0 {
+                if i == self.selected {
                     style_selected
                 } else {
                     style_default
@@ -75,11 +138,6 @@ impl Mode for Find {
             );
         }
 
-        let mut status_view = status_rect.to_renderer(&mut render);
-        status_view.print(
-            render::Coord { x: 0, y: 0 },
-            &format!("find: {}", self.match_str),
-            style_default,
-        );
+        render_prompt(&self.editor, "find: ", "", render, status_rect);
     }
 }
@@ -0,0 +1,48 @@
+use super::*;
+
+use crate::motion::CharFind;
+
+/// Waiting for the char of a find motion, entered with `f` and `t`
+#[derive(Clone, Debug)]
+pub struct FindChar {
+    till: bool,
+    backward: bool,
+    extend: bool,
+    /// Count typed before the key, the occurrence to go to
+    n: usize,
+}
+
+impl FindChar {
+    pub fn new(till: bool, backward: bool, extend: bool, n: usize) -> Self {
+        Self {
+            till,
+            backward,
+            extend,
+            n,
+        }
+    }
+}
+
+impl Mode for FindChar {
+    fn name(&self) -> &str {
+        if self.till {
+            "till"
+        } else {
+            "find"
+        }
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        let ch = match key {
+            Key::Char(ch) => ch,
+            _ => return,
+        };
+        let find = CharFind {
+            ch,
+            till: self.till,
+            backward: self.backward,
+        };
+        state.find_char(find, self.n, self.extend);
+    }
+}
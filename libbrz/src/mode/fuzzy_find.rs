@@ -0,0 +1,112 @@
+use super::*;
+
+/// Pick a file of the project, fuzzily, in a box over the windows
+///
+/// The files come in while the project is walked, as described in
+/// `finder`, from `State::list_files` on; the best matches are on top,
+/// `Enter` opens the one selected in the current window.
+#[derive(Default, Debug, Clone)]
+pub struct FuzzyFind {
+    editor: LineEditor,
+    selected: usize,
+}
+
+impl FuzzyFind {
+    fn leave(&mut self, state: &mut State) {
+        state.stop_listing_files();
+        state.set_mode(Normal);
+    }
+}
+
+impl Mode for FuzzyFind {
+    fn name(&self) -> &str {
+        "files"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        let count = state
+            .file_list
+            .as_ref()
+            .map_or(0, |list| list.match_count());
+        match key {
+            Key::Down | Key::Ctrl('n') => {
+                if self.selected + 1 < count {
+                    self.selected += 1;
+                }
+                return;
+            }
+            Key::Up | Key::Ctrl('p') => {
+                self.selected = self.selected.saturating_sub(1);
+                return;
+            }
+            _ => {}
+        }
+        let text = self.editor.text();
+        match handle_prompt_key(&mut self.editor, state, key) {
+            LineEditorEvent::Continue if self.editor.text() != text => {
+                let pattern = self.editor.text();
+                if let Some(list) = state.file_list.as_mut() {
+                    list.set_pattern(&pattern);
+                }
+                self.selected = 0;
+            }
+            LineEditorEvent::Continue => {}
+            LineEditorEvent::Submit(_) => {
+                let path = state
+                    .file_list
+                    .as_ref()
+                    .and_then(|list| list.matches().nth(self.selected))
+                    .map(|(path, _)| path.clone());
+                self.leave(state);
+                if let Some(path) = path {
+                    state.open_buffer(&path);
+                }
+            }
+            LineEditorEvent::Cancel => self.leave(state),
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+        default_render_status(self, state, render, status_rect);
+
+        let list = match state.file_list {
+            Some(ref list) => list,
+            None => return,
+        };
+        let title = format!(
+            "files {}/{}{}",
+            list.match_count(),
+            list.len(),
+            if list.done { "" } else { "…" }
+        );
+        let mut area = buffer_rect.to_renderer(&mut render);
+        let inner = super::render_overlay(state, &mut area, 80, 60, &title);
+        let mut themed = state.themed(&mut area, "overlay");
+        let mut view = inner.to_renderer(&mut themed);
+        let (prompt_rect, results_rect) = view.dimensions_rect().split_horizontaly_at(1);
+        let style_default = view.color_map().default;
+        let style_selected = view.color_map().selection;
+        // the selected one in sight
+        let rows = results_rect.dimensions.y;
+        let skip = (self.selected + 1).saturating_sub(rows);
+        for (i, (path, indices)) in list.matches().enumerate().skip(skip).take(rows) {
+            super::print_picker_entry(
+                &mut view,
+                Coord {
+                    x: 1,
+                    y: results_rect.offset.y + i - skip,
+                },
+                &path.display().to_string(),
+                indices,
+                if i == self.selected {
+                    style_selected
+                } else {
+                    style_default
+                },
+            );
+        }
+        render_prompt(&self.editor, "> ", "", &mut view, prompt_rect);
+    }
+}
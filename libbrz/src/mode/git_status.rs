@@ -0,0 +1,122 @@
+use super::*;
+
+use crate::git::{FileStatus, GitRequest};
+
+/// Picker of the files with changes
+///
+/// `s` stages the selected file, `u` unstages it, `c` starts a commit
+/// and `a` amends the last one. The diff of the selected file is shown
+/// next to the list.
+#[derive(Default, Debug, Clone)]
+pub struct GitStatus {
+    files: Vec<FileStatus>,
+    selected: usize,
+    diff: String,
+}
+
+impl GitStatus {
+    fn refresh(&mut self, state: &mut State) {
+        self.files = state.git_status().unwrap_or_default();
+        if self.files.is_empty() && state.msg.is_none() {
+            state.msg = Some("no changes".into());
+        }
+        self.selected = min(self.selected, self.files.len().saturating_sub(1));
+        self.update_diff(state);
+    }
+
+    fn update_diff(&mut self, state: &State) {
+        self.diff = match self.files.get(self.selected) {
+            Some(file) if file.is_untracked() => "untracked file".into(),
+            Some(file) => state.git_diff(!file.is_unstaged(), Some(file.path.clone())),
+            None => String::new(),
+        };
+    }
+
+    fn selected_path(&self) -> Option<PathBuf> {
+        self.files.get(self.selected).map(|file| file.path.clone())
+    }
+}
+
+impl Mode for GitStatus {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn name4(&self) -> &str {
+        "git "
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        self.refresh(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Esc | Key::Char('q') => state.set_mode(Normal),
+            Key::Up | Key::Char('k') | Key::Ctrl('p') => {
+                self.selected = self.selected.saturating_sub(1);
+                self.update_diff(state);
+            }
+            Key::Down | Key::Char('j') | Key::Ctrl('n') => {
+                if self.selected + 1 < self.files.len() {
+                    self.selected += 1;
+                }
+                self.update_diff(state);
+            }
+            Key::Char('s') => {
+                if let Some(path) = self.selected_path() {
+                    state.git_run(&GitRequest::Stage(path));
+                    self.refresh(state);
+                }
+            }
+            Key::Char('u') => {
+                if let Some(path) = self.selected_path() {
+                    state.git_run(&GitRequest::Unstage(path));
+                    self.refresh(state);
+                }
+            }
+            Key::Char('c') | Key::Char('a') => {
+                state.set_mode(Normal);
+                state.git_commit_buffer(key == Key::Char('a'));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (main_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (list_rect, diff_rect) = main_rect.split_verticaly();
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_special = render.color_map().special;
+        let mut list_view = list_rect.to_renderer(&mut render);
+        let height = list_view.dimensions().y;
+        let first = (self.selected + 1).saturating_sub(height);
+        for (y, (i, file)) in self
+            .files
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            list_view.print(
+                render::Coord { x: 0, y },
+                &format!("{}{} {}", file.staged, file.unstaged, file.path.display()),
+                if i == self.selected {
+                    style_selected
+                } else {
+                    style_default
+                },
+            );
+        }
+
+        render::print_text(
+            &mut diff_rect.to_renderer(&mut render),
+            &self.diff,
+            style_special,
+        );
+    }
+}
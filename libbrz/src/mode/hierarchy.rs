@@ -0,0 +1,168 @@
+use super::*;
+
+use crate::hierarchy::{HierarchyKind, HierarchyRequest, Tree};
+use crate::remote::Location;
+
+/// Tree of the calls or types related to the item under the cursor
+///
+/// `l` or `Tab` expands the selected item, `h` collapses it (or goes to
+/// its parent) and `Enter` jumps to it. The tree is shown next to the
+/// buffer.
+#[derive(Debug, Clone)]
+pub struct Hierarchy {
+    kind: HierarchyKind,
+    tree: Tree,
+    selected: usize,
+}
+
+impl Hierarchy {
+    pub fn new(kind: HierarchyKind) -> Self {
+        Self {
+            kind,
+            tree: Tree::default(),
+            selected: 0,
+        }
+    }
+
+    fn expand(&mut self, state: &mut State) {
+        let children = match self.tree.nodes.get(self.selected) {
+            Some(node) if self.tree.needs_children(self.selected) => {
+                let request = HierarchyRequest::Children {
+                    kind: self.kind,
+                    item: node.item.clone(),
+                };
+                match state.hierarchy(&request) {
+                    Some(children) => Some(children),
+                    None => return,
+                }
+            }
+            _ => None,
+        };
+        self.tree.expand(self.selected, children);
+    }
+
+    fn collapse(&mut self) {
+        match self.tree.nodes.get(self.selected) {
+            Some(node) if node.expanded => self.tree.collapse(self.selected),
+            Some(_) => {
+                if let Some(parent) = self.tree.parent(self.selected) {
+                    self.selected = parent;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+impl Mode for Hierarchy {
+    fn name(&self) -> &str {
+        "hierarchy"
+    }
+
+    fn name4(&self) -> &str {
+        "hier"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        let path = match state.cur_document_opt().and_then(|d| d.path.clone()) {
+            Some(path) => path,
+            None => {
+                state.msg = Some("buffer has no file".into());
+                return;
+            }
+        };
+        let cursor = state
+            .cur_view()
+            .selection
+            .cursor_coord(&state.cur_buffer().text);
+        let request = HierarchyRequest::Prepare {
+            kind: self.kind,
+            path,
+            position: crate::lsp::Position {
+                line: cursor.line,
+                character: cursor.column,
+            },
+        };
+        self.tree = Tree::new(state.hierarchy(&request).unwrap_or_default());
+        if self.tree.nodes.is_empty() {
+            if state.msg.is_none() {
+                state.msg = Some(format!("no {} here", self.kind.title()));
+            }
+            return;
+        }
+        // the calls or types of the item are what was asked for
+        self.expand(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Esc | Key::Char('q') => state.set_mode(Normal),
+            Key::Up | Key::Char('k') | Key::Ctrl('p') => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            Key::Down | Key::Char('j') | Key::Ctrl('n')
+                if self.selected + 1 < self.tree.nodes.len() =>
+            {
+                self.selected += 1;
+            }
+            Key::Char('l') | Key::Char('\t') | Key::Right => self.expand(state),
+            Key::Char('h') | Key::Left => self.collapse(),
+            Key::Char('\n') => {
+                if let Some(node) = self.tree.nodes.get(self.selected) {
+                    let location = Location {
+                        path: node.item.path.clone(),
+                        line: Some(node.item.position.line + 1),
+                    };
+                    state.set_mode(Normal);
+                    state.open_location(&location);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (main_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (buffer_rect, tree_rect) = main_rect.split_verticaly();
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_special = render.color_map().special;
+        let mut tree_view = tree_rect.to_renderer(&mut render);
+        tree_view.print(
+            render::Coord { x: 0, y: 0 },
+            self.kind.title(),
+            style_special,
+        );
+        let height = tree_view.dimensions().y.saturating_sub(1);
+        let first = (self.selected + 1).saturating_sub(height);
+        for (y, (i, node)) in self
+            .tree
+            .nodes
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if node.expanded { '▾' } else { '▸' };
+            tree_view.print(
+                render::Coord { x: 0, y: y + 1 },
+                &format!(
+                    "{}{} {} {}",
+                    "  ".repeat(node.depth),
+                    marker,
+                    node.item.name,
+                    node.item.detail
+                ),
+                if i == self.selected {
+                    style_selected
+                } else {
+                    style_default
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use super::*;
+
+/// What a language server says of the symbol under the cursor, in a box
+/// over the windows; `Esc` closes it, any other key too and is handled
+/// in normal mode
+#[derive(Clone, Debug)]
+pub struct Hover {
+    text: String,
+}
+
+impl Hover {
+    pub fn new(text: String) -> Self {
+        Self { text }
+    }
+}
+
+impl Mode for Hover {
+    fn name(&self) -> &str {
+        "hover"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        if key != Key::Esc {
+            Normal.handle(state, key);
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+        default_render_status(self, state, render, status_rect);
+
+        let mut area = buffer_rect.to_renderer(&mut render);
+        let inner = super::render_overlay(state, &mut area, 80, 60, "hover");
+        let mut themed = state.themed(&mut area, "overlay");
+        let mut view = inner.to_renderer(&mut themed);
+        let style = view.color_map().default;
+        let width = view.dimensions().x.saturating_sub(2).max(1);
+        let height = view.dimensions().y;
+        let pieces = self.text.trim_end().lines().flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars
+                .chunks(width)
+                .map(|chunk| chunk.iter().collect())
+                .collect()
+        });
+        for (y, piece) in pieces.take(height).enumerate() {
+            view.print(Coord { x: 1, y }, &piece, style);
+        }
+    }
+}
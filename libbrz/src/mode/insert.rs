@@ -1,17 +1,49 @@
 use super::*;
 
+use crate::completion;
+use crate::sanitize;
+use crate::Motion;
+
+/// Chars typed before the next word boundary ends an undo chunk
+const UNDO_CHUNK_CHARS: usize = 20;
+
+/// Insert mode
+///
+/// Typing is undone in chunks: a chunk ends after a newline, at the
+/// first word boundary once it's `UNDO_CHUNK_CHARS` long, after a pause
+/// (see `State::note_idle`), or on `Ctrl-g`. Typing one of the format on
+/// type triggers formats the code around (see `State::format_on_type`).
+///
+/// Typing a word opens the completion menu, as described in
+/// `completion`, or `Ctrl-n` does; while it's shown, `Tab` and the
+/// arrows pick a candidate, `Enter` accepts it and `Ctrl-e` closes it.
 #[derive(Clone, Debug, Default)]
 pub struct Insert {
     extend: bool,
+    last_char: Option<char>,
 }
 
 impl Insert {
     pub fn new_normal() -> Self {
-        Self { extend: false }
+        Self {
+            extend: false,
+            last_char: None,
+        }
     }
 
     pub fn new_extend() -> Self {
-        Self { extend: true }
+        Self {
+            extend: true,
+            last_char: None,
+        }
+    }
+
+    fn maybe_end_undo_chunk(&mut self, state: &mut State, ch: char) {
+        let at_word_start = self.last_char.is_some_and(char::is_whitespace) && !ch.is_whitespace();
+        if at_word_start && UNDO_CHUNK_CHARS <= state.chars_since_undo_point {
+            state.commit_undo_point();
+        }
+        self.last_char = Some(ch);
     }
 }
 
@@ -19,14 +51,60 @@ impl Mode for Insert {
     fn name(&self) -> &str {
         "insert"
     }
+
+    fn on_enter(&mut self, state: &mut State) {
+        state.set_insert_session(true);
+    }
     fn handle(&mut self, state: &mut State, key: Key) {
-        let buffer = state.cur_buffer_mut();
+        match state.completion.as_mut().filter(|menu| menu.is_shown()) {
+            Some(menu) => match key {
+                Key::Char('\t') | Key::Down | Key::Ctrl('n') => {
+                    menu.select(true);
+                    return;
+                }
+                Key::Up | Key::Ctrl('p') => {
+                    menu.select(false);
+                    return;
+                }
+                Key::Ctrl('e') => {
+                    menu.dismissed = true;
+                    return;
+                }
+                Key::Char('\n') if state.accept_completion() => return,
+                _ => {}
+            },
+            None if key == Key::Ctrl('n') => {
+                state.complete();
+                return;
+            }
+            None => {}
+        }
+        match key {
+            Key::Char(ch) if sanitize::is_suspicious(ch) => {
+                // eg. pasted, rather than typed
+                state.msg = Some(format!("dropped control char U+{:04X}", ch as u32));
+                return;
+            }
+            Key::Char(ch) => {
+                self.maybe_end_undo_chunk(state, ch);
+                state.chars_since_undo_point += 1;
+            }
+            Key::Ctrl('g') => {
+                state.execute_action(&action::ActionCall::new("UndoCheckpoint"));
+                return;
+            }
+            _ => {}
+        }
+        let mut buffer = state.cur_buffer_mut();
         match key {
             Key::Esc => {
-                state.set_mode(Normal::default());
+                state.set_insert_session(false);
+                state.set_mode(Normal);
             }
             Key::Char('\n') => {
                 buffer.insert_enter(self.extend);
+                state.commit_undo_point();
+                self.last_char = Some('\n');
             }
             Key::Char('\t') => {
                 buffer.insert_tab(self.extend);
@@ -34,40 +112,156 @@ impl Mode for Insert {
             Key::Backspace => {
                 buffer.backspace(self.extend);
             }
-            Key::Left => {
-                if self.extend {
-                    buffer.extend_cursor_backward(1);
-                } else {
-                    buffer.move_cursor_backward(1);
-                }
-            }
-            Key::Right => {
-                if self.extend {
-                    buffer.extend_cursor_forward(1);
-                } else {
-                    buffer.move_cursor_forward(1);
-                }
-            }
-            Key::Up => {
-                if self.extend {
-                    buffer.extend_cursor_up(1);
-                } else {
-                    buffer.move_cursor_up(1);
-                }
-            }
-            Key::Down => {
-                if self.extend {
-                    buffer.extend_cursor_down(1);
-                } else {
-                    buffer.move_cursor_down(1);
-                }
-            }
+            Key::Left => buffer.apply_motion(Motion::Left(1), self.extend),
+            Key::Right => buffer.apply_motion(Motion::Right(1), self.extend),
+            Key::Up => buffer.apply_motion(Motion::Up(1), self.extend),
+            Key::Down => buffer.apply_motion(Motion::Down(1), self.extend),
             Key::Char(ch) => {
-                if !ch.is_control() {
-                    buffer.insert_char(ch, self.extend);
-                }
+                buffer.insert_char(ch, self.extend);
             }
             _ => {}
         }
+        if let Key::Char(ch) = key {
+            state.format_on_type(ch);
+        }
+        state.update_completion(matches!(key, Key::Char(ch) if completion::is_word_char(ch)));
+    }
+}
+
+#[test]
+fn undo_chunks_test() {
+    let mut state = State::default();
+    state.open_scratch_buffer("");
+    state.set_mode(Insert::new_normal());
+    let type_str = |state: &mut State, s: &str| {
+        for ch in s.chars() {
+            state.handle_key(Key::Char(ch));
+        }
+    };
+    type_str(&mut state, "first line\nthe quick brown fox jumps over");
+    state.note_idle(std::time::Duration::from_secs(2));
+    type_str(&mut state, " the lazy dog");
+    state.handle_key(Key::Ctrl('g'));
+    type_str(&mut state, "!");
+    state.handle_key(Key::Esc);
+
+    let undo = |state: &mut State| {
+        state.handle_key(Key::Char('u'));
+        state.cur_buffer().text.to_string()
+    };
+    assert_eq!(
+        undo(&mut state),
+        "first line\nthe quick brown fox jumps over the lazy dog"
+    );
+    assert_eq!(
+        undo(&mut state),
+        "first line\nthe quick brown fox jumps over"
+    );
+    assert_eq!(undo(&mut state), "first line\nthe quick brown fox ");
+    assert_eq!(undo(&mut state), "first line\n");
+    assert_eq!(undo(&mut state), "");
+}
+
+#[test]
+fn format_on_type_test() {
+    let mut state = State::default();
+    state.open_scratch_buffer("");
+    state.set_mode(Insert::new_normal());
+    for ch in "fn a() {\nif b {\nc();\n}\n}".chars() {
+        state.handle_key(Key::Char(ch));
+    }
+    state.handle_key(Key::Char('x'));
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "fn a() {\n    if b {\n        c();\n    }\n}x"
+    );
+}
+
+#[test]
+fn completion_menu_test() {
+    let mut state = State::default();
+    state.open_scratch_buffer("");
+    state.set_mode(Insert::new_normal());
+    let type_str = |state: &mut State, s: &str| {
+        for ch in s.chars() {
+            state.handle_key(Key::Char(ch));
+        }
+    };
+    type_str(&mut state, "alphabet alpine\nal");
+    assert!(state
+        .completion
+        .as_ref()
+        .is_some_and(|menu| menu.is_shown()));
+    state.handle_key(Key::Char('\t'));
+    state.handle_key(Key::Char('\n'));
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "alphabet alpine\nalpine"
+    );
+    // let through once dismissed
+    type_str(&mut state, " al");
+    state.handle_key(Key::Ctrl('e'));
+    type_str(&mut state, "\t");
+    assert_eq!(state.mode_name(), "insert");
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "alphabet alpine\nalpine al   "
+    );
+
+    type_str(&mut state, " alph");
+    assert_eq!(state.completion.as_ref().unwrap().match_count(), 1);
+    state.handle_key(Key::Char(' '));
+    assert!(state.completion.is_none());
+    state.handle_key(Key::Ctrl('n'));
+    assert_eq!(state.completion.as_ref().unwrap().match_count(), 4);
+}
+
+#[test]
+fn completion_multi_cursor_test() {
+    let mut state = State::default();
+    state.open_scratch_buffer("alphabet\nal\na\nalpha");
+    // after `al`, `a`, and within `alpha`, twice
+    state.cur_buffer_mut().view.selection.selections = [11, 13, 16, 17]
+        .iter()
+        .map(|&idx| crate::Selection::new_from_normalized(crate::Idx(idx), crate::Idx(idx)))
+        .collect();
+    state.set_mode(Insert::new_normal());
+    state.handle_key(Key::Ctrl('n'));
+    state.handle_key(Key::Char('\t'));
+    state.handle_key(Key::Char('\n'));
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "alphabet\nalpha\nalpha\nalphaha"
+    );
+}
+
+#[test]
+fn indent_on_enter_test() {
+    let mut state = State::default();
+    state.open_scratch_buffer("");
+    state.set_indent(crate::indent::Indent::for_language(Some("python")));
+    state.set_mode(Insert::new_normal());
+    for ch in "def f(x):\nif x:\nreturn [\n1,".chars() {
+        state.handle_key(Key::Char(ch));
+    }
+    assert_eq!(
+        state.cur_buffer().text.to_string(),
+        "def f(x):\n    if x:\n        return [\n            1,"
+    );
+}
+
+#[test]
+fn backspace_meeting_cursors_test() {
+    // the cursors that meet deleting back become one
+    let cases = [
+        ("ab\ncd\n", "%<a-l>cx<bs>", "y"),
+        ("hello world\n", "gbs\\w+<ret>l<a-w>i<bs><bs>", "hello wory"),
+    ];
+    for (text, script, typed) in cases {
+        let mut state = crate::testing::state_with_text(text);
+        crate::testing::type_keys(&mut state, script);
+        state.handle_key(Key::Char('y'));
+        assert_eq!(state.cur_buffer().text.to_string(), typed);
+        assert_eq!(state.cur_buffer_mut().view.selection.selections.len(), 1);
     }
 }
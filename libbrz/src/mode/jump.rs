@@ -0,0 +1,98 @@
+use super::*;
+
+/// Waiting for the two chars of a jump, then for the label of the place
+/// to go to, as described in `jump`
+#[derive(Clone, Debug)]
+pub struct Jump {
+    extend: bool,
+    chars: Vec<char>,
+    /// Labels of the places found, and where they are
+    targets: Vec<(String, usize)>,
+    /// What's typed of a label
+    label: String,
+}
+
+impl Jump {
+    pub fn new(extend: bool) -> Self {
+        Self {
+            extend,
+            chars: vec![],
+            targets: vec![],
+            label: String::new(),
+        }
+    }
+}
+
+impl Mode for Jump {
+    fn name(&self) -> &str {
+        "jump"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        let ch = match key {
+            Key::Char(ch) if !ch.is_control() => ch,
+            Key::Backspace if self.targets.is_empty() && !self.chars.is_empty() => {
+                self.chars.pop();
+                return;
+            }
+            Key::Backspace if !self.label.is_empty() => {
+                self.label.pop();
+                return;
+            }
+            _ => return state.set_mode(Normal),
+        };
+        if self.chars.len() < 2 {
+            self.chars.push(ch);
+            if self.chars.len() < 2 {
+                return;
+            }
+            let targets = state.jump_targets([self.chars[0], self.chars[1]]);
+            match targets.len() {
+                0 => {
+                    state.set_mode(Normal);
+                    state.msg = Some(format!(
+                        "no {}{} in the window",
+                        self.chars[0], self.chars[1]
+                    ));
+                }
+                1 => {
+                    state.set_mode(Normal);
+                    state.jump_to(targets[0], self.extend);
+                }
+                count => {
+                    self.targets = crate::jump::labels(count)
+                        .into_iter()
+                        .zip(targets)
+                        .collect();
+                }
+            }
+            return;
+        }
+        self.label.push(ch);
+        let label = &self.label;
+        if let Some(&(_, idx)) = self.targets.iter().find(|(l, _)| l == label) {
+            state.set_mode(Normal);
+            state.jump_to(idx, self.extend);
+        } else if !self
+            .targets
+            .iter()
+            .any(|(l, _)| l.starts_with(label.as_str()))
+        {
+            state.set_mode(Normal);
+        }
+    }
+
+    fn render(&self, state: &State, render: &mut dyn Renderer) {
+        let _ = default_render(self, state, render);
+        let style = render.color_map().jump_label;
+        for (label, idx) in &self.targets {
+            let rest = match label.strip_prefix(self.label.as_str()) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            if let Some(coord) = state.cur_coord_of(*idx) {
+                render.print(coord, rest, style);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::*;
+
+use crate::action::{Action, ActionCall};
+use crate::buffer::SelectionSet;
+use crate::NaturalyOrderedKey;
+
+/// Selecting whole lines, entered with `V`
+///
+/// The keys of normal mode work as there, but their motions extend, from
+/// selections kept here, and what the view shows is the whole lines from
+/// that of their anchor to that of their cursor. Actions editing the text
+/// or yanking it do it line-wise, and go back to normal mode.
+#[derive(Clone, Debug, Default)]
+pub struct LineExtend {
+    /// As the motions left them, before being made line-wise
+    selection: Option<SelectionSet>,
+}
+
+impl LineExtend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show `selection`, line-wise, and keep it
+    fn show(&mut self, state: &mut State, selection: SelectionSet) {
+        let edit = state.cur_buffer_mut();
+        let mut lines = selection.clone();
+        for sel in &mut lines.selections {
+            *sel = sel.line_wise(&edit.buffer.text);
+        }
+        edit.view.selection = lines;
+        self.selection = Some(selection);
+    }
+}
+
+impl Mode for LineExtend {
+    fn name(&self) -> &str {
+        "line"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        let mut selection = match state.cur_view_opt() {
+            Some(view) => view.selection.clone(),
+            None => return,
+        };
+        // from their last char rather than past it, for those already
+        // made of whole lines
+        for sel in &mut selection.selections {
+            if sel.is_empty() {
+                continue;
+            } else if sel.is_forward() {
+                sel.cursor.0 -= 1;
+            } else {
+                sel.anchor.0 -= 1;
+            }
+        }
+        self.show(state, selection);
+    }
+
+    fn actions(&self) -> &action::ActionsById {
+        action::registry()
+    }
+
+    fn key_mappings(&self) -> &action::KeyMappings {
+        action::normal::default_key_mappings()
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        let name = match key {
+            Key::Esc | Key::Char('V') => {
+                state.set_mode(Normal);
+                return;
+            }
+            Key::Char(n @ '0'..='9') => {
+                state.num_prefix = Some(
+                    state
+                        .num_prefix
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(n as usize - '0' as usize),
+                );
+                return;
+            }
+            key => match self.key_mappings().get(&NaturalyOrderedKey(key)) {
+                Some(name) => *name,
+                None => {
+                    action::ActionNotFound.execute(state, &[]);
+                    return;
+                }
+            },
+        };
+        if state.cur_buffer_opt().is_none() {
+            return;
+        }
+        state.commit_undo_point();
+        let extend = name
+            .strip_prefix("Move")
+            .map(|motion| format!("Extend{}", motion))
+            .filter(|extend| action::registry().contains_key(extend.as_str()));
+        let version = state.cur_buffer().version();
+        match extend {
+            Some(extend) => {
+                if let Some(selection) = self.selection.clone() {
+                    state.cur_buffer_mut().view.selection = selection;
+                }
+                state.execute_action(&ActionCall::new(&extend));
+            }
+            None => state.execute_action(&ActionCall::new(name)),
+        }
+        state.num_prefix = None;
+        state.commit_undo_point();
+        if state.mode_was_set() || state.cur_buffer_opt().is_none() {
+            return;
+        }
+        if name == "Yank" || state.cur_buffer().version() != version {
+            state.set_mode(Normal);
+            return;
+        }
+        let selection = state.cur_view().selection.clone();
+        self.show(state, selection);
+    }
+}
@@ -0,0 +1,32 @@
+use super::*;
+
+/// Waiting for the char naming a mark, to set it or go to it
+#[derive(Clone, Debug)]
+pub struct PickMark {
+    set: bool,
+}
+
+impl PickMark {
+    pub fn new(set: bool) -> Self {
+        Self { set }
+    }
+}
+
+impl Mode for PickMark {
+    fn name(&self) -> &str {
+        "mark"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        let name = match key {
+            Key::Char(ch) if !ch.is_whitespace() && !ch.is_control() => ch,
+            _ => return,
+        };
+        if self.set {
+            state.set_mark(name);
+        } else {
+            state.goto_mark(name);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use super::*;
+use crate::object::Object;
+use crate::TextObject;
+
+/// Waiting for the key of a text object, to select it or extend to it
+#[derive(Clone, Debug)]
+pub struct PickObject {
+    around: bool,
+    extend: bool,
+}
+
+impl PickObject {
+    pub fn new(around: bool, extend: bool) -> Self {
+        Self { around, extend }
+    }
+}
+
+impl Mode for PickObject {
+    fn name(&self) -> &str {
+        "object"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        let object = match key {
+            Key::Char(ch) => match Object::from_char(ch) {
+                Some(object) => object,
+                None => return state.show_message(&format!("No text object {}", ch)),
+            },
+            _ => return,
+        };
+        let object = if self.around {
+            TextObject::Around(object)
+        } else {
+            TextObject::Inner(object)
+        };
+        if self.extend {
+            state.cur_buffer_mut().extend_object(object);
+        } else {
+            state.cur_buffer_mut().select_object(object);
+        }
+    }
+}
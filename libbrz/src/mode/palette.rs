@@ -0,0 +1,135 @@
+use super::*;
+
+use crate::action::ActionCall;
+use crate::fuzzy;
+
+/// Recently used actions shown first, at most
+const RECENT: usize = 5;
+
+/// Pick an action by name or help, and run it
+///
+/// The actions run from here the most, and the most recently, come
+/// first, marked with `*`, and stay ahead of those matching as well.
+#[derive(Default, Debug, Clone)]
+pub struct Palette {
+    editor: LineEditor,
+    /// Names of the matching actions, best first, whether they are recent
+    /// ones, and the indices of the chars found in `name help`
+    cur_matches: Vec<(&'static str, bool, Vec<usize>)>,
+    selected: usize,
+}
+
+impl Palette {
+    fn update_matches(&mut self, state: &State) {
+        let pattern = self.editor.text();
+        let registry = action::registry();
+        let recent: Vec<&'static str> = state
+            .action_usage
+            .top(RECENT)
+            .into_iter()
+            .filter_map(|name| registry.get_key_value(name).map(|(name, _)| *name))
+            .collect();
+        let actions = recent.iter().map(|&name| (name, true)).chain(
+            registry
+                .keys()
+                .filter(|name| !recent.contains(name))
+                .map(|&name| (name, false)),
+        );
+        self.cur_matches = fuzzy::filter(&pattern, actions, |&(name, _)| {
+            format!("{} {}", name, registry[name].help())
+        })
+        .into_iter()
+        .map(|((name, recent), found)| (name, recent, found.indices))
+        .collect();
+        self.selected = 0;
+    }
+}
+
+impl Mode for Palette {
+    fn name(&self) -> &str {
+        "palette"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        self.update_matches(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Up | Key::Ctrl('p') => {
+                if self.selected + 1 < self.cur_matches.len() {
+                    self.selected += 1;
+                }
+                return;
+            }
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = self.selected.saturating_sub(1);
+                return;
+            }
+            _ => {}
+        }
+        let text = self.editor.text();
+        match handle_prompt_key(&mut self.editor, state, key) {
+            LineEditorEvent::Continue if self.editor.text() != text => self.update_matches(state),
+            LineEditorEvent::Continue => {}
+            LineEditorEvent::Submit(_) => {
+                state.set_mode(Normal);
+                if let Some(&(name, _, _)) = self.cur_matches.get(self.selected) {
+                    state.action_usage.note(name);
+                    state.execute_action(&ActionCall::new(name));
+                }
+            }
+            LineEditorEvent::Cancel => state.set_mode(Normal),
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let dimensions = render.dimensions();
+        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (buffer_rect, results_rect) =
+            buffer_rect.split_horizontaly_at(-(dimensions.y as isize / 3));
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_special = render.color_map().special;
+        let registry = action::registry();
+        let mut view = results_rect.to_renderer(&mut render);
+        let view_y = view.dimensions().y;
+        for (i, (name, recent, indices)) in self.cur_matches.iter().enumerate().take(view_y) {
+            let (name, recent) = (*name, *recent);
+            let style = if i == self.selected {
+                style_selected
+            } else if recent {
+                style_special
+            } else {
+                style_default
+            };
+            // past the mark, and the name for the dash
+            let name_len = name.chars().count();
+            let indices: Vec<usize> = indices
+                .iter()
+                .map(|&i| if i < name_len { i + 2 } else { i + 4 })
+                .collect();
+            super::print_picker_entry(
+                &mut view,
+                render::Coord {
+                    x: 0,
+                    y: view_y.saturating_sub(1).saturating_sub(i),
+                },
+                &format!(
+                    "{} {} - {}",
+                    if recent { '*' } else { ' ' },
+                    name,
+                    registry[name].help()
+                ),
+                &indices,
+                style,
+            );
+        }
+
+        render_prompt(&self.editor, "action: ", "", render, status_rect);
+    }
+}
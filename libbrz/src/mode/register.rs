@@ -0,0 +1,42 @@
+use super::*;
+
+/// What to do with the register named by the next key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterUse {
+    /// For the next yank, delete or paste
+    Select,
+    Record,
+    /// The count typed before, the number of times to replay
+    Replay(usize),
+}
+
+/// Waiting for the char naming a register
+#[derive(Clone, Debug)]
+pub struct PickRegister {
+    purpose: RegisterUse,
+}
+
+impl PickRegister {
+    pub fn new(purpose: RegisterUse) -> Self {
+        Self { purpose }
+    }
+}
+
+impl Mode for PickRegister {
+    fn name(&self) -> &str {
+        "register"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        let register = match key {
+            Key::Char(ch) if !ch.is_whitespace() && !ch.is_control() => ch,
+            _ => return,
+        };
+        match self.purpose {
+            RegisterUse::Select => state.select_register(register),
+            RegisterUse::Record => state.toggle_recording(Some(register)),
+            RegisterUse::Replay(n) => state.replay_macro(register, n),
+        }
+    }
+}
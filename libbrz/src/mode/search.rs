@@ -0,0 +1,194 @@
+use super::*;
+
+use crate::search::{self, SearchOptions};
+
+/// What the pattern of the prompt is for
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+enum Purpose {
+    /// Select the next match
+    #[default]
+    Next,
+    Prev,
+    /// Select the matches inside the selections
+    Select,
+    /// Split the selections on the matches
+    Split,
+}
+
+/// Search prompt
+///
+/// Matches of the pattern are highlighted as it's typed. `Alt-c` cycles
+/// the case matching, `Alt-r` toggles between regex and literal matching
+/// and `Alt-w` toggles whole-word matching. `Ctrl-r` brings back the
+/// pattern of the last search, and its options; an empty pattern is
+/// that of the last search.
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    editor: LineEditor,
+    options: SearchOptions,
+    purpose: Purpose,
+    /// Number of matches of the pattern typed so far
+    live_count: Option<usize>,
+}
+
+impl Search {
+    pub fn new(options: SearchOptions, backward: bool) -> Self {
+        Self {
+            editor: LineEditor::new(),
+            options,
+            purpose: if backward {
+                Purpose::Prev
+            } else {
+                Purpose::Next
+            },
+            live_count: None,
+        }
+    }
+
+    /// Prompt for a pattern to select the matches of inside the
+    /// selections, or to `split` the selections on
+    pub fn selecting(options: SearchOptions, split: bool) -> Self {
+        Self {
+            purpose: if split {
+                Purpose::Split
+            } else {
+                Purpose::Select
+            },
+            ..Self::new(options, false)
+        }
+    }
+
+    /// Highlight the matches of the pattern typed so far, and count them
+    fn update_live(&mut self, state: &mut State) {
+        let search = if self.editor.is_empty() {
+            None
+        } else {
+            search::Search::new(&self.editor.text(), self.options).ok()
+        };
+        self.live_count = search.as_ref().and_then(|search| {
+            let buffer = state.cur_buffer_opt()?;
+            let matches = search.find_all(&buffer.text);
+            Some(match self.purpose {
+                Purpose::Next | Purpose::Prev => matches.len(),
+                Purpose::Select | Purpose::Split => state
+                    .cur_view()
+                    .selection
+                    .selections
+                    .iter()
+                    .map(|sel| sel.select_matches(&matches).len())
+                    .sum(),
+            })
+        });
+        state.search_preview = search;
+    }
+
+    fn submit(&self, state: &mut State, pattern: &str) {
+        state.search_preview = None;
+        state.set_mode(Normal);
+        let search = if pattern.is_empty() {
+            match state.last_search {
+                Some(ref search) => search.clone(),
+                None => return,
+            }
+        } else {
+            match search::Search::new(pattern, self.options) {
+                Ok(search) => search,
+                Err(e) => {
+                    state.msg = Some(format!("{}", e));
+                    return;
+                }
+            }
+        };
+        state.last_search = Some(search.clone());
+        match self.purpose {
+            Purpose::Next => state.search_next(false),
+            Purpose::Prev => state.search_next(true),
+            Purpose::Select => {
+                if !state.cur_buffer_mut().select_matches(&search) {
+                    state.msg = Some(format!("no matches in the selections: {}", search.pattern));
+                }
+            }
+            Purpose::Split => {
+                state.cur_buffer_mut().split_selections(&search);
+            }
+        }
+    }
+
+    fn prefix(&self) -> String {
+        let prompt = match self.purpose {
+            Purpose::Next => "/",
+            Purpose::Prev => "?",
+            Purpose::Select => "select/",
+            Purpose::Split => "split/",
+        };
+        format!("{}[{}] ", prompt, self.options)
+    }
+
+    fn suffix(&self) -> String {
+        self.live_count
+            .map(|count| format!("  ({} matches)", count))
+            .unwrap_or_default()
+    }
+}
+
+impl Mode for Search {
+    fn name(&self) -> &str {
+        "search"
+    }
+
+    fn cmd_string(&self) -> Option<String> {
+        Some(format!(
+            "{}{}{}",
+            self.prefix(),
+            self.editor.text(),
+            self.suffix()
+        ))
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Alt('c') => {
+                self.options.case = self.options.case.next();
+                state.search_options = self.options;
+            }
+            Key::Alt('r') => {
+                self.options.literal = !self.options.literal;
+                state.search_options = self.options;
+            }
+            Key::Alt('w') => {
+                self.options.whole_word = !self.options.whole_word;
+                state.search_options = self.options;
+            }
+            Key::Ctrl('r') => {
+                if let Some(ref search) = state.last_search {
+                    self.editor = LineEditor::new().with_text(&search.pattern);
+                    self.options = search.options;
+                }
+            }
+            _ => match handle_prompt_key(&mut self.editor, state, key) {
+                LineEditorEvent::Continue => {}
+                LineEditorEvent::Submit(pattern) => {
+                    self.submit(state, &pattern);
+                    return;
+                }
+                LineEditorEvent::Cancel => {
+                    state.search_preview = None;
+                    state.set_mode(Normal);
+                    return;
+                }
+            },
+        }
+        self.update_live(state);
+    }
+
+    fn render(&self, state: &State, render: &mut dyn Renderer) {
+        let (_, status_rect) = super::default_render(self, state, render);
+        render_prompt(
+            &self.editor,
+            &self.prefix(),
+            &self.suffix(),
+            render,
+            status_rect,
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use super::*;
+
+use crate::keymap::{self, Binding};
+use crate::layer;
+use std::collections::BTreeMap;
+
+/// The rest of a key sequence of a keymap: a key ending one runs its
+/// action, one going on waits for the next key, and any other key goes
+/// back to normal mode and is handled there
+#[derive(Clone, Debug)]
+pub struct KeySequence {
+    /// The keys so far, eg. "space f"
+    name: String,
+    keys: BTreeMap<Key, Binding>,
+    /// For the next key, before going back to normal mode
+    timeout: Option<Duration>,
+}
+
+impl KeySequence {
+    pub fn new(name: String, keys: BTreeMap<Key, Binding>, timeout: Option<Duration>) -> Self {
+        Self {
+            name,
+            keys,
+            timeout,
+        }
+    }
+}
+
+impl Mode for KeySequence {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn name4(&self) -> &str {
+        let name = &self.name;
+        name.char_indices().nth(4).map_or(name, |(i, _)| &name[..i])
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        state.msg = Some(format!(
+            "{}: {}, other keys exit",
+            self.name,
+            keymap::key_list(&self.keys)
+        ));
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match self.keys.get(&key) {
+            Some(Binding::Action(call)) => {
+                let call = call.clone();
+                state.set_mode(Normal);
+                state.execute_action(&call);
+            }
+            Some(Binding::Prefix(keys)) => {
+                let name = format!("{} {}", self.name, layer::key_name(key));
+                state.set_mode(KeySequence::new(name, keys.clone(), self.timeout));
+            }
+            None if key == Key::Esc => state.set_mode(Normal),
+            None => {
+                state.set_mode(Normal);
+                Normal.handle(state, key);
+            }
+        }
+    }
+}
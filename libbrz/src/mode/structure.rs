@@ -0,0 +1,86 @@
+use super::*;
+
+use crate::structure::Tree;
+
+/// Moving the selection between the nodes of `structure::Tree`
+///
+/// `l` and `h` select the next and previous sibling, `k` the parent and
+/// `j` the first child, telling the kind of the node. `Esc` goes back to
+/// normal mode; any other key is handled by normal mode, so that `d`
+/// deletes the node right away.
+#[derive(Clone, Debug, Default)]
+pub struct Structure {
+    tree: Tree,
+    node: usize,
+}
+
+impl Structure {
+    fn select(&mut self, state: &mut State, node: Option<usize>) {
+        let node = match node {
+            Some(node) => node,
+            None => {
+                state.msg = Some(format!("{}, no node there", self.kind_name()));
+                return;
+            }
+        };
+        self.node = node;
+        state
+            .cur_buffer_mut()
+            .select_range(self.tree.nodes[node].range.clone());
+        state.msg = Some(self.kind_name().to_owned());
+    }
+
+    fn kind_name(&self) -> &'static str {
+        self.tree
+            .nodes
+            .get(self.node)
+            .map_or("", |node| node.kind.name())
+    }
+}
+
+impl Mode for Structure {
+    fn name(&self) -> &str {
+        "syntax"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        if state.cur_buffer_opt().is_none() {
+            state.set_mode(Normal);
+            return;
+        }
+        self.tree = Tree::parse(
+            &state.cur_buffer().text,
+            state.cur_document().language.as_deref(),
+        );
+        let view = state.cur_view();
+        let range = view.selection.selections[view.selection.primary].sorted_range_usize();
+        let node = self.tree.node_covering(range);
+        self.select(state, Some(node));
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Esc => state.set_mode(Normal),
+            Key::Char('l') | Key::Right => {
+                let next = self.tree.sibling(self.node, false);
+                self.select(state, next);
+            }
+            Key::Char('h') | Key::Left => {
+                let prev = self.tree.sibling(self.node, true);
+                self.select(state, prev);
+            }
+            Key::Char('k') | Key::Up => {
+                let parent = self.tree.nodes[self.node].parent;
+                self.select(state, parent);
+            }
+            Key::Char('j') | Key::Down => {
+                let child = self.tree.nodes[self.node].children.first().copied();
+                self.select(state, child);
+            }
+            key => {
+                state.set_mode(Normal);
+                Normal.handle(state, key);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use super::*;
+
+use crate::layer::Layer;
+
+/// A keymap layer: its keys run their action and keep it on, any other
+/// key goes back to normal mode and is handled there
+#[derive(Clone, Debug)]
+pub struct Transient {
+    layer: Layer,
+}
+
+impl Transient {
+    pub fn new(layer: Layer) -> Self {
+        Self { layer }
+    }
+}
+
+impl Mode for Transient {
+    fn name(&self) -> &str {
+        &self.layer.name
+    }
+
+    fn name4(&self) -> &str {
+        let name = &self.layer.name;
+        name.char_indices().nth(4).map_or(name, |(i, _)| &name[..i])
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        state.msg = Some(format!(
+            "{}: {}, other keys exit",
+            self.layer.name,
+            self.layer.key_list()
+        ));
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.layer.timeout
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match self.layer.keys.get(&key) {
+            Some(call) => state.execute_action(call),
+            None if key == Key::Esc => state.set_mode(Normal),
+            None => {
+                state.set_mode(Normal);
+                Normal.handle(state, key);
+            }
+        }
+    }
+}
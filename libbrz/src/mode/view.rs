@@ -0,0 +1,42 @@
+use super::*;
+
+use crate::action::ActionCall;
+use crate::NaturalyOrderedKey;
+
+/// Scrolling the view around the cursor line, entered with `z`
+#[derive(Clone, Debug, Default)]
+pub struct View {
+    /// Count typed before `z`, the line to scroll to
+    line: Option<usize>,
+}
+
+impl View {
+    pub fn new(line: Option<usize>) -> Self {
+        Self { line }
+    }
+}
+
+impl Mode for View {
+    fn name(&self) -> &str {
+        "view"
+    }
+
+    fn actions(&self) -> &action::ActionsById {
+        action::registry()
+    }
+
+    fn key_mappings(&self) -> &action::KeyMappings {
+        action::view::default_key_mappings()
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        state.num_prefix = self.line;
+        if !state.run_user_key("view", key) {
+            if let Some(name) = self.key_mappings().get(&NaturalyOrderedKey(key)) {
+                state.execute_action(&ActionCall::new(*name));
+            }
+        }
+        state.num_prefix = None;
+    }
+}
@@ -0,0 +1,32 @@
+use super::*;
+
+use crate::action::ActionCall;
+use crate::NaturalyOrderedKey;
+
+/// Splitting, closing and moving between windows, entered with `c-w`
+#[derive(Clone, Debug, Default)]
+pub struct Window;
+
+impl Mode for Window {
+    fn name(&self) -> &str {
+        "window"
+    }
+
+    fn actions(&self) -> &action::ActionsById {
+        action::registry()
+    }
+
+    fn key_mappings(&self) -> &action::KeyMappings {
+        action::window::default_key_mappings()
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        if state.run_user_key("window", key) {
+            return;
+        }
+        if let Some(name) = self.key_mappings().get(&NaturalyOrderedKey(key)) {
+            state.execute_action(&ActionCall::new(*name));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::*;
+
+use crate::fuzzy;
+
+/// Pick one of the recent yanks and deletes, and paste it
+///
+/// What's picked is pasted from then on, as if just yanked.
+#[derive(Default, Debug, Clone)]
+pub struct YankPicker {
+    editor: LineEditor,
+    /// Indices in the yank history of the matching entries, best first,
+    /// their summary, and the indices of the chars found in it
+    cur_matches: Vec<(usize, String, Vec<usize>)>,
+    selected: usize,
+}
+
+impl YankPicker {
+    fn update_matches(&mut self, state: &State) {
+        let entries = state
+            .yank_history
+            .iter()
+            .enumerate()
+            .map(|(i, register)| (i, register.summary()));
+        self.cur_matches =
+            fuzzy::filter(&self.editor.text(), entries, |(_, summary)| summary.clone())
+                .into_iter()
+                .map(|((i, summary), found)| (i, summary, found.indices))
+                .collect();
+        self.selected = 0;
+    }
+}
+
+impl Mode for YankPicker {
+    fn name(&self) -> &str {
+        "yank history"
+    }
+
+    fn on_enter(&mut self, state: &mut State) {
+        self.update_matches(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Up | Key::Ctrl('p') => {
+                if self.selected + 1 < self.cur_matches.len() {
+                    self.selected += 1;
+                }
+                return;
+            }
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = self.selected.saturating_sub(1);
+                return;
+            }
+            _ => {}
+        }
+        let text = self.editor.text();
+        match handle_prompt_key(&mut self.editor, state, key) {
+            LineEditorEvent::Continue if self.editor.text() != text => self.update_matches(state),
+            LineEditorEvent::Continue => {}
+            LineEditorEvent::Submit(_) => {
+                state.set_mode(Normal);
+                if let Some(&(i, _, _)) = self.cur_matches.get(self.selected) {
+                    state.paste_from_history(i);
+                }
+            }
+            LineEditorEvent::Cancel => state.set_mode(Normal),
+        }
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let dimensions = render.dimensions();
+        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (buffer_rect, results_rect) =
+            buffer_rect.split_horizontaly_at(-(dimensions.y as isize / 3));
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let mut view = results_rect.to_renderer(&mut render);
+        let view_y = view.dimensions().y;
+        for (i, (history_i, summary, indices)) in self.cur_matches.iter().enumerate().take(view_y) {
+            let style = if i == self.selected {
+                style_selected
+            } else {
+                style_default
+            };
+            let number = format!("{:>3} ", history_i + 1);
+            let indices: Vec<usize> = indices.iter().map(|&i| i + number.len()).collect();
+            super::print_picker_entry(
+                &mut view,
+                render::Coord {
+                    x: 0,
+                    y: view_y.saturating_sub(1).saturating_sub(i),
+                },
+                &format!("{}{}", number, summary),
+                &indices,
+                style,
+            );
+        }
+
+        render_prompt(&self.editor, "yank: ", "", render, status_rect);
+    }
+}
@@ -0,0 +1,125 @@
+//! Motions and text objects
+//!
+//! The editing engine can be used without the rest of the editor: a
+//! `Buffer` holds the text and its undo history, a `ViewState` holds the
+//! `SelectionSet`, and a `BufferView` borrowing both applies motions and
+//! edits to every selection at once.
+//!
+//! ```
+//! use libbrz::{Buffer, BufferView, Motion, TextObject, ViewState};
+//!
+//! let mut buffer = Buffer::from_text("hello world\nsecond line\n".into());
+//! let mut view = ViewState::default();
+//! let mut edit = BufferView::new(&mut buffer, &mut view);
+//!
+//! edit.apply_motion(Motion::WordForward, false);
+//! edit.delete();
+//! assert_eq!(edit.text().to_string(), "world\nsecond line\n");
+//!
+//! edit.apply_motion(Motion::Down(1), false);
+//! edit.select_object(TextObject::Line);
+//! edit.delete();
+//! assert_eq!(edit.text().to_string(), "world\n");
+//! ```
+//!
+//! Motions with `extend` set move only the cursors of the selections,
+//! leaving the anchors in place, like the uppercase keys do in normal
+//! mode.
+//!
+//! Word motions select the text they move over, and what a word is
+//! depends on their `WordKind`, described with the classes of chars in
+//! `idx::WordKind`.
+//!
+//! Find motions go to the `n`th occurrence of a char, across lines, with
+//! what they move over selected. Those till a char, which stop before it,
+//! never stop where they start, so that repeating them goes on to the next
+//! occurrence. Those past a char skip the one a selection ends with on
+//! their side, for going back the other way over the char a find just
+//! went past. Where a cursor finds nothing, its selection is left alone.
+//!
+//! Text objects of `object::Object`, inner or around, are found at the
+//! cursors, and grown to at both ends of the selections when extending;
+//! the selections with none there are left alone too.
+use crate::object::Object;
+
+/// A way to move the cursors of all the selections
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Motion {
+    /// `n` grapheme clusters back
+    Left(usize),
+    /// `n` grapheme clusters forward
+    Right(usize),
+    /// `n` lines up, preserving the column
+    Up(usize),
+    /// `n` lines down, preserving the column
+    Down(usize),
+    /// Next word, along with the whitespace after it
+    WordForward,
+    /// Previous word
+    WordBackward,
+    /// Whitespace after the cursor and the next word, up to its end
+    WordEnd,
+    /// The same as the word motions, by whitespace-delimited words
+    BigWordForward,
+    BigWordBackward,
+    BigWordEnd,
+    /// The same as the word motions, by the parts of `camelCase` and
+    /// `snake_case` words
+    SubWordForward,
+    SubWordBackward,
+    SubWordEnd,
+    LineStart,
+    /// Last char of the line, before the newline
+    LineEnd,
+    FirstNonWhitespace,
+    FirstLine,
+    LastLine,
+    /// 0-based line number
+    Line(usize),
+    /// Up to the start of the next paragraph, as in `Idx::forward_paragraph`
+    ParagraphForward,
+    ParagraphBackward,
+    /// Up to the start of the next sentence, as in `Idx::forward_sentence`
+    SentenceForward,
+    SentenceBackward,
+    /// `n`th occurrence of a char
+    Find(CharFind, usize),
+}
+
+/// What a find motion looks for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CharFind {
+    pub ch: char,
+    /// Stop before the char rather than past it
+    pub till: bool,
+    pub backward: bool,
+}
+
+impl CharFind {
+    /// The same in the other direction
+    pub fn reversed(self) -> Self {
+        Self {
+            backward: !self.backward,
+            ..self
+        }
+    }
+}
+
+/// A part of the text around the cursors, to select as a whole
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextObject {
+    /// Whole lines, including the newline
+    Line,
+    /// Inside of the closest surrounding pair of brackets or quotes
+    InnerSurrounding,
+    /// The whole text
+    All,
+    /// The paragraph around the cursor, and the blank lines after it
+    Paragraph,
+    /// The sentence around the cursor, and the whitespace after it
+    Sentence,
+    /// The inside of an object of the keys after `a i`
+    Inner(Object),
+    /// An object of the keys after `a a`, with what's around it
+    Around(Object),
+}
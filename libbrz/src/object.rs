@@ -0,0 +1,292 @@
+//! Text objects: the word, the quoted string or the block around a char
+//!
+//! Each is picked by a key after `a i` (inside) or `a a` (around) in
+//! normal mode, and found around the cursor of each selection:
+//!
+//! | key               | object                     | around, also        |
+//! |-------------------|----------------------------|---------------------|
+//! | `w`, `W`          | word, whitespace-delimited | the blanks after it |
+//! | `"`, `'`, `` ` `` | quoted string, on the line | the quotes          |
+//! | `(`, `)`, `b`     | in parentheses             | the parentheses     |
+//! | `[`, `]`, `r`     | in brackets                | the brackets        |
+//! | `{`, `}`, `B`     | in braces                  | the braces          |
+//! | `<`, `>`, `a`     | in angle brackets          | the angle brackets  |
+//! | `m`               | in the closest of those    | the two of them     |
+//! | `p`               | paragraph                  | the blank lines     |
+//! | `s`               | sentence                   | the blanks after it |
+//! | `i`               | lines indented as much     | the lines around    |
+//!
+//! Words without blanks after them take those before them around them.
+//! Quotes escaped with `\` don't count, and those of a line pair up in
+//! order; the cursor on none takes the next string of the line. Brackets
+//! pair up with those they match, what's between nested in them. Blank
+//! lines within an indented block are a part of it, and the lines around
+//! it are those less indented right before and after it, like the line
+//! opening a block and the one closing it.
+use crate::idx::{is_blank_line, Idx, WordKind};
+use ropey::Rope;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Object {
+    Word(WordKind),
+    Quoted(char),
+    Pair(char, char),
+    /// The closest pair of brackets
+    Brackets,
+    Paragraph,
+    Sentence,
+    Indent,
+}
+
+const PAIRS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
+impl Object {
+    /// The object of the key typed after `a i` or `a a`
+    pub fn from_char(ch: char) -> Option<Self> {
+        Some(match ch {
+            'w' => Object::Word(WordKind::Word),
+            'W' => Object::Word(WordKind::BigWord),
+            '"' | '\'' | '`' => Object::Quoted(ch),
+            '(' | ')' | 'b' => Object::Pair('(', ')'),
+            '[' | ']' | 'r' => Object::Pair('[', ']'),
+            '{' | '}' | 'B' => Object::Pair('{', '}'),
+            '<' | '>' | 'a' => Object::Pair('<', '>'),
+            'm' => Object::Brackets,
+            'p' => Object::Paragraph,
+            's' => Object::Sentence,
+            'i' => Object::Indent,
+            _ => return None,
+        })
+    }
+
+    /// The chars of the object at `idx`, with what's around it if
+    /// `around`; `None` if there's none there
+    pub fn range(self, idx: usize, around: bool, text: &Rope) -> Option<Range<usize>> {
+        let len = text.len_chars();
+        if len == 0 {
+            return None;
+        }
+        let idx = idx.min(len - 1);
+        match self {
+            Object::Word(kind) => Some(word(kind, idx, around, text)),
+            Object::Quoted(quote) => quoted(quote, idx, around, text),
+            Object::Pair(open, close) => {
+                pair(open, close, idx, text).map(|range| inside(range, around))
+            }
+            Object::Brackets => PAIRS
+                .iter()
+                .filter_map(|&(open, close)| pair(open, close, idx, text))
+                .max_by_key(|range| range.start)
+                .map(|range| inside(range, around)),
+            Object::Paragraph => {
+                let (start, end) = Idx(idx).paragraph_around(text);
+                let mut end = end.0;
+                if !around {
+                    while start.0 < end && is_blank_line(text, text.char_to_line(end - 1)) {
+                        end = text.line_to_char(text.char_to_line(end - 1));
+                    }
+                    // a paragraph of blank lines is all there is
+                    if end == start.0 {
+                        end = Idx(idx).paragraph_around(text).1 .0;
+                    }
+                }
+                Some(start.0..end)
+            }
+            Object::Sentence => {
+                let (start, end) = Idx(idx).sentence_around(text);
+                let mut end = end.0;
+                if !around {
+                    while start.0 < end && text.char(end - 1).is_whitespace() {
+                        end -= 1;
+                    }
+                }
+                Some(start.0..end.max(start.0 + 1).min(len))
+            }
+            Object::Indent => indent(idx, around, text),
+        }
+    }
+}
+
+/// The inside of the pair of chars at the ends of `range`, or all of it
+fn inside(range: Range<usize>, around: bool) -> Range<usize> {
+    if around {
+        range
+    } else {
+        range.start + 1..range.end - 1
+    }
+}
+
+fn word(kind: WordKind, idx: usize, around: bool, text: &Rope) -> Range<usize> {
+    let ch = text.char(idx);
+    let same = |i: usize| {
+        let other = text.char(i);
+        other != '\n' && kind.same_class(ch, other)
+    };
+    let mut start = idx;
+    while 0 < start && same(start - 1) {
+        start -= 1;
+    }
+    let mut end = idx + 1;
+    while end < text.len_chars() && same(end) {
+        end += 1;
+    }
+    if !around || ch.is_whitespace() {
+        return start..end;
+    }
+    let blank = |i: usize| text.char(i) != '\n' && text.char(i).is_whitespace();
+    let mut after = end;
+    while after < text.len_chars() && blank(after) {
+        after += 1;
+    }
+    if after != end {
+        return start..after;
+    }
+    while 0 < start && blank(start - 1) {
+        start -= 1;
+    }
+    start..end
+}
+
+fn quoted(quote: char, idx: usize, around: bool, text: &Rope) -> Option<Range<usize>> {
+    let line = text.char_to_line(idx);
+    let line_start = text.line_to_char(line);
+    let mut quotes = vec![];
+    let mut escaped = false;
+    for (i, ch) in text.line(line).chars().enumerate() {
+        if ch == quote && !escaped {
+            quotes.push(line_start + i);
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| idx <= close)?;
+    Some(inside(open..close + 1, around))
+}
+
+/// From the `open` char before `idx`, or at it, to the `close` char
+/// matching it, both included
+fn pair(open: char, close: char, idx: usize, text: &Rope) -> Option<Range<usize>> {
+    // a closing char at the cursor closes the pair it's in
+    let mut i = if text.char(idx) == close {
+        idx
+    } else {
+        idx + 1
+    };
+    let mut depth = 0;
+    let mut start = None;
+    while 0 < i {
+        i -= 1;
+        let ch = text.char(i);
+        if ch == close {
+            depth += 1;
+        } else if ch == open {
+            if depth == 0 {
+                start = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = start?;
+    let mut depth = 0;
+    for i in start + 1..text.len_chars() {
+        let ch = text.char(i);
+        if ch == open {
+            depth += 1;
+        } else if ch == close {
+            if depth == 0 {
+                return Some(start..i + 1);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+fn indent_of(text: &Rope, line: usize) -> usize {
+    text.line(line)
+        .chars()
+        .take_while(|&ch| ch == ' ' || ch == '\t')
+        .count()
+}
+
+fn indent(idx: usize, around: bool, text: &Rope) -> Option<Range<usize>> {
+    let lines = text.len_lines();
+    let line = text.char_to_line(idx);
+    // a blank line is a part of the block of the next line that's not
+    let line = (line..lines).find(|&line| !is_blank_line(text, line))?;
+    let level = indent_of(text, line);
+    let within = |line: usize| is_blank_line(text, line) || level <= indent_of(text, line);
+    let mut first = line;
+    while 0 < first && within(first - 1) {
+        first -= 1;
+    }
+    let mut last = line;
+    while last + 1 < lines && within(last + 1) {
+        last += 1;
+    }
+    // blank lines at its ends belong around it
+    while first < line && is_blank_line(text, first) {
+        first += 1;
+    }
+    while line < last && is_blank_line(text, last) {
+        last -= 1;
+    }
+    if around {
+        first = first.saturating_sub(1);
+        last = (last + 1).min(lines - 1);
+    }
+    Some(text.line_to_char(first)..text.line_to_char(last + 1).min(text.len_chars()))
+}
+
+#[test]
+fn object_test() {
+    let text = Rope::from_str("let s = f(a, (b + c), \"x \\\" y\");\n");
+    let range = |object: char, idx: usize, around: bool| {
+        let range = Object::from_char(object)
+            .unwrap()
+            .range(idx, around, &text)?;
+        Some(text.slice(range).to_string())
+    };
+    assert_eq!(range('w', 1, false).as_deref(), Some("let"));
+    assert_eq!(range('w', 1, true).as_deref(), Some("let "));
+    assert_eq!(range('W', 9, false).as_deref(), Some("f(a,"));
+    assert_eq!(range('(', 15, false).as_deref(), Some("b + c"));
+    assert_eq!(range(')', 19, true).as_deref(), Some("(b + c)"));
+    assert_eq!(
+        range('b', 11, false).as_deref(),
+        Some("a, (b + c), \"x \\\" y\"")
+    );
+    assert_eq!(range('b', 4, true), None);
+    assert_eq!(range('"', 1, false).as_deref(), Some("x \\\" y"));
+    assert_eq!(range('"', 1, true).as_deref(), Some("\"x \\\" y\""));
+    assert_eq!(range('\'', 1, false), None);
+    assert_eq!(range('m', 15, false).as_deref(), Some("b + c"));
+
+    let text = Rope::from_str("fn a() {\n    one();\n\n    two();\n}\n\nnext\n");
+    let range = |object: char, idx: usize, around: bool| {
+        let range = Object::from_char(object)
+            .unwrap()
+            .range(idx, around, &text)?;
+        Some(text.slice(range).to_string())
+    };
+    assert_eq!(
+        range('i', 12, false).as_deref(),
+        Some("    one();\n\n    two();\n")
+    );
+    assert_eq!(
+        range('i', 20, true).as_deref(),
+        Some("fn a() {\n    one();\n\n    two();\n}\n")
+    );
+    assert_eq!(
+        range('p', 0, false).as_deref(),
+        Some("fn a() {\n    one();\n")
+    );
+    assert_eq!(
+        range('p', 0, true).as_deref(),
+        Some("fn a() {\n    one();\n\n")
+    );
+}
@@ -0,0 +1,90 @@
+//! Selections piped through shell commands
+//!
+//! `:|cmd` runs `cmd` once for every selection, with the text selected as
+//! its input, and replaces the selection with what it prints; `:!cmd`
+//! runs it once, with no input, and inserts what it prints before every
+//! selection. The frontend runs the commands in the background, so the
+//! editor goes on meanwhile: what they print is dropped if the buffer
+//! changed before they were done.
+use crate::preview::CancelToken;
+use crate::progress::TaskId;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct PipeRequest {
+    pub id: u64,
+    /// For the shell to run
+    pub command: String,
+    /// A run of the command for each input, given on its stdin, or a
+    /// single run with no input if `None`
+    pub inputs: Option<Vec<String>>,
+    pub cancel: CancelToken,
+}
+
+/// A `PipeRequest` in flight, and where its output goes
+#[derive(Debug, Clone)]
+pub(crate) struct PendingPipe {
+    pub id: u64,
+    pub command: String,
+    pub buffer_i: usize,
+    pub view_i: usize,
+    /// Version of the buffer the ranges are for
+    pub version: u64,
+    /// Chars to replace with the output of each run, or to insert the
+    /// output of the single run at
+    pub ranges: Vec<Range<usize>>,
+    pub insert: bool,
+    pub cancel: CancelToken,
+    pub task: TaskId,
+}
+
+impl PendingPipe {
+    /// The edits to make with the `outputs` of the runs
+    pub fn edits(&self, outputs: Vec<String>) -> Result<Vec<(Range<usize>, String)>, String> {
+        let expected = if self.insert { 1 } else { self.ranges.len() };
+        if outputs.len() != expected {
+            return Err(format!(
+                "{}: {} outputs for {} runs",
+                self.command,
+                outputs.len(),
+                expected
+            ));
+        }
+        Ok(if self.insert {
+            self.ranges
+                .iter()
+                .map(|range| (range.clone(), outputs[0].clone()))
+                .collect()
+        } else {
+            self.ranges.iter().cloned().zip(outputs).collect()
+        })
+    }
+}
+
+#[test]
+fn edits_test() {
+    let mut pending = PendingPipe {
+        id: 0,
+        command: "sort".into(),
+        buffer_i: 0,
+        view_i: 0,
+        version: 0,
+        ranges: vec![0..3, 5..8],
+        insert: false,
+        cancel: CancelToken::default(),
+        task: crate::progress::Tasks::default().begin("sort"),
+    };
+    assert_eq!(
+        pending.edits(vec!["a".into(), "b".into()]),
+        Ok(vec![(0..3, "a".into()), (5..8, "b".into())])
+    );
+    assert!(pending.edits(vec!["a".into()]).is_err());
+
+    // the output of the single run goes before every selection
+    pending.insert = true;
+    pending.ranges = vec![0..0, 5..5];
+    assert_eq!(
+        pending.edits(vec!["date".into()]),
+        Ok(vec![(0..0, "date".into()), (5..5, "date".into())])
+    );
+}
@@ -0,0 +1,245 @@
+//! Plugins: scripts extending the editor without rebuilding it
+//!
+//! The frontend runs each plugin as a program of its own, a Lua script
+//! with `lua`, a WASM component with `wasmtime`, and speaks to it in JSON,
+//! a message a line, over its stdin and stdout. The editor tells it what
+//! happened:
+//!
+//! ```text
+//! {"event":"hello","version":1}
+//! {"event":"open","path":"src/main.rs"}
+//! {"event":"save","path":"src/main.rs"}
+//! {"event":"change","path":"src/main.rs","version":12}
+//! {"event":"command","name":"wc","args":"-l"}
+//! {"event":"reply","id":3,"result":"fn main() {}\n"}
+//! ```
+//!
+//! and the plugin asks for what it does, in the current buffer:
+//!
+//! ```text
+//! {"op":"text","id":3}
+//! {"op":"selections","id":4}
+//! {"op":"edit","start":0,"end":2,"text":"hi"}
+//! {"op":"select","ranges":[[0,2],[5,5]]}
+//! {"op":"command","name":"wc","help":"count the words"}
+//! {"op":"bind","mode":"normal","keys":"leader,w","run":":wc"}
+//! {"op":"overlay","title":"wc","lines":["12 words"]}
+//! {"op":"message","text":"done"}
+//! ```
+//!
+//! Positions are chars of the text, and selections anchor and cursor
+//! pairs. `text` and `selections` are answered with a `reply` of the same
+//! `id`; the commands a plugin adds run it with a `command` event, and the
+//! keys it binds run what `run` says, as in a keymap. An overlay with no
+//! lines is closed. The `version` of `hello` changes with what breaks the
+//! plugins written before.
+use crate::json::{self, Value};
+use std::ops::Range;
+use std::path::PathBuf;
+
+pub const API_VERSION: u64 = 1;
+
+/// What the editor tells a plugin
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Hello,
+    Open(PathBuf),
+    Save(PathBuf),
+    /// Of the buffer, to its version
+    Change(PathBuf, u64),
+    /// Of a command the plugin added
+    Command {
+        name: String,
+        args: String,
+    },
+    Reply {
+        id: u64,
+        result: Value,
+    },
+}
+
+impl Event {
+    pub fn to_json(&self) -> Value {
+        let path = |path: &PathBuf| Value::from(path.to_string_lossy().into_owned());
+        match self {
+            Event::Hello => Value::object(vec![
+                ("event", "hello".into()),
+                ("version", API_VERSION.into()),
+            ]),
+            Event::Open(p) => Value::object(vec![("event", "open".into()), ("path", path(p))]),
+            Event::Save(p) => Value::object(vec![("event", "save".into()), ("path", path(p))]),
+            Event::Change(p, version) => Value::object(vec![
+                ("event", "change".into()),
+                ("path", path(p)),
+                ("version", (*version).into()),
+            ]),
+            Event::Command { name, args } => Value::object(vec![
+                ("event", "command".into()),
+                ("name", name.as_str().into()),
+                ("args", args.as_str().into()),
+            ]),
+            Event::Reply { id, result } => Value::object(vec![
+                ("event", "reply".into()),
+                ("id", (*id).into()),
+                ("result", result.clone()),
+            ]),
+        }
+    }
+}
+
+/// What a plugin asks the editor for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Text {
+        id: u64,
+    },
+    Selections {
+        id: u64,
+    },
+    Edit {
+        range: Range<usize>,
+        text: String,
+    },
+    /// Anchor and cursor of each, the first the primary one
+    Select(Vec<(usize, usize)>),
+    Command {
+        name: String,
+        help: String,
+    },
+    /// Of `mode`, `run` being an action or a command line after `:`
+    Bind {
+        mode: String,
+        keys: String,
+        run: String,
+    },
+    /// No lines to close it
+    Overlay {
+        title: String,
+        lines: Vec<String>,
+    },
+    Message(String),
+}
+
+impl Request {
+    /// The request of a line the plugin wrote
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let message = json::parse(line).map_err(|e| e.to_string())?;
+        let str_of = |key: &str| {
+            message
+                .get(key)
+                .as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| format!("no {}", key))
+        };
+        let usize_of = |value: &Value, key: &str| {
+            value
+                .get(key)
+                .as_usize()
+                .ok_or_else(|| format!("no {}", key))
+        };
+        let pair = |value: &Value| match value.items() {
+            [anchor, cursor] => anchor.as_usize().zip(cursor.as_usize()),
+            _ => None,
+        };
+        let op = str_of("op")?;
+        Ok(match op.as_str() {
+            "text" => Request::Text {
+                id: usize_of(&message, "id")? as u64,
+            },
+            "selections" => Request::Selections {
+                id: usize_of(&message, "id")? as u64,
+            },
+            "edit" => {
+                let start = usize_of(&message, "start")?;
+                let end = usize_of(&message, "end")?;
+                if end < start {
+                    return Err(format!("edit ends at {}, before {}", end, start));
+                }
+                Request::Edit {
+                    range: start..end,
+                    text: str_of("text")?,
+                }
+            }
+            "select" => Request::Select(
+                message
+                    .get("ranges")
+                    .items()
+                    .iter()
+                    .map(pair)
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|ranges| !ranges.is_empty())
+                    .ok_or("no ranges")?,
+            ),
+            "command" => Request::Command {
+                name: str_of("name")?,
+                help: str_of("help").unwrap_or_default(),
+            },
+            "bind" => Request::Bind {
+                mode: str_of("mode").unwrap_or_else(|_| "normal".into()),
+                keys: str_of("keys")?,
+                run: str_of("run")?,
+            },
+            "overlay" => Request::Overlay {
+                title: str_of("title").unwrap_or_default(),
+                lines: message
+                    .get("lines")
+                    .items()
+                    .iter()
+                    .filter_map(|line| line.as_str().map(str::to_owned))
+                    .collect(),
+            },
+            "message" => Request::Message(str_of("text")?),
+            op => return Err(format!("no op {}", op)),
+        })
+    }
+}
+
+/// What a plugin draws over the windows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlay {
+    /// Of the plugin
+    pub plugin: String,
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+#[test]
+fn plugin_test() {
+    assert_eq!(
+        Event::Change("a.rs".into(), 3).to_json().to_string(),
+        r#"{"event":"change","path":"a.rs","version":3}"#
+    );
+    assert_eq!(
+        Request::parse(r#"{"op":"edit","start":1,"end":3,"text":"x"}"#),
+        Ok(Request::Edit {
+            range: 1..3,
+            text: "x".into()
+        })
+    );
+    assert_eq!(
+        Request::parse(r#"{"op":"select","ranges":[[0,2],[5,4]]}"#),
+        Ok(Request::Select(vec![(0, 2), (5, 4)]))
+    );
+    assert_eq!(
+        Request::parse(r#"{"op":"bind","keys":"leader,w","run":":wc"}"#),
+        Ok(Request::Bind {
+            mode: "normal".into(),
+            keys: "leader,w".into(),
+            run: ":wc".into()
+        })
+    );
+    assert_eq!(
+        Request::parse(r#"{"op":"overlay"}"#),
+        Ok(Request::Overlay {
+            title: String::new(),
+            lines: vec![]
+        })
+    );
+    assert!(Request::parse(r#"{"op":"edit","start":3,"end":1,"text":""}"#).is_err());
+    assert!(Request::parse(r#"{"op":"select","ranges":[]}"#).is_err());
+    assert_eq!(
+        Request::parse(r#"{"op":"fly"}"#),
+        Err("no op fly".to_owned())
+    );
+    assert!(Request::parse("not json").is_err());
+}
@@ -1,3 +1,6 @@
+pub mod convert;
+pub mod encoding;
+
 use ropey::Rope;
 
 use crate::idx::*;
@@ -0,0 +1,243 @@
+//! Conversions between the different ways of addressing text
+//!
+//! Internally text is addressed by `char` index (`Idx`), but:
+//!
+//! * ropes, files and most external tools count bytes,
+//! * the cursor should never stop in the middle of a grapheme cluster
+//!   (eg. a letter followed by a combining accent),
+//! * the terminal counts cells, where a tab expands to the next
+//!   tabstop and East Asian wide characters take two.
+//!
+//! Grapheme segmentation works on a single line at a time, so it never
+//! has to look at the whole rope.
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+use crate::buffer::distance_to_next_tabstop;
+use crate::idx::Idx;
+
+pub fn char_to_byte(text: &Rope, idx: Idx) -> usize {
+    text.char_to_byte(idx.0.min(text.len_chars()))
+}
+
+/// `Idx` of the char containing `byte`
+pub fn byte_to_char(text: &Rope, byte: usize) -> Idx {
+    Idx(text.byte_to_char(byte.min(text.len_bytes())))
+}
+
+/// Char offsets of the grapheme boundaries in `s`, including `0` and the end
+fn grapheme_boundaries(s: &str) -> impl Iterator<Item = usize> + '_ {
+    std::iter::once(0).chain(s.graphemes(true).scan(0, |offset, g| {
+        *offset += g.chars().count();
+        Some(*offset)
+    }))
+}
+
+/// Line of `idx` as a string, along with the `Idx` of its start
+fn line_at(text: &Rope, idx: Idx) -> (usize, String) {
+    let line = text.char_to_line(idx.0);
+    (text.line_to_char(line), text.line(line).to_string())
+}
+
+pub fn is_grapheme_boundary(text: &Rope, idx: Idx) -> bool {
+    if idx.0 == 0 || text.len_chars() <= idx.0 {
+        return true;
+    }
+    let (line_start, line) = line_at(text, idx);
+    let column = idx.0 - line_start;
+    let is_boundary = grapheme_boundaries(&line).any(|b| b == column);
+    is_boundary
+}
+
+/// First grapheme boundary after `idx`
+pub fn next_grapheme_boundary(text: &Rope, idx: Idx) -> Idx {
+    if text.len_chars() <= idx.0 {
+        return Idx(text.len_chars());
+    }
+    let (line_start, line) = line_at(text, idx);
+    let column = idx.0 - line_start;
+    let next = grapheme_boundaries(&line)
+        .find(|&b| column < b)
+        .expect("end of the line is always a boundary");
+    Idx(line_start + next)
+}
+
+/// Last grapheme boundary before `idx`
+pub fn prev_grapheme_boundary(text: &Rope, idx: Idx) -> Idx {
+    let idx = Idx(idx.0.min(text.len_chars()));
+    if idx.0 == 0 {
+        return idx;
+    }
+    let (line_start, line) = line_at(text, Idx(idx.0 - 1));
+    let column = idx.0 - line_start;
+    let prev = grapheme_boundaries(&line)
+        .take_while(|&b| b < column)
+        .last()
+        .unwrap_or(0);
+    Idx(line_start + prev)
+}
+
+/// Number of cells `ch` takes in the terminal, ignoring tabs
+///
+/// Control characters, and the other suspicious ones, have no width of
+/// their own, but are counted as one cell, as that's how they are
+/// displayed.
+pub fn char_width(ch: char) -> usize {
+    if crate::sanitize::is_suspicious(ch) {
+        return 1;
+    }
+    UnicodeWidthChar::width(ch).unwrap_or(1)
+}
+
+/// Width of `ch` when it starts at `cell_column`
+pub fn char_cells_at(ch: char, cell_column: usize, tabstop: usize) -> usize {
+    if ch == '\t' {
+        distance_to_next_tabstop(cell_column, tabstop)
+    } else {
+        char_width(ch)
+    }
+}
+
+/// Width of the grapheme cluster `grapheme` when it starts at
+/// `cell_column`
+///
+/// A cluster is as wide as the char it starts with, the chars combining
+/// with it add nothing, unless it's shown as an emoji: a flag, or a
+/// symbol asking for the emoji presentation, takes two cells.
+pub fn grapheme_cells_at(grapheme: &str, cell_column: usize, tabstop: usize) -> usize {
+    let mut chars = grapheme.chars();
+    let first = match chars.next() {
+        Some(first) => first,
+        None => return 0,
+    };
+    let width = char_cells_at(first, cell_column, tabstop);
+    let is_flag = ('\u{1F1E6}'..='\u{1F1FF}').contains(&first) && chars.next().is_some();
+    if is_flag || grapheme.contains('\u{FE0F}') {
+        width.max(2)
+    } else {
+        width
+    }
+}
+
+/// Cells `text` takes, ignoring tabs
+pub fn str_width(text: &str) -> usize {
+    text.graphemes(true)
+        .map(|grapheme| grapheme_cells_at(grapheme, 0, 1))
+        .sum()
+}
+
+/// Cell column of `idx` within its line
+///
+/// Inside a grapheme cluster, that's the column the cluster starts at.
+pub fn cell_column(text: &Rope, idx: Idx, tabstop: usize) -> usize {
+    let idx = Idx(idx.0.min(text.len_chars()));
+    if text.len_chars() <= idx.0 && idx.0 != 0 && text.char(idx.0 - 1) == '\n' {
+        return 0;
+    }
+    let (line_start, line) = line_at(text, Idx(idx.0.min(text.len_chars().saturating_sub(1))));
+    let column = idx.0 - line_start;
+    let mut cell = 0;
+    let mut offset = 0;
+    for grapheme in line.graphemes(true) {
+        offset += grapheme.chars().count();
+        if column < offset {
+            break;
+        }
+        cell += grapheme_cells_at(grapheme, cell, tabstop);
+    }
+    cell
+}
+
+/// `Idx` of the grapheme cluster covering `cell_column` in `line`
+///
+/// Past the end of the line, clamps to the last cluster of the line (the
+/// line ending, if there's one).
+pub fn cell_column_to_idx(text: &Rope, line: usize, cell_column: usize, tabstop: usize) -> Idx {
+    let line = line.min(text.len_lines().saturating_sub(1));
+    let line_start = text.line_to_char(line);
+    let line_text = text.line(line).to_string();
+
+    let mut cell = 0;
+    let mut offset = 0;
+    for grapheme in line_text.graphemes(true) {
+        let width = grapheme_cells_at(grapheme, cell, tabstop);
+        if cell_column < cell + width.max(1) || grapheme.ends_with('\n') {
+            return Idx(line_start + offset);
+        }
+        cell += width;
+        offset += grapheme.chars().count();
+    }
+    Idx(line_start + offset)
+}
+
+#[test]
+fn byte_char_test() {
+    let text = Rope::from_str("zażółć\n中文");
+    assert_eq!(char_to_byte(&text, Idx(3)), 4);
+    assert_eq!(byte_to_char(&text, 4), Idx(3));
+    // middle of `ż` maps to itself
+    assert_eq!(byte_to_char(&text, 3), Idx(2));
+    assert_eq!(char_to_byte(&text, Idx(8)), 14);
+    assert_eq!(char_to_byte(&text, Idx(100)), text.len_bytes());
+}
+
+#[test]
+fn grapheme_boundary_test() {
+    // `e` + combining acute accent, then a flag made of two regional indicators
+    let text = Rope::from_str("e\u{301}x\n\u{1F1F5}\u{1F1F1}");
+    assert_eq!(next_grapheme_boundary(&text, Idx(0)), Idx(2));
+    assert_eq!(next_grapheme_boundary(&text, Idx(2)), Idx(3));
+    assert_eq!(next_grapheme_boundary(&text, Idx(3)), Idx(4));
+    assert_eq!(next_grapheme_boundary(&text, Idx(4)), Idx(6));
+    assert_eq!(next_grapheme_boundary(&text, Idx(6)), Idx(6));
+
+    assert_eq!(prev_grapheme_boundary(&text, Idx(6)), Idx(4));
+    assert_eq!(prev_grapheme_boundary(&text, Idx(4)), Idx(3));
+    assert_eq!(prev_grapheme_boundary(&text, Idx(2)), Idx(0));
+    assert_eq!(prev_grapheme_boundary(&text, Idx(1)), Idx(0));
+    assert_eq!(prev_grapheme_boundary(&text, Idx(0)), Idx(0));
+
+    assert!(is_grapheme_boundary(&text, Idx(2)));
+    assert!(!is_grapheme_boundary(&text, Idx(1)));
+    assert!(!is_grapheme_boundary(&text, Idx(5)));
+}
+
+#[test]
+fn cell_column_test() {
+    let text = Rope::from_str("a\tb中c\n\t\tx");
+    let cols: Vec<_> = (0..=10).map(|i| cell_column(&text, Idx(i), 4)).collect();
+    assert_eq!(cols, vec![0, 1, 4, 5, 7, 8, 0, 4, 8, 9, 9]);
+
+    assert_eq!(cell_column_to_idx(&text, 0, 0, 4), Idx(0));
+    assert_eq!(cell_column_to_idx(&text, 0, 2, 4), Idx(1));
+    assert_eq!(cell_column_to_idx(&text, 0, 4, 4), Idx(2));
+    assert_eq!(cell_column_to_idx(&text, 0, 6, 4), Idx(3));
+    assert_eq!(cell_column_to_idx(&text, 0, 100, 4), Idx(5));
+    assert_eq!(cell_column_to_idx(&text, 1, 5, 4), Idx(7));
+    assert_eq!(cell_column_to_idx(&text, 1, 100, 4), Idx(9));
+
+    for i in 0..text.len_chars() {
+        let idx = Idx(i);
+        let line = text.char_to_line(i);
+        assert_eq!(
+            cell_column_to_idx(&text, line, cell_column(&text, idx, 4), 4),
+            idx
+        );
+    }
+}
+
+#[test]
+fn grapheme_width_test() {
+    // wide, combining, a flag and an emoji made of a few
+    let text = Rope::from_str("中e\u{301}\u{1F1F5}\u{1F1F1}x\u{1F468}\u{200D}\u{1F469}y\r\nz");
+    let cols: Vec<_> = (0..=13).map(|i| cell_column(&text, Idx(i), 4)).collect();
+    assert_eq!(cols, vec![0, 2, 2, 3, 3, 5, 6, 6, 6, 8, 9, 9, 0, 1]);
+    assert_eq!(cell_column_to_idx(&text, 0, 1, 4), Idx(0));
+    assert_eq!(cell_column_to_idx(&text, 0, 2, 4), Idx(1));
+    assert_eq!(cell_column_to_idx(&text, 0, 4, 4), Idx(3));
+    assert_eq!(cell_column_to_idx(&text, 0, 7, 4), Idx(6));
+    assert_eq!(cell_column_to_idx(&text, 0, 100, 4), Idx(10));
+    assert_eq!(str_width("中e\u{301}x"), 4);
+    assert_eq!(str_width("\u{2764}\u{FE0F}"), 2);
+}
@@ -0,0 +1,230 @@
+//! Positions as counted by external protocols
+//!
+//! Language servers address text by line and a column counted in code
+//! units of an encoding agreed upon during initialization: UTF-8 bytes,
+//! UTF-16 code units (the default, and the only one older servers know)
+//! or UTF-32 code units, which are just `char`s.
+use ropey::Rope;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::idx::Idx;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl Default for PositionEncoding {
+    /// What has to be assumed when the other side says nothing
+    fn default() -> Self {
+        PositionEncoding::Utf16
+    }
+}
+
+impl PositionEncoding {
+    /// All the encodings we support, most preferred first
+    ///
+    /// `Utf32` maps directly to our `char` indices, so it's the cheapest one.
+    pub const SUPPORTED: [PositionEncoding; 3] = [
+        PositionEncoding::Utf32,
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PositionEncoding::Utf8 => "utf-8",
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf32 => "utf-32",
+        }
+    }
+
+    /// Pick the encoding to use given what the server answered
+    ///
+    /// The server is supposed to choose one of the encodings we offered
+    /// in `SUPPORTED`, or omit it, meaning UTF-16. Anything we don't
+    /// understand falls back to UTF-16 too, as that's what every server
+    /// has to handle.
+    pub fn negotiate(server_choice: Option<&str>) -> Self {
+        server_choice
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Number of code units `ch` takes in this encoding
+    pub fn char_len(self, ch: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => ch.len_utf8(),
+            PositionEncoding::Utf16 => ch.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+impl fmt::Display for PositionEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPositionEncoding(pub String);
+
+impl fmt::Display for UnknownPositionEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown position encoding: {}", self.0)
+    }
+}
+
+impl FromStr for PositionEncoding {
+    type Err = UnknownPositionEncoding;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "utf-8" => PositionEncoding::Utf8,
+            "utf-16" => PositionEncoding::Utf16,
+            "utf-32" => PositionEncoding::Utf32,
+            other => return Err(UnknownPositionEncoding(other.to_owned())),
+        })
+    }
+}
+
+/// Line and column counted in code units of some `PositionEncoding`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EncodedPosition {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl EncodedPosition {
+    pub fn from_idx(idx: Idx, text: &Rope, encoding: PositionEncoding) -> Self {
+        let idx = idx.0.min(text.len_chars());
+        let line = text.char_to_line(idx);
+        let line_start = text.line_to_char(line);
+        let character = text
+            .slice(line_start..idx)
+            .chars()
+            .map(|ch| encoding.char_len(ch))
+            .sum();
+        EncodedPosition { line, character }
+    }
+
+    /// Convert back to `Idx`
+    ///
+    /// Lines past the end of the text map to the end of the text, and
+    /// columns past the end of a line to the end of that line, with the
+    /// newline excluded. A column in the middle of a multi-unit char
+    /// (which a well-behaved server never sends) maps to that char.
+    pub fn to_idx(self, text: &Rope, encoding: PositionEncoding) -> Idx {
+        if text.len_lines() <= self.line {
+            return Idx(text.len_chars());
+        }
+        let line_start = text.line_to_char(self.line);
+        let mut units = 0;
+        for (i, ch) in text.line(self.line).chars().enumerate() {
+            if ch == '\n' || self.character < units + encoding.char_len(ch) {
+                return Idx(line_start + i);
+            }
+            units += encoding.char_len(ch);
+        }
+        Idx(line_start + text.line(self.line).len_chars())
+    }
+}
+
+#[test]
+fn negotiate_test() {
+    assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+    assert_eq!(
+        PositionEncoding::negotiate(Some("utf-8")),
+        PositionEncoding::Utf8
+    );
+    assert_eq!(
+        PositionEncoding::negotiate(Some("utf-7")),
+        PositionEncoding::Utf16
+    );
+    for enc in &PositionEncoding::SUPPORTED {
+        assert_eq!(enc.as_str().parse::<PositionEncoding>(), Ok(*enc));
+    }
+}
+
+#[test]
+fn encoded_position_property_test() {
+    // ASCII, 2 and 3 byte chars, astral plane (surrogate pairs in UTF-16)
+    // and a combining mark
+    let alphabet = ['a', '\n', 'ż', '中', '😀', '\u{1F1F5}', '\u{301}', '\t'];
+    // small LCG, to stay deterministic without extra dependencies
+    let mut seed: u32 = 7;
+    for _ in 0..50 {
+        let s: String = (0..40)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                alphabet[(seed >> 16) as usize % alphabet.len()]
+            })
+            .collect();
+        let text = Rope::from_str(&s);
+
+        for i in 0..=text.len_chars() {
+            let idx = Idx(i);
+            let line = text.char_to_line(i);
+            let before: String = text.slice(text.line_to_char(line)..i).to_string();
+
+            let utf8 = EncodedPosition::from_idx(idx, &text, PositionEncoding::Utf8);
+            let utf16 = EncodedPosition::from_idx(idx, &text, PositionEncoding::Utf16);
+            let utf32 = EncodedPosition::from_idx(idx, &text, PositionEncoding::Utf32);
+            assert_eq!(utf8.character, before.len());
+            assert_eq!(utf16.character, before.encode_utf16().count());
+            assert_eq!(utf32.character, before.chars().count());
+
+            for (pos, enc) in &[
+                (utf8, PositionEncoding::Utf8),
+                (utf16, PositionEncoding::Utf16),
+                (utf32, PositionEncoding::Utf32),
+            ] {
+                assert_eq!(pos.line, line);
+                assert_eq!(pos.to_idx(&text, *enc), idx);
+            }
+        }
+    }
+}
+
+#[test]
+fn encoded_position_clamp_test() {
+    let text = Rope::from_str("a😀b\nc");
+    let enc = PositionEncoding::Utf16;
+    // middle of the surrogate pair
+    assert_eq!(
+        EncodedPosition {
+            line: 0,
+            character: 2
+        }
+        .to_idx(&text, enc),
+        Idx(1)
+    );
+    assert_eq!(
+        EncodedPosition {
+            line: 0,
+            character: 100
+        }
+        .to_idx(&text, enc),
+        Idx(3)
+    );
+    assert_eq!(
+        EncodedPosition {
+            line: 1,
+            character: 100
+        }
+        .to_idx(&text, enc),
+        Idx(5)
+    );
+    assert_eq!(
+        EncodedPosition {
+            line: 7,
+            character: 0
+        }
+        .to_idx(&text, enc),
+        Idx(5)
+    );
+}
@@ -0,0 +1,137 @@
+//! Read-only previews of files, as shown next to pickers
+//!
+//! Loading happens in the frontend (usually in a background thread),
+//! so that moving around the picker never waits for the disk. Every
+//! request gets a new id and a `CancelToken`; requesting another
+//! preview cancels the previous one, and results delivered for
+//! anything but the latest request are dropped.
+use ropey::Rope;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How much of a file to load for a preview
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+const CHUNK_SIZE: usize = 4 * 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// What the frontend is asked to load
+#[derive(Clone, Debug)]
+pub struct PreviewRequest {
+    pub id: u64,
+    pub path: PathBuf,
+    pub max_bytes: usize,
+    pub cancel: CancelToken,
+}
+
+#[derive(Clone, Debug)]
+pub enum Preview {
+    Loading,
+    Text {
+        text: Rope,
+        /// Only the beginning of the file was loaded
+        truncated: bool,
+    },
+    Binary,
+    Failed(String),
+}
+
+/// Preview the picker is currently showing
+#[derive(Clone, Debug)]
+pub(crate) struct CurrentPreview {
+    pub id: u64,
+    pub path: PathBuf,
+    pub cancel: CancelToken,
+    pub preview: Preview,
+}
+
+/// Load at most `max_bytes` from `reader`
+///
+/// Reads in small chunks, checking `cancel` in between, and returns
+/// `None` if cancelled. A multi-byte char cut in half at the limit is
+/// dropped; invalid UTF-8 anywhere else, or a NUL byte, makes the
+/// content `Binary`.
+pub fn load_partial(
+    mut reader: impl Read,
+    max_bytes: usize,
+    cancel: &CancelToken,
+) -> io::Result<Option<Preview>> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; CHUNK_SIZE];
+    let mut truncated = false;
+    loop {
+        if cancel.is_cancelled() {
+            return Ok(None);
+        }
+        let len = reader.read(&mut chunk)?;
+        if len == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..len]);
+        if max_bytes < buf.len() {
+            buf.truncate(max_bytes);
+            truncated = true;
+            break;
+        }
+    }
+
+    if buf.contains(&0) {
+        return Ok(Some(Preview::Binary));
+    }
+
+    let s = match std::str::from_utf8(&buf) {
+        Ok(s) => s,
+        Err(e) if truncated && e.error_len().is_none() => {
+            std::str::from_utf8(&buf[..e.valid_up_to()]).expect("valid")
+        }
+        Err(_) => return Ok(Some(Preview::Binary)),
+    };
+
+    Ok(Some(Preview::Text {
+        text: Rope::from_str(s),
+        truncated,
+    }))
+}
+
+#[test]
+fn load_partial_test() {
+    let cancel = CancelToken::default();
+    match load_partial("zażółć".as_bytes(), 3, &cancel).unwrap() {
+        // `ż` got cut in half
+        Some(Preview::Text { text, truncated }) => {
+            assert_eq!(text.to_string(), "za");
+            assert!(truncated);
+        }
+        other => panic!("{:?}", other),
+    }
+    match load_partial("abc".as_bytes(), 4, &cancel).unwrap() {
+        Some(Preview::Text { text, truncated }) => {
+            assert_eq!(text.to_string(), "abc");
+            assert!(!truncated);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        load_partial(&b"a\0b"[..], 4, &cancel).unwrap(),
+        Some(Preview::Binary)
+    ));
+
+    cancel.cancel();
+    assert!(load_partial("abc".as_bytes(), 4, &cancel)
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,192 @@
+//! Per-keystroke latency tracking
+//!
+//! `libbrz` does not measure time by itself (it has to work where there's
+//! no clock available), so the frontend times each stage of handling a
+//! key press and feeds the results here. It also tells how many cells it
+//! drew to the terminal, and how long language servers took to answer,
+//! for the debug HUD to show with what the state counts itself.
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// How many most recent frames to keep for statistics
+const DEFAULT_CAPACITY: usize = 1024;
+/// How many most recent server round trips to keep
+const ROUND_TRIPS_CAPACITY: usize = 64;
+
+/// Timing of a single input→update→render cycle
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// Reading and decoding the input event
+    pub input: Duration,
+    /// Handling the key in `State`
+    pub update: Duration,
+    /// Rendering and flushing the frame
+    pub render: Duration,
+}
+
+impl FrameTiming {
+    pub fn total(&self) -> Duration {
+        self.input + self.update + self.render
+    }
+}
+
+type StageFn = fn(&FrameTiming) -> Duration;
+
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    samples: VecDeque<FrameTiming>,
+    capacity: usize,
+    frames_total: u64,
+    /// Cells the frontend drew for the last frame
+    cells_drawn: Option<usize>,
+    round_trips: VecDeque<Duration>,
+    pub show_overlay: bool,
+    /// Show the debug HUD, with the counters of the last frame
+    pub show_hud: bool,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(DEFAULT_CAPACITY),
+            capacity: DEFAULT_CAPACITY,
+            frames_total: 0,
+            cells_drawn: None,
+            round_trips: VecDeque::with_capacity(ROUND_TRIPS_CAPACITY),
+            show_overlay: false,
+            show_hud: false,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn record(&mut self, timing: FrameTiming) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(timing);
+        self.frames_total += 1;
+    }
+
+    pub fn last(&self) -> Option<FrameTiming> {
+        self.samples.back().copied()
+    }
+
+    pub fn record_cells_drawn(&mut self, cells: usize) {
+        self.cells_drawn = Some(cells);
+    }
+
+    pub fn cells_drawn(&self) -> Option<usize> {
+        self.cells_drawn
+    }
+
+    /// Time between a request to a language server and its response
+    pub fn record_round_trip(&mut self, round_trip: Duration) {
+        if self.round_trips.len() == ROUND_TRIPS_CAPACITY {
+            self.round_trips.pop_front();
+        }
+        self.round_trips.push_back(round_trip);
+    }
+
+    pub fn last_round_trip(&self) -> Option<Duration> {
+        self.round_trips.back().copied()
+    }
+
+    /// Slowest of the round trips kept
+    pub fn max_round_trip(&self) -> Option<Duration> {
+        self.round_trips.iter().max().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// `p`-th percentile (`0.0..=1.0`) of a stage selected with `f`
+    ///
+    /// Uses nearest-rank method.
+    pub fn percentile(&self, p: f64, f: impl Fn(&FrameTiming) -> Duration) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::default();
+        }
+        let mut values: Vec<_> = self.samples.iter().map(f).collect();
+        values.sort();
+        let rank = (p * values.len() as f64).ceil() as usize;
+        values[rank.saturating_sub(1).min(values.len() - 1)]
+    }
+
+    /// Human readable report of all the stages
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "frames: {} total, {} sampled",
+            self.frames_total,
+            self.samples.len()
+        );
+        let _ = writeln!(
+            out,
+            "{:<8} {:>10} {:>10} {:>10} {:>10}",
+            "stage", "p50", "p90", "p99", "max"
+        );
+        let stages: [(&str, StageFn); 4] = [
+            ("input", |t| t.input),
+            ("update", |t| t.update),
+            ("render", |t| t.render),
+            ("total", FrameTiming::total),
+        ];
+        for (name, f) in stages.iter() {
+            let _ = writeln!(
+                out,
+                "{:<8} {:>10} {:>10} {:>10} {:>10}",
+                name,
+                format_duration(self.percentile(0.5, f)),
+                format_duration(self.percentile(0.9, f)),
+                format_duration(self.percentile(0.99, f)),
+                format_duration(self.percentile(1.0, f)),
+            );
+        }
+        out
+    }
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let micros = d.as_micros();
+    if micros < 1000 {
+        format!("{}µs", micros)
+    } else {
+        format!("{:.1}ms", micros as f64 / 1000.0)
+    }
+}
+
+/// `512B`, `3.2K`, `1.5M`
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{}B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1}K", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1}M", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[test]
+fn percentile_test() {
+    let mut p = Profiler::default();
+    for ms in 1..=100 {
+        p.record(FrameTiming {
+            update: Duration::from_millis(ms),
+            ..FrameTiming::default()
+        });
+    }
+    assert_eq!(p.percentile(0.5, |t| t.update), Duration::from_millis(50));
+    assert_eq!(p.percentile(0.99, |t| t.update), Duration::from_millis(99));
+    assert_eq!(p.percentile(1.0, |t| t.update), Duration::from_millis(100));
+
+    assert_eq!(format_bytes(512), "512B");
+    assert_eq!(format_bytes(3 * 1024 + 200), "3.2K");
+}
@@ -0,0 +1,92 @@
+//! Progress of background tasks
+//!
+//! Anything taking a while (a language server indexing, a scan of the
+//! workspace, highlighting a big file) is registered as a task, so the
+//! status line can tell the editor is busy rather than hung. `:tasks`
+//! lists them all.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub id: TaskId,
+    pub name: String,
+    /// `None` while it's not known how far along the task is
+    pub percentage: Option<u8>,
+    pub message: Option<String>,
+}
+
+impl Task {
+    /// Eg. `indexing 40%`
+    pub fn title(&self) -> String {
+        match self.percentage {
+            Some(percentage) => format!("{} {}%", self.name, percentage),
+            None => format!("{}…", self.name),
+        }
+    }
+}
+
+/// Tasks running, in the order they started
+#[derive(Debug, Clone, Default)]
+pub struct Tasks {
+    next_id: u64,
+    tasks: Vec<Task>,
+    /// List the tasks over the buffer
+    pub show_list: bool,
+}
+
+impl Tasks {
+    pub fn begin(&mut self, name: &str) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id,
+            name: name.to_owned(),
+            percentage: None,
+            message: None,
+        });
+        id
+    }
+
+    /// Update how far along a task is; ignored if it's over
+    pub fn report(&mut self, id: TaskId, percentage: Option<u8>, message: Option<&str>) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.id == id) {
+            task.percentage = percentage.map(|p| p.min(100));
+            task.message = message.map(str::to_owned);
+        }
+    }
+
+    pub fn end(&mut self, id: TaskId) {
+        self.tasks.retain(|task| task.id != id);
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    /// What the status line shows: the oldest task, and how many others
+    pub fn summary(&self) -> Option<String> {
+        let first = self.tasks.first()?;
+        Some(match self.tasks.len() {
+            1 => first.title(),
+            n => format!("{} (+{})", first.title(), n - 1),
+        })
+    }
+}
+
+#[test]
+fn tasks_test() {
+    let mut tasks = Tasks::default();
+    assert_eq!(tasks.summary(), None);
+    let index = tasks.begin("indexing");
+    assert_eq!(tasks.summary().as_deref(), Some("indexing…"));
+    let grep = tasks.begin("grep");
+    tasks.report(index, Some(140), Some("src/lib.rs"));
+    assert_eq!(tasks.summary().as_deref(), Some("indexing 100% (+1)"));
+    tasks.end(index);
+    tasks.report(index, Some(10), None);
+    assert_eq!(tasks.summary().as_deref(), Some("grep…"));
+    tasks.end(grep);
+    assert!(tasks.tasks().is_empty());
+}
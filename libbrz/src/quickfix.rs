@@ -0,0 +1,499 @@
+//! Quickfix lists: locations in files, eg. grep results
+//!
+//! A quickfix list is shown as a regular buffer with one
+//! `path:line:text` entry per line. The buffer can be edited, and the
+//! changes to the `text` parts written back to the files they came from.
+//! The text is highlighted in the language of its file, with what was
+//! matched underlined.
+use crate::document;
+use crate::syntax::{self, HighlightKind};
+use regex::{NoExpand, Regex};
+use ropey::Rope;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// 0-based
+    pub line: usize,
+    /// Content of the line, without the newline
+    pub text: String,
+    /// Bytes of `text` that matched, if it's a search result
+    pub matched: Option<Range<usize>>,
+}
+
+impl Entry {
+    fn to_line(&self) -> String {
+        format!("{}:{}:{}", self.path.display(), self.line + 1, self.text)
+    }
+
+    fn parse(s: &str) -> Option<Entry> {
+        let mut parts = s.splitn(3, ':');
+        let path = parts.next()?;
+        let line: usize = parts.next()?.parse().ok()?;
+        let text = parts.next()?;
+        Some(Entry {
+            path: PathBuf::from(path),
+            line: line.checked_sub(1)?,
+            text: text.to_owned(),
+            matched: None,
+        })
+    }
+}
+
+/// How a part of a line of the list is drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineSpan {
+    /// The `path:line:` part
+    Location,
+    Syntax(HighlightKind),
+    Match,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quickfix {
+    pub entries: Vec<Entry>,
+}
+
+/// Replace the content of a single line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEdit {
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// Edits waiting for a confirmation, grouped by file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingEdits {
+    pub files: BTreeMap<PathBuf, Vec<LineEdit>>,
+    /// Buffer with the `Quickfix` the edits come from, if any
+    pub quickfix_buffer_i: Option<usize>,
+    /// File renamed, old path then new, once the edits are applied
+    pub rename: Option<(PathBuf, PathBuf)>,
+}
+
+impl Quickfix {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn to_text(&self) -> String {
+        lines_of(&self.entries)
+    }
+
+    /// Add `entries` at the end, returning the lines they add to the text
+    pub fn extend(&mut self, entries: Vec<Entry>) -> String {
+        let lines = lines_of(&entries);
+        self.entries.extend(entries);
+        lines
+    }
+
+    /// The location a `line` of the list points to, as it reads
+    pub fn location(line: &str) -> Option<(PathBuf, usize)> {
+        Entry::parse(line).map(|entry| (entry.path, entry.line))
+    }
+
+    /// Parts of a `line` of the list to draw differently, in chars of
+    /// the line
+    ///
+    /// The match is only shown while the text is the one of the entry.
+    pub fn line_spans(&self, line: &str) -> Vec<(Range<usize>, LineSpan)> {
+        let shown = match Entry::parse(line) {
+            Some(shown) => shown,
+            None => return vec![],
+        };
+        let text_start = line.chars().count() - shown.text.chars().count();
+        let mut spans = vec![(0..text_start, LineSpan::Location)];
+        let highlights = document::detect_language(&shown.path)
+            .and_then(|language| syntax::highlight_str(&shown.text, language));
+        for highlight in highlights.into_iter().flatten() {
+            spans.push((
+                text_start + highlight.range.start..text_start + highlight.range.end,
+                LineSpan::Syntax(highlight.kind),
+            ));
+        }
+        let matched = self
+            .entries
+            .iter()
+            .find(|e| e.path == shown.path && e.line == shown.line && e.text == shown.text)
+            .and_then(|e| e.matched.clone())
+            .filter(|matched| shown.text.get(matched.clone()).is_some());
+        if let Some(matched) = matched {
+            let chars = |bytes: usize| text_start + shown.text[..bytes].chars().count();
+            spans.push((chars(matched.start)..chars(matched.end), LineSpan::Match));
+        }
+        spans
+    }
+
+    /// Compare the (possibly edited) text of the list with the entries
+    ///
+    /// Removed lines are ignored, so the list can be narrowed down before
+    /// editing. Lines that don't correspond to any of the entries are an
+    /// error.
+    pub fn compute_edits(&self, text: &Rope) -> Result<BTreeMap<PathBuf, Vec<LineEdit>>, String> {
+        let mut files: BTreeMap<PathBuf, Vec<LineEdit>> = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.to_string();
+            let line = line.trim_end_matches('\n');
+            if line.is_empty() {
+                continue;
+            }
+            let edited = Entry::parse(line)
+                .ok_or_else(|| format!("line {}: not a quickfix entry", i + 1))?;
+            let orig = self
+                .entries
+                .iter()
+                .find(|e| e.path == edited.path && e.line == edited.line)
+                .ok_or_else(|| format!("line {}: no such entry", i + 1))?;
+            if orig.text == edited.text {
+                continue;
+            }
+            let edits = files.entry(edited.path.clone()).or_default();
+            if let Some(prev) = edits.iter().find(|e| e.line == edited.line) {
+                if prev.new != edited.text {
+                    return Err(format!("line {}: conflicting edits", i + 1));
+                }
+                continue;
+            }
+            edits.push(LineEdit {
+                line: edited.line,
+                old: orig.text.clone(),
+                new: edited.text,
+            });
+        }
+        Ok(files)
+    }
+
+    /// Make the entries reflect the edits applied to `path`
+    pub fn update_applied(&mut self, path: &Path, edits: &[LineEdit]) {
+        for entry in self.entries.iter_mut().filter(|e| e.path == path) {
+            if let Some(edit) = edits.iter().find(|e| e.line == entry.line) {
+                entry.text = edit.new.clone();
+            }
+        }
+    }
+}
+
+fn lines_of(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&entry.to_line());
+        out.push('\n');
+    }
+    out
+}
+
+impl PendingEdits {
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Diff of all the changes, for the user to confirm
+    pub fn to_diff(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# {} changes in {} files, :w to apply, :db to discard",
+            self.len(),
+            self.files.len()
+        );
+        if let Some((old, new)) = &self.rename {
+            let _ = writeln!(
+                out,
+                "# then {} is renamed to {}",
+                old.display(),
+                new.display()
+            );
+        }
+        let _ = writeln!(out, "# delete a file or a change from this list to skip it");
+        for (path, edits) in &self.files {
+            let _ = writeln!(out, "\n{}", path.display());
+            for edit in edits {
+                let _ = writeln!(out, "  {:>5} - {}", edit.line + 1, edit.old);
+                let _ = writeln!(out, "  {:>5} + {}", edit.line + 1, edit.new);
+            }
+        }
+        out
+    }
+
+    /// Keep only the edits still listed in the (possibly edited) diff `text`
+    pub fn retain_listed(&mut self, text: &Rope) {
+        let mut listed = BTreeSet::new();
+        let mut path = None;
+        for line in text.lines() {
+            let line = line.to_string();
+            let line = line.trim_end_matches('\n');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(' ') {
+                path = Some(PathBuf::from(line));
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            if let (Some(path), Some(Ok(line)), Some("+")) = (
+                path.as_ref(),
+                parts.next().map(str::parse::<usize>),
+                parts.next(),
+            ) {
+                listed.insert((path.clone(), line.saturating_sub(1)));
+            }
+        }
+        for (path, edits) in &mut self.files {
+            edits.retain(|edit| listed.contains(&(path.clone(), edit.line)));
+        }
+        self.files.retain(|_, edits| !edits.is_empty());
+    }
+}
+
+/// Edits replacing every match of `re` in the `entries` with `replacement`
+pub fn replace_edits(
+    entries: &[Entry],
+    re: &Regex,
+    replacement: &str,
+) -> BTreeMap<PathBuf, Vec<LineEdit>> {
+    let mut files: BTreeMap<PathBuf, Vec<LineEdit>> = BTreeMap::new();
+    for entry in entries {
+        let new = re.replace_all(&entry.text, NoExpand(replacement));
+        if new != entry.text {
+            files.entry(entry.path.clone()).or_default().push(LineEdit {
+                line: entry.line,
+                old: entry.text.clone(),
+                new: new.into_owned(),
+            });
+        }
+    }
+    files
+}
+
+/// The path without its extension, `/`-separated, from the current
+/// directory if relative
+fn module_path(path: &Path) -> String {
+    let path = path.with_extension("");
+    let parts: Vec<_> = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+/// Edits updating the references to a file renamed from `old` to `new`
+/// in the `entries`: its path without the extension, then its stem as a
+/// whole word
+pub fn rename_file_edits(
+    entries: &[Entry],
+    old: &Path,
+    new: &Path,
+) -> BTreeMap<PathBuf, Vec<LineEdit>> {
+    let stem = |path: &Path| path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    let (old_module, new_module) = (module_path(old), module_path(new));
+    let stems = match (stem(old), stem(new)) {
+        (Some(old_stem), Some(new_stem)) if old_stem != new_stem => {
+            let re = Regex::new(&format!(r"\b{}\b", regex::escape(&old_stem)))
+                .expect("escaped pattern is valid");
+            Some((re, new_stem))
+        }
+        _ => None,
+    };
+    let mut files: BTreeMap<PathBuf, Vec<LineEdit>> = BTreeMap::new();
+    for entry in entries {
+        let mut new = entry.text.replace(&old_module, &new_module);
+        if let Some((re, new_stem)) = &stems {
+            // not the stem of the new path just put in
+            new = new
+                .split(&new_module)
+                .map(|part| re.replace_all(part, NoExpand(new_stem)))
+                .collect::<Vec<_>>()
+                .join(&new_module);
+        }
+        if new != entry.text {
+            files.entry(entry.path.clone()).or_default().push(LineEdit {
+                line: entry.line,
+                old: entry.text.clone(),
+                new,
+            });
+        }
+    }
+    files
+}
+
+/// Apply `edits` to `text`
+///
+/// Fails without changing anything if any line doesn't hold the expected
+/// old content anymore.
+pub fn apply_edits(text: &mut Rope, edits: &[LineEdit]) -> Result<(), String> {
+    let line_range = |text: &Rope, line: usize| {
+        let start = text.line_to_char(line);
+        let len = text.line(line).chars().take_while(|&ch| ch != '\n').count();
+        (start, start + len)
+    };
+
+    for edit in edits {
+        if text.len_lines() <= edit.line {
+            return Err(format!("line {} is gone", edit.line + 1));
+        }
+        let (start, end) = line_range(text, edit.line);
+        if text.slice(start..end) != edit.old.as_str() {
+            return Err(format!("line {} has changed", edit.line + 1));
+        }
+    }
+    for edit in edits {
+        let (start, end) = line_range(text, edit.line);
+        text.remove(start..end);
+        text.insert(start, &edit.new);
+    }
+    Ok(())
+}
+
+#[test]
+fn compute_and_apply_edits_test() {
+    let entry = |path: &str, line, text: &str| Entry {
+        path: PathBuf::from(path),
+        line,
+        text: text.to_owned(),
+        matched: None,
+    };
+    let qf = Quickfix::new(vec![
+        entry("a.rs", 0, "fn foo() {"),
+        entry("a.rs", 2, "foo();"),
+        entry("b.rs", 4, "foo();"),
+    ]);
+    assert_eq!(
+        qf.to_text(),
+        "a.rs:1:fn foo() {\na.rs:3:foo();\nb.rs:5:foo();\n"
+    );
+
+    // rename in a.rs, b.rs entry removed from the list
+    let edited = Rope::from_str("a.rs:1:fn bar() {\na.rs:3:foo();\n");
+    let files = qf.compute_edits(&edited).unwrap();
+    assert_eq!(files.len(), 1);
+    let edits = &files[&PathBuf::from("a.rs")];
+    assert_eq!(
+        edits,
+        &vec![LineEdit {
+            line: 0,
+            old: "fn foo() {".into(),
+            new: "fn bar() {".into(),
+        }]
+    );
+
+    assert!(qf.compute_edits(&Rope::from_str("c.rs:1:new\n")).is_err());
+
+    let mut text = Rope::from_str("fn foo() {\n}\n");
+    apply_edits(&mut text, edits).unwrap();
+    assert_eq!(text.to_string(), "fn bar() {\n}\n");
+    // already applied, so the old content doesn't match
+    assert!(apply_edits(&mut text, edits).is_err());
+}
+
+#[test]
+fn replace_and_exclude_test() {
+    let entry = |path: &str, line, text: &str| Entry {
+        path: PathBuf::from(path),
+        line,
+        text: text.to_owned(),
+        matched: None,
+    };
+    let entries = vec![
+        entry("a.rs", 0, "fn foo() {}"),
+        entry("a.rs", 3, "foo(); foo_bar();"),
+        entry("b.rs", 1, "foo()"),
+    ];
+    let re = Regex::new(r"\bfoo\b").unwrap();
+    let mut pending = PendingEdits {
+        files: replace_edits(&entries, &re, "$bar"),
+        quickfix_buffer_i: None,
+        rename: None,
+    };
+    assert_eq!(pending.len(), 3);
+    assert_eq!(
+        pending.files[&PathBuf::from("a.rs")][1].new,
+        "$bar(); foo_bar();"
+    );
+
+    // drop b.rs and the first change of a.rs
+    let diff = pending.to_diff();
+    let kept: String = diff
+        .lines()
+        .filter(|line| !line.contains("b.rs") && !line.starts_with("      1"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    pending.retain_listed(&Rope::from_str(&kept));
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending.files[&PathBuf::from("a.rs")][0].line, 3);
+}
+
+#[test]
+fn line_spans_test() {
+    let qf = Quickfix::new(vec![Entry {
+        path: PathBuf::from("a.rs"),
+        line: 0,
+        text: "let é = foo;".into(),
+        matched: Some(9..12),
+    }]);
+    assert_eq!(
+        qf.line_spans("a.rs:1:let é = foo;"),
+        vec![
+            (0..7, LineSpan::Location),
+            (7..10, LineSpan::Syntax(HighlightKind::Keyword)),
+            (15..18, LineSpan::Match),
+        ]
+    );
+    // edited, so the match may not be there anymore
+    assert_eq!(
+        qf.line_spans("a.rs:1:let é = bar;"),
+        vec![
+            (0..7, LineSpan::Location),
+            (7..10, LineSpan::Syntax(HighlightKind::Keyword)),
+        ]
+    );
+    assert_eq!(qf.line_spans("# not an entry"), vec![]);
+}
+
+#[test]
+fn rename_file_edits_test() {
+    let entry = |path: &str, line, text: &str| Entry {
+        path: PathBuf::from(path),
+        line,
+        text: text.to_owned(),
+        matched: None,
+    };
+    let entries = vec![
+        entry("src/main.rs", 0, "mod util;"),
+        entry("src/main.rs", 3, "use crate::util::util_fn;"),
+        entry("web/app.js", 1, "import x from './src/util';"),
+    ];
+    let files = rename_file_edits(
+        &entries,
+        Path::new("./src/util.rs"),
+        Path::new("src/helpers.rs"),
+    );
+    let new = |path: &str| -> Vec<&str> {
+        files[&PathBuf::from(path)]
+            .iter()
+            .map(|e| e.new.as_str())
+            .collect()
+    };
+    assert_eq!(
+        new("src/main.rs"),
+        vec!["mod helpers;", "use crate::helpers::util_fn;"]
+    );
+    assert_eq!(new("web/app.js"), vec!["import x from './src/helpers';"]);
+
+    // moved, not renamed: only the paths change
+    let files = rename_file_edits(&entries, Path::new("src/util.rs"), Path::new("lib/util.rs"));
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        files[&PathBuf::from("web/app.js")][0].new,
+        "import x from './lib/util';"
+    );
+}
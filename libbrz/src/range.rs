@@ -23,6 +23,10 @@ impl Range {
         sorted.to.0 - sorted.from.0
     }
 
+    pub fn is_empty(self) -> bool {
+        self.from == self.to
+    }
+
     pub fn reversed(self) -> Self {
         Self {
             to: self.from,
@@ -30,7 +34,7 @@ impl Range {
         }
     }
 
-    pub fn slice(self, text: &Rope) -> RopeSlice {
+    pub fn slice(self, text: &Rope) -> RopeSlice<'_> {
         let sorted = self.sorted();
         text.slice(sorted.from.0..sorted.to.0)
     }
@@ -0,0 +1,96 @@
+//! Copies of the buffers with changes not written, to recover them after
+//! a crash
+//!
+//! The text of a buffer with changes is copied aside, to a swap file, once
+//! a few seconds went by or enough edits were made since the last copy,
+//! and whenever the editor is idle.
+//! The copy goes once the buffer has no changes left, or is deleted, and
+//! on a normal exit. Those left over, after a crash or a terminal gone,
+//! are told at the next start: `:recover` applies them to the buffers of
+//! their files, to be written, and `:recover-drop` forgets them.
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Time since the last copy of a buffer after which its changes are
+/// copied again
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+/// Edits after which they are, whatever the time
+pub const SWAP_EDITS: u64 = 200;
+
+/// What's asked of the swap handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapOp {
+    /// Keep `text` as the copy of the file at `path`
+    Write { path: PathBuf, text: String },
+    /// Drop the copy of the file at `path`, if there's one
+    Remove(PathBuf),
+}
+
+/// A copy left over, of the file at `path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swap {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// Whether a buffer is to be copied again, after `edits` since its last
+/// copy, made `since` ago
+pub fn is_due(edits: u64, since: Duration) -> bool {
+    0 < edits && (SWAP_EDITS <= edits || SWAP_INTERVAL <= since)
+}
+
+#[test]
+fn recover_test() {
+    use crate::state::State;
+    use crate::Key;
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let ops = Rc::new(RefCell::new(vec![]));
+    let mut state = State::default();
+    let kept = ops.clone();
+    state.register_swap_handler(move |op| {
+        kept.borrow_mut().push(op.clone());
+        Ok(())
+    });
+    state.register_read_handler(|_| Ok(b"one\n".to_vec()));
+    state.register_write_handler(|_, _| Ok(()));
+    state.note_time(UNIX_EPOCH);
+    state.open_buffer(Path::new("a.txt"));
+    state.handle_key(Key::Char('d'));
+    // not yet
+    assert!(ops.borrow().is_empty());
+    state.note_time(UNIX_EPOCH + SWAP_INTERVAL);
+    state.handle_key(Key::Char('d'));
+    assert_eq!(
+        ops.borrow_mut().pop(),
+        Some(SwapOp::Write {
+            path: "a.txt".into(),
+            text: "e\n".into(),
+        })
+    );
+    state.write_buffer(None);
+    state.write_swaps();
+    assert_eq!(ops.borrow_mut().pop(), Some(SwapOp::Remove("a.txt".into())));
+    // when idle, at once
+    state.handle_key(Key::Char('d'));
+    assert!(ops.borrow().is_empty());
+    state.write_swaps();
+    assert_eq!(ops.borrow().len(), 1);
+
+    // left over from before
+    let mut state = State::default();
+    state.register_read_handler(|_| Ok(b"one\n".to_vec()));
+    state.note_time(SystemTime::now());
+    state.offer_recovery(vec![Swap {
+        path: "a.txt".into(),
+        text: "one\ntwo\n".into(),
+    }]);
+    state.recover();
+    assert_eq!(state.cur_buffer().text.to_string(), "one\ntwo\n");
+    assert!(state.is_modified());
+    state.handle_key(Key::Char('u'));
+    assert_eq!(state.cur_buffer().text.to_string(), "one\n");
+}
@@ -0,0 +1,408 @@
+//! Yanked text, and the way it was selected
+//!
+//! Pasting depends on the kind of what was yanked: whole lines are
+//! pasted as lines of their own, a column of selections one per line
+//! goes back as a column, and anything else where the cursors are.
+//!
+//! Recent yanks and deletes are kept in a `YankHistory`, to paste one of
+//! them again, or to swap what was just pasted for an older one.
+//!
+//! Besides the unnamed register, pasted by default, yanks and deletes can
+//! go to `Registers` named by a letter or a digit, an uppercase letter
+//! appending to its lowercase one. Some names are special: `_` drops
+//! what goes there, `/` and `:` give the last search and command line,
+//! and `+` is the system clipboard, through the frontend.
+//!
+//! Keys typed can be recorded in a register of `Macros` named by a char,
+//! to be replayed as if typed again.
+use crate::Key;
+use ropey::Rope;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+
+/// Yanks a `YankHistory` keeps
+const HISTORY_LEN: usize = 30;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RegisterKind {
+    #[default]
+    Chars,
+    /// Whole lines, with their line ends
+    Lines,
+    /// One part in each of consecutive lines
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Register {
+    pub kind: RegisterKind,
+    /// Text of each selection
+    pub parts: Vec<Rope>,
+}
+
+impl Register {
+    /// The `ranges` of `text` yanked, one per selection
+    pub fn yanked(text: &Rope, ranges: &[Range<usize>]) -> Self {
+        Self {
+            kind: kind_of(text, ranges),
+            parts: ranges
+                .iter()
+                .map(|range| text.slice(range.clone()).into())
+                .collect(),
+        }
+    }
+
+    /// What to paste where there are no lines, like in a prompt: the
+    /// parts without their line end
+    pub fn to_chars(&self) -> Self {
+        let parts = match self.kind {
+            RegisterKind::Lines => self
+                .parts
+                .iter()
+                .map(|part| {
+                    let part = part.to_string();
+                    Rope::from_str(part.strip_suffix('\n').unwrap_or(&part))
+                })
+                .collect(),
+            _ => self.parts.clone(),
+        };
+        Self {
+            kind: RegisterKind::Chars,
+            parts,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.iter().all(|part| part.len_chars() == 0)
+    }
+
+    /// The first line yanked, and how many parts and lines there are
+    /// besides
+    pub fn summary(&self) -> String {
+        let first = self
+            .parts
+            .first()
+            .map(|part| {
+                part.lines()
+                    .next()
+                    .map(|l| l.to_string())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+        let lines: usize = self
+            .parts
+            .iter()
+            .map(|part| part.len_lines() - (self.kind == RegisterKind::Lines) as usize)
+            .sum();
+        let mut summary = first.trim_end_matches('\n').to_owned();
+        if 1 < self.parts.len() {
+            summary.push_str(&format!(" ({} parts)", self.parts.len()));
+        } else if 1 < lines {
+            summary.push_str(&format!(" ({} lines)", lines));
+        }
+        summary
+    }
+}
+
+/// Recent yanks and deletes, newest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct YankHistory {
+    entries: VecDeque<Register>,
+}
+
+impl YankHistory {
+    /// Keep `register` as the newest entry; it moves up if it was there
+    pub fn push(&mut self, register: Register) {
+        if register.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| *entry != register);
+        self.entries.push_front(register);
+        self.entries.truncate(HISTORY_LEN);
+    }
+
+    pub fn get(&self, i: usize) -> Option<&Register> {
+        self.entries.get(i)
+    }
+
+    pub fn position(&self, register: &Register) -> Option<usize> {
+        self.entries.iter().position(|entry| entry == register)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Register> {
+        self.entries.iter()
+    }
+}
+
+/// What `"` names, the unnamed register
+pub const UNNAMED: char = '"';
+/// What goes there is dropped
+pub const BLACK_HOLE: char = '_';
+pub const LAST_SEARCH: char = '/';
+pub const LAST_COMMAND: char = ':';
+pub const CLIPBOARD: char = '+';
+
+/// Copying to the system clipboard, or reading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardOp {
+    Copy(String),
+    /// Gives what's in the clipboard, if anything
+    Paste,
+}
+
+impl Register {
+    /// Of `text`, whole lines if it ends with a line end
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            kind: if text.ends_with('\n') {
+                RegisterKind::Lines
+            } else {
+                RegisterKind::Chars
+            },
+            parts: vec![Rope::from_str(text)],
+        }
+    }
+
+    /// The parts one per line, as for other programs
+    pub fn to_text(&self) -> String {
+        let separator = if self.kind == RegisterKind::Lines {
+            ""
+        } else {
+            "\n"
+        };
+        let parts: Vec<String> = self.parts.iter().map(Rope::to_string).collect();
+        parts.join(separator)
+    }
+}
+
+/// Whether `name` is a register that can be yanked to
+pub fn is_valid(name: char) -> bool {
+    name.is_ascii_alphanumeric() || [UNNAMED, BLACK_HOLE, CLIPBOARD].contains(&name)
+}
+
+/// The registers named by a letter or a digit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registers {
+    named: BTreeMap<char, Register>,
+}
+
+impl Registers {
+    pub fn get(&self, name: char) -> Option<&Register> {
+        self.named.get(&name.to_ascii_lowercase())
+    }
+
+    /// Keep `register` in `name`, appended to what's there if uppercase:
+    /// part to part if there are as many, as more parts otherwise
+    pub fn set(&mut self, name: char, register: Register) {
+        let lower = name.to_ascii_lowercase();
+        let register = match self.named.remove(&lower) {
+            Some(mut old) if name.is_ascii_uppercase() => {
+                if old.parts.len() == register.parts.len() {
+                    for (part, more) in old.parts.iter_mut().zip(&register.parts) {
+                        part.append(more.clone());
+                    }
+                } else {
+                    old.parts.extend(register.parts);
+                }
+                if old.kind != register.kind {
+                    old.kind = RegisterKind::Chars;
+                }
+                old
+            }
+            _ => register,
+        };
+        self.named.insert(lower, register);
+    }
+
+    /// Names and contents, in the order of the names
+    pub fn iter(&self) -> impl Iterator<Item = (char, &Register)> {
+        self.named.iter().map(|(&name, register)| (name, register))
+    }
+}
+
+/// Keys recorded in registers, and the one being recorded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Macros {
+    registers: BTreeMap<char, Vec<Key>>,
+    recording: Option<(char, Vec<Key>)>,
+    /// The last one replayed
+    last: Option<char>,
+}
+
+impl Macros {
+    /// Start recording in `register`, dropping what was recorded before
+    /// unless it's uppercase, to append to its lowercase one
+    pub fn start(&mut self, register: char) {
+        let keys = if register.is_uppercase() {
+            self.get(register).map(<[Key]>::to_vec).unwrap_or_default()
+        } else {
+            vec![]
+        };
+        self.recording = Some((register.to_lowercase().next().unwrap_or(register), keys));
+    }
+
+    /// Stop recording, returning the register recorded in
+    pub fn stop(&mut self) -> Option<char> {
+        let (register, keys) = self.recording.take()?;
+        self.registers.insert(register, keys);
+        Some(register)
+    }
+
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|&(register, _)| register)
+    }
+
+    /// Note `key` in the register being recorded, if any
+    pub fn record(&mut self, key: Key) {
+        if let Some((_, keys)) = self.recording.as_mut() {
+            keys.push(key);
+        }
+    }
+
+    /// The register to replay for `register`, `@` being the last one
+    /// replayed, and its keys; it becomes the one to replay again
+    pub fn replay(&mut self, register: char) -> Option<(char, &[Key])> {
+        let register = match register {
+            '@' => self.last?,
+            register => register.to_lowercase().next().unwrap_or(register),
+        };
+        self.last = Some(register);
+        let keys = self.registers.get(&register)?;
+        Some((register, keys))
+    }
+
+    pub fn get(&self, register: char) -> Option<&[Key]> {
+        let register = register.to_lowercase().next().unwrap_or(register);
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+}
+
+fn kind_of(text: &Rope, ranges: &[Range<usize>]) -> RegisterKind {
+    let whole_line = |range: &Range<usize>| {
+        !range.is_empty()
+            && text.line_to_char(text.char_to_line(range.start)) == range.start
+            && (range.end == text.len_chars() || text.char(range.end - 1) == '\n')
+    };
+    if !ranges.is_empty() && ranges.iter().all(whole_line) {
+        return RegisterKind::Lines;
+    }
+    let mut lines: Vec<(usize, &Range<usize>)> = ranges
+        .iter()
+        .map(|range| (text.char_to_line(range.start), range))
+        .collect();
+    lines.sort_by_key(|&(line, _)| line);
+    let in_line = |&(line, range): &(usize, &Range<usize>)| {
+        range.is_empty() || text.char_to_line(range.end - 1) == line
+    };
+    let consecutive = lines.windows(2).all(|pair| pair[0].0 + 1 == pair[1].0);
+    if 2 <= lines.len() && consecutive && lines.iter().all(in_line) {
+        RegisterKind::Block
+    } else {
+        RegisterKind::Chars
+    }
+}
+
+#[test]
+fn kind_test() {
+    let text = Rope::from_str("one\ntwo\nthree");
+    let kind = |ranges: &[(usize, usize)]| {
+        let ranges: Vec<_> = ranges.iter().map(|&(start, end)| start..end).collect();
+        Register::yanked(&text, &ranges).kind
+    };
+    assert_eq!(kind(&[(0, 4)]), RegisterKind::Lines);
+    assert_eq!(kind(&[(0, 8)]), RegisterKind::Lines);
+    assert_eq!(kind(&[(8, 13)]), RegisterKind::Lines);
+    assert_eq!(kind(&[(0, 3)]), RegisterKind::Chars);
+    assert_eq!(kind(&[(1, 2), (5, 6)]), RegisterKind::Block);
+    assert_eq!(kind(&[(1, 2), (9, 10)]), RegisterKind::Chars);
+    assert_eq!(kind(&[(1, 6), (9, 10)]), RegisterKind::Chars);
+    let lines = Register::yanked(&text, &[0..4, 4..8]);
+    assert_eq!(lines.kind, RegisterKind::Lines);
+    assert_eq!(
+        lines.to_chars().parts,
+        vec![Rope::from_str("one"), Rope::from_str("two")]
+    );
+}
+
+#[test]
+fn yank_history_test() {
+    let text = Rope::from_str("one\ntwo\nthree");
+    let yanked = |text: &Rope, range: Range<usize>| Register::yanked(text, &[range]);
+    let mut history = YankHistory::default();
+    history.push(yanked(&text, 0..3));
+    history.push(yanked(&text, 0..8));
+    history.push(yanked(&text, 4..4));
+    history.push(yanked(&text, 0..3));
+    assert_eq!(history.len(), 2);
+    let summaries: Vec<String> = history.iter().map(Register::summary).collect();
+    assert_eq!(summaries, vec!["one", "one (2 lines)"]);
+    assert_eq!(history.position(&yanked(&text, 0..8)), Some(1));
+    assert_eq!(
+        Register::yanked(&text, &[1..2, 5..6]).summary(),
+        "n (2 parts)"
+    );
+    for i in 0..40 {
+        let text = Rope::from_str(&i.to_string());
+        history.push(yanked(&text, 0..text.len_chars()));
+    }
+    assert_eq!(history.len(), HISTORY_LEN);
+    assert_eq!(history.get(0).map(Register::summary).as_deref(), Some("39"));
+}
+
+#[test]
+fn macros_test() {
+    let mut macros = Macros::default();
+    assert_eq!(macros.stop(), None);
+    macros.record(Key::Char('x'));
+    macros.start('a');
+    assert_eq!(macros.recording(), Some('a'));
+    macros.record(Key::Char('w'));
+    assert_eq!(macros.stop(), Some('a'));
+    macros.record(Key::Char('x'));
+    assert_eq!(macros.get('a'), Some(&[Key::Char('w')][..]));
+
+    // uppercase appends
+    macros.start('A');
+    macros.record(Key::Esc);
+    macros.stop();
+    assert_eq!(macros.get('a'), Some(&[Key::Char('w'), Key::Esc][..]));
+    assert_eq!(macros.replay('@'), None);
+    assert_eq!(
+        macros.replay('A').map(|(r, keys)| (r, keys.len())),
+        Some(('a', 2))
+    );
+    assert_eq!(macros.replay('@').map(|(r, _)| r), Some('a'));
+}
+
+#[test]
+fn registers_test() {
+    let mut registers = Registers::default();
+    registers.set('a', Register::from_text("one"));
+    registers.set('A', Register::from_text(" two"));
+    assert_eq!(
+        registers.get('a').map(Register::to_text).as_deref(),
+        Some("one two")
+    );
+    registers.set('A', Register::from_text("three\n"));
+    let a = registers.get('A').cloned().unwrap_or_default();
+    assert_eq!(a.kind, RegisterKind::Chars);
+    assert_eq!(a.to_text(), "one twothree\n");
+    registers.set('a', Register::from_text("four\n"));
+    assert_eq!(
+        registers.get('a').map(|r| r.kind),
+        Some(RegisterKind::Lines)
+    );
+
+    let text = Rope::from_str("ab\ncd\n");
+    assert_eq!(Register::yanked(&text, &[0..1, 3..4]).to_text(), "a\nc");
+    assert_eq!(Register::yanked(&text, &[0..3, 3..6]).to_text(), "ab\ncd\n");
+    assert!(is_valid('9') && is_valid('+') && !is_valid('/') && !is_valid('é'));
+}
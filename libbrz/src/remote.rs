@@ -0,0 +1,204 @@
+//! Opening files in an already running instance, and serving its state
+//!
+//! `brz --remote` sends the files to open over a socket to the instance
+//! already running, if there's one, instead of starting a new one. Each
+//! request is a line of the form `open\t<line>\t<path>`, with the line
+//! empty when not given.
+//!
+//! The same socket takes requests in JSON, a line each, answered with a
+//! line of JSON each, which is how the editor started with `brz --server`,
+//! with no terminal of its own, is driven:
+//!
+//! ```text
+//! {"op":"open","path":"src/main.rs","line":3}
+//! {"op":"keys","keys":["i","h","i","esc"]}
+//! {"op":"state","text":true}
+//! ```
+//!
+//! Keys are named as in the keymaps. An answer is `{"ok":…}`, with the
+//! state of the editor for `state`, or `{"error":"…"}`.
+//!
+//! Keys sent this way do anything the keys typed would, running commands
+//! included: the socket is for the user's own processes alone, which the
+//! frontend makes sure of by the user of each client.
+use crate::json::{self, Value};
+use crate::Key;
+use std::path::{Path, PathBuf};
+
+/// A file to open, and the 1-based line to put the cursor on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+}
+
+impl Location {
+    pub fn encode(&self) -> String {
+        format!(
+            "open\t{}\t{}\n",
+            self.line.map(|line| line.to_string()).unwrap_or_default(),
+            self.path.display()
+        )
+    }
+
+    pub fn decode(request: &str) -> Option<Self> {
+        let mut parts = request.trim_end_matches('\n').splitn(3, '\t');
+        if parts.next()? != "open" {
+            return None;
+        }
+        let line = match parts.next()? {
+            "" => None,
+            line => Some(line.parse().ok()?),
+        };
+        Some(Self {
+            path: PathBuf::from(parts.next()?),
+            line,
+        })
+    }
+}
+
+/// What is asked of the instance over its socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Open(Location),
+    Keys(Vec<Key>),
+    /// Of the editor, with the text of the current buffer if `text`
+    State {
+        text: bool,
+    },
+}
+
+impl Request {
+    /// The request of a line sent, in JSON or as `--remote` sends it
+    pub fn decode(line: &str) -> Result<Self, String> {
+        if !line.trim_start().starts_with('{') {
+            return Location::decode(line)
+                .map(Request::Open)
+                .ok_or_else(|| "no request".to_owned());
+        }
+        let message = json::parse(line).map_err(|e| e.to_string())?;
+        let op = message.get("op").as_str().ok_or("no op")?;
+        Ok(match op {
+            "open" => Request::Open(Location {
+                path: message.get("path").as_str().ok_or("no path")?.into(),
+                line: message.get("line").as_usize(),
+            }),
+            "keys" => Request::Keys(
+                message
+                    .get("keys")
+                    .items()
+                    .iter()
+                    .map(|key| {
+                        let name = key.as_str().ok_or("keys are strings")?;
+                        crate::layer::parse_key(name).ok_or_else(|| format!("no key {}", name))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            "state" => Request::State {
+                text: message.get("text").as_bool().unwrap_or(false),
+            },
+            op => return Err(format!("no op {}", op)),
+        })
+    }
+
+    /// Whether it's sent in JSON, and so answered
+    pub fn is_answered(line: &str) -> bool {
+        line.trim_start().starts_with('{')
+    }
+}
+
+/// The line answering a request
+pub fn answer(result: Result<Value, String>) -> String {
+    match result {
+        Ok(value) => Value::object(vec![("ok", value)]),
+        Err(e) => Value::object(vec![("error", e.into())]),
+    }
+    .to_string()
+}
+
+/// The files to open from the command line arguments
+///
+/// A `+<line>` argument applies to the file before it, or to the next
+/// one if it comes first.
+pub fn parse_args(args: &[PathBuf]) -> Vec<Location> {
+    let mut locations: Vec<Location> = vec![];
+    let mut pending_line = None;
+    for arg in args {
+        let line = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix('+'))
+            .and_then(|line| line.parse().ok());
+        match (line, locations.last_mut()) {
+            (Some(line), Some(last)) if last.line.is_none() => last.line = Some(line),
+            (Some(line), _) => pending_line = Some(line),
+            (None, _) => locations.push(Location {
+                path: arg.clone(),
+                line: pending_line.take(),
+            }),
+        }
+    }
+    locations
+}
+
+/// `path` relative to `dir`, if it's inside it
+pub fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    path.strip_prefix(dir).unwrap_or(path).to_owned()
+}
+
+#[test]
+fn parse_args_test() {
+    let args: Vec<PathBuf> = ["+3", "a.rs", "b.rs", "+10", "c.rs"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    let locations = parse_args(&args);
+    assert_eq!(
+        locations,
+        vec![
+            Location {
+                path: "a.rs".into(),
+                line: Some(3),
+            },
+            Location {
+                path: "b.rs".into(),
+                line: Some(10),
+            },
+            Location {
+                path: "c.rs".into(),
+                line: None,
+            },
+        ]
+    );
+    for location in &locations {
+        assert_eq!(
+            Location::decode(&location.encode()).as_ref(),
+            Some(location)
+        );
+    }
+    assert_eq!(Location::decode("close\t\ta.rs"), None);
+
+    assert_eq!(
+        Request::decode("open\t3\ta.rs"),
+        Ok(Request::Open(Location {
+            path: "a.rs".into(),
+            line: Some(3)
+        }))
+    );
+    assert_eq!(
+        Request::decode(r#"{"op":"keys","keys":["i","c-w","esc"]}"#),
+        Ok(Request::Keys(vec![
+            Key::Char('i'),
+            Key::Ctrl('w'),
+            Key::Esc
+        ]))
+    );
+    assert_eq!(
+        Request::decode(r#"{"op":"keys","keys":["nope"]}"#),
+        Err("no key nope".to_owned())
+    );
+    assert_eq!(
+        Request::decode(r#"{"op":"state"}"#),
+        Ok(Request::State { text: false })
+    );
+    assert_eq!(answer(Err("no op".into())), r#"{"error":"no op"}"#);
+}
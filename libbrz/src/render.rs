@@ -4,9 +4,11 @@
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Style {
-    pub fg: Option<u32>,
-    pub bg: Option<u32>,
-    pub style: Option<u32>,
+    /// `None` leaves it to the style painted over, and to the terminal in
+    /// the end, like the others
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attrs: Option<Attrs>,
 }
 
 impl Style {
@@ -18,11 +20,70 @@ impl Style {
         if let Some(o_bg) = other.bg {
             self.bg = Some(o_bg)
         }
-        if let Some(o_style) = other.style {
-            self.style = Some(o_style)
+        if let Some(o_attrs) = other.attrs {
+            self.attrs = Some(o_attrs)
         }
         self
     }
+
+    /// The same, underlined
+    pub fn underlined(mut self) -> Self {
+        self.attrs = Some(self.attrs.unwrap_or_default() | Attrs::UNDERLINE);
+        self
+    }
+}
+
+/// A color of the terminal
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// The terminal's own foreground or background
+    Default,
+    /// One of the 256 of the palette
+    Ansi(u8),
+    /// 24-bit, for terminals with true color
+    Rgb(u8, u8, u8),
+}
+
+/// Set of attributes of a `Style`, like `Attrs::BOLD | Attrs::ITALIC`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Attrs(u8);
+
+impl Attrs {
+    pub const BOLD: Attrs = Attrs(1);
+    pub const ITALIC: Attrs = Attrs(2);
+    pub const UNDERLINE: Attrs = Attrs(4);
+    /// Foreground and background swapped
+    pub const REVERSE: Attrs = Attrs(8);
+
+    pub fn empty() -> Self {
+        Attrs(0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Attrs) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The attributes of `self` not in `other`
+    pub fn without(self, other: Attrs) -> Attrs {
+        Attrs(self.0 & !other.0)
+    }
+}
+
+impl std::ops::BitOr for Attrs {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Attrs(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Attrs {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
@@ -33,9 +94,95 @@ pub struct ColorMap {
     pub direction_marker: Style,
     pub selection: Style,
     pub special: Style,
+    /// Lines of merge conflict markers
+    pub conflict_marker: Style,
+    pub conflict_ours: Style,
+    pub conflict_base: Style,
+    pub conflict_theirs: Style,
+    pub syntax_keyword: Style,
+    pub syntax_string: Style,
+    pub syntax_comment: Style,
+    pub syntax_number: Style,
+    pub diagnostic_error: Style,
+    pub diagnostic_warning: Style,
+    pub diagnostic_info: Style,
+    pub diagnostic_hint: Style,
+    pub code_lens: Style,
+    /// Summary of the lines of a closed fold, after its first one
+    pub fold: Style,
+    /// Labels of the places to jump to, over them
+    pub jump_label: Style,
+    /// Occurrences of the symbol under the cursor
+    pub reference_text: Style,
+    pub reference_read: Style,
+    pub reference_write: Style,
+    /// Painted over `default` on the status line
+    pub status: Style,
+    /// The name of the mode, on the status line
+    pub mode: Style,
+    /// Matches of the last search
+    pub search_match: Style,
+    /// The bracket at a cursor, and the one it matches
+    pub bracket_match: Style,
+    /// Chars of the entries of a picker found by the pattern typed
+    pub picker_match: Style,
+    /// Signs in the gutter of the lines changed since `HEAD`
+    pub git_added: Style,
+    pub git_modified: Style,
+    pub git_removed: Style,
+}
+
+impl ColorMap {
+    pub fn line_change(&self, change: crate::git::LineChange) -> Style {
+        use crate::git::LineChange;
+        match change {
+            LineChange::Added => self.git_added,
+            LineChange::Modified => self.git_modified,
+            LineChange::Removed | LineChange::RemovedAbove => self.git_removed,
+        }
+    }
+
+    pub fn conflict_line(&self, kind: crate::conflict::LineKind) -> Style {
+        use crate::conflict::LineKind;
+        match kind {
+            LineKind::Marker => self.conflict_marker,
+            LineKind::Ours => self.conflict_ours,
+            LineKind::Base => self.conflict_base,
+            LineKind::Theirs => self.conflict_theirs,
+        }
+    }
+
+    pub fn syntax(&self, kind: crate::syntax::HighlightKind) -> Style {
+        use crate::syntax::HighlightKind;
+        match kind {
+            HighlightKind::Keyword => self.syntax_keyword,
+            HighlightKind::String => self.syntax_string,
+            HighlightKind::Comment => self.syntax_comment,
+            HighlightKind::Number => self.syntax_number,
+        }
+    }
+
+    pub fn reference(&self, kind: crate::lsp::DocumentHighlightKind) -> Style {
+        use crate::lsp::DocumentHighlightKind;
+        match kind {
+            DocumentHighlightKind::Text => self.reference_text,
+            DocumentHighlightKind::Read => self.reference_read,
+            DocumentHighlightKind::Write => self.reference_write,
+        }
+    }
+
+    pub fn diagnostic(&self, severity: crate::document::Severity) -> Style {
+        use crate::document::Severity;
+        match severity {
+            Severity::Error => self.diagnostic_error,
+            Severity::Warning => self.diagnostic_warning,
+            Severity::Info => self.diagnostic_info,
+            Severity::Hint => self.diagnostic_hint,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Coord {
     pub x: usize,
     pub y: usize,
@@ -87,6 +234,96 @@ impl Coord {
     }
 }
 
+/// Combining marks a cell keeps with its char, at most; the rest are
+/// dropped
+const MAX_MARKS: usize = 2;
+
+/// What's drawn in a cell of the screen
+///
+/// A wide char takes the cell to its right too, which holds a
+/// continuation and is left to the char when drawing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    /// Chars combining with `ch`, like accents
+    pub marks: [Option<char>; MAX_MARKS],
+    /// Takes two cells
+    pub wide: bool,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell::new(' ', Style::default())
+    }
+}
+
+impl Cell {
+    pub fn new(ch: char, style: Style) -> Self {
+        Cell {
+            ch,
+            marks: [None; MAX_MARKS],
+            wide: crate::position::convert::char_width(ch) == 2,
+            style,
+        }
+    }
+
+    /// The right half of a wide char
+    pub fn continuation(style: Style) -> Self {
+        Cell::new('\0', style)
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.ch == '\0'
+    }
+
+    /// The cell of a grapheme cluster, and how many cells it takes, if
+    /// any: a cluster with no char of its own takes none
+    pub fn grapheme(grapheme: &str, style: Style) -> (Self, usize) {
+        let width = crate::position::convert::grapheme_cells_at(grapheme, 0, 1);
+        let mut chars = grapheme.chars();
+        let mut cell = Cell::new(chars.next().unwrap_or(' '), style);
+        for (mark, ch) in cell.marks.iter_mut().zip(chars) {
+            *mark = Some(ch);
+        }
+        cell.wide = 1 < width;
+        (cell, width.min(2))
+    }
+
+    /// The chars to write for the cell, none for a continuation
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        let ch = Some(self.ch).filter(|_| !self.is_continuation());
+        ch.into_iter().chain(self.marks.iter().flatten().copied())
+    }
+}
+
+/// The cells of `text`, with a continuation after each wide one
+pub fn cells(text: &str, style: Style) -> impl Iterator<Item = Cell> + '_ {
+    use unicode_segmentation::UnicodeSegmentation;
+    text.graphemes(true).flat_map(move |grapheme| {
+        let (cell, width) = Cell::grapheme(grapheme, style);
+        let continuation = Some(Cell::continuation(style)).filter(|_| cell.wide);
+        Some(cell)
+            .filter(|_| width != 0)
+            .into_iter()
+            .chain(continuation)
+    })
+}
+
+/// A window drawn, for the frontends to tell apart what's on the screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub view_i: usize,
+    pub rect: Rect,
+    /// Of the file shown, if any
+    pub path: Option<std::path::PathBuf>,
+    /// As the tabline shows it
+    pub label: String,
+    /// Of the current view
+    pub focused: bool,
+    pub modified: bool,
+}
+
 pub trait Renderer {
     fn color_map(&self) -> &ColorMap;
     fn dimensions(&self) -> Coord;
@@ -98,6 +335,11 @@ pub trait Renderer {
         }
     }
     fn put(&mut self, coord: Coord, ch: char, style: Style);
+    /// Put a `Cell`, marks and all, where the renderer can draw them
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        let ch = if cell.is_continuation() { ' ' } else { cell.ch };
+        self.put(coord, ch, cell.style);
+    }
     fn fill(&mut self, rect: Rect, ch: char, style: Style) {
         for y in rect.offset.y..rect.offset.y + rect.dimensions.y {
             for x in rect.offset.x..rect.offset.x + rect.dimensions.x {
@@ -106,23 +348,42 @@ pub trait Renderer {
         }
     }
 
+    /// Print `text` from `coord` on, as much as fits, a cell per char
+    /// but two for wide ones
     fn print(&mut self, coord: Coord, text: &str, style: Style) {
         let dims = self.dimensions();
-        for (i, ch) in text.chars().enumerate() {
+        for (i, cell) in cells(text, style).enumerate() {
             let coord = coord.add_x(i);
-            if !coord.is_inside_dimensions(dims) {
+            let end = coord.add_x(cell.wide as usize);
+            if !end.is_inside_dimensions(dims) {
                 break;
             }
-            self.put(coord, ch, style);
+            self.put_cell(coord, cell);
         }
     }
     fn print_centered(&mut self, coord: Coord, text: &str, style: Style) {
-        let len = text.len();
-        let coord = coord.sub_x(len / 2);
+        let width = crate::position::convert::str_width(text);
+        let coord = coord.sub_x(width / 2);
         self.print(coord, text, style);
     }
 
     fn set_cursor(&mut self, coord: Option<Coord>);
+
+    /// Position of the top left corner on the screen
+    fn origin(&self) -> Coord {
+        Coord { x: 0, y: 0 }
+    }
+
+    /// Leave the `rect` as it was drawn in the previous frame
+    ///
+    /// Returns `false` if that's not possible, and the `rect` has to be
+    /// drawn.
+    fn keep(&mut self, _rect: Rect) -> bool {
+        false
+    }
+
+    /// Tell a window was drawn in `window.rect`
+    fn window(&mut self, _window: Window) {}
 }
 
 impl<T> Renderer for &mut T
@@ -138,18 +399,86 @@ where
     fn put(&mut self, coord: Coord, ch: char, style: Style) {
         (**self).put(coord, ch, style)
     }
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        (**self).put_cell(coord, cell)
+    }
     fn set_cursor(&mut self, coord: Option<Coord>) {
         (**self).set_cursor(coord)
     }
+    fn origin(&self) -> Coord {
+        (**self).origin()
+    }
+    fn keep(&mut self, rect: Rect) -> bool {
+        (**self).keep(rect)
+    }
+    fn window(&mut self, window: Window) {
+        (**self).window(window)
+    }
+}
+
+/// Print multi-line `text` from the top left, as much as fits
+///
+/// Returns the number of lines printed.
+pub fn print_text(render: &mut dyn Renderer, text: &str, style: Style) -> usize {
+    let height = render.dimensions().y;
+    let mut y = 0;
+    for line in text.lines().take(height) {
+        let line: String = line
+            .chars()
+            .map(|ch| if ch == '\t' { ' ' } else { ch })
+            .collect();
+        render.print(Coord { x: 0, y }, &line, style);
+        y += 1;
+    }
+    y
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Rect {
     pub offset: Coord,
     pub dimensions: Coord,
 }
 
 impl Rect {
+    pub fn contains(self, coord: Coord) -> bool {
+        let end = self.offset + self.dimensions;
+        (self.offset.x..end.x).contains(&coord.x) && (self.offset.y..end.y).contains(&coord.y)
+    }
+
+    pub fn overlaps(self, other: Rect) -> bool {
+        let end = |rect: Rect| rect.offset + rect.dimensions;
+        self.offset.x < end(other).x
+            && other.offset.x < end(self).x
+            && self.offset.y < end(other).y
+            && other.offset.y < end(self).y
+    }
+
+    /// A rect of at most `size` in this one, starting at the column of
+    /// `anchor` and under its row, or over it if there's more room there;
+    /// moved left if it doesn't fit
+    pub fn popup(self, anchor: Coord, size: Coord) -> Rect {
+        let end = self.offset + self.dimensions;
+        let below = end.y.saturating_sub(anchor.y + 1);
+        let above = anchor.y.saturating_sub(self.offset.y);
+        let (y, height) = if size.y <= below || above <= below {
+            (anchor.y + 1, size.y.min(below))
+        } else {
+            let height = size.y.min(above);
+            (anchor.y - height, height)
+        };
+        let width = size.x.min(self.dimensions.x);
+        Rect {
+            offset: Coord {
+                x: anchor.x.min(end.x - width).max(self.offset.x),
+                y,
+            },
+            dimensions: Coord {
+                x: width,
+                y: height,
+            },
+        }
+    }
+
     pub fn split_verticaly(self) -> (Rect, Rect) {
         self.split_verticaly_at((self.dimensions.x / 2) as isize)
     }
@@ -255,7 +584,25 @@ where
             self.backend.put(coord + self.rect.offset, ch, style)
         }
     }
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        if coord.is_inside(self.rect) {
+            self.backend.put_cell(coord + self.rect.offset, cell)
+        }
+    }
     fn set_cursor(&mut self, coord: Option<Coord>) {
         self.backend.set_cursor(coord.map(|c| c + self.rect.offset))
     }
+    fn origin(&self) -> Coord {
+        self.backend.origin() + self.rect.offset
+    }
+    fn keep(&mut self, rect: Rect) -> bool {
+        self.backend.keep(Rect {
+            offset: rect.offset + self.rect.offset,
+            dimensions: rect.dimensions,
+        })
+    }
+    fn window(&mut self, mut window: Window) {
+        window.rect.offset = window.rect.offset + self.rect.offset;
+        self.backend.window(window)
+    }
 }
@@ -0,0 +1,66 @@
+//! Keys repeated faster than they are drawn
+//!
+//! A key held down comes in quicker than a slow terminal shows each move.
+//! The frontend takes the repeats of a key waiting at once, as far as
+//! `State::coalesces` lets it, and passes them to
+//! `State::handle_repeated_key`, drawing only once after: a movement of
+//! normal mode runs with a count, rather than once per repeat.
+
+/// Repeats taken at once at most, for the cursor to stop soon after the
+/// key is released
+pub const MAX_REPEATS: usize = 64;
+
+/// Actions of normal mode that only move or scroll, by their count
+pub const MOVEMENTS: &[&str] = &[
+    "MoveLeft",
+    "ExtendLeft",
+    "MoveRight",
+    "ExtendRight",
+    "MoveDown",
+    "ExtendDown",
+    "MoveUp",
+    "ExtendUp",
+    "MoveDownPage",
+    "ExtendDownPage",
+    "MoveUpPage",
+    "ExtendUpPage",
+    "ScrollHalfPageDown",
+    "ScrollHalfPageUp",
+    "ScrollPageDown",
+    "ScrollPageUp",
+    "ScrollLineDown",
+    "ScrollLineUp",
+];
+
+/// Which repeats of a key are taken at once
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Coalescing {
+    /// None: each key is drawn
+    Off,
+    /// Those of movements
+    #[default]
+    Movements,
+    /// Those of any key, the others handled one after the other
+    All,
+}
+
+impl Coalescing {
+    pub const NAMES: &'static [&'static str] = &["off", "movements", "all"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Coalescing::Off => "off",
+            Coalescing::Movements => "movements",
+            Coalescing::All => "all",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "off" => Coalescing::Off,
+            "movements" => Coalescing::Movements,
+            "all" => Coalescing::All,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,65 @@
+//! Chars that shouldn't reach the terminal or hide what the text does
+//!
+//! Control chars can be escape sequences to the terminal, and bidi
+//! overrides make code read differently from how it's compiled ("trojan
+//! source"). Files keep them, but they are drawn visibly and reported
+//! when the file is opened; text typed or inserted gets them stripped.
+use ropey::Rope;
+
+pub fn is_suspicious(ch: char) -> bool {
+    match ch {
+        '\n' | '\t' => false,
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => true,
+        '\u{200e}' | '\u{200f}' | '\u{061c}' => true,
+        ch => ch.is_control(),
+    }
+}
+
+/// How to draw a suspicious char, eg. `␛` for an escape
+pub fn visible(ch: char) -> char {
+    match ch {
+        '\0'..='\u{1f}' => char::from_u32(0x2400 + ch as u32).unwrap_or('�'),
+        '\u{7f}' => '␡',
+        _ => '�',
+    }
+}
+
+/// `text` without its suspicious chars, and how many were removed
+///
+/// The `\r` of `\r\n` line endings is kept.
+pub fn strip(text: &str) -> (String, usize) {
+    let mut removed = 0;
+    let mut chars = text.chars().peekable();
+    let mut out = String::with_capacity(text.len());
+    while let Some(ch) = chars.next() {
+        if is_suspicious(ch) && !(ch == '\r' && chars.peek() == Some(&'\n')) {
+            removed += 1;
+        } else {
+            out.push(ch);
+        }
+    }
+    (out, removed)
+}
+
+/// How many suspicious chars are in `text`, not counting `\r\n` line endings
+pub fn count(text: &Rope) -> usize {
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if is_suspicious(ch) && !(ch == '\r' && chars.peek() == Some(&'\n')) {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[test]
+fn sanitize_test() {
+    let text = "let a = 1;\r\n\u{1b}[2J\t/* \u{202e} } \u{2066}*/\r";
+    assert_eq!(strip(text), ("let a = 1;\r\n[2J\t/*  } */".to_owned(), 4));
+    assert_eq!(count(&Rope::from_str(text)), 4);
+    assert_eq!(visible('\u{1b}'), '␛');
+    assert_eq!(visible('\r'), '␍');
+    assert_eq!(visible('\u{202e}'), '�');
+    assert!(!is_suspicious('é'));
+}
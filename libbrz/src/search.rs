@@ -0,0 +1,327 @@
+//! Searching in the buffer text
+use regex::{Regex, RegexBuilder};
+use ropey::Rope;
+use std::fmt;
+
+use crate::buffer::Buffer;
+use crate::idx::Idx;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum CaseMode {
+    /// Case-insensitive, unless the pattern contains an uppercase letter
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+    pub fn next(self) -> Self {
+        match self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+
+    fn is_insensitive(self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Smart => !pattern.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    pub case: CaseMode,
+    /// Match the pattern as is, instead of as a regex
+    pub literal: bool,
+    pub whole_word: bool,
+}
+
+impl fmt::Display for SearchOptions {
+    /// Short form to show in the prompt, eg. `smart regex`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.case {
+            CaseMode::Smart => "smart",
+            CaseMode::Sensitive => "Aa",
+            CaseMode::Insensitive => "aa",
+        })?;
+        f.write_str(if self.literal { " literal" } else { " regex" })?;
+        if self.whole_word {
+            f.write_str(" word")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub pattern: String,
+    pub options: SearchOptions,
+    regex: Regex,
+}
+
+impl Search {
+    pub fn new(pattern: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        let mut re = if options.literal {
+            regex::escape(pattern)
+        } else {
+            pattern.to_owned()
+        };
+        if options.whole_word {
+            re = format!(r"\b(?:{})\b", re);
+        }
+        let regex = RegexBuilder::new(&re)
+            .case_insensitive(options.case.is_insensitive(pattern))
+            .multi_line(true)
+            .build()?;
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            options,
+            regex,
+        })
+    }
+
+    /// Same pattern and options
+    pub fn is_same(&self, other: &Search) -> bool {
+        self.pattern == other.pattern && self.options == other.options
+    }
+
+    /// All the matches, as `(start, end)` char `Idx` pairs
+    pub fn find_all(&self, text: &Rope) -> Vec<(Idx, Idx)> {
+        self.find_in(text, 0..text.len_chars())
+    }
+
+    /// Matches inside `range` of `text`
+    ///
+    /// `range` should span whole lines, so that line anchors and word
+    /// boundaries work like when searching the whole text.
+    fn find_in(&self, text: &Rope, range: std::ops::Range<usize>) -> Vec<(Idx, Idx)> {
+        let slice = text.slice(range.clone());
+        let s = slice.to_string();
+        self.regex
+            .find_iter(&s)
+            .filter(|m| !m.as_str().is_empty())
+            .map(|m| {
+                (
+                    Idx(range.start + slice.byte_to_char(m.start())),
+                    Idx(range.start + slice.byte_to_char(m.end())),
+                )
+            })
+            .collect()
+    }
+
+    /// First match starting after `idx`, wrapping around the end
+    pub fn find_next(&self, text: &Rope, idx: Idx) -> Option<(Idx, Idx)> {
+        let matches = self.find_all(text);
+        matches
+            .iter()
+            .find(|(start, _)| idx < *start)
+            .or_else(|| matches.first())
+            .copied()
+    }
+
+    /// Last match starting before `idx`, wrapping around the beginning
+    pub fn find_prev(&self, text: &Rope, idx: Idx) -> Option<(Idx, Idx)> {
+        let matches = self.find_all(text);
+        matches
+            .iter()
+            .rev()
+            .find(|(start, _)| *start < idx)
+            .or_else(|| matches.last())
+            .copied()
+    }
+}
+
+/// All the matches of a `Search` in a buffer
+///
+/// Kept up to date with the edits by rescanning only the lines they
+/// touched.
+#[derive(Clone, Debug)]
+pub struct MatchSet {
+    pub search: Search,
+    matches: Vec<(Idx, Idx)>,
+    version: u64,
+    len_chars: usize,
+}
+
+impl MatchSet {
+    pub fn new(search: Search, buffer: &Buffer) -> Self {
+        Self {
+            matches: search.find_all(&buffer.text),
+            search,
+            version: buffer.version(),
+            len_chars: buffer.text.len_chars(),
+        }
+    }
+
+    pub fn matches(&self) -> &[(Idx, Idx)] {
+        &self.matches
+    }
+
+    /// Matches overlapping the `range` of chars
+    pub fn in_range(&self, range: std::ops::Range<usize>) -> &[(Idx, Idx)] {
+        let start = self
+            .matches
+            .partition_point(|(_, end)| end.0 <= range.start);
+        let end = start + self.matches[start..].partition_point(|(s, _)| s.0 < range.end);
+        &self.matches[start..end]
+    }
+
+    /// Index of the match spanning exactly `start..end`
+    pub fn index_of(&self, start: Idx, end: Idx) -> Option<usize> {
+        self.matches.binary_search(&(start, end)).ok()
+    }
+
+    pub fn refresh(&mut self, buffer: &Buffer) {
+        if self.version == buffer.version() {
+            return;
+        }
+        if !self.refresh_incrementally(buffer) {
+            *self = Self::new(self.search.clone(), buffer);
+        }
+    }
+
+    fn refresh_incrementally(&mut self, buffer: &Buffer) -> bool {
+        let changes = match buffer.changes_since(self.version) {
+            Some(changes) => changes,
+            None => return false,
+        };
+
+        // region of the new text that has to be rescanned
+        let mut dirty: Option<(usize, usize)> = None;
+        let mut len_chars = self.len_chars;
+        for change in changes {
+            let start = change.start.0;
+            let removed_end = start + change.removed;
+            let shift = |idx: usize| idx + change.inserted - change.removed;
+
+            self.matches
+                .retain(|&(s, e)| e.0 <= start || removed_end <= s.0);
+            for (s, e) in &mut self.matches {
+                if removed_end <= s.0 {
+                    *s = Idx(shift(s.0));
+                    *e = Idx(shift(e.0));
+                }
+            }
+            dirty = Some(match dirty {
+                None => (start, start + change.inserted),
+                Some((lo, hi)) => (
+                    lo.min(start),
+                    if removed_end <= hi {
+                        shift(hi)
+                    } else {
+                        start + change.inserted
+                    },
+                ),
+            });
+            len_chars = (len_chars + change.inserted)
+                .checked_sub(change.removed)
+                .unwrap_or(usize::MAX);
+        }
+
+        let text = &buffer.text;
+        if len_chars != text.len_chars() {
+            // text was changed without going through `Buffer` methods
+            return false;
+        }
+        if let Some((lo, hi)) = dirty {
+            let lo = text.line_to_char(text.char_to_line(lo));
+            let hi_line = text.char_to_line(hi) + 1;
+            let hi = if hi_line < text.len_lines() {
+                text.line_to_char(hi_line)
+            } else {
+                text.len_chars()
+            };
+            self.matches.retain(|&(s, e)| e.0 <= lo || hi <= s.0);
+            self.matches.extend(self.search.find_in(text, lo..hi));
+            self.matches.sort();
+        }
+
+        self.version = buffer.version();
+        self.len_chars = len_chars;
+        true
+    }
+}
+
+#[test]
+fn search_options_test() {
+    let text = Rope::from_str("Foo foo food f.o");
+    let starts = |pattern, options| {
+        Search::new(pattern, options)
+            .unwrap()
+            .find_all(&text)
+            .into_iter()
+            .map(|(start, _)| start.0)
+            .collect::<Vec<_>>()
+    };
+    let default = SearchOptions::default();
+
+    assert_eq!(starts("foo", default), vec![0, 4, 8]);
+    assert_eq!(starts("Foo", default), vec![0]);
+    assert_eq!(
+        starts(
+            "foo",
+            SearchOptions {
+                case: CaseMode::Sensitive,
+                ..default
+            }
+        ),
+        vec![4, 8]
+    );
+    assert_eq!(
+        starts(
+            "foo",
+            SearchOptions {
+                whole_word: true,
+                ..default
+            }
+        ),
+        vec![0, 4]
+    );
+    assert_eq!(starts("f.o", default), vec![0, 4, 8, 13]);
+    assert_eq!(
+        starts(
+            "f.o",
+            SearchOptions {
+                literal: true,
+                ..default
+            }
+        ),
+        vec![13]
+    );
+}
+
+#[test]
+fn find_next_prev_test() {
+    let text = Rope::from_str("ab ab ab");
+    let search = Search::new("ab", SearchOptions::default()).unwrap();
+    assert_eq!(search.find_next(&text, Idx(0)), Some((Idx(3), Idx(5))));
+    assert_eq!(search.find_next(&text, Idx(6)), Some((Idx(0), Idx(2))));
+    assert_eq!(search.find_prev(&text, Idx(3)), Some((Idx(0), Idx(2))));
+    assert_eq!(search.find_prev(&text, Idx(0)), Some((Idx(6), Idx(8))));
+}
+
+#[test]
+fn match_set_refresh_test() {
+    let search = Search::new("foo", SearchOptions::default()).unwrap();
+    let mut buffer = Buffer::from_text(Rope::from_str("foo bar\nbar foo\nfoo\n"));
+    let mut set = MatchSet::new(search.clone(), &buffer);
+    assert_eq!(set.matches().len(), 3);
+
+    buffer.insert(Idx(4), "foo ");
+    buffer.remove(8..12);
+    buffer.insert(Idx(0), "xx\n");
+    buffer.remove(14..19);
+    set.refresh(&buffer);
+
+    assert_eq!(set.matches(), &search.find_all(&buffer.text)[..]);
+    assert_eq!(set.index_of(Idx(7), Idx(10)), Some(1));
+
+    buffer.replace_text(Rope::from_str("no match"));
+    set.refresh(&buffer);
+    assert!(set.matches().is_empty());
+}
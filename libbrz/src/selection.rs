@@ -12,11 +12,7 @@ pub struct SelectionUnaligned {
 
 impl SelectionUnaligned {
     pub fn sorted(self) -> (Position, Position) {
-        if self.anchor.line < self.cursor.line {
-            (self.anchor, self.cursor)
-        } else if self.cursor.line < self.anchor.line {
-            (self.cursor, self.anchor)
-        } else if self.cursor.column < self.anchor.column {
+        if (self.cursor.line, self.cursor.column) < (self.anchor.line, self.anchor.column) {
             (self.cursor, self.anchor)
         } else {
             (self.anchor, self.cursor)
@@ -142,16 +138,42 @@ impl Selection {
         }
     }
 
+    /// The whole lines from that of the anchor to that of the cursor, the
+    /// cursor staying on its side; the empty line after a last line end
+    /// counts as the line before it
+    pub fn line_wise(self, text: &Rope) -> Self {
+        let line = |idx: Idx| {
+            let line = text.char_to_line(idx.0);
+            if 0 < line && text.line_to_char(line) == text.len_chars() {
+                line - 1
+            } else {
+                line
+            }
+        };
+        let start = |line| Idx(text.line_to_char(line));
+        let end = |line: usize| Idx(text.line_to_char((line + 1).min(text.len_lines())));
+        let (anchor, cursor) = (line(self.anchor), line(self.cursor));
+        if cursor < anchor {
+            Self {
+                anchor: end(anchor),
+                cursor: start(cursor),
+            }
+        } else {
+            Self {
+                anchor: start(anchor),
+                cursor: end(cursor),
+            }
+        }
+    }
+
     pub fn is_forward(self) -> bool {
         let anchor = self.anchor;
         let cursor = self.cursor;
 
         if anchor < cursor {
             true
-        } else if cursor < anchor {
-            false
         } else {
-            true
+            cursor >= anchor
         }
     }
 
@@ -198,4 +220,53 @@ impl Selection {
     pub fn is_empty(self) -> bool {
         self.cursor == self.anchor
     }
+
+    /// The `matches`, sorted, that lie inside the selection, as
+    /// selections of their own
+    pub fn select_matches(self, matches: &[(Idx, Idx)]) -> Vec<Selection> {
+        let (from, to) = self.sorted_pair();
+        matches
+            .iter()
+            .filter(|&&(start, end)| from <= start && end <= to)
+            .map(|&(start, end)| Selection::new_from_normalized(start, end))
+            .collect()
+    }
+
+    /// What's left of the selection between the `matches`, sorted, that
+    /// lie inside it, without the empty parts
+    pub fn split_on(self, matches: &[(Idx, Idx)]) -> Vec<Selection> {
+        let (from, to) = self.sorted_pair();
+        let mut parts = vec![];
+        let mut part_start = from;
+        for &(start, end) in matches {
+            if start < from || to < end {
+                continue;
+            }
+            if part_start < start {
+                parts.push(Selection::new_from_normalized(part_start, start));
+            }
+            part_start = end;
+        }
+        if part_start < to {
+            parts.push(Selection::new_from_normalized(part_start, to));
+        }
+        parts
+    }
+}
+
+#[test]
+fn line_wise_test() {
+    let text = Rope::from_str("one\ntwo\nthree\n");
+    let line_wise = |anchor, cursor| {
+        let sel = Selection {
+            anchor: Idx(anchor),
+            cursor: Idx(cursor),
+        }
+        .line_wise(&text);
+        (sel.anchor.0, sel.cursor.0)
+    };
+    assert_eq!(line_wise(1, 1), (0, 4));
+    assert_eq!(line_wise(1, 9), (0, 14));
+    assert_eq!(line_wise(9, 1), (14, 0));
+    assert_eq!(line_wise(14, 14), (8, 14));
 }
@@ -0,0 +1,83 @@
+//! The selections a view had, to go back and forth through
+//!
+//! Apart from the undo of the text: `c-h` goes back to the selections
+//! before the last key that changed them, and `c-k` forward again. Only
+//! keys that don't edit the text are remembered, as an edit moves the
+//! selections along anyway; those remembered move along with the edits
+//! made since.
+use crate::buffer::{Buffer, SelectionSet};
+
+/// Selections a view remembers
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Default)]
+pub struct SelectionHistory {
+    /// Of the buffer as of a version, oldest first
+    past: Vec<(SelectionSet, u64)>,
+    /// Gone back from, the next one last
+    future: Vec<(SelectionSet, u64)>,
+}
+
+impl SelectionHistory {
+    /// Note the selections a key changed; those gone back from are
+    /// forgotten
+    pub fn push(&mut self, selection: SelectionSet, buffer: &Buffer) {
+        self.future.clear();
+        self.past.push((selection, buffer.version()));
+        if CAPACITY < self.past.len() {
+            self.past.remove(0);
+        }
+    }
+
+    /// The selections before `cur`, to come forward to again
+    pub fn back(&mut self, cur: &SelectionSet, buffer: &Buffer) -> Option<SelectionSet> {
+        let (selection, version) = self.past.pop()?;
+        self.future.push((cur.clone(), buffer.version()));
+        Some(followed(selection, version, buffer))
+    }
+
+    /// The selections gone back from last
+    pub fn forward(&mut self, cur: &SelectionSet, buffer: &Buffer) -> Option<SelectionSet> {
+        let (selection, version) = self.future.pop()?;
+        self.past.push((cur.clone(), buffer.version()));
+        Some(followed(selection, version, buffer))
+    }
+}
+
+/// `selection` as of `version`, moved along the changes made since
+fn followed(mut selection: SelectionSet, version: u64, buffer: &Buffer) -> SelectionSet {
+    if let Some(changes) = buffer.changes_since(version) {
+        for change in changes {
+            selection.follow_change(change);
+        }
+    }
+    selection.trim_to_text(&buffer.text);
+    selection.clear_cursor_column();
+    selection
+}
+
+#[test]
+fn selection_history_test() {
+    use crate::{Idx, Selection};
+    use ropey::Rope;
+    let mut buffer = Buffer::from_text(Rope::from_str("one two three"));
+    let at = |idx: usize| SelectionSet {
+        selections: vec![Selection::new_from_normalized(Idx(idx), Idx(idx + 1))],
+        ..SelectionSet::default()
+    };
+    let mut history = SelectionHistory::default();
+    assert_eq!(history.back(&at(0), &buffer), None);
+    history.push(at(0), &buffer);
+    history.push(at(4), &buffer);
+    assert_eq!(history.back(&at(8), &buffer), Some(at(4)));
+    assert_eq!(history.forward(&at(4), &buffer), Some(at(8)));
+    assert_eq!(history.forward(&at(8), &buffer), None);
+
+    // moved along the edits, and forgetting what was gone back from
+    buffer.insert(Idx(3), "!!");
+    assert_eq!(history.back(&at(10), &buffer), Some(at(6)));
+    history.push(at(6), &buffer);
+    assert_eq!(history.forward(&at(0), &buffer), None);
+    assert_eq!(history.back(&at(0), &buffer), Some(at(6)));
+    assert_eq!(history.back(&at(6), &buffer), Some(at(0)));
+}
@@ -0,0 +1,307 @@
+//! Sessions, to pick up later where the editing was left
+//!
+//! `:mksession` writes to a file, `Session.brz` of the project unless
+//! another is given, the files open, the tabs with the split tree of
+//! their windows, the selections and the scroll of each window, and the
+//! registers of text. `:loadsession`, or starting with
+//! `brz --session <file>`, opens the files again and puts the windows
+//! back as they were. Buffers not backed by a file are left out, with
+//! their windows, as are the files that can't be read anymore; in a
+//! file that got shorter, the selections past its end end with it.
+//!
+//! The file is text, a line per file, window and tab starting with what
+//! it is. The layout of a tab is its split tree from the top, `w<view>`
+//! for a window, `h<percent>` or `v<percent>` for a split followed by
+//! its two parts. A register is its name, its kind and the count of its
+//! parts, each part being its length in chars on a line, then its text.
+use crate::layout::{Direction, Layout};
+use crate::register::{Register, RegisterKind};
+use crate::selection::Selection;
+use crate::tab::Tab;
+use crate::Idx;
+use ropey::Rope;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// Where `:mksession` and `:loadsession` go without a path, in the
+/// project
+pub const DEFAULT_PATH: &str = "Session.brz";
+
+const HEADER: &str = "brz-session 1";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    /// The directory the paths of `files` are relative to, if they are
+    pub project: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub views: Vec<View>,
+    /// The layouts and the view focused of each, by index in `views`
+    pub tabs: Vec<Tab>,
+    /// Index of the current tab
+    pub cur_tab: usize,
+    /// Registers of text by name, `"` for the unnamed one
+    pub registers: Vec<(char, Register)>,
+}
+
+/// A window, of the file at its index in `files`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct View {
+    pub file: usize,
+    pub line_offset: usize,
+    pub primary: usize,
+    pub selections: Vec<Selection>,
+}
+
+fn kind_name(kind: RegisterKind) -> &'static str {
+    match kind {
+        RegisterKind::Chars => "chars",
+        RegisterKind::Lines => "lines",
+        RegisterKind::Block => "block",
+    }
+}
+
+fn write_layout(out: &mut String, layout: &Layout) {
+    match layout {
+        Layout::Window(view_i) => {
+            let _ = write!(out, " w{}", view_i);
+        }
+        Layout::Split {
+            direction,
+            percent,
+            first,
+            second,
+        } => {
+            let direction = match direction {
+                Direction::Horizontal => 'h',
+                Direction::Vertical => 'v',
+            };
+            let _ = write!(out, " {}{}", direction, percent);
+            write_layout(out, first);
+            write_layout(out, second);
+        }
+    }
+}
+
+fn parse_layout<'t>(tokens: &mut impl Iterator<Item = &'t str>) -> Option<Layout> {
+    let token = tokens.next()?;
+    let number = token.get(1..)?.parse().ok()?;
+    let direction = match token.chars().next()? {
+        'w' => return Some(Layout::Window(number)),
+        'h' => Direction::Horizontal,
+        'v' => Direction::Vertical,
+        _ => return None,
+    };
+    Some(Layout::Split {
+        direction,
+        percent: number,
+        first: Box::new(parse_layout(tokens)?),
+        second: Box::new(parse_layout(tokens)?),
+    })
+}
+
+impl Session {
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\nproject {}\n", HEADER, self.project.display());
+        for file in &self.files {
+            let _ = writeln!(out, "file {}", file.display());
+        }
+        for view in &self.views {
+            let _ = write!(
+                out,
+                "view {} {} {}",
+                view.file, view.line_offset, view.primary
+            );
+            for selection in &view.selections {
+                let _ = write!(out, " {} {}", selection.anchor.0, selection.cursor.0);
+            }
+            out.push('\n');
+        }
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let layout = match &tab.layout {
+                Some(layout) => layout,
+                None => continue,
+            };
+            let focused = tab.view_i.unwrap_or_else(|| layout.windows()[0]);
+            let _ = write!(out, "tab {}", focused);
+            write_layout(&mut out, layout);
+            out.push('\n');
+            if let Some(name) = &tab.name {
+                let _ = writeln!(out, "name {}", name);
+            }
+            if i == self.cur_tab {
+                out.push_str("cur\n");
+            }
+        }
+        for (name, register) in &self.registers {
+            let kind = kind_name(register.kind);
+            let _ = writeln!(out, "register {} {} {}", name, kind, register.parts.len());
+            for part in &register.parts {
+                let _ = writeln!(out, "{}", part.len_chars());
+                out.extend(part.chars());
+            }
+        }
+        out
+    }
+
+    /// What `to_text` wrote, if it's a session whose windows are all of
+    /// its views, and its views of its files
+    pub fn from_text(s: &str) -> Option<Self> {
+        let mut chars = s.chars();
+        let line = |chars: &mut std::str::Chars| -> Option<String> {
+            let line: String = chars.take_while(|&ch| ch != '\n').collect();
+            Some(line).filter(|line| !line.is_empty())
+        };
+        if line(&mut chars)? != HEADER {
+            return None;
+        }
+        let mut session = Session::default();
+        while let Some(entry) = line(&mut chars) {
+            let (what, rest) = entry.split_once(' ').unwrap_or((&entry, ""));
+            let numbers = || -> Option<Vec<usize>> {
+                rest.split(' ').map(|field| field.parse().ok()).collect()
+            };
+            match what {
+                "project" => session.project = PathBuf::from(rest),
+                "file" => session.files.push(PathBuf::from(rest)),
+                "view" => {
+                    let numbers = numbers()?;
+                    let (file, line_offset, primary) = match numbers[..] {
+                        [file, line_offset, primary, ..] => (file, line_offset, primary),
+                        _ => return None,
+                    };
+                    let selections: Vec<Selection> = numbers[3..]
+                        .chunks(2)
+                        .map(|pair| match *pair {
+                            [anchor, cursor] => {
+                                Some(Selection::new_from_normalized(Idx(anchor), Idx(cursor)))
+                            }
+                            _ => None,
+                        })
+                        .collect::<Option<_>>()?;
+                    if session.files.len() <= file || selections.len() <= primary {
+                        return None;
+                    }
+                    session.views.push(View {
+                        file,
+                        line_offset,
+                        primary,
+                        selections,
+                    });
+                }
+                "tab" => {
+                    let mut tokens = rest.split(' ');
+                    let focused = tokens.next()?.parse().ok()?;
+                    let layout = parse_layout(&mut tokens)?;
+                    let windows = layout.windows();
+                    if !windows.contains(&focused)
+                        || windows.iter().any(|&i| session.views.len() <= i)
+                    {
+                        return None;
+                    }
+                    session.tabs.push(Tab {
+                        name: None,
+                        layout: Some(layout),
+                        view_i: Some(focused),
+                    });
+                }
+                "name" => session.tabs.last_mut()?.name = Some(rest.to_owned()),
+                "cur" => session.cur_tab = session.tabs.len().checked_sub(1)?,
+                "register" => {
+                    let fields: Vec<&str> = rest.split(' ').collect();
+                    let (name, kind, count) = match fields[..] {
+                        [name, kind, count] => (name, kind, count.parse::<usize>().ok()?),
+                        _ => return None,
+                    };
+                    let mut name_chars = name.chars();
+                    let name = match (name_chars.next(), name_chars.next()) {
+                        (Some(name), None) => name,
+                        _ => return None,
+                    };
+                    let kind = match kind {
+                        "chars" => RegisterKind::Chars,
+                        "lines" => RegisterKind::Lines,
+                        "block" => RegisterKind::Block,
+                        _ => return None,
+                    };
+                    let mut parts = vec![];
+                    for _ in 0..count {
+                        let len: usize = line(&mut chars).and_then(|len| len.parse().ok())?;
+                        let part: String = chars.by_ref().take(len).collect();
+                        if part.chars().count() != len {
+                            return None;
+                        }
+                        parts.push(Rope::from_str(&part));
+                    }
+                    session.registers.push((name, Register { kind, parts }));
+                }
+                _ => return None,
+            }
+        }
+        Some(session)
+    }
+}
+
+#[test]
+fn session_test() {
+    let session = Session {
+        project: "/p".into(),
+        files: vec!["src/a.rs".into(), "/etc/hosts".into()],
+        views: vec![
+            View {
+                file: 0,
+                line_offset: 10,
+                primary: 1,
+                selections: vec![
+                    Selection::new_from_normalized(Idx(3), Idx(5)),
+                    Selection::new_from_normalized(Idx(20), Idx(20)),
+                ],
+            },
+            View {
+                file: 1,
+                line_offset: 0,
+                primary: 0,
+                selections: vec![Selection::default()],
+            },
+        ],
+        tabs: vec![
+            Tab {
+                name: None,
+                layout: Some(Layout::Window(1)),
+                view_i: Some(1),
+            },
+            Tab {
+                name: Some("two windows".into()),
+                layout: Some(Layout::Split {
+                    direction: Direction::Vertical,
+                    percent: 30,
+                    first: Box::new(Layout::Window(0)),
+                    second: Box::new(Layout::Window(1)),
+                }),
+                view_i: Some(0),
+            },
+        ],
+        cur_tab: 1,
+        registers: vec![
+            ('"', Register::from_text("one\ntwo\n")),
+            (
+                'a',
+                Register {
+                    kind: RegisterKind::Chars,
+                    parts: vec![Rope::from_str("x\n"), Rope::from_str("é")],
+                },
+            ),
+        ],
+    };
+    let text = session.to_text();
+    assert!(text.contains("\ntab 0 v30 w0 w1\nname two windows\ncur\n"));
+    assert!(text.contains("\nview 0 10 1 3 5 20 20\n"));
+    assert_eq!(Session::from_text(&text), Some(session));
+
+    // windows of views it doesn't have
+    assert_eq!(Session::from_text("brz-session 1\ntab 0 w0\n"), None);
+    assert_eq!(
+        Session::from_text("brz-session 1\nfile a\nview 0 0 0\n"),
+        None
+    );
+    assert_eq!(Session::from_text("not a session\n"), None);
+}
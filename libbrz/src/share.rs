@@ -0,0 +1,137 @@
+//! Selections written out, to select them again later or elsewhere
+//!
+//! They're JSON, for a script to make as well as for a teammate to paste:
+//!
+//! ```json
+//! {"path":"src/a.rs","primary":0,"selections":[
+//!   {"anchor":{"line":3,"column":4},"cursor":{"line":3,"column":9}}]}
+//! ```
+//!
+//! Lines and columns count chars from 0. They're no indices of the text,
+//! for the selections to survive edits elsewhere in the file; whatever's
+//! past the end of the text now is brought back to it.
+use crate::buffer::SelectionSet;
+use crate::json::{self, Value};
+use crate::position::Position;
+use crate::selection::Selection;
+use ropey::Rope;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedSelections {
+    /// File of the selections, if it has one
+    pub path: Option<PathBuf>,
+    pub primary: usize,
+    /// Anchor, then cursor
+    pub selections: Vec<(Position, Position)>,
+}
+
+impl SharedSelections {
+    pub fn new(path: Option<PathBuf>, selection: &SelectionSet, text: &Rope) -> Self {
+        Self {
+            path,
+            primary: selection.primary,
+            selections: selection
+                .selections
+                .iter()
+                .map(|sel| (sel.anchor.to_position(text), sel.cursor.to_position(text)))
+                .collect(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value = json::parse(text.trim()).map_err(|e| format!("not selections: {}", e))?;
+        let position = |value: &Value| -> Result<Position, String> {
+            match (value.get("line").as_usize(), value.get("column").as_usize()) {
+                (Some(line), Some(column)) => Ok(Position { line, column }),
+                _ => Err("not selections: a position without line or column".into()),
+            }
+        };
+        let selections = value
+            .get("selections")
+            .items()
+            .iter()
+            .map(|sel| Ok((position(sel.get("anchor"))?, position(sel.get("cursor"))?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        if selections.is_empty() {
+            return Err("not selections: none in there".into());
+        }
+        Ok(Self {
+            path: value.get("path").as_str().map(PathBuf::from),
+            primary: value.get("primary").as_usize().unwrap_or(0),
+            selections,
+        })
+    }
+
+    /// The selections in `text`, brought back to it
+    pub fn to_selection(&self, text: &Rope) -> SelectionSet {
+        let idx = |position: Position| position.trim_line_to_buf(text).to_idx(text);
+        SelectionSet {
+            selections: self
+                .selections
+                .iter()
+                .map(|&(anchor, cursor)| Selection::new_from_normalized(idx(anchor), idx(cursor)))
+                .collect(),
+            primary: self.primary.min(self.selections.len() - 1),
+            cursor_column: vec![],
+        }
+    }
+}
+
+impl std::fmt::Display for SharedSelections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = |position: Position| {
+            Value::object([
+                ("line", position.line.into()),
+                ("column", position.column.into()),
+            ])
+        };
+        let selections: Vec<Value> = self
+            .selections
+            .iter()
+            .map(|&(anchor, cursor)| {
+                Value::object([("anchor", position(anchor)), ("cursor", position(cursor))])
+            })
+            .collect();
+        let path = self
+            .path
+            .as_ref()
+            .map_or(Value::Null, |path| path.display().to_string().into());
+        let value = Value::object([
+            ("path", path),
+            ("primary", self.primary.into()),
+            ("selections", selections.into()),
+        ]);
+        write!(f, "{}", value)
+    }
+}
+
+#[test]
+fn share_test() {
+    let text = Rope::from_str("one\ntwo three\n");
+    let selection = SelectionSet {
+        selections: vec![
+            Selection::new_from_normalized(crate::Idx(0), crate::Idx(2)),
+            Selection::new_from_normalized(crate::Idx(12), crate::Idx(8)),
+        ],
+        primary: 1,
+        cursor_column: vec![],
+    };
+    let shared = SharedSelections::new(Some("a.txt".into()), &selection, &text);
+    let json = shared.to_string();
+    assert!(
+        json.contains(r#""cursor":{"line":1,"column":4}"#),
+        "{}",
+        json
+    );
+    let parsed = SharedSelections::parse(&json).unwrap();
+    assert_eq!(parsed, shared);
+    assert_eq!(parsed.to_selection(&text), selection);
+
+    // brought back to a shorter text
+    let short = Rope::from_str("one\n");
+    let sel = parsed.to_selection(&short).selections[1];
+    assert_eq!((sel.anchor.0, sel.cursor.0), (4, 4));
+    assert!(SharedSelections::parse(r#"{"selections":[]}"#).is_err());
+    assert!(SharedSelections::parse(r#"{"selections":[{"anchor":{}}]}"#).is_err());
+}
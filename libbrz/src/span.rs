@@ -0,0 +1,188 @@
+//! Ranges of the text edited recently
+//!
+//! Taken from the `TextChange`s of a buffer and moved along the changes
+//! made after, so that what was just pasted or typed can be selected
+//! again, eg. to indent it or to surround it.
+use crate::buffer::{Buffer, TextChange};
+use std::ops::Range;
+
+/// Where `idx` goes after the `change`; `end` of a range or its start,
+/// which don't grow over text inserted right before or after them
+fn map_idx(idx: usize, change: &TextChange, end: bool) -> usize {
+    let start = change.start.0;
+    if idx < start || (end && idx == start) {
+        idx
+    } else if start + change.removed <= idx {
+        idx - change.removed + change.inserted
+    } else {
+        start
+    }
+}
+
+pub(crate) fn map_range(range: &Range<usize>, change: &TextChange) -> Range<usize> {
+    let start = map_idx(range.start, change, false);
+    start..map_idx(range.end, change, true).max(start)
+}
+
+/// Chars of a buffer, as of some version of it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    version: u64,
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl Span {
+    /// What the changes of `buffer` since `version` inserted or removed
+    /// text at, merged and in order
+    ///
+    /// `None` if nothing changed, or the changes are too old to know.
+    pub fn since(buffer: &Buffer, version: u64) -> Option<Self> {
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for change in buffer.changes_since(version)? {
+            for range in &mut ranges {
+                *range = map_range(range, change);
+            }
+            let start = change.start.0;
+            ranges.push(start..start + change.inserted);
+            ranges.sort_by_key(|range| range.start);
+            ranges.dedup_by(|next, range| {
+                let touching = next.start <= range.end;
+                if touching {
+                    range.end = range.end.max(next.end);
+                }
+                touching
+            });
+        }
+        if ranges.is_empty() {
+            return None;
+        }
+        Some(Self {
+            version: buffer.version(),
+            ranges,
+        })
+    }
+
+    /// Move along the changes made since; `false` if they are too old to
+    /// know and the span is lost
+    pub fn follow(&mut self, buffer: &Buffer) -> bool {
+        let changes = match buffer.changes_since(self.version) {
+            Some(changes) => changes,
+            None => return false,
+        };
+        for change in changes {
+            for range in &mut self.ranges {
+                *range = map_range(range, change);
+            }
+        }
+        self.version = buffer.version();
+        true
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecentEdit {
+    /// The last key or action that edited the buffer, or the whole
+    /// insert session it's a part of
+    Change,
+    Insert,
+    Paste,
+}
+
+impl RecentEdit {
+    pub fn name(self) -> &'static str {
+        match self {
+            RecentEdit::Change => "change",
+            RecentEdit::Insert => "insert",
+            RecentEdit::Paste => "paste",
+        }
+    }
+}
+
+/// The recent edits of a buffer
+#[derive(Debug, Clone, Default)]
+pub struct RecentEdits {
+    change: Option<Span>,
+    insert: Option<Span>,
+    paste: Option<Span>,
+    /// Version the insert session going on started at
+    insert_start: Option<u64>,
+}
+
+impl RecentEdits {
+    pub fn get(&self, edit: RecentEdit) -> Option<&Span> {
+        match edit {
+            RecentEdit::Change => self.change.as_ref(),
+            RecentEdit::Insert => self.insert.as_ref(),
+            RecentEdit::Paste => self.paste.as_ref(),
+        }
+    }
+
+    pub(crate) fn start_insert(&mut self, buffer: &Buffer) {
+        self.insert_start = Some(buffer.version());
+    }
+
+    pub(crate) fn end_insert(&mut self) {
+        self.insert_start = None;
+    }
+
+    /// Note what changed since `version`, the version before a key was
+    /// handled
+    ///
+    /// An insert session started by the key, like a change deleting the
+    /// selection first, starts before it.
+    pub(crate) fn note_change(&mut self, buffer: &Buffer, version: u64) {
+        if let Some(start) = &mut self.insert_start {
+            *start = (*start).min(version);
+        }
+        let span = match Span::since(buffer, self.insert_start.unwrap_or(version)) {
+            Some(span) => span,
+            None => return,
+        };
+        if self.insert_start.is_some() {
+            self.insert = Some(span.clone());
+        }
+        self.change = Some(span);
+    }
+
+    pub(crate) fn note_paste(&mut self, buffer: &Buffer, version: u64) {
+        if let Some(span) = Span::since(buffer, version) {
+            self.paste = Some(span);
+        }
+    }
+
+    pub(crate) fn follow(&mut self, buffer: &Buffer) {
+        for span in [&mut self.change, &mut self.insert, &mut self.paste] {
+            if span.as_mut().is_some_and(|span| !span.follow(buffer)) {
+                *span = None;
+            }
+        }
+    }
+}
+
+#[test]
+fn span_test() {
+    use crate::Idx;
+    use ropey::Rope;
+    let mut buffer = Buffer::from_text(Rope::from_str("one two"));
+    let start = buffer.version();
+    for (i, ch) in "abc".chars().enumerate() {
+        buffer.insert(Idx(4 + i), &ch.to_string());
+    }
+    buffer.remove(5..6);
+    let mut span = Span::since(&buffer, start).expect("changed");
+    assert_eq!(span.ranges, vec![4..6]);
+    assert_eq!(buffer.text.slice(4..6).to_string(), "ac");
+
+    // edits at the edges don't make it grow
+    buffer.insert(Idx(6), "!");
+    buffer.insert(Idx(4), "> ");
+    buffer.insert(Idx(0), "x");
+    assert!(span.follow(&buffer));
+    assert_eq!(span.ranges, vec![7..9]);
+    assert_eq!(buffer.text.to_string(), "xone > ac!two");
+
+    buffer.remove(0..9);
+    assert!(span.follow(&buffer));
+    assert_eq!(span.ranges, vec![0..0]);
+    assert_eq!(Span::since(&buffer, buffer.version()), None);
+}
@@ -0,0 +1,202 @@
+//! Startup, in phases run one after the other
+//!
+//! The frontend sets the editor up with hooks, each added to a phase: the
+//! core handlers first, then the config, the themes, the plugins, and
+//! what's done once all is set, like opening the files. The hooks of a
+//! phase run in the order they were added, after all those of the phases
+//! before, so that a plugin sees the config whenever it was added.
+//!
+//! `libbrz` has no clock: the frontend passes one to time the hooks, for a
+//! report of where startup spent its time.
+use std::fmt;
+use std::time::Duration;
+
+/// Phases of startup, in the order they run
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// What reaches the files, the tools and the terminal
+    Core,
+    /// Keys, options and environment of the user and the project
+    Config,
+    /// Themes and colors
+    Themes,
+    /// What extends the editor, eg. language servers
+    Plugins,
+    /// Once all is set: the state of earlier sessions, the files to open
+    PostStartup,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Core => "core",
+            Phase::Config => "config",
+            Phase::Themes => "themes",
+            Phase::Plugins => "plugins",
+            Phase::PostStartup => "post-startup",
+        }
+    }
+}
+
+type Hook<T, E> = Box<dyn FnOnce(&mut T) -> Result<(), E>>;
+
+/// Hooks of startup, setting up a `T`, or failing with an `E`
+pub struct Startup<T, E> {
+    hooks: Vec<(Phase, String, Hook<T, E>)>,
+}
+
+impl<T, E> Default for Startup<T, E> {
+    fn default() -> Self {
+        Self { hooks: vec![] }
+    }
+}
+
+impl<T, E> Startup<T, E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `hook` in `phase`, after those added to it before
+    pub fn add(
+        &mut self,
+        phase: Phase,
+        name: &str,
+        hook: impl FnOnce(&mut T) -> Result<(), E> + 'static,
+    ) {
+        self.hooks.push((phase, name.to_owned(), Box::new(hook)));
+    }
+
+    /// Run the hooks, phase after phase, until one fails
+    ///
+    /// `clock` tells the time since startup began, for the report.
+    pub fn run(self, target: &mut T, clock: impl Fn() -> Duration) -> Result<Report, E> {
+        let mut hooks = self.hooks;
+        hooks.sort_by_key(|(phase, _, _)| *phase);
+        let mut report = Report::default();
+        for (phase, name, hook) in hooks {
+            let start = clock();
+            hook(target)?;
+            report.hooks.push(HookTiming {
+                phase,
+                name,
+                start,
+                took: clock().saturating_sub(start),
+            });
+        }
+        report.total = clock();
+        Ok(report)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookTiming {
+    pub phase: Phase,
+    pub name: String,
+    /// Time since startup began, when the hook started
+    pub start: Duration,
+    pub took: Duration,
+}
+
+/// Where startup spent its time, as `--startuptime` writes it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// In the order they ran
+    pub hooks: Vec<HookTiming>,
+    pub total: Duration,
+}
+
+impl Report {
+    /// Time taken by the hooks of `phase`
+    pub fn phase_time(&self, phase: Phase) -> Duration {
+        self.hooks
+            .iter()
+            .filter(|hook| hook.phase == phase)
+            .map(|hook| hook.took)
+            .sum()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for Report {
+    /// A line per hook, with the clock when it started and the time it
+    /// took, in ms, then a line per phase
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>9} {:>9}  {:<12} hook", "clock", "took", "phase")?;
+        for hook in &self.hooks {
+            writeln!(
+                f,
+                "{:>9.3} {:>9.3}  {:<12} {}",
+                millis(hook.start),
+                millis(hook.took),
+                hook.phase.name(),
+                hook.name
+            )?;
+        }
+        let mut phases: Vec<Phase> = self.hooks.iter().map(|hook| hook.phase).collect();
+        phases.dedup();
+        for phase in phases {
+            writeln!(
+                f,
+                "{:>9} {:>9.3}  {:<12} (all)",
+                "",
+                millis(self.phase_time(phase)),
+                phase.name()
+            )?;
+        }
+        writeln!(f, "{:>9.3} {:>9}  total", millis(self.total), "")
+    }
+}
+
+#[test]
+fn startup_test() {
+    use std::cell::Cell;
+    let mut startup: Startup<Vec<&str>, String> = Startup::new();
+    startup.add(Phase::Plugins, "plugin", |done| {
+        // the config is there, though added after
+        assert_eq!(done.last(), Some(&"config"));
+        done.push("plugin");
+        Ok(())
+    });
+    startup.add(Phase::Config, "config", |done| {
+        done.push("config");
+        Ok(())
+    });
+    startup.add(Phase::Core, "core", |done| {
+        done.push("core");
+        Ok(())
+    });
+    startup.add(Phase::Plugins, "second plugin", |done| {
+        done.push("second plugin");
+        Ok(())
+    });
+    let now = Cell::new(0);
+    let clock = || {
+        now.set(now.get() + 1);
+        Duration::from_millis(now.get())
+    };
+    let mut done = vec![];
+    let report = startup.run(&mut done, clock).unwrap();
+    assert_eq!(done, ["core", "config", "plugin", "second plugin"]);
+    assert_eq!(report.hooks[2].name, "plugin");
+    assert_eq!(report.phase_time(Phase::Plugins), Duration::from_millis(2));
+    let text = report.to_string();
+    assert!(text.contains("    7.000     1.000  plugins      second plugin\n"));
+    assert!(text.contains("plugins      (all)\n"));
+
+    // stops at the first failing
+    let mut startup: Startup<Vec<&str>, String> = Startup::new();
+    startup.add(Phase::Themes, "after", |done| {
+        done.push("after");
+        Ok(())
+    });
+    startup.add(Phase::Config, "failing", |_| Err("no config".into()));
+    let mut done = vec![];
+    assert_eq!(
+        startup.run(&mut done, Duration::default),
+        Err("no config".into())
+    );
+    assert!(done.is_empty());
+}
@@ -1,104 +1,383 @@
-use crate::buffer::Buffer;
-use crate::buffer::VisualSelection;
+use crate::action::{self, Action, ActionCall};
+use crate::buffer::{Buffer, BufferView, TextChange, VisualSelection};
+use crate::command::{CommandDef, CommandRegistry};
+use crate::completion::{self, Candidate, Completion, CompletionRequest};
+use crate::conflict::{ConflictSet, Pick};
+use crate::dashboard::{self, Dashboard};
+use crate::diff;
+use crate::document::{
+    self, Diagnostic, DiagnosticDisplay, Document, DocumentKind, FilePreview, LargeFileLimits,
+    LineNumbers, Severity, Wrap,
+};
+use crate::dropped;
+use crate::encoding::{self, Encoding, FileFormat, LineEnding};
+use crate::env;
+use crate::frecency::Frecency;
+use crate::git::{
+    self, CommitMessage, FileRevision, FileStatus, GitBaseRequest, GitRequest, Gutter, Revision,
+};
+use crate::grep::{GrepRequest, PendingGrep};
 use crate::mode::{self, Mode};
+use crate::motion::{CharFind, Motion};
+use crate::pipe::{PendingPipe, PipeRequest};
+use crate::position::{convert, Position};
 use crate::Idx;
 use crate::Key;
+use crate::Mouse;
+use crate::NaturalyOrderedKey;
+use crate::{Selection, SelectionSet};
 use default::default;
 use ropey::Rope;
 
-use crate::render::{self, Coord, Renderer};
-use crate::{buffer, position};
-use std::cell::RefCell;
+use crate::finder::{FileList, FileListRequest};
+use crate::fold;
+use crate::follow::{Follow, FollowMap};
+use crate::health::{self, Finding, Section};
+use crate::hierarchy::{HierarchyItem, HierarchyRequest};
+use crate::highlight::Highlighter;
+use crate::indent::Indent;
+use crate::invalidate::{Invalidation, PaneCache, PaneFrame, PaneKey, StyledLine};
+use crate::json;
+use crate::jump;
+use crate::keymap::{self, Binding, Keymaps};
+use crate::layer;
+use crate::layout::{Direction, Layout};
+use crate::lsp;
+use crate::mark::Marks;
+use crate::plugin;
+use crate::preview::{self, CurrentPreview, Preview, PreviewRequest};
+use crate::progress::{TaskId, Tasks};
+use crate::quickfix::{self, PendingEdits, Quickfix};
+use crate::recover::{self, Swap, SwapOp};
+use crate::register::{self, ClipboardOp, Macros, Register, Registers, YankHistory};
+use crate::remote;
+use crate::render::{self, ColorMap, Coord, Rect, Renderer, Window};
+use crate::repeat::{self, Coalescing};
+use crate::sanitize;
+use crate::search::{MatchSet, Search, SearchOptions};
+use crate::session::{self, Session};
+use crate::share::SharedSelections;
+use crate::span::{RecentEdit, RecentEdits};
+use crate::status::{self, StatusTemplate};
+use crate::structure::{self, Tree};
+use crate::surface::Surface;
+use crate::syntax::{self, Highlight, HighlightRequest, Highlights};
+use crate::tab::{Tab, Tabs};
+use crate::target::{self, Target};
+use crate::term::{Capabilities, Degraded};
+use crate::theme::{self, Background, ColorOverrides, Theme};
+use crate::todo::{self, Todo, TodoRequest};
+use crate::trash::TrashOp;
+use crate::undo::{UndoFileOp, UndoTree};
+use crate::vfs::{self, Vfs};
+use crate::view::{self, Row, ViewState};
+use crate::{buffer, profile};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use slab::Slab;
+use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Clone)]
-pub struct BufferState {
-    pub(crate) buffer: Buffer,
-    pub(crate) buffer_history: Vec<Buffer>,
-    pub(crate) buffer_history_undo_i: Option<usize>,
+/// Pause in typing after which a new undo chunk starts
+const UNDO_PAUSE: Duration = Duration::from_secs(1);
 
-    path: Option<PathBuf>,
-}
+/// Undo points kept for large files
+const REDUCED_UNDO_POINTS: usize = 20;
 
-impl BufferState {
-    pub(crate) fn maybe_commit_undo_point(&mut self) {
-        if let Some(restored_i) = self.buffer_history_undo_i {
-            if self.buffer_history[restored_i].text != self.buffer.text {
-                // if we started editing and content changed after restoring from undo,
-                // we reset the undo point and start appending commit new undo points
-                self.buffer_history_undo_i = None;
-
-                let new_buffer = self.buffer.clone();
-                self.buffer = self.buffer_history[restored_i].clone();
-                self.maybe_commit_undo_point();
-                self.buffer = new_buffer;
-                self.maybe_commit_undo_point();
-            } else if self.buffer_history[restored_i].selection != self.buffer.selection {
-                // XXX: TODO: We're editing history... :/ ... seems bad; does it give better UX?
-                self.buffer_history[restored_i].selection = self.buffer.selection.clone();
-            }
-        } else if let Some(last) = self.buffer_history.last_mut() {
-            if last.text != self.buffer.text {
-                // if buffer changed, we make it a new undo point
-                self.buffer_history.push(self.buffer.clone());
-            } else if last.selection != self.buffer.selection {
-                // if only the selection changed, we previous undo point,
-                // so undo always jumps to last cursor/selectin position from
-                // before the edit
-                last.selection = self.buffer.selection.clone();
-            }
-        } else {
-            self.buffer_history.push(self.buffer.clone());
-        }
-    }
+/// Files the find handler of `register_vfs` returns at most
+const MAX_FOUND: usize = 10;
 
-    pub(crate) fn undo(&mut self, times: usize) {
-        let i = if let Some(restored_i) = self.buffer_history_undo_i {
-            restored_i.saturating_sub(times)
-        } else {
-            self.maybe_commit_undo_point(); // commit to unify
-            self.buffer_history
-                .len()
-                .saturating_sub(1)
-                .saturating_sub(times)
-        };
+/// Recent files, and projects, on the dashboard at most
+const DASHBOARD_ENTRIES: usize = 10;
 
-        self.buffer_history_undo_i = Some(i);
-        self.buffer = self.buffer_history[i].clone();
-    }
+/// Lines from which highlighting a buffer takes long enough to show
+const HIGHLIGHT_TASK_LINES: usize = 10_000;
 
-    pub(crate) fn redo(&mut self, times: usize) {
-        if let Some(undo_i) = self.buffer_history_undo_i.as_mut() {
-            let new_i = min(undo_i.saturating_add(times), self.buffer_history.len() - 1);
-            *undo_i = new_i;
-            self.buffer = self.buffer_history[new_i].clone();
-        }
+/// Completions shown at once
+const COMPLETION_ROWS: usize = 10;
+
+/// Lines a turn of the mouse wheel scrolls
+const WHEEL_LINES: isize = 3;
+
+/// Why there's no earlier undo point, or later one with `later`
+fn no_undo_point(later: bool) -> String {
+    if later {
+        "already at the newest change".into()
+    } else {
+        "already at the oldest change".into()
     }
 }
 
+/// The paste that a paste of an older yank would replace
+struct JustPasted {
+    buffer_i: usize,
+    /// Version of the buffer right after the paste
+    version: u64,
+    /// Text and selection from before it
+    text: Rope,
+    selection: SelectionSet,
+    /// Of what was pasted in the yank history
+    history_i: usize,
+    extend: bool,
+}
+
+/// A buffer and the document it holds
+#[derive(Clone, Default)]
+pub struct BufferState {
+    pub(crate) buffer: Buffer,
+    pub(crate) document: Document,
+    /// Matches of the last search
+    pub(crate) matches: Option<MatchSet>,
+    pub(crate) conflicts: ConflictSet,
+    pub(crate) highlights: Highlights,
+    pub(crate) code_lenses: lsp::CodeLenses,
+    pub(crate) references: lsp::DocumentHighlights,
+    /// Task of the pending highlight request
+    pub(crate) highlight_task: Option<TaskId>,
+    pub(crate) gutter: Gutter,
+    /// Pending on-type formatting request, and the version it's for
+    pub(crate) on_type_formatting: Option<(u64, u64)>,
+    pub(crate) recent_edits: RecentEdits,
+    pub(crate) marks: Marks,
+    /// Version of the buffer when it was last read or written
+    pub(crate) written_version: u64,
+    /// Modification time of the file when it was last read or written
+    pub(crate) disk_modified: Option<SystemTime>,
+    /// Watch of the file, for changes made elsewhere, and its path
+    watch: Option<(PathBuf, Arc<vfs::Watch>)>,
+    /// Version last copied to the swap file, and when, as described in
+    /// `recover`
+    swapped: Option<(u64, SystemTime)>,
+    /// There's a swap file of the buffer
+    swap_kept: bool,
+    /// Path and version of the document the servers last saw
+    pub(crate) synced: Option<(PathBuf, u64)>,
+    /// Brackets of the text, and the version they're of
+    tree: RefCell<Option<(u64, Tree)>>,
+}
+
+pub type ReadHandler = Arc<dyn Fn(&Path) -> io::Result<Vec<u8>>>;
+pub type WriteHandler = Arc<dyn Fn(&Path, &[u8]) -> io::Result<()>>;
+pub type WritableHandler = Arc<dyn Fn(&Path) -> io::Result<bool>>;
+pub type StatHandler = Arc<dyn Fn(&Path) -> io::Result<vfs::Metadata>>;
+pub type WatchHandler = Arc<dyn Fn(&Path) -> io::Result<vfs::Watch>>;
+pub type FindHandler = Arc<dyn Fn(&str) -> io::Result<Vec<PathBuf>>>;
+pub type ListDirHandler = Arc<dyn Fn(&Path) -> io::Result<Vec<vfs::DirEntry>>>;
+pub type FileListHandler = Arc<dyn Fn(FileListRequest) -> io::Result<()>>;
+pub type PreviewHandler = Arc<dyn Fn(PreviewRequest) -> io::Result<()>>;
+pub type GrepHandler = Arc<dyn Fn(&str) -> io::Result<Vec<quickfix::Entry>>>;
+pub type ProjectSearchHandler = Arc<dyn Fn(GrepRequest) -> io::Result<()>>;
+pub type GitHandler = Arc<dyn Fn(&GitRequest) -> io::Result<String>>;
+pub type GitBaseHandler = Arc<dyn Fn(GitBaseRequest) -> io::Result<()>>;
+pub type HighlightHandler = Arc<dyn Fn(HighlightRequest) -> io::Result<()>>;
+pub type TodoHandler = Arc<dyn Fn(TodoRequest) -> io::Result<()>>;
+pub type PipeHandler = Arc<dyn Fn(PipeRequest) -> io::Result<()>>;
+pub type ResourceHandler = Arc<dyn Fn(&lsp::ResourceOp) -> io::Result<()>>;
+pub type TrashHandler = Arc<dyn Fn(&TrashOp) -> io::Result<PathBuf>>;
+pub type UndoFileHandler = Arc<dyn Fn(&UndoFileOp) -> io::Result<Option<String>>>;
+pub type SwapHandler = Arc<dyn Fn(&SwapOp) -> io::Result<()>>;
+pub type CodeLensHandler = Arc<dyn Fn(lsp::CodeLensRequest) -> io::Result<()>>;
+pub type CommandHandler = Arc<dyn Fn(&lsp::Command) -> io::Result<()>>;
+pub type DocumentHighlightHandler = Arc<dyn Fn(lsp::DocumentHighlightRequest) -> io::Result<()>>;
+pub type OnTypeFormattingHandler = Arc<dyn Fn(lsp::OnTypeFormattingRequest) -> io::Result<()>>;
+pub type DocumentSyncHandler = Arc<dyn Fn(&lsp::DocumentSync)>;
+pub type QueryHandler = Arc<dyn Fn(lsp::QueryRequest) -> io::Result<()>>;
+pub type HierarchyHandler = Arc<dyn Fn(&HierarchyRequest) -> io::Result<Vec<HierarchyItem>>>;
+pub type ChdirHandler = Arc<dyn Fn(&Path) -> io::Result<()>>;
+pub type ClipboardHandler = Arc<dyn Fn(&ClipboardOp) -> io::Result<Option<String>>>;
+pub type HealthCheck = Arc<dyn Fn() -> Vec<Finding>>;
+pub type PluginHandler = Arc<dyn Fn(&plugin::Event) -> io::Result<()>>;
+
 /// The editor state
 pub struct State {
     pub(crate) quit: bool,
     mode: Option<Box<dyn Mode + 'static>>,
-    pub(crate) yanked: Vec<Rope>,
+    pub(crate) yanked: Register,
+    pub(crate) yank_history: YankHistory,
+    pub(crate) registers: Registers,
+    /// Named with `"` for the next yank, delete or paste
+    pending_register: Option<char>,
+    /// Typed at the command prompt
+    pub(crate) last_command: Option<String>,
+    pub(crate) macros: Macros,
+    /// Registers being replayed, innermost last
+    replaying: Vec<char>,
+    /// The key went back or forward through the selection history, and
+    /// is not to be noted in it
+    selection_restored: bool,
+    just_pasted: Option<JustPasted>,
 
     pub(crate) msg: Option<String>,
 
-    pub(crate) read_handler: Arc<dyn Fn(&Path) -> io::Result<Rope>>,
-    pub(crate) write_handler: Arc<dyn Fn(&Path, &Rope) -> io::Result<()>>,
-    pub(crate) find_handler: Arc<dyn Fn(&str) -> io::Result<Vec<PathBuf>>>,
+    /// Chars typed in insert mode since the last undo point
+    pub(crate) chars_since_undo_point: usize,
+
+    pub(crate) read_handler: ReadHandler,
+    pub(crate) write_handler: WriteHandler,
+    pub(crate) writable_handler: WritableHandler,
+    pub(crate) stat_handler: StatHandler,
+    /// Watches files, to call `file_changed` when they change
+    pub(crate) watch_handler: WatchHandler,
+    /// Writes with more rights, eg. through `sudo`
+    pub(crate) elevated_write_handler: WriteHandler,
+    pub(crate) find_handler: FindHandler,
+    pub(crate) list_dir_handler: ListDirHandler,
+    pub(crate) file_list_handler: FileListHandler,
+    pub(crate) preview_handler: PreviewHandler,
+    pub(crate) grep_handler: GrepHandler,
+    pub(crate) project_search_handler: ProjectSearchHandler,
+    pub(crate) git_handler: GitHandler,
+    pub(crate) git_base_handler: GitBaseHandler,
+    pub(crate) highlight_handler: HighlightHandler,
+    /// Finds the spans of the buffers to color, and their styles
+    pub(crate) highlighter: Arc<dyn Highlighter>,
+    pub(crate) todo_handler: TodoHandler,
+    pub(crate) pipe_handler: PipeHandler,
+    pub(crate) resource_handler: ResourceHandler,
+    pub(crate) trash_handler: TrashHandler,
+    /// Keeps undo histories of files across sessions
+    pub(crate) undo_file_handler: UndoFileHandler,
+    pub(crate) swap_handler: SwapHandler,
+    /// Swap files left over by an earlier session, to `recover`
+    recoverable: Vec<Swap>,
+    pub(crate) clipboard_handler: ClipboardHandler,
+    /// Checks of `:health` registered by the frontend, by section
+    health_checks: Vec<(String, HealthCheck)>,
+    /// Plugins running, by name, as described in `plugin`
+    plugins: Vec<(String, PluginHandler)>,
+    pub(crate) plugin_overlay: Option<plugin::Overlay>,
+    /// What the config files that didn't parse told, for `:health`
+    config_errors: Vec<String>,
+    /// The time, as last told by the frontend
+    now: SystemTime,
+    /// Files deleted with `delete_file` go to the trash
+    pub(crate) delete_to_trash: bool,
+    /// Scratch buffers are kept for the next session, as described in
+    /// `scratch_buffers`
+    pub(crate) keep_scratch: bool,
+    /// Buffers without changes are read again when their file changes
+    pub(crate) auto_reload: bool,
+    /// Repeats of a key taken at once, as described in `repeat`
+    key_coalescing: Coalescing,
+    /// Where the last file trashed was, where it went, and the buffer it
+    /// was deleted from
+    last_trashed: Option<(PathBuf, PathBuf, usize)>,
+    pub(crate) code_lens_handler: CodeLensHandler,
+    pub(crate) command_handler: CommandHandler,
+    pub(crate) hierarchy_handler: HierarchyHandler,
+    pub(crate) document_highlight_handler: DocumentHighlightHandler,
+    pub(crate) on_type_formatting_handler: OnTypeFormattingHandler,
+    pub(crate) document_sync_handler: DocumentSyncHandler,
+    pub(crate) query_handler: QueryHandler,
+    /// Chars formatting the code around once typed
+    format_on_type_triggers: Vec<char>,
 
     buffers: Slab<BufferState>,
-    cur_buffer_i: Option<usize>,
+    views: Slab<ViewState>,
+    cur_view_i: Option<usize>,
+    /// Windows, with the current view focused; `None` with no view
+    layout: Option<Layout>,
+    /// With the windows of the other tabs, as described in `tab`
+    tabs: Tabs,
 
     pub last_visual_cursor_coord: RefCell<Option<Coord>>,
+    /// Where the cursor was last drawn on the screen, for popups
+    cursor_screen_coord: Cell<Option<Coord>>,
+    /// The view and char the left button was pressed at, while it's held
+    mouse_anchor: Option<(usize, Idx)>,
 
     pub num_prefix: Option<usize>,
+
+    pub(crate) last_search: Option<Search>,
+    /// The last find motion, and whether it extended
+    last_find: Option<(CharFind, bool)>,
+    /// Of the pattern typed in the search prompt, highlighted in place of
+    /// the last search until it's submitted
+    pub(crate) search_preview: Option<Search>,
+    /// Options the search prompt starts with, as last toggled
+    pub(crate) search_options: SearchOptions,
+    pub(crate) diagnostic_display: DiagnosticDisplay,
+    pub(crate) line_numbers: LineNumbers,
+    /// What the terminal can show, as told by the frontend
+    pub(crate) capabilities: Capabilities,
+    pub(crate) large_file_limits: LargeFileLimits,
+
+    preview: Option<CurrentPreview>,
+    next_preview_id: u64,
+    next_highlight_id: u64,
+    next_git_base_id: u64,
+    next_code_lens_id: u64,
+    next_document_highlight_id: u64,
+    next_on_type_formatting_id: u64,
+    next_todo_id: u64,
+    /// Id, cancel token and task of the pending todo scan
+    todo_scan: Option<(u64, preview::CancelToken, TaskId)>,
+    next_file_list_id: u64,
+    /// Files of the project listed for the fuzzy finder, while it's open
+    pub(crate) file_list: Option<FileList>,
+    /// Tagged comments found by the last todo scan, if any was done
+    todos: Option<Vec<Todo>>,
+    /// Open the todo list once the pending scan is done
+    open_todos_when_scanned: bool,
+    next_pipe_id: u64,
+    pending_pipe: Option<PendingPipe>,
+    next_grep_id: u64,
+    pending_grep: Option<PendingGrep>,
+    next_query_id: u64,
+    /// Id, query and task of the pending query
+    pending_query: Option<(u64, lsp::Query, TaskId)>,
+    /// Asked in order, the first to name a label gets it
+    completion_sources: Vec<Arc<dyn completion::Source>>,
+    next_completion_id: u64,
+    /// The completion menu, while in insert mode
+    pub(crate) completion: Option<Completion>,
+
+    pub profiler: profile::Profiler,
+    /// Environment of the tools the frontend runs
+    pub env: env::Environment,
+    pub(crate) tasks: Tasks,
+    layers: Vec<layer::Layer>,
+    keymaps: Keymaps,
+    pub(crate) commands: CommandRegistry,
+    /// Layout of the status line, instead of the default one
+    pub(crate) status_template: Option<StatusTemplate>,
+    /// Colors of the kinds of panes, by their name in `theme::PANE_KINDS`
+    pane_colors: BTreeMap<String, ColorOverrides>,
+    themes: BTreeMap<String, Theme>,
+    /// Name of the theme picked, if not the colors of the frontend
+    pub(crate) theme: Option<String>,
+    /// Picks the variant of the theme
+    pub(crate) background: Background,
+
+    /// Marks for the next frame
+    invalidation: RefCell<Invalidation>,
+    /// Marks for the frame being drawn
+    frame_invalidation: RefCell<Invalidation>,
+    /// Number of frames drawn
+    frame: Cell<u64>,
+    /// By view
+    pane_cache: RefCell<BTreeMap<usize, PaneCache>>,
+
+    /// How many times each action was executed
+    pub action_counts: BTreeMap<&'static str, u64>,
+    /// Actions run from the palette
+    pub(crate) action_usage: Frecency,
+
+    /// Directory the editor works in
+    project_dir: PathBuf,
+    pub(crate) chdir_handler: ChdirHandler,
+    /// Files opened, by absolute path
+    recent_files: Frecency,
+    /// Directories the editor worked in
+    recent_projects: Frecency,
+    /// Files open at the end of the last session of each project
+    sessions: BTreeMap<PathBuf, Vec<PathBuf>>,
 }
 
 impl State {
@@ -110,311 +389,6544 @@ impl State {
         }
     }
 
-    pub(crate) fn take_num_prefix(&mut self) -> usize {
-        self.num_prefix.take().unwrap_or(1)
+    /// Execute an action by name
+    ///
+    /// All the actions, whether triggered by a key, a command or
+    /// anything else, should go through here.
+    pub fn execute_action(&mut self, call: &ActionCall) {
+        match action::registry().get_key_value(call.name.as_str()) {
+            Some((name, action)) => {
+                *self.action_counts.entry(name).or_insert(0) += 1;
+                action.execute(self, &call.args);
+            }
+            None => action::ActionNotFound.execute(self, &call.args),
+        }
     }
 
-    pub(crate) fn set_mode(&mut self, mode: impl Mode + 'static) {
-        self.cur_buffer_state_mut_opt()
-            .map(|b| b.maybe_commit_undo_point());
-        self.mode = None;
-        let mut mode = Box::new(mode) as Box<dyn Mode>;
-        mode.on_enter(self);
-        self.mode = Some(mode);
+    /// What the palette knows of the actions run from it, to keep for the
+    /// next session
+    pub fn action_usage(&self) -> String {
+        self.action_usage.to_text()
     }
 
-    pub fn get_mode(&self) -> &(dyn Mode + 'static) {
-        &**self.mode.as_ref().unwrap()
+    /// Restore what `action_usage` returned
+    pub fn load_action_usage(&mut self, text: &str) {
+        self.action_usage = Frecency::from_text(text);
     }
 
-    /*
-    pub(crate) fn get_mode(&mut self) -> &mut Mode {
-        &mut self.mode
+    /// Work in `dir`, the absolute path of the directory the relative
+    /// paths are relative to
+    pub fn set_project_dir(&mut self, dir: PathBuf) {
+        self.recent_projects.note(&dir.to_string_lossy());
+        self.project_dir = dir;
     }
-    */
 
-    pub fn open_buffer(&mut self, path: &Path) {
-        let mut found = None;
-
-        for (i, buffer_state) in self.buffers.iter() {
-            if buffer_state.path.as_ref().map(|p| p.as_path()) == Some(path) {
-                found = Some(i);
-                break;
-            }
-        }
+    /// The files opened recently, to keep for the next session
+    pub fn recent_files(&self) -> String {
+        self.recent_files.to_text()
+    }
 
-        if let Some(found) = found {
-            self.cur_buffer_i = Some(found);
-            return;
-        }
+    /// Restore what `recent_files` returned
+    pub fn load_recent_files(&mut self, text: &str) {
+        self.recent_files = Frecency::from_text(text);
+    }
 
-        let rope = match (self.read_handler)(path) {
-            Err(e) => {
-                self.msg = Some(format!("{}", e));
-                return;
-            }
-            Ok(rope) => rope,
-        };
+    /// The projects worked in recently, to keep for the next session
+    pub fn recent_projects(&self) -> String {
+        self.recent_projects.to_text()
+    }
 
-        let entry = self.buffers.vacant_entry();
+    /// Restore what `recent_projects` returned
+    pub fn load_recent_projects(&mut self, text: &str) {
+        self.recent_projects = Frecency::from_text(text);
+    }
 
-        self.cur_buffer_i = Some(entry.key());
-        entry.insert(BufferState {
-            path: Some(path.to_owned()),
-            buffer: Buffer::from_text(rope),
-            ..default()
-        });
+    /// The files open in each project at the end of its last session, this
+    /// one included, a `project<TAB>file` line per file
+    pub fn sessions(&self) -> String {
+        let open: Vec<PathBuf> = self
+            .buffers
+            .iter()
+            .filter(|(_, buffer_state)| buffer_state.document.kind == DocumentKind::Text)
+            .filter_map(|(_, buffer_state)| buffer_state.document.path.as_ref())
+            .map(|path| self.project_dir.join(path))
+            .collect();
+        let mut sessions = self.sessions.clone();
+        if !open.is_empty() {
+            sessions.insert(self.project_dir.clone(), open);
+        }
+        sessions
+            .iter()
+            .flat_map(|(project, files)| {
+                files
+                    .iter()
+                    .map(move |file| format!("{}\t{}\n", project.display(), file.display()))
+            })
+            .collect()
     }
 
-    pub fn write_buffer(&mut self, path: Option<PathBuf>) {
-        if let Some(path) = path.or_else(|| self.cur_buffer_state().path.clone()) {
-            match self.try_write_buffer(&path) {
-                Ok(()) => {
-                    self.cur_buffer_state_mut().path = Some(path);
-                }
-                Err(e) => {
-                    self.msg = Some(format!("{}", e));
-                }
+    /// The text of the scratch buffers written in, to keep for the next
+    /// session
+    ///
+    /// Each is its length in bytes on a line, then the text. Those only
+    /// opened to show something, like `:lsp-info`, are left out.
+    pub fn scratch_buffers(&self) -> String {
+        let mut kept = String::new();
+        for (_, buffer_state) in self.buffers.iter() {
+            let document = &buffer_state.document;
+            let buffer = &buffer_state.buffer;
+            if document.kind != DocumentKind::Text
+                || document.path.is_some()
+                || buffer_state.written_version == buffer.version()
+                || buffer.text.len_chars() == 0
+            {
+                continue;
             }
-        } else {
-            self.msg = Some("No path given".to_string());
+            let text = buffer.text.to_string();
+            kept.push_str(&format!("{}\n{}", text.len(), text));
         }
+        kept
     }
 
-    fn try_write_buffer(&self, path: &Path) -> io::Result<()> {
-        if let Some(cur_buffer_i) = self.cur_buffer_i {
-            (self.write_handler)(path, &self.buffers[cur_buffer_i].buffer.text)?;
+    /// Open scratch buffers with what `scratch_buffers` returned, and
+    /// return how many
+    pub fn load_scratch_buffers(&mut self, mut kept: &str) -> usize {
+        let mut count = 0;
+        while let Some((len, rest)) = kept.split_once('\n') {
+            let text = match len.parse().ok().and_then(|len: usize| rest.get(..len)) {
+                Some(text) => text,
+                None => break,
+            };
+            kept = &rest[text.len()..];
+            let buffer_i = self.open_special_buffer(text, DocumentKind::Text);
+            // still not written anywhere, to be kept again
+            let buffer_state = &mut self.buffers[buffer_i];
+            buffer_state.written_version = buffer_state.buffer.version().wrapping_sub(1);
+            count += 1;
         }
-
-        Ok(())
+        count
     }
 
-    pub fn delete_buffer(&mut self) {
-        if let Some(cur_buffer_i) = self.cur_buffer_i {
-            self.buffers.remove(cur_buffer_i);
-            self.buffer_next()
+    /// Restore what `sessions` returned
+    pub fn load_sessions(&mut self, text: &str) {
+        self.sessions.clear();
+        for (project, file) in text.lines().filter_map(|line| line.split_once('\t')) {
+            self.sessions
+                .entry(PathBuf::from(project))
+                .or_default()
+                .push(PathBuf::from(file));
         }
     }
 
-    pub fn buffer_next(&mut self) {
-        if !self.buffers.is_empty() {
-            let mut cur_buffer_i = self.cur_buffer_i.expect("cur_buffer_i set");
-            loop {
-                cur_buffer_i += 1;
-                cur_buffer_i %= self.buffers.capacity();
-                if self.buffers.contains(cur_buffer_i) {
-                    self.cur_buffer_i = Some(cur_buffer_i);
-                    break;
-                }
-            }
-        } else {
-            self.cur_buffer_i = None;
+    /// Show the dashboard, in place of an empty buffer
+    pub fn open_dashboard(&mut self) {
+        let hints = [
+            ("OpenFile", "open a file"),
+            ("Palette", "pick an action"),
+            ("Command", "run a command, :q to quit"),
+        ]
+        .iter()
+        .filter_map(|&(name, help)| {
+            let (key, _) = action::normal::default_key_mappings()
+                .iter()
+                .find(|(_, action)| **action == name)?;
+            Some((layer::key_name(key.0), help.to_owned()))
+        })
+        .chain(Some((
+            "enter".to_owned(),
+            "open the entry of the line".to_owned(),
+        )))
+        .collect();
+        let to_paths = |names: Vec<&str>| names.into_iter().map(PathBuf::from).collect();
+        let contents = dashboard::Contents {
+            files: to_paths(self.recent_files.top(DASHBOARD_ENTRIES)),
+            projects: to_paths(self.recent_projects.top(DASHBOARD_ENTRIES + 1)),
+            session: self
+                .sessions
+                .get(&self.project_dir)
+                .cloned()
+                .unwrap_or_default(),
+            hints,
+        };
+        let (dashboard, text) = Dashboard::new(&self.project_dir, &contents);
+        let old = self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| {
+                matches!(buffer_state.document.kind, DocumentKind::Dashboard(_))
+            })
+            .map(|(i, _)| i);
+        // a new buffer, not to be taken for an edit of the old one
+        self.open_special_buffer(&text, DocumentKind::Dashboard(dashboard));
+        if let Some(old) = old {
+            let cur_view_i = self.cur_view_i.expect("view");
+            self.close_windows_of(old, cur_view_i);
+            self.buffers.remove(old);
+            self.views.retain(|_, view| view.buffer_i != old);
         }
     }
 
-    pub fn buffer_prev(&mut self) {
-        if !self.buffers.is_empty() {
-            let mut cur_buffer_i = self.cur_buffer_i.expect("cur_buffer_i set");
-            loop {
-                if cur_buffer_i == 0 {
-                    cur_buffer_i = self.buffers.capacity() - 1;
-                } else {
-                    cur_buffer_i -= 1;
+    /// Open what the line of the cursor is about, in the dashboard or a
+    /// quickfix list
+    pub(crate) fn open_entry(&mut self) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        let line = self
+            .cur_view()
+            .selection
+            .cursor_coord(&self.cur_buffer().text)
+            .line;
+        let entry = match self.cur_document().kind {
+            DocumentKind::Dashboard(ref dashboard) => dashboard.entry_at(line).cloned(),
+            DocumentKind::Quickfix(_) => {
+                let text = self.cur_buffer().text.line(line).to_string();
+                match Quickfix::location(text.trim_end_matches(&['\r', '\n'][..])) {
+                    Some((path, line)) => self.open_location(&remote::Location {
+                        path,
+                        line: Some(line + 1),
+                    }),
+                    None => self.msg = Some("not a location".into()),
                 }
-                if self.buffers.contains(cur_buffer_i) {
-                    self.cur_buffer_i = Some(cur_buffer_i);
-                    break;
+                return;
+            }
+            _ => {
+                self.msg = Some("nothing to open here".into());
+                return;
+            }
+        };
+        match entry {
+            Some(dashboard::Entry::File(path)) => self.open_buffer(&path),
+            Some(dashboard::Entry::RestoreSession(files)) => {
+                for path in files {
+                    self.open_buffer(&path);
                 }
             }
+            Some(dashboard::Entry::Project(dir)) => match (self.chdir_handler)(&dir) {
+                Ok(()) => {
+                    self.set_project_dir(dir);
+                    self.open_dashboard();
+                }
+                Err(e) => self.msg = Some(format!("{}: {}", dir.display(), e)),
+            },
+            None => {}
+        }
+    }
+
+    /// Select the next (or previous) match of the last search
+    pub(crate) fn search_next(&mut self, backward: bool) {
+        let search = if let Some(search) = self.last_search.clone() {
+            search
         } else {
-            self.cur_buffer_i = None;
+            self.msg = Some("no previous search".into());
+            return;
+        };
+        self.note_jump();
+        if let Some(mut buffer) = self.cur_buffer_mut_opt() {
+            if !buffer.select_next_match(&search, backward) {
+                self.msg = Some(format!("not found: {}", search.pattern));
+            }
         }
     }
 
-    pub fn is_finished(&self) -> bool {
-        self.quit
+    /// Go to the `n`th occurrence of a char from every cursor, and keep
+    /// the find to repeat
+    pub(crate) fn find_char(&mut self, find: CharFind, n: usize, extend: bool) {
+        self.last_find = Some((find, extend));
+        if let Some(mut buffer) = self.cur_buffer_mut_opt() {
+            buffer.apply_motion(Motion::Find(find, n), extend);
+        }
     }
 
-    pub fn handle_key(&mut self, key: Key) {
-        self.msg = None;
-        let mut mode = self.mode.take().expect("mode set");
+    /// Repeat the last find, extending if it did, in the other direction
+    /// with `reversed`
+    pub(crate) fn repeat_find(&mut self, reversed: bool, n: usize) {
+        let (find, extend) = match self.last_find {
+            Some(last) => last,
+            None => {
+                self.msg = Some("no previous find".into());
+                return;
+            }
+        };
+        let find = if reversed { find.reversed() } else { find };
+        if let Some(mut buffer) = self.cur_buffer_mut_opt() {
+            buffer.apply_motion(Motion::Find(find, n), extend);
+        }
+    }
 
-        mode.handle(self, key);
+    /// Start recording keys in `register`, or stop if recording
+    pub(crate) fn toggle_recording(&mut self, register: Option<char>) {
+        if let Some(register) = self.macros.stop() {
+            self.msg = Some(format!("recorded @{}", register));
+        } else if let Some(register) = register {
+            self.macros.start(register);
+        }
+    }
 
-        if self.mode.is_none() {
-            self.mode = Some(mode)
+    /// Handle the keys recorded in `register` again, `n` times
+    pub(crate) fn replay_macro(&mut self, register: char, n: usize) {
+        let (register, keys) = match self.macros.replay(register) {
+            Some((register, keys)) => (register, keys.to_vec()),
+            None => {
+                self.msg = Some(format!("nothing recorded in @{}", register));
+                return;
+            }
+        };
+        if self.replaying.contains(&register) {
+            self.msg = Some(format!("@{} replays itself", register));
+            return;
+        }
+        self.replaying.push(register);
+        for _ in 0..n {
+            for &key in &keys {
+                self.handle_key(key);
+            }
         }
+        self.replaying.pop();
     }
 
-    pub fn cur_buffer_opt(&self) -> Option<&Buffer> {
-        self.cur_buffer_i.map(|i| &self.buffers[i].buffer)
+    pub(crate) fn recording_indicator(&self) -> Option<String> {
+        self.macros
+            .recording()
+            .map(|register| format!("recording @{}", register))
     }
 
-    pub fn cur_buffer_mut_opt(&mut self) -> Option<&mut Buffer> {
-        self.cur_buffer_i.map(move |i| &mut self.buffers[i].buffer)
+    pub fn set_key_coalescing(&mut self, coalescing: Coalescing) {
+        self.key_coalescing = coalescing;
     }
 
-    pub fn cur_buffer(&self) -> &Buffer {
-        self.cur_buffer_opt().expect("cur_buffer set")
+    pub fn key_coalescing(&self) -> Coalescing {
+        self.key_coalescing
     }
 
-    pub fn cur_buffer_mut(&mut self) -> &mut Buffer {
-        self.cur_buffer_mut_opt().expect("cur_buffer set")
+    /// The repeats of `key` waiting may be taken at once, and handled with
+    /// `handle_repeated_key`
+    pub fn coalesces(&self, key: Key) -> bool {
+        match self.key_coalescing {
+            Coalescing::Off => false,
+            Coalescing::Movements => self.is_movement(key),
+            Coalescing::All => true,
+        }
     }
 
-    pub fn cur_buffer_state_opt(&self) -> Option<&BufferState> {
-        self.cur_buffer_i.map(|i| &self.buffers[i])
+    /// `key` runs one of the `repeat::MOVEMENTS` in normal mode, with no
+    /// count typed before, and none of the keymaps binding it otherwise
+    fn is_movement(&self, key: Key) -> bool {
+        if self.mode_name() != "normal"
+            || self.num_prefix.is_some()
+            || self.cur_buffer_opt().is_none()
+        {
+            return false;
+        }
+        let language = self.cur_document_opt().and_then(|d| d.language.as_deref());
+        if self.keymaps.lookup("normal", key, language).is_some() {
+            return false;
+        }
+        self.get_mode()
+            .key_mappings()
+            .get(&NaturalyOrderedKey(key))
+            .is_some_and(|name| repeat::MOVEMENTS.contains(name))
     }
 
-    pub fn cur_buffer_state_mut_opt(&mut self) -> Option<&mut BufferState> {
-        self.cur_buffer_i.map(move |i| &mut self.buffers[i])
+    /// Handle `key` pressed `times` in a row: a movement with the count,
+    /// other keys one after the other
+    ///
+    /// The last step of a movement is taken on its own, for it to select
+    /// what it would have after the others.
+    pub fn handle_repeated_key(&mut self, key: Key, times: usize) {
+        // the count would not be recorded
+        let recording = self.macros.recording().is_some();
+        if 2 < times && !recording && self.is_movement(key) {
+            self.num_prefix = Some(times - 1);
+            self.handle_key(key);
+            self.handle_key(key);
+            return;
+        }
+        for _ in 0..times {
+            if self.is_finished() {
+                return;
+            }
+            self.handle_key(key);
+        }
     }
 
-    pub fn cur_buffer_state(&self) -> &BufferState {
-        self.cur_buffer_state_opt().expect("cur_buffer set")
+    pub(crate) fn take_num_prefix(&mut self) -> usize {
+        self.num_prefix.take().unwrap_or(1)
     }
 
-    pub fn cur_buffer_state_mut(&mut self) -> &mut BufferState {
-        self.cur_buffer_state_mut_opt().expect("cur_buffer set")
+    /// While a mode handles a key, whether it set another mode
+    pub(crate) fn mode_was_set(&self) -> bool {
+        self.mode.is_some()
     }
 
-    pub fn mode_name(&self) -> &str {
-        self.mode.as_ref().expect("mode set").name()
+    pub(crate) fn set_mode(&mut self, mode: impl Mode + 'static) {
+        self.commit_undo_point();
+        self.completion = None;
+        self.invalidation().invalidate_all();
+        self.mode = None;
+        let mut mode = Box::new(mode) as Box<dyn Mode>;
+        mode.on_enter(self);
+        self.mode = Some(mode);
     }
 
-    pub fn register_read_handler(&mut self, f: impl Fn(&Path) -> io::Result<Rope> + 'static) {
-        self.read_handler = Arc::new(f);
+    /// Go back to a mode left for a while, as it was
+    pub(crate) fn restore_mode(&mut self, mode: Box<dyn Mode>) {
+        self.invalidation().invalidate_all();
+        self.mode = Some(mode);
     }
 
-    pub fn register_write_handler(&mut self, f: impl Fn(&Path, &Rope) -> io::Result<()> + 'static) {
-        self.write_handler = Arc::new(f);
+    /// Handle text pasted in the terminal
+    ///
+    /// The paths of files dropped on it are offered to be opened; other
+    /// text is handled as if typed.
+    pub fn handle_paste(&mut self, text: &str) {
+        match dropped::parse_dropped(text) {
+            Some(paths) => {
+                let previous = self.mode.take().expect("mode set");
+                self.restore_mode(Box::new(mode::OpenDropped::new(
+                    paths,
+                    text.to_owned(),
+                    previous,
+                )));
+            }
+            None => self.type_text(text),
+        }
     }
 
-    pub fn register_find_handler(
-        &mut self,
-        f: impl Fn(&str) -> io::Result<Vec<PathBuf>> + 'static,
-    ) {
-        self.find_handler = Arc::new(f);
+    /// Handle each char of `text` as a key, with line ends as `\n`
+    pub(crate) fn type_text(&mut self, text: &str) {
+        for ch in text.replace("\r\n", "\n").chars() {
+            self.handle_key(Key::Char(if ch == '\r' { '\n' } else { ch }));
+        }
     }
 
-    pub fn render(&self, render: &mut dyn Renderer) {
-        self.mode.as_ref().expect("mode set").render(self, render);
+    pub fn get_mode(&self) -> &(dyn Mode + 'static) {
+        &**self.mode.as_ref().unwrap()
     }
 
-    pub fn render_buffer(&self, mut render: &mut dyn Renderer) {
-        if self.cur_buffer_opt().is_none() {
-            self.render_splash(render);
+    /*
+    pub(crate) fn get_mode(&mut self) -> &mut Mode {
+        &mut self.mode
+    }
+    */
+
+    pub fn open_buffer(&mut self, path: &Path) {
+        let found = self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| buffer_state.document.path.as_deref() == Some(path))
+            .map(|(i, _)| i);
+
+        if let Some(found) = found {
+            self.focus_buffer(found);
             return;
         }
 
-        let buffer = self.cur_buffer();
-        let dims = render.dimensions();
+        // not knowing is not a reason not to read it
+        let len = (self.stat_handler)(path).map_or(0, |metadata| metadata.len);
+        if self.large_file_limits.previews(len) {
+            self.open_file_preview(path, len);
+            return;
+        }
+        let (rope, format) = match self.read_file(path) {
+            Err(e) => {
+                self.msg = Some(format!("{}", e));
+                return;
+            }
+            Ok(read) => read,
+        };
+        self.recent_files
+            .note(&self.project_dir.join(path).to_string_lossy());
+        let suspicious = sanitize::count(&rope);
+        let reduced = self.large_file_limits.exceeded_by(&rope);
+        let mut buffer = Buffer::from_text(rope);
+        let mut document = Document::from_path(path);
+        document.format = format;
+        buffer.indent = Indent::detect(document.language.as_deref(), &buffer.text);
+        // not knowing is not a reason to warn
+        document.unwritable = !(self.writable_handler)(path).unwrap_or(true);
+        let kept = (self.undo_file_handler)(&UndoFileOp::Read(path.to_owned()));
+        if let Some(history) = kept
+            .ok()
+            .flatten()
+            .and_then(|kept| UndoTree::from_text(&kept, &buffer.text))
+        {
+            buffer.history = history;
+        }
+        if reduced {
+            buffer.history.limit = Some(REDUCED_UNDO_POINTS);
+            document.reduced = true;
+        }
 
-        let window_height = dims.y;
-        let window_margin = window_height / 4;
-        let cursor_coord = buffer.cursor_coord();
-        let first_line_that_must_be_visible = cursor_coord.line.saturating_sub(window_margin);
-        let last_line_that_must_by_visible = min(
-            cursor_coord.line.saturating_add(window_margin),
-            buffer.lines(),
-        );
-        debug_assert!(first_line_that_must_be_visible <= last_line_that_must_by_visible);
+        let buffer_i = self.buffers.insert(BufferState {
+            buffer,
+            document,
+            matches: None,
+            conflicts: default(),
+            highlights: default(),
+            code_lenses: default(),
+            references: default(),
+            highlight_task: None,
+            gutter: default(),
+            on_type_formatting: None,
+            recent_edits: default(),
+            marks: default(),
+            written_version: 0,
+            disk_modified: None,
+            watch: None,
+            swapped: None,
+            swap_kept: false,
+            synced: None,
+            tree: default(),
+        });
+        self.note_disk_state(buffer_i);
+        self.focus_buffer(buffer_i);
+        self.notify_plugins(&plugin::Event::Open(path.to_owned()));
+        self.request_code_lenses();
+        self.sync_documents();
+        self.request_git_base(buffer_i);
+        if 0 < suspicious {
+            self.msg = Some(format!(
+                "{}: {} control or bidi chars, shown as ␛ or �",
+                path.display(),
+                suspicious
+            ));
+        } else if reduced {
+            self.msg = Some(format!(
+                "{} is large: no highlighting or language servers, :full to enable",
+                path.display()
+            ));
+        } else if self.cur_document().unwritable {
+            self.msg = Some(format!(
+                "{} is not writable: :w <path> to save elsewhere, or :sudo-w",
+                path.display()
+            ));
+        }
+    }
 
-        let mut line_offset = buffer.view_line_offset.borrow_mut();
+    /// Open the beginning of a file too large to read whole, as described
+    /// in `document::FilePreview`
+    fn open_file_preview(&mut self, path: &Path, len: u64) {
+        let id = self.next_preview_id;
+        self.next_preview_id += 1;
+        let request = PreviewRequest {
+            id,
+            path: path.to_owned(),
+            max_bytes: document::PREVIEW_BYTES,
+            cancel: default(),
+        };
+        if let Err(e) = (self.preview_handler)(request) {
+            self.msg = Some(format!("{}: {}", path.display(), e));
+            return;
+        }
+        self.recent_files
+            .note(&self.project_dir.join(path).to_string_lossy());
+        let preview = FilePreview {
+            id,
+            len,
+            loaded: false,
+        };
+        let buffer_i = self.open_special_buffer("", DocumentKind::Preview(preview));
+        let document = &mut self.buffers[buffer_i].document;
+        document.set_path(path.to_owned());
+        document.reduced = true;
+        document.read_only = true;
+        self.msg = Some(format!(
+            "{} is {} MB: only its beginning is shown, :full to read it all and edit it",
+            path.display(),
+            len / (1024 * 1024)
+        ));
+    }
 
-        if first_line_that_must_be_visible < *line_offset {
-            *line_offset = first_line_that_must_be_visible;
+    /// Read the whole file of the preview of `buffer_i`, to edit it
+    fn load_previewed(&mut self, buffer_i: usize) {
+        let path = match self.buffers[buffer_i].document.path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let (rope, format) = match self.read_file(&path) {
+            Ok(read) => read,
+            Err(e) => {
+                self.msg = Some(format!("{}: {}", path.display(), e));
+                return;
+            }
+        };
+        let reduced = self.large_file_limits.exceeded_by(&rope);
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state.buffer = Buffer::from_text(rope);
+        buffer_state.buffer.indent = Indent::detect(
+            buffer_state.document.language.as_deref(),
+            &buffer_state.buffer.text,
+        );
+        if reduced {
+            buffer_state.buffer.history.limit = Some(REDUCED_UNDO_POINTS);
         }
-        if line_offset.saturating_add(window_height) < last_line_that_must_by_visible {
-            *line_offset = last_line_that_must_by_visible.saturating_sub(window_height);
+        let document = &mut buffer_state.document;
+        document.kind = DocumentKind::Text;
+        document.format = format;
+        document.read_only = false;
+        document.reduced = true;
+        document.unwritable = !(self.writable_handler)(&path).unwrap_or(true);
+        buffer_state.written_version = buffer_state.buffer.version();
+        self.note_disk_state(buffer_i);
+        self.invalidation().invalidate_buffer(buffer_i);
+        self.notify_plugins(&plugin::Event::Open(path.clone()));
+        if reduced {
+            self.msg = Some(format!(
+                "{} read: no highlighting or language servers, :full to enable",
+                path.display()
+            ));
+        } else {
+            self.full_features();
         }
+    }
 
-        let start_line = min(*line_offset, buffer.lines().saturating_sub(window_height));
-        let end_line = min(start_line.saturating_add(window_height), buffer.lines());
+    /// Open a file and move the cursor to the line, if any
+    pub fn open_location(&mut self, location: &remote::Location) {
+        self.note_jump();
+        self.open_buffer(&location.path);
+        let opened = self
+            .cur_document_opt()
+            .is_some_and(|document| document.path.as_deref() == Some(location.path.as_path()));
+        if let (true, Some(line)) = (opened, location.line) {
+            self.cur_buffer_mut()
+                .apply_motion(Motion::Line(line.saturating_sub(1)), false);
+        }
+    }
 
-        drop(line_offset);
-        let line_nums_width = buffer.lines().to_string().len() + 1;
-        let (line_nums_rect, content_rect) = render
-            .dimensions_rect()
-            .split_verticaly_at(line_nums_width as isize);
-        self.render_line_nums(
-            &mut line_nums_rect.to_renderer(&mut render),
-            start_line,
-            end_line,
-        );
-        self.render_content(&mut content_rect.to_renderer(&mut render), start_line);
+    /// Attach a server to the buffer open on `path`
+    ///
+    /// Returns `false` if there's no such buffer, or it's opened with
+    /// reduced features.
+    pub fn attach_server(&mut self, path: &Path, info: lsp::ServerInfo) -> bool {
+        match self.buffer_i_by_path(path) {
+            Some(buffer_i) if !self.buffers[buffer_i].document.reduced => {
+                self.buffers[buffer_i].document.attach_server(info);
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn render_line_nums(&self, render: &mut dyn Renderer, start_line: usize, end_line: usize) {
-        let width = render.dimensions().x;
-        let style = render.color_map().line_num;
-        for line in start_line..end_line {
-            let line_str = format!("{} ", line.to_string());
-            render.print(
-                render::Coord {
-                    x: width - line_str.len(),
-                    y: line - start_line,
-                },
-                &line_str,
-                style,
-            );
+    pub fn detach_server(&mut self, path: &Path, name: &str) {
+        if let Some(buffer_i) = self.buffer_i_by_path(path) {
+            self.buffers[buffer_i].document.detach_server(name);
+            self.invalidation().invalidate_buffer(buffer_i);
         }
     }
 
-    pub fn render_content(&self, render: &mut dyn Renderer, start_line: usize) {
-        let buffer = self.cur_buffer();
-        let window_dims = render.dimensions();
-        let cursor_coord = buffer.cursor_coord();
+    /// Show `msg` in the message line, eg. what a server says
+    pub fn show_message(&mut self, msg: &str) {
+        self.msg = Some(msg.to_owned());
+    }
 
-        let color_map = { render.color_map().to_owned() };
+    /// Replace the diagnostics the server `name` reports for `path`
+    ///
+    /// Returns `false` if the server is not attached to a buffer of `path`.
+    pub fn publish_diagnostics(
+        &mut self,
+        path: &Path,
+        name: &str,
+        diagnostics: Vec<Diagnostic>,
+    ) -> bool {
+        let buffer_i = match self.buffer_i_by_path(path) {
+            Some(buffer_i) => buffer_i,
+            None => return false,
+        };
+        let published = self.buffers[buffer_i]
+            .document
+            .publish_diagnostics(name, diagnostics);
+        self.invalidation().invalidate_buffer(buffer_i);
+        published
+    }
 
-        let mut cur_ch_idx = position::Position {
-            line: start_line,
-            column: 0,
-        }
-        .to_idx(&buffer.text)
-        .0;
+    pub fn set_diagnostic_display(&mut self, display: DiagnosticDisplay) {
+        self.diagnostic_display = display;
+    }
 
-        let mut cur_visual_coord = render::Coord { x: 0, y: 0 };
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+    }
 
-        loop {
-            if window_dims.y <= cur_visual_coord.y {
-                break;
-            }
+    /// Draw only what a terminal with `capabilities` can show
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+        self.invalidation().invalidate_all();
+    }
 
-            if buffer.text.len_chars() <= cur_ch_idx {
-                break;
-            }
+    /// Wrap the lines of the current buffer wider than the view, or not
+    /// Indent the current buffer so from now on
+    pub fn set_indent(&mut self, indent: Indent) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        self.buffers[buffer_i].buffer.indent = indent;
+        self.invalidation().invalidate_buffer(buffer_i);
+    }
 
-            let ch = buffer.text.char(cur_ch_idx);
+    /// Keep the current buffer from being written, or not
+    pub fn set_read_only(&mut self, read_only: bool) {
+        if self.cur_buffer_i().is_some() {
+            self.cur_document_mut().read_only = read_only;
+        }
+    }
 
-            let visual_selection = buffer.idx_selection_type(Idx(cur_ch_idx));
+    /// Keep the current buffer from being edited, or not
+    pub fn set_modifiable(&mut self, modifiable: bool) {
+        if self.cur_buffer_i().is_some() {
+            self.cur_document_mut().unmodifiable = !modifiable;
+        }
+    }
 
-            let style = color_map.default;
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        self.buffers[buffer_i].document.wrap = wrap;
+        self.invalidation().invalidate_buffer(buffer_i);
+    }
 
-            let (visual_ch, visual_ch_width, special) = match ch {
-                '\n' => {
-                    if visual_selection != VisualSelection::None {
-                        (Some('↩'), 1, true) // alternatives: ⤶  🡿
-                    } else {
-                        (None, 0, true)
-                    }
-                }
-                '\t' => (
-                    Some('.'),
-                    buffer::distance_to_next_tabstop(
-                        Idx(cur_ch_idx).to_position(&buffer.text).column,
-                        4,
-                    ),
+    /// Undo the feature downgrade of a large file, or read the whole of
+    /// one previewed
+    pub fn full_features(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        if let DocumentKind::Preview(_) = self.buffers[buffer_i].document.kind {
+            self.load_previewed(buffer_i);
+            return;
+        }
+        let buffer_state = &mut self.buffers[buffer_i];
+        if !buffer_state.document.reduced {
+            self.msg = Some("all features already enabled".into());
+            return;
+        }
+        buffer_state.document.reduced = false;
+        buffer_state.buffer.history.limit = None;
+        self.request_highlights();
+        self.request_code_lenses();
+        self.sync_documents();
+        self.request_git_base(buffer_i);
+        self.invalidation().invalidate_all();
+    }
+
+    /// Open large files with fewer features from these sizes on
+    pub fn set_large_file_limits(&mut self, limits: LargeFileLimits) {
+        self.large_file_limits = limits;
+    }
+
+    /// Show the servers attached to the current buffer in a new buffer
+    pub fn lsp_info(&mut self) {
+        let servers = match self.cur_document_opt() {
+            Some(document) => &document.servers,
+            None => {
+                self.msg = Some("no buffer".into());
+                return;
+            }
+        };
+        let text = lsp::info_text(servers);
+        self.open_report(&text);
+    }
+
+    /// Check the environment, as described in `health`, and show the
+    /// report in a new buffer
+    pub fn health(&mut self) {
+        let languages = self
+            .buffers
+            .iter()
+            .filter_map(|(_, b)| b.document.language.as_deref())
+            .map(|language| (language, self.highlighter.is_known(language)));
+        let mut sections = vec![
+            Section {
+                name: "terminal".into(),
+                findings: health::terminal(self.capabilities),
+            },
+            Section {
+                name: "config".into(),
+                findings: health::config(&self.config_errors),
+            },
+            Section {
+                name: "syntax".into(),
+                findings: health::languages(languages),
+            },
+        ];
+        for (name, check) in &self.health_checks {
+            let findings = check();
+            match sections.iter_mut().find(|section| &section.name == name) {
+                Some(section) => section.findings.extend(findings),
+                None => sections.push(Section {
+                    name: name.clone(),
+                    findings,
+                }),
+            }
+        }
+        let text = health::report_text(&sections);
+        self.open_report(&text);
+    }
+
+    fn buffer_i_by_path(&self, path: &Path) -> Option<usize> {
+        self.buffers
+            .iter()
+            .find(|(_, b)| b.document.path.as_deref() == Some(path))
+            .map(|(i, _)| i)
+    }
+
+    /// Apply the changes of a workspace edit, in order
+    ///
+    /// The versions the edits were computed for are checked first, and
+    /// nothing is changed if a buffer has been edited since. Otherwise the
+    /// changes are applied until one fails; the ones before it stay.
+    pub fn apply_workspace_edit(
+        &mut self,
+        edit: &lsp::WorkspaceEdit,
+    ) -> Result<(), lsp::EditFailure> {
+        let checked = edit.changes.iter().enumerate().try_for_each(|(i, change)| {
+            let (path, version) = match change {
+                lsp::DocumentChange::Edit(lsp::TextDocumentEdit {
+                    path,
+                    version: Some(version),
+                    ..
+                }) => (path, *version),
+                _ => return Ok(()),
+            };
+            let reason = match self.buffer_i_by_path(path) {
+                Some(buffer_i) if self.buffers[buffer_i].buffer.version() == version => {
+                    return Ok(())
+                }
+                Some(_) => "edited since the changes were computed".to_owned(),
+                None => "not open anymore".to_owned(),
+            };
+            Err((i, reason))
+        });
+        let result = checked.and_then(|()| {
+            edit.changes.iter().enumerate().try_for_each(|(i, change)| {
+                self.apply_document_change(change)
+                    .map_err(|reason| (i, reason))
+            })
+        });
+        self.sync_documents();
+        match result {
+            Ok(()) => {
+                self.msg = Some(format!("applied {} changes", edit.changes.len()));
+                Ok(())
+            }
+            Err((failed_change, reason)) => {
+                self.msg = Some(format!(
+                    "applied {} of {} changes; {}: {}",
+                    failed_change,
+                    edit.changes.len(),
+                    edit.changes[failed_change].path().display(),
+                    reason
+                ));
+                Err(lsp::EditFailure {
+                    failed_change,
+                    reason,
+                })
+            }
+        }
+    }
+
+    /// Open buffers are edited in place, other files on disk. Buffers of
+    /// renamed and deleted files follow.
+    fn apply_document_change(&mut self, change: &lsp::DocumentChange) -> Result<(), String> {
+        let op = match change {
+            lsp::DocumentChange::Edit(edit) => {
+                if let Some(buffer_i) = self.buffer_i_by_path(&edit.path) {
+                    self.check_modifiable(buffer_i)?;
+                    let buffer = &mut self.buffers[buffer_i].buffer;
+                    let mut text = buffer.text.clone();
+                    lsp::apply_text_edits(&mut text, &edit.edits)?;
+                    buffer.replace_text(text);
+                    return Ok(());
+                }
+                let (mut text, format) = self.read_file(&edit.path).map_err(|e| e.to_string())?;
+                lsp::apply_text_edits(&mut text, &edit.edits)?;
+                return self
+                    .write_file(&edit.path, &text, format)
+                    .map_err(|e| e.to_string());
+            }
+            lsp::DocumentChange::Resource(op) => op,
+        };
+        (self.resource_handler)(op).map_err(|e| e.to_string())?;
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            let document = &mut buffer_state.document;
+            let path = match document.path {
+                Some(ref path) => path,
+                None => continue,
+            };
+            match op {
+                lsp::ResourceOp::Rename { old, new, .. } => {
+                    if let Ok(rest) = path.strip_prefix(old) {
+                        let path = if rest.as_os_str().is_empty() {
+                            new.clone()
+                        } else {
+                            new.join(rest)
+                        };
+                        document.set_path(path);
+                    }
+                }
+                lsp::ResourceOp::Delete {
+                    path: deleted,
+                    recursive,
+                    ..
+                } => {
+                    if path == deleted || (*recursive && path.starts_with(deleted)) {
+                        document.path = None;
+                    }
+                }
+                lsp::ResourceOp::Create { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the file of the current buffer, to the trash if so set; the
+    /// buffer stays, without a path
+    pub fn delete_file(&mut self) {
+        let path = match self.cur_document_opt().and_then(|d| d.path.clone()) {
+            Some(path) => path,
+            None => {
+                self.msg = Some("no file to delete".into());
+                return;
+            }
+        };
+        let trashed = if self.delete_to_trash {
+            Some((self.trash_handler)(&TrashOp::Trash(path.clone())))
+        } else {
+            None
+        };
+        let fallback = match trashed {
+            Some(Ok(trashed)) => {
+                for (_, buffer_state) in self.buffers.iter_mut() {
+                    if buffer_state.document.path.as_ref() == Some(&path) {
+                        buffer_state.document.path = None;
+                    }
+                }
+                self.msg = Some(format!(
+                    "moved {} to the trash, :undelete-file to bring it back",
+                    path.display()
+                ));
+                let buffer_i = self.cur_buffer_i().expect("cur_buffer set");
+                self.last_trashed = Some((path, trashed, buffer_i));
+                return;
+            }
+            Some(Err(e))
+                if e.kind() == io::ErrorKind::Unsupported
+                    || e.kind() == io::ErrorKind::NotConnected =>
+            {
+                Some(e)
+            }
+            Some(Err(e)) => {
+                self.msg = Some(format!("{}: not trashed: {}", path.display(), e));
+                return;
+            }
+            None => None,
+        };
+        let delete = lsp::DocumentChange::Resource(lsp::ResourceOp::Delete {
+            path: path.clone(),
+            recursive: false,
+            ignore_if_not_exists: false,
+        });
+        self.msg = Some(match (self.apply_document_change(&delete), fallback) {
+            (Ok(()), Some(e)) => format!("deleted {}, no trash: {}", path.display(), e),
+            (Ok(()), None) => format!("deleted {}", path.display()),
+            (Err(e), _) => format!("{}: {}", path.display(), e),
+        });
+    }
+
+    /// Bring the file last moved to the trash back, with the buffer it
+    /// was deleted from if it's still there
+    pub fn undelete_file(&mut self) {
+        let (path, trashed, buffer_i) = match self.last_trashed.take() {
+            Some(last) => last,
+            None => {
+                self.msg = Some("nothing trashed to bring back".into());
+                return;
+            }
+        };
+        let op = TrashOp::Restore {
+            trashed: trashed.clone(),
+            path: path.clone(),
+        };
+        if let Err(e) = (self.trash_handler)(&op) {
+            self.msg = Some(format!("{}: {}", path.display(), e));
+            self.last_trashed = Some((path, trashed, buffer_i));
+            return;
+        }
+        match self.buffers.get_mut(buffer_i) {
+            Some(buffer_state) if buffer_state.document.path.is_none() => {
+                buffer_state.document.set_path(path.clone());
+                self.focus_buffer(buffer_i);
+            }
+            _ => self.open_buffer(&path),
+        }
+        self.msg = Some(format!("brought {} back", path.display()));
+    }
+
+    /// Open a new buffer not backed by any file
+    pub fn open_scratch_buffer(&mut self, text: &str) {
+        self.open_special_buffer(text, DocumentKind::Text);
+    }
+
+    /// Open `text` in a new buffer that can't be edited, for reading
+    pub(crate) fn open_report(&mut self, text: &str) {
+        self.open_special_buffer(text, DocumentKind::Report);
+    }
+
+    fn open_special_buffer(&mut self, text: &str, kind: DocumentKind) -> usize {
+        let buffer_i = self.buffers.insert(BufferState {
+            buffer: Buffer::from_text(Rope::from_str(text)),
+            document: Document { kind, ..default() },
+            matches: None,
+            conflicts: default(),
+            highlights: default(),
+            code_lenses: default(),
+            references: default(),
+            highlight_task: None,
+            gutter: default(),
+            on_type_formatting: None,
+            recent_edits: default(),
+            marks: default(),
+            written_version: 0,
+            disk_modified: None,
+            watch: None,
+            swapped: None,
+            swap_kept: false,
+            synced: None,
+            tree: default(),
+        });
+        self.focus_buffer(buffer_i);
+        buffer_i
+    }
+
+    /// Search all the files for `pattern` in the background, the results
+    /// filling a quickfix buffer as they are found
+    pub fn grep(&mut self, pattern: &str) {
+        if let Err(e) = regex::Regex::new(pattern) {
+            self.msg = Some(format!("{}", e));
+            return;
+        }
+        self.cancel_grep();
+        let id = self.next_grep_id;
+        self.next_grep_id += 1;
+        let cancel = preview::CancelToken::default();
+        let request = GrepRequest {
+            id,
+            pattern: pattern.to_owned(),
+            cancel: cancel.clone(),
+        };
+        if let Err(e) = (self.project_search_handler)(request) {
+            self.msg = Some(format!("grep: {}", e));
+            return;
+        }
+        let buffer_i = self.open_special_buffer("", DocumentKind::Quickfix(default()));
+        self.buffers[buffer_i].document.unmodifiable = true;
+        self.pending_grep = Some(PendingGrep {
+            id,
+            pattern: pattern.to_owned(),
+            buffer_i,
+            cancel,
+            task: self.tasks.begin(&format!("grep {}", pattern)),
+        });
+    }
+
+    fn cancel_grep(&mut self) {
+        if let Some(pending) = self.pending_grep.take() {
+            pending.cancel.cancel();
+            self.tasks.end(pending.task);
+            if let Some(buffer_state) = self.buffers.get_mut(pending.buffer_i) {
+                buffer_state.document.unmodifiable = false;
+            }
+        }
+    }
+
+    /// Pass a batch of the matches found for a `GrepRequest`, `done` for
+    /// the last one
+    ///
+    /// Returns `false` if the request is no longer current and the
+    /// matches were dropped.
+    pub fn deliver_grep(&mut self, id: u64, entries: Vec<quickfix::Entry>, done: bool) -> bool {
+        let pending = match self.pending_grep {
+            Some(ref pending) if pending.id == id => pending,
+            _ => return false,
+        };
+        let buffer_i = pending.buffer_i;
+        let buffer_state = &mut self.buffers[buffer_i];
+        if let DocumentKind::Quickfix(ref mut quickfix) = buffer_state.document.kind {
+            let lines = quickfix.extend(entries);
+            let end = Idx(buffer_state.buffer.text.len_chars());
+            buffer_state.buffer.insert(end, &lines);
+        }
+        self.invalidation().invalidate_buffer(buffer_i);
+        if done {
+            let pending = self.pending_grep.take().expect("pending grep");
+            self.tasks.end(pending.task);
+            let buffer_state = &mut self.buffers[buffer_i];
+            buffer_state.document.unmodifiable = false;
+            self.msg = Some(match buffer_state.document.kind {
+                DocumentKind::Quickfix(ref quickfix) if quickfix.entries.is_empty() => {
+                    format!("no matches for {}", pending.pattern)
+                }
+                DocumentKind::Quickfix(ref quickfix) => {
+                    format!("{} matches for {}", quickfix.entries.len(), pending.pattern)
+                }
+                _ => return true,
+            });
+        }
+        true
+    }
+
+    /// Rename the whole-word occurrences of `old` in all the files to `new`
+    ///
+    /// Nothing is changed until the edits are confirmed in the preview.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        let pattern = format!(r"\b{}\b", regex::escape(old));
+        let re = regex::Regex::new(&pattern).expect("escaped pattern is valid");
+        let entries = match (self.grep_handler)(&pattern) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.msg = Some(format!("{}", e));
+                return;
+            }
+        };
+        let pending = PendingEdits {
+            files: quickfix::replace_edits(&entries, &re, new),
+            quickfix_buffer_i: None,
+            rename: None,
+        };
+        if pending.is_empty() {
+            self.msg = Some(format!("no occurrences of {}", old));
+            return;
+        }
+        self.open_special_buffer(&pending.to_diff(), DocumentKind::PendingEdits(pending));
+    }
+
+    /// Rename the file of the current buffer to `new`, updating the
+    /// references to it in all the files: its path without the extension,
+    /// and its stem as a whole word, as in `mod` and `import` lines
+    ///
+    /// Nothing is changed until the edits and the rename are confirmed in
+    /// the preview, where the references not to update can be dropped.
+    pub fn rename_file(&mut self, new: PathBuf) {
+        let old = match self.cur_document_opt().and_then(|d| d.path.clone()) {
+            Some(path) => path,
+            None => {
+                self.msg = Some("no file to rename".into());
+                return;
+            }
+        };
+        if old == new {
+            self.msg = Some(format!("already named {}", new.display()));
+            return;
+        }
+        let files = match old.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => match (self.grep_handler)(&format!(r"\b{}\b", regex::escape(stem))) {
+                Ok(entries) => quickfix::rename_file_edits(&entries, &old, &new),
+                Err(e) => {
+                    self.msg = Some(format!("{}", e));
+                    return;
+                }
+            },
+            None => BTreeMap::new(),
+        };
+        let pending = PendingEdits {
+            files,
+            quickfix_buffer_i: None,
+            rename: Some((old, new)),
+        };
+        self.open_special_buffer(&pending.to_diff(), DocumentKind::PendingEdits(pending));
+    }
+
+    /// Switch to a view of a given buffer, creating one if needed
+    ///
+    /// Views shown in other windows are left to them: a new view starts
+    /// where the first of those is.
+    pub fn focus_buffer(&mut self, buffer_i: usize) {
+        let cur_view_i = self.cur_view_i;
+        let layout = &self.layout;
+        let tabs = &self.tabs;
+        let mut views = self
+            .views
+            .iter()
+            .filter(|(_, view)| view.buffer_i == buffer_i)
+            .map(|(i, _)| i);
+        let mut shown = None;
+        let found = views.find(|&i| {
+            let in_window = Some(i) != cur_view_i
+                && (layout.as_ref().is_some_and(|l| l.contains(i)) || tabs.shown_elsewhere(i));
+            shown = shown.or(Some(i).filter(|_| in_window));
+            !in_window
+        });
+        let view_i = match (found, shown) {
+            (Some(view_i), _) => view_i,
+            (None, Some(shown)) => self.clone_view(shown),
+            (None, None) => self.views.insert(ViewState::new(buffer_i)),
+        };
+        self.set_cur_view(Some(view_i));
+    }
+
+    /// Click, drag and scroll in the windows
+    ///
+    /// Pressing the left button moves the cursor there, focusing the
+    /// window, and dragging selects from there; the wheel scrolls the
+    /// focused window. Only in normal and insert mode: the others take
+    /// keys for a prompt or a list.
+    pub fn handle_mouse(&mut self, mouse: Mouse) {
+        if !matches!(self.mode_name(), "normal" | "insert") {
+            return;
+        }
+        match mouse {
+            Mouse::Press(at) => {
+                let (view_i, idx) = match self.window_at(at) {
+                    Some(view_i) => match self.idx_at(view_i, at) {
+                        Some(idx) => (view_i, idx),
+                        None => return,
+                    },
+                    None => return,
+                };
+                self.cur_view_i = Some(view_i);
+                self.completion = None;
+                self.cur_buffer_mut().select_range(idx.0..idx.0);
+                self.mouse_anchor = Some((view_i, idx));
+                self.move_followers(view_i);
+            }
+            Mouse::Drag(at) => {
+                let (view_i, anchor) = match self.mouse_anchor {
+                    Some((view_i, anchor)) if Some(view_i) == self.cur_view_i => (view_i, anchor),
+                    _ => return,
+                };
+                if let Some(idx) = self.idx_at(view_i, at) {
+                    self.cur_buffer_mut().select_range(anchor.0..idx.0);
+                    self.move_followers(view_i);
+                }
+            }
+            Mouse::Release(_) => self.mouse_anchor = None,
+            Mouse::Scroll { up, .. } => {
+                if self.cur_view_opt().is_some() {
+                    let lines = if up { -WHEEL_LINES } else { WHEEL_LINES };
+                    self.cur_buffer_mut().scroll(lines);
+                }
+            }
+        }
+    }
+
+    /// The window of the current tab drawn at `coord` of the screen
+    fn window_at(&self, coord: Coord) -> Option<usize> {
+        let windows = self.layout.as_ref().map(Layout::windows)?;
+        windows.into_iter().find(|&view_i| {
+            let drawn = self.views[view_i].drawn.borrow();
+            drawn
+                .as_ref()
+                .is_some_and(|drawn| drawn.rect.contains(coord))
+        })
+    }
+
+    /// The char of the text of a view at `coord` of the screen, or the
+    /// nearest one
+    fn idx_at(&self, view_i: usize, coord: Coord) -> Option<Idx> {
+        let view = &self.views[view_i];
+        let (line, column) = view.drawn.borrow().as_ref()?.position_at(coord)?;
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        Some(convert::cell_column_to_idx(
+            &buffer.text,
+            line,
+            column,
+            buffer.indent.tab_width,
+        ))
+    }
+
+    /// The cell of the screen the char `idx` of the current view was last
+    /// drawn at, if it was
+    pub(crate) fn cur_coord_of(&self, idx: usize) -> Option<Coord> {
+        let view = self.cur_view_opt()?;
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        let line = buffer.text.char_to_line(idx);
+        let column = convert::cell_column(&buffer.text, Idx(idx), buffer.indent.tab_width);
+        view.drawn.borrow().as_ref()?.coord_of(line, column)
+    }
+
+    /// Where `pair` is in the current window, nearest to the cursor first,
+    /// as described in `jump`
+    pub(crate) fn jump_targets(&self, pair: [char; 2]) -> Vec<usize> {
+        let view = match self.cur_view_opt() {
+            Some(view) => view,
+            None => return vec![],
+        };
+        let lines = match view.drawn.borrow().as_ref() {
+            Some(drawn) => match (drawn.rows.first(), drawn.rows.last()) {
+                (Some(first), Some(last)) => first.line()..last.line() + 1,
+                _ => return vec![],
+            },
+            None => return vec![],
+        };
+        let text = &self.buffers[view.buffer_i].buffer.text;
+        let cursor = view.selection.selections[view.selection.primary].cursor.0;
+        jump::targets(text, lines, pair, cursor)
+            .into_iter()
+            .filter(|&idx| self.cur_coord_of(idx).is_some())
+            .collect()
+    }
+
+    /// Select `idx` alone; with `extend`, from the anchor of the primary
+    /// selection
+    pub(crate) fn jump_to(&mut self, idx: usize, extend: bool) {
+        let mut buffer = self.cur_buffer_mut();
+        let anchor = if extend {
+            let selection = &buffer.view.selection;
+            selection.selections[selection.primary].anchor.0
+        } else {
+            idx
+        };
+        buffer.select_range(anchor..idx);
+    }
+
+    /// Note where the cursor of the focused window is, in its jump list,
+    /// before a large jump from there
+    pub(crate) fn note_jump(&mut self) {
+        let view_i = match self.cur_view_i {
+            Some(view_i) => view_i,
+            None => return,
+        };
+        let view = &mut self.views[view_i];
+        let idx = view.selection.selections[view.selection.primary].cursor.0;
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        view.jumps.push(view.buffer_i, idx, buffer);
+    }
+
+    /// Go back to where the focused window jumped from, or forward again,
+    /// `n` times
+    pub(crate) fn jump_back(&mut self, n: usize, forward: bool) {
+        let view_i = match self.cur_view_i {
+            Some(view_i) => view_i,
+            None => return,
+        };
+        let view = &mut self.views[view_i];
+        let idx = view.selection.selections[view.selection.primary].cursor.0;
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        let mut place = None;
+        for _ in 0..n {
+            let next = if forward {
+                view.jumps.forward()
+            } else {
+                view.jumps.back(view.buffer_i, idx, buffer)
+            };
+            match next {
+                Some(next) => place = Some(next),
+                None => break,
+            }
+        }
+        let (buffer_i, idx) = match place {
+            Some(place) => place,
+            None => {
+                self.msg = Some(if forward {
+                    "no newer jump".into()
+                } else {
+                    "no older jump".into()
+                });
+                return;
+            }
+        };
+        if self.cur_buffer_i() != Some(buffer_i) {
+            self.focus_buffer(buffer_i);
+        }
+        let idx = idx.min(self.cur_buffer().text.len_chars());
+        self.jump_to(idx, false);
+    }
+
+    /// Name the char of the primary cursor `name`, in its buffer
+    pub(crate) fn set_mark(&mut self, name: char) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let selection = &self.cur_view().selection;
+        let idx = selection.selections[selection.primary].cursor.0;
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state.marks.set(name, idx, &buffer_state.buffer);
+        self.msg = Some(format!("marked '{}", name));
+    }
+
+    /// Go to the mark `name` of the buffer, as a jump
+    pub(crate) fn goto_mark(&mut self, name: char) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        match self.buffers[buffer_i].marks.get(name) {
+            Some(idx) => {
+                self.note_jump();
+                self.jump_to(idx, false);
+            }
+            None => self.msg = Some(format!("no mark '{}", name)),
+        }
+    }
+
+    /// Show `view_i` in the focused window, for it to be the current view
+    ///
+    /// The jump list is the window's, and goes along.
+    fn set_cur_view(&mut self, view_i: Option<usize>) {
+        match (self.cur_view_i, view_i, self.layout.as_mut()) {
+            (_, None, _) => self.layout = None,
+            (Some(old), Some(new), Some(layout)) => {
+                layout.replace(old, new);
+                if old != new {
+                    if let Some(jumps) = self.views.get(old).map(|view| view.jumps.clone()) {
+                        self.views[new].jumps = jumps;
+                    }
+                }
+            }
+            (_, Some(new), _) => self.layout = Some(Layout::Window(new)),
+        }
+        self.cur_view_i = view_i;
+    }
+
+    /// A new view of the buffer of `view_i`, with the same selections and
+    /// scroll
+    fn clone_view(&mut self, view_i: usize) -> usize {
+        let view = &self.views[view_i];
+        let new = ViewState {
+            selection: view.selection.clone(),
+            line_offset: view.line_offset.clone(),
+            column_offset: view.column_offset.clone(),
+            colors: view.colors.clone(),
+            folds: view.folds.clone(),
+            jumps: view.jumps.clone(),
+            ..ViewState::new(view.buffer_i)
+        };
+        self.views.insert(new)
+    }
+
+    /// Split the focused window in two, the new one above or to the left
+    /// of it, showing the same, and focused
+    pub fn split_window(&mut self, direction: Direction) {
+        let view_i = match self.cur_view_i {
+            Some(view_i) => view_i,
+            None => {
+                self.msg = Some("no buffer to split".into());
+                return;
+            }
+        };
+        let new = self.clone_view(view_i);
+        if let Some(layout) = self.layout.as_mut() {
+            layout.split(view_i, new, direction);
+        }
+        self.cur_view_i = Some(new);
+        self.invalidation().invalidate_all();
+    }
+
+    /// Close the focused window, focusing the one taking its room
+    pub fn close_window(&mut self) {
+        let closed = self
+            .cur_view_i
+            .zip(self.layout.as_mut())
+            .and_then(|(view_i, layout)| layout.close(view_i));
+        match closed {
+            Some(view_i) => {
+                self.cur_view_i = Some(view_i);
+                self.invalidation().invalidate_all();
+            }
+            None => self.msg = Some("can't close the last window".into()),
+        }
+    }
+
+    /// Close all the windows but the focused one
+    pub fn only_window(&mut self) {
+        if let Some(view_i) = self.cur_view_i {
+            self.layout = Some(Layout::Window(view_i));
+            self.invalidation().invalidate_all();
+        }
+    }
+
+    /// Focus the window `step` windows after the focused one, from the top
+    /// left, going around
+    pub fn focus_window(&mut self, step: isize) {
+        let windows = self
+            .layout
+            .as_ref()
+            .map(Layout::windows)
+            .unwrap_or_default();
+        let cur = windows.iter().position(|&i| Some(i) == self.cur_view_i);
+        if let Some(cur) = cur {
+            let next = (cur as isize + step).rem_euclid(windows.len() as isize);
+            self.cur_view_i = Some(windows[next as usize]);
+        }
+    }
+
+    /// Make the focused window take `delta` percent more of the room, as
+    /// described in `layout`
+    pub fn resize_window(&mut self, direction: Direction, delta: isize) {
+        let resized = self
+            .cur_view_i
+            .zip(self.layout.as_mut())
+            .is_some_and(|(view_i, layout)| layout.resize(view_i, direction, delta));
+        if resized {
+            self.invalidation().invalidate_all();
+        } else {
+            self.msg = Some(match direction {
+                Direction::Horizontal => "no window above or below".into(),
+                Direction::Vertical => "no window to the side".into(),
+            });
+        }
+    }
+
+    /// Open a tab after the current one, with a window showing what the
+    /// focused one does, and switch to it
+    pub fn new_tab(&mut self) {
+        let view_i = match self.cur_view_i {
+            Some(view_i) => view_i,
+            None => {
+                self.msg = Some("no buffer to open a tab with".into());
+                return;
+            }
+        };
+        let new = self.clone_view(view_i);
+        self.tabs.open(new, &mut self.layout, &mut self.cur_view_i);
+        self.invalidation().invalidate_all();
+    }
+
+    /// Close the current tab, switching to the next one
+    pub fn close_tab(&mut self) {
+        if self.tabs.close(&mut self.layout, &mut self.cur_view_i) {
+            self.invalidation().invalidate_all();
+        } else {
+            self.msg = Some("can't close the last tab".into());
+        }
+    }
+
+    /// Switch to the tab `step` tabs after the current one, going around
+    pub fn focus_tab(&mut self, step: isize) {
+        let len = self.tabs.len() as isize;
+        let next = (self.tabs.cur() as isize + step).rem_euclid(len) as usize;
+        self.goto_tab(next);
+    }
+
+    /// Switch to the tab of index `i`, from 0
+    pub fn goto_tab(&mut self, i: usize) {
+        if self.tabs.len() <= i {
+            self.msg = Some(format!("no tab {}", i + 1));
+            return;
+        }
+        if i != self.tabs.cur() {
+            self.tabs.switch(i, &mut self.layout, &mut self.cur_view_i);
+            self.invalidation().invalidate_all();
+        }
+    }
+
+    /// Name the current tab, or with `None` let it go by its buffer
+    pub fn rename_tab(&mut self, name: Option<String>) {
+        self.tabs.set_name(name);
+    }
+
+    pub fn write_buffer(&mut self, path: Option<PathBuf>) {
+        self.write_buffer_impl(path, false)
+    }
+
+    /// Write the current buffer like `write_buffer`, even over changes
+    /// made to its file elsewhere since it was read
+    pub fn write_buffer_forced(&mut self, path: Option<PathBuf>) {
+        self.write_buffer_impl(path, true)
+    }
+
+    fn write_buffer_impl(&mut self, path: Option<PathBuf>, force: bool) {
+        if path.is_none() {
+            match self.cur_document_opt().map(|d| &d.kind) {
+                Some(DocumentKind::Quickfix(_)) => return self.write_quickfix(),
+                Some(DocumentKind::PendingEdits(_)) => return self.apply_pending_edits(),
+                Some(DocumentKind::GitCommit(_)) => return self.git_commit(),
+                _ => {}
+            }
+        }
+        if path.is_none() && self.cur_document_opt().is_some_and(|d| d.read_only) {
+            self.msg = Some("buffer is read-only: :set readonly=false to write it".into());
+            return;
+        }
+        let unwritable = self
+            .cur_document_opt()
+            .filter(|document| document.unwritable)
+            .and_then(|document| document.path.clone());
+        if let (None, Some(path)) = (&path, unwritable) {
+            return self.offer_write_elsewhere(&path);
+        }
+        if let Some(path) = path.or_else(|| self.cur_document().path.clone()) {
+            if !force && self.changed_on_disk(&path) {
+                self.msg = Some(format!(
+                    "{} changed on disk since read: :reload, or :w! to write over it",
+                    path.display()
+                ));
+                return;
+            }
+            match self.try_write_buffer(&path) {
+                Ok(()) => {
+                    let document = self.cur_document_mut();
+                    document.set_path(path);
+                    document.unwritable = false;
+                    self.note_written();
+                    self.write_undo_file();
+                    self.request_code_lenses();
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    if self.cur_document().path.as_ref() == Some(&path) {
+                        self.cur_document_mut().unwritable = true;
+                    }
+                    self.offer_write_elsewhere(&path);
+                }
+                Err(e) => {
+                    self.msg = Some(format!("{}", e));
+                }
+            }
+        } else {
+            self.msg = Some("No path given".to_string());
+        }
+    }
+
+    fn note_written(&mut self) {
+        let buffer_i = self.cur_buffer_i().expect("cur_buffer set");
+        let buffer = &mut self.buffers[buffer_i];
+        buffer.written_version = buffer.buffer.version();
+        if let Some(path) = buffer.document.path.clone() {
+            self.notify_plugins(&plugin::Event::Save(path));
+        }
+        self.note_disk_state(buffer_i);
+        // `HEAD` may have moved meanwhile
+        self.request_git_base(buffer_i);
+        // keep the counts up to date once they are shown
+        if self.todos.is_some() {
+            let _ = self.request_todos();
+        }
+    }
+
+    /// Keep when the file of `buffer_i` was last modified, and watch it for
+    /// changes made elsewhere; it's not watched if it can't be
+    fn note_disk_state(&mut self, buffer_i: usize) {
+        let path = match self.buffers[buffer_i].document.path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let modified = (self.stat_handler)(&path)
+            .ok()
+            .and_then(|metadata| metadata.modified);
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state.disk_modified = modified;
+        if buffer_state.watch.as_ref().map(|(watched, _)| watched) != Some(&path) {
+            buffer_state.watch = (self.watch_handler)(&path)
+                .ok()
+                .map(|watch| (path, Arc::new(watch)));
+        }
+    }
+
+    /// Was the file of the current buffer, at `path`, modified since it
+    /// was last read or written, by someone else
+    fn changed_on_disk(&self, path: &Path) -> bool {
+        let buffer_state = &self.buffers[self.cur_buffer_i().expect("cur_buffer set")];
+        if buffer_state.document.path.as_deref() != Some(path) {
+            return false;
+        }
+        let modified = (self.stat_handler)(path)
+            .ok()
+            .and_then(|metadata| metadata.modified);
+        match (buffer_state.disk_modified, modified) {
+            (Some(known), Some(modified)) => known != modified,
+            _ => false,
+        }
+    }
+
+    /// Tell that the file at `path` changed on disk, as its watch does
+    ///
+    /// A buffer of it without changes is read again, unless `auto-reload`
+    /// is off; otherwise a message points to `:reload`. The writes of the
+    /// editor itself are told apart by the modification time they left.
+    pub fn file_changed(&mut self, path: &Path) {
+        let buffer_i = match self.buffer_i_by_path(path) {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let modified = match (self.stat_handler)(path) {
+            Ok(metadata) => metadata.modified,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.msg = Some(format!("{} was deleted on disk", path.display()));
+                return;
+            }
+            Err(_) => return,
+        };
+        let buffer_state = &self.buffers[buffer_i];
+        if modified.is_some() && modified == buffer_state.disk_modified {
+            return;
+        }
+        let changed = buffer_state.written_version != buffer_state.buffer.version();
+        if self.auto_reload && !changed {
+            match self.reload_buffer_i(buffer_i) {
+                Ok(0) => {}
+                Ok(_) => self.msg = Some(format!("{} changed on disk: reloaded", path.display())),
+                Err(e) => self.msg = Some(e),
+            }
+        } else {
+            self.msg = Some(format!(
+                "{} changed on disk: :reload to read it again{}",
+                path.display(),
+                if changed {
+                    ", dropping the changes"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+
+    /// Read the files changed on disk again, if the buffers of them have
+    /// no changes
+    pub fn set_auto_reload(&mut self, auto_reload: bool) {
+        self.auto_reload = auto_reload;
+    }
+
+    /// Keep the undo history of the current buffer with the text written,
+    /// for the next session
+    fn write_undo_file(&mut self) {
+        self.commit_undo_point();
+        let buffer_state = &self.buffers[self.cur_buffer_i().expect("cur_buffer set")];
+        let path = match buffer_state.document.path {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+        let text = buffer_state.buffer.history.to_text();
+        if let Err(e) = (self.undo_file_handler)(&UndoFileOp::Write { path, text }) {
+            self.msg = Some(format!("undo history not kept: {}", e));
+        }
+    }
+
+    /// Has the current buffer changed since it was read or written
+    pub fn is_modified(&self) -> bool {
+        self.cur_buffer_i().is_some_and(|buffer_i| {
+            let buffer = &self.buffers[buffer_i];
+            buffer.written_version != buffer.buffer.version()
+        })
+    }
+
+    /// Prompt for another path to write the current buffer to
+    fn offer_write_elsewhere(&mut self, path: &Path) {
+        self.set_mode(mode::Command::with_text(
+            &format!("w {}", path.display()),
+            "  not writable: change the path, or :sudo-w",
+        ));
+    }
+
+    /// Write the current buffer to its file through the elevated write
+    /// handler, for files that can't be written otherwise
+    pub fn write_buffer_elevated(&mut self) {
+        let path = match self.cur_document_opt() {
+            Some(document) if document.read_only => {
+                self.msg = Some("buffer is read-only: :set readonly=false to write it".into());
+                return;
+            }
+            Some(Document {
+                path: Some(path), ..
+            }) => path.clone(),
+            _ => {
+                self.msg = Some("No path given".to_string());
+                return;
+            }
+        };
+        let written = encoding::encode(&self.cur_buffer().text, self.cur_document().format)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            .and_then(|bytes| (self.elevated_write_handler)(&path, &bytes));
+        match written {
+            Ok(()) => {
+                self.note_written();
+                self.write_undo_file();
+                self.msg = Some(format!("wrote {} with elevated rights", path.display()));
+                self.request_code_lenses();
+            }
+            Err(e) => self.msg = Some(format!("{}", e)),
+        }
+    }
+
+    /// Show the changes made in the current quickfix buffer for confirmation
+    fn write_quickfix(&mut self) {
+        let quickfix_buffer_i = self.cur_buffer_i().expect("cur_buffer set");
+        let files = match self.buffers[quickfix_buffer_i].document.kind {
+            DocumentKind::Quickfix(ref quickfix) => {
+                quickfix.compute_edits(&self.buffers[quickfix_buffer_i].buffer.text)
+            }
+            _ => return,
+        };
+        let pending = match files {
+            Ok(files) => PendingEdits {
+                files,
+                quickfix_buffer_i: Some(quickfix_buffer_i),
+                rename: None,
+            },
+            Err(e) => {
+                self.msg = Some(e);
+                return;
+            }
+        };
+        if pending.is_empty() {
+            self.msg = Some("no changes".into());
+            return;
+        }
+        self.open_special_buffer(&pending.to_diff(), DocumentKind::PendingEdits(pending));
+    }
+
+    /// Apply the edits from the current confirmation buffer and close it
+    fn apply_pending_edits(&mut self) {
+        let mut pending = match self.cur_document().kind {
+            DocumentKind::PendingEdits(ref pending) => pending.clone(),
+            _ => return,
+        };
+        pending.retain_listed(&self.cur_buffer().text);
+
+        let mut applied_files = 0;
+        let mut errors = vec![];
+        for (path, edits) in &pending.files {
+            match self.apply_file_edits(path, edits) {
+                Ok(()) => {
+                    applied_files += 1;
+                    if let Some(BufferState {
+                        document:
+                            Document {
+                                kind: DocumentKind::Quickfix(ref mut quickfix),
+                                ..
+                            },
+                        ..
+                    }) = pending
+                        .quickfix_buffer_i
+                        .and_then(|i| self.buffers.get_mut(i))
+                    {
+                        quickfix.update_applied(path, edits);
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        let mut renamed = String::new();
+        if let Some((old, new)) = pending.rename {
+            let rename = lsp::DocumentChange::Resource(lsp::ResourceOp::Rename {
+                old: old.clone(),
+                new: new.clone(),
+                options: lsp::FileOptions::default(),
+            });
+            match self.apply_document_change(&rename) {
+                Ok(()) => renamed = format!(", renamed {} to {}", old.display(), new.display()),
+                Err(e) => errors.push(format!("{}: {}", old.display(), e)),
+            }
+        }
+
+        self.delete_buffer();
+        if let Some(i) = pending
+            .quickfix_buffer_i
+            .filter(|&i| self.buffers.contains(i))
+        {
+            self.focus_buffer(i);
+        }
+        self.msg = Some(if errors.is_empty() {
+            format!("applied changes to {} files{}", applied_files, renamed)
+        } else {
+            format!(
+                "applied changes to {} files{}; failed: {}",
+                applied_files,
+                renamed,
+                errors.join(", ")
+            )
+        });
+    }
+
+    /// Files already open are changed in their buffers, others on disk,
+    /// after saving their original content next to them with a `~` suffix
+    fn apply_file_edits(
+        &mut self,
+        path: &Path,
+        edits: &[quickfix::LineEdit],
+    ) -> Result<(), String> {
+        if let Some(buffer_i) = self.buffer_i_by_path(path) {
+            self.check_modifiable(buffer_i)?;
+            let buffer = &mut self.buffers[buffer_i].buffer;
+            let mut text = buffer.text.clone();
+            quickfix::apply_edits(&mut text, edits)?;
+            buffer.replace_text(text);
+            return Ok(());
+        }
+
+        let orig = (self.read_handler)(path).map_err(|e| e.to_string())?;
+        let mut backup = path.as_os_str().to_owned();
+        backup.push("~");
+        let (mut text, format) = encoding::decode(orig.clone());
+        quickfix::apply_edits(&mut text, edits)?;
+        (self.write_handler)(Path::new(&backup), &orig)
+            .map_err(|e| format!("backup failed: {}", e))?;
+        self.write_file(path, &text, format)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Files with changes, or `None` if git failed
+    pub fn git_status(&mut self) -> Option<Vec<FileStatus>> {
+        match (self.git_handler)(&GitRequest::Status) {
+            Ok(out) => Some(git::parse_status(&out)),
+            Err(e) => {
+                self.msg = Some(format!("git: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Run a git command that changes something, reporting failures
+    pub(crate) fn git_run(&mut self, request: &GitRequest) -> bool {
+        match (self.git_handler)(request) {
+            Ok(_) => true,
+            Err(e) => {
+                self.msg = Some(format!("git: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Diff to preview, or the error if git failed
+    pub(crate) fn git_diff(&self, staged: bool, path: Option<PathBuf>) -> String {
+        (self.git_handler)(&GitRequest::Diff { staged, path }).unwrap_or_else(|e| e.to_string())
+    }
+
+    /// Open a buffer to write the message of a new commit, or to edit the last one
+    pub fn git_commit_buffer(&mut self, amend: bool) {
+        let handler = self.git_handler.clone();
+        let res = handler(&GitRequest::Diff {
+            staged: true,
+            path: None,
+        })
+        .and_then(|diff| {
+            let message = if amend {
+                handler(&GitRequest::LastCommitMessage)?
+            } else {
+                String::new()
+            };
+            Ok((diff, message))
+        });
+        let (diff, message) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                self.msg = Some(format!("git: {}", e));
+                return;
+            }
+        };
+        if diff.trim().is_empty() && !amend {
+            self.msg = Some("nothing staged to commit".into());
+            return;
+        }
+        let commit = CommitMessage { amend, diff };
+        self.open_special_buffer(&commit.template(&message), DocumentKind::GitCommit(commit));
+    }
+
+    /// Commit with the message from the current buffer and close it
+    fn git_commit(&mut self) {
+        let amend = match self.cur_document().kind {
+            DocumentKind::GitCommit(ref commit) => commit.amend,
+            _ => return,
+        };
+        let message = git::clean_message(&self.cur_buffer().text.to_string());
+        if message.is_empty() {
+            self.msg = Some("empty commit message".into());
+            return;
+        }
+        if self.git_run(&GitRequest::Commit { message, amend }) {
+            self.delete_buffer();
+            let buffers: Vec<usize> = self.buffers.iter().map(|(i, _)| i).collect();
+            for buffer_i in buffers {
+                self.request_git_base(buffer_i);
+            }
+            self.msg = Some(if amend { "amended" } else { "committed" }.into());
+        }
+    }
+
+    /// Commits that changed `path`, newest first, or `None` if git failed
+    pub fn git_log(&mut self, path: &Path) -> Option<Vec<Revision>> {
+        match (self.git_handler)(&GitRequest::Log(path.to_owned())) {
+            Ok(out) => Some(git::parse_log(&out)),
+            Err(e) => {
+                self.msg = Some(format!("git: {}", e));
+                None
+            }
+        }
+    }
+
+    pub(crate) fn git_show(&self, rev: &str, path: &Path) -> io::Result<String> {
+        (self.git_handler)(&GitRequest::Show {
+            rev: rev.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+
+    /// Open the content of a file at an old revision in a read-only buffer
+    pub(crate) fn open_file_revision(&mut self, text: &str, revision: FileRevision) {
+        self.open_special_buffer(text, DocumentKind::FileRevision(revision));
+    }
+
+    /// Replace the lines of the working copy corresponding to the selected
+    /// lines of the current old revision buffer with them
+    pub fn restore_from_revision(&mut self) {
+        let revision = match self.cur_document_opt().map(|d| &d.kind) {
+            Some(DocumentKind::FileRevision(revision)) => revision.clone(),
+            _ => {
+                self.msg = Some("not a file revision buffer".into());
+                return;
+            }
+        };
+        if !self.buffers.contains(revision.source_buffer_i) {
+            self.msg = Some("the working copy buffer is gone".into());
+            return;
+        }
+        if let Err(e) = self.check_modifiable(revision.source_buffer_i) {
+            self.msg = Some(e);
+            return;
+        }
+        let old_text = self.cur_buffer().text.clone();
+        let selected = self.cur_view().selection.to_lines(&old_text);
+        let (first, last) = match (selected.iter().next(), selected.iter().next_back()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return,
+        };
+        let old_lines = diff::rope_lines(&old_text);
+        let last = last.min(old_lines.len().saturating_sub(1));
+        let restored: String = old_lines[first..=last]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        self.focus_buffer(revision.source_buffer_i);
+        let new_text = self.cur_buffer().text.clone();
+        let hunks = diff::diff_lines(&old_lines, &diff::rope_lines(&new_text));
+        let target = diff::map_range(&hunks, first..last + 1);
+        let line_start = |line: usize| {
+            if line < new_text.len_lines() {
+                new_text.line_to_char(line)
+            } else {
+                new_text.len_chars()
+            }
+        };
+        let range = line_start(target.start)..line_start(target.end);
+        self.commit_undo_point();
+        self.cur_buffer_mut().replace_range(range, &restored);
+        self.commit_undo_point();
+        self.msg = Some(format!(
+            "restored {} lines from {}",
+            last + 1 - first,
+            revision.revision.short
+        ));
+    }
+
+    /// Read the file of the current buffer again, after it changed on
+    /// disk
+    ///
+    /// What changed is applied as edits, in a single undo point, so that
+    /// it can be undone, and the selections of every view of the buffer
+    /// move along with the text around them rather than go back to the
+    /// start. Changes not written yet are dropped like the rest, but can
+    /// be undone back to as well.
+    pub fn reload_buffer(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        self.msg = Some(match self.reload_buffer_i(buffer_i) {
+            Ok(0) => "unchanged on disk".to_owned(),
+            Ok(1) => "reloaded, 1 change".to_owned(),
+            Ok(n) => format!("reloaded, {} changes", n),
+            Err(e) => e,
+        });
+    }
+
+    /// Read the file of `buffer_i` again, as `reload_buffer` does, and
+    /// tell how many changes it made
+    fn reload_buffer_i(&mut self, buffer_i: usize) -> Result<usize, String> {
+        let path = match self.buffers[buffer_i].document.path.clone() {
+            Some(path) => path,
+            None => return Err("no file to reload".into()),
+        };
+        if let DocumentKind::Preview(_) = self.buffers[buffer_i].document.kind {
+            return Err(format!("{}: :full to read it all", path.display()));
+        }
+        let (text, format) = self
+            .read_file(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let changes = self.replace_text(buffer_i, &text);
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state.document.format = format;
+        buffer_state.written_version = buffer_state.buffer.version();
+        self.note_disk_state(buffer_i);
+        Ok(changes)
+    }
+
+    /// Change the text of `buffer_i` to `text`, in a single undo point,
+    /// with the selections following the text around them; tells how many
+    /// changes it made
+    fn replace_text(&mut self, buffer_i: usize, text: &Rope) -> usize {
+        let edits = diff::edits(&self.buffers[buffer_i].buffer.text, text);
+        if !edits.is_empty() {
+            self.commit_undo_point_of(buffer_i);
+            let buffer = &mut self.buffers[buffer_i].buffer;
+            for (range, inserted) in edits.iter().rev() {
+                let start = Idx(range.start);
+                for (_, view) in self.views.iter_mut() {
+                    if view.buffer_i == buffer_i {
+                        view.selection
+                            .fix_on_delete(start, range.len(), &buffer.text);
+                        view.selection
+                            .fix_on_insert(start, inserted.chars().count());
+                    }
+                }
+                buffer.remove(range.clone());
+                buffer.insert(start, inserted);
+            }
+            self.commit_undo_point_of(buffer_i);
+            self.invalidation().invalidate_buffer(buffer_i);
+        }
+        edits.len()
+    }
+
+    /// Copy the changes of the buffers to their swap files, without
+    /// waiting for it to be due, as when the editor is idle
+    pub fn write_swaps(&mut self) {
+        self.copy_swaps(true)
+    }
+
+    /// Copy the buffers with changes to their swap files, those whose copy
+    /// is due or `all` of them, and drop the swap files of those without
+    /// changes any more
+    fn copy_swaps(&mut self, all: bool) {
+        let now = self.now;
+        let mut failed = None;
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            let path = match (&buffer_state.document.kind, &buffer_state.document.path) {
+                (DocumentKind::Text, Some(path)) => path,
+                _ => continue,
+            };
+            let version = buffer_state.buffer.version();
+            if buffer_state.written_version == version {
+                if buffer_state.swap_kept {
+                    let _ = (self.swap_handler)(&SwapOp::Remove(path.clone()));
+                    buffer_state.swap_kept = false;
+                }
+                buffer_state.swapped = Some((version, now));
+                continue;
+            }
+            let written = buffer_state.written_version;
+            let (swapped, at) = *buffer_state.swapped.get_or_insert((written, now));
+            let since = now.duration_since(at).unwrap_or_default();
+            let edits = version.wrapping_sub(swapped);
+            if !(recover::is_due(edits, since) || all && 0 < edits) {
+                continue;
+            }
+            let op = SwapOp::Write {
+                path: path.clone(),
+                text: buffer_state.buffer.text.to_string(),
+            };
+            match (self.swap_handler)(&op) {
+                Ok(()) => buffer_state.swap_kept = true,
+                Err(e) => failed = Some(e),
+            }
+            // not retried before it's due again
+            buffer_state.swapped = Some((version, now));
+        }
+        if let Some(e) = failed {
+            self.msg = Some(format!("changes not copied to the swap file: {}", e));
+        }
+    }
+
+    /// Drop all the swap files, on a normal exit
+    pub fn remove_swaps(&mut self) {
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            if let (true, Some(path)) = (buffer_state.swap_kept, &buffer_state.document.path) {
+                let _ = (self.swap_handler)(&SwapOp::Remove(path.clone()));
+                buffer_state.swap_kept = false;
+            }
+        }
+    }
+
+    /// Tell of the swap files left over by an earlier session, to
+    /// `recover` them
+    pub fn offer_recovery(&mut self, swaps: Vec<Swap>) {
+        self.recoverable = swaps;
+        match self.recoverable.len() {
+            0 => {}
+            1 => {
+                self.msg = Some(format!(
+                    "{} has changes not written before a crash: :recover, or :recover-drop",
+                    self.recoverable[0].path.display()
+                ))
+            }
+            n => {
+                self.msg = Some(format!(
+                    "{} files have changes not written before a crash: :recover, or :recover-drop",
+                    n
+                ))
+            }
+        }
+    }
+
+    /// Apply the swap files left over to the buffers of their files, which
+    /// are opened if need be; they're yet to be written
+    pub fn recover(&mut self) {
+        if self.recoverable.is_empty() {
+            self.msg = Some("nothing to recover".into());
+            return;
+        }
+        let mut recovered = 0;
+        for swap in std::mem::take(&mut self.recoverable) {
+            let buffer_i = match self.buffer_i_by_path(&swap.path) {
+                Some(buffer_i) => buffer_i,
+                None => {
+                    self.open_buffer(&swap.path);
+                    match self.buffer_i_by_path(&swap.path) {
+                        Some(buffer_i) => buffer_i,
+                        None => continue,
+                    }
+                }
+            };
+            self.focus_buffer(buffer_i);
+            if 0 < self.replace_text(buffer_i, &Rope::from_str(&swap.text)) {
+                recovered += 1;
+            }
+            // it's the same file now
+            self.buffers[buffer_i].swap_kept = true;
+        }
+        self.msg = Some(format!(
+            "recovered the changes of {} file{}: :w to keep them, u to undo them",
+            recovered,
+            if recovered == 1 { "" } else { "s" }
+        ));
+    }
+
+    /// Drop the swap files left over, not to be told of them again
+    pub fn drop_recovery(&mut self) {
+        let count = self.recoverable.len();
+        for swap in std::mem::take(&mut self.recoverable) {
+            let _ = (self.swap_handler)(&SwapOp::Remove(swap.path));
+        }
+        self.msg = Some(format!(
+            "dropped the changes of {} file{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    fn try_write_buffer(&self, path: &Path) -> io::Result<()> {
+        if let Some(buffer) = self.cur_buffer_opt() {
+            self.write_file(path, &buffer.text, self.cur_document().format)?;
+        }
+
+        Ok(())
+    }
+
+    /// The text of the file at `path`, and the format it's written in
+    fn read_file(&self, path: &Path) -> io::Result<(Rope, FileFormat)> {
+        (self.read_handler)(path).map(encoding::decode)
+    }
+
+    /// Write `text` to `path` in `format`, unless it has chars `format`
+    /// can't have
+    fn write_file(&self, path: &Path, text: &Rope, format: FileFormat) -> io::Result<()> {
+        let bytes = encoding::encode(text, format)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        (self.write_handler)(path, &bytes)
+    }
+
+    /// Write the current buffer in another encoding or with other line
+    /// endings from now on, or tell those it's in with neither
+    pub(crate) fn set_file_format(
+        &mut self,
+        encoding: Option<Encoding>,
+        line_ending: Option<LineEnding>,
+    ) -> Result<(), String> {
+        let buffer_i = self.cur_buffer_i().ok_or("no buffer")?;
+        let buffer_state = &mut self.buffers[buffer_i];
+        let old = buffer_state.document.format;
+        let format = FileFormat {
+            encoding: encoding.unwrap_or(old.encoding),
+            line_ending: line_ending.unwrap_or(old.line_ending),
+        };
+        let tell = |format: FileFormat| {
+            format!("{}, {}", format.encoding.name(), format.line_ending.name())
+        };
+        if format != old {
+            encoding::encode(&buffer_state.buffer.text, format).map_err(|e| e.to_string())?;
+            buffer_state.document.format = format;
+            // to be written again, though the text is the same
+            if buffer_state.written_version == buffer_state.buffer.version() {
+                buffer_state.written_version = buffer_state.buffer.version().wrapping_sub(1);
+            }
+        }
+        self.msg = Some(tell(format));
+        Ok(())
+    }
+
+    /// Close the windows of all the tabs showing `buffer_i`, but that of
+    /// `keep`, and the other tabs left without any
+    fn close_windows_of(&mut self, buffer_i: usize, keep: usize) {
+        let views = &self.views;
+        let shows = |view_i: usize| view_i != keep && views[view_i].buffer_i == buffer_i;
+        if let Some(layout) = self.layout.as_mut() {
+            for view_i in layout.windows() {
+                if shows(view_i) {
+                    layout.close(view_i);
+                }
+            }
+        }
+        self.tabs.retain_windows(|view_i| !shows(view_i));
+    }
+
+    /// Delete the current buffer; its other windows close, and the
+    /// focused one goes to the next buffer
+    pub fn delete_buffer(&mut self) {
+        let (cur_buffer_i, cur_view_i) = match self.cur_buffer_i().zip(self.cur_view_i) {
+            Some(cur) => cur,
+            None => return,
+        };
+        if self.pending_grep.as_ref().map(|pending| pending.buffer_i) == Some(cur_buffer_i) {
+            self.cancel_grep();
+        }
+        let buffer_state = self.buffers.remove(cur_buffer_i);
+        if let (true, Some(path)) = (buffer_state.swap_kept, buffer_state.document.path) {
+            let _ = (self.swap_handler)(&SwapOp::Remove(path));
+        }
+        if let Some((path, _)) = buffer_state.synced {
+            (self.document_sync_handler)(&lsp::DocumentSync::Close { path });
+        }
+        self.close_windows_of(cur_buffer_i, cur_view_i);
+        self.views
+            .retain(|i, view| i == cur_view_i || view.buffer_i != cur_buffer_i);
+        self.focus_buffer_after(cur_buffer_i, 1);
+        self.views.remove(cur_view_i);
+        self.invalidation().invalidate_all();
+    }
+
+    pub fn buffer_next(&mut self) {
+        if let Some(cur_buffer_i) = self.cur_buffer_i() {
+            self.focus_buffer_after(cur_buffer_i, 1);
+        }
+    }
+
+    pub fn buffer_prev(&mut self) {
+        if let Some(cur_buffer_i) = self.cur_buffer_i() {
+            self.focus_buffer_after(cur_buffer_i, -1);
+        }
+    }
+
+    /// Focus the closest existing buffer after `buffer_i` in `direction`
+    fn focus_buffer_after(&mut self, mut buffer_i: usize, direction: isize) {
+        if self.buffers.is_empty() {
+            self.set_cur_view(None);
+            return;
+        }
+        let capacity = self.buffers.capacity();
+        loop {
+            buffer_i = if direction < 0 {
+                buffer_i.checked_sub(1).unwrap_or(capacity - 1)
+            } else {
+                (buffer_i + 1) % capacity
+            };
+            if self.buffers.contains(buffer_i) {
+                self.focus_buffer(buffer_i);
+                return;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.quit
+    }
+
+    pub fn handle_key(&mut self, key: Key) {
+        self.msg = None;
+        let unmodifiable = self
+            .cur_buffer_i()
+            .filter(|&i| !self.buffers[i].document.is_modifiable())
+            .map(|i| {
+                (
+                    i,
+                    self.buffers[i].buffer.version(),
+                    self.buffers[i].buffer.text.clone(),
+                )
+            });
+        let edited = self
+            .cur_buffer_i()
+            .map(|i| (i, self.buffers[i].buffer.version()));
+        let leader = self.cur_view_opt().map(|view| {
+            let buffer = &self.buffers[view.buffer_i].buffer;
+            (
+                self.cur_view_i.expect("view"),
+                buffer.version(),
+                view.selection.cursor_coord(&buffer.text),
+            )
+        });
+        let selected = self
+            .cur_view_i
+            .map(|view_i| (view_i, self.views[view_i].selection.clone()));
+        let mut mode = self.mode.take().expect("mode set");
+
+        // keys replayed were recorded already, as the one replaying them
+        let recording = self.macros.recording().is_some() && self.replaying.is_empty();
+        mode.handle(self, key);
+        if recording {
+            self.macros.record(key);
+        }
+        if let Some((buffer_i, version)) = edited {
+            if let Some(buffer_state) = self.buffers.get_mut(buffer_i) {
+                if buffer_state.buffer.version() != version {
+                    buffer_state
+                        .recent_edits
+                        .note_change(&buffer_state.buffer, version);
+                    let view_i = leader.as_ref().map(|&(view_i, _, _)| view_i);
+                    self.fix_other_views(buffer_i, view_i, version);
+                    self.notify_change(buffer_i);
+                }
+            }
+        }
+
+        if self.mode.is_none() {
+            self.mode = Some(mode)
+        }
+        let restored = std::mem::take(&mut self.selection_restored);
+        if let (Some((view_i, selection)), Some((buffer_i, version))) = (selected, edited) {
+            if let Some(view) = self.views.get_mut(view_i) {
+                let buffer = &self.buffers[buffer_i].buffer;
+                let changed = view.selection.selections != selection.selections
+                    || view.selection.primary != selection.primary;
+                if !restored && changed && view.buffer_i == buffer_i && buffer.version() == version
+                {
+                    view.selection_history.push(selection, buffer);
+                }
+            }
+        }
+        if let Some((buffer_i, version, text)) = unmodifiable {
+            self.revert_unmodifiable(buffer_i, version, text);
+        }
+        self.refresh_matches();
+        self.refresh_conflicts();
+        self.sync_documents();
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            buffer_state.highlights.follow(&buffer_state.buffer);
+            buffer_state.code_lenses.follow(&buffer_state.buffer);
+            buffer_state.recent_edits.follow(&buffer_state.buffer);
+            buffer_state.marks.follow(&buffer_state.buffer);
+            if buffer_state.highlights.pending_id().is_none() {
+                if let Some(task) = buffer_state.highlight_task.take() {
+                    self.tasks.end(task);
+                }
+            }
+        }
+        let buffers = &self.buffers;
+        for (_, view) in self.views.iter_mut() {
+            view.folds.follow(&buffers[view.buffer_i].buffer);
+            view.jumps.follow(|buffer_i| {
+                buffers
+                    .get(buffer_i)
+                    .map(|buffer_state| &buffer_state.buffer)
+            });
+        }
+        if let Some((view_i, _, cursor)) = leader {
+            self.keep_out_of_folds(view_i, cursor.line);
+        }
+        if let (Some(buffer_i), Some(view)) = (self.cur_buffer_i(), self.cur_view_opt()) {
+            let cursor = view.selection.selections[0].cursor.0;
+            let buffer_state = &mut self.buffers[buffer_i];
+            if buffer_state.references.follow(&buffer_state.buffer, cursor) {
+                self.invalidation().invalidate_buffer(buffer_i);
+            }
+        }
+        if let Some((view_i, version, cursor)) = leader {
+            let moved = self.views.get(view_i).is_some_and(|view| {
+                let buffer = &self.buffers[view.buffer_i].buffer;
+                buffer.version() != version || view.selection.cursor_coord(&buffer.text) != cursor
+            });
+            if moved {
+                self.move_followers(view_i);
+            }
+        }
+        self.copy_swaps(false);
+    }
+
+    /// Move the selections of the views of a buffer other than `view_i`
+    /// along with the changes made since `version`
+    fn fix_other_views(&mut self, buffer_i: usize, view_i: Option<usize>, version: u64) {
+        let buffer = &self.buffers[buffer_i].buffer;
+        let changes: Vec<TextChange> = buffer
+            .changes_since(version)
+            .map(|changes| changes.copied().collect())
+            .unwrap_or_default();
+        for (_, view) in self
+            .views
+            .iter_mut()
+            .filter(|&(i, ref view)| Some(i) != view_i && view.buffer_i == buffer_i)
+        {
+            for change in &changes {
+                view.selection.follow_change(change);
+            }
+            view.selection.trim_to_text(&buffer.text);
+        }
+    }
+
+    /// Why the text of `buffer_i` can't be edited, if it can't
+    pub(crate) fn check_modifiable(&self, buffer_i: usize) -> Result<(), String> {
+        let document = &self.buffers[buffer_i].document;
+        if let DocumentKind::Preview(_) = document.kind {
+            Err("only the beginning of the file is shown: :full to read it all and edit it".into())
+        } else if document.kind.is_read_only() {
+            Err("buffer is read-only".into())
+        } else if document.unmodifiable {
+            Err(format!(
+                "{} is not modifiable: :set modifiable=true to edit it",
+                self.buffer_label(buffer_i)
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Undo any change made to a buffer that can't be edited
+    fn revert_unmodifiable(&mut self, buffer_i: usize, version: u64, text: Rope) {
+        let buffer = match self.buffers.get_mut(buffer_i) {
+            Some(buffer_state) if buffer_state.buffer.version() != version => {
+                &mut buffer_state.buffer
+            }
+            _ => return,
+        };
+        buffer.replace_text(text);
+        for (_, view) in self
+            .views
+            .iter_mut()
+            .filter(|(_, v)| v.buffer_i == buffer_i)
+        {
+            view.selection.trim_to_text(&buffer.text);
+        }
+        self.msg = self.check_modifiable(buffer_i).err();
+        if self.mode_name() == "insert" {
+            self.set_mode(mode::Normal);
+        }
+    }
+
+    fn refresh_conflicts(&mut self) {
+        if let Some(buffer_i) = self.cur_buffer_i() {
+            let buffer_state = &mut self.buffers[buffer_i];
+            let old = buffer_state.conflicts.conflicts().to_vec();
+            buffer_state.conflicts.refresh(&buffer_state.buffer);
+            let new = buffer_state.conflicts.conflicts();
+            let mut invalidation = self.invalidation.borrow_mut();
+            for conflict in old
+                .iter()
+                .filter(|c| !new.contains(c))
+                .chain(new.iter().filter(|c| !old.contains(c)))
+            {
+                invalidation.invalidate_lines(
+                    buffer_i,
+                    buffer_state.buffer.version(),
+                    conflict.start..conflict.end + 1,
+                );
+            }
+        }
+    }
+
+    pub fn cur_conflicts(&self) -> Option<&ConflictSet> {
+        self.cur_buffer_i().map(|i| &self.buffers[i].conflicts)
+    }
+
+    /// Move to the next (or previous) merge conflict
+    pub(crate) fn goto_conflict(&mut self, backward: bool) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        self.refresh_conflicts();
+        let line = self
+            .cur_view()
+            .selection
+            .cursor_coord(&self.cur_buffer().text)
+            .line;
+        let conflicts = self.cur_conflicts().expect("cur_buffer set");
+        let start = match conflicts.next_from(line, backward) {
+            Some(conflict) => conflict.start,
+            None => {
+                self.msg = Some("no conflicts".into());
+                return;
+            }
+        };
+        let count = conflicts.conflicts().len();
+        let i = conflicts.index_at_line(start).expect("found");
+        let mut buffer = self.cur_buffer_mut();
+        buffer.apply_motion(Motion::Line(start), false);
+        buffer.collapse();
+        self.msg = Some(format!("conflict {}/{}", i + 1, count));
+    }
+
+    /// Ranges of the current buffer covered by `target`s, sorted
+    ///
+    /// Hunks are the changes since `HEAD`; `None` if git failed.
+    fn target_ranges(&mut self, target: Target) -> Option<Vec<Range<usize>>> {
+        let text = self.cur_buffer().text.clone();
+        match target {
+            Target::Hunk => {
+                let path = match self.cur_document().path.clone() {
+                    Some(path) => path,
+                    None => {
+                        self.msg = Some("buffer has no file".into());
+                        return None;
+                    }
+                };
+                let head = match self.git_show("HEAD", &path) {
+                    Ok(head) => head,
+                    Err(e) => {
+                        self.msg = Some(format!("git: {}", e));
+                        return None;
+                    }
+                };
+                let hunks = diff::diff_ropes(&Rope::from_str(&head), &text);
+                Some(target::hunk_ranges(&hunks, &text))
+            }
+            Target::Diagnostic => Some(
+                self.cur_document()
+                    .diagnostics
+                    .iter()
+                    .map(|d| d.start.0.min(text.len_chars())..d.end.0.min(text.len_chars()))
+                    .map(|range| range.start..range.end.max(range.start))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Select the `i`th of the `ranges`, telling which one it is
+    fn select_target_range(&mut self, target: Target, ranges: &[Range<usize>], i: usize) {
+        self.cur_buffer_mut().select_range(ranges[i].clone());
+        let mut msg = format!("{} {}/{}", target.name(), i + 1, ranges.len());
+        if target == Target::Diagnostic {
+            if let Some(line) = self.cur_document().diagnostics[i].message.lines().next() {
+                msg = format!("{}: {}", msg, line);
+            }
+        }
+        self.msg = Some(msg);
+    }
+
+    /// Select the next (or previous) `target` from the cursor
+    pub(crate) fn goto_target(&mut self, target: Target, backward: bool) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        let ranges = match self.target_ranges(target) {
+            Some(ranges) => ranges,
+            None => return,
+        };
+        let cursor = self.cur_view().selection.selections[self.cur_view().selection.primary];
+        match target::next_from(&ranges, cursor.sorted_pair().0 .0, backward) {
+            Some(i) => self.select_target_range(target, &ranges, i),
+            None => self.msg = Some(format!("no {}s", target.name())),
+        }
+    }
+
+    /// Select the `target` under the cursor
+    pub(crate) fn select_target(&mut self, target: Target) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        let ranges = match self.target_ranges(target) {
+            Some(ranges) => ranges,
+            None => return,
+        };
+        let cursor = self.cur_view().selection.selections[self.cur_view().selection.primary];
+        match target::at(&ranges, cursor.cursor.0) {
+            Some(i) => self.select_target_range(target, &ranges, i),
+            None => self.msg = Some(format!("no {} under the cursor", target.name())),
+        }
+    }
+
+    /// Toggle the bracket groups around the selections between their
+    /// single-line and multi-line forms
+    pub(crate) fn split_join(&mut self) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        let buffer = self.cur_buffer();
+        let tree = Tree::parse(&buffer.text, self.cur_document().language.as_deref());
+        let mut groups: Vec<usize> = self
+            .cur_view()
+            .selection
+            .selections
+            .iter()
+            .filter_map(|sel| tree.group_covering(sel.sorted_range_usize()))
+            .collect();
+        groups.sort_by_key(|&group| tree.nodes[group].range.start);
+        groups.dedup();
+        // of nested groups, the outer one is reshaped
+        let mut outer: Vec<usize> = vec![];
+        for group in groups {
+            let range = &tree.nodes[group].range;
+            if outer
+                .last()
+                .is_some_and(|&last| range.end <= tree.nodes[last].range.end)
+            {
+                continue;
+            }
+            outer.push(group);
+        }
+        let indent = buffer.indent_text(1);
+        let edits: Vec<_> = outer
+            .into_iter()
+            .filter_map(|group| {
+                let text = tree.split_join(group, &buffer.text, &indent)?;
+                Some((tree.nodes[group].range.clone(), text))
+            })
+            .collect();
+        if edits.is_empty() {
+            self.msg = Some("nothing to split or join".into());
+            return;
+        }
+        self.cur_buffer_mut().replace_ranges(edits);
+    }
+
+    /// Resolve the merge conflict under the cursor
+    pub(crate) fn pick_conflict(&mut self, pick: Pick) {
+        if self.cur_buffer_opt().is_none() {
+            return;
+        }
+        self.refresh_conflicts();
+        let line = self
+            .cur_view()
+            .selection
+            .cursor_coord(&self.cur_buffer().text)
+            .line;
+        let conflict = match self.cur_conflicts().and_then(|c| c.at_line(line)) {
+            Some(conflict) => conflict.clone(),
+            None => {
+                self.msg = Some("no conflict under the cursor".into());
+                return;
+            }
+        };
+        self.commit_undo_point();
+        let mut buffer = self.cur_buffer_mut();
+        let range = conflict.char_range(buffer.text());
+        let resolution = conflict.resolution(buffer.text(), pick);
+        buffer.replace_range(range, &resolution);
+        self.commit_undo_point();
+        self.refresh_conflicts();
+    }
+
+    /// Bring the match set of the current buffer up to date with the
+    /// search typed, or the last one
+    fn refresh_matches(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(i) => i,
+            None => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let search = match self.search_preview.as_ref().or(self.last_search.as_ref()) {
+            Some(search) => search,
+            None => {
+                // the pattern typed was cancelled, with no search before
+                if buffer_state.matches.take().is_some() {
+                    self.invalidation().invalidate_buffer(buffer_i);
+                }
+                return;
+            }
+        };
+        match buffer_state.matches {
+            Some(ref mut matches) if matches.search.is_same(search) => {
+                matches.refresh(&buffer_state.buffer)
+            }
+            _ => {
+                buffer_state.matches = Some(MatchSet::new(search.clone(), &buffer_state.buffer));
+                // lines drawn before don't show the new matches
+                self.invalidation().invalidate_buffer(buffer_i);
+            }
+        }
+    }
+
+    /// `match 3/47` if the primary selection is a match, `47 matches` otherwise
+    pub fn match_indicator(&self) -> Option<String> {
+        let buffer_i = self.cur_buffer_i()?;
+        let matches = self.buffers[buffer_i].matches.as_ref()?;
+        let total = matches.matches().len();
+        if total == 0 {
+            return Some("no matches".into());
+        }
+        let view = self.cur_view();
+        let (start, end) = view.selection.selections[view.selection.primary].sorted_pair();
+        Some(match matches.index_of(start, end) {
+            Some(i) => format!("match {}/{}", i + 1, total),
+            None => format!("{} matches", total),
+        })
+    }
+
+    pub fn cur_buffer_i(&self) -> Option<usize> {
+        self.cur_view_opt().map(|view| view.buffer_i)
+    }
+
+    pub fn cur_view_opt(&self) -> Option<&ViewState> {
+        self.cur_view_i.map(|i| &self.views[i])
+    }
+
+    pub fn cur_view(&self) -> &ViewState {
+        self.cur_view_opt().expect("cur_view set")
+    }
+
+    pub fn cur_view_i(&self) -> Option<usize> {
+        self.cur_view_i
+    }
+
+    /// Make the view `follower` follow `leader`, going where `map` tells,
+    /// as described in `follow`
+    pub fn follow_view(&mut self, follower: usize, leader: usize, map: FollowMap) {
+        if follower == leader || !self.views.contains(leader) {
+            return;
+        }
+        if let Some(view) = self.views.get_mut(follower) {
+            view.follow = Some(Follow { leader, map });
+            self.move_followers(leader);
+        }
+    }
+
+    pub fn unfollow_view(&mut self, follower: usize) {
+        if let Some(view) = self.views.get_mut(follower) {
+            view.follow = None;
+        }
+    }
+
+    /// Move the views following `leader` where their maps tell, and those
+    /// following them in turn
+    fn move_followers(&mut self, leader: usize) {
+        let mut leaders = vec![leader];
+        let mut moved = vec![leader];
+        while let Some(leader) = leaders.pop() {
+            let (text, cursor) = match self.views.get(leader) {
+                Some(view) => {
+                    let text = &self.buffers[view.buffer_i].buffer.text;
+                    (text.clone(), view.selection.cursor_coord(text))
+                }
+                None => continue,
+            };
+            let followers: Vec<usize> = self
+                .views
+                .iter()
+                .filter(|(i, view)| {
+                    !moved.contains(i) && view.follow.as_ref().is_some_and(|f| f.leader == leader)
+                })
+                .map(|(i, _)| i)
+                .collect();
+            for i in followers {
+                moved.push(i);
+                leaders.push(i);
+                let view = &mut self.views[i];
+                let map = view.follow.as_ref().expect("following").map.clone();
+                let follower_text = &self.buffers[view.buffer_i].buffer.text;
+                let position = match map(&text, cursor, follower_text) {
+                    Some(position) => position.trim_line_to_buf(follower_text),
+                    None => continue,
+                };
+                let idx = position.to_idx(follower_text);
+                view.selection = SelectionSet {
+                    selections: vec![Selection::new_from_normalized(idx, idx)],
+                    ..SelectionSet::default()
+                };
+                let height = view.viewport().height;
+                *view.line_offset.borrow_mut() = position.line.saturating_sub(height / 2);
+                let buffer_i = view.buffer_i;
+                self.invalidation().invalidate_buffer(buffer_i);
+            }
+        }
+    }
+
+    pub fn cur_buffer_opt(&self) -> Option<&Buffer> {
+        self.cur_buffer_i().map(|i| &self.buffers[i].buffer)
+    }
+
+    pub fn cur_buffer(&self) -> &Buffer {
+        self.cur_buffer_opt().expect("cur_buffer set")
+    }
+
+    /// Current buffer, edited through the current view
+    pub fn cur_buffer_mut_opt(&mut self) -> Option<BufferView<'_>> {
+        let view = &mut self.views[self.cur_view_i?];
+        let buffer = &mut self.buffers[view.buffer_i].buffer;
+        Some(BufferView::new(buffer, view))
+    }
+
+    pub fn cur_buffer_mut(&mut self) -> BufferView<'_> {
+        self.cur_buffer_mut_opt().expect("cur_buffer set")
+    }
+
+    pub fn cur_document_opt(&self) -> Option<&Document> {
+        self.cur_buffer_i().map(|i| &self.buffers[i].document)
+    }
+
+    pub fn cur_document(&self) -> &Document {
+        self.cur_document_opt().expect("cur_buffer set")
+    }
+
+    pub fn cur_document_mut(&mut self) -> &mut Document {
+        let i = self.cur_buffer_i().expect("cur_buffer set");
+        &mut self.buffers[i].document
+    }
+
+    pub(crate) fn commit_undo_point(&mut self) {
+        self.chars_since_undo_point = 0;
+        self.stamp_cur_buffer();
+        if let Some(buffer) = self.cur_buffer_mut_opt() {
+            buffer
+                .buffer
+                .maybe_commit_undo_point(&buffer.view.selection);
+        }
+    }
+
+    /// Commit an undo point in `buffer_i`, with the selection of the
+    /// current view if it shows it, or of another that does
+    fn commit_undo_point_of(&mut self, buffer_i: usize) {
+        if Some(buffer_i) == self.cur_buffer_i() {
+            return self.commit_undo_point();
+        }
+        let selection = self
+            .views
+            .iter()
+            .find(|(_, view)| view.buffer_i == buffer_i)
+            .map_or_else(SelectionSet::default, |(_, view)| view.selection.clone());
+        let now = self.now;
+        let buffer = &mut self.buffers[buffer_i].buffer;
+        buffer.history.now = now;
+        buffer.maybe_commit_undo_point(&selection);
+    }
+
+    /// Undo points made in the current buffer are made now
+    fn stamp_cur_buffer(&mut self) {
+        let now = self.now;
+        if let Some(buffer) = self.cur_buffer_mut_opt() {
+            buffer.buffer.history.now = now;
+        }
+    }
+
+    /// Tell the editor the time, for the undo history to know when edits
+    /// were made, as described in `undo`
+    pub fn note_time(&mut self, now: SystemTime) {
+        self.now = now;
+    }
+
+    /// Tell the editor how long it waited for the key about to be handled
+    ///
+    /// A long enough pause while typing ends the current undo chunk.
+    /// `libbrz` has no clock of its own, so it's up to the frontend to
+    /// measure it.
+    pub fn note_idle(&mut self, idle: Duration) {
+        if UNDO_PAUSE <= idle {
+            self.commit_undo_point();
+        }
+        if self
+            .get_mode()
+            .timeout()
+            .is_some_and(|timeout| timeout <= idle)
+        {
+            self.set_mode(mode::Normal);
+        }
+    }
+
+    /// Add the layers defined in `text`, replacing those of the same name
+    pub fn define_layers(&mut self, text: &str) {
+        match layer::parse_layers(text) {
+            Ok(layers) => {
+                for layer in layers {
+                    self.layers.retain(|l| l.name != layer.name);
+                    self.layers.push(layer);
+                }
+            }
+            Err(e) => self.config_error(format!("layers: {}", e)),
+        }
+    }
+
+    /// Paint `overrides` over the theme in the panes of a kind, or with
+    /// `view`, in the current view; no overrides go back to the theme
+    pub fn set_colors(&mut self, scope: &str, overrides: &str) {
+        let overrides = match ColorOverrides::parse(overrides) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                self.msg = Some(format!("colors: {}", e));
+                return;
+            }
+        };
+        if scope == "view" {
+            match self.cur_view_i {
+                Some(view_i) => self.views[view_i].colors = overrides,
+                None => self.msg = Some("colors: no view".into()),
+            }
+        } else if theme::PANE_KINDS.contains(&scope) {
+            self.pane_colors.insert(scope.to_owned(), overrides);
+        } else {
+            self.msg = Some(format!("colors: no pane kind {}", scope));
+        }
+    }
+
+    /// Add the theme `name` with `text`, as described in `theme`
+    pub fn define_theme(&mut self, name: &str, text: &str) {
+        match Theme::parse(text) {
+            Ok(theme) => {
+                self.themes.insert(name.to_owned(), theme);
+            }
+            Err(e) => self.config_error(format!("theme {}: {}", name, e)),
+        }
+    }
+
+    /// Draw with the theme `name` from now on; `default` goes back to the
+    /// colors of the frontend
+    pub fn set_theme(&mut self, name: &str) {
+        let known = [
+            name.to_owned(),
+            format!("{}-dark", name),
+            format!("{}-light", name),
+        ]
+        .iter()
+        .any(|name| self.themes.contains_key(name));
+        if name == "default" {
+            self.theme = None;
+        } else if known {
+            self.theme = Some(name.to_owned());
+        } else {
+            self.msg = Some(format!("no theme {}", name));
+            return;
+        }
+        self.invalidation().invalidate_all();
+    }
+
+    /// Draw with the variants of the themes for `background`, as
+    /// described in `theme`
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+        self.invalidation().invalidate_all();
+    }
+
+    /// The theme picked, with the background, and the names of all of them
+    pub fn theme_info(&self) -> String {
+        let names: Vec<&str> = Some("default")
+            .into_iter()
+            .chain(self.themes.keys().map(String::as_str))
+            .collect();
+        format!(
+            "theme: {}, {} background ({})",
+            self.theme.as_deref().unwrap_or("default"),
+            self.background.name(),
+            names.join(", ")
+        )
+    }
+
+    /// Lay the status line out with `template`, as described in `status`;
+    /// an empty one goes back to the default
+    pub fn set_status_template(&mut self, template: &str) {
+        if template.trim().is_empty() {
+            self.status_template = None;
+            return;
+        }
+        match StatusTemplate::parse(template) {
+            Ok(template) => self.status_template = Some(template),
+            Err(e) => self.msg = Some(format!("statusline: {}", e)),
+        }
+    }
+
+    /// Lay the status line out with `segments`, as described in `status`,
+    /// or with the default ones if that's empty
+    pub fn set_status_segments(&mut self, segments: &str) {
+        let segments = match segments.trim() {
+            "" => status::DEFAULT_SEGMENTS,
+            segments => segments,
+        };
+        match status::segments(segments) {
+            Ok(template) => self.status_template = Some(template),
+            Err(e) => self.msg = Some(format!("status-segments: {}", e)),
+        }
+    }
+
+    /// Value of a field of the status line that's not about the mode
+    pub(crate) fn status_field(&self, field: status::Field) -> Option<String> {
+        use status::Field;
+        let document = self.cur_document_opt();
+        let cursor = || {
+            let buffer = self.cur_buffer_opt()?;
+            let coord = self.cur_view().selection.cursor_coord(&buffer.text);
+            Some((buffer, coord))
+        };
+        match field {
+            Field::Mode | Field::Mode4 => None,
+            Field::File => document?
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            Field::Modified => Some("[+]".to_owned()).filter(|_| self.is_modified()),
+            Field::Language => document?.language.clone(),
+            Field::Line => cursor().map(|(_, coord)| (coord.line + 1).to_string()),
+            Field::Col => {
+                cursor().map(|(buffer, coord)| (buffer.to_visual(coord).column + 1).to_string())
+            }
+            Field::Percent => cursor().map(|(buffer, coord)| {
+                format!("{}%", (coord.line + 1) * 100 / buffer.lines().max(1))
+            }),
+            Field::Lines => self
+                .cur_buffer_opt()
+                .map(|buffer| buffer.lines().to_string()),
+            Field::Matches => self.match_indicator(),
+            Field::Tasks => self.task_summary(),
+            Field::Todos => self.todo_summary(),
+            Field::Recording => self.recording_indicator(),
+            Field::Reduced => document
+                .filter(|document| document.reduced)
+                .map(|_| "reduced".to_owned()),
+            Field::Unwritable => document
+                .filter(|document| document.unwritable)
+                .map(|_| "unwritable".to_owned()),
+            Field::ReadOnly => document.and_then(|document| {
+                if !document.is_modifiable() {
+                    Some("unmodifiable".to_owned())
+                } else if document.read_only {
+                    Some("readonly".to_owned())
+                } else {
+                    None
+                }
+            }),
+            Field::Format => document?.format.indicator(),
+            Field::Selections => {
+                let count = self.cur_view_opt()?.selection.selections.len();
+                Some(format!("{} sel", count)).filter(|_| 1 < count)
+            }
+            Field::Diagnostics => {
+                let count = |severity| {
+                    document
+                        .iter()
+                        .flat_map(|document| &document.diagnostics)
+                        .filter(|d| d.severity == severity)
+                        .count()
+                };
+                let counts: Vec<String> = [("E", Severity::Error), ("W", Severity::Warning)]
+                    .iter()
+                    .map(|&(letter, severity)| (letter, count(severity)))
+                    .filter(|&(_, count)| 0 < count)
+                    .map(|(letter, count)| format!("{}{}", letter, count))
+                    .collect();
+                Some(counts.join(" ")).filter(|counts| !counts.is_empty())
+            }
+            Field::Msg => self.msg.clone(),
+        }
+    }
+
+    /// Set the colors of each line of `text`, a scope followed by its
+    /// overrides as for `set_colors`; lines starting with `#` are comments
+    pub fn define_colors(&mut self, text: &str) {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (scope, overrides) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            self.set_colors(scope, overrides);
+        }
+    }
+
+    /// `render`, with the colors of the panes of `kind`
+    pub fn themed<'r>(
+        &self,
+        render: &'r mut dyn Renderer,
+        kind: &str,
+    ) -> theme::Themed<&'r mut dyn Renderer> {
+        theme::Themed::new(render, self.pane_colors.get(kind))
+    }
+
+    /// Set the variables of the `env` config file with `text` for the
+    /// tools the frontend runs, as described in `env`
+    pub fn define_env(&mut self, text: &str) {
+        if let Err(e) = self.env.define(text) {
+            self.config_error(format!("env: {}", e));
+        }
+    }
+
+    /// Bind the keys of the keymaps in `text`, as described in `keymap`;
+    /// `project` tells they come from the project
+    pub fn define_keys(&mut self, text: &str, project: bool) {
+        match keymap::parse_keymaps(text, project) {
+            Ok((keymaps, leader)) => {
+                self.keymaps.define(keymaps);
+                if let Some(leader) = leader {
+                    self.keymaps.leader = leader;
+                }
+            }
+            Err(e) => self.config_error(format!("keys: {}", e)),
+        }
+    }
+
+    /// Bind the keys of the `config.toml` in `text`, as described in
+    /// `keymap`; `project` tells it's the one of the project
+    pub fn define_config(&mut self, text: &str, project: bool) {
+        match keymap::parse_config(text, project) {
+            Ok(config) => {
+                self.keymaps.define(config.keymaps);
+                if let Some(leader) = config.leader {
+                    self.keymaps.leader = leader;
+                }
+                if let Some(timeout) = config.timeout {
+                    self.keymaps.timeout = Some(timeout);
+                }
+            }
+            Err(e) => self.config_error(format!("config.toml: {}", e)),
+        }
+    }
+
+    /// Tell `e`, of a config file, and keep it for `:health`
+    fn config_error(&mut self, e: String) {
+        self.msg = Some(e.clone());
+        self.config_errors.push(e);
+    }
+
+    /// Run what a user keymap binds `key` to in `mode` in the current
+    /// buffer; `false` if none does
+    pub(crate) fn run_user_key(&mut self, mode: &str, key: Key) -> bool {
+        let language = self.cur_document_opt().and_then(|d| d.language.as_deref());
+        match self.keymaps.lookup(mode, key, language) {
+            Some((Binding::Action(call), _)) => self.execute_action(&call),
+            Some((Binding::Prefix(keys), _)) => {
+                let timeout = self.keymaps.timeout;
+                let name = layer::key_name(key);
+                self.set_mode(mode::KeySequence::new(name, keys, timeout))
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Tell what `key` does in normal mode, and where that's from
+    pub(crate) fn describe_key(&mut self, key: Key) {
+        let name = layer::key_name(key);
+        let language = self.cur_document_opt().and_then(|d| d.language.as_deref());
+        self.msg = Some(match self.keymaps.lookup("normal", key, language) {
+            Some((Binding::Action(call), keymap)) => {
+                format!("{}: {}, from the {}", name, call, keymap.origin())
+            }
+            Some((Binding::Prefix(keys), keymap)) => format!(
+                "{}: then {}, from the {}",
+                name,
+                keymap::key_list(&keys),
+                keymap.origin()
+            ),
+            None => {
+                match action::normal::default_key_mappings().get(&crate::NaturalyOrderedKey(key)) {
+                    Some(action) => format!("{}: {}, built in", name, action),
+                    None => format!("{}: not bound", name),
+                }
+            }
+        });
+    }
+
+    pub(crate) fn enter_layer(&mut self, name: &str) {
+        match self.layers.iter().find(|layer| layer.name == name) {
+            Some(layer) => {
+                let layer = layer.clone();
+                self.set_mode(mode::Transient::new(layer));
+            }
+            None => self.msg = Some(format!("no layer {}", name)),
+        }
+    }
+
+    pub(crate) fn undo(&mut self, times: usize) {
+        self.stamp_cur_buffer();
+        if let Some(buffer) = self.cur_buffer_mut_opt() {
+            buffer.view.selection = buffer.buffer.undo(times, &buffer.view.selection);
+        }
+    }
+
+    /// Go back to the selections before, or forward again, as described
+    /// in `selection_history`
+    pub(crate) fn undo_selection(&mut self, forward: bool) {
+        self.selection_restored = true;
+        let restored = self
+            .cur_buffer_mut_opt()
+            .is_some_and(|mut buffer| buffer.undo_selection(forward));
+        if !restored {
+            self.msg = Some(if forward {
+                "no selections after".to_owned()
+            } else {
+                "no selections before".to_owned()
+            });
+        }
+    }
+
+    pub(crate) fn redo(&mut self, times: usize) {
+        if let Some(buffer) = self.cur_buffer_mut_opt() {
+            if let Some(selection) = buffer.buffer.redo(times) {
+                buffer.view.selection = selection;
+            }
+        }
+    }
+
+    /// Go `steps` undo points back in the order they were made, or
+    /// forward with negative `steps`, whatever the branch
+    pub(crate) fn undo_steps(&mut self, steps: isize) {
+        self.stamp_cur_buffer();
+        let buffer = match self.cur_buffer_mut_opt() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        match buffer.buffer.undo_steps(steps, &buffer.view.selection) {
+            Some(selection) => buffer.view.selection = selection,
+            None => self.msg = Some(no_undo_point(steps < 0)),
+        }
+    }
+
+    /// Go to the state the current buffer was in `duration` earlier, or
+    /// later with `later`
+    pub(crate) fn undo_by_time(&mut self, duration: Duration, later: bool) {
+        self.stamp_cur_buffer();
+        let buffer = match self.cur_buffer_mut_opt() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        let selection = &buffer.view.selection;
+        match buffer.buffer.undo_by_time(duration, later, selection) {
+            Some(selection) => buffer.view.selection = selection,
+            None => self.msg = Some(no_undo_point(later)),
+        }
+    }
+
+    /// Start or end the insert session of the current buffer, which is
+    /// one change as far as `RecentEdit`s are concerned
+    pub(crate) fn set_insert_session(&mut self, on: bool) {
+        if let Some(buffer_i) = self.cur_buffer_i() {
+            let buffer_state = &mut self.buffers[buffer_i];
+            if on {
+                buffer_state.recent_edits.start_insert(&buffer_state.buffer);
+            } else {
+                buffer_state.recent_edits.end_insert();
+            }
+        }
+    }
+
+    /// Name the register for the next yank, delete or paste
+    pub(crate) fn select_register(&mut self, name: char) {
+        if register::is_valid(name)
+            || [register::LAST_SEARCH, register::LAST_COMMAND].contains(&name)
+        {
+            self.pending_register = Some(name);
+        } else {
+            self.msg = Some(format!("no register {}", name));
+        }
+    }
+
+    /// Make `register` what's pasted, and the newest of the yank history,
+    /// keeping it in the register named before too
+    pub(crate) fn set_yanked(&mut self, register: Register) {
+        match self.pending_register.take() {
+            Some(register::BLACK_HOLE) => return,
+            Some(register::CLIPBOARD) => {
+                let op = ClipboardOp::Copy(register.to_text());
+                if let Err(e) = (self.clipboard_handler)(&op) {
+                    self.msg = Some(format!("clipboard: {}", e));
+                }
+            }
+            Some(name) if name.is_ascii_alphanumeric() => {
+                self.registers.set(name, register.clone());
+            }
+            Some(register::UNNAMED) | None => {}
+            Some(name) => self.msg = Some(format!("register {} is read-only", name)),
+        }
+        self.yank_history.push(register.clone());
+        self.yanked = register;
+    }
+
+    /// What's in the register `name`
+    fn register(&mut self, name: char) -> Result<Register, String> {
+        let text = |text: Option<String>| {
+            text.map(|text| Register::from_text(&text))
+                .ok_or_else(|| format!("register {} is empty", name))
+        };
+        match name {
+            register::UNNAMED => Ok(self.yanked.clone()),
+            register::LAST_SEARCH => text(self.last_search.as_ref().map(|s| s.pattern.clone())),
+            register::LAST_COMMAND => text(self.last_command.clone()),
+            register::CLIPBOARD => match (self.clipboard_handler)(&ClipboardOp::Paste) {
+                Ok(clipboard) => text(clipboard),
+                Err(e) => Err(format!("clipboard: {}", e)),
+            },
+            name => self
+                .registers
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("register {} is empty", name)),
+        }
+    }
+
+    /// The selections of the current view, written out as
+    /// `SharedSelections`
+    pub fn export_selections(&self) -> Option<String> {
+        let view = self.cur_view_opt()?;
+        let buffer_state = &self.buffers[view.buffer_i];
+        let path = buffer_state.document.path.clone();
+        Some(SharedSelections::new(path, &view.selection, &buffer_state.buffer.text).to_string())
+    }
+
+    /// Select again what `export_selections` wrote, in its file, opened
+    /// first if it's not the current one
+    pub fn import_selections(&mut self, text: &str) -> Result<(), String> {
+        let shared = SharedSelections::parse(text)?;
+        let cur_path = |state: &Self| {
+            state
+                .cur_document_opt()
+                .and_then(|document| document.path.clone())
+        };
+        if let Some(ref path) = shared.path {
+            if cur_path(self).as_ref() != Some(path) {
+                self.open_buffer(path);
+                if cur_path(self).as_ref() != Some(path) {
+                    return Err(format!("can't open {}", path.display()));
+                }
+            }
+        }
+        if self.cur_buffer_i().is_none() {
+            return Err("no buffer to select in".into());
+        }
+        let buffer = self.cur_buffer_mut();
+        buffer.view.selection = shared.to_selection(&buffer.buffer.text);
+        self.msg = Some(format!("{} selections", shared.selections.len()));
+        Ok(())
+    }
+
+    /// Write the selections to `path`, or yank them to the register named
+    /// before
+    pub(crate) fn write_selections(&mut self, path: Option<&Path>) -> Result<(), String> {
+        let text = self.export_selections().ok_or("no selections")?;
+        let count = self.cur_view().selection.selections.len();
+        let selections = format!("{} selections", count);
+        match path {
+            Some(path) => {
+                (self.write_handler)(path, text.as_bytes()).map_err(|e| e.to_string())?;
+                self.msg = Some(format!("{} written to {}", selections, path.display()));
+            }
+            None => {
+                self.set_yanked(Register::from_text(&text));
+                self.msg = Some(format!("{} yanked", selections));
+            }
+        }
+        Ok(())
+    }
+
+    /// Select again the selections written to `path`, or yanked to the
+    /// register named before
+    pub(crate) fn read_selections(&mut self, path: Option<&Path>) -> Result<(), String> {
+        let text = match path {
+            Some(path) => self
+                .read_file(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+                .0
+                .to_string(),
+            None => {
+                let name = self.pending_register.take().unwrap_or(register::UNNAMED);
+                self.register(name)?.to_text()
+            }
+        };
+        self.import_selections(&text)
+    }
+
+    /// The files open, their windows and the registers, as described in
+    /// `session`
+    pub fn session(&self) -> Session {
+        let mut session = Session {
+            project: self.project_dir.clone(),
+            ..default()
+        };
+        let mut file_of = HashMap::new();
+        for (buffer_i, buffer_state) in self.buffers.iter() {
+            let document = &buffer_state.document;
+            if let (DocumentKind::Text, Some(path)) = (&document.kind, &document.path) {
+                file_of.insert(buffer_i, session.files.len());
+                session.files.push(path.clone());
+            }
+        }
+        let cur = Tab {
+            name: self.tabs.tabs()[self.tabs.cur()].name.clone(),
+            layout: self.layout.clone(),
+            view_i: self.cur_view_i,
+        };
+        let tabs: Vec<&Tab> = self
+            .tabs
+            .tabs()
+            .iter()
+            .enumerate()
+            .map(|(i, tab)| if i == self.tabs.cur() { &cur } else { tab })
+            .collect();
+        let mut views = HashMap::new();
+        let windows = tabs.iter().filter_map(|tab| tab.layout.as_ref());
+        for view_i in windows.flat_map(Layout::windows) {
+            let view = &self.views[view_i];
+            let file = match file_of.get(&view.buffer_i) {
+                Some(&file) if !views.contains_key(&view_i) => file,
+                _ => continue,
+            };
+            views.insert(view_i, session.views.len());
+            session.views.push(session::View {
+                file,
+                line_offset: *view.line_offset.borrow(),
+                primary: view.selection.primary,
+                selections: view.selection.selections.clone(),
+            });
+        }
+        for (i, tab) in tabs.into_iter().enumerate() {
+            let layout = tab
+                .layout
+                .as_ref()
+                .and_then(|layout| layout.filter_map(&|view_i| views.get(&view_i).copied()));
+            let layout = match layout {
+                Some(layout) => layout,
+                None => continue,
+            };
+            if i == self.tabs.cur() {
+                session.cur_tab = session.tabs.len();
+            }
+            let focused = tab.view_i.and_then(|view_i| views.get(&view_i).copied());
+            session.tabs.push(Tab {
+                name: tab.name.clone(),
+                view_i: focused.filter(|&i| layout.contains(i)),
+                layout: Some(layout),
+            });
+        }
+        if !self.yanked.is_empty() {
+            session
+                .registers
+                .push((register::UNNAMED, self.yanked.clone()));
+        }
+        for (name, register) in self.registers.iter() {
+            session.registers.push((name, register.clone()));
+        }
+        session
+    }
+
+    /// Write the session to `path`, or to `Session.brz` of the project
+    pub(crate) fn write_session(&mut self, path: Option<&Path>) -> Result<(), String> {
+        let path = path.unwrap_or_else(|| Path::new(session::DEFAULT_PATH));
+        let session = self.session();
+        (self.write_handler)(path, session.to_text().as_bytes())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.msg = Some(format!(
+            "session of {} files written to {}",
+            session.files.len(),
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Open the files of the session at `path`, or at `Session.brz` of the
+    /// project, and put their windows back as they were
+    pub fn load_session(&mut self, path: Option<&Path>) -> Result<(), String> {
+        let path = path.unwrap_or_else(|| Path::new(session::DEFAULT_PATH));
+        let text = self
+            .read_file(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .0
+            .to_string();
+        let session = Session::from_text(&text)
+            .ok_or_else(|| format!("{}: not a session", path.display()))?;
+        self.restore_session(session)
+    }
+
+    fn restore_session(&mut self, mut session: Session) -> Result<(), String> {
+        let buffers: Vec<Option<usize>> = session
+            .files
+            .iter()
+            .map(|path| {
+                // relative to the project of the session
+                let path = if self.project_dir == session.project {
+                    path.clone()
+                } else {
+                    session.project.join(path)
+                };
+                self.open_buffer(&path);
+                self.buffer_i_by_path(&path)
+            })
+            .collect();
+        let views: Vec<Option<usize>> = session
+            .views
+            .iter()
+            .map(|view| {
+                let buffer_i = buffers[view.file]?;
+                let text = &self.buffers[buffer_i].buffer.text;
+                let end = |idx: Idx| Idx(idx.0.min(text.len_chars()));
+                let selections = view
+                    .selections
+                    .iter()
+                    .map(|s| Selection::new_from_normalized(end(s.anchor), end(s.cursor)))
+                    .collect();
+                let line_offset = view.line_offset.min(text.len_lines() - 1);
+                Some(self.views.insert(ViewState {
+                    selection: SelectionSet {
+                        primary: view.primary,
+                        selections,
+                        cursor_column: vec![],
+                    },
+                    line_offset: RefCell::new(line_offset),
+                    ..ViewState::new(buffer_i)
+                }))
+            })
+            .collect();
+        let mut cur_tab = session.cur_tab;
+        let mut tabs = vec![];
+        for (i, tab) in session.tabs.drain(..).enumerate() {
+            let layout = tab
+                .layout
+                .and_then(|layout| layout.filter_map(&|view_i| views[view_i]));
+            let layout = match layout {
+                Some(layout) => layout,
+                None => {
+                    cur_tab -= (i < session.cur_tab) as usize;
+                    continue;
+                }
+            };
+            let focused = tab.view_i.and_then(|view_i| views[view_i]);
+            tabs.push(Tab {
+                name: tab.name,
+                view_i: focused
+                    .filter(|&i| layout.contains(i))
+                    .or_else(|| Some(layout.windows()[0])),
+                layout: Some(layout),
+            });
+        }
+        if tabs.is_empty() {
+            return Err("no file of the session could be opened".into());
+        }
+        self.tabs = Tabs::restore(tabs, cur_tab, &mut self.layout, &mut self.cur_view_i);
+        for (name, register) in session.registers {
+            match name {
+                register::UNNAMED => self.yanked = register,
+                name => self.registers.set(name, register),
+            }
+        }
+        self.invalidation().invalidate_all();
+        let opened = buffers.iter().flatten().count();
+        self.msg = Some(match session.files.len() - opened {
+            0 => format!("session of {} files", opened),
+            missing => format!("session of {} files, {} not opened", opened, missing),
+        });
+        Ok(())
+    }
+
+    /// Paste the register named before, or the unnamed one, into the
+    /// current buffer, remembering what was pasted
+    pub(crate) fn paste(&mut self, extend: bool) {
+        let name = self.pending_register.take().unwrap_or(register::UNNAMED);
+        let register = match self.register(name) {
+            Ok(register) => register,
+            Err(e) => {
+                self.msg = Some(e);
+                return;
+            }
+        };
+        let history_i = self.yank_history.position(&register).unwrap_or(0);
+        self.paste_register(&register, extend, history_i);
+    }
+
+    fn paste_register(&mut self, register: &Register, extend: bool, history_i: usize) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let text = self.buffers[buffer_i].buffer.text.clone();
+        let selection = self.cur_view().selection.clone();
+        let version = self.buffers[buffer_i].buffer.version();
+        if extend {
+            self.cur_buffer_mut().paste_extend(register);
+        } else {
+            self.cur_buffer_mut().paste(register);
+        }
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state
+            .recent_edits
+            .note_paste(&buffer_state.buffer, version);
+        self.just_pasted = Some(JustPasted {
+            buffer_i,
+            version: buffer_state.buffer.version(),
+            text,
+            selection,
+            history_i,
+            extend,
+        });
+    }
+
+    /// Paste entry `i` of the yank history, making it what's pasted next
+    pub(crate) fn paste_from_history(&mut self, i: usize) {
+        let register = match self.yank_history.get(i) {
+            Some(register) => register.clone(),
+            None => return,
+        };
+        self.commit_undo_point();
+        self.paste_register(&register, false, i);
+        self.yanked = register;
+    }
+
+    /// Swap what was just pasted for the yank before it in the history,
+    /// going back to the newest after the oldest
+    pub(crate) fn paste_older(&mut self) {
+        let just_pasted = match self.just_pasted.take() {
+            Some(pasted)
+                if Some(pasted.buffer_i) == self.cur_buffer_i()
+                    && self.buffers[pasted.buffer_i].buffer.version() == pasted.version =>
+            {
+                pasted
+            }
+            _ => {
+                self.msg = Some("nothing just pasted".into());
+                return;
+            }
+        };
+        let history_i = (just_pasted.history_i + 1) % self.yank_history.len().max(1);
+        let register = match self.yank_history.get(history_i) {
+            Some(register) => register.clone(),
+            None => return,
+        };
+        let buffer = self.cur_buffer_mut();
+        buffer.buffer.revert_last_change(just_pasted.text);
+        buffer.view.selection = just_pasted.selection;
+        self.paste_register(&register, just_pasted.extend, history_i);
+        self.msg = Some(format!(
+            "yank {}/{}",
+            history_i + 1,
+            self.yank_history.len()
+        ));
+    }
+
+    /// Select the text of a recent edit of the current buffer
+    pub(crate) fn select_recent_edit(&mut self, edit: RecentEdit) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let ranges = match self.buffers[buffer_i].recent_edits.get(edit) {
+            Some(span) => span.ranges.clone(),
+            None => {
+                self.msg = Some(format!("no {} yet", edit.name()));
+                return;
+            }
+        };
+        self.cur_buffer_mut().select_ranges(ranges);
+    }
+
+    pub fn mode_name(&self) -> &str {
+        self.mode.as_ref().expect("mode set").name()
+    }
+
+    pub fn register_read_handler(&mut self, f: impl Fn(&Path) -> io::Result<Vec<u8>> + 'static) {
+        self.read_handler = Arc::new(f);
+    }
+
+    pub fn register_write_handler(&mut self, f: impl Fn(&Path, &[u8]) -> io::Result<()> + 'static) {
+        self.write_handler = Arc::new(f);
+    }
+
+    /// Tell whether a file can be written; files that can't are reported
+    /// when they are opened, instead of when saving them fails
+    pub fn register_writable_handler(&mut self, f: impl Fn(&Path) -> io::Result<bool> + 'static) {
+        self.writable_handler = Arc::new(f);
+    }
+
+    pub fn register_elevated_write_handler(
+        &mut self,
+        f: impl Fn(&Path, &[u8]) -> io::Result<()> + 'static,
+    ) {
+        self.elevated_write_handler = Arc::new(f);
+    }
+
+    /// Read, write and find files in `vfs`
+    ///
+    /// This registers the read, write, writable, stat, find and list dir
+    /// handlers; the find handler looks at most at `MAX_FOUND` files
+    /// from the current directory. Watching needs the frontend to tell
+    /// the changes, with `register_watch_handler`.
+    pub fn register_vfs(&mut self, vfs: Arc<dyn Vfs>) {
+        let read = vfs.clone();
+        self.register_read_handler(move |path| read.read(path));
+        let write = vfs.clone();
+        self.register_write_handler(move |path, bytes| write.write(path, bytes));
+        let stat = vfs.clone();
+        self.register_writable_handler(move |path| match stat.stat(path) {
+            Ok(metadata) => Ok(metadata.writable),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir,
+                    _ => Path::new("."),
+                };
+                stat.stat(dir).map(|metadata| metadata.writable)
+            }
+            Err(e) => Err(e),
+        });
+        let metadata = vfs.clone();
+        self.register_stat_handler(move |path| metadata.stat(path));
+        let list = vfs.clone();
+        self.register_list_dir_handler(move |path| list.list_dir(path));
+        self.register_find_handler(move |pattern| {
+            vfs::find_files(&*vfs, Path::new("."), pattern, MAX_FOUND)
+        });
+    }
+
+    /// Register a handler telling the metadata of a file, eg. when it was
+    /// last modified
+    pub fn register_stat_handler(
+        &mut self,
+        f: impl Fn(&Path) -> io::Result<vfs::Metadata> + 'static,
+    ) {
+        self.stat_handler = Arc::new(f);
+    }
+
+    /// Register a handler watching a file, until the watch it returns is
+    /// dropped
+    ///
+    /// It should not block: the changes are to be passed to
+    /// `file_changed`, with the path watched.
+    pub fn register_watch_handler(
+        &mut self,
+        f: impl Fn(&Path) -> io::Result<vfs::Watch> + 'static,
+    ) {
+        self.watch_handler = Arc::new(f);
+    }
+
+    /// Register a handler listing a directory, eg. to complete paths
+    pub fn register_list_dir_handler(
+        &mut self,
+        f: impl Fn(&Path) -> io::Result<Vec<vfs::DirEntry>> + 'static,
+    ) {
+        self.list_dir_handler = Arc::new(f);
+    }
+
+    /// Add a command to the `:` prompt, replacing the one of the same
+    /// name, as described in `command`
+    pub fn register_command(&mut self, def: CommandDef) {
+        self.commands.register(def);
+    }
+
+    pub fn register_find_handler(
+        &mut self,
+        f: impl Fn(&str) -> io::Result<Vec<PathBuf>> + 'static,
+    ) {
+        self.find_handler = Arc::new(f);
+    }
+
+    /// Register a handler searching all the files for a regex pattern
+    pub fn register_grep_handler(
+        &mut self,
+        f: impl Fn(&str) -> io::Result<Vec<quickfix::Entry>> + 'static,
+    ) {
+        self.grep_handler = Arc::new(f);
+    }
+
+    /// Register a handler searching all the files for a regex pattern, in
+    /// the background
+    ///
+    /// It should not block: the matches it finds are to be passed to
+    /// `deliver_grep` in batches, the last one once done.
+    pub fn register_project_search_handler(
+        &mut self,
+        f: impl Fn(GrepRequest) -> io::Result<()> + 'static,
+    ) {
+        self.project_search_handler = Arc::new(f);
+    }
+
+    /// Register a handler starting to load a preview
+    ///
+    /// It should not block: the result is to be passed to
+    /// `deliver_preview` once ready.
+    pub fn register_preview_handler(
+        &mut self,
+        f: impl Fn(PreviewRequest) -> io::Result<()> + 'static,
+    ) {
+        self.preview_handler = Arc::new(f);
+    }
+
+    pub fn register_git_handler(
+        &mut self,
+        f: impl Fn(&GitRequest) -> io::Result<String> + 'static,
+    ) {
+        self.git_handler = Arc::new(f);
+    }
+
+    /// Register a handler moving files to the trash and back
+    ///
+    /// Without one, or if it fails with `Unsupported`, files are deleted
+    /// outright.
+    pub fn register_clipboard_handler(
+        &mut self,
+        f: impl Fn(&ClipboardOp) -> io::Result<Option<String>> + 'static,
+    ) {
+        self.clipboard_handler = Arc::new(f);
+    }
+
+    /// Check with `check` for the section `name` of `:health`, in place
+    /// of the check registered for it before
+    pub fn register_health_check(
+        &mut self,
+        name: &str,
+        check: impl Fn() -> Vec<Finding> + 'static,
+    ) {
+        self.health_checks.retain(|(n, _)| n != name);
+        self.health_checks.push((name.to_owned(), Arc::new(check)));
+    }
+
+    pub fn register_undo_file_handler(
+        &mut self,
+        f: impl Fn(&UndoFileOp) -> io::Result<Option<String>> + 'static,
+    ) {
+        self.undo_file_handler = Arc::new(f);
+    }
+
+    /// Register a handler keeping the swap files, as described in
+    /// `recover`
+    pub fn register_swap_handler(&mut self, f: impl Fn(&SwapOp) -> io::Result<()> + 'static) {
+        self.swap_handler = Arc::new(f);
+    }
+
+    pub fn register_trash_handler(
+        &mut self,
+        f: impl Fn(&TrashOp) -> io::Result<PathBuf> + 'static,
+    ) {
+        self.trash_handler = Arc::new(f);
+    }
+
+    /// Run the plugin `name`, told of the events by `handler`, as
+    /// described in `plugin`; returns its index, to pass its requests to
+    /// `deliver_plugin_request` with
+    pub fn register_plugin(
+        &mut self,
+        name: &str,
+        handler: impl Fn(&plugin::Event) -> io::Result<()> + 'static,
+    ) -> usize {
+        let handler: PluginHandler = Arc::new(handler);
+        if let Err(e) = handler(&plugin::Event::Hello) {
+            self.msg = Some(format!("plugin {}: {}", name, e));
+        }
+        self.plugins.push((name.to_owned(), handler));
+        self.plugins.len() - 1
+    }
+
+    /// Names of the plugins running
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.plugins.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    fn notify_plugin(&mut self, plugin_i: usize, event: &plugin::Event) {
+        let (name, handler) = match self.plugins.get(plugin_i) {
+            Some((name, handler)) => (name.clone(), handler.clone()),
+            None => return,
+        };
+        if let Err(e) = handler(event) {
+            self.msg = Some(format!("plugin {}: {}", name, e));
+        }
+    }
+
+    fn notify_plugins(&mut self, event: &plugin::Event) {
+        for plugin_i in 0..self.plugins.len() {
+            self.notify_plugin(plugin_i, event);
+        }
+    }
+
+    /// Tell the plugins `buffer_i` changed, if it's of a file
+    fn notify_change(&mut self, buffer_i: usize) {
+        if self.plugins.is_empty() {
+            return;
+        }
+        let buffer_state = &self.buffers[buffer_i];
+        if let Some(path) = buffer_state.document.path.clone() {
+            let version = buffer_state.buffer.version();
+            self.notify_plugins(&plugin::Event::Change(path, version));
+        }
+    }
+
+    /// What a client of `--server` is told of the editor: its mode, the
+    /// buffers, and the current one with its selections, and its text if
+    /// `text`
+    pub fn remote_state(&self, text: bool) -> json::Value {
+        let path_of = |buffer_state: &BufferState| {
+            buffer_state
+                .document
+                .path
+                .as_ref()
+                .map_or(json::Value::Null, |path| {
+                    path.to_string_lossy().into_owned().into()
+                })
+        };
+        let buffers = self
+            .buffers
+            .iter()
+            .map(|(_, buffer_state)| {
+                json::Value::object(vec![
+                    ("path", path_of(buffer_state)),
+                    (
+                        "modified",
+                        (buffer_state.written_version != buffer_state.buffer.version()).into(),
+                    ),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let current = match self.cur_view_opt() {
+            Some(view) => {
+                let buffer_state = &self.buffers[view.buffer_i];
+                let mut members = vec![
+                    ("path", path_of(buffer_state)),
+                    ("version", buffer_state.buffer.version().into()),
+                    (
+                        "selections",
+                        json::Value::Array(
+                            view.selection
+                                .selections
+                                .iter()
+                                .map(|sel| vec![sel.anchor.0.into(), sel.cursor.0.into()].into())
+                                .collect(),
+                        ),
+                    ),
+                ];
+                if text {
+                    members.push(("text", buffer_state.buffer.text.to_string().into()));
+                }
+                json::Value::object(members)
+            }
+            None => json::Value::Null,
+        };
+        json::Value::object(vec![
+            ("mode", self.get_mode().name().into()),
+            ("buffers", buffers.into()),
+            ("current", current),
+            (
+                "message",
+                self.msg
+                    .clone()
+                    .map_or(json::Value::Null, json::Value::from),
+            ),
+        ])
+    }
+
+    /// Do what the plugin `plugin_i` asks
+    pub fn deliver_plugin_request(&mut self, plugin_i: usize, request: plugin::Request) {
+        use plugin::Request;
+        let name = match self.plugins.get(plugin_i) {
+            Some((name, _)) => name.clone(),
+            None => return,
+        };
+        let result = match request {
+            Request::Text { id } => {
+                let text = self.cur_buffer_opt().map(|buffer| buffer.text.to_string());
+                let result = text.map_or(json::Value::Null, json::Value::from);
+                self.notify_plugin(plugin_i, &plugin::Event::Reply { id, result });
+                Ok(())
+            }
+            Request::Selections { id } => {
+                let result = match self.cur_view_opt() {
+                    Some(view) => json::Value::Array(
+                        view.selection
+                            .selections
+                            .iter()
+                            .map(|sel| vec![sel.anchor.0.into(), sel.cursor.0.into()].into())
+                            .collect(),
+                    ),
+                    None => json::Value::Null,
+                };
+                self.notify_plugin(plugin_i, &plugin::Event::Reply { id, result });
+                Ok(())
+            }
+            Request::Edit { range, text } => self.plugin_edit(range, &text),
+            Request::Select(ranges) => match self.cur_buffer_mut_opt() {
+                Some(mut buffer) => {
+                    let len = buffer.text().len_chars();
+                    buffer.select_ranges(
+                        ranges
+                            .into_iter()
+                            .map(|(anchor, cursor)| anchor.min(len)..cursor.min(len))
+                            .collect(),
+                    );
+                    Ok(())
+                }
+                None => Err("no buffer".to_owned()),
+            },
+            Request::Command {
+                name: command,
+                help,
+            } => {
+                let sent = command.clone();
+                self.register_command(CommandDef::new(
+                    &command,
+                    "[<args>…]",
+                    &help,
+                    move |state, args| {
+                        state.notify_plugin(
+                            plugin_i,
+                            &plugin::Event::Command {
+                                name: sent.clone(),
+                                args: args.rest.to_owned(),
+                            },
+                        );
+                        Ok(())
+                    },
+                ));
+                Ok(())
+            }
+            Request::Bind { mode, keys, run } => {
+                keymap::single(&mode, &keys, &run).map(|keymap| self.keymaps.define(vec![keymap]))
+            }
+            Request::Overlay { title, lines } => {
+                self.plugin_overlay = Some(plugin::Overlay {
+                    plugin: name.clone(),
+                    title,
+                    lines,
+                })
+                .filter(|overlay| !overlay.lines.is_empty());
+                Ok(())
+            }
+            Request::Message(text) => {
+                self.msg = Some(text);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.msg = Some(format!("plugin {}: {}", name, e));
+        }
+    }
+
+    /// Replace `range` of the current buffer with `text`, for a plugin,
+    /// in an undo point of its own
+    fn plugin_edit(&mut self, range: Range<usize>, text: &str) -> Result<(), String> {
+        let buffer_i = self.cur_buffer_i().ok_or("no buffer")?;
+        self.check_modifiable(buffer_i)?;
+        let buffer = &self.buffers[buffer_i].buffer;
+        if buffer.text.len_chars() < range.end {
+            return Err(format!("edit past the end, at {}", range.end));
+        }
+        let version = buffer.version();
+        self.commit_undo_point();
+        self.cur_buffer_mut()
+            .edit_ranges(vec![(range, text.to_owned())]);
+        self.commit_undo_point();
+        self.fix_other_views(buffer_i, self.cur_view_i, version);
+        self.invalidation().invalidate_buffer(buffer_i);
+        self.sync_documents();
+        self.notify_change(buffer_i);
+        Ok(())
+    }
+
+    pub fn register_chdir_handler(&mut self, f: impl Fn(&Path) -> io::Result<()> + 'static) {
+        self.chdir_handler = Arc::new(f);
+    }
+
+    /// Have `delete_file` move files to the trash, or delete them outright
+    pub fn set_delete_to_trash(&mut self, trash: bool) {
+        self.delete_to_trash = trash;
+    }
+
+    /// Keep the scratch buffers for the next session, or let them go
+    pub fn set_keep_scratch(&mut self, keep: bool) {
+        self.keep_scratch = keep;
+    }
+
+    pub fn keeps_scratch(&self) -> bool {
+        self.keep_scratch
+    }
+
+    /// Register a handler creating, renaming and deleting files
+    ///
+    /// It's expected to honour the options of the operation, eg. fail to
+    /// create a file that exists unless told to overwrite it or ignore it.
+    pub fn register_resource_handler(
+        &mut self,
+        f: impl Fn(&lsp::ResourceOp) -> io::Result<()> + 'static,
+    ) {
+        self.resource_handler = Arc::new(f);
+    }
+
+    /// Start previewing `path`, cancelling the pending preview, if any
+    ///
+    /// `None` clears the preview.
+    pub(crate) fn request_preview(&mut self, path: Option<&Path>) {
+        if self.preview.as_ref().map(|p| p.path.as_path()) == path {
+            return;
+        }
+        if let Some(prev) = self.preview.take() {
+            prev.cancel.cancel();
+        }
+        let path = if let Some(path) = path {
+            path.to_owned()
+        } else {
+            return;
+        };
+
+        let id = self.next_preview_id;
+        self.next_preview_id += 1;
+        let cancel = preview::CancelToken::default();
+        let preview = match (self.preview_handler)(PreviewRequest {
+            id,
+            path: path.clone(),
+            max_bytes: preview::DEFAULT_MAX_BYTES,
+            cancel: cancel.clone(),
+        }) {
+            Ok(()) => Preview::Loading,
+            Err(e) => Preview::Failed(e.to_string()),
+        };
+        self.preview = Some(CurrentPreview {
+            id,
+            path,
+            cancel,
+            preview,
+        });
+    }
+
+    /// Pass the result of a `PreviewRequest`
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_preview(&mut self, id: u64, preview: Preview) -> bool {
+        match self.preview {
+            Some(ref mut current) if current.id == id => {
+                current.preview = preview;
+                return true;
+            }
+            _ => {}
+        }
+        // or the beginning of a file too large to open
+        let buffer_i =
+            self.buffers
+                .iter()
+                .find_map(|(i, buffer_state)| match buffer_state.document.kind {
+                    DocumentKind::Preview(ref preview) if preview.id == id && !preview.loaded => {
+                        Some(i)
+                    }
+                    _ => None,
+                });
+        let buffer_i = match buffer_i {
+            Some(buffer_i) => buffer_i,
+            None => return false,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        match preview {
+            Preview::Loading => return false,
+            Preview::Text { text, .. } => buffer_state.buffer.replace_text(text),
+            Preview::Binary => self.msg = Some("binary file: nothing to show".into()),
+            Preview::Failed(e) => self.msg = Some(e),
+        }
+        if let DocumentKind::Preview(ref mut preview) = self.buffers[buffer_i].document.kind {
+            preview.loaded = true;
+        }
+        self.invalidation().invalidate_buffer(buffer_i);
+        true
+    }
+
+    pub fn cur_preview(&self) -> Option<&Preview> {
+        self.preview.as_ref().map(|p| &p.preview)
+    }
+
+    /// Register a handler starting to highlight a buffer
+    ///
+    /// It should not block: the result is to be passed to
+    /// `deliver_highlights` once ready.
+    pub fn register_highlight_handler(
+        &mut self,
+        f: impl Fn(HighlightRequest) -> io::Result<()> + 'static,
+    ) {
+        self.highlight_handler = Arc::new(f);
+    }
+
+    /// Highlight the buffers with `highlighter`, instead of the rules of
+    /// `syntax`
+    ///
+    /// The highlights already there are asked for again.
+    pub fn register_highlighter(&mut self, highlighter: Arc<dyn Highlighter>) {
+        self.highlighter = highlighter;
+        let buffers: Vec<usize> = self.buffers.iter().map(|(i, _)| i).collect();
+        for buffer_i in buffers {
+            self.buffers[buffer_i].highlights.cancel();
+            self.buffers[buffer_i].highlights = Highlights::default();
+            self.end_highlight_task(buffer_i);
+            self.invalidation().invalidate_buffer(buffer_i);
+        }
+    }
+
+    /// Start highlighting the current buffer, if it changed since
+    ///
+    /// To be called when the input is idle.
+    pub fn request_highlights(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let language = match buffer_state.document.language {
+            Some(ref language)
+                if !buffer_state.document.reduced
+                    && buffer_state.highlights.is_outdated(&buffer_state.buffer) =>
+            {
+                language.clone()
+            }
+            _ => return,
+        };
+        let id = self.next_highlight_id;
+        self.next_highlight_id += 1;
+        let cancel = preview::CancelToken::default();
+        buffer_state
+            .highlights
+            .start(id, &buffer_state.buffer, cancel.clone());
+        if let Some(task) = buffer_state.highlight_task.take() {
+            self.tasks.end(task);
+        }
+        if HIGHLIGHT_TASK_LINES <= buffer_state.buffer.lines() {
+            buffer_state.highlight_task = Some(self.tasks.begin("highlighting"));
+        }
+        let request = HighlightRequest {
+            id,
+            language,
+            text: buffer_state.buffer.text.clone(),
+            cancel,
+            highlighter: self.highlighter.clone(),
+        };
+        if (self.highlight_handler)(request).is_err() {
+            self.buffers[buffer_i].highlights.cancel();
+            self.end_highlight_task(buffer_i);
+        }
+    }
+
+    /// Pass the result of a `HighlightRequest`
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_highlights(&mut self, id: u64, highlights: Vec<Highlight>) -> bool {
+        let buffer_i = match self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| buffer_state.highlights.pending_id() == Some(id))
+        {
+            Some((buffer_i, _)) => buffer_i,
+            None => return false,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        if !buffer_state
+            .highlights
+            .deliver(id, highlights, &buffer_state.buffer)
+        {
+            return false;
+        }
+        self.end_highlight_task(buffer_i);
+        self.invalidation().invalidate_buffer(buffer_i);
+        true
+    }
+
+    fn end_highlight_task(&mut self, buffer_i: usize) {
+        if let Some(task) = self.buffers[buffer_i].highlight_task.take() {
+            self.tasks.end(task);
+        }
+    }
+
+    /// Register a handler getting the content of a file in `HEAD`, for
+    /// the gutter
+    ///
+    /// It should not block: the content is to be passed to
+    /// `deliver_git_base` once read.
+    pub fn register_git_base_handler(
+        &mut self,
+        f: impl Fn(GitBaseRequest) -> io::Result<()> + 'static,
+    ) {
+        self.git_base_handler = Arc::new(f);
+    }
+
+    /// Get the file of a buffer as in `HEAD` again, to mark the lines
+    /// changed since in the gutter
+    fn request_git_base(&mut self, buffer_i: usize) {
+        let buffer_state = &mut self.buffers[buffer_i];
+        let path = match buffer_state.document.path {
+            Some(ref path) if !buffer_state.document.reduced => path.clone(),
+            _ => return,
+        };
+        let id = self.next_git_base_id;
+        self.next_git_base_id += 1;
+        buffer_state.gutter.start(id);
+        let _ = (self.git_base_handler)(GitBaseRequest { id, path });
+    }
+
+    /// Pass the result of a `GitBaseRequest`: the content of the file in
+    /// `HEAD`, if it's in it
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_git_base(&mut self, id: u64, base: Option<String>) -> bool {
+        let buffer_i = match self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| buffer_state.gutter.pending_id() == Some(id))
+        {
+            Some((buffer_i, _)) => buffer_i,
+            None => return false,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let base = base.map(|base| Rope::from_str(&base));
+        if !buffer_state.gutter.deliver(id, base, &buffer_state.buffer) {
+            return false;
+        }
+        self.invalidation().invalidate_buffer(buffer_i);
+        true
+    }
+
+    /// Mark the lines of the current buffer changed since `HEAD` again,
+    /// if it changed
+    ///
+    /// To be called when the input is idle.
+    pub fn update_git_gutter(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        if buffer_state.gutter.update(&buffer_state.buffer) {
+            self.invalidation().invalidate_buffer(buffer_i);
+        }
+    }
+
+    /// Register a handler starting to scan the workspace for tagged
+    /// comments
+    ///
+    /// It should not block: what `todo::scan` finds in the files is to
+    /// be passed to `deliver_todos` once done.
+    pub fn register_todo_handler(&mut self, f: impl Fn(TodoRequest) -> io::Result<()> + 'static) {
+        self.todo_handler = Arc::new(f);
+    }
+
+    /// Start scanning the workspace for TODO, FIXME and HACK comments,
+    /// cancelling the pending scan
+    pub fn request_todos(&mut self) -> io::Result<()> {
+        self.cancel_todo_scan();
+        let id = self.next_todo_id;
+        self.next_todo_id += 1;
+        let cancel = preview::CancelToken::default();
+        let request = TodoRequest {
+            id,
+            cancel: cancel.clone(),
+        };
+        (self.todo_handler)(request)?;
+        self.todo_scan = Some((id, cancel, self.tasks.begin("scanning todos")));
+        Ok(())
+    }
+
+    fn cancel_todo_scan(&mut self) {
+        if let Some((_, cancel, task)) = self.todo_scan.take() {
+            cancel.cancel();
+            self.tasks.end(task);
+        }
+    }
+
+    /// Pass the result of a `TodoRequest`
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_todos(&mut self, id: u64, mut todos: Vec<Todo>) -> bool {
+        match self.todo_scan {
+            Some((pending, _, _)) if pending == id => {}
+            _ => return false,
+        }
+        self.cancel_todo_scan();
+        // grouped by tag, in the order the files were walked
+        todos.sort_by_key(|todo| todo.tag);
+        self.todos = Some(todos);
+        if self.open_todos_when_scanned {
+            self.open_todos_when_scanned = false;
+            self.open_todos();
+        }
+        true
+    }
+
+    /// Register a handler listing the files of the project, in the
+    /// background
+    ///
+    /// It should not block: the paths it finds are to be passed to
+    /// `deliver_files` in batches, the last one once done.
+    pub fn register_file_list_handler(
+        &mut self,
+        f: impl Fn(FileListRequest) -> io::Result<()> + 'static,
+    ) {
+        self.file_list_handler = Arc::new(f);
+    }
+
+    /// Start listing the files of the project for the fuzzy finder,
+    /// dropping those listed before; `false` if it can't be
+    pub(crate) fn list_files(&mut self) -> bool {
+        self.stop_listing_files();
+        let id = self.next_file_list_id;
+        self.next_file_list_id += 1;
+        let cancel = preview::CancelToken::default();
+        let request = FileListRequest {
+            id,
+            cancel: cancel.clone(),
+        };
+        if let Err(e) = (self.file_list_handler)(request) {
+            self.msg = Some(format!("listing files: {}", e));
+            return false;
+        }
+        let task = self.tasks.begin("listing files");
+        self.file_list = Some(FileList::new(id, cancel, task));
+        true
+    }
+
+    /// Drop the files listed, cancelling the walk if it's not done
+    pub(crate) fn stop_listing_files(&mut self) {
+        if let Some(mut list) = self.file_list.take() {
+            list.cancel.cancel();
+            if let Some(task) = list.task.take() {
+                self.tasks.end(task);
+            }
+        }
+    }
+
+    /// Pass a batch of the paths found for a `FileListRequest`, `done`
+    /// for the last one
+    ///
+    /// Returns `false` if the request is no longer current and the paths
+    /// were dropped.
+    pub fn deliver_files(&mut self, id: u64, paths: Vec<PathBuf>, done: bool) -> bool {
+        let list = match self.file_list {
+            Some(ref mut list) if list.id == id => list,
+            _ => return false,
+        };
+        list.add(paths);
+        if done {
+            list.done = true;
+            if let Some(task) = list.task.take() {
+                self.tasks.end(task);
+            }
+        }
+        true
+    }
+
+    /// Open the TODO, FIXME and HACK comments of the workspace in a
+    /// quickfix buffer, grouped by tag
+    ///
+    /// The workspace is scanned again meanwhile; without a previous scan,
+    /// the list opens once it's done.
+    pub fn show_todos(&mut self) {
+        if self.todos.is_some() {
+            self.open_todos();
+        } else {
+            self.open_todos_when_scanned = true;
+        }
+        if let Err(e) = self.request_todos() {
+            self.open_todos_when_scanned = false;
+            self.msg = Some(format!("todos: {}", e));
+        }
+    }
+
+    fn open_todos(&mut self) {
+        let entries: Vec<quickfix::Entry> = self
+            .todos
+            .iter()
+            .flatten()
+            .map(|todo| todo.entry.clone())
+            .collect();
+        if entries.is_empty() {
+            self.msg = Some("no TODO, FIXME or HACK comments".into());
+            return;
+        }
+        let quickfix = Quickfix::new(entries);
+        self.open_special_buffer(&quickfix.to_text(), DocumentKind::Quickfix(quickfix));
+    }
+
+    /// Counts of the tagged comments found by the last scan, eg. "3 todo"
+    pub fn todo_summary(&self) -> Option<String> {
+        todo::summary(self.todos.as_ref()?)
+    }
+
+    pub fn register_pipe_handler(&mut self, f: impl Fn(PipeRequest) -> io::Result<()> + 'static) {
+        self.pipe_handler = Arc::new(f);
+    }
+
+    /// Run `command` for each selection, with its text as the input, to
+    /// replace it with the output, or with `insert`, once with no input,
+    /// to insert the output before each selection, as described in `pipe`
+    pub fn pipe_selections(&mut self, command: &str, insert: bool) {
+        let (view_i, buffer_i) = match self.cur_view_i {
+            Some(view_i) => (view_i, self.views[view_i].buffer_i),
+            None => return,
+        };
+        if let Err(e) = self.check_modifiable(buffer_i) {
+            self.msg = Some(e);
+            return;
+        }
+        self.cancel_pipe();
+        let buffer = &self.buffers[buffer_i].buffer;
+        let mut ranges: Vec<_> = self.views[view_i]
+            .selection
+            .selections
+            .iter()
+            .map(|sel| sel.sorted_range_usize())
+            .collect();
+        let inputs = if insert {
+            ranges = ranges
+                .iter()
+                .map(|range| range.start..range.start)
+                .collect();
+            None
+        } else {
+            Some(
+                ranges
+                    .iter()
+                    .map(|range| buffer.text.slice(range.clone()).to_string())
+                    .collect(),
+            )
+        };
+        let id = self.next_pipe_id;
+        self.next_pipe_id += 1;
+        let cancel = preview::CancelToken::default();
+        let request = PipeRequest {
+            id,
+            command: command.to_owned(),
+            inputs,
+            cancel: cancel.clone(),
+        };
+        let version = buffer.version();
+        if let Err(e) = (self.pipe_handler)(request) {
+            self.msg = Some(format!("{}: {}", command, e));
+            return;
+        }
+        self.pending_pipe = Some(PendingPipe {
+            id,
+            command: command.to_owned(),
+            buffer_i,
+            view_i,
+            version,
+            ranges,
+            insert,
+            cancel,
+            task: self.tasks.begin(&format!("running {}", command)),
+        });
+    }
+
+    fn cancel_pipe(&mut self) {
+        if let Some(pending) = self.pending_pipe.take() {
+            pending.cancel.cancel();
+            self.tasks.end(pending.task);
+        }
+    }
+
+    /// Pass the output of each run of a `PipeRequest`, or why it failed
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_pipe(&mut self, id: u64, outputs: Result<Vec<String>, String>) -> bool {
+        let pending = match self.pending_pipe.take() {
+            Some(pending) if pending.id == id => pending,
+            other => {
+                self.pending_pipe = other;
+                return false;
+            }
+        };
+        self.tasks.end(pending.task);
+        let edits = match outputs.and_then(|outputs| pending.edits(outputs)) {
+            Ok(edits) => edits,
+            Err(e) => {
+                self.msg = Some(e);
+                return true;
+            }
+        };
+        let buffer_i = pending.buffer_i;
+        let changed = self
+            .buffers
+            .get(buffer_i)
+            .is_none_or(|buffer_state| buffer_state.buffer.version() != pending.version);
+        let view_i = match self.views.get(pending.view_i) {
+            Some(view) if view.buffer_i == buffer_i => Some(pending.view_i),
+            _ => self
+                .views
+                .iter()
+                .find(|(_, view)| view.buffer_i == buffer_i)
+                .map(|(view_i, _)| view_i),
+        };
+        let view_i = match view_i {
+            Some(view_i) if !changed => view_i,
+            _ => {
+                self.msg = Some(format!(
+                    "{}: the buffer changed meanwhile, output dropped",
+                    pending.command
+                ));
+                return true;
+            }
+        };
+        if let Err(e) = self.check_modifiable(buffer_i) {
+            self.msg = Some(format!("{}: {}, output dropped", pending.command, e));
+            return true;
+        }
+        let now = self.now;
+        let buffer = &mut self.buffers[buffer_i].buffer;
+        let view = &mut self.views[view_i];
+        buffer.history.now = now;
+        buffer.maybe_commit_undo_point(&view.selection);
+        let mut edit = BufferView::new(buffer, view);
+        edit.replace_ranges(edits);
+        edit.buffer.maybe_commit_undo_point(&edit.view.selection);
+        self.invalidation().invalidate_buffer(buffer_i);
+        self.sync_documents();
+        true
+    }
+
+    /// Start reporting the progress of a background task
+    ///
+    /// The task is shown until `end_task` is called with the returned id.
+    pub fn begin_task(&mut self, name: &str) -> TaskId {
+        self.tasks.begin(name)
+    }
+
+    /// Tell how far along a task is: a percentage, if it's known, and what
+    /// it's doing, eg. the file being indexed
+    pub fn report_task(&mut self, id: TaskId, percentage: Option<u8>, message: Option<&str>) {
+        self.tasks.report(id, percentage, message);
+    }
+
+    pub fn end_task(&mut self, id: TaskId) {
+        self.tasks.end(id);
+    }
+
+    pub fn task_summary(&self) -> Option<String> {
+        self.tasks.summary()
+    }
+
+    /// Register a handler starting to get the code lenses of a file
+    ///
+    /// It should not block: the result is to be passed to
+    /// `deliver_code_lenses` once ready.
+    pub fn register_code_lens_handler(
+        &mut self,
+        f: impl Fn(lsp::CodeLensRequest) -> io::Result<()> + 'static,
+    ) {
+        self.code_lens_handler = Arc::new(f);
+    }
+
+    /// Register a handler running the commands of code lenses
+    pub fn register_command_handler(
+        &mut self,
+        f: impl Fn(&lsp::Command) -> io::Result<()> + 'static,
+    ) {
+        self.command_handler = Arc::new(f);
+    }
+
+    /// Get the code lenses of the current buffer again
+    ///
+    /// Done when a file is opened and saved.
+    pub fn request_code_lenses(&mut self) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let document = &buffer_state.document;
+        let path = match document.path {
+            Some(ref path) if document.kind == DocumentKind::Text && !document.reduced => {
+                path.clone()
+            }
+            _ => return,
+        };
+        let id = self.next_code_lens_id;
+        self.next_code_lens_id += 1;
+        buffer_state.code_lenses.start(id, &buffer_state.buffer);
+        let request = lsp::CodeLensRequest {
+            id,
+            path,
+            text: buffer_state.buffer.text.clone(),
+        };
+        if (self.code_lens_handler)(request).is_err() {
+            self.buffers[buffer_i].code_lenses.cancel();
+        }
+    }
+
+    /// Pass the result of a `CodeLensRequest`
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_code_lenses(&mut self, id: u64, lenses: Vec<lsp::CodeLens>) -> bool {
+        let buffer_i = match self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| buffer_state.code_lenses.pending_id() == Some(id))
+        {
+            Some((buffer_i, _)) => buffer_i,
+            None => return false,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        if !buffer_state
+            .code_lenses
+            .deliver(id, lenses, &buffer_state.buffer)
+        {
+            return false;
+        }
+        self.invalidation().invalidate_buffer(buffer_i);
+        true
+    }
+
+    /// Code lenses shown above a line of the buffer of a view
+    fn line_code_lenses(&self, view_i: usize, line: usize) -> impl Iterator<Item = &lsp::Command> {
+        let buffer_state = &self.buffers[self.views[view_i].buffer_i];
+        let text = &buffer_state.buffer.text;
+        let start = text.line_to_char(line);
+        buffer_state
+            .code_lenses
+            .in_range(start..start + text.line(line).len_chars())
+            .iter()
+            .filter_map(|lens| lens.command.as_ref())
+    }
+
+    /// Run the `n`th (from 1) code lens of the cursor line
+    pub fn run_code_lens(&mut self, n: usize) {
+        let line = match self.cur_buffer_opt() {
+            Some(buffer) => self.cur_view().selection.cursor_coord(&buffer.text).line,
+            None => return,
+        };
+        let command = self
+            .line_code_lenses(self.cur_view_i.expect("view"), line)
+            .nth(n.saturating_sub(1))
+            .cloned();
+        let command = match command {
+            Some(command) => command,
+            None => {
+                self.msg = Some("no code lens here".into());
+                return;
+            }
+        };
+        if let Err(e) = (self.command_handler)(&command) {
+            self.msg = Some(format!("{}: {}", command.title, e));
+        }
+    }
+
+    /// Register a handler listing the items of call and type hierarchies
+    pub fn register_hierarchy_handler(
+        &mut self,
+        f: impl Fn(&HierarchyRequest) -> io::Result<Vec<HierarchyItem>> + 'static,
+    ) {
+        self.hierarchy_handler = Arc::new(f);
+    }
+
+    pub(crate) fn hierarchy(&mut self, request: &HierarchyRequest) -> Option<Vec<HierarchyItem>> {
+        match (self.hierarchy_handler)(request) {
+            Ok(items) => Some(items),
+            Err(e) => {
+                self.msg = Some(format!("hierarchy: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Register a handler starting to find the occurrences of a symbol
+    ///
+    /// It should not block: the result is to be passed to
+    /// `deliver_document_highlights` once ready. Buffers with no server
+    /// attached get the occurrences of the word instead.
+    pub fn register_document_highlight_handler(
+        &mut self,
+        f: impl Fn(lsp::DocumentHighlightRequest) -> io::Result<()> + 'static,
+    ) {
+        self.document_highlight_handler = Arc::new(f);
+    }
+
+    /// Find the occurrences of the symbol under the cursor, if it moved
+    ///
+    /// To be called when the input is idle, so moving the cursor around
+    /// doesn't send a request for every key.
+    pub fn request_document_highlights(&mut self) {
+        let (buffer_i, cursor) = match (self.cur_buffer_i(), self.cur_view_opt()) {
+            (Some(buffer_i), Some(view)) => (buffer_i, view.selection.selections[0].cursor),
+            _ => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let buffer = &buffer_state.buffer;
+        if buffer_state.document.reduced || buffer_state.references.is_current(buffer, cursor.0) {
+            return;
+        }
+        let path = buffer_state.document.path.clone();
+        if let (Some(path), false) = (path, buffer_state.document.servers.is_empty()) {
+            let id = self.next_document_highlight_id;
+            self.next_document_highlight_id += 1;
+            buffer_state.references.start(id, buffer, cursor.0);
+            let position = cursor.to_position(&buffer.text);
+            let request = lsp::DocumentHighlightRequest {
+                id,
+                path,
+                position: lsp::Position {
+                    line: position.line,
+                    character: position.column,
+                },
+            };
+            if (self.document_highlight_handler)(request).is_ok() {
+                return;
+            }
+        }
+        let buffer_state = &mut self.buffers[buffer_i];
+        let highlights = lsp::word_highlights(&buffer_state.buffer.text, cursor.0);
+        buffer_state
+            .references
+            .set(highlights, &buffer_state.buffer, cursor.0);
+        self.invalidation().invalidate_buffer(buffer_i);
+    }
+
+    /// Pass the result of a `DocumentHighlightRequest`
+    ///
+    /// Returns `false` if the request is no longer current and the result
+    /// was dropped.
+    pub fn deliver_document_highlights(
+        &mut self,
+        id: u64,
+        highlights: Vec<lsp::DocumentHighlight>,
+    ) -> bool {
+        let buffer_i = match self
+            .buffers
+            .iter()
+            .find(|(_, buffer_state)| buffer_state.references.pending_id() == Some(id))
+        {
+            Some((buffer_i, _)) => buffer_i,
+            None => return false,
+        };
+        if !self.buffers[buffer_i].references.deliver(id, highlights) {
+            return false;
+        }
+        self.invalidation().invalidate_buffer(buffer_i);
+        true
+    }
+
+    /// Register a handler starting to format the code around a char typed
+    ///
+    /// It should not block: the edits are to be passed to
+    /// `deliver_on_type_formatting` once ready. Buffers with no server
+    /// attached get closing brackets indented like the line they open on
+    /// instead.
+    pub fn register_on_type_formatting_handler(
+        &mut self,
+        f: impl Fn(lsp::OnTypeFormattingRequest) -> io::Result<()> + 'static,
+    ) {
+        self.on_type_formatting_handler = Arc::new(f);
+    }
+
+    /// Chars formatting the code around once typed in insert mode
+    pub fn set_format_on_type_triggers(&mut self, triggers: Vec<char>) {
+        self.format_on_type_triggers = triggers;
+    }
+
+    /// Format after `ch` was typed, if it's a trigger
+    pub(crate) fn format_on_type(&mut self, ch: char) {
+        if !self.format_on_type_triggers.contains(&ch) {
+            return;
+        }
+        let (buffer_i, cursor) = match (self.cur_buffer_i(), self.cur_view_opt()) {
+            (Some(buffer_i), Some(view)) => (
+                buffer_i,
+                view.selection.selections[view.selection.primary].cursor,
+            ),
+            _ => return,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        let document = &buffer_state.document;
+        if document.reduced || document.kind != DocumentKind::Text || document.unmodifiable {
+            return;
+        }
+        if let (Some(path), false) = (document.path.clone(), document.servers.is_empty()) {
+            let id = self.next_on_type_formatting_id;
+            self.next_on_type_formatting_id += 1;
+            let version = buffer_state.buffer.version();
+            buffer_state.on_type_formatting = Some((id, version));
+            let position = cursor.to_position(&buffer_state.buffer.text);
+            let request = lsp::OnTypeFormattingRequest {
+                id,
+                path,
+                version,
+                position: lsp::Position {
+                    line: position.line,
+                    character: position.column,
+                },
+                ch,
+            };
+            if (self.on_type_formatting_handler)(request).is_ok() {
+                return;
+            }
+            self.buffers[buffer_i].on_type_formatting = None;
+        }
+        self.indent_closing_brackets(ch);
+    }
+
+    /// Indent the lines starting with the closing bracket `ch` before each
+    /// cursor like the line of the opening bracket
+    fn indent_closing_brackets(&mut self, ch: char) {
+        if !matches!(ch, ')' | ']' | '}') {
+            return;
+        }
+        let language = self.cur_document().language.clone();
+        let mut buffer = self.cur_buffer_mut();
+        let text = buffer.text().clone();
+        let tree = Tree::parse(&text, language.as_deref());
+        let line_indent = |line: usize| -> String {
+            text.line(line)
+                .chars()
+                .take_while(|&ch| ch == ' ' || ch == '\t')
+                .collect()
+        };
+        let mut edits = BTreeMap::new();
+        for sel in &buffer.view.selection.selections {
+            let bracket = match sel.cursor.0.checked_sub(1) {
+                Some(bracket) if text.char(bracket) == ch => bracket,
+                _ => continue,
+            };
+            let line = text.char_to_line(bracket);
+            let line_start = text.line_to_char(line);
+            let indent = line_indent(line);
+            if line_start + indent.chars().count() != bracket {
+                continue;
+            }
+            let open = match tree.group_covering(bracket..bracket + 1) {
+                Some(group) if tree.nodes[group].range.end == bracket + 1 => {
+                    tree.nodes[group].range.start
+                }
+                _ => continue,
+            };
+            let open_indent = line_indent(text.char_to_line(open));
+            if open_indent != indent {
+                edits.insert(line_start, (line_start..bracket, open_indent));
+            }
+        }
+        buffer.edit_ranges(edits.into_values().collect());
+    }
+
+    /// Pass the edits of an `OnTypeFormattingRequest`
+    ///
+    /// Returns `false` if the request is no longer current, or the buffer
+    /// changed since, and the edits were dropped.
+    pub fn deliver_on_type_formatting(&mut self, id: u64, edits: Vec<lsp::TextEdit>) -> bool {
+        let (buffer_i, version) = match self.buffers.iter().find_map(|(buffer_i, buffer_state)| {
+            match buffer_state.on_type_formatting {
+                Some((pending, version)) if pending == id => Some((buffer_i, version)),
+                _ => None,
+            }
+        }) {
+            Some(found) => found,
+            None => return false,
+        };
+        let buffer_state = &mut self.buffers[buffer_i];
+        buffer_state.on_type_formatting = None;
+        if buffer_state.buffer.version() != version || !buffer_state.document.is_modifiable() {
+            return false;
+        }
+        let ranges = match lsp::text_edit_ranges(&buffer_state.buffer.text, &edits) {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                self.msg = Some(format!("formatting: {}", e));
+                return false;
+            }
+        };
+        let view = match self
+            .views
+            .iter_mut()
+            .find(|(_, view)| view.buffer_i == buffer_i)
+        {
+            Some((_, view)) => view,
+            None => return false,
+        };
+        BufferView::new(&mut self.buffers[buffer_i].buffer, view).edit_ranges(ranges);
+        self.invalidation().invalidate_buffer(buffer_i);
+        self.sync_documents();
+        true
+    }
+
+    /// Register a handler keeping the servers up to date with the
+    /// documents
+    ///
+    /// Documents with a language are opened to it once their buffer is
+    /// opened, if the file isn't opened with reduced features; it's to
+    /// start the servers of that language, and attach them with
+    /// `attach_server`.
+    pub fn register_document_sync_handler(&mut self, f: impl Fn(&lsp::DocumentSync) + 'static) {
+        self.document_sync_handler = Arc::new(f);
+    }
+
+    /// Tell the servers of the documents opened, edited, renamed or
+    /// closed since the last time
+    fn sync_documents(&mut self) {
+        let sync = self.document_sync_handler.clone();
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            let document = &buffer_state.document;
+            let version = buffer_state.buffer.version();
+            let path = match (&document.path, &document.language) {
+                (Some(path), Some(_)) if !document.reduced => Some(path),
+                _ => None,
+            };
+            let text = || buffer_state.buffer.text.clone();
+            match (buffer_state.synced.as_ref(), path) {
+                (Some((synced, synced_version)), Some(path)) if synced == path => {
+                    if *synced_version != version {
+                        sync(&lsp::DocumentSync::Change {
+                            path: path.clone(),
+                            version,
+                            text: text(),
+                        });
+                    }
+                }
+                (None, None) => continue,
+                (synced, path) => {
+                    if let Some((synced, _)) = synced {
+                        sync(&lsp::DocumentSync::Close {
+                            path: synced.clone(),
+                        });
+                    }
+                    if let (Some(path), Some(language)) = (path, &document.language) {
+                        sync(&lsp::DocumentSync::Open {
+                            path: path.clone(),
+                            language: language.clone(),
+                            version,
+                            text: text(),
+                        });
+                    }
+                }
+            }
+            buffer_state.synced = path.map(|path| (path.clone(), version));
+        }
+    }
+
+    /// Register a handler asking the servers about a position of a
+    /// document
+    ///
+    /// It should not block: the answer is to be passed to `deliver_query`
+    /// once ready.
+    pub fn register_query_handler(
+        &mut self,
+        f: impl Fn(lsp::QueryRequest) -> io::Result<()> + 'static,
+    ) {
+        self.query_handler = Arc::new(f);
+    }
+
+    /// Ask the servers of the current buffer about the symbol under the
+    /// cursor, dropping the answer to the question asked before
+    pub(crate) fn query(&mut self, query: lsp::Query) {
+        let (buffer_i, cursor) = match (self.cur_buffer_i(), self.cur_view_opt()) {
+            (Some(buffer_i), Some(view)) => (buffer_i, view.selection.selections[0].cursor),
+            _ => return,
+        };
+        let buffer_state = &self.buffers[buffer_i];
+        let servers = &buffer_state.document.servers;
+        let path = match buffer_state.document.path {
+            Some(ref path) if !servers.is_empty() => path.clone(),
+            _ => {
+                self.msg = Some("no language server".into());
+                return;
+            }
+        };
+        let able = servers
+            .iter()
+            .any(|server| server.info.capabilities.iter().any(|c| c == query.name()));
+        if !able {
+            self.msg = Some(format!("no language server does {}", query.name()));
+            return;
+        }
+        let position = cursor.to_position(&buffer_state.buffer.text);
+        if let Some((_, _, task)) = self.pending_query.take() {
+            self.tasks.end(task);
+        }
+        let id = self.next_query_id;
+        self.next_query_id += 1;
+        let request = lsp::QueryRequest {
+            id,
+            query,
+            path,
+            position: lsp::Position {
+                line: position.line,
+                character: position.column,
+            },
+        };
+        if let Err(e) = (self.query_handler)(request) {
+            self.msg = Some(format!("{}: {}", query.name(), e));
+            return;
+        }
+        self.pending_query = Some((id, query, self.tasks.begin(query.name())));
+    }
+
+    /// Pass the answer to a `QueryRequest`: a single definition is jumped
+    /// to, other locations are listed in a quickfix buffer, and hovers
+    /// are shown over the buffer if still in normal mode
+    ///
+    /// Returns `false` if the request is no longer current and the answer
+    /// was dropped.
+    pub fn deliver_query(&mut self, id: u64, result: lsp::QueryResult) -> bool {
+        let query = match self.pending_query.take() {
+            Some((pending, query, task)) if pending == id => {
+                self.tasks.end(task);
+                query
+            }
+            other => {
+                self.pending_query = other;
+                return false;
+            }
+        };
+        match result {
+            lsp::QueryResult::Locations(entries) if entries.is_empty() => {
+                self.msg = Some(format!("no {} found", query.name()));
+            }
+            lsp::QueryResult::Locations(mut entries)
+                if entries.len() == 1 && query == lsp::Query::Definition =>
+            {
+                let entry = entries.remove(0);
+                self.open_location(&remote::Location {
+                    path: entry.path.clone(),
+                    line: Some(entry.line + 1),
+                });
+                let opened = self
+                    .cur_document_opt()
+                    .is_some_and(|document| document.path.as_ref() == Some(&entry.path));
+                if let (true, Some(matched)) = (opened, entry.matched) {
+                    let column = entry
+                        .text
+                        .get(..matched.start)
+                        .unwrap_or("")
+                        .chars()
+                        .count();
+                    let mut edit = self.cur_buffer_mut();
+                    edit.apply_motion(Motion::LineStart, false);
+                    edit.apply_motion(Motion::Right(column), false);
+                }
+            }
+            lsp::QueryResult::Locations(entries) => {
+                let quickfix = Quickfix::new(entries);
+                self.open_special_buffer(&quickfix.to_text(), DocumentKind::Quickfix(quickfix));
+            }
+            lsp::QueryResult::Hover(text) if text.trim().is_empty() => {
+                self.msg = Some("nothing to show".into());
+            }
+            lsp::QueryResult::Hover(text) if self.mode_name() == "normal" => {
+                self.set_mode(mode::Hover::new(text));
+            }
+            lsp::QueryResult::Hover(text) => {
+                self.msg = text
+                    .lines()
+                    .find(|line| !line.trim().is_empty())
+                    .map(str::to_owned);
+            }
+        }
+        true
+    }
+
+    /// Add a source of completions, asked before those already there
+    pub fn register_completion_source(&mut self, source: impl completion::Source + 'static) {
+        self.completion_sources.insert(0, Arc::new(source));
+    }
+
+    /// The buffer and primary cursor the completion menu follows
+    fn completion_cursor(&self) -> Option<(usize, Idx)> {
+        let buffer_i = self.cur_buffer_i()?;
+        let selection = &self.cur_view_opt()?.selection;
+        Some((buffer_i, selection.selections[selection.primary].cursor))
+    }
+
+    /// Open the completion menu for the word before the primary cursor,
+    /// asking the sources what it completes to
+    pub(crate) fn complete(&mut self) {
+        let (buffer_i, cursor) = match self.completion_cursor() {
+            Some(found) => found,
+            None => return,
+        };
+        // for the servers to complete the text as it is
+        self.sync_documents();
+        let buffer_state = &self.buffers[buffer_i];
+        let text = buffer_state.buffer.text.clone();
+        let word = completion::word_before(&text, cursor);
+        let position = cursor.to_position(&text);
+        let id = self.next_completion_id;
+        self.next_completion_id += 1;
+        let request = CompletionRequest {
+            id,
+            path: buffer_state.document.path.clone(),
+            prefix: text.slice(word.clone()).to_string(),
+            text,
+            cursor,
+            position: lsp::Position {
+                line: position.line,
+                character: position.column,
+            },
+        };
+        let mut menu = Completion::new(id, Idx(word.start), &request.prefix);
+        for source in &self.completion_sources {
+            if let Some(candidates) = source.complete(&request) {
+                menu.add(candidates);
+            }
+        }
+        self.completion = Some(menu);
+    }
+
+    /// Pass what a completion source found for a `CompletionRequest`
+    ///
+    /// Returns `false` if the menu was closed, or opened again since, and
+    /// the candidates were dropped.
+    pub fn deliver_completions(&mut self, id: u64, candidates: Vec<Candidate>) -> bool {
+        match self.completion {
+            Some(ref mut menu) if menu.id == id => {
+                menu.add(candidates);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Follow the cursor after a key of insert mode: the menu is filtered
+    /// by the word as it's typed further, and closed once the cursor
+    /// leaves it; it opens once a word `typed` is `MIN_PREFIX` chars long
+    pub(crate) fn update_completion(&mut self, typed: bool) {
+        let (buffer_i, cursor) = match self.completion_cursor() {
+            Some(found) => found,
+            None => {
+                self.completion = None;
+                return;
+            }
+        };
+        let buffer_state = &self.buffers[buffer_i];
+        let text = &buffer_state.buffer.text;
+        let word = completion::word_before(text, cursor);
+        match self.completion {
+            Some(ref mut menu) if menu.start.0 == word.start && !word.is_empty() => {
+                let prefix = text.slice(word).to_string();
+                if prefix != menu.pattern() {
+                    menu.set_pattern(&prefix);
+                }
+            }
+            _ => {
+                self.completion = None;
+                let long_enough = completion::MIN_PREFIX <= word.len();
+                if typed && long_enough && !buffer_state.document.reduced {
+                    self.complete();
+                }
+            }
+        }
+    }
+
+    /// Replace the word before each cursor with the candidate picked;
+    /// `false` if none is
+    ///
+    /// The word is that of each cursor, typed as far as it was there, or
+    /// none; cursors within the same word complete it once.
+    pub(crate) fn accept_completion(&mut self) -> bool {
+        let label = match self
+            .completion
+            .as_ref()
+            .and_then(|menu| menu.selected_candidate())
+        {
+            Some(candidate) => candidate.label.clone(),
+            None => return false,
+        };
+        self.completion = None;
+        let mut buffer = self.cur_buffer_mut();
+        let mut words: Vec<Range<usize>> = buffer
+            .view
+            .selection
+            .selections
+            .iter()
+            .map(|selection| completion::word_before(&buffer.buffer.text, selection.cursor))
+            .collect();
+        words.sort_by_key(|word| (word.start, word.end));
+        let mut edits: Vec<(Range<usize>, String)> = vec![];
+        for word in words {
+            match edits.last_mut() {
+                Some((last, _)) if word.start < last.end || *last == word => {
+                    last.end = last.end.max(word.end);
+                }
+                _ => edits.push((word, label.clone())),
+            }
+        }
+        buffer.edit_ranges(edits);
+        for selection in &mut buffer.view.selection.selections {
+            selection.anchor = selection.cursor;
+        }
+        true
+    }
+
+    /// Mark what has to be drawn again in the next frame
+    pub fn invalidation(&self) -> RefMut<'_, Invalidation> {
+        self.invalidation.borrow_mut()
+    }
+
+    pub fn render(&self, render: &mut dyn Renderer) {
+        self.frame.set(self.frame.get() + 1);
+        *self.frame_invalidation.borrow_mut() = self.invalidation.take();
+        let theme = self
+            .theme
+            .as_ref()
+            .and_then(|name| theme::variant(&self.themes, name, self.background));
+        let mut render = theme::Themed::new(render, theme.map(Theme::overrides));
+        let mut degraded;
+        let render: &mut dyn Renderer = if self.capabilities.is_full() {
+            &mut render
+        } else {
+            degraded = Degraded::new(&mut render, self.capabilities);
+            &mut degraded
+        };
+        self.mode.as_ref().expect("mode set").render(self, render);
+        if self.profiler.show_overlay {
+            self.render_profile_overlay(render);
+        }
+        if self.profiler.show_hud {
+            self.render_debug_hud(render);
+        }
+        if self.tasks.show_list {
+            self.render_tasks_overlay(render);
+        }
+        if let Some(ref overlay) = self.plugin_overlay {
+            let title = format!("{}: {}", overlay.plugin, overlay.title);
+            let inner = mode::render_overlay(self, render, 60, 40, title.trim_end_matches(": "));
+            let mut themed = self.themed(render, "overlay");
+            let style = themed.color_map().default;
+            let mut view = inner.to_renderer(&mut themed);
+            for (y, line) in overlay.lines.iter().take(inner.dimensions.y).enumerate() {
+                view.print(Coord { x: 1, y }, line, style);
+            }
+        }
+        self.render_completion(render);
+    }
+
+    /// A whole frame of `dimensions`, drawn with `color_map`, for the
+    /// frontends that take it all at once
+    ///
+    /// The frame drawn next with `render` draws everything again, as what
+    /// it kept from the last one may not be on the screen.
+    pub fn render_to_frame(&self, dimensions: Coord, color_map: ColorMap) -> Surface {
+        let mut surface = Surface::new(dimensions, color_map);
+        self.render(&mut surface);
+        surface.finish();
+        self.invalidation().invalidate_all();
+        surface
+    }
+
+    /// The completion menu, under the word completed, or over it if
+    /// there's more room there
+    fn render_completion(&self, render: &mut dyn Renderer) {
+        let (completion, cursor) = match (&self.completion, self.cursor_screen_coord.get()) {
+            (Some(completion), Some(cursor)) if completion.is_shown() => {
+                let origin = render.origin();
+                let cursor = Coord {
+                    x: cursor.x.saturating_sub(origin.x),
+                    y: cursor.y.saturating_sub(origin.y),
+                };
+                (completion, cursor)
+            }
+            _ => return,
+        };
+        let label_width = completion
+            .matches()
+            .map(|(candidate, _)| convert::str_width(&candidate.label))
+            .max()
+            .unwrap_or(0);
+        let source_width = completion
+            .matches()
+            .map(|(candidate, _)| convert::str_width(&candidate.source))
+            .max()
+            .unwrap_or(0);
+        let size = Coord {
+            x: label_width + source_width + 4,
+            y: completion.match_count().min(COMPLETION_ROWS),
+        };
+        let anchor = Coord {
+            x: cursor
+                .x
+                .saturating_sub(convert::str_width(completion.pattern()) + 1),
+            y: cursor.y,
+        };
+        let rect = render.dimensions_rect().popup(anchor, size);
+        self.invalidation().invalidate_region(Rect {
+            offset: render.origin() + rect.offset,
+            ..rect
+        });
+        let mut render = self.themed(render, "popup");
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_source = render.color_map().line_num;
+        let mut view = rect.to_renderer(&mut render);
+        view.fill(view.dimensions_rect(), ' ', style_default);
+        // the selected one in sight
+        let rows = rect.dimensions.y;
+        let skip = (completion.selected.unwrap_or(0) + 1).saturating_sub(rows);
+        for (i, (candidate, indices)) in completion.matches().enumerate().skip(skip).take(rows) {
+            let y = i - skip;
+            let style = if completion.selected == Some(i) {
+                style_selected
+            } else {
+                style_default
+            };
+            view.fill(
+                Rect {
+                    offset: Coord { x: 0, y },
+                    dimensions: Coord {
+                        x: rect.dimensions.x,
+                        y: 1,
+                    },
+                },
+                ' ',
+                style,
+            );
+            mode::print_picker_entry(
+                &mut view,
+                Coord { x: 1, y },
+                &candidate.label,
+                indices,
+                style,
+            );
+            view.print(
+                Coord {
+                    x: label_width + 3,
+                    y,
+                },
+                &candidate.source,
+                style.paintover(style_source),
+            );
+        }
+    }
+
+    /// Tasks running, under the top right corner
+    pub fn render_tasks_overlay(&self, render: &mut dyn Renderer) {
+        let mut lines: Vec<String> = self
+            .tasks
+            .tasks()
+            .iter()
+            .map(|task| match task.message {
+                Some(ref message) => format!(" {} {} ", task.title(), message),
+                None => format!(" {} ", task.title()),
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push(" no tasks running ".into());
+        }
+        let style = render.color_map().actions;
+        let dims = render.dimensions();
+        let width = lines
+            .iter()
+            .map(|line| convert::str_width(line))
+            .max()
+            .unwrap_or(0)
+            .min(dims.x);
+        let height = lines.len().min(dims.y.saturating_sub(2));
+        let x = dims.x - width;
+        for (y, line) in lines.iter().take(height).enumerate() {
+            let padding = " ".repeat(width.saturating_sub(convert::str_width(line)));
+            render.print(
+                Coord { x, y: y + 1 },
+                &format!("{}{}", line, padding),
+                style,
+            );
+        }
+        self.invalidation().invalidate_region(Rect {
+            offset: render.origin().add_x(x).add_y(1),
+            dimensions: Coord {
+                x: width,
+                y: height,
+            },
+        });
+    }
+
+    /// Frame time of the previous frame in the top right corner
+    pub fn render_profile_overlay(&self, render: &mut dyn Renderer) {
+        let text = match self.profiler.last() {
+            Some(last) => format!(
+                " {} (p99 {}) ",
+                profile::format_duration(last.total()),
+                profile::format_duration(self.profiler.percentile(0.99, |t| t.total()))
+            ),
+            None => " no frames yet ".into(),
+        };
+        let style = render.color_map().actions;
+        let width = convert::str_width(&text);
+        let x = render.dimensions().x.saturating_sub(width);
+        render.print(Coord { x, y: 0 }, &text, style);
+        self.invalidation().invalidate_region(Rect {
+            offset: render.origin().add_x(x),
+            dimensions: Coord { x: width, y: 1 },
+        });
+    }
+
+    /// Counters of the frame, above the bottom right corner
+    pub fn render_debug_hud(&self, render: &mut dyn Renderer) {
+        let lines = self.debug_hud_lines();
+        let style = render.color_map().actions;
+        let dims = render.dimensions();
+        let width = lines
+            .iter()
+            .map(|line| convert::str_width(line))
+            .max()
+            .unwrap_or(0)
+            .min(dims.x);
+        let height = lines.len().min(dims.y.saturating_sub(1));
+        let x = dims.x - width;
+        let y = dims.y.saturating_sub(1 + height);
+        for (i, line) in lines.iter().take(height).enumerate() {
+            let padding = " ".repeat(width.saturating_sub(convert::str_width(line)));
+            render.print(
+                Coord { x, y: y + i },
+                &format!("{}{}", line, padding),
+                style,
+            );
+        }
+        self.invalidation().invalidate_region(Rect {
+            offset: render.origin().add_x(x).add_y(y),
+            dimensions: Coord {
+                x: width,
+                y: height,
+            },
+        });
+    }
+
+    fn debug_hud_lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        lines.push(match self.profiler.cells_drawn() {
+            Some(cells) => format!(" cells drawn {} ", cells),
+            None => " cells drawn - ".into(),
+        });
+        let invalidation = self.frame_invalidation.borrow();
+        lines.push(if invalidation.is_all_dirty() {
+            " dirty all ".into()
+        } else {
+            format!(
+                " dirty {} buffers, {} regions ",
+                invalidation.dirty_buffers(),
+                invalidation.dirty_regions()
+            )
+        });
+        if let Some(buffer) = self.cur_buffer_opt() {
+            lines.push(format!(
+                " text {}, {} lines ",
+                profile::format_bytes(buffer.text.len_bytes()),
+                buffer.text.len_lines()
+            ));
+            lines.push(format!(
+                " selections {} ",
+                self.cur_view().selection.selections.len()
+            ));
+            lines.push(format!(" undo states {} ", buffer.history.len()));
+        }
+        lines.push(match self.profiler.last_round_trip() {
+            Some(last) => format!(
+                " lsp {} (max {}) ",
+                profile::format_duration(last),
+                profile::format_duration(self.profiler.max_round_trip().unwrap_or(last))
+            ),
+            None => " lsp - ".into(),
+        });
+        for (name, bytes) in self.cache_sizes() {
+            lines.push(format!(" {} {} ", name, profile::format_bytes(bytes)));
+        }
+        lines
+    }
+
+    /// Rough size of what's kept for all the buffers, to draw them or to
+    /// go back to
+    fn cache_sizes(&self) -> Vec<(&'static str, usize)> {
+        use std::mem::size_of_val;
+        let mut highlights = 0;
+        let mut matches = 0;
+        for (_, buffer_state) in self.buffers.iter() {
+            highlights += size_of_val(buffer_state.highlights.highlights());
+            matches += buffer_state
+                .matches
+                .as_ref()
+                .map_or(0, |matches| size_of_val(matches.matches()));
+        }
+        let yanks = self
+            .yank_history
+            .iter()
+            .flat_map(|register| register.parts.iter())
+            .map(Rope::len_bytes)
+            .sum();
+        vec![
+            ("highlights", highlights),
+            ("matches", matches),
+            ("yanks", yanks),
+        ]
+    }
+
+    /// The windows, as laid out, or the splash with no buffer
+    pub fn render_buffer(&self, mut render: &mut dyn Renderer) {
+        let layout = match self.layout {
+            Some(ref layout) if self.cur_buffer_opt().is_some() => layout,
+            _ => {
+                self.render_splash(render);
+                return;
+            }
+        };
+        let mut rect = render.dimensions_rect();
+        if 1 < self.tabs.len() && 1 < rect.dimensions.y {
+            let (tabline, rest) = rect.split_horizontaly_at(1);
+            self.render_tabline(&mut tabline.to_renderer(&mut render));
+            rect = rest;
+        }
+        let placement = layout.place(rect);
+        for &(view_i, rect) in &placement.windows {
+            self.render_window(view_i, &mut rect.to_renderer(&mut render));
+            let buffer_i = self.views[view_i].buffer_i;
+            let buffer_state = &self.buffers[buffer_i];
+            render.window(Window {
+                view_i,
+                rect,
+                path: buffer_state.document.path.clone(),
+                label: self.buffer_label(buffer_i),
+                focused: Some(view_i) == self.cur_view_i,
+                modified: buffer_state.written_version != buffer_state.buffer.version(),
+            });
+        }
+        let style = render.color_map().line_num;
+        for &(direction, rect) in &placement.lines {
+            let ch = match direction {
+                Direction::Horizontal => '─',
+                Direction::Vertical => '│',
+            };
+            render.fill(rect, ch, style);
+        }
+        self.pane_cache
+            .borrow_mut()
+            .retain(|view_i, _| placement.windows.iter().any(|&(i, _)| i == *view_i));
+    }
+
+    /// The tabs, the current one standing out
+    fn render_tabline(&self, render: &mut dyn Renderer) {
+        let color_map = *render.color_map();
+        let style = color_map.default.paintover(color_map.status);
+        render.fill(render.dimensions_rect(), ' ', style);
+        let mut x = 0;
+        for (i, tab) in self.tabs.tabs().iter().enumerate() {
+            let cur = i == self.tabs.cur();
+            let view_i = if cur { self.cur_view_i } else { tab.view_i };
+            let name = match (&tab.name, view_i) {
+                (Some(name), _) => name.clone(),
+                (None, Some(view_i)) => self.buffer_label(self.views[view_i].buffer_i),
+                (None, None) => String::new(),
+            };
+            let label = format!(" {} {} ", i + 1, name);
+            let label_style = if cur {
+                style.paintover(color_map.mode)
+            } else {
+                style
+            };
+            render.print(Coord { x, y: 0 }, &label, label_style);
+            x += convert::str_width(&label);
+        }
+    }
+
+    /// The file name of a buffer, or the kind of buffer it is
+    fn buffer_label(&self, buffer_i: usize) -> String {
+        let document = &self.buffers[buffer_i].document;
+        match document.path.as_ref().and_then(|path| path.file_name()) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => match document.kind {
+                DocumentKind::Text => "scratch".to_owned(),
+                ref kind => kind.name().to_owned(),
+            },
+        }
+    }
+
+    /// A window showing the view `view_i`
+    fn render_window(&self, view_i: usize, render: &mut dyn Renderer) {
+        let view = &self.views[view_i];
+        let document = &self.buffers[view.buffer_i].document;
+        let kind = document.kind.name();
+        let overrides = self.pane_colors.get(kind).into_iter();
+        let mut render = theme::Themed::new(render, overrides.chain(Some(&view.colors)));
+        let mut render: &mut dyn Renderer = &mut render;
+        let default = render.color_map().default;
+        if default.bg.is_some() {
+            // a background of its own goes under the whole pane; what's
+            // kept from the last frame is drawn over it
+            render.fill(render.dimensions_rect(), ' ', default);
+        }
+        if let DocumentKind::GitCommit(ref commit) = document.kind {
+            let (message_rect, diff_rect) = render.dimensions_rect().split_verticaly();
+            let style = render.color_map().special;
+            render::print_text(&mut diff_rect.to_renderer(&mut render), &commit.diff, style);
+            self.render_buffer_text(view_i, &mut message_rect.to_renderer(&mut render));
+            return;
+        }
+        self.render_buffer_text(view_i, render);
+    }
+
+    /// Line numbers and text of the buffer of a view
+    fn render_buffer_text(&self, view_i: usize, mut render: &mut dyn Renderer) {
+        let view = &self.views[view_i];
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        let dims = render.dimensions();
+        let (signs, gutter) = self.sign_columns(view_i);
+        let line_nums_width = self.margin_width(view_i);
+        if dims.x <= line_nums_width || dims.y == 0 {
+            return;
+        }
+
+        let window_height = dims.y;
+        let window_margin = crate::view::scroll_margin(window_height);
+        view.height.set(window_height);
+        let cursor_coord = view.selection.cursor_coord(&buffer.text);
+        let first_line_that_must_be_visible = cursor_coord.line.saturating_sub(window_margin);
+        let last_line_that_must_by_visible = min(
+            cursor_coord.line.saturating_add(window_margin),
+            buffer.lines(),
+        );
+        debug_assert!(first_line_that_must_be_visible <= last_line_that_must_by_visible);
+
+        let mut line_offset = view.line_offset.borrow_mut();
+
+        if first_line_that_must_be_visible < *line_offset {
+            *line_offset = first_line_that_must_be_visible;
+        }
+        if line_offset.saturating_add(window_height) < last_line_that_must_by_visible {
+            *line_offset = last_line_that_must_by_visible.saturating_sub(window_height);
+        }
+
+        let mut start_line = min(*line_offset, buffer.lines().saturating_sub(window_height));
+        let content_width = dims.x.saturating_sub(line_nums_width);
+        let cursor_line = cursor_coord.line;
+        let cursor_column = buffer.to_visual(cursor_coord).column;
+        // lines that don't wrap scroll sideways, like the lines do up and down
+        view.column_offset
+            .set(match self.buffers[view.buffer_i].document.wrap {
+                Wrap::None => {
+                    view::follow_column(view.column_offset.get(), cursor_column, content_width)
+                }
+                Wrap::Chars | Wrap::Words => 0,
+            });
+        let mut skip = 0;
+        let mut rows = self.layout_rows(view_i, start_line, skip, window_height, content_width);
+
+        // virtual and wrapped lines take room too: scroll further to keep
+        // the margin, by rows within the cursor line if it's that long
+        let margin_below = window_margin.min(buffer.lines().saturating_sub(cursor_line + 1));
+        loop {
+            let cursor_row = view::row_of(&rows, cursor_line, cursor_column);
+            if cursor_row.is_some_and(|y| y < window_height && y + margin_below <= window_height) {
+                break;
+            }
+            if start_line < cursor_line {
+                start_line += 1;
+                *line_offset = start_line;
+            } else if cursor_row != Some(0)
+                && rows.first().is_some_and(|row| row.line() == cursor_line)
+            {
+                skip += 1;
+            } else {
+                break;
+            }
+            rows = self.layout_rows(view_i, start_line, skip, window_height, content_width);
+        }
+
+        drop(line_offset);
+        *view.drawn.borrow_mut() = Some(view::Drawn {
+            rect: Rect {
+                offset: render.origin(),
+                dimensions: dims,
+            },
+            margin: line_nums_width,
+            rows: rows.clone(),
+        });
+        if line_nums_width == 0 {
+            self.render_content(view_i, render, &rows);
+            return;
+        }
+        let (line_nums_rect, content_rect) = render
+            .dimensions_rect()
+            .split_verticaly_at(line_nums_width as isize);
+        let numbers = match self.line_numbers {
+            LineNumbers::Relative | LineNumbers::Hybrid
+                if Some(view_i) == self.cur_view_i && self.mode_name() == "insert" =>
+            {
+                LineNumbers::Absolute
+            }
+            numbers => numbers,
+        };
+        if numbers != LineNumbers::Off {
+            self.render_line_nums(
+                &mut line_nums_rect.to_renderer(&mut render),
+                &rows,
+                numbers,
+                cursor_line,
+            );
+        }
+        if signs {
+            self.render_signs(view_i, &mut line_nums_rect.to_renderer(&mut render), &rows);
+        }
+        if gutter {
+            self.render_gutter(
+                view_i,
+                &mut line_nums_rect.to_renderer(&mut render),
+                &rows,
+                usize::from(signs),
+            );
+        }
+        self.render_content(view_i, &mut content_rect.to_renderer(&mut render), &rows);
+    }
+
+    /// Are there columns for the signs of the lines with diagnostics, and
+    /// for those of the lines changed since `HEAD`, in a view
+    fn sign_columns(&self, view_i: usize) -> (bool, bool) {
+        let buffer_state = &self.buffers[self.views[view_i].buffer_i];
+        (
+            !buffer_state.document.diagnostics.is_empty(),
+            buffer_state.gutter.is_shown(),
+        )
+    }
+
+    /// Columns of a view left of the text: the line numbers, as wide as
+    /// the last one, and the sign columns
+    fn margin_width(&self, view_i: usize) -> usize {
+        let numbers = match self.line_numbers {
+            LineNumbers::Off => 0,
+            _ => {
+                let lines = self.buffers[self.views[view_i].buffer_i].buffer.lines();
+                lines.to_string().len() + 1
+            }
+        };
+        let (signs, gutter) = self.sign_columns(view_i);
+        numbers + usize::from(signs) + usize::from(gutter)
+    }
+
+    /// Rows of a view from `start_line`, at most `height`, after the first
+    /// `skip` ones
+    fn layout_rows(
+        &self,
+        view_i: usize,
+        start_line: usize,
+        skip: usize,
+        height: usize,
+        width: usize,
+    ) -> Vec<Row> {
+        let mut rows = vec![];
+        let buffer_state = &self.buffers[self.views[view_i].buffer_i];
+        let buffer = &buffer_state.buffer;
+        let lines = buffer.lines();
+        let hidden = self.views[view_i].folds.hidden(&buffer.text);
+        let left = match buffer_state.document.wrap {
+            Wrap::None => self.views[view_i].column_offset.get(),
+            Wrap::Chars | Wrap::Words => 0,
+        };
+        for line in start_line..lines {
+            if height + skip <= rows.len() {
+                break;
+            }
+            if hidden.iter().any(|hidden| hidden.contains(&line)) {
+                continue;
+            }
+            let lenses: Vec<_> = self
+                .line_code_lenses(view_i, line)
+                .map(|command| command.title.as_str())
+                .collect();
+            if !lenses.is_empty() {
+                rows.push(Row::CodeLens {
+                    line,
+                    indent: self.line_indent(view_i, line),
+                    text: lenses.join(" | "),
+                });
+            }
+            rows.push(Row::Line { line, start: left });
+            rows.extend(
+                self.wrap_starts(view_i, line, width)
+                    .into_iter()
+                    .map(|start| Row::Wrap { line, start }),
+            );
+            if !self.has_virtual_lines(view_i, line) {
+                continue;
+            }
+            for diagnostic in self.line_diagnostics(view_i, line) {
+                let prefix_width = diagnostic.severity.name().len() + 2;
+                let pieces = diagnostic.wrap_message(width.saturating_sub(prefix_width));
+                for (i, text) in pieces.into_iter().enumerate() {
+                    rows.push(Row::Diagnostic {
+                        line,
+                        severity: diagnostic.severity,
+                        first: i == 0,
+                        text,
+                    });
+                }
+            }
+        }
+        rows.drain(..skip.min(rows.len()));
+        rows.truncate(height);
+        rows
+    }
+
+    /// Cell columns the rows of a line of the buffer of a view after the
+    /// first start at, wrapped at `width`, if the buffer wraps
+    fn wrap_starts(&self, view_i: usize, line: usize, width: usize) -> Vec<usize> {
+        let buffer_state = &self.buffers[self.views[view_i].buffer_i];
+        let words = match buffer_state.document.wrap {
+            Wrap::None => return vec![],
+            Wrap::Chars => false,
+            Wrap::Words => true,
+        };
+        let buffer = &buffer_state.buffer;
+        let text = buffer.text.line(line).to_string();
+        view::wrap_line(&text, width, buffer.indent.tab_width, words)
+    }
+
+    /// Visual width of the whitespace a line of the buffer of a view
+    /// starts with
+    fn line_indent(&self, view_i: usize, line: usize) -> usize {
+        let buffer = &self.buffers[self.views[view_i].buffer_i].buffer;
+        let column = buffer
+            .text
+            .line(line)
+            .chars()
+            .take_while(|&ch| ch == ' ' || ch == '\t')
+            .count();
+        buffer.to_visual(Position { line, column }).column
+    }
+
+    /// Diagnostics starting on a line of the buffer of a view
+    fn line_diagnostics(&self, view_i: usize, line: usize) -> &[Diagnostic] {
+        let buffer_state = &self.buffers[self.views[view_i].buffer_i];
+        let text = &buffer_state.buffer.text;
+        let start = text.line_to_char(line);
+        buffer_state
+            .document
+            .diagnostics_in(start..start + text.line(line).len_chars())
+    }
+
+    /// Are the diagnostics of a line shown in full under it, in a view
+    fn has_virtual_lines(&self, view_i: usize, line: usize) -> bool {
+        match self.diagnostic_display {
+            DiagnosticDisplay::Hints => false,
+            DiagnosticDisplay::VirtualLines { cursor_line_only } => {
+                let view = &self.views[view_i];
+                let text = &self.buffers[view.buffer_i].buffer.text;
+                !cursor_line_only || view.selection.cursor_coord(text).line == line
+            }
+        }
+    }
+
+    /// Numbers of the lines of `rows`, or how far they are from
+    /// `cursor_line`, as `numbers` has it
+    pub fn render_line_nums(
+        &self,
+        render: &mut dyn Renderer,
+        rows: &[Row],
+        numbers: LineNumbers,
+        cursor_line: usize,
+    ) {
+        let width = render.dimensions().x;
+        let style = render.color_map().line_num;
+        for (y, row) in rows.iter().enumerate() {
+            let line = match *row {
+                Row::Line { line, .. } => line,
+                Row::Wrap { .. } | Row::CodeLens { .. } | Row::Diagnostic { .. } => continue,
+            };
+            let number = match numbers {
+                LineNumbers::Relative => (line as isize - cursor_line as isize).unsigned_abs(),
+                LineNumbers::Hybrid if line != cursor_line => {
+                    (line as isize - cursor_line as isize).unsigned_abs()
+                }
+                _ => line,
+            };
+            let line_str = format!("{} ", number);
+            render.print(
+                render::Coord {
+                    x: width - line_str.len(),
+                    y,
+                },
+                &line_str,
+                style,
+            );
+        }
+    }
+
+    /// The first letter of the worst severity of the diagnostics starting
+    /// on each line of `rows`, in the first column
+    fn render_signs(&self, view_i: usize, render: &mut dyn Renderer, rows: &[Row]) {
+        let color_map = *render.color_map();
+        for (y, row) in rows.iter().enumerate() {
+            let line = match *row {
+                Row::Line { line, .. } => line,
+                Row::Wrap { .. } | Row::CodeLens { .. } | Row::Diagnostic { .. } => continue,
+            };
+            let severity = self
+                .line_diagnostics(view_i, line)
+                .iter()
+                .map(|diagnostic| diagnostic.severity)
+                .min();
+            if let Some(severity) = severity {
+                let sign = severity.name()[..1].to_uppercase();
+                render.print(Coord { x: 0, y }, &sign, color_map.diagnostic(severity));
+            }
+        }
+    }
+
+    /// The signs of the lines of `rows` changed since `HEAD`, in the
+    /// column `x`
+    fn render_gutter(&self, view_i: usize, render: &mut dyn Renderer, rows: &[Row], x: usize) {
+        let color_map = *render.color_map();
+        let gutter = &self.buffers[self.views[view_i].buffer_i].gutter;
+        for (y, row) in rows.iter().enumerate() {
+            let line = match *row {
+                Row::Line { line, .. } => line,
+                Row::Wrap { .. } | Row::CodeLens { .. } | Row::Diagnostic { .. } => continue,
+            };
+            if let Some(change) = gutter.line(line) {
+                render.print(
+                    Coord { x, y },
+                    &change.sign().to_string(),
+                    color_map.line_change(change),
+                );
+            }
+        }
+    }
+
+    /// Move the cursors of a view out of the folds it closed, after a key
+    /// moved them from `old_line`: past the folds if they came from the
+    /// line above, or up to it from the line below; otherwise, as for a
+    /// search or a jump, the folds open
+    fn keep_out_of_folds(&mut self, view_i: usize, old_line: usize) {
+        let view = match self.views.get_mut(view_i) {
+            Some(view) if !view.folds.is_empty() => view,
+            _ => return,
+        };
+        let text = &self.buffers[view.buffer_i].buffer.text;
+        let mut opened = false;
+        for sel in &mut view.selection.selections {
+            let position = sel.cursor.to_position(text);
+            let hidden = match view.folds.hidden_at(text, position.line) {
+                Some(hidden) => hidden,
+                None => continue,
+            };
+            let line = if old_line + 1 == hidden.start && hidden.end < text.len_lines() {
+                hidden.end
+            } else if old_line + 1 == hidden.start || old_line == hidden.end {
+                hidden.start - 1
+            } else {
+                opened |= view.folds.open_at(text, position.line);
+                continue;
+            };
+            sel.cursor = position.set_line(line, text).to_idx(text);
+        }
+        if opened {
+            let buffer_i = view.buffer_i;
+            self.invalidation().invalidate_buffer(buffer_i);
+        }
+    }
+
+    /// Move the cursors of the current view hidden in its closed folds to
+    /// the first lines of the folds
+    fn hide_folded_cursors(&mut self) {
+        let view_i = self.cur_view_i.expect("view");
+        let view = &mut self.views[view_i];
+        let text = &self.buffers[view.buffer_i].buffer.text;
+        for sel in &mut view.selection.selections {
+            let position = sel.cursor.to_position(text);
+            if let Some(hidden) = view.folds.hidden_at(text, position.line) {
+                sel.cursor = position.set_line(hidden.start - 1, text).to_idx(text);
+            }
+        }
+        let buffer_i = view.buffer_i;
+        self.invalidation().invalidate_buffer(buffer_i);
+    }
+
+    /// Close the innermost fold the primary cursor is in, or the one
+    /// around it if that one is closed already
+    pub(crate) fn close_fold(&mut self) {
+        let view = match self.cur_view_i {
+            Some(view_i) => &mut self.views[view_i],
+            None => return,
+        };
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        let (text, tab_width) = (&buffer.text, buffer.indent.tab_width);
+        let line = view.selection.cursor_coord(text).line;
+        let mut found = fold::fold_at(text, line, tab_width);
+        while let Some(lines) = found
+            .clone()
+            .filter(|lines| view.folds.is_closed(text, lines))
+        {
+            found = fold::enclosing_fold(text, lines.start, tab_width);
+        }
+        match found {
+            Some(lines) => {
+                view.folds.close(buffer, lines);
+                self.hide_folded_cursors();
+            }
+            None => self.msg = Some("no fold here".into()),
+        }
+    }
+
+    /// Open the closed folds the primary cursor is in
+    pub(crate) fn open_fold(&mut self) {
+        let view = match self.cur_view_i {
+            Some(view_i) => &mut self.views[view_i],
+            None => return,
+        };
+        let text = &self.buffers[view.buffer_i].buffer.text;
+        let line = view.selection.cursor_coord(text).line;
+        if view.folds.open_at(text, line) {
+            let buffer_i = view.buffer_i;
+            self.invalidation().invalidate_buffer(buffer_i);
+        } else {
+            self.msg = Some("no fold closed here".into());
+        }
+    }
+
+    /// Open the folds the primary cursor is in, if it's in a closed one;
+    /// close the innermost one otherwise
+    pub(crate) fn toggle_fold(&mut self) {
+        let closed = self.cur_view_opt().is_some_and(|view| {
+            let text = &self.buffers[view.buffer_i].buffer.text;
+            let line = view.selection.cursor_coord(text).line;
+            view.folds
+                .closed(text)
+                .iter()
+                .any(|lines| lines.contains(&line))
+        });
+        if closed {
+            self.open_fold();
+        } else {
+            self.close_fold();
+        }
+    }
+
+    pub(crate) fn close_all_folds(&mut self) {
+        let view = match self.cur_view_i {
+            Some(view_i) => &mut self.views[view_i],
+            None => return,
+        };
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        for lines in fold::all_folds(&buffer.text, buffer.indent.tab_width) {
+            view.folds.close(buffer, lines);
+        }
+        self.hide_folded_cursors();
+    }
+
+    pub(crate) fn open_all_folds(&mut self) {
+        if let Some(view_i) = self.cur_view_i {
+            let view = &mut self.views[view_i];
+            view.folds.open_all();
+            let buffer_i = view.buffer_i;
+            self.invalidation().invalidate_buffer(buffer_i);
+        }
+    }
+
+    /// The `Tree` of a buffer, parsed again once it changed
+    fn tree(&self, buffer_i: usize) -> Ref<'_, Tree> {
+        let buffer_state = &self.buffers[buffer_i];
+        let version = buffer_state.buffer.version();
+        let parsed = buffer_state
+            .tree
+            .borrow()
+            .as_ref()
+            .is_some_and(|&(of, _)| of == version);
+        if !parsed {
+            let tree = Tree::parse(
+                &buffer_state.buffer.text,
+                buffer_state.document.language.as_deref(),
+            );
+            *buffer_state.tree.borrow_mut() = Some((version, tree));
+        }
+        Ref::map(buffer_state.tree.borrow(), |tree| {
+            &tree.as_ref().expect("just parsed").1
+        })
+    }
+
+    /// The brackets at the cursors of the current view, and those they
+    /// match, in order
+    fn matched_brackets(&self, view_i: usize) -> Vec<usize> {
+        let view = &self.views[view_i];
+        let buffer_state = &self.buffers[view.buffer_i];
+        let text = &buffer_state.buffer.text;
+        if Some(view_i) != self.cur_view_i || buffer_state.document.reduced {
+            return vec![];
+        }
+        let mut brackets = vec![];
+        for sel in &view.selection.selections {
+            let idx = sel.cursor.0;
+            if idx >= text.len_chars() || !is_bracket(text.char(idx)) {
+                continue;
+            }
+            if let Some(matching) = self.tree(view.buffer_i).matching_bracket(text, idx) {
+                brackets.extend([idx, matching]);
+            }
+        }
+        brackets.sort_unstable();
+        brackets.dedup();
+        brackets
+    }
+
+    /// Move each cursor to the bracket matching the one it's on, or the
+    /// first one after it on its line, selecting from there; in HTML, to
+    /// the tag matching the one it's in, first
+    ///
+    /// With `extend`, the selections are kept and extended.
+    pub(crate) fn jump_to_match(&mut self, extend: bool) {
+        let buffer_i = match self.cur_buffer_i() {
+            Some(buffer_i) => buffer_i,
+            None => return,
+        };
+        let text = self.buffers[buffer_i].buffer.text.clone();
+        let html = self.buffers[buffer_i].document.language.as_deref() == Some("html");
+        let tree = self.tree(buffer_i).clone();
+        let found = |cursor: usize| -> Option<(usize, usize)> {
+            if let Some(matching) = Some(cursor)
+                .filter(|_| html)
+                .and_then(|idx| structure::matching_tag(&text, idx))
+            {
+                return Some((cursor, matching));
+            }
+            let line = text.char_to_line(cursor.min(text.len_chars()));
+            let line_end = text.line_to_char(line) + text.line(line).len_chars();
+            (cursor..line_end)
+                .filter(|&idx| is_bracket(text.char(idx)))
+                .find_map(|idx| Some((idx, tree.matching_bracket(&text, idx)?)))
+        };
+        let mut moved = false;
+        let buffer = self.cur_buffer_mut();
+        buffer.view.selection.clear_cursor_column();
+        for sel in &mut buffer.view.selection.selections {
+            if let Some((from, to)) = found(sel.cursor.0) {
+                if !extend {
+                    sel.anchor = Idx(from);
+                }
+                sel.cursor = Idx(to);
+                moved = true;
+            }
+        }
+        if !moved {
+            self.msg = Some("no matching bracket".into());
+        }
+    }
+
+    /// Rows of a view, with the cursor if it's the current one
+    ///
+    /// Rows that are not dirty are kept as drawn in the last frame, and
+    /// lines are drawn from the cache if the view scrolled.
+    pub fn render_content(&self, view_i: usize, render: &mut dyn Renderer, rows: &[Row]) {
+        let view = &self.views[view_i];
+        let buffer = &self.buffers[view.buffer_i].buffer;
+        let dims = render.dimensions();
+        let color_map = *render.color_map();
+
+        let invalidation = self.frame_invalidation.borrow();
+        let mut caches = self.pane_cache.borrow_mut();
+        let cache = caches.entry(view_i).or_default();
+        let key = PaneKey {
+            view_i,
+            buffer_i: view.buffer_i,
+            color_map,
+            tabstop: buffer.indent.tab_width,
+            diagnostic_display: self.diagnostic_display,
+        };
+        let brackets = self.matched_brackets(view_i);
+        let dirty = cache.update(key, buffer, &view.selection, &brackets, &invalidation);
+        let frame = PaneFrame {
+            frame: self.frame.get(),
+            rect: Rect {
+                offset: render.origin(),
+                dimensions: dims,
+            },
+            rows: rows.to_vec(),
+        };
+        let can_keep = cache.drawn.as_ref().is_some_and(|drawn| {
+            drawn.frame + 1 == frame.frame && drawn.rect == frame.rect && drawn.rows == frame.rows
+        });
+        let row_rect = |y| Rect {
+            offset: Coord { x: 0, y },
+            dimensions: Coord { x: dims.x, y: 1 },
+        };
+        let is_row_clean = |y: usize| {
+            can_keep
+                && !dirty.contains(rows[y].line())
+                && !invalidation.is_region_dirty(Rect {
+                    offset: frame.rect.offset + row_rect(y).offset,
+                    ..row_rect(y)
+                })
+        };
+
+        let all_clean = (0..rows.len()).all(is_row_clean);
+        if !(all_clean && render.keep(render.dimensions_rect())) {
+            for (y, row) in rows.iter().enumerate() {
+                if is_row_clean(y) && render.keep(row_rect(y)) {
+                    continue;
+                }
+                match *row {
+                    Row::Line { line, .. } | Row::Wrap { line, .. } => {
+                        let start = row.start().unwrap_or(0);
+                        let end = self
+                            .wrap_starts(view_i, line, dims.x)
+                            .into_iter()
+                            .find(|&next| start < next)
+                            .unwrap_or(usize::MAX);
+                        let styled = cache.styled.entry(line).or_insert_with(|| {
+                            self.styled_line(view_i, line, &brackets, &color_map)
+                        });
+                        let cells = styled.iter().skip(start).take(end - start);
+                        let mut width = 0;
+                        for (x, &cell) in cells.enumerate().take(dims.x) {
+                            render.put_cell(Coord { x, y }, cell);
+                            width = x + 1;
+                        }
+                        // a line scrolled past goes on beyond the edges
+                        if let Row::Line { start, .. } = *row {
+                            let style = color_map.default.paintover(color_map.special);
+                            if 0 < start && !styled.is_empty() {
+                                render.print(Coord { x: 0, y }, "<", style);
+                            }
+                            if start + dims.x < styled.len() {
+                                render.print(Coord { x: dims.x - 1, y }, ">", style);
+                            }
+                        }
+                        let last_row = rows
+                            .get(y + 1)
+                            .is_none_or(|next| next.line() != line || next.start().is_none());
+                        if let (true, Some(folded)) =
+                            (last_row, view.folds.folded_under(&buffer.text, line))
+                        {
+                            let summary = format!("⋯ {} lines", folded);
+                            render.print(Coord { x: width + 1, y }, &summary, color_map.fold);
+                        }
+                    }
+                    Row::CodeLens {
+                        indent, ref text, ..
+                    } => {
+                        render.print(Coord { x: indent, y }, text, color_map.code_lens);
+                    }
+                    Row::Diagnostic {
+                        severity,
+                        first,
+                        ref text,
+                        ..
+                    } => {
+                        let prefix_width = severity.name().len() + 2;
+                        if first {
+                            let prefix = format!("{}: ", severity.name());
+                            render.print(
+                                Coord { x: 0, y },
+                                &prefix,
+                                color_map.diagnostic(severity),
+                            );
+                        }
+                        render.print(Coord { x: prefix_width, y }, text, color_map.default);
+                    }
+                }
+            }
+        }
+        // only what's visible is worth keeping
+        cache.styled.retain(|line, _| {
+            rows.iter()
+                .any(|row| row.line() == *line && row.start().is_some())
+        });
+        cache.drawn = Some(frame);
+
+        if Some(view_i) != self.cur_view_i {
+            return;
+        }
+        let cursor_coord = view.selection.cursor_coord(&buffer.text);
+        let cursor_column = buffer.to_visual(cursor_coord).column;
+        let first_line = rows.first().map_or(0, Row::line);
+        let cursor_row = view::row_of(rows, cursor_coord.line, cursor_column);
+        let visual_cursor_coord = render::Coord {
+            y: cursor_row.unwrap_or_else(|| cursor_coord.line.saturating_sub(first_line)),
+            x: cursor_column - cursor_row.and_then(|y| rows[y].start()).unwrap_or(0),
+        };
+        *self.last_visual_cursor_coord.borrow_mut() = Some(visual_cursor_coord);
+        self.cursor_screen_coord
+            .set(Some(render.origin() + visual_cursor_coord));
+        render.set_cursor(Some(visual_cursor_coord));
+    }
+
+    /// Visual chars of a line of a view, styled
+    fn styled_line(
+        &self,
+        view_i: usize,
+        line: usize,
+        brackets: &[usize],
+        color_map: &render::ColorMap,
+    ) -> StyledLine {
+        let view = &self.views[view_i];
+        let buffer_state = &self.buffers[view.buffer_i];
+        let buffer = &buffer_state.buffer;
+        let conflict_style = buffer_state
+            .conflicts
+            .at_line(line)
+            .and_then(|conflict| conflict.line_kind(line))
+            .map(|kind| color_map.conflict_line(kind));
+
+        let mut styled = vec![];
+        let start_idx = buffer.text.line_to_char(line);
+        let line_len = buffer.text.line(line).len_chars();
+        let mut highlights = buffer_state
+            .highlights
+            .in_range(start_idx..start_idx + line_len)
+            .iter()
+            .peekable();
+        let search_matches = buffer_state.matches.as_ref().map_or(&[][..], |matches| {
+            matches.in_range(start_idx..start_idx + line_len)
+        });
+        let quickfix_spans = match buffer_state.document.kind {
+            DocumentKind::Quickfix(ref quickfix) => {
+                let text = buffer.text.line(line).to_string();
+                quickfix.line_spans(text.trim_end_matches(&['\r', '\n'][..]))
+            }
+            _ => vec![],
+        };
+        // also those starting on lines before, spanning this one
+        let diagnosed = buffer_state
+            .document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.start.0, diagnostic.end.0))
+            .filter(|&(start, end)| start < start_idx + line_len && start_idx <= end);
+        let line_text = buffer.text.line(line).to_string();
+        let mut next_i = 0;
+        for grapheme in line_text.graphemes(true) {
+            let i = next_i;
+            let idx = start_idx + i;
+            next_i += grapheme.chars().count();
+            let ch = grapheme.chars().next().expect("graphemes are not empty");
+            while highlights.peek().is_some_and(|h| h.range.end <= idx) {
+                highlights.next();
+            }
+            let mut base_style = color_map.default;
+            if let Some(h) = highlights.peek().filter(|h| h.range.start <= idx) {
+                base_style = base_style.paintover(self.highlighter.style(h, color_map));
+            }
+            for (_, span) in quickfix_spans
+                .iter()
+                .filter(|(range, _)| range.contains(&i))
+            {
+                base_style = match *span {
+                    quickfix::LineSpan::Location => base_style.paintover(color_map.line_num),
+                    quickfix::LineSpan::Syntax(kind) => {
+                        base_style.paintover(color_map.syntax(kind))
+                    }
+                    quickfix::LineSpan::Match => base_style.underlined(),
+                };
+            }
+            if let Some(kind) = buffer_state.references.kind_at(idx) {
+                base_style = base_style.paintover(color_map.reference(kind));
+            }
+            if brackets.binary_search(&idx).is_ok() {
+                base_style = base_style.paintover(color_map.bracket_match);
+            }
+            if diagnosed
+                .clone()
+                .any(|(start, end)| start <= idx && (idx < end || (start == end && idx == start)))
+            {
+                base_style = base_style.underlined();
+            }
+            if search_matches
+                .iter()
+                .any(|&(start, end)| start.0 <= idx && idx < end.0)
+            {
+                base_style = base_style.paintover(color_map.search_match);
+            }
+            if let Some(conflict_style) = conflict_style {
+                base_style = base_style.paintover(conflict_style);
+            }
+
+            let visual_selection = view.selection.idx_selection_type(Idx(idx), &buffer.text);
+
+            let (visual_ch, visual_ch_width, special) = match ch {
+                // a line ending, even `\r\n`, which is a single grapheme
+                '\n' | '\r' if grapheme.ends_with('\n') => {
+                    if visual_selection != VisualSelection::None {
+                        (Some(render::Cell::new('↩', base_style)), 1, true) // alternatives: ⤶  🡿
+                    } else {
+                        (None, 0, true)
+                    }
+                }
+                '\t' => (
+                    Some(render::Cell::new('.', base_style)),
+                    buffer::distance_to_next_tabstop(styled.len(), buffer.indent.tab_width),
                     false,
                 ),
-                ch => (Some(ch), 1, false),
+                ch if sanitize::is_suspicious(ch) => (
+                    Some(render::Cell::new(sanitize::visible(ch), base_style)),
+                    1,
+                    true,
+                ),
+                _ => {
+                    let (cell, width) = render::Cell::grapheme(grapheme, base_style);
+                    (Some(cell).filter(|_| width != 0), width, false)
+                }
             };
 
             let style = if special & visual_ch.is_some() {
-                style.paintover(color_map.special)
+                base_style.paintover(color_map.special)
             } else {
-                style
+                base_style
             };
 
             let style = match visual_selection {
@@ -423,29 +6935,25 @@ impl State {
                 VisualSelection::None => style,
             };
 
-            if ch == '\n' {
-                if let Some(visual_ch) = visual_ch {
-                    render.put(cur_visual_coord, visual_ch, style);
-                }
-                cur_visual_coord.x = 0;
-                cur_visual_coord.y += 1;
-            } else {
-                for _ in 0..visual_ch_width {
-                    if let Some(visual_ch) = visual_ch {
-                        render.put(cur_visual_coord, visual_ch, style);
-                    }
-                    cur_visual_coord.x += 1;
+            if let Some(visual_ch) = visual_ch {
+                let cell = render::Cell { style, ..visual_ch };
+                if cell.wide {
+                    styled.extend(&[cell, render::Cell::continuation(style)]);
+                } else {
+                    styled.extend(std::iter::repeat_n(cell, visual_ch_width));
                 }
             }
-            cur_ch_idx += 1;
         }
-
-        let visual_cursor_coord = render::Coord {
-            y: cursor_coord.line.saturating_sub(start_line),
-            x: cursor_coord.column,
-        };
-        *self.last_visual_cursor_coord.borrow_mut() = Some(visual_cursor_coord);
-        render.set_cursor(Some(visual_cursor_coord));
+        let hint = self
+            .line_diagnostics(view_i, line)
+            .first()
+            .filter(|_| !self.has_virtual_lines(view_i, line));
+        if let Some(diagnostic) = hint {
+            let style = color_map.diagnostic(diagnostic.severity);
+            let message = diagnostic.message.lines().next().unwrap_or_default();
+            styled.extend(render::cells("  ", style).chain(render::cells(message, style)));
+        }
+        styled
     }
 
     pub fn render_splash(&self, render: &mut dyn Renderer) {
@@ -458,30 +6966,33 @@ impl State {
         );
         render.print_centered(center.add_y(1), "by Dawid Ciężarkiewicz", style);
         *self.last_visual_cursor_coord.borrow_mut() = None;
+        self.cursor_screen_coord.set(None);
         render.set_cursor(None);
     }
 }
 
-impl Default for BufferState {
-    fn default() -> Self {
-        Self {
-            buffer: default(),
-            buffer_history: vec![],
-            buffer_history_undo_i: None,
-            path: None,
-        }
-    }
-}
 impl Default for State {
     fn default() -> Self {
         State {
             quit: false,
-            mode: Some(Box::new(mode::Normal::default())),
-            yanked: vec![],
+            mode: Some(Box::new(mode::Normal)),
+            yanked: default(),
+            yank_history: default(),
+            registers: default(),
+            pending_register: None,
+            last_command: None,
+            macros: default(),
+            replaying: vec![],
+            selection_restored: false,
+            just_pasted: None,
             msg: None,
+            chars_since_undo_point: 0,
 
             buffers: Slab::new(),
-            cur_buffer_i: None,
+            views: Slab::new(),
+            cur_view_i: None,
+            layout: None,
+            tabs: default(),
 
             read_handler: Arc::new(|_path| {
                 Err(io::Error::new(
@@ -489,7 +7000,31 @@ impl Default for State {
                     "handler not registered",
                 ))
             }),
-            write_handler: Arc::new(|_path, _rope| {
+            write_handler: Arc::new(|_path, _bytes| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            writable_handler: Arc::new(|_path| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            elevated_write_handler: Arc::new(|_path, _bytes| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            stat_handler: Arc::new(|_path| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            watch_handler: Arc::new(|_path| {
                 Err(io::Error::new(
                     io::ErrorKind::NotConnected,
                     "handler not registered",
@@ -501,8 +7036,201 @@ impl Default for State {
                     "handler not registered",
                 ))
             }),
+            list_dir_handler: Arc::new(|_path| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            file_list_handler: Arc::new(|_req| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            preview_handler: Arc::new(|_req| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            grep_handler: Arc::new(|_pattern| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            project_search_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            git_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            git_base_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            highlight_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            highlighter: Arc::new(syntax::RuleHighlighter),
+            todo_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            pipe_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            resource_handler: Arc::new(|_op| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            undo_file_handler: Arc::new(|_op| Ok(None)),
+            swap_handler: Arc::new(|_op| Ok(())),
+            recoverable: vec![],
+            clipboard_handler: Arc::new(|_op| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            health_checks: vec![],
+            plugins: vec![],
+            plugin_overlay: None,
+            config_errors: vec![],
+            now: UNIX_EPOCH,
+            trash_handler: Arc::new(|_op| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            delete_to_trash: true,
+            keep_scratch: false,
+            auto_reload: true,
+            key_coalescing: default(),
+            last_trashed: None,
+            code_lens_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            command_handler: Arc::new(|_command| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            hierarchy_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            document_highlight_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            on_type_formatting_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            document_sync_handler: Arc::new(|_sync| {}),
+            query_handler: Arc::new(|_request| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            format_on_type_triggers: vec!['}', ';', '\n'],
             last_visual_cursor_coord: RefCell::new(None),
+            cursor_screen_coord: Cell::new(None),
+            mouse_anchor: None,
             num_prefix: None,
+            last_search: None,
+            last_find: None,
+            search_preview: None,
+            search_options: default(),
+            diagnostic_display: default(),
+            line_numbers: default(),
+            capabilities: default(),
+            large_file_limits: default(),
+            preview: None,
+            next_preview_id: 0,
+            next_highlight_id: 0,
+            next_git_base_id: 0,
+            next_code_lens_id: 0,
+            next_document_highlight_id: 0,
+            next_on_type_formatting_id: 0,
+            next_todo_id: 0,
+            next_file_list_id: 0,
+            file_list: None,
+            todo_scan: None,
+            next_pipe_id: 0,
+            pending_pipe: None,
+            next_grep_id: 0,
+            pending_grep: None,
+            next_query_id: 0,
+            pending_query: None,
+            completion_sources: vec![Arc::new(completion::BufferWords)],
+            next_completion_id: 0,
+            completion: None,
+            todos: None,
+            open_todos_when_scanned: false,
+            tasks: default(),
+            layers: vec![],
+            keymaps: Keymaps::default(),
+            commands: CommandRegistry::builtin(),
+            status_template: None,
+            pane_colors: BTreeMap::new(),
+            themes: BTreeMap::new(),
+            theme: None,
+            background: Background::default(),
+            profiler: default(),
+            env: default(),
+            invalidation: default(),
+            frame_invalidation: default(),
+            frame: default(),
+            pane_cache: default(),
+            action_counts: BTreeMap::new(),
+            action_usage: Frecency::default(),
+            project_dir: PathBuf::new(),
+            chdir_handler: Arc::new(|_dir| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            recent_files: Frecency::default(),
+            recent_projects: Frecency::default(),
+            sessions: BTreeMap::new(),
         }
     }
 }
+
+fn is_bracket(ch: char) -> bool {
+    matches!(ch, '(' | ')' | '[' | ']' | '{' | '}')
+}
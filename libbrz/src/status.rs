@@ -0,0 +1,430 @@
+//! The status line, laid out by a template
+//!
+//! A template is text with fields in braces, replaced by what they tell
+//! about the editor when the status line is drawn:
+//!
+//! ```text
+//! {mode4} {file}{modified}{>}{?matches}{matches} {/}{line}:{col} {percent}
+//! ```
+//!
+//! * `{field}` is the value of the field, or nothing;
+//! * `{?field}…{/}` is what's between only if the field has a value;
+//! * `{#style}` draws what follows with a style of the `ColorMap`, like
+//!   `mode`, and `{#}` goes back to `status`;
+//! * `{>}` aligns what follows to the right;
+//! * `{{` is a `{`.
+//!
+//! Templates are parsed once, when set, and evaluated for each frame.
+//!
+//! Segments are the shorter way to lay it out: a list of what to show, in
+//! order, like
+//!
+//! ```text
+//! mode file:special modified > diagnostics:diagnostic_error position
+//! ```
+//!
+//! each segment a field, or `position` for `{line}:{col}`, shown with a
+//! space before it when it has a value. `:style` draws it with a style of
+//! the `ColorMap` instead of its own, which is `mode` for the mode and
+//! that of `{#}` for the others; `>` aligns the segments after to the right.
+use crate::render::{ColorMap, Style};
+use crate::theme;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Field {
+    Mode,
+    /// The first 4 chars of the mode name
+    Mode4,
+    File,
+    /// `[+]`, if changed since opened or written
+    Modified,
+    Language,
+    /// Of the primary cursor, from 1
+    Line,
+    Col,
+    /// How far the cursor line is into the text
+    Percent,
+    Lines,
+    Matches,
+    Tasks,
+    /// Counts of the tagged comments of the workspace
+    Todos,
+    /// `recording @<register>`, while keys are recorded
+    Recording,
+    Reduced,
+    Unwritable,
+    /// `readonly`, or `unmodifiable`, if it can't be written or edited
+    ReadOnly,
+    /// The encoding and line endings, like `latin-1 crlf`, of a file not
+    /// in UTF-8 with `\n`
+    Format,
+    /// `3 sel`, when there's more than one
+    Selections,
+    /// Counts of the errors and warnings, like `E2 W1`
+    Diagnostics,
+    Msg,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "mode" => Field::Mode,
+            "mode4" => Field::Mode4,
+            "file" => Field::File,
+            "modified" => Field::Modified,
+            "language" => Field::Language,
+            "line" => Field::Line,
+            "col" => Field::Col,
+            "percent" => Field::Percent,
+            "lines" => Field::Lines,
+            "matches" => Field::Matches,
+            "tasks" => Field::Tasks,
+            "todos" => Field::Todos,
+            "recording" => Field::Recording,
+            "reduced" => Field::Reduced,
+            "unwritable" => Field::Unwritable,
+            "readonly" => Field::ReadOnly,
+            "format" | "encoding" => Field::Format,
+            "selections" => Field::Selections,
+            "diagnostics" => Field::Diagnostics,
+            "msg" => Field::Msg,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(Field),
+    /// `None` for `default`
+    Style(Option<String>),
+    If(Field, Vec<Piece>),
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTemplateError {
+    /// Char the error is at
+    pub at: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {}: {}", self.at, self.reason)
+    }
+}
+
+/// Text in a style
+pub type Span = (String, Style);
+
+/// The status line, split into what goes on the left and what goes on
+/// the right
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusLine {
+    pub left: Vec<Span>,
+    pub right: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusTemplate {
+    pieces: Vec<Piece>,
+}
+
+impl StatusTemplate {
+    pub fn parse(text: &str) -> Result<Self, ParseTemplateError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        let (pieces, _) = parse_pieces(&chars, &mut i, false)?;
+        Ok(Self { pieces })
+    }
+
+    /// Does the template show `field` anywhere
+    pub fn uses(&self, field: Field) -> bool {
+        fn uses(pieces: &[Piece], field: Field) -> bool {
+            pieces.iter().any(|piece| match piece {
+                Piece::Field(f) => *f == field,
+                Piece::If(f, pieces) => *f == field || uses(pieces, field),
+                _ => false,
+            })
+        }
+        uses(&self.pieces, field)
+    }
+
+    /// The status line, with `value` telling the values of the fields
+    pub fn eval(&self, map: &ColorMap, value: impl Fn(Field) -> Option<String>) -> StatusLine {
+        let mut eval = Eval {
+            map,
+            value: &value,
+            style: map.default.paintover(map.status),
+            line: StatusLine::default(),
+            right: false,
+        };
+        eval.pieces(&self.pieces);
+        eval.line
+    }
+}
+
+/// Segments of the status line when none are given
+pub const DEFAULT_SEGMENTS: &str =
+    "mode file modified > diagnostics selections language format position";
+
+/// The status line of `segments`, as described above
+pub fn segments(segments: &str) -> Result<StatusTemplate, ParseTemplateError> {
+    let mut pieces = vec![];
+    let mut first = true;
+    let mut at = 0;
+    for token in segments.split(' ') {
+        let start = at;
+        at += token.chars().count() + 1;
+        let error = |reason: String| ParseTemplateError { at: start, reason };
+        if token.is_empty() {
+            continue;
+        }
+        if token == ">" {
+            pieces.push(Piece::Right);
+            continue;
+        }
+        let (name, style) = match token.split_once(':') {
+            Some((name, style)) if theme::STYLE_NAMES.contains(&style) => {
+                (name, Some(style.to_owned()))
+            }
+            Some((_, style)) => return Err(error(format!("no style {}", style))),
+            None if token == "mode" => (token, Some("mode".to_owned())),
+            None => (token, None),
+        };
+        let (field, shown) = match name {
+            "position" => (
+                Field::Line,
+                vec![
+                    Piece::Field(Field::Line),
+                    Piece::Text(":".into()),
+                    Piece::Field(Field::Col),
+                ],
+            ),
+            "mode" => (
+                Field::Mode,
+                vec![
+                    Piece::Text(" ".into()),
+                    Piece::Field(Field::Mode),
+                    Piece::Text(" ".into()),
+                ],
+            ),
+            _ => {
+                let field =
+                    Field::from_name(name).ok_or_else(|| error(format!("no segment {}", name)))?;
+                (field, vec![Piece::Field(field)])
+            }
+        };
+        let mut inner = vec![];
+        if !first {
+            inner.push(Piece::Text(" ".into()));
+        }
+        inner.push(Piece::Style(style));
+        inner.extend(shown);
+        inner.push(Piece::Style(None));
+        pieces.push(Piece::If(field, inner));
+        first = false;
+    }
+    Ok(StatusTemplate { pieces })
+}
+
+/// Pieces from `i` on, up to the `{/}` closing them if `nested`; tells
+/// if that `{/}` was there
+fn parse_pieces(
+    chars: &[char],
+    i: &mut usize,
+    nested: bool,
+) -> Result<(Vec<Piece>, bool), ParseTemplateError> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    while *i < chars.len() {
+        let ch = chars[*i];
+        *i += 1;
+        if ch != '{' {
+            text.push(ch);
+            continue;
+        }
+        if chars.get(*i) == Some(&'{') {
+            *i += 1;
+            text.push('{');
+            continue;
+        }
+        let start = *i - 1;
+        let error = |reason: String| ParseTemplateError { at: start, reason };
+        let end = chars[*i..]
+            .iter()
+            .position(|&ch| ch == '}')
+            .ok_or_else(|| error("unclosed {".into()))?;
+        let tag: String = chars[*i..*i + end].iter().collect();
+        *i += end + 1;
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        let field =
+            |name: &str| Field::from_name(name).ok_or_else(|| error(format!("no field {}", name)));
+        match tag.as_str() {
+            "/" if nested => return Ok((pieces, true)),
+            "/" => return Err(error("{/} without {?…}".into())),
+            ">" => pieces.push(Piece::Right),
+            "#" | "#default" => pieces.push(Piece::Style(None)),
+            _ => {
+                if let Some(name) = tag.strip_prefix('#') {
+                    if !theme::STYLE_NAMES.contains(&name) {
+                        return Err(error(format!("no style {}", name)));
+                    }
+                    pieces.push(Piece::Style(Some(name.to_owned())));
+                } else if let Some(name) = tag.strip_prefix('?') {
+                    let field = field(name)?;
+                    let (inner, closed) = parse_pieces(chars, i, true)?;
+                    if !closed {
+                        return Err(error(format!("{{?{}}} without {{/}}", name)));
+                    }
+                    pieces.push(Piece::If(field, inner));
+                } else {
+                    pieces.push(Piece::Field(field(&tag)?));
+                }
+            }
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok((pieces, false))
+}
+
+struct Eval<'e> {
+    map: &'e ColorMap,
+    value: &'e dyn Fn(Field) -> Option<String>,
+    style: Style,
+    line: StatusLine,
+    right: bool,
+}
+
+impl<'e> Eval<'e> {
+    fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        let spans = if self.right {
+            &mut self.line.right
+        } else {
+            &mut self.line.left
+        };
+        match spans.last_mut() {
+            Some((last, style)) if *style == self.style => last.push_str(&text),
+            _ => spans.push((text, self.style)),
+        }
+    }
+
+    fn pieces(&mut self, pieces: &[Piece]) {
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => self.push(text.clone()),
+                Piece::Field(field) => {
+                    if let Some(value) = (self.value)(*field) {
+                        self.push(value);
+                    }
+                }
+                Piece::Style(name) => {
+                    self.style = match name {
+                        Some(name) => theme::style(self.map, name).expect("checked when parsed"),
+                        None => self.map.default.paintover(self.map.status),
+                    }
+                }
+                Piece::If(field, pieces) => {
+                    if (self.value)(*field).is_some_and(|value| !value.is_empty()) {
+                        self.pieces(pieces);
+                    }
+                }
+                Piece::Right => self.right = true,
+            }
+        }
+    }
+}
+
+#[test]
+fn status_template_test() {
+    let map = ColorMap {
+        special: Style {
+            fg: Some(crate::render::Color::Ansi(14)),
+            ..Style::default()
+        },
+        ..ColorMap::default()
+    };
+    let template = StatusTemplate::parse(
+        "{mode4} {#special}{file}{#}{?modified} {modified}{/}{>}{line}:{col} {{x}",
+    )
+    .expect("valid");
+    assert!(template.uses(Field::Modified));
+    assert!(!template.uses(Field::Msg));
+    let value = |modified: bool| {
+        move |field| match field {
+            Field::Mode4 => Some("norm".to_owned()),
+            Field::File => Some("a.rs".to_owned()),
+            Field::Modified if modified => Some("[+]".to_owned()),
+            Field::Line => Some("3".to_owned()),
+            Field::Col => Some("7".to_owned()),
+            _ => None,
+        }
+    };
+    let line = template.eval(&map, value(true));
+    assert_eq!(
+        line.left,
+        vec![
+            ("norm ".to_owned(), map.default),
+            ("a.rs".to_owned(), map.special),
+            (" [+]".to_owned(), map.default),
+        ]
+    );
+    assert_eq!(line.right, vec![("3:7 {x}".to_owned(), map.default)]);
+    assert_eq!(template.eval(&map, value(false)).left.len(), 2);
+
+    let error = |text| StatusTemplate::parse(text).map_err(|e| e.to_string());
+    assert_eq!(error("a {nope}"), Err("at 2: no field nope".to_owned()));
+    assert_eq!(error("{#nope}"), Err("at 0: no style nope".to_owned()));
+    assert!(error("{?file}x").is_err());
+    assert!(error("x{/}").is_err());
+    assert!(error("{file").is_err());
+}
+
+#[test]
+fn segments_test() {
+    let map = ColorMap {
+        mode: Style {
+            attrs: Some(crate::render::Attrs::BOLD),
+            ..Style::default()
+        },
+        special: Style {
+            attrs: Some(crate::render::Attrs::UNDERLINE),
+            ..Style::default()
+        },
+        ..ColorMap::default()
+    };
+    let template = segments("mode file modified:special > selections position").expect("valid");
+    let line = template.eval(&map, |field| match field {
+        Field::Mode => Some("normal".to_owned()),
+        Field::File => Some("a.rs".to_owned()),
+        Field::Modified => Some("[+]".to_owned()),
+        Field::Line => Some("3".to_owned()),
+        Field::Col => Some("7".to_owned()),
+        _ => None,
+    });
+    assert_eq!(
+        line.left,
+        vec![
+            (" normal ".to_owned(), map.mode),
+            (" a.rs ".to_owned(), map.default),
+            ("[+]".to_owned(), map.special),
+        ]
+    );
+    // the selections are left out, with the space before them
+    assert_eq!(line.right, vec![(" 3:7".to_owned(), map.default)]);
+    assert!(segments(DEFAULT_SEGMENTS).is_ok());
+
+    let error = |text| segments(text).map_err(|e| e.to_string());
+    assert_eq!(error("mode nope"), Err("at 5: no segment nope".to_owned()));
+    assert_eq!(error("file:nope"), Err("at 0: no style nope".to_owned()));
+}
@@ -0,0 +1,492 @@
+//! Structure of code, from its brackets
+//!
+//! There is no parser for the languages behind the editor: the tree is
+//! made of the bracket groups, `(..)`, `[..]` and `{..}`, and of the items
+//! separated by commas or semicolons in them, which is enough to move
+//! around and reshape argument lists, struct literals and blocks. In
+//! braces, and at the top level, a `}` ending a line also ends an item,
+//! so that functions and `if` blocks are items of their own. Brackets in
+//! strings and comments are skipped, for the languages `syntax` knows.
+//!
+//! The same groups pair each bracket with the one matching it; the tags
+//! of HTML are paired apart, as they're no groups.
+use crate::preview::CancelToken;
+use crate::syntax::{self, HighlightKind};
+use ropey::Rope;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole text
+    Root,
+    /// Brackets and what's in them, with the opening bracket
+    Group(char),
+    /// Part of a group between separators
+    Item,
+}
+
+impl NodeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeKind::Root => "file",
+            NodeKind::Group('(') => "parens",
+            NodeKind::Group('[') => "brackets",
+            NodeKind::Group(_) => "braces",
+            NodeKind::Item => "item",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// Chars covered; items don't include the whitespace and separators
+    /// around them
+    pub range: Range<usize>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+fn closing(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// A group being parsed
+struct Frame {
+    node: usize,
+    item: Option<usize>,
+    /// End of the last char of the current item that's not whitespace
+    item_end: usize,
+    /// A `}` ended the last line, so the item ends with it
+    item_ends_at_newline: bool,
+}
+
+/// The nodes of a text, the root first; children are in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub nodes: Vec<Node>,
+}
+
+impl Tree {
+    pub fn parse(text: &Rope, language: Option<&str>) -> Self {
+        let skipped: Vec<Range<usize>> = language
+            .and_then(|language| syntax::highlight(text, language, &CancelToken::default()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|h| matches!(h.kind, HighlightKind::String | HighlightKind::Comment))
+            .map(|h| h.range)
+            .collect();
+        let mut skipped = skipped.iter().peekable();
+
+        let mut tree = Tree {
+            nodes: vec![Node {
+                kind: NodeKind::Root,
+                range: 0..text.len_chars(),
+                parent: None,
+                children: vec![],
+            }],
+        };
+        let mut stack = vec![Frame {
+            node: 0,
+            item: None,
+            item_end: 0,
+            item_ends_at_newline: false,
+        }];
+        for (i, ch) in text.chars().enumerate() {
+            while skipped.peek().is_some_and(|range| range.end <= i) {
+                skipped.next();
+            }
+            let in_skipped = skipped.peek().is_some_and(|range| range.start <= i);
+            match ch {
+                _ if in_skipped => {
+                    tree.item_char(&mut stack, i);
+                }
+                '(' | '[' | '{' => {
+                    let item = tree.item_char(&mut stack, i);
+                    let node = tree.push(NodeKind::Group(ch), i..i + 1, item);
+                    stack.push(Frame {
+                        node,
+                        item: None,
+                        item_end: i + 1,
+                        item_ends_at_newline: false,
+                    });
+                }
+                ')' | ']' | '}' => {
+                    let open =
+                        stack[1..]
+                            .iter()
+                            .rposition(|frame| match tree.nodes[frame.node].kind {
+                                NodeKind::Group(open) => closing(open) == ch,
+                                _ => false,
+                            });
+                    match open {
+                        Some(depth) => {
+                            while depth + 1 < stack.len() {
+                                let mut frame = stack.pop().expect("deeper than depth");
+                                tree.end_item(&mut frame);
+                                tree.nodes[frame.node].range.end = i + 1;
+                            }
+                            let parent = stack.last_mut().expect("root stays");
+                            parent.item_end = i + 1;
+                            parent.item_ends_at_newline = ch == '}';
+                        }
+                        None => {
+                            tree.item_char(&mut stack, i);
+                        }
+                    }
+                }
+                ',' | ';' => tree.end_item(stack.last_mut().expect("root stays")),
+                '\n' => {
+                    let frame = stack.last_mut().expect("root stays");
+                    let breaks = match tree.nodes[frame.node].kind {
+                        NodeKind::Root | NodeKind::Group('{') => frame.item_ends_at_newline,
+                        _ => false,
+                    };
+                    if breaks {
+                        tree.end_item(frame);
+                    }
+                }
+                ch if ch.is_whitespace() => {}
+                _ => {
+                    tree.item_char(&mut stack, i);
+                }
+            }
+        }
+        let end = text.len_chars();
+        while let Some(mut frame) = stack.pop() {
+            tree.end_item(&mut frame);
+            tree.nodes[frame.node].range.end = end;
+            // an unclosed group goes on to the end, and so does its item
+            if let Some(parent) = stack.last_mut() {
+                parent.item_end = end;
+            }
+        }
+        tree
+    }
+
+    fn push(&mut self, kind: NodeKind, range: Range<usize>, parent: usize) -> usize {
+        let i = self.nodes.len();
+        self.nodes.push(Node {
+            kind,
+            range,
+            parent: Some(parent),
+            children: vec![],
+        });
+        self.nodes[parent].children.push(i);
+        i
+    }
+
+    /// A char of an item at `i`, starting the item if needed; returns it
+    fn item_char(&mut self, stack: &mut [Frame], i: usize) -> usize {
+        let frame = stack.last_mut().expect("root stays");
+        let item = match frame.item {
+            Some(item) => item,
+            None => {
+                let item = self.push(NodeKind::Item, i..i + 1, frame.node);
+                frame.item = Some(item);
+                item
+            }
+        };
+        frame.item_end = i + 1;
+        frame.item_ends_at_newline = false;
+        item
+    }
+
+    /// End the current item of the `frame`, if any
+    fn end_item(&mut self, frame: &mut Frame) {
+        if let Some(item) = frame.item.take() {
+            self.nodes[item].range.end = frame.item_end;
+        }
+        frame.item_ends_at_newline = false;
+    }
+
+    /// The smallest node covering all of `range`
+    pub fn node_covering(&self, range: Range<usize>) -> usize {
+        let mut i = 0;
+        while let Some(&child) = self.nodes[i].children.iter().find(|&&child| {
+            let node = &self.nodes[child];
+            node.range.start <= range.start && range.end <= node.range.end
+        }) {
+            i = child;
+        }
+        i
+    }
+
+    /// The smallest group covering all of `range`
+    pub fn group_covering(&self, range: Range<usize>) -> Option<usize> {
+        let mut i = Some(self.node_covering(range));
+        while let Some(node) = i {
+            if let NodeKind::Group(_) = self.nodes[node].kind {
+                return Some(node);
+            }
+            i = self.nodes[node].parent;
+        }
+        None
+    }
+
+    /// The bracket matching the one at `idx`, opening or closing a group;
+    /// a group left unclosed matches nothing
+    pub fn matching_bracket(&self, text: &Rope, idx: usize) -> Option<usize> {
+        let group = self.group_covering(idx..idx + 1)?;
+        let (open, range) = match self.nodes[group] {
+            Node {
+                kind: NodeKind::Group(open),
+                ref range,
+                ..
+            } => (open, range),
+            _ => return None,
+        };
+        let close = range.end - 1;
+        if close == range.start || text.char(close) != closing(open) {
+            return None;
+        }
+        match idx {
+            _ if idx == range.start => Some(close),
+            _ if idx == close => Some(range.start),
+            _ => None,
+        }
+    }
+
+    /// The sibling after (or before) the node `i`
+    pub fn sibling(&self, i: usize, backward: bool) -> Option<usize> {
+        let siblings = &self.nodes[self.nodes[i].parent?].children;
+        let pos = siblings.iter().position(|&s| s == i)?;
+        if backward {
+            siblings.get(pos.checked_sub(1)?).copied()
+        } else {
+            siblings.get(pos + 1).copied()
+        }
+    }
+
+    /// Replacement of the group `i` for the other of its single-line and
+    /// multi-line forms
+    ///
+    /// Split items go one `indent` deeper than the line the group starts
+    /// on; a trailing comma is added to split commas, and dropped when
+    /// joining.
+    pub fn split_join(&self, i: usize, text: &Rope, indent: &str) -> Option<String> {
+        let node = &self.nodes[i];
+        let open = match node.kind {
+            NodeKind::Group(open) => open,
+            _ => return None,
+        };
+        let close = closing(open);
+        let closed = 2 <= node.range.len() && text.char(node.range.end - 1) == close;
+        if node.children.is_empty() || !closed {
+            return None;
+        }
+        let slice = |range: Range<usize>| text.slice(range).to_string();
+        let items: Vec<String> = node
+            .children
+            .iter()
+            .map(|&child| slice(self.nodes[child].range.clone()))
+            .collect();
+        // what comes after each item: its separator, if any
+        let separators: Vec<String> = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(n, &child)| {
+                let end = match node.children.get(n + 1) {
+                    Some(&next) => self.nodes[next].range.start,
+                    None => node.range.end - 1,
+                };
+                slice(self.nodes[child].range.end..end).trim().to_owned()
+            })
+            .collect();
+        let content = slice(node.range.start + 1..node.range.end - 1);
+        let mut out = String::new();
+        out.push(open);
+        if content.contains('\n') {
+            if open == '{' {
+                out.push(' ');
+            }
+            for (n, (item, separator)) in items.iter().zip(&separators).enumerate() {
+                let last = n + 1 == items.len();
+                if !(last && separator == ",") {
+                    out.push_str(item);
+                    out.push_str(separator);
+                } else {
+                    out.push_str(item);
+                }
+                if !last {
+                    out.push(' ');
+                }
+            }
+            if open == '{' {
+                out.push(' ');
+            }
+        } else {
+            let line = text.char_to_line(node.range.start);
+            let line_indent: String = text
+                .line(line)
+                .chars()
+                .take_while(|&ch| ch == ' ' || ch == '\t')
+                .collect();
+            let commas = separators.iter().any(|separator| separator == ",");
+            for (n, (item, separator)) in items.iter().zip(&separators).enumerate() {
+                out.push('\n');
+                out.push_str(&line_indent);
+                out.push_str(indent);
+                out.push_str(item);
+                out.push_str(separator);
+                if n + 1 == items.len() && commas && separator.is_empty() {
+                    out.push(',');
+                }
+            }
+            out.push('\n');
+            out.push_str(&line_indent);
+        }
+        out.push(close);
+        Some(out)
+    }
+}
+
+/// Elements of HTML with no closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// The tags of HTML: the chars they cover, their names, and whether they
+/// close
+fn tags(text: &str) -> Vec<(Range<usize>, String, bool)> {
+    let re = regex::Regex::new(r"<(/?)([A-Za-z][\w:-]*)[^<>]*?(/?)>").expect("valid regex");
+    let mut tags = vec![];
+    let mut chars = 0;
+    let mut last = 0;
+    for found in re.captures_iter(text) {
+        let whole = found.get(0).expect("whole match");
+        let name = found[2].to_ascii_lowercase();
+        if !found[3].is_empty() || VOID_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        chars += text[last..whole.start()].chars().count();
+        last = whole.start();
+        let len = whole.as_str().chars().count();
+        tags.push((chars..chars + len, name, !found[1].is_empty()));
+    }
+    tags
+}
+
+/// Start of the HTML tag matching the one `idx` is in, nested tags of the
+/// same name skipped
+pub fn matching_tag(text: &Rope, idx: usize) -> Option<usize> {
+    let tags = tags(&text.to_string());
+    let i = tags.iter().position(|(range, _, _)| range.contains(&idx))?;
+    let (_, name, closes) = &tags[i];
+    let mut depth = 0usize;
+    let same = |(range, other, other_closes): &(Range<usize>, String, bool)| {
+        Some((range.start, *other_closes)).filter(|_| other == name)
+    };
+    if *closes {
+        for (start, other_closes) in tags[..i].iter().rev().filter_map(same) {
+            match (other_closes, depth) {
+                (false, 0) => return Some(start),
+                (false, _) => depth -= 1,
+                (true, _) => depth += 1,
+            }
+        }
+    } else {
+        for (start, other_closes) in tags[i + 1..].iter().filter_map(same) {
+            match (other_closes, depth) {
+                (true, 0) => return Some(start),
+                (true, _) => depth -= 1,
+                (false, _) => depth += 1,
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn matching_test() {
+    let text = Rope::from_str("f(a, \")\", [b]) {\n    (c]\n}\n");
+    let tree = Tree::parse(&text, Some("rust"));
+    let at = |s: &str| text.to_string().find(s).unwrap();
+    assert_eq!(tree.matching_bracket(&text, 1), Some(at(") {")));
+    assert_eq!(tree.matching_bracket(&text, at(") {")), Some(1));
+    assert_eq!(tree.matching_bracket(&text, at("]")), Some(at("[b")));
+    // in a string, unclosed, or no bracket
+    assert_eq!(tree.matching_bracket(&text, at("\")\"") + 1), None);
+    assert_eq!(tree.matching_bracket(&text, at("(c")), None);
+    assert_eq!(tree.matching_bracket(&text, 2), None);
+
+    let text = Rope::from_str("<div a=\"1\">\n  <div><br><img/></div>\n</div>\n");
+    let at = |s: &str| text.to_string().find(s).unwrap();
+    assert_eq!(matching_tag(&text, 3), Some(at("</div>\n</div>") + 7));
+    assert_eq!(matching_tag(&text, at("</div>\n</") + 8), Some(0));
+    assert_eq!(matching_tag(&text, at("<div><br>") + 1), Some(at("</div>")));
+    assert_eq!(matching_tag(&text, at("<br>")), None);
+}
+
+#[test]
+fn tree_test() {
+    let text = Rope::from_str("fn a(x: u8, y: (u8, \")\")) {\n    b(x); c\n}\nlet z = [1];\n");
+    let tree = Tree::parse(&text, Some("rust"));
+    let shape = |i: usize| -> (&'static str, String) {
+        let node = &tree.nodes[i];
+        (node.kind.name(), text.slice(node.range.clone()).to_string())
+    };
+    let root = &tree.nodes[0];
+    let items: Vec<_> = root.children.iter().map(|&i| shape(i)).collect();
+    assert_eq!(
+        items,
+        vec![
+            (
+                "item",
+                "fn a(x: u8, y: (u8, \")\")) {\n    b(x); c\n}".to_owned()
+            ),
+            ("item", "let z = [1]".to_owned())
+        ]
+    );
+    let args = tree.node_covering(5..6);
+    assert_eq!(shape(args), ("item", "x: u8".to_owned()));
+    let y = tree.sibling(args, false).expect("second argument");
+    assert_eq!(shape(y), ("item", "y: (u8, \")\")".to_owned()));
+    assert_eq!(tree.sibling(y, false), None);
+    let group = tree.group_covering(5..6).expect("in parens");
+    assert_eq!(shape(group).0, "parens");
+    let block = tree.nodes[root.children[0]].children[1];
+    assert_eq!(shape(block).0, "braces");
+    let statements: Vec<_> = tree.nodes[block]
+        .children
+        .iter()
+        .map(|&i| shape(i).1)
+        .collect();
+    assert_eq!(statements, vec!["b(x)", "c"]);
+}
+
+#[test]
+fn split_join_test() {
+    let text = Rope::from_str("    f(a, g(b), c);\n");
+    let tree = Tree::parse(&text, Some("rust"));
+    let group = tree.group_covering(6..7).expect("in parens");
+    let split = tree.split_join(group, &text, "    ").expect("a group");
+    assert_eq!(split, "(\n        a,\n        g(b),\n        c,\n    )");
+
+    let text = Rope::from_str(&format!("    f{};\n", split));
+    let tree = Tree::parse(&text, Some("rust"));
+    let group = tree.group_covering(6..7).expect("in parens");
+    assert_eq!(
+        tree.split_join(group, &text, "    ").as_deref(),
+        Some("(a, g(b), c)")
+    );
+
+    let text = Rope::from_str("S { a: 1, b }");
+    let tree = Tree::parse(&text, None);
+    let group = tree.group_covering(4..5).expect("in braces");
+    let split = tree.split_join(group, &text, "  ").expect("a group");
+    assert_eq!(split, "{\n  a: 1,\n  b,\n}");
+    let text = Rope::from_str(&format!("S {}", split));
+    let tree = Tree::parse(&text, None);
+    let group = tree.group_covering(4..5).expect("in braces");
+    assert_eq!(
+        tree.split_join(group, &text, "  ").as_deref(),
+        Some("{ a: 1, b }")
+    );
+}
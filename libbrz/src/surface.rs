@@ -0,0 +1,126 @@
+//! A whole frame, for the frontends that draw it all at once
+//!
+//! `State::render_to_frame` draws into a `Surface` instead of a renderer
+//! of the frontend's, and gives back every cell with its style, where the
+//! cursor is, and the windows drawn: what a GUI needs to draw a frame, or
+//! a test to look at one. A terminal frontend is better off with the
+//! `DiffRenderer`, which tells only what changed.
+use crate::grid;
+use crate::render::{Cell, ColorMap, Coord, Renderer, Style, Window};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Surface {
+    color_map: ColorMap,
+    dimensions: Coord,
+    /// Row by row
+    cells: Vec<Cell>,
+    cursor: Option<Coord>,
+    windows: Vec<Window>,
+}
+
+impl Surface {
+    /// Blank, drawn with `color_map`
+    pub fn new(dimensions: Coord, color_map: ColorMap) -> Self {
+        Self {
+            color_map,
+            dimensions,
+            cells: vec![Cell::default(); dimensions.x * dimensions.y],
+            cursor: None,
+            windows: vec![],
+        }
+    }
+
+    pub fn cell(&self, coord: Coord) -> Option<&Cell> {
+        if coord.x < self.dimensions.x && coord.y < self.dimensions.y {
+            self.cells.get(coord.x + coord.y * self.dimensions.x)
+        } else {
+            None
+        }
+    }
+
+    /// The cells, a row at a time from the top
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.dimensions.x.max(1))
+    }
+
+    /// The chars of the row `y`, combining marks and all
+    pub fn row_text(&self, y: usize) -> String {
+        self.rows().nth(y).map_or_else(String::new, |row| {
+            row.iter().flat_map(Cell::chars).collect()
+        })
+    }
+
+    pub fn cursor(&self) -> Option<Coord> {
+        self.cursor
+    }
+
+    /// From the top left, in screen coordinates
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    /// Done drawing: wide chars without their other half are blanked
+    pub(crate) fn finish(&mut self) {
+        grid::fix_wide(&mut self.cells, self.dimensions.x);
+    }
+}
+
+impl Renderer for Surface {
+    fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    fn dimensions(&self) -> Coord {
+        self.dimensions
+    }
+
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        self.put_cell(coord, Cell::new(ch, style))
+    }
+
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        if coord.x < self.dimensions.x && coord.y < self.dimensions.y {
+            self.cells[coord.x + coord.y * self.dimensions.x] = cell;
+        }
+    }
+
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.cursor = coord;
+    }
+
+    fn window(&mut self, window: Window) {
+        self.windows.push(window);
+    }
+}
+
+#[test]
+fn surface_test() {
+    use crate::render::Rect;
+    let mut surface = Surface::new(Coord { x: 6, y: 2 }, ColorMap::default());
+    surface.print(Coord { x: 0, y: 0 }, "a中bcdefg", Style::default());
+    let rect = Rect {
+        offset: Coord { x: 2, y: 1 },
+        dimensions: Coord { x: 4, y: 1 },
+    };
+    let mut view = rect.to_renderer(&mut surface);
+    view.print(Coord { x: 0, y: 0 }, "xy", Style::default());
+    view.set_cursor(Some(Coord { x: 1, y: 0 }));
+    view.window(Window {
+        view_i: 3,
+        rect: Rect {
+            offset: Coord { x: 0, y: 0 },
+            dimensions: rect.dimensions,
+        },
+        path: None,
+        label: "scratch".into(),
+        focused: true,
+        modified: false,
+    });
+    surface.finish();
+    assert_eq!(surface.row_text(0), "a中bcd");
+    assert_eq!(surface.row_text(1), "  xy  ");
+    assert_eq!(surface.cursor(), Some(Coord { x: 3, y: 1 }));
+    // in screen coordinates, out of the view
+    assert_eq!(surface.windows()[0].rect, rect);
+    assert_eq!(surface.cell(Coord { x: 6, y: 0 }), None);
+}
@@ -0,0 +1,562 @@
+//! Syntax highlighting
+//!
+//! Highlighting a big file can take a while, so it never happens while
+//! handling a key. When the input is idle the frontend calls
+//! `State::request_highlights`, which hands a snapshot of the text to the
+//! registered handler (usually running `highlight` in a background
+//! thread). Editing the buffer cancels the pending request, and results
+//! that arrive for anything else than the latest request are dropped.
+//! Until the new highlights arrive, the old ones follow the edits.
+//!
+//! `highlight` is a simple tokenizer knowing the comments, strings and
+//! keywords of a few languages, and the headings and code of Markdown,
+//! not a full parser. `RuleHighlighter` is it as the default
+//! `highlight::Highlighter`.
+//!
+//! Parts of a file can be of another language, highlighted with its own
+//! rules: fenced code blocks of Markdown, scripts of HTML, and strings of
+//! Rust that start like SQL. The highlights of a string of another
+//! language take the place of the parts of the string they cover.
+use crate::buffer::Buffer;
+use crate::document::detect_language;
+use crate::highlight::Highlighter;
+use crate::preview::CancelToken;
+use crate::render::{ColorMap, Style};
+use ropey::Rope;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Lines highlighted between checks for cancellation
+const LINES_PER_CHECK: usize = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HighlightKind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// A highlighted span of the text, in chars
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub range: Range<usize>,
+    pub kind: HighlightKind,
+}
+
+/// What the frontend is asked to highlight, and with what
+#[derive(Clone)]
+pub struct HighlightRequest {
+    pub id: u64,
+    pub language: String,
+    pub text: Rope,
+    pub cancel: CancelToken,
+    pub highlighter: Arc<dyn Highlighter>,
+}
+
+impl fmt::Debug for HighlightRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HighlightRequest")
+            .field("id", &self.id)
+            .field("language", &self.language)
+            .field("cancel", &self.cancel)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Highlights by the rules of `highlight`, in the colors of the theme
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RuleHighlighter;
+
+impl Highlighter for RuleHighlighter {
+    fn is_known(&self, language: &str) -> bool {
+        is_known(language)
+    }
+
+    fn highlight(
+        &self,
+        text: &Rope,
+        language: &str,
+        cancel: &CancelToken,
+    ) -> Option<Vec<Highlight>> {
+        highlight(text, language, cancel)
+    }
+
+    fn style(&self, highlight: &Highlight, colors: &ColorMap) -> Style {
+        colors.syntax(highlight.kind)
+    }
+}
+
+struct Rules {
+    line_comment: &'static [&'static str],
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    /// Lines starting with `#` are headings, highlighted as keywords
+    headings: bool,
+    /// Keywords match in any case
+    ignore_case: bool,
+    regions: &'static [Region],
+    /// Strings starting with one of the words, in any case, are of the
+    /// language
+    injected_strings: Option<(&'static [&'static str], &'static str)>,
+}
+
+/// Lines of another language, after a line starting with `start` and
+/// before one with `end`
+struct Region {
+    start: &'static str,
+    end: &'static str,
+    /// `None` for the one named after `start`, as in "```rust"
+    language: Option<&'static str>,
+}
+
+const SQL_STARTS: &[&str] = &["select", "insert", "update", "delete", "create", "with"];
+
+fn rules(language: &str) -> Option<Rules> {
+    Some(match language {
+        "rust" => Rules {
+            line_comment: &["//"],
+            quotes: &['"'],
+            keywords: &[
+                "as", "break", "const", "continue", "crate", "else", "enum", "false", "fn", "for",
+                "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+                "unsafe", "use", "where", "while", "dyn",
+            ],
+            headings: false,
+            ignore_case: false,
+            regions: &[],
+            injected_strings: Some((SQL_STARTS, "sql")),
+        },
+        "c" | "cpp" | "go" | "javascript" | "typescript" => Rules {
+            line_comment: &["//"],
+            quotes: &['"', '\''],
+            keywords: &[
+                "break", "case", "class", "const", "continue", "default", "do", "else", "enum",
+                "false", "for", "func", "function", "if", "import", "let", "new", "package",
+                "return", "static", "struct", "switch", "this", "true", "type", "var", "void",
+                "while",
+            ],
+            headings: false,
+            ignore_case: false,
+            regions: &[],
+            injected_strings: None,
+        },
+        "python" => Rules {
+            line_comment: &["#"],
+            quotes: &['"', '\''],
+            keywords: &[
+                "and", "as", "class", "def", "elif", "else", "False", "for", "from", "if",
+                "import", "in", "is", "lambda", "None", "not", "or", "pass", "return", "True",
+                "while", "with", "yield",
+            ],
+            headings: false,
+            ignore_case: false,
+            regions: &[],
+            injected_strings: None,
+        },
+        "shell" | "toml" | "yaml" | "make" => Rules {
+            line_comment: &["#"],
+            quotes: &['"', '\''],
+            keywords: &["true", "false"],
+            headings: false,
+            ignore_case: false,
+            regions: &[],
+            injected_strings: None,
+        },
+        // `code` as strings
+        "markdown" => Rules {
+            line_comment: &[],
+            quotes: &['`'],
+            keywords: &[],
+            headings: true,
+            ignore_case: false,
+            regions: &[
+                Region {
+                    start: "```",
+                    end: "```",
+                    language: None,
+                },
+                Region {
+                    start: "~~~",
+                    end: "~~~",
+                    language: None,
+                },
+            ],
+            injected_strings: None,
+        },
+        "html" => Rules {
+            line_comment: &[],
+            quotes: &['"', '\''],
+            keywords: &[],
+            headings: false,
+            ignore_case: false,
+            regions: &[Region {
+                start: "<script",
+                end: "</script>",
+                language: Some("javascript"),
+            }],
+            injected_strings: None,
+        },
+        "sql" => Rules {
+            line_comment: &["--"],
+            quotes: &['\''],
+            keywords: &[
+                "and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join",
+                "left", "limit", "not", "null", "on", "or", "order", "select", "set", "table",
+                "update", "values", "where", "with",
+            ],
+            headings: false,
+            ignore_case: true,
+            regions: &[],
+            injected_strings: None,
+        },
+        _ => return None,
+    })
+}
+
+/// Highlights of `text`, in order
+///
+/// `None` if cancelled, or if the language is not known.
+pub fn highlight(text: &Rope, language: &str, cancel: &CancelToken) -> Option<Vec<Highlight>> {
+    let rules = rules(language)?;
+    let mut highlights = vec![];
+    // region the line is in, and its rules if the language is known
+    let mut region: Option<(&Region, Option<Rules>)> = None;
+    for (i, line) in text.lines().enumerate() {
+        if i % LINES_PER_CHECK == 0 && cancel.is_cancelled() {
+            return None;
+        }
+        let start = text.line_to_char(i);
+        let chars: Vec<char> = line.chars().collect();
+        let line: String = chars.iter().collect();
+        match region {
+            Some((Region { end, .. }, _)) if line.contains(end) => region = None,
+            Some((_, Some(ref nested))) => {
+                highlight_line(&chars, start, nested, &mut highlights);
+                continue;
+            }
+            Some((_, None)) => continue,
+            None => region = region_start(&line, &rules),
+        }
+        highlight_line(&chars, start, &rules, &mut highlights);
+    }
+    Some(highlights)
+}
+
+/// The region `line` starts, and its rules
+fn region_start(line: &str, rules: &Rules) -> Option<(&'static Region, Option<Rules>)> {
+    let line = line.trim_start();
+    let region = rules.regions.iter().find(|region| {
+        line.strip_prefix(region.start)
+            .is_some_and(|rest| !rest.contains(region.end))
+    })?;
+    let nested = match region.language {
+        Some(language) => rules_of(language),
+        None => {
+            let name = line[region.start.len()..].trim_start();
+            let end = name
+                .find(|ch: char| ch.is_whitespace() || ch == '{' || ch == ',')
+                .unwrap_or(name.len());
+            rules_of(&name[..end])
+        }
+    };
+    Some((region, nested))
+}
+
+/// Rules of the language by its name or the extension of its files, as
+/// code blocks are tagged with either
+fn rules_of(name: &str) -> Option<Rules> {
+    rules(name).or_else(|| {
+        let path = Path::new("file").with_extension(name);
+        detect_language(&path).and_then(rules)
+    })
+}
+
+/// Whether `highlight` knows the rules of `language`
+pub fn is_known(language: &str) -> bool {
+    rules(language).is_some()
+}
+
+/// Highlights of a single `line` of text of `language`, small enough
+/// to highlight right away, eg. of a grep result
+pub fn highlight_str(line: &str, language: &str) -> Option<Vec<Highlight>> {
+    let rules = rules(language)?;
+    let chars: Vec<char> = line.chars().collect();
+    let mut highlights = vec![];
+    highlight_line(&chars, 0, &rules, &mut highlights);
+    Some(highlights)
+}
+
+fn highlight_line(chars: &[char], start: usize, rules: &Rules, out: &mut Vec<Highlight>) {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let mut push = |range: Range<usize>, kind| {
+        out.push(Highlight {
+            range: start + range.start..start + range.end,
+            kind,
+        })
+    };
+    let end_of_line = chars.len() - (chars.last() == Some(&'\n')) as usize;
+    if rules.headings && chars.first() == Some(&'#') {
+        push(0..end_of_line, HighlightKind::Keyword);
+        return;
+    }
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        if rules.line_comment.iter().any(|c| rest.starts_with(c)) {
+            push(i..end_of_line, HighlightKind::Comment);
+            return;
+        } else if rules.quotes.contains(&ch) {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != ch && chars[end] != '\n' {
+                end += 1 + (chars[end] == '\\') as usize;
+            }
+            let end = if chars.get(end) == Some(&ch) {
+                end + 1
+            } else {
+                end.min(chars.len())
+            };
+            let inner = i + 1..if chars.get(end - 1) == Some(&ch) && end > i + 1 {
+                end - 1
+            } else {
+                end
+            };
+            let mut at = i;
+            for h in injected_string(&chars[inner.clone()], inner.start, rules) {
+                if at < h.range.start {
+                    push(at..h.range.start, HighlightKind::String);
+                }
+                at = h.range.end;
+                push(h.range, h.kind);
+            }
+            if at < end {
+                push(at..end, HighlightKind::String);
+            }
+            i = end;
+        } else if is_word(ch) {
+            let end = i + chars[i..].iter().take_while(|&&ch| is_word(ch)).count();
+            let word: String = chars[i..end].iter().collect();
+            if ch.is_ascii_digit() {
+                push(i..end, HighlightKind::Number);
+            } else if rules.keywords.contains(&word.as_str())
+                || rules.ignore_case && rules.keywords.contains(&word.to_lowercase().as_str())
+            {
+                push(i..end, HighlightKind::Keyword);
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Highlights of the `text` of a string starting at `start` in the line,
+/// if it's of another language
+fn injected_string(text: &[char], start: usize, rules: &Rules) -> Vec<Highlight> {
+    let (words, language) = match rules.injected_strings {
+        Some(injected) => injected,
+        None => return vec![],
+    };
+    let first: String = text
+        .iter()
+        .skip_while(|ch| ch.is_whitespace())
+        .take_while(|ch| ch.is_alphanumeric())
+        .collect();
+    let nested = match rules_of(language) {
+        Some(nested) if words.contains(&first.to_lowercase().as_str()) => nested,
+        _ => return vec![],
+    };
+    let mut highlights = vec![];
+    highlight_line(text, start, &nested, &mut highlights);
+    highlights
+}
+
+/// Highlights of a buffer, and the request that will replace them
+#[derive(Debug, Clone, Default)]
+pub struct Highlights {
+    /// Version of the buffer the highlights were moved to
+    version: u64,
+    highlights: Vec<Highlight>,
+    /// Request in flight, and the version it's for
+    pending: Option<(u64, u64, CancelToken)>,
+    /// Version that was last requested
+    requested: Option<u64>,
+}
+
+impl Highlights {
+    pub fn highlights(&self) -> &[Highlight] {
+        &self.highlights
+    }
+
+    /// Highlights overlapping the `range` of chars
+    pub fn in_range(&self, range: Range<usize>) -> &[Highlight] {
+        let start = self
+            .highlights
+            .partition_point(|h| h.range.end <= range.start);
+        let end = start + self.highlights[start..].partition_point(|h| h.range.start < range.end);
+        &self.highlights[start..end]
+    }
+
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending.as_ref().map(|&(id, _, _)| id)
+    }
+
+    /// Does the buffer need to be highlighted again
+    pub fn is_outdated(&self, buffer: &Buffer) -> bool {
+        self.requested != Some(buffer.version())
+    }
+
+    pub(crate) fn start(&mut self, id: u64, buffer: &Buffer, cancel: CancelToken) {
+        self.cancel();
+        self.pending = Some((id, buffer.version(), cancel));
+        self.requested = Some(buffer.version());
+    }
+
+    /// Give up on the pending request
+    pub(crate) fn cancel(&mut self) {
+        if let Some((_, _, cancel)) = self.pending.take() {
+            cancel.cancel();
+        }
+    }
+
+    /// Take the result of the pending request `id`
+    ///
+    /// Returns `false` if it's not the pending one.
+    pub(crate) fn deliver(&mut self, id: u64, highlights: Vec<Highlight>, buffer: &Buffer) -> bool {
+        match self.pending {
+            Some((pending_id, version, _)) if pending_id == id && version == buffer.version() => {
+                self.pending = None;
+                self.highlights = highlights;
+                self.version = version;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the highlights along the edits made since, and cancel the
+    /// pending request, now outdated
+    pub(crate) fn follow(&mut self, buffer: &Buffer) {
+        if self.version == buffer.version() {
+            return;
+        }
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|&(_, version, _)| version != buffer.version())
+        {
+            self.cancel();
+        }
+        let changes = match buffer.changes_since(self.version) {
+            Some(changes) => changes,
+            None => {
+                self.highlights.clear();
+                self.version = buffer.version();
+                return;
+            }
+        };
+        for change in changes {
+            let start = change.start.0;
+            let removed_end = start + change.removed;
+            let shift = |idx: usize| idx + change.inserted - change.removed;
+            // spans around the change stretch, the ones it cuts are dropped
+            self.highlights.retain_mut(|h| {
+                if h.range.end <= start {
+                    true
+                } else if removed_end <= h.range.start {
+                    h.range = shift(h.range.start)..shift(h.range.end);
+                    true
+                } else if h.range.start < start && removed_end <= h.range.end {
+                    h.range.end = shift(h.range.end);
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+        self.version = buffer.version();
+    }
+}
+
+#[test]
+fn highlight_test() {
+    let text = Rope::from_str("fn main() {\n    let s = \"a \\\" // b\"; // done\n    42\n}\n");
+    let highlights = highlight(&text, "rust", &CancelToken::default()).unwrap();
+    let spans: Vec<_> = highlights
+        .iter()
+        .map(|h| (text.slice(h.range.clone()).to_string(), h.kind))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            ("fn".into(), HighlightKind::Keyword),
+            ("let".into(), HighlightKind::Keyword),
+            ("\"a \\\" // b\"".into(), HighlightKind::String),
+            ("// done".into(), HighlightKind::Comment),
+            ("42".into(), HighlightKind::Number),
+        ]
+    );
+
+    let cancel = CancelToken::default();
+    cancel.cancel();
+    assert_eq!(highlight(&text, "rust", &cancel), None);
+    assert_eq!(highlight(&text, "klingon", &CancelToken::default()), None);
+
+    let text = Rope::from_str("# Title\nrun `cargo` now\n");
+    let highlights = highlight(&text, "markdown", &CancelToken::default()).unwrap();
+    assert_eq!(
+        highlights,
+        vec![
+            Highlight {
+                range: 0..7,
+                kind: HighlightKind::Keyword
+            },
+            Highlight {
+                range: 12..19,
+                kind: HighlightKind::String
+            },
+        ]
+    );
+}
+
+#[test]
+fn injection_test() {
+    let spans = |text: &str, language| -> Vec<(String, HighlightKind)> {
+        let text = Rope::from_str(text);
+        highlight(&text, language, &CancelToken::default())
+            .unwrap()
+            .iter()
+            .map(|h| (text.slice(h.range.clone()).to_string(), h.kind))
+            .collect()
+    };
+    assert_eq!(
+        spans("text\n```rs\nlet x = 1;\n```\nlet\n", "markdown"),
+        vec![
+            ("``".into(), HighlightKind::String),
+            ("`rs".into(), HighlightKind::String),
+            ("let".into(), HighlightKind::Keyword),
+            ("1".into(), HighlightKind::Number),
+            ("``".into(), HighlightKind::String),
+            ("`".into(), HighlightKind::String),
+        ]
+    );
+    assert_eq!(
+        spans("<script>\nif (a) {}\n</script>\n<p>if</p>\n", "html"),
+        vec![("if".into(), HighlightKind::Keyword)]
+    );
+    assert_eq!(
+        spans("let q = \"SELECT a FROM t\";\n", "rust"),
+        vec![
+            ("let".into(), HighlightKind::Keyword),
+            ("\"".into(), HighlightKind::String),
+            ("SELECT".into(), HighlightKind::Keyword),
+            (" a ".into(), HighlightKind::String),
+            ("FROM".into(), HighlightKind::Keyword),
+            (" t\"".into(), HighlightKind::String),
+        ]
+    );
+}
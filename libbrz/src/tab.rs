@@ -0,0 +1,199 @@
+//! Tab pages, each with windows of its own
+//!
+//! A tab has its layout of windows and the view focused in it, and only
+//! the current tab is shown. Its layout is the one of the state while
+//! it's current: the entry of the tab here only gets it back when
+//! another tab is switched to. With more than one tab, a tabline at the
+//! top of the screen lists them, named by the user or after the buffer
+//! of their focused window.
+use crate::layout::Layout;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tab {
+    /// Given by the user, rather than the name of the buffer
+    pub name: Option<String>,
+    pub layout: Option<Layout>,
+    /// View of the window focused
+    pub view_i: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    cur: usize,
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self {
+            tabs: vec![Tab::default()],
+            cur: 0,
+        }
+    }
+}
+
+impl Tabs {
+    /// Tabs in place of these, the current one `cur`, whose layout and
+    /// focused view are taken out to be those of the state
+    pub fn restore(
+        mut tabs: Vec<Tab>,
+        cur: usize,
+        layout: &mut Option<Layout>,
+        view_i: &mut Option<usize>,
+    ) -> Self {
+        if tabs.is_empty() {
+            tabs.push(Tab::default());
+        }
+        let cur = cur.min(tabs.len() - 1);
+        *layout = tabs[cur].layout.take();
+        *view_i = tabs[cur].view_i.take();
+        Self { tabs, cur }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    /// Index of the current tab
+    pub fn cur(&self) -> usize {
+        self.cur
+    }
+
+    /// The tabs, that of the current one without its layout
+    pub fn tabs(&self) -> &[Tab] {
+        &self.tabs
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.tabs[self.cur].name = name;
+    }
+
+    /// Switch to tab `i`, the layout and the focused view of the current
+    /// tab being `layout` and `view_i`, which become those of tab `i`
+    pub fn switch(&mut self, i: usize, layout: &mut Option<Layout>, view_i: &mut Option<usize>) {
+        if i == self.cur || self.tabs.len() <= i {
+            return;
+        }
+        let cur = &mut self.tabs[self.cur];
+        cur.layout = layout.take();
+        cur.view_i = view_i.take();
+        let tab = &mut self.tabs[i];
+        *layout = tab.layout.take();
+        *view_i = tab.view_i.take();
+        self.cur = i;
+    }
+
+    /// Add a tab after the current one, with a window of `new`, and
+    /// switch to it
+    pub fn open(&mut self, new: usize, layout: &mut Option<Layout>, view_i: &mut Option<usize>) {
+        self.tabs.insert(
+            self.cur + 1,
+            Tab {
+                name: None,
+                layout: Some(Layout::Window(new)),
+                view_i: Some(new),
+            },
+        );
+        self.switch(self.cur + 1, layout, view_i);
+    }
+
+    /// Close the current tab, switching to the one after it, or before
+    /// it for the last one; `false` if it's the only tab
+    pub fn close(&mut self, layout: &mut Option<Layout>, view_i: &mut Option<usize>) -> bool {
+        if self.tabs.len() == 1 {
+            return false;
+        }
+        let closed = self.cur;
+        let next = if closed + 1 < self.tabs.len() {
+            closed + 1
+        } else {
+            closed - 1
+        };
+        self.switch(next, layout, view_i);
+        self.tabs.remove(closed);
+        if closed < self.cur {
+            self.cur -= 1;
+        }
+        true
+    }
+
+    /// Close the windows of the other tabs whose view `keep` doesn't
+    /// keep, and the tabs left without any
+    pub fn retain_windows(&mut self, keep: impl Fn(usize) -> bool) {
+        let mut i = 0;
+        while i < self.tabs.len() {
+            if i == self.cur || close_windows(&mut self.tabs[i], &keep) {
+                i += 1;
+                continue;
+            }
+            self.tabs.remove(i);
+            if i < self.cur {
+                self.cur -= 1;
+            }
+        }
+    }
+
+    /// Whether a window of another tab than the current one shows `view_i`
+    pub fn shown_elsewhere(&self, view_i: usize) -> bool {
+        self.tabs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != self.cur)
+            .any(|(_, tab)| tab.layout.as_ref().is_some_and(|l| l.contains(view_i)))
+    }
+}
+
+/// Close the windows of `tab` whose view `keep` doesn't keep; `false`
+/// if none is left
+fn close_windows(tab: &mut Tab, keep: impl Fn(usize) -> bool) -> bool {
+    let layout = match tab.layout.as_mut() {
+        Some(layout) => layout,
+        None => return false,
+    };
+    for view_i in layout.windows() {
+        if keep(view_i) {
+            continue;
+        }
+        match layout.close(view_i) {
+            Some(focus) if tab.view_i == Some(view_i) => tab.view_i = Some(focus),
+            Some(_) => {}
+            None => return false,
+        }
+    }
+    true
+}
+
+#[test]
+fn tabs_test() {
+    let mut tabs = Tabs::default();
+    let mut layout = Some(Layout::Window(0));
+    let mut view_i = Some(0);
+    assert!(!tabs.close(&mut layout, &mut view_i));
+
+    tabs.open(1, &mut layout, &mut view_i);
+    tabs.set_name(Some("one".into()));
+    tabs.switch(0, &mut layout, &mut view_i);
+    tabs.open(2, &mut layout, &mut view_i);
+    assert_eq!((tabs.cur(), view_i), (1, Some(2)));
+    let names: Vec<_> = tabs.tabs().iter().map(|tab| tab.name.as_deref()).collect();
+    assert_eq!(names, vec![None, None, Some("one")]);
+    assert!(tabs.shown_elsewhere(1));
+    assert!(!tabs.shown_elsewhere(2));
+
+    // tabs before the current one left without windows go
+    if let Some(ref mut layout) = layout {
+        layout.split(2, 3, crate::layout::Direction::Vertical);
+    }
+    tabs.retain_windows(|view_i| view_i != 0);
+    assert_eq!((tabs.len(), tabs.cur()), (2, 0));
+    tabs.switch(1, &mut layout, &mut view_i);
+    assert_eq!((layout.clone(), view_i), (Some(Layout::Window(1)), Some(1)));
+
+    // the closed tab goes to the one after, or before for the last
+    assert!(tabs.close(&mut layout, &mut view_i));
+    assert_eq!((tabs.len(), tabs.cur(), view_i), (1, 0, Some(2)));
+}
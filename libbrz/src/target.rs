@@ -0,0 +1,91 @@
+//! Regions of the text found by a provider, to jump to and select
+//!
+//! Git hunks and diagnostics cover ranges of the buffer that aren't
+//! found in the text itself. Selecting one works like a text object, so
+//! the usual commands apply to it, eg. `Alt-h` then `d` deletes the hunk
+//! under the cursor.
+use crate::diff::Hunk;
+use ropey::Rope;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Lines changed since `HEAD`
+    Hunk,
+    Diagnostic,
+}
+
+impl Target {
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Hunk => "hunk",
+            Target::Diagnostic => "diagnostic",
+        }
+    }
+}
+
+/// Index of the first of the `ranges` starting after `idx` (or the last
+/// starting before it), wrapping around
+///
+/// `ranges` are sorted by their start.
+pub fn next_from(ranges: &[Range<usize>], idx: usize, backward: bool) -> Option<usize> {
+    if backward {
+        ranges
+            .iter()
+            .rposition(|range| range.start < idx)
+            .or_else(|| ranges.len().checked_sub(1))
+    } else {
+        ranges
+            .iter()
+            .position(|range| idx < range.start)
+            .or(if ranges.is_empty() { None } else { Some(0) })
+    }
+}
+
+/// Index of the last of the `ranges` containing `idx`
+///
+/// Empty ranges contain the index they are at.
+pub fn at(ranges: &[Range<usize>], idx: usize) -> Option<usize> {
+    ranges
+        .iter()
+        .rposition(|range| range.start <= idx && (idx < range.end || range.start == idx))
+}
+
+/// Chars of `text` that are the new lines of each hunk
+///
+/// Hunks only removing lines are empty ranges where the lines were.
+pub fn hunk_ranges(hunks: &[Hunk], text: &Rope) -> Vec<Range<usize>> {
+    let line_start = |line: usize| {
+        if line < text.len_lines() {
+            text.line_to_char(line)
+        } else {
+            text.len_chars()
+        }
+    };
+    hunks
+        .iter()
+        .map(|hunk| line_start(hunk.new.start)..line_start(hunk.new.end))
+        .collect()
+}
+
+#[test]
+fn target_test() {
+    use crate::diff;
+
+    let old = Rope::from_str("a\nb\nc\nd\n");
+    let new = Rope::from_str("a\nx\ny\nc\n");
+    let hunks = diff::diff_ropes(&old, &new);
+    let ranges = hunk_ranges(&hunks, &new);
+    assert_eq!(ranges, vec![2..6, 8..8]);
+
+    assert_eq!(next_from(&ranges, 0, false), Some(0));
+    assert_eq!(next_from(&ranges, 2, false), Some(1));
+    assert_eq!(next_from(&ranges, 8, false), Some(0));
+    assert_eq!(next_from(&ranges, 8, true), Some(0));
+    assert_eq!(next_from(&ranges, 0, true), Some(1));
+    assert_eq!(next_from(&[], 0, true), None);
+
+    assert_eq!(at(&ranges, 5), Some(0));
+    assert_eq!(at(&ranges, 6), None);
+    assert_eq!(at(&ranges, 8), Some(1));
+}
@@ -0,0 +1,222 @@
+//! What the terminal can show, and drawing within it
+//!
+//! Dumb terminals and serial consoles may have no colors, no italics or
+//! no fonts beyond ASCII. On those, a `Degraded` renderer stands between
+//! the editor and the frontend: colors go, a background making way for
+//! reverse video, as selections do, emphasis the terminal lacks becomes
+//! bold, and chars outside of ASCII are drawn as ASCII ones, `-` and `|`
+//! for the lines between windows, `?` for text.
+use crate::render::{Attrs, Cell, Color, ColorMap, Coord, Rect, Renderer, Style, Window};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub colors: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub unicode: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            colors: true,
+            italic: true,
+            underline: true,
+            unicode: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Nothing but ASCII, as on a dumb terminal
+    pub fn none() -> Self {
+        Self {
+            colors: false,
+            italic: false,
+            underline: false,
+            unicode: false,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Guess from the `TERM` variable, whether `NO_COLOR` is set, and the
+    /// character set of the locale, as in `LC_ALL`, `LC_CTYPE` or `LANG`:
+    /// `en_US.UTF-8` for unicode, `C` or `POSIX` for ASCII
+    pub fn detect(term: Option<&str>, no_color: bool, locale: Option<&str>) -> Self {
+        let term = term.unwrap_or("").trim();
+        let mut caps = match term {
+            "" | "dumb" | "unknown" => Self::none(),
+            _ if term.starts_with("vt52") => Self::none(),
+            _ if term.starts_with("vt1") || term.starts_with("vt2") => Self {
+                underline: true,
+                ..Self::none()
+            },
+            "linux" | "cons25" | "ansi" => Self {
+                italic: false,
+                unicode: term == "linux",
+                ..Self::default()
+            },
+            _ if term.starts_with("screen") => Self {
+                italic: false,
+                ..Self::default()
+            },
+            _ => Self::default(),
+        };
+        caps.colors &= !no_color;
+        if let Some(locale) = locale.map(str::to_lowercase) {
+            if !locale.is_empty() {
+                caps.unicode &= locale.contains("utf-8") || locale.contains("utf8");
+            }
+        }
+        caps
+    }
+
+    /// `style` as this terminal can show it
+    pub fn style(&self, mut style: Style) -> Style {
+        let mut attrs = style.attrs.unwrap_or_default();
+        if !self.colors {
+            let bg = style.bg.take();
+            style.fg = None;
+            if bg.is_some_and(|bg| bg != Color::Default) {
+                attrs |= Attrs::REVERSE;
+            }
+        }
+        for (attr, kept) in [
+            (Attrs::ITALIC, self.italic),
+            (Attrs::UNDERLINE, self.underline),
+        ] {
+            if !kept && attrs.contains(attr) {
+                attrs = attrs.without(attr) | Attrs::BOLD;
+            }
+        }
+        if style.attrs.is_some() || !attrs.is_empty() {
+            style.attrs = Some(attrs);
+        }
+        style
+    }
+
+    /// `cell` as this terminal can show it, taking as many cells
+    pub fn cell(&self, mut cell: Cell) -> Cell {
+        cell.style = self.style(cell.style);
+        if self.unicode {
+            return cell;
+        }
+        if cell.is_continuation() {
+            // of a wide char drawn as a narrow one
+            return Cell::new(' ', cell.style);
+        }
+        // combining marks are never ASCII
+        cell.marks = Default::default();
+        Cell::new(ascii(cell.ch), cell.style)
+    }
+}
+
+/// An ASCII char to draw instead of `ch`
+pub fn ascii(ch: char) -> char {
+    match ch {
+        _ if ch.is_ascii() => ch,
+        '─' | '━' | '—' | '–' => '-',
+        '│' | '┃' => '|',
+        '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' => '+',
+        '…' | '·' => '.',
+        '▎' => '|',
+        '▁' => '_',
+        '▔' => '-',
+        '▸' | '▶' | '→' => '>',
+        '▾' | '▼' => 'v',
+        '↩' => '$',
+        'µ' => 'u',
+        _ => '?',
+    }
+}
+
+/// Another `Renderer`, drawing only what the terminal can show
+pub struct Degraded<R> {
+    capabilities: Capabilities,
+    backend: R,
+}
+
+impl<R> Degraded<R>
+where
+    R: Renderer,
+{
+    pub fn new(backend: R, capabilities: Capabilities) -> Self {
+        Self {
+            capabilities,
+            backend,
+        }
+    }
+}
+
+impl<R> Renderer for Degraded<R>
+where
+    R: Renderer,
+{
+    fn color_map(&self) -> &ColorMap {
+        self.backend.color_map()
+    }
+    fn dimensions(&self) -> Coord {
+        self.backend.dimensions()
+    }
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        self.put_cell(coord, Cell::new(ch, style))
+    }
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        self.backend.put_cell(coord, self.capabilities.cell(cell))
+    }
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.backend.set_cursor(coord)
+    }
+    fn origin(&self) -> Coord {
+        self.backend.origin()
+    }
+    fn keep(&mut self, rect: Rect) -> bool {
+        self.backend.keep(rect)
+    }
+    fn window(&mut self, window: Window) {
+        self.backend.window(window)
+    }
+}
+
+#[test]
+fn capabilities_test() {
+    let detect = Capabilities::detect;
+    assert!(detect(Some("xterm-256color"), false, Some("en_US.UTF-8")).is_full());
+    assert!(detect(Some("xterm-256color"), false, None).is_full());
+    assert_eq!(detect(None, false, None), Capabilities::none());
+    assert_eq!(detect(Some("dumb"), false, None), Capabilities::none());
+    assert!(!detect(Some("xterm"), true, None).colors);
+    assert!(!detect(Some("xterm"), false, Some("C")).unicode);
+    assert!(!detect(Some("screen"), false, None).italic);
+    let vt = detect(Some("vt100"), false, None);
+    assert!(vt.underline && !vt.colors && !vt.unicode);
+
+    let style = Style {
+        fg: Some(Color::Ansi(16)),
+        bg: Some(Color::Ansi(4)),
+        attrs: Some(Attrs::ITALIC),
+    };
+    assert_eq!(Capabilities::default().style(style), style);
+    // selections stand out in reverse video, emphasis in bold
+    assert_eq!(
+        Capabilities::none().style(style),
+        Style {
+            attrs: Some(Attrs::BOLD | Attrs::REVERSE),
+            ..Style::default()
+        }
+    );
+    let fg_only = Style {
+        fg: Some(Color::Ansi(5)),
+        ..Style::default()
+    };
+    assert_eq!(Capabilities::none().style(fg_only), Style::default());
+
+    let ascii = Capabilities::none();
+    assert_eq!(ascii.cell(Cell::new('│', style)).ch, '|');
+    let wide = ascii.cell(Cell::new('中', style));
+    assert_eq!((wide.ch, wide.wide), ('?', false));
+    assert_eq!(ascii.cell(Cell::continuation(style)).ch, ' ');
+}
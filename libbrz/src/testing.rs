@@ -0,0 +1,184 @@
+//! Driving the editor in tests, and looking at what it draws
+//!
+//! The `TestRenderer` keeps the cells and the cursor of a frame, printed
+//! as text to compare with what's expected, and `keys` turns a script
+//! like `ihello<esc>:w<ret>` into the keys typed. Built for the tests of
+//! `libbrz`, and for others with the `testing` feature.
+use crate::layer;
+use crate::render::{Cell, ColorMap, Coord, Renderer, Style, Window};
+use crate::state::State;
+use crate::surface::Surface;
+use crate::Key;
+use std::fmt;
+
+/// Renders a frame into cells, printed as text
+///
+/// Rows are printed without the spaces they end with, followed by a
+/// `cursor <x>:<y>` line, or `cursor none`.
+#[derive(Debug, Clone)]
+pub struct TestRenderer {
+    surface: Surface,
+}
+
+impl TestRenderer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_color_map(width, height, ColorMap::default())
+    }
+
+    /// Drawn in `color_map`, to tell the styles apart
+    pub fn with_color_map(width: usize, height: usize, color_map: ColorMap) -> Self {
+        Self {
+            surface: Surface::new(
+                Coord {
+                    x: width,
+                    y: height,
+                },
+                color_map,
+            ),
+        }
+    }
+
+    /// A frame of `state`
+    pub fn draw(state: &State, width: usize, height: usize) -> Self {
+        let mut render = Self::new(width, height);
+        state.render(&mut render);
+        render.surface.finish();
+        render
+    }
+
+    pub fn row(&self, y: usize) -> String {
+        self.surface.row_text(y).trim_end().to_owned()
+    }
+
+    pub fn style_at(&self, x: usize, y: usize) -> Option<Style> {
+        self.surface.cell(Coord { x, y }).map(|cell| cell.style)
+    }
+
+    pub fn cursor(&self) -> Option<Coord> {
+        self.surface.cursor()
+    }
+
+    pub fn windows(&self) -> &[Window] {
+        self.surface.windows()
+    }
+}
+
+impl fmt::Display for TestRenderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.surface.dimensions().y {
+            writeln!(f, "{}", self.row(y))?;
+        }
+        match self.cursor() {
+            Some(cursor) => write!(f, "cursor {}:{}", cursor.x, cursor.y),
+            None => write!(f, "cursor none"),
+        }
+    }
+}
+
+impl Renderer for TestRenderer {
+    fn color_map(&self) -> &ColorMap {
+        self.surface.color_map()
+    }
+    fn dimensions(&self) -> Coord {
+        self.surface.dimensions()
+    }
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        self.surface.put(coord, ch, style)
+    }
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        self.surface.put_cell(coord, cell)
+    }
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.surface.set_cursor(coord)
+    }
+    fn window(&mut self, window: Window) {
+        self.surface.window(window)
+    }
+}
+
+/// The keys of a script: chars stand for themselves, and keys named as
+/// in the keymaps go between `<` and `>`, like `<esc>`, `<ret>` or
+/// `<c-w>`, with `<lt>` for `<`
+///
+/// Panics on a name it doesn't know, as a test would be wrong to go on.
+pub fn keys(script: &str) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut rest = script;
+    while let Some(ch) = rest.chars().next() {
+        let name = rest
+            .strip_prefix('<')
+            .and_then(|after| after.split_once('>'))
+            .filter(|(name, _)| !name.is_empty() && !name.contains('<'));
+        match name {
+            Some((name, after)) => {
+                keys.push(match name {
+                    "lt" => Key::Char('<'),
+                    name => layer::parse_key(name)
+                        .unwrap_or_else(|| panic!("no key <{}> in {:?}", name, script)),
+                });
+                rest = after;
+            }
+            None => {
+                keys.push(Key::Char(ch));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    keys
+}
+
+/// Type the keys of `script`, as `keys` reads it
+pub fn type_keys(state: &mut State, script: &str) {
+    for key in keys(script) {
+        state.handle_key(key);
+    }
+}
+
+/// An editor with a scratch buffer of `text`, the cursor at its start
+pub fn state_with_text(text: &str) -> State {
+    let mut state = State::default();
+    state.open_scratch_buffer(text);
+    state
+}
+
+#[test]
+fn testing_test() {
+    assert_eq!(
+        keys("ia<esc><c-w><lt>x>:q<ret>"),
+        vec![
+            Key::Char('i'),
+            Key::Char('a'),
+            Key::Esc,
+            Key::Ctrl('w'),
+            Key::Char('<'),
+            Key::Char('x'),
+            Key::Char('>'),
+            Key::Char(':'),
+            Key::Char('q'),
+            Key::Char('\n'),
+        ]
+    );
+    let mut state = state_with_text("one\ntwo\n");
+    type_keys(&mut state, "jiso <esc>");
+    let frame = TestRenderer::draw(&state, 60, 12);
+    let text = frame.to_string();
+    assert!(text.starts_with("0 one\n1 so two\n2\n"), "{}", text);
+    assert!(text.ends_with("\ncursor 5:1"), "{}", text);
+    assert_eq!(frame.row(1), "1 so two");
+    assert_eq!(frame.windows().len(), 1);
+    assert!(frame.windows()[0].focused && frame.windows()[0].modified);
+
+    // lines that don't wrap scroll sideways to the cursor
+    let mut state = state_with_text(&format!("{}\nshort\n", "x".repeat(50)));
+    assert_eq!(
+        TestRenderer::draw(&state, 20, 6).row(0),
+        "0 xxxxxxxxxxxxxxxxx>"
+    );
+    type_keys(&mut state, "gl");
+    let frame = TestRenderer::draw(&state, 20, 6);
+    assert_eq!(
+        (frame.row(0).as_str(), frame.row(1).as_str()),
+        ("0 <xxxxxxxxxxxxxx", "1 <")
+    );
+    assert_eq!(frame.cursor(), Some(Coord { x: 17, y: 0 }));
+}
@@ -0,0 +1,501 @@
+//! Themes, and colors of a pane overriding parts of the theme
+//!
+//! The `ColorMap` of the `Renderer` is painted over with the `Theme`
+//! picked, if any, to give the theme. Kinds of panes, like
+//! the preview or the buffers of a commit message, and single views can
+//! paint some of its styles over, eg. for a darker background. A pane is
+//! drawn with the theme, then the overrides of its kind, then those of
+//! its view.
+//!
+//! Overrides are written as the styles to change, each with what to
+//! change of it: `default=bg:235 line_num=fg:#808080,bold`. Colors are
+//! one of the 256 of the palette, `#rrggbb`, or `default` for the
+//! terminal's own. The attributes are `bold`, `italic`, `underline` and
+//! `reverse`, and `plain` undoes them.
+//!
+//! A theme is a file in a bit of TOML, with a style for any of the
+//! `STYLE_NAMES`, written as for overrides:
+//!
+//! ```text
+//! # dusk
+//! default = "fg:#c0c0c0,bg:#202028"
+//! status = "bg:#303040"
+//! mode = "fg:#202028,bg:#80a0ff,bold"
+//! search_match = "bg:58"
+//! ```
+//!
+//! A theme can come in a light and a dark variant, named as it is with
+//! `-light` and `-dark` after: picking the theme draws with the one for
+//! the background of the terminal, which the frontend tells, or else the
+//! one picked with `:set background`.
+use crate::render::{Attrs, Cell, Color, ColorMap, Coord, Rect, Renderer, Style, Window};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Kinds of panes that can have colors of their own
+pub const PANE_KINDS: &[&str] = &[
+    "text",
+    "quickfix",
+    "pending-edits",
+    "commit",
+    "revision",
+    "dashboard",
+    "report",
+    "preview",
+    "help",
+    "overlay",
+    "popup",
+];
+
+/// Names of the styles of a `ColorMap`, as used in overrides
+pub const STYLE_NAMES: &[&str] = &[
+    "default",
+    "actions",
+    "line_num",
+    "direction_marker",
+    "selection",
+    "special",
+    "conflict_marker",
+    "conflict_ours",
+    "conflict_base",
+    "conflict_theirs",
+    "syntax_keyword",
+    "syntax_string",
+    "syntax_comment",
+    "syntax_number",
+    "diagnostic_error",
+    "diagnostic_warning",
+    "diagnostic_info",
+    "diagnostic_hint",
+    "code_lens",
+    "fold",
+    "jump_label",
+    "reference_text",
+    "reference_read",
+    "reference_write",
+    "status",
+    "mode",
+    "search_match",
+    "bracket_match",
+    "picker_match",
+    "git_added",
+    "git_modified",
+    "git_removed",
+];
+
+fn style_mut<'m>(map: &'m mut ColorMap, name: &str) -> Option<&'m mut Style> {
+    Some(match name {
+        "default" => &mut map.default,
+        "actions" => &mut map.actions,
+        "line_num" => &mut map.line_num,
+        "direction_marker" => &mut map.direction_marker,
+        "selection" => &mut map.selection,
+        "special" => &mut map.special,
+        "conflict_marker" => &mut map.conflict_marker,
+        "conflict_ours" => &mut map.conflict_ours,
+        "conflict_base" => &mut map.conflict_base,
+        "conflict_theirs" => &mut map.conflict_theirs,
+        "syntax_keyword" => &mut map.syntax_keyword,
+        "syntax_string" => &mut map.syntax_string,
+        "syntax_comment" => &mut map.syntax_comment,
+        "syntax_number" => &mut map.syntax_number,
+        "diagnostic_error" => &mut map.diagnostic_error,
+        "diagnostic_warning" => &mut map.diagnostic_warning,
+        "diagnostic_info" => &mut map.diagnostic_info,
+        "diagnostic_hint" => &mut map.diagnostic_hint,
+        "code_lens" => &mut map.code_lens,
+        "fold" => &mut map.fold,
+        "jump_label" => &mut map.jump_label,
+        "reference_text" => &mut map.reference_text,
+        "reference_read" => &mut map.reference_read,
+        "reference_write" => &mut map.reference_write,
+        "status" => &mut map.status,
+        "mode" => &mut map.mode,
+        "search_match" => &mut map.search_match,
+        "bracket_match" => &mut map.bracket_match,
+        "picker_match" => &mut map.picker_match,
+        "git_added" => &mut map.git_added,
+        "git_modified" => &mut map.git_modified,
+        "git_removed" => &mut map.git_removed,
+        _ => return None,
+    })
+}
+
+/// The style of `map` named `name`, one of `STYLE_NAMES`
+pub fn style(map: &ColorMap, name: &str) -> Option<Style> {
+    let mut map = *map;
+    style_mut(&mut map, name).map(|style| *style)
+}
+
+/// A color as `N` of the palette, `#rrggbb` or `default`
+fn parse_color(s: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color: {}", s);
+    if s == "default" {
+        return Ok(Color::Default);
+    }
+    match s.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.is_ascii() => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+            Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+        }
+        Some(_) => Err(invalid()),
+        None => s.parse().map(Color::Ansi).map_err(|_| invalid()),
+    }
+}
+
+/// `fg:COLOR`, `bg:COLOR`, attributes and `plain`, separated by commas
+fn parse_style(s: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    for attr in s.split(',') {
+        let mut add = |attrs| style.attrs = Some(style.attrs.unwrap_or_default() | attrs);
+        match attr.split_once(':') {
+            Some(("fg", color)) => style.fg = Some(parse_color(color)?),
+            Some(("bg", color)) => style.bg = Some(parse_color(color)?),
+            None if attr == "bold" => add(Attrs::BOLD),
+            None if attr == "italic" => add(Attrs::ITALIC),
+            None if attr == "underline" => add(Attrs::UNDERLINE),
+            None if attr == "reverse" => add(Attrs::REVERSE),
+            None if attr == "plain" => style.attrs = Some(Attrs::empty()),
+            _ => return Err(format!("invalid style: {}", attr)),
+        }
+    }
+    Ok(style)
+}
+
+/// Styles to paint over those of a `ColorMap`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorOverrides {
+    styles: BTreeMap<String, Style>,
+}
+
+impl ColorOverrides {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut overrides = Self::default();
+        for word in text.split_whitespace() {
+            let (name, style) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected <style>=<attrs>: {}", word))?;
+            if !STYLE_NAMES.contains(&name) {
+                return Err(format!("no style {}", name));
+            }
+            let style = parse_style(style)?;
+            let old = overrides.styles.entry(name.to_owned()).or_default();
+            *old = old.paintover(style);
+        }
+        Ok(overrides)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+
+    /// `map` with the overrides painted over
+    pub fn apply(&self, map: &ColorMap) -> ColorMap {
+        let mut map = *map;
+        for (name, style) in &self.styles {
+            let target = style_mut(&mut map, name).expect("checked when parsed");
+            *target = target.paintover(*style);
+        }
+        map
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseThemeError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line + 1, self.msg)
+    }
+}
+
+/// Whether the background of the terminal is dark or light
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn name(self) -> &'static str {
+        match self {
+            Background::Dark => "dark",
+            Background::Light => "light",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dark" => Some(Background::Dark),
+            "light" => Some(Background::Light),
+            _ => None,
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Background::Dark => Background::Light,
+            Background::Light => Background::Dark,
+        }
+    }
+
+    fn of_rgb(r: f64, g: f64, b: f64) -> Self {
+        // relative luminance, as perceived
+        if 0.2126 * r + 0.7152 * g + 0.0722 * b < 0.5 {
+            Background::Dark
+        } else {
+            Background::Light
+        }
+    }
+
+    /// The background in the answer of a terminal to the OSC 11 query,
+    /// eg. `\x1b]11;rgb:ffff/ffff/ffff\x07`, each channel of 1 to 4 hex
+    /// digits
+    pub fn from_osc11_reply(reply: &str) -> Option<Self> {
+        let color = reply.split_once("]11;")?.1;
+        // up to the BEL or the ST ending it
+        let color = color.split(['\x07', '\x1b']).next()?;
+        let channels: Vec<f64> = color
+            .strip_prefix("rgb:")?
+            .split('/')
+            .map(|hex| {
+                let max = 16f64.powi(hex.len() as i32) - 1.0;
+                let valid = (1..=4).contains(&hex.len());
+                let value = u16::from_str_radix(hex, 16).ok().filter(|_| valid)?;
+                Some(f64::from(value) / max)
+            })
+            .collect::<Option<_>>()?;
+        match channels[..] {
+            [r, g, b] => Some(Self::of_rgb(r, g, b)),
+            _ => None,
+        }
+    }
+
+    /// The background in `COLORFGBG`, as some terminals set it, eg.
+    /// `15;0`: the last is the color of the background, of the 16 first
+    /// of the palette
+    pub fn from_colorfgbg(value: &str) -> Option<Self> {
+        let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+        match bg {
+            // white, and light grey
+            7 | 15 => Some(Background::Light),
+            0..=15 => Some(Background::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// The theme to draw with when `name` is picked: its variant for
+/// `background`, if there's one
+pub fn variant<'t>(
+    themes: &'t BTreeMap<String, Theme>,
+    name: &str,
+    background: Background,
+) -> Option<&'t Theme> {
+    themes
+        .get(&format!("{}-{}", name, background.name()))
+        .or_else(|| themes.get(name))
+}
+
+/// Styles painted over all of those of the frontend
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Theme {
+    overrides: ColorOverrides,
+}
+
+impl Theme {
+    pub fn parse(text: &str) -> Result<Self, ParseThemeError> {
+        let mut overrides = ColorOverrides::default();
+        for (i, line) in text.lines().enumerate() {
+            let error = |msg: String| ParseThemeError { line: i, msg };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(error("no tables in a theme".into()));
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected <style> = \"<attrs>\"".into()))?;
+            let name = name.trim();
+            if !STYLE_NAMES.contains(&name) {
+                return Err(error(format!("no style {}", name)));
+            }
+            let value = value.trim();
+            let value = [('"', '"'), ('\'', '\'')]
+                .iter()
+                .find_map(|&(open, close)| value.strip_prefix(open)?.strip_suffix(close))
+                .ok_or_else(|| error(format!("expected a quoted style: {}", value)))?;
+            let style = parse_style(value).map_err(error)?;
+            let old = overrides.styles.entry(name.to_owned()).or_default();
+            *old = old.paintover(style);
+        }
+        Ok(Self { overrides })
+    }
+
+    pub fn overrides(&self) -> &ColorOverrides {
+        &self.overrides
+    }
+}
+
+/// Another `Renderer`, with colors of its own
+pub struct Themed<R> {
+    color_map: ColorMap,
+    backend: R,
+}
+
+impl<R> Themed<R>
+where
+    R: Renderer,
+{
+    /// `backend` with `overrides` painted over its colors, in order
+    pub fn new<'o>(backend: R, overrides: impl IntoIterator<Item = &'o ColorOverrides>) -> Self {
+        let color_map = overrides
+            .into_iter()
+            .fold(*backend.color_map(), |map, overrides| overrides.apply(&map));
+        Self { color_map, backend }
+    }
+}
+
+impl<R> Renderer for Themed<R>
+where
+    R: Renderer,
+{
+    fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+    fn dimensions(&self) -> Coord {
+        self.backend.dimensions()
+    }
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        self.backend.put(coord, ch, style)
+    }
+    fn put_cell(&mut self, coord: Coord, cell: Cell) {
+        self.backend.put_cell(coord, cell)
+    }
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.backend.set_cursor(coord)
+    }
+    fn origin(&self) -> Coord {
+        self.backend.origin()
+    }
+    fn keep(&mut self, rect: Rect) -> bool {
+        self.backend.keep(rect)
+    }
+    fn window(&mut self, window: Window) {
+        self.backend.window(window)
+    }
+}
+
+#[test]
+fn color_overrides_test() {
+    let base = ColorMap {
+        default: Style {
+            fg: Some(Color::Ansi(7)),
+            ..Style::default()
+        },
+        ..ColorMap::default()
+    };
+    let kind =
+        ColorOverrides::parse("default=bg:235 line_num=fg:#ff8000,bold,italic").expect("valid");
+    let view = ColorOverrides::parse("default=bg:17").expect("valid");
+    let map = view.apply(&kind.apply(&base));
+    assert_eq!(
+        map.default,
+        Style {
+            fg: Some(Color::Ansi(7)),
+            bg: Some(Color::Ansi(17)),
+            attrs: None
+        }
+    );
+    assert_eq!(
+        map.line_num,
+        Style {
+            fg: Some(Color::Rgb(255, 128, 0)),
+            bg: None,
+            attrs: Some(Attrs::BOLD | Attrs::ITALIC)
+        }
+    );
+    assert_eq!(map.selection, base.selection);
+    assert!(ColorOverrides::parse("").expect("valid").is_empty());
+    assert_eq!(
+        ColorOverrides::parse("nope=bold"),
+        Err("no style nope".to_owned())
+    );
+    assert!(ColorOverrides::parse("default=fg:x").is_err());
+    assert!(ColorOverrides::parse("default=fg:#12345").is_err());
+    assert_eq!(
+        ColorOverrides::parse("default=fg:default")
+            .expect("valid")
+            .apply(&base)
+            .default
+            .fg,
+        Some(Color::Default)
+    );
+    assert!(ColorOverrides::parse("default").is_err());
+}
+
+#[test]
+fn theme_test() {
+    let theme =
+        Theme::parse("# dusk\n\ndefault = \"fg:252,bg:#202028\"\nmode = 'bold'\nmode = \"fg:4\"\n")
+            .expect("valid");
+    let map = theme.overrides().apply(&ColorMap::default());
+    assert_eq!(map.default.bg, Some(Color::Rgb(0x20, 0x20, 0x28)));
+    assert_eq!(
+        map.mode,
+        Style {
+            fg: Some(Color::Ansi(4)),
+            bg: None,
+            attrs: Some(Attrs::BOLD)
+        }
+    );
+    assert_eq!(map.status, Style::default());
+
+    let err = Theme::parse("default = \"bold\"\nstatus = bold\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: expected a quoted style: bold");
+    assert!(Theme::parse("[styles]").is_err());
+    assert!(Theme::parse("nope = \"bold\"").is_err());
+}
+
+#[test]
+fn background_test() {
+    let from_reply = Background::from_osc11_reply;
+    assert_eq!(
+        from_reply("\x1b]11;rgb:ffff/ffff/f0f0\x07"),
+        Some(Background::Light)
+    );
+    assert_eq!(
+        from_reply("\x1b]11;rgb:1c/1c/24\x1b\\"),
+        Some(Background::Dark)
+    );
+    assert_eq!(
+        from_reply("\x1b]11;rgb:8/8/8\x07\x1b[?62;22c"),
+        Some(Background::Light)
+    );
+    assert_eq!(from_reply("\x1b]11;rgb:00/ff\x07"), None);
+    assert_eq!(from_reply("\x1b[?62c"), None);
+    assert_eq!(Background::from_colorfgbg("0;15"), Some(Background::Light));
+    assert_eq!(
+        Background::from_colorfgbg("15;default;0"),
+        Some(Background::Dark)
+    );
+    assert_eq!(Background::from_colorfgbg("15;default"), None);
+
+    let mut themes = BTreeMap::new();
+    themes.insert("dusk".to_owned(), Theme::default());
+    themes.insert(
+        "dusk-light".to_owned(),
+        Theme::parse("mode = \"bold\"").unwrap(),
+    );
+    let light = variant(&themes, "dusk", Background::Light).unwrap();
+    assert!(!light.overrides().is_empty());
+    let dark = variant(&themes, "dusk", Background::Dark).unwrap();
+    assert!(dark.overrides().is_empty());
+    assert!(variant(&themes, "dawn", Background::Dark).is_none());
+}
@@ -0,0 +1,118 @@
+//! TODO, FIXME and HACK comments of the workspace
+//!
+//! A tag counts when it's a whole upper case word followed by `:` or
+//! `(`, after a comment marker on the same line, like
+//! `// TODO(alice): …` or `# FIXME: …`. The frontend walks the files in
+//! the background, and passes what `scan` found in them to
+//! `State::deliver_todos`.
+use crate::preview::CancelToken;
+use crate::quickfix;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tag {
+    Todo,
+    Fixme,
+    Hack,
+}
+
+pub const TAGS: [Tag; 3] = [Tag::Todo, Tag::Fixme, Tag::Hack];
+
+/// What comments start with, in the languages around
+const COMMENT_MARKERS: [&str; 5] = ["//", "#", "--", "/*", ";"];
+
+impl Tag {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tag::Todo => "TODO",
+            Tag::Fixme => "FIXME",
+            Tag::Hack => "HACK",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Todo {
+    pub tag: Tag,
+    pub entry: quickfix::Entry,
+}
+
+/// What the frontend is asked to scan the workspace for
+#[derive(Clone, Debug)]
+pub struct TodoRequest {
+    pub id: u64,
+    pub cancel: CancelToken,
+}
+
+/// The tag of a comment in `line`, if any, and the byte it's at
+fn find_tag(line: &str) -> Option<(usize, Tag)> {
+    let start = COMMENT_MARKERS
+        .iter()
+        .filter_map(|marker| line.find(marker).map(|i| i + marker.len()))
+        .min()?;
+    let comment = &line[start..];
+    let is_word = |ch: Option<char>| ch.is_some_and(|ch| ch.is_alphanumeric() || ch == '_');
+    TAGS.iter()
+        .filter_map(|&tag| {
+            comment
+                .match_indices(tag.name())
+                .find(|&(i, name)| {
+                    !is_word(comment[..i].chars().next_back())
+                        && matches!(comment[i + name.len()..].chars().next(), Some(':' | '('))
+                })
+                .map(|(i, _)| (start + i, tag))
+        })
+        .min()
+}
+
+/// The tagged comments of the file at `path` with `text`, in order
+pub fn scan(path: &Path, text: &str) -> Vec<Todo> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let (at, tag) = find_tag(text)?;
+            Some(Todo {
+                tag,
+                entry: quickfix::Entry {
+                    path: path.to_owned(),
+                    line,
+                    text: text.to_owned(),
+                    matched: Some(at..at + tag.name().len()),
+                },
+            })
+        })
+        .collect()
+}
+
+/// "3 todo 1 fixme", leaving out tags with none
+pub fn summary(todos: &[Todo]) -> Option<String> {
+    let counts: Vec<String> = TAGS
+        .iter()
+        .filter_map(|&tag| {
+            let count = todos.iter().filter(|todo| todo.tag == tag).count();
+            Some(format!("{} {}", count, tag.name().to_lowercase())).filter(|_| 0 < count)
+        })
+        .collect();
+    Some(counts.join(" ")).filter(|summary| !summary.is_empty())
+}
+
+#[test]
+fn scan_test() {
+    let text = "\
+// TODO: one
+let todo = \"TODO: not a comment\";
+x(); // FIXME(bob) two
+# HACK three
+-- NOTHACK: no
+/* TODOS: no */
+; HACK: four
+";
+    let todos = scan(Path::new("a.rs"), text);
+    let found: Vec<(Tag, usize)> = todos.iter().map(|t| (t.tag, t.entry.line)).collect();
+    assert_eq!(found, vec![(Tag::Todo, 0), (Tag::Fixme, 2), (Tag::Hack, 6)]);
+    assert_eq!(todos[1].entry.text, "x(); // FIXME(bob) two");
+    assert_eq!(todos[1].entry.matched, Some(8..13));
+    assert_eq!(summary(&todos), Some("1 todo 1 fixme 1 hack".to_owned()));
+    assert_eq!(summary(&todos[..1]), Some("1 todo".to_owned()));
+    assert_eq!(summary(&[]), None);
+}
@@ -0,0 +1,10 @@
+//! Deleting files to the trash, from where they can be brought back
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrashOp {
+    /// Move a file to the trash; gives where it went
+    Trash(PathBuf),
+    /// Move a trashed file back to where it was; gives `path`
+    Restore { trashed: PathBuf, path: PathBuf },
+}
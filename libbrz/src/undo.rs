@@ -0,0 +1,580 @@
+//! The undo history of a buffer, as a tree
+//!
+//! Every undo point is a node with the text at the time, its parent
+//! being the point it was edited from: editing after undoing starts a
+//! new branch rather than dropping what was undone. Redo goes down the
+//! branch last gone down. The nodes are also in the order they were
+//! made, each stamped with the time it was, to go to an earlier or a
+//! later state whatever the branch, by steps or by time. `libbrz` has no
+//! clock of its own: the time is `now`, as last told.
+//!
+//! Kept across sessions as text, per file, with the changes from the
+//! parent of each node rather than its text, so it's only of use with
+//! the text of the file as it was written.
+use crate::buffer::SelectionSet;
+use crate::selection::Selection;
+use crate::Idx;
+use ropey::Rope;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reading or writing the undo history of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoFileOp {
+    /// Gives the history kept, if there is one
+    Read(PathBuf),
+    Write {
+        path: PathBuf,
+        text: String,
+    },
+}
+
+/// How far to go back or forward in the history
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UndoSpan {
+    /// Undo points
+    Steps(isize),
+    Time(Duration),
+}
+
+/// `3` for undo points, or `10s`, `5m`, `2h` or `1d` for a time
+pub fn parse_span(s: &str) -> Option<UndoSpan> {
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return s.parse().ok().map(UndoSpan::Steps),
+    };
+    let n: u64 = s[..s.len() - 1].parse().ok()?;
+    Some(UndoSpan::Time(Duration::from_secs(n * unit)))
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoNode {
+    pub text: Rope,
+    /// Selection of the view that was editing at the time
+    pub selection: SelectionSet,
+    /// A version of the buffer at which it had this `text`, so that
+    /// telling whether the text changed is mostly free, even in huge files
+    pub version: u64,
+    pub time: SystemTime,
+    pub parent: Option<usize>,
+    /// The child last made or gone to, where redo goes
+    pub last_child: Option<usize>,
+}
+
+impl UndoNode {
+    /// Whether it has `text`, at `version`; remembers the version if so
+    fn is_at(&mut self, text: &Rope, version: u64) -> bool {
+        if self.version == version {
+            return true;
+        }
+        let same = self.text == *text;
+        if same {
+            self.version = version;
+        }
+        same
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoTree {
+    /// Oldest first
+    nodes: Vec<UndoNode>,
+    /// The node the text was last committed at or restored from
+    cur: Option<usize>,
+    /// Time of the nodes made
+    pub now: SystemTime,
+    /// Nodes kept at most, the oldest that can go dropped first
+    pub limit: Option<usize>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            cur: None,
+            now: UNIX_EPOCH,
+            limit: None,
+        }
+    }
+}
+
+impl UndoTree {
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn cur(&self) -> Option<usize> {
+        self.cur
+    }
+
+    pub fn node(&self, i: usize) -> &UndoNode {
+        &self.nodes[i]
+    }
+
+    /// Make `text` an undo point, a child of the current one, or only
+    /// update the selection of the current one if it has the same text
+    pub fn commit(&mut self, text: &Rope, version: u64, selection: &SelectionSet) {
+        if let Some(cur) = self.cur {
+            let node = &mut self.nodes[cur];
+            if node.is_at(text, version) {
+                // so that undo always goes back to where the selection
+                // was right before the edit
+                node.selection = selection.clone();
+                return;
+            }
+        }
+        let new = self.nodes.len();
+        self.nodes.push(UndoNode {
+            text: text.clone(),
+            selection: selection.clone(),
+            version,
+            time: self.now,
+            parent: self.cur,
+            last_child: None,
+        });
+        if let Some(cur) = self.cur {
+            self.nodes[cur].last_child = Some(new);
+        }
+        self.cur = Some(new);
+        self.trim();
+    }
+
+    /// Make node `i` the current one, the buffer having its text at
+    /// `version`
+    pub fn restore(&mut self, i: usize, version: u64) -> &UndoNode {
+        self.nodes[i].version = version;
+        if let Some(parent) = self.nodes[i].parent {
+            self.nodes[parent].last_child = Some(i);
+        }
+        self.cur = Some(i);
+        &self.nodes[i]
+    }
+
+    /// Whether the current node has `text`, at `version`, edits since
+    /// having been committed
+    pub fn is_at_cur(&mut self, text: &Rope, version: u64) -> bool {
+        match self.cur {
+            Some(cur) => self.nodes[cur].is_at(text, version),
+            None => false,
+        }
+    }
+
+    /// The node `times` parents up from the current one, as far as the
+    /// root
+    pub fn undo_target(&self, times: usize) -> Option<usize> {
+        let mut i = self.cur?;
+        for _ in 0..times {
+            match self.nodes[i].parent {
+                Some(parent) => i = parent,
+                None => break,
+            }
+        }
+        Some(i)
+    }
+
+    /// The node `times` down the branch last gone down, as far as it
+    /// goes; `None` if there's nothing to redo
+    pub fn redo_target(&self, times: usize) -> Option<usize> {
+        let mut i = self.cur?;
+        for _ in 0..times {
+            match self.nodes[i].last_child {
+                Some(child) => i = child,
+                None => break,
+            }
+        }
+        Some(i).filter(|&i| Some(i) != self.cur)
+    }
+
+    /// The node made `steps` before the current one, or after with
+    /// negative `steps`, whatever the branch
+    pub fn step_target(&self, steps: isize) -> Option<usize> {
+        let cur = self.cur? as isize;
+        let i = (cur - steps).clamp(0, self.nodes.len() as isize - 1) as usize;
+        Some(i).filter(|&i| Some(i) != self.cur)
+    }
+
+    /// The last node made by `duration` before the current one was, or
+    /// after it with `later`
+    pub fn time_target(&self, duration: Duration, later: bool) -> Option<usize> {
+        let cur = self.cur?;
+        let time = self.nodes[cur].time;
+        let i = if later {
+            let until = time + duration;
+            cur + self.nodes[cur..]
+                .iter()
+                .rposition(|node| node.time <= until)
+                .unwrap_or(0)
+        } else {
+            let until = time.checked_sub(duration).unwrap_or(UNIX_EPOCH);
+            self.nodes[..cur]
+                .iter()
+                .rposition(|node| node.time <= until)
+                .unwrap_or(0)
+        };
+        Some(i).filter(|&i| i != cur)
+    }
+
+    /// Forget the current node, if it has `text` at `version` and nothing
+    /// was made from it, as if the change to it never happened
+    pub fn forget_cur(&mut self, text: &Rope, version: u64) {
+        let cur = match self.cur {
+            Some(cur) if cur + 1 == self.nodes.len() => cur,
+            _ => return,
+        };
+        if !self.nodes[cur].is_at(text, version) {
+            return;
+        }
+        let parent = self.nodes[cur].parent;
+        self.remove(cur);
+        self.cur = parent;
+    }
+
+    fn children(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        (i + 1..self.nodes.len()).filter(move |&child| self.nodes[child].parent == Some(i))
+    }
+
+    /// Drop the oldest nodes past the limit, but the current one; those
+    /// are leaves, or a root with one child, for the others to stay
+    /// connected
+    fn trim(&mut self) {
+        let limit = match self.limit {
+            Some(limit) => limit.max(1),
+            None => return,
+        };
+        while limit < self.nodes.len() {
+            let removable = (0..self.nodes.len()).find(|&i| {
+                let children = self.children(i).count();
+                Some(i) != self.cur
+                    && (children == 0 || (self.nodes[i].parent.is_none() && children == 1))
+            });
+            match removable {
+                Some(i) => self.remove(i),
+                None => break,
+            }
+        }
+    }
+
+    /// Remove node `i`, a leaf or a root with one child
+    fn remove(&mut self, i: usize) {
+        let parent = self.nodes[i].parent;
+        let child = self.children(i).next();
+        if let Some(child) = child {
+            self.nodes[child].parent = None;
+        }
+        if let Some(parent) = parent {
+            if self.nodes[parent].last_child == Some(i) {
+                self.nodes[parent].last_child = self.children(parent).filter(|&c| c != i).last();
+            }
+        }
+        self.nodes.remove(i);
+        let shift = |index: &mut Option<usize>| {
+            if let Some(index) = index.as_mut().filter(|index| i < **index) {
+                *index -= 1;
+            }
+        };
+        for node in &mut self.nodes {
+            shift(&mut node.parent);
+            shift(&mut node.last_child);
+        }
+        shift(&mut self.cur);
+    }
+
+    /// The history as text, to be read back by `from_text` along with the
+    /// text of the current node
+    ///
+    /// A `cur` line tells the current node, and the length and hash of
+    /// its text. A line per node tells its parent, time, primary
+    /// selection, and where it changed the text of its parent: how many
+    /// chars it removed from where and how many it inserted. Those chars
+    /// follow, then a newline.
+    pub fn to_text(&self) -> String {
+        let cur = match self.cur {
+            Some(cur) => cur,
+            None => return String::new(),
+        };
+        let text = &self.nodes[cur].text;
+        let mut out = format!("cur {} {} {:x}\n", cur, text.len_chars(), hash(text));
+        for node in &self.nodes {
+            let parent = node.parent.map(|parent| &self.nodes[parent].text);
+            let (start, removed, inserted) = match parent {
+                Some(parent) => diff(parent, &node.text),
+                None => (0, 0..0, 0..0),
+            };
+            let time = node.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let selection = node.selection.selections[node.selection.primary];
+            let _ = writeln!(
+                out,
+                "node {} {} {} {} {} {} {}",
+                node.parent
+                    .map_or("-".to_owned(), |parent| parent.to_string()),
+                time.as_millis(),
+                selection.anchor.0,
+                selection.cursor.0,
+                start,
+                removed.len(),
+                inserted.len()
+            );
+            if let Some(parent) = parent {
+                out.extend(parent.slice(removed).chars());
+            }
+            out.extend(node.text.slice(inserted).chars());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Read back what `to_text` gave, `text` being the text of the
+    /// current node; `None` if it's not, or that's not a history
+    pub fn from_text(s: &str, text: &Rope) -> Option<Self> {
+        let mut chars = s.chars();
+        let line = |chars: &mut std::str::Chars| -> Option<String> {
+            let line: String = chars.take_while(|&ch| ch != '\n').collect();
+            Some(line).filter(|line| !line.is_empty())
+        };
+        let header = line(&mut chars)?;
+        let header: Vec<&str> = header.split(' ').collect();
+        let (cur, len, expected) = match header[..] {
+            ["cur", cur, len, hash] => {
+                (cur.parse::<usize>().ok()?, len.parse::<usize>().ok()?, hash)
+            }
+            _ => return None,
+        };
+        if len != text.len_chars() || format!("{:x}", self::hash(text)) != expected {
+            return None;
+        }
+
+        // the changes from their parent, to find the texts from that of
+        // the current node
+        struct Change {
+            start: usize,
+            removed: String,
+            inserted: String,
+        }
+        let mut nodes = vec![];
+        let mut changes = vec![];
+        while let Some(header) = line(&mut chars) {
+            let fields: Vec<&str> = header.split(' ').collect();
+            let numbers = match fields[..] {
+                ["node", parent, ref rest @ ..] if rest.len() == 6 => {
+                    let parent = match parent {
+                        "-" => None,
+                        parent => Some(parent.parse::<usize>().ok()?),
+                    };
+                    let numbers: Vec<u64> =
+                        rest.iter().map(|n| n.parse().ok()).collect::<Option<_>>()?;
+                    (parent, numbers)
+                }
+                _ => return None,
+            };
+            let (parent, n) = numbers;
+            if parent.is_some_and(|parent| nodes.len() <= parent) {
+                return None;
+            }
+            let take = |chars: &mut std::str::Chars, count: u64| -> Option<String> {
+                let taken: String = chars.take(count as usize).collect();
+                Some(taken).filter(|taken| taken.chars().count() as u64 == count)
+            };
+            let removed = take(&mut chars, n[4])?;
+            let inserted = take(&mut chars, n[5])?;
+            if chars.next() != Some('\n') {
+                return None;
+            }
+            nodes.push(UndoNode {
+                text: Rope::new(),
+                selection: SelectionSet {
+                    selections: vec![Selection::new_from_normalized(
+                        Idx(n[1] as usize),
+                        Idx(n[2] as usize),
+                    )],
+                    ..SelectionSet::default()
+                },
+                version: u64::MAX,
+                time: UNIX_EPOCH + Duration::from_millis(n[0]),
+                parent,
+                last_child: None,
+            });
+            changes.push(Change {
+                start: n[3] as usize,
+                removed,
+                inserted,
+            });
+        }
+        if nodes.len() <= cur {
+            return None;
+        }
+
+        // up from the current node, then down from there in order
+        let mut known = vec![false; nodes.len()];
+        nodes[cur].text = text.clone();
+        known[cur] = true;
+        let mut i = cur;
+        while let Some(parent) = nodes[i].parent {
+            let change = &changes[i];
+            let mut parent_text = nodes[i].text.clone();
+            let end = change.start + change.inserted.chars().count();
+            if parent_text.len_chars() < end {
+                return None;
+            }
+            parent_text.remove(change.start..end);
+            parent_text.insert(change.start, &change.removed);
+            nodes[parent].text = parent_text;
+            known[parent] = true;
+            i = parent;
+        }
+        for i in 0..nodes.len() {
+            if known[i] {
+                continue;
+            }
+            let parent = nodes[i].parent.filter(|&parent| known[parent])?;
+            let change = &changes[i];
+            let mut child_text = nodes[parent].text.clone();
+            let end = change.start + change.removed.chars().count();
+            if child_text.len_chars() < end {
+                return None;
+            }
+            child_text.remove(change.start..end);
+            child_text.insert(change.start, &change.inserted);
+            nodes[i].text = child_text;
+            known[i] = true;
+        }
+        for i in 0..nodes.len() {
+            let len = nodes[i].text.len_chars();
+            for selection in &mut nodes[i].selection.selections {
+                selection.anchor.0 = selection.anchor.0.min(len);
+                selection.cursor.0 = selection.cursor.0.min(len);
+            }
+            if let Some(parent) = nodes[i].parent {
+                nodes[parent].last_child = Some(i);
+            }
+        }
+        Some(Self {
+            nodes,
+            cur: Some(cur),
+            ..Self::default()
+        })
+    }
+}
+
+/// Where `new` differs from `old`: from where, what of `old` it doesn't
+/// have and what of `new` replaced it, in chars
+fn diff(old: &Rope, new: &Rope) -> (usize, std::ops::Range<usize>, std::ops::Range<usize>) {
+    let prefix = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let max_suffix = old.len_chars().min(new.len_chars()) - prefix;
+    let suffix = (1..=max_suffix)
+        .take_while(|&n| old.char(old.len_chars() - n) == new.char(new.len_chars() - n))
+        .count();
+    (
+        prefix,
+        prefix..old.len_chars() - suffix,
+        prefix..new.len_chars() - suffix,
+    )
+}
+
+/// FNV-1a of the bytes of `text`
+fn hash(text: &Rope) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for chunk in text.chunks() {
+        for &byte in chunk.as_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+    hash
+}
+
+#[test]
+fn undo_tree_test() {
+    let mut tree = UndoTree::default();
+    let selection = SelectionSet::default();
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+    let commit = |tree: &mut UndoTree, text: &str, version, secs| {
+        tree.now = at(secs);
+        tree.commit(&Rope::from_str(text), version, &selection);
+    };
+    commit(&mut tree, "a", 0, 0);
+    commit(&mut tree, "ab", 1, 10);
+    commit(&mut tree, "abc", 2, 20);
+    // the same text again is the same node
+    commit(&mut tree, "abc", 3, 30);
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.redo_target(1), None);
+
+    // edits after undoing branch off
+    let undone = tree.undo_target(1).expect("node");
+    tree.restore(undone, 4);
+    commit(&mut tree, "abd", 5, 40);
+    assert_eq!(tree.len(), 4);
+    assert_eq!(tree.node(3).parent, Some(1));
+    let text = |tree: &UndoTree, i: Option<usize>| tree.node(i.expect("node")).text.to_string();
+    assert_eq!(text(&tree, tree.undo_target(1)), "ab");
+    assert_eq!(text(&tree, tree.undo_target(10)), "a");
+
+    // redo goes down the branch last gone down
+    tree.restore(1, 6);
+    assert_eq!(text(&tree, tree.redo_target(1)), "abd");
+    tree.restore(2, 7);
+    tree.restore(1, 8);
+    assert_eq!(text(&tree, tree.redo_target(1)), "abc");
+
+    // in the order made, whatever the branch
+    tree.restore(3, 9);
+    assert_eq!(text(&tree, tree.step_target(1)), "abc");
+    assert_eq!(tree.step_target(-1), None);
+    assert_eq!(
+        text(&tree, tree.time_target(Duration::from_secs(25), false)),
+        "ab"
+    );
+    tree.restore(0, 10);
+    assert_eq!(
+        text(&tree, tree.time_target(Duration::from_secs(20), true)),
+        "abc"
+    );
+
+    // kept as text, given the text of the current node
+    tree.restore(3, 11);
+    let kept = tree.to_text();
+    assert!(UndoTree::from_text(&kept, &Rope::from_str("abc")).is_none());
+    let read = UndoTree::from_text(&kept, &Rope::from_str("abd")).expect("history");
+    let texts: Vec<String> = (0..read.len())
+        .map(|i| read.node(i).text.to_string())
+        .collect();
+    assert_eq!(texts, vec!["a", "ab", "abc", "abd"]);
+    assert_eq!(read.node(2).time, at(20));
+    assert_eq!(read.cur(), Some(3));
+    assert!(UndoTree::from_text("nope", &Rope::new()).is_none());
+
+    // the oldest nodes that can go do
+    let mut limited = read.clone();
+    limited.limit = Some(2);
+    commit(&mut limited, "abde", 12, 50);
+    let texts: Vec<String> = (0..limited.len())
+        .map(|i| limited.node(i).text.to_string())
+        .collect();
+    assert_eq!(texts, vec!["abd", "abde"]);
+    assert_eq!(limited.undo_target(5), Some(0));
+
+    // the change just made can be forgotten
+    limited.forget_cur(&Rope::from_str("abde"), 12);
+    assert_eq!((limited.len(), limited.cur()), (1, Some(0)));
+    assert_eq!(limited.node(0).last_child, None);
+
+    assert_eq!(parse_span("3"), Some(UndoSpan::Steps(3)));
+    assert_eq!(
+        parse_span("5m"),
+        Some(UndoSpan::Time(Duration::from_secs(300)))
+    );
+    assert_eq!(parse_span("m"), None);
+    assert_eq!(parse_span("2w"), None);
+}
@@ -1 +1,2 @@
 pub mod char;
+pub mod uri;
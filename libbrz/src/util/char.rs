@@ -1,15 +1,9 @@
 pub fn is_opening_indent(ch: char) -> bool {
-    match ch {
-        '[' | '(' | '<' | '{' => true,
-        _ => false,
-    }
+    matches!(ch, '[' | '(' | '<' | '{')
 }
 
 pub fn is_closing_indent(ch: char) -> bool {
-    match ch {
-        ']' | ')' | '>' | '}' => true,
-        _ => false,
-    }
+    matches!(ch, ']' | ')' | '>' | '}')
 }
 
 pub fn is_word_forming(ch: char) -> bool {
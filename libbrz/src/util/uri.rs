@@ -0,0 +1,31 @@
+//! `file://` URIs, as terminals and language servers write them
+
+/// `s` with its `%20` and the like decoded; `None` if one isn't followed
+/// by two hex digits, or what they make isn't UTF-8
+pub fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[test]
+fn percent_decode_test() {
+    assert_eq!(
+        percent_decode("/a%20b/caf%C3%A9").as_deref(),
+        Some("/a b/café")
+    );
+    assert_eq!(percent_decode("/100%"), None);
+    assert_eq!(percent_decode("/%zz"), None);
+    assert_eq!(percent_decode("/%E9"), None);
+}
@@ -0,0 +1,354 @@
+//! File systems the editor works on
+//!
+//! The local one, a remote host or, for tests, one in memory: they all
+//! list, stat, read, write and watch paths the same way, and
+//! `State::register_vfs` makes the buffers and the pickers use one.
+//! Calls block; frontends make them from other threads where it matters,
+//! like for the previews. Files are read and written as bytes, which
+//! `encoding` turns into text and back.
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// Anything else, like a device
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: EntryKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub writable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+/// A watch goes on until this is dropped
+#[derive(Debug)]
+pub struct Watch {
+    active: Arc<AtomicBool>,
+}
+
+impl Watch {
+    /// A watch, and the flag telling the watcher it's still wanted
+    pub fn new() -> (Self, Arc<AtomicBool>) {
+        let active = Arc::new(AtomicBool::new(true));
+        (
+            Self {
+                active: active.clone(),
+            },
+            active,
+        )
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Called with the path that changed, or with the watched one if that's
+/// all that's known
+pub type OnChange = Box<dyn Fn(&Path) + Send>;
+
+pub trait Vfs: Send + Sync {
+    /// Entries of a directory, sorted by name
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    fn stat(&self, path: &Path) -> io::Result<Metadata>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+
+    /// Tell about the changes of a file, or of the entries of a directory
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<Watch>;
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: not found", path.display()),
+    )
+}
+
+/// Files of `root` and its subdirectories whose path contains the chars
+/// of `pattern` in order, at most `limit` of them
+///
+/// Hidden files and directories are skipped.
+pub fn find_files(
+    vfs: &dyn Vfs,
+    root: &Path,
+    pattern: &str,
+    limit: usize,
+) -> io::Result<Vec<PathBuf>> {
+    let matches = |path: &Path| {
+        let path = path.to_string_lossy();
+        let mut rest = &path[..];
+        pattern.chars().all(|ch| match rest.find(ch) {
+            Some(i) => {
+                rest = &rest[i + ch.len_utf8()..];
+                true
+            }
+            None => false,
+        })
+    };
+    let mut found = vec![];
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match vfs.list_dir(&dir) {
+            Ok(entries) => entries,
+            // unreadable subdirectories are skipped
+            Err(e) if dir == root => return Err(e),
+            Err(_) => continue,
+        };
+        let mut subdirs = vec![];
+        for entry in entries.into_iter().filter(|e| !e.name.starts_with('.')) {
+            let path = if dir == Path::new(".") {
+                PathBuf::from(&entry.name)
+            } else {
+                dir.join(&entry.name)
+            };
+            match entry.kind {
+                EntryKind::Dir => subdirs.push(path),
+                EntryKind::File if matches(&path) => {
+                    found.push(path);
+                    if found.len() == limit {
+                        return Ok(found);
+                    }
+                }
+                _ => {}
+            }
+        }
+        // depth first, in order
+        dirs.extend(subdirs.into_iter().rev());
+    }
+    Ok(found)
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    read_only: Vec<PathBuf>,
+    watches: Vec<(PathBuf, Arc<AtomicBool>, OnChange)>,
+}
+
+/// Files kept in memory, for tests
+///
+/// Directories are implied by the paths of the files. Watchers are
+/// called with the files locked, so they must not use them.
+#[derive(Default, Clone)]
+pub struct MemoryFs {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, path: impl Into<PathBuf>, text: impl AsRef<[u8]>) {
+        let path = path.into();
+        let mut inner = self.inner.lock().expect("not poisoned");
+        inner.files.insert(path.clone(), text.as_ref().to_vec());
+        inner.notify(&path);
+    }
+
+    pub fn set_read_only(&self, path: impl Into<PathBuf>) {
+        let mut inner = self.inner.lock().expect("not poisoned");
+        inner.read_only.push(path.into());
+    }
+
+    pub fn text(&self, path: &Path) -> Option<String> {
+        let inner = self.inner.lock().expect("not poisoned");
+        inner
+            .files
+            .get(path)
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+impl MemoryInner {
+    fn notify(&mut self, path: &Path) {
+        self.watches
+            .retain(|(_, active, _)| active.load(Ordering::Relaxed));
+        for (watched, _, on_change) in &self.watches {
+            if path == watched || path.parent() == Some(watched) {
+                on_change(path);
+            }
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.as_os_str().is_empty()
+            || path == Path::new(".")
+            || self
+                .files
+                .keys()
+                .any(|file| file != path && file.starts_with(path))
+    }
+}
+
+/// `path` relative to the in-memory root, where `.` is
+fn relative(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
+
+impl Vfs for MemoryFs {
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let path = relative(path);
+        let inner = self.inner.lock().expect("not poisoned");
+        if !inner.is_dir(path) {
+            return Err(not_found(path));
+        }
+        let mut entries: BTreeMap<String, EntryKind> = BTreeMap::new();
+        for file in inner.files.keys() {
+            let rest = match file.strip_prefix(path) {
+                Ok(rest) => rest,
+                Err(_) => continue,
+            };
+            let mut components = rest.components();
+            let name = match components.next() {
+                Some(name) => name.as_os_str().to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let kind = if components.next().is_some() {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            };
+            entries.insert(name, kind);
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(name, kind)| DirEntry { name, kind })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Metadata> {
+        let path = relative(path);
+        let inner = self.inner.lock().expect("not poisoned");
+        let writable = !inner.read_only.iter().any(|ro| path.starts_with(ro));
+        match inner.files.get(path) {
+            Some(bytes) => Ok(Metadata {
+                kind: EntryKind::File,
+                len: bytes.len() as u64,
+                modified: None,
+                writable,
+            }),
+            None if inner.is_dir(path) => Ok(Metadata {
+                kind: EntryKind::Dir,
+                len: 0,
+                modified: None,
+                writable,
+            }),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let path = relative(path);
+        let inner = self.inner.lock().expect("not poisoned");
+        inner
+            .files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let path = relative(path);
+        let mut inner = self.inner.lock().expect("not poisoned");
+        if inner.read_only.iter().any(|ro| path.starts_with(ro)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{}: read-only", path.display()),
+            ));
+        }
+        inner.files.insert(path.to_owned(), bytes.to_vec());
+        inner.notify(path);
+        Ok(())
+    }
+
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<Watch> {
+        let (watch, active) = Watch::new();
+        let mut inner = self.inner.lock().expect("not poisoned");
+        inner
+            .watches
+            .push((relative(path).to_owned(), active, on_change));
+        Ok(watch)
+    }
+}
+
+#[test]
+fn memory_fs_test() {
+    let fs = MemoryFs::new();
+    fs.add("src/main.rs", "fn main() {}\n");
+    fs.add("src/util/mod.rs", "");
+    fs.add(".git/HEAD", "");
+    fs.add("README", "hi\n");
+    fs.set_read_only("README");
+
+    let names: Vec<_> = fs
+        .list_dir(Path::new("src"))
+        .expect("a dir")
+        .into_iter()
+        .map(|e| (e.name, e.kind))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("main.rs".to_owned(), EntryKind::File),
+            ("util".to_owned(), EntryKind::Dir)
+        ]
+    );
+    assert_eq!(
+        fs.stat(Path::new("src")).map(|m| m.kind).ok(),
+        Some(EntryKind::Dir)
+    );
+    assert!(!fs.stat(Path::new("README")).expect("a file").writable);
+    assert_eq!(
+        fs.read(Path::new("nope")).map_err(|e| e.kind()).err(),
+        Some(io::ErrorKind::NotFound)
+    );
+
+    assert_eq!(
+        find_files(&fs, Path::new("."), "rs", 10).expect("listed"),
+        vec![
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/util/mod.rs")
+        ]
+    );
+    assert_eq!(
+        find_files(&fs, Path::new("."), "um", 10).expect("listed"),
+        vec![PathBuf::from("src/util/mod.rs")]
+    );
+
+    let changed = Arc::new(Mutex::new(vec![]));
+    let log = changed.clone();
+    let watch = fs
+        .watch(
+            Path::new("src"),
+            Box::new(move |path| log.lock().unwrap().push(path.to_owned())),
+        )
+        .expect("watching");
+    fs.write(Path::new("src/main.rs"), b"").expect("writable");
+    fs.write(Path::new("other"), b"").expect("writable");
+    drop(watch);
+    fs.add("src/new.rs", "");
+    assert_eq!(*changed.lock().unwrap(), vec![PathBuf::from("src/main.rs")]);
+    assert!(fs.write(Path::new("README"), b"").is_err());
+}
@@ -0,0 +1,352 @@
+//! View state
+//!
+//! What one particular view into a `Buffer` looks like: where are the
+//! selections and how far is it scrolled. Many views can look into the
+//! same buffer.
+use crate::buffer::SelectionSet;
+use crate::document::Severity;
+use crate::fold::Folds;
+use crate::follow::Follow;
+use crate::mark::JumpList;
+use crate::render::{Coord, Rect};
+use crate::selection_history::SelectionHistory;
+use crate::theme::ColorOverrides;
+use std::cell::{Cell, RefCell};
+
+/// Height assumed for views that were not rendered yet
+const DEFAULT_HEIGHT: usize = 25;
+
+/// Lines to keep visible around the cursor in a view of `height` lines
+pub fn scroll_margin(height: usize) -> usize {
+    height / 4
+}
+
+/// Cells to keep visible left and right of the cursor in a view of
+/// `width` cells, when its lines don't wrap
+///
+/// At least one, for the cursor not to go under the `<` and `>` showing
+/// the lines go on past the edges.
+pub fn side_margin(width: usize) -> usize {
+    (width / 8).max(1).min(width.saturating_sub(1) / 2)
+}
+
+/// The first cell column to show of the lines of a view `width` cells
+/// wide, scrolled right by `left`, for the cursor at `column` to be in it
+/// with the side margin
+pub fn follow_column(left: usize, column: usize, width: usize) -> usize {
+    let margin = side_margin(width);
+    if column < left + margin {
+        column.saturating_sub(margin)
+    } else if left + width <= column + margin {
+        (column + margin + 1).saturating_sub(width)
+    } else {
+        left
+    }
+}
+
+/// The lines of a view visible on the screen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub first_line: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    pub fn margin(&self) -> usize {
+        scroll_margin(self.height)
+    }
+
+    /// Lines the cursor can be on without the view having to scroll
+    ///
+    /// `lines` is the number of lines of the whole text. Returns the
+    /// first and last line, inclusive.
+    pub fn cursor_lines(&self, lines: usize) -> (usize, usize) {
+        let last_line = lines.saturating_sub(1);
+        let top = if self.first_line == 0 {
+            0
+        } else {
+            self.first_line + self.margin()
+        };
+        let bottom = if lines <= self.first_line + self.height {
+            last_line
+        } else {
+            (self.first_line + self.height).saturating_sub(self.margin())
+        };
+        let bottom = bottom.min(last_line);
+        (top.min(bottom), bottom)
+    }
+
+    /// Line the cursor goes to, to be at the top, middle or bottom of the view
+    pub fn line_at(&self, lines: usize, align: ViewAlign) -> usize {
+        let (top, bottom) = self.cursor_lines(lines);
+        match align {
+            ViewAlign::Top => top,
+            ViewAlign::Center => {
+                let last_visible = (self.first_line + self.height).min(lines).saturating_sub(1);
+                ((self.first_line + last_visible) / 2).clamp(top, bottom)
+            }
+            ViewAlign::Bottom => bottom,
+        }
+    }
+}
+
+/// A row of a view on the screen: a line of the text, or a virtual line
+/// shown around one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    /// A line, or its first row if it wraps, from the cell column `start`
+    /// if the view is scrolled right
+    Line { line: usize, start: usize },
+    /// A row of a wrapped line after the first, from the cell column
+    /// `start` of the line
+    Wrap { line: usize, start: usize },
+    /// Titles of the code lenses of the line, above it, at its indentation
+    CodeLens {
+        line: usize,
+        indent: usize,
+        text: String,
+    },
+    /// A piece of a diagnostic message; the first one comes after a prefix
+    /// naming the severity, the others are indented as much
+    Diagnostic {
+        line: usize,
+        severity: Severity,
+        first: bool,
+        text: String,
+    },
+}
+
+impl Row {
+    /// Line of the text the row belongs to
+    pub fn line(&self) -> usize {
+        match *self {
+            Row::Line { line, .. }
+            | Row::Wrap { line, .. }
+            | Row::CodeLens { line, .. }
+            | Row::Diagnostic { line, .. } => line,
+        }
+    }
+
+    /// Cell column of the line the row starts with, if it shows the text
+    pub fn start(&self) -> Option<usize> {
+        match *self {
+            Row::Line { start, .. } | Row::Wrap { start, .. } => Some(start),
+            Row::CodeLens { .. } | Row::Diagnostic { .. } => None,
+        }
+    }
+}
+
+/// Index of the row of `rows` showing the cell `column` of `line`
+pub fn row_of(rows: &[Row], line: usize, column: usize) -> Option<usize> {
+    rows.iter()
+        .rposition(|row| row.line() == line && row.start().is_some_and(|start| start <= column))
+}
+
+/// Cell columns of `line` the rows after the first start at, to wrap it
+/// at `width`
+///
+/// Wide chars are never cut, and breaking `words` leaves the spaces at
+/// the end of the rows, unless a word is wider than a whole row. The end
+/// of the line takes a cell, for the cursor to go.
+pub fn wrap_line(line: &str, width: usize, tabstop: usize, words: bool) -> Vec<usize> {
+    use crate::position::convert::grapheme_cells_at;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let width = width.max(1);
+    let mut starts = vec![];
+    let mut row_start = 0;
+    let mut cell = 0;
+    // where the row could break, after its last space
+    let mut space_end = None;
+    let graphemes = line.graphemes(true).map(Some).chain(Some(None));
+    for grapheme in graphemes {
+        let cells = match grapheme {
+            Some(grapheme) if !grapheme.ends_with('\n') => {
+                grapheme_cells_at(grapheme, cell, tabstop)
+            }
+            // the end of the line, or of the text
+            _ => 1,
+        };
+        if row_start + width < cell + cells && row_start < cell {
+            row_start = match space_end {
+                Some(end) if words => end,
+                _ => cell,
+            };
+            starts.push(row_start);
+            space_end = None;
+        }
+        cell += cells;
+        match grapheme {
+            Some(grapheme) if grapheme.chars().all(|ch| ch == ' ' || ch == '\t') => {
+                space_end = Some(cell)
+            }
+            Some(grapheme) if grapheme.ends_with('\n') => break,
+            _ => {}
+        }
+    }
+    starts
+}
+
+/// Where to put the cursor line in the view
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ViewState {
+    /// Index of the buffer in `State`
+    pub buffer_i: usize,
+    pub selection: SelectionSet,
+
+    /// First line visible; adjusted during rendering to follow the cursor
+    pub line_offset: RefCell<usize>,
+    /// First cell column visible when the lines don't wrap, adjusted
+    /// during rendering like `line_offset`
+    pub column_offset: Cell<usize>,
+    /// Number of lines visible, as of the last rendering
+    pub height: Cell<usize>,
+    /// Colors painted over those of the kind of the buffer
+    pub colors: ColorOverrides,
+    /// The view this one follows, as described in `follow`
+    pub follow: Option<Follow>,
+    /// Folds closed in it
+    pub folds: Folds,
+    /// Of the window it's shown in, as described in `mark`
+    pub jumps: JumpList,
+    /// As described in `selection_history`
+    pub selection_history: SelectionHistory,
+    /// Where it was drawn in the last frame, for the mouse
+    pub drawn: RefCell<Option<Drawn>>,
+}
+
+/// Where the text of a view was drawn on the screen, and its rows
+#[derive(Debug, Clone)]
+pub struct Drawn {
+    /// With the line numbers and signs, on the left
+    pub rect: Rect,
+    /// Width of the line numbers and signs
+    pub margin: usize,
+    pub rows: Vec<Row>,
+}
+
+impl Drawn {
+    /// The line and cell column of the text at `coord` of the screen,
+    /// or nearest to it if it's out of the view
+    ///
+    /// The margin is the start of its lines; below the text, the last row
+    /// goes on.
+    pub fn position_at(&self, coord: Coord) -> Option<(usize, usize)> {
+        let last = self.rows.len().checked_sub(1)?;
+        let y = coord.y.saturating_sub(self.rect.offset.y).min(last);
+        let x = coord
+            .x
+            .saturating_sub(self.rect.offset.x)
+            .saturating_sub(self.margin);
+        let row = &self.rows[y];
+        Some((row.line(), row.start().unwrap_or(0) + x))
+    }
+
+    /// The cell of the screen showing the cell `column` of `line`, if it's
+    /// shown
+    pub fn coord_of(&self, line: usize, column: usize) -> Option<Coord> {
+        let y = row_of(&self.rows, line, column)?;
+        let x = self.margin + column - self.rows[y].start().unwrap_or(0);
+        Some(Coord {
+            x: self.rect.offset.x + x,
+            y: self.rect.offset.y + y,
+        })
+        .filter(|_| x < self.rect.dimensions.x)
+    }
+}
+
+impl ViewState {
+    pub fn new(buffer_i: usize) -> Self {
+        Self {
+            buffer_i,
+            ..Self::default()
+        }
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            first_line: *self.line_offset.borrow(),
+            height: match self.height.get() {
+                0 => DEFAULT_HEIGHT,
+                height => height,
+            },
+        }
+    }
+}
+
+#[test]
+fn drawn_test() {
+    let drawn = Drawn {
+        rect: Rect {
+            offset: Coord { x: 10, y: 5 },
+            dimensions: Coord { x: 20, y: 4 },
+        },
+        margin: 3,
+        rows: vec![
+            Row::Line { line: 7, start: 0 },
+            Row::Wrap { line: 7, start: 17 },
+            Row::Line { line: 8, start: 0 },
+            Row::Line { line: 9, start: 30 },
+        ],
+    };
+    assert!(drawn.rect.contains(Coord { x: 10, y: 8 }));
+    assert!(!drawn.rect.contains(Coord { x: 30, y: 5 }));
+    assert_eq!(drawn.position_at(Coord { x: 15, y: 5 }), Some((7, 2)));
+    assert_eq!(drawn.position_at(Coord { x: 14, y: 6 }), Some((7, 18)));
+    // in the margin, and past the last row
+    assert_eq!(drawn.position_at(Coord { x: 11, y: 7 }), Some((8, 0)));
+    assert_eq!(drawn.coord_of(7, 18), Some(Coord { x: 14, y: 6 }));
+    assert_eq!(drawn.coord_of(8, 0), Some(Coord { x: 13, y: 7 }));
+    assert_eq!(drawn.coord_of(8, 17), None);
+    assert_eq!(drawn.coord_of(10, 0), None);
+    // scrolled right
+    assert_eq!(drawn.position_at(Coord { x: 0, y: 20 }), Some((9, 30)));
+    assert_eq!(drawn.position_at(Coord { x: 15, y: 8 }), Some((9, 32)));
+    assert_eq!(drawn.coord_of(9, 32), Some(Coord { x: 15, y: 8 }));
+    assert_eq!(drawn.coord_of(9, 29), None);
+
+    assert_eq!(follow_column(0, 10, 40), 0);
+    assert_eq!(follow_column(0, 39, 40), 5);
+    assert_eq!(follow_column(20, 22, 40), 17);
+    assert_eq!(follow_column(20, 30, 40), 20);
+    assert_eq!(follow_column(5, 0, 40), 0);
+}
+
+#[test]
+fn cursor_lines_test() {
+    let viewport = |first_line| Viewport {
+        first_line,
+        height: 20,
+    };
+    assert_eq!(viewport(0).cursor_lines(100), (0, 15));
+    assert_eq!(viewport(10).cursor_lines(100), (15, 25));
+    assert_eq!(viewport(90).cursor_lines(100), (95, 99));
+    assert_eq!(viewport(0).cursor_lines(3), (0, 2));
+
+    assert_eq!(viewport(10).line_at(100, ViewAlign::Center), 19);
+    assert_eq!(viewport(0).line_at(3, ViewAlign::Center), 1);
+    assert_eq!(viewport(90).line_at(100, ViewAlign::Top), 95);
+}
+
+#[test]
+fn wrap_line_test() {
+    assert_eq!(wrap_line("short\n", 10, 4, false), Vec::<usize>::new());
+    // the end of the line takes a cell
+    assert_eq!(wrap_line("0123456789\n", 10, 4, false), vec![10]);
+    assert_eq!(wrap_line("0123456789", 10, 4, false), vec![10]);
+    assert_eq!(wrap_line("aaaa bbbb cccc dd\n", 8, 4, false), vec![8, 16]);
+    assert_eq!(wrap_line("aaaa bbbb cccc dd\n", 8, 4, true), vec![5, 10]);
+    // too long for a row, so cut anyway
+    assert_eq!(wrap_line("a bbbbbbbbbbbb\n", 8, 4, true), vec![2, 10]);
+    assert_eq!(wrap_line("bbbbbbbbbbbb\n", 8, 4, true), vec![8]);
+    // wide chars go on the next row rather than get cut
+    assert_eq!(wrap_line("abc中文\n", 4, 4, false), vec![3, 7]);
+    assert_eq!(wrap_line("abc中文", 6, 4, false), vec![5]);
+}